@@ -2,10 +2,11 @@
 use conduwuit::Result;
 
 use crate::{
-	appservice, appservice::AppserviceCommand, check, check::CheckCommand, command::Command,
-	debug, debug::DebugCommand, federation, federation::FederationCommand, media,
-	media::MediaCommand, query, query::QueryCommand, room, room::RoomCommand, server,
-	server::ServerCommand, user, user::UserCommand,
+	appservice, appservice::AppserviceCommand, cache, cache::CacheCommand, check,
+	check::CheckCommand, command::Command, database, database::DatabaseCommand, debug,
+	debug::DebugCommand, federation, federation::FederationCommand, media, media::MediaCommand,
+	query, query::QueryCommand, room, room::RoomCommand, server, server::ServerCommand, user,
+	user::UserCommand,
 };
 
 #[derive(Debug, Parser)]
@@ -35,10 +36,18 @@ pub(super) enum AdminCommand {
 	/// - Commands for managing media
 	Media(MediaCommand),
 
+	#[command(subcommand)]
+	/// - Commands for inspecting and resizing caches
+	Cache(CacheCommand),
+
 	#[command(subcommand)]
 	/// - Commands for checking integrity
 	Check(CheckCommand),
 
+	#[command(subcommand)]
+	/// - Commands for verifying the database
+	Database(DatabaseCommand),
+
 	#[command(subcommand)]
 	/// - Commands for debugging things
 	Debug(DebugCommand),
@@ -61,7 +70,9 @@ pub(super) async fn process(command: AdminCommand, context: &Command<'_>) -> Res
 		| Server(command) => server::process(command, context).await?,
 		| Debug(command) => debug::process(command, context).await?,
 		| Query(command) => query::process(command, context).await?,
+		| Cache(command) => cache::process(command, context).await?,
 		| Check(command) => check::process(command, context).await?,
+		| Database(command) => database::process(command, context).await?,
 	}
 
 	Ok(())
@@ -1,3 +1,5 @@
+use std::fmt::Write;
+
 use ruma::{api::appservice::Registration, events::room::message::RoomMessageEventContent};
 
 use crate::{Result, admin_command};
@@ -59,21 +61,59 @@ pub(super) async fn show_appservice_config(
 	&self,
 	appservice_identifier: String,
 ) -> Result<RoomMessageEventContent> {
-	match self
+	let Some(info) = self
 		.services
 		.appservice
-		.get_registration(&appservice_identifier)
+		.read()
 		.await
+		.get(&appservice_identifier)
+		.cloned()
+	else {
+		return Ok(RoomMessageEventContent::text_plain("Appservice does not exist."));
+	};
+
+	let config_str = serde_yaml::to_string(&info.registration)
+		.expect("config should've been validated on register");
+	let mut output =
+		format!("Config for {appservice_identifier}:\n\n```yaml\n{config_str}\n```\n");
+
+	writeln!(output, "Compiled namespaces:")?;
+	for (kind, namespace) in
+		[("users", &info.users), ("aliases", &info.aliases), ("rooms", &info.rooms)]
 	{
-		| Some(config) => {
-			let config_str = serde_yaml::to_string(&config)
-				.expect("config should've been validated on register");
-			let output =
-				format!("Config for {appservice_identifier}:\n\n```yaml\n{config_str}\n```",);
-			Ok(RoomMessageEventContent::notice_markdown(output))
-		},
-		| None => Ok(RoomMessageEventContent::text_plain("Appservice does not exist.")),
+		let (exclusive, non_exclusive) = namespace.patterns();
+		writeln!(
+			output,
+			"- {kind}: exclusive {}; non-exclusive {}",
+			patterns(exclusive),
+			patterns(non_exclusive)
+		)?;
 	}
+
+	Ok(RoomMessageEventContent::notice_markdown(output))
+}
+
+#[admin_command]
+pub(super) async fn reload(&self) -> Result<RoomMessageEventContent> {
+	let (registered, failed) = self.services.appservice.reload().await;
+	let mut output = format!("Reloaded {registered} appservice registrations.");
+	for (id, e) in &failed {
+		write!(output, "\n- {id} is not registered: {e}")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(output))
+}
+
+fn patterns(patterns: &[String]) -> String {
+	if patterns.is_empty() {
+		return String::from("none");
+	}
+
+	patterns
+		.iter()
+		.map(|pattern| format!("`{pattern}`"))
+		.collect::<Vec<_>>()
+		.join(", ")
 }
 
 #[admin_command]
@@ -27,13 +27,21 @@ pub(super) enum AppserviceCommand {
 
 	/// - Show an appservice's config using its ID
 	///
-	/// You can find the ID using the `list-appservices` command.
+	/// The regular expressions its namespaces are matched with follow the
+	/// config. You can find the ID using the `list-appservices` command.
 	#[clap(alias("show"))]
 	ShowAppserviceConfig {
 		/// The appservice to show
 		appservice_identifier: String,
 	},
 
+	/// - Read all registrations from the database again
+	///
+	/// Registering and unregistering take effect at once; this is for
+	/// registrations changed in the database otherwise. Registrations which
+	/// cannot be read are listed and left unregistered.
+	Reload,
+
 	/// - List all the currently registered appservices
 	#[clap(alias("list"))]
 	ListRegistered,
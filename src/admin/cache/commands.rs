@@ -0,0 +1,101 @@
+use std::fmt::Write;
+
+use conduwuit::{Result, utils::bytes::pretty};
+use ruma::{OwnedEventId, events::room::message::RoomMessageEventContent};
+use service::cache::Usage;
+
+use crate::admin_command;
+
+const RUNTIME_ONLY: &str =
+	"Capacities changed here are not saved and revert to the configured values on restart.";
+
+#[admin_command]
+pub(super) async fn list(&self) -> Result<RoomMessageEventContent> {
+	let mut msg = String::from("```\n");
+	for (name, usage) in self.services.cache_usage().await {
+		writeln!(msg, "{name}: {}", format_usage(&usage))?;
+	}
+	writeln!(msg, "```\n{RUNTIME_ONLY}")?;
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn stats(&self, reset: bool) -> Result<RoomMessageEventContent> {
+	let mut msg = String::from("```\n");
+	for (name, usage) in self.services.cache_usage().await {
+		let (Some(hits), Some(misses)) = (usage.hits, usage.misses) else {
+			writeln!(msg, "{name}: not counted")?;
+			continue;
+		};
+
+		writeln!(msg, "{name}: {hits} hits, {misses} misses (hit rate: {})", hit_rate(&usage))?;
+	}
+	msg.push_str("```");
+
+	if reset {
+		self.services.reset_cache_counters().await;
+		msg.push_str("\nCounters have been reset.");
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn set(&self, name: String, capacity: usize) -> Result<RoomMessageEventContent> {
+	let usage = self.services.set_cache_capacity(&name, capacity).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{name}: {}\n\n{RUNTIME_ONLY}",
+		format_usage(&usage)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn evict(
+	&self,
+	service: String,
+	key: String,
+) -> Result<RoomMessageEventContent> {
+	let evicted = self.services.clear_cache_entry(&service, &key).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(if evicted {
+		format!("Evicted {key:?} from the caches of {service}.")
+	} else {
+		format!("{service} has nothing cached for {key:?}.")
+	}))
+}
+
+#[admin_command]
+pub(super) async fn evict_shortstatehash(
+	&self,
+	shortstatehash: u64,
+) -> Result<RoomMessageEventContent> {
+	self.evict("rooms::state_compressor".to_owned(), shortstatehash.to_string())
+		.await
+}
+
+#[admin_command]
+pub(super) async fn evict_eventid(
+	&self,
+	event_id: OwnedEventId,
+) -> Result<RoomMessageEventContent> {
+	self.evict("rooms::auth_chain".to_owned(), event_id.to_string())
+		.await
+}
+
+fn format_usage(usage: &Usage) -> String {
+	let bytes = match (usage.bytes, usage.byte_budget) {
+		| (Some(bytes), Some(budget)) => format!(", {} of {}", pretty(bytes), pretty(budget)),
+		| (Some(bytes), None) => format!(", {}", pretty(bytes)),
+		| _ => String::new(),
+	};
+
+	format!("{} of {}{bytes} (hit rate: {})", usage.len, usage.capacity, hit_rate(usage))
+}
+
+fn hit_rate(usage: &Usage) -> String {
+	usage
+		.hit_rate()
+		.map_or_else(|| "n/a".to_owned(), |rate| format!("{:.1}%", rate * 100.0))
+}
@@ -0,0 +1,57 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+use ruma::OwnedEventId;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum CacheCommand {
+	/// - List the resizable caches with their capacity, length and hit rate
+	List,
+
+	/// - Show the hit and miss counts of each cache
+	Stats {
+		/// Zero the counters after showing them
+		#[arg(long)]
+		reset: bool,
+	},
+
+	/// - Change the capacity of a cache until the server restarts
+	///
+	/// Shrinking a cache evicts its least recently used entries immediately.
+	/// The capacity reverts to the configured value on restart.
+	Set {
+		name: String,
+
+		capacity: usize,
+	},
+
+	/// - Evict the entries for a key from the caches of one service
+	///
+	/// The key is a shortstatehash for rooms::state_compressor, an event ID
+	/// for rooms::auth_chain and a room ID for rooms::spaces. Other services
+	/// can only be cleared entirely with `server clear-caches`.
+	Evict {
+		service: String,
+
+		key: String,
+	},
+
+	/// - Evict the state info of a shortstatehash from the
+	///   rooms::state_compressor cache
+	EvictShortstatehash {
+		shortstatehash: u64,
+	},
+
+	/// - Evict the auth chains including an event from the rooms::auth_chain
+	///   cache
+	///
+	/// The short event ID mappings of rooms::short are held only in the
+	/// database cache and are not affected.
+	EvictEventid {
+		event_id: OwnedEventId,
+	},
+}
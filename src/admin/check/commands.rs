@@ -1,7 +1,7 @@
 use conduwuit::Result;
 use conduwuit_macros::implement;
 use futures::StreamExt;
-use ruma::events::room::message::RoomMessageEventContent;
+use ruma::{OwnedRoomOrAliasId, events::room::message::RoomMessageEventContent};
 
 use crate::Command;
 
@@ -26,3 +26,23 @@ pub(super) async fn check_all_users(&self) -> Result<RoomMessageEventContent> {
 
 	Ok(RoomMessageEventContent::notice_markdown(message))
 }
+
+/// Looks every event of the room's timeline up by its ID, restoring the index
+/// entries of those which are missing.
+#[implement(Command, params = "<'_>")]
+pub(super) async fn event_index(
+	&self,
+	room: OwnedRoomOrAliasId,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let (events, repaired) = self
+		.services
+		.rooms
+		.timeline
+		.repair_room_pdu_ids(&room_id)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Checked {events} events of {room_id}; restored {repaired} missing index entries."
+	)))
+}
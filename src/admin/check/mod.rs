@@ -2,6 +2,7 @@
 
 use clap::Subcommand;
 use conduwuit::Result;
+use ruma::OwnedRoomOrAliasId;
 
 use crate::admin_command_dispatch;
 
@@ -9,4 +10,10 @@
 #[derive(Debug, Subcommand)]
 pub(super) enum CheckCommand {
 	CheckAllUsers,
+
+	/// - Restore the event ID index entries missing for the events of a room's
+	///   timeline
+	EventIndex {
+		room: OwnedRoomOrAliasId,
+	},
 }
@@ -0,0 +1,232 @@
+use std::{fmt::Write, path::PathBuf};
+
+use conduwuit::{
+	Err, Result,
+	utils::{bytes, time},
+};
+use ruma::events::room::message::RoomMessageEventContent;
+use tokio::time::Instant;
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn check(&self, sample: Option<f64>) -> Result<RoomMessageEventContent> {
+	let timer = Instant::now();
+	let engine = self.services.db.db.clone();
+	let names: Vec<String> = self.services.db.keys().map(ToString::to_string).collect();
+
+	let results = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking(move || {
+			names
+				.into_iter()
+				.map(|name| {
+					let check = engine.check(&name, sample);
+					(name, check)
+				})
+				.collect::<Vec<_>>()
+		})
+		.await?;
+
+	let (mut ok, mut failed, mut keys) = (0_usize, 0_usize, 0_u64);
+	let mut errors = String::new();
+	for (name, check) in results {
+		match check {
+			| Ok(check) if check.errors == 0 => {
+				ok = ok.saturating_add(1);
+				keys = keys.saturating_add(check.keys);
+			},
+			| Ok(check) => {
+				failed = failed.saturating_add(1);
+				keys = keys.saturating_add(check.keys);
+				let first = check.first_error.unwrap_or_default();
+				writeln!(errors, "| {name} | {} | {} | {first} |", check.keys, check.errors)?;
+			},
+			| Err(e) => {
+				failed = failed.saturating_add(1);
+				writeln!(errors, "| {name} | 0 | 1 | {e} |")?;
+			},
+		}
+	}
+
+	let elapsed = time::pretty(timer.elapsed());
+	let sampled = sample.map_or_else(String::new, |s| format!(" (sampled {:.2}%)", s * 100.0));
+	let mut out = format!(
+		"Checked {keys} keys{sampled} in {elapsed}.\n\nColumns OK: {ok}\nColumns with errors: \
+		 {failed}\n"
+	);
+
+	if failed > 0 {
+		writeln!(out, "\n| column | keys | errors | first error |")?;
+		writeln!(out, "| :----- | ---: | -----: | :---------- |")?;
+		out.push_str(&errors);
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn catch_up(&self) -> Result<RoomMessageEventContent> {
+	if !self.services.db.is_secondary() {
+		return Err!("Database is not running as a secondary.");
+	}
+
+	let timer = Instant::now();
+	let applied = self.services.secondary.catch_up().await?;
+	let elapsed = time::pretty(timer.elapsed());
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Caught up with primary in {elapsed}; applied {applied} sequence numbers."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn stats(&self) -> Result<RoomMessageEventContent> {
+	let stats = self.services.db_stats.stats().await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("```\n{stats}```")))
+}
+
+#[admin_command]
+pub(super) async fn usage(&self) -> Result<RoomMessageEventContent> {
+	let usage = self.services.db.db.memory_usage()?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("```\n{usage}```")))
+}
+
+#[admin_command]
+pub(super) async fn latency(&self) -> Result<RoomMessageEventContent> {
+	let mut out = String::new();
+	writeln!(out, "| lane | queued | requests | mean wait | max wait |")?;
+	writeln!(out, "| :--- | -----: | -------: | --------: | -------: |")?;
+	for stats in self.services.db.db.lane_stats() {
+		writeln!(
+			out,
+			"| {} | {} | {} | {} | {} |",
+			stats.lane.name(),
+			stats.queued,
+			stats.requests,
+			time::pretty(stats.waited_mean()),
+			time::pretty(stats.waited_max),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn drop_column(
+	&self,
+	name: Option<String>,
+	yes_i_want_to_do_this: bool,
+) -> Result<RoomMessageEventContent> {
+	let engine = self.services.db.db.clone();
+	let Some(name) = name else {
+		let columns = engine.unknown_columns();
+		if columns.is_empty() {
+			return Ok(RoomMessageEventContent::notice_markdown("There are no unknown columns."));
+		}
+
+		let list: String = columns.iter().map(|name| format!("- {name}\n")).collect();
+		return Ok(RoomMessageEventContent::notice_markdown(format!("Unknown columns:\n{list}")));
+	};
+
+	if engine.is_read_only() {
+		return Err!("Columns cannot be dropped in read-only or secondary mode.");
+	}
+
+	if !yes_i_want_to_do_this {
+		return Ok(RoomMessageEventContent::notice_markdown(
+			"You must pass the --yes-i-want-to-do-this flag to ensure you really want to delete \
+			 this column and all of its data.",
+		));
+	}
+
+	let size = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking({
+			let name = name.clone();
+			move || engine.drop_column(&name)
+		})
+		.await??;
+
+	let size = bytes::pretty(size.try_into()?);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Dropped column {name:?}, reclaiming an estimated {size}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn export(
+	&self,
+	column: String,
+	path: PathBuf,
+) -> Result<RoomMessageEventContent> {
+	let timer = Instant::now();
+	let engine = self.services.db.db.clone();
+	let keys = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking({
+			let path = path.clone();
+			move || engine.export_column(&column, &path)
+		})
+		.await??;
+
+	let elapsed = time::pretty(timer.elapsed());
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Exported {keys} keys to {path:?} in {elapsed}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn import(
+	&self,
+	column: String,
+	path: PathBuf,
+	overwrite: bool,
+) -> Result<RoomMessageEventContent> {
+	if self.services.db.is_read_only() {
+		return Err!("Columns cannot be imported in read-only or secondary mode.");
+	}
+
+	let timer = Instant::now();
+	let engine = self.services.db.db.clone();
+	let name = column.clone();
+	let import = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking(move || engine.import_column(&name, &path, overwrite))
+		.await??;
+
+	let elapsed = time::pretty(timer.elapsed());
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Imported keys into column {column:?} in {elapsed}; wrote {}, skipped {} already \
+		 present.",
+		import.written, import.skipped
+	)))
+}
+
+/// Parses a sampling percentage such as `1%` or `0.5%` into a fraction.
+pub(super) fn parse_sample(input: &str) -> Result<f64, String> {
+	let percent: f64 = input
+		.trim()
+		.trim_end_matches('%')
+		.parse()
+		.map_err(|e| format!("Invalid sample percentage {input:?}: {e}"))?;
+
+	if !(percent > 0.0 && percent <= 100.0) {
+		return Err("Sample percentage must be greater than 0% and at most 100%.".to_owned());
+	}
+
+	Ok(percent / 100.0)
+}
@@ -0,0 +1,80 @@
+mod commands;
+
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use conduwuit::Result;
+
+use self::commands::parse_sample;
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum DatabaseCommand {
+	/// - Verify the checksums of every column in the database
+	///
+	/// Reads all keys with checksum verification and reports the columns
+	/// which could not be read. Errors in one column do not stop the others
+	/// from being checked.
+	Check {
+		/// Only read a percentage of each column's table files (e.g. `1%`)
+		#[arg(long, value_parser = parse_sample)]
+		sample: Option<f64>,
+	},
+
+	/// - Catch a `rocksdb_secondary` instance up with its primary now
+	CatchUp,
+
+	/// - Print the database statistics published to the server metrics
+	Stats,
+
+	/// - Print the memory used by the database and the sizes of its caches
+	Usage,
+
+	/// - Show how long database requests waited for a worker, per lane
+	///
+	/// Requests are queued in the interactive lane for clients, in the
+	/// background lane for scans and other long jobs, and in the normal lane
+	/// otherwise. Times are since the server started.
+	Latency,
+
+	/// - Drop a column family which the server does not use
+	///
+	/// Deletes the column and all of its data, for example one left behind by
+	/// a downgrade. Without a name the unknown columns are listed. Columns in
+	/// use by the server cannot be dropped.
+	///
+	/// Requires the `--yes-i-want-to-do-this` flag.
+	DropColumn {
+		name: Option<String>,
+
+		#[arg(long)]
+		yes_i_want_to_do_this: bool,
+	},
+
+	/// - Export all keys and values of a column to a file
+	///
+	/// The file is created by the server and must not already exist. Exports
+	/// are read from a snapshot and are permitted in read-only mode.
+	Export {
+		column: String,
+
+		path: PathBuf,
+	},
+
+	/// - Import keys and values into a column from a file made by `export`
+	///
+	/// The file must have been exported from the same column, and is checked
+	/// in full before any key is written. Keys already in the column are
+	/// skipped unless `--overwrite` is given. Caches are not refreshed, so
+	/// importing into a column in use by the server is best followed by a
+	/// restart.
+	Import {
+		column: String,
+
+		path: PathBuf,
+
+		#[arg(long)]
+		overwrite: bool,
+	},
+}
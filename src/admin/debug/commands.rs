@@ -2,11 +2,11 @@
 	collections::HashMap,
 	fmt::Write,
 	iter::once,
-	time::{Instant, SystemTime},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use conduwuit::{
-	Error, Result, debug_error, err, info,
+	Error, Result, at, debug_error, err, info,
 	matrix::pdu::{PduEvent, PduId, RawPduId},
 	trace, utils,
 	utils::{
@@ -15,16 +15,20 @@
 	},
 	warn,
 };
+use conduwuit_database::Lane;
 use futures::{FutureExt, StreamExt, TryStreamExt};
 use ruma::{
-	CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomOrAliasId, RoomId, RoomVersionId,
-	ServerName,
+	CanonicalJsonObject, CanonicalJsonValue, EventId, OwnedEventId, OwnedRoomId,
+	OwnedRoomOrAliasId, RoomId, RoomVersionId, ServerName,
 	api::{client::error::ErrorKind, federation::event::get_room_state},
 	events::room::message::RoomMessageEventContent,
 };
-use service::rooms::{
-	short::{ShortEventId, ShortRoomId},
-	state_compressor::HashSetCompressStateEvent,
+use service::{
+	rooms::{
+		short::{ShortEventId, ShortRoomId},
+		state_compressor::HashSetCompressStateEvent,
+	},
+	uisi::top,
 };
 use tracing_subscriber::EnvFilter;
 
@@ -170,6 +174,94 @@ pub(super) async fn get_short_pdu(
 	}
 }
 
+#[admin_command]
+pub(super) async fn event_provenance(
+	&self,
+	event_id: Box<EventId>,
+) -> Result<RoomMessageEventContent> {
+	let Ok(pdu_json) = self.services.rooms.timeline.get_pdu_json(&event_id).await else {
+		return Ok(RoomMessageEventContent::text_plain("PDU not found locally."));
+	};
+
+	let field = |name: &str| pdu_json.get(name).and_then(CanonicalJsonValue::as_str);
+	let claimed_origin = field("origin")
+		.or_else(|| {
+			field("sender")
+				.and_then(|sender| sender.split_once(':'))
+				.map(at!(1))
+		})
+		.unwrap_or("unknown");
+
+	let signatures = pdu_json
+		.get("signatures")
+		.and_then(CanonicalJsonValue::as_object)
+		.into_iter()
+		.flatten()
+		.map(|(server, keys)| {
+			let key_ids = keys
+				.as_object()
+				.into_iter()
+				.flat_map(|keys| keys.keys().map(String::as_str))
+				.collect::<Vec<_>>()
+				.join(", ");
+
+			format!("{server} ({key_ids})")
+		})
+		.collect::<Vec<_>>()
+		.join(", ");
+
+	let mut out = format!("Claimed origin: {claimed_origin}\nSigned by: {signatures}\n");
+	match self
+		.services
+		.rooms
+		.event_handler
+		.get_provenance(&event_id)
+		.await
+	{
+		| Ok(provenance) => {
+			let received = UNIX_EPOCH
+				.checked_add(Duration::from_millis(provenance.received))
+				.map_or_else(
+					|| provenance.received.to_string(),
+					|received| utils::time::format(received, "%+"),
+				);
+
+			writeln!(out, "Delivered by: {}", provenance.server)?;
+			writeln!(out, "Delivered through: {}", provenance.delivery)?;
+			writeln!(out, "Received at: {received}")?;
+			writeln!(
+				out,
+				"Arrived as: {}",
+				if provenance.timeline {
+					"timeline event"
+				} else {
+					"outlier"
+				}
+			)?;
+		},
+		| Err(_) => {
+			writeln!(out, "No provenance recorded; the event is local or predates recording.")?;
+		},
+	}
+
+	let stored = if self
+		.services
+		.rooms
+		.timeline
+		.get_non_outlier_pdu_json(&event_id)
+		.await
+		.is_ok()
+	{
+		"timeline event"
+	} else {
+		"outlier"
+	};
+
+	writeln!(out, "Stored as: {stored}")?;
+
+	Ok(RoomMessageEventContent::notice_plain(out))
+}
+
 #[admin_command]
 pub(super) async fn get_remote_pdu_list(
 	&self,
@@ -1018,3 +1110,194 @@ pub(super) async fn trim_memory(&self) -> Result<RoomMessageEventContent> {
 
 	Ok(RoomMessageEventContent::notice_plain(""))
 }
+
+#[admin_command]
+pub(super) async fn gc_short_ids(&self, dry_run: bool) -> Result<RoomMessageEventContent> {
+	let services = self.services;
+	let watermark = services.globals.current_count()?.saturating_add(1);
+
+	let mut locks = Vec::new();
+	if !dry_run {
+		let room_ids: Vec<OwnedRoomId> = services
+			.rooms
+			.metadata
+			.iter_ids()
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for room_id in &room_ids {
+			let federation = services
+				.rooms
+				.event_handler
+				.mutex_federation
+				.lock(room_id)
+				.await;
+
+			let state = services.rooms.state.mutex.lock(room_id).await;
+			locks.push((federation, state));
+		}
+	}
+
+	let start = Instant::now();
+	let gc = {
+		let _cork = services.db.cork_and_flush();
+		Lane::Background
+			.scope(services.rooms.short.gc_short_ids(watermark, dry_run))
+			.await?
+	};
+
+	drop(locks);
+	let elapsed = start.elapsed();
+	let verb = if dry_run { "Would remove" } else { "Removed" };
+	let bytes = utils::bytes::pretty(gc.bytes);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{verb} {} short event IDs and {} short state keys ({bytes}) in {elapsed:?}",
+		gc.events, gc.statekeys,
+	)))
+}
+
+#[admin_command]
+pub(super) async fn state_compression_info(
+	&self,
+	room: Option<OwnedRoomOrAliasId>,
+) -> Result<RoomMessageEventContent> {
+	let compressor = &self.services.rooms.state_compressor;
+	let Some(room) = room else {
+		let (rooms, snapshots) = compressor.snapshots.totals();
+		let cached = compressor.stateinfo_cache.lock()?.len();
+
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"{snapshots} full state snapshots taken in {rooms} rooms since startup; {cached} \
+			 states cached"
+		)));
+	};
+
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let shortstatehash = self
+		.services
+		.rooms
+		.state
+		.get_room_shortstatehash(&room_id)
+		.await
+		.map_err(|_| err!("Room {room_id} has no state"))?;
+
+	let layers = compressor.load_shortstatehash_info(shortstatehash).await?;
+
+	let mut out = format!("Current state {shortstatehash} of {room_id}:\n```\n");
+	for layer in &layers {
+		writeln!(
+			out,
+			"{}: {} entries (+{} -{})",
+			layer.shortstatehash,
+			layer.full_state.len(),
+			layer.added.len(),
+			layer.removed.len(),
+		)?;
+	}
+
+	let stats = compressor.snapshots.stats(&room_id).unwrap_or_default();
+	let last = stats
+		.last
+		.map_or_else(|| "never".to_owned(), |last| format!("{:?} ago", last.elapsed()));
+
+	writeln!(
+		out,
+		"```\n{} full state snapshots since startup, the last {last}; {} state changes since",
+		stats.snapshots, stats.changes,
+	)?;
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn uisi_stats(
+	&self,
+	room: Option<OwnedRoomOrAliasId>,
+	since: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	/// Most rows of each table.
+	const TOP: usize = 20;
+
+	/// Rooms whose encrypted messages are counted, as that scans their
+	/// timeline.
+	const COUNTED: usize = 10;
+
+	let uisi = &self.services.uisi;
+	let since = match since {
+		| Some(since) => {
+			let ago = utils::time::parse_duration(&since)?;
+			let ago = u64::try_from(ago.as_millis()).unwrap_or(u64::MAX);
+			utils::millis_since_unix_epoch().saturating_sub(ago)
+		},
+		| None => 0,
+	};
+
+	let room_id = match room {
+		| Some(room) => Some(self.services.rooms.alias.resolve(&room).await?),
+		| None => None,
+	};
+
+	let stats = uisi.stats(room_id.as_deref(), since).await;
+
+	let mut out = String::new();
+	if !uisi.is_enabled() {
+		writeln!(out, "Clients may not report decryption failures; `uisi_reports` is off.\n")?;
+	}
+
+	if stats.failures == 0 {
+		out.push_str("No decryption failures were reported.");
+		return Ok(RoomMessageEventContent::notice_markdown(out));
+	}
+
+	writeln!(
+		out,
+		"{} decryption failures of {} events reported by {} users.\n",
+		stats.failures,
+		stats.events(),
+		stats.reporters(),
+	)?;
+
+	writeln!(out, "| Room | Failures | Encrypted messages |\n| --- | --- | --- |")?;
+	for (i, (room_id, failures)) in top(&stats.rooms, TOP).into_iter().enumerate() {
+		let encrypted = if i < COUNTED {
+			uisi.encrypted_messages(&room_id, since).await.to_string()
+		} else {
+			"-".to_owned()
+		};
+
+		writeln!(out, "| {room_id} | {failures} | {encrypted} |")?;
+	}
+
+	writeln!(out, "\n| Sender server | Failures |\n| --- | --- |")?;
+	for (server, failures) in top(&stats.servers, TOP) {
+		let server = server
+			.as_ref()
+			.map_or("(event unknown here)", |server| server.as_str());
+
+		writeln!(out, "| {server} | {failures} |")?;
+	}
+
+	writeln!(out, "\n| Failure | Count |\n| --- | --- |")?;
+	for (class, failures) in &stats.classes {
+		writeln!(out, "| {class} | {failures} |")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn repairs(&self) -> Result<RoomMessageEventContent> {
+	let repairs = self.services.rooms.timeline.pdu_id_repairs();
+	let enabled = self.services.server.config.rebuild_missing_event_index;
+	let fallback = if enabled {
+		""
+	} else {
+		" (rebuild_missing_event_index is disabled)"
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Event ID index entries restored since startup: {repairs}{fallback}"
+	)))
+}
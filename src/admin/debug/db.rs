@@ -0,0 +1,216 @@
+use std::{fmt::Write, ops::Deref, sync::Arc};
+
+use conduwuit::{Err, Result, err, utils::stream::TryIgnore};
+use conduwuit_database::{Map, SEP};
+use conduwuit_service::Services;
+use futures::StreamExt;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::admin_command;
+
+/// Values longer than this many bytes are only printed with `--full`.
+const VALUE_CAP: usize = 4096;
+
+/// Bytes of each value a scan previews without `--full`.
+const PREVIEW_LEN: usize = 64;
+
+/// Columns suggested for a mistyped name are at most this many edits away.
+const SIMILAR_DISTANCE: usize = 3;
+
+#[admin_command]
+pub(super) async fn db_get(
+	&self,
+	column: String,
+	key: String,
+	full: bool,
+) -> Result<RoomMessageEventContent> {
+	let map = find_column(self.services, &column)?;
+	let key = parse_key(&key)?;
+	let value = match map.get(&key).await {
+		| Ok(value) => value,
+		| Err(e) if e.is_not_found() => {
+			return Ok(RoomMessageEventContent::notice_plain(format!(
+				"No value in {column} at key {}.",
+				hex(&key)
+			)));
+		},
+		| Err(e) => return Err(e),
+	};
+
+	if value.len() > VALUE_CAP && !full {
+		return Err!(
+			"The value is {} bytes, more than the {VALUE_CAP} printed without --full.",
+			value.len()
+		);
+	}
+
+	let mut out = format!(
+		"Key: {}\nValue, {} bytes:\n```\n{}\n```\n",
+		render(&key).unwrap_or_else(|| hex(&key)),
+		value.len(),
+		hex(&value)
+	);
+
+	if let Some(rendered) = render(&value) {
+		writeln!(out, "As text:\n```\n{rendered}\n```")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn db_scan(
+	&self,
+	column: String,
+	prefix: String,
+	limit: usize,
+	full: bool,
+) -> Result<RoomMessageEventContent> {
+	let map = find_column(self.services, &column)?;
+	let prefix = parse_key(&prefix)?;
+	let entries: Vec<(Vec<u8>, usize, Vec<u8>)> = map
+		.raw_stream_prefix(&prefix)
+		.ignore_err()
+		.take(limit)
+		.map(|(key, value)| {
+			let shown = if full { value } else { preview(value) };
+			(key.to_vec(), value.len(), shown.to_vec())
+		})
+		.collect()
+		.await;
+
+	if entries.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain(format!(
+			"No keys in {column} start with {}.",
+			hex(&prefix)
+		)));
+	}
+
+	let mut out = format!("{} keys of {column}:\n```\n", entries.len());
+	for (key, len, shown) in &entries {
+		let cut = if shown.len() < *len { "…" } else { "" };
+		writeln!(
+			out,
+			"{} | key {} B | value {len} B | {}{cut}",
+			render(key).unwrap_or_else(|| hex(key)),
+			key.len(),
+			render(shown).unwrap_or_else(|| hex(shown)),
+		)?;
+	}
+	out.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+/// The column of that name, or an error suggesting those it may be a typo of.
+fn find_column(services: &Services, name: &str) -> Result<Arc<Map>> {
+	if let Ok(map) = services.db.get(name) {
+		return Ok(map.clone());
+	}
+
+	let names: Vec<&str> = services.db.keys().map(Deref::deref).collect();
+	let similar = similar(name, &names);
+	if similar.is_empty() {
+		return Err!("No column named {name:?}. The columns are: {}", names.join(", "));
+	}
+
+	Err!("No column named {name:?}; did you mean {}?", similar.join(", "))
+}
+
+/// Columns whose names contain `name` or are a few edits away from it.
+pub(super) fn similar<'a>(name: &str, names: &[&'a str]) -> Vec<&'a str> {
+	names
+		.iter()
+		.filter(|candidate| {
+			candidate.contains(name) || edit_distance(name, candidate) <= SIMILAR_DISTANCE
+		})
+		.copied()
+		.collect()
+}
+
+/// Edits of one character turning `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut above: Vec<usize> = (0..=b.len()).collect();
+	for (i, a) in a.chars().enumerate() {
+		let mut row = Vec::with_capacity(above.len());
+		row.push(i.saturating_add(1));
+		for ((b, diagonal), up) in b.iter().zip(&above).zip(above.iter().skip(1)) {
+			let left = row.last().copied().unwrap_or_default();
+			let replaced = diagonal.saturating_add((a != *b).into());
+			row.push(
+				replaced
+					.min(up.saturating_add(1))
+					.min(left.saturating_add(1)),
+			);
+		}
+
+		above = row;
+	}
+
+	above.last().copied().unwrap_or_default()
+}
+
+/// A key given as hex after `0x`, or else as the bytes of the string.
+pub(super) fn parse_key(key: &str) -> Result<Vec<u8>> {
+	let Some(digits) = key.strip_prefix("0x") else {
+		return Ok(key.as_bytes().to_vec());
+	};
+
+	if digits.len() % 2 != 0 {
+		return Err!("Hex key {key:?} has an odd number of digits.");
+	}
+
+	digits
+		.as_bytes()
+		.chunks(2)
+		.map(|pair| {
+			std::str::from_utf8(pair)
+				.ok()
+				.and_then(|pair| u8::from_str_radix(pair, 16).ok())
+				.ok_or_else(|| err!("Hex key {key:?} has a digit which is not hex."))
+		})
+		.collect()
+}
+
+/// The bytes as `0x` followed by hex.
+pub(super) fn hex(bytes: &[u8]) -> String {
+	bytes.iter().fold(String::from("0x"), |mut hex, byte| {
+		write!(hex, "{byte:02x}").expect("written to string");
+		hex
+	})
+}
+
+/// The bytes as pretty JSON if they are JSON, or else as the text of each part
+/// between the database's separators, if there is any text. Parts which are
+/// not text are shown as hex.
+pub(super) fn render(bytes: &[u8]) -> Option<String> {
+	if let Ok(json) = serde_json::from_slice::<serde_json::Value>(bytes) {
+		if json.is_object() || json.is_array() {
+			return serde_json::to_string_pretty(&json).ok();
+		}
+	}
+
+	let parts: Vec<Option<&str>> = bytes
+		.split(|&byte| byte == SEP)
+		.map(|part| std::str::from_utf8(part).ok().filter(|part| is_text(part)))
+		.collect();
+
+	if !parts.iter().any(Option::is_some) {
+		return None;
+	}
+
+	let separator = format!(" {} ", hex(&[SEP]));
+	let rendered: Vec<String> = bytes
+		.split(|&byte| byte == SEP)
+		.zip(parts)
+		.map(|(part, text)| text.map_or_else(|| hex(part), |text| format!("{text:?}")))
+		.collect();
+
+	Some(rendered.join(&separator))
+}
+
+fn is_text(part: &str) -> bool { !part.is_empty() && !part.chars().any(char::is_control) }
+
+/// The start of a value shown by a scan.
+fn preview(value: &[u8]) -> &[u8] { value.get(..PREVIEW_LEN).unwrap_or(value) }
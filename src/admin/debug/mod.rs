@@ -1,9 +1,13 @@
 mod commands;
+mod db;
+mod room_state;
 pub(crate) mod tester;
+#[cfg(test)]
+mod tests;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::{EventId, OwnedRoomOrAliasId, RoomId, ServerName};
+use ruma::{EventId, OwnedEventId, OwnedRoomOrAliasId, RoomId, ServerName};
 use service::rooms::short::{ShortEventId, ShortRoomId};
 
 use self::tester::TesterCommand;
@@ -47,6 +51,13 @@ pub(super) enum DebugCommand {
 		shorteventid: ShortEventId,
 	},
 
+	/// - Show which server delivered an event to us, how and when, next to the
+	///   origin it claims and the servers which signed it
+	EventProvenance {
+		/// An event ID (a $ followed by the base64 reference hash)
+		event_id: Box<EventId>,
+	},
+
 	/// - Attempts to retrieve a PDU from a remote server. Inserts it into our
 	///   database/timeline if found and we do not have this PDU already
 	///   (following normal event auth rules, handles it as an incoming PDU).
@@ -86,6 +97,20 @@ pub(super) enum DebugCommand {
 		room_id: OwnedRoomOrAliasId,
 	},
 
+	/// - Export the full state of a room as JSON, with the shortstatehash and
+	///   state hash of the snapshot. Large state is uploaded as a file.
+	///
+	/// The state is that at the room's head, or at the given event. State
+	/// entries which cannot be resolved to a stored event are listed rather
+	/// than failing the export.
+	RoomState {
+		/// Room ID or alias
+		room: OwnedRoomOrAliasId,
+
+		/// Event at which to export the state
+		event_id: Option<OwnedEventId>,
+	},
+
 	/// - Get and display signing keys from local cache or remote server.
 	GetSigningKeys {
 		server_name: Option<Box<ServerName>>,
@@ -179,6 +204,27 @@ pub(super) enum DebugCommand {
 		server_name: Box<ServerName>,
 	},
 
+	/// - Forcefully replaces the current state of our local copy of the room
+	///   with the stored state at one of its events
+	///
+	/// For recovering from a bad state reset, such as one losing everyone's
+	/// power levels: the state at an event from before it becomes the room's
+	/// state again. Nothing is sent to the room, so other servers keep their
+	/// state. The state is that the event was sent in, before the event itself.
+	///
+	/// Without the `--yes-i-want-to-do-this` flag, only says how many state
+	/// entries would change.
+	ForceSetRoomState {
+		/// Room ID or alias
+		room: OwnedRoomOrAliasId,
+
+		/// Event at which the state is taken
+		event_id: OwnedEventId,
+
+		#[arg(long)]
+		yes_i_want_to_do_this: bool,
+	},
+
 	/// - Runs a server name through conduwuit's true destination resolution
 	///   process
 	///
@@ -223,6 +269,40 @@ pub(super) enum DebugCommand {
 		map: Option<String>,
 	},
 
+	/// - Print the raw value at a key of a database column
+	///
+	/// The key is given as hex after `0x`, or else as a string. The value is
+	/// printed as hex, and as JSON or text when it looks like either.
+	DbGet {
+		/// Column name
+		column: String,
+
+		/// Key, as hex after `0x` or a string
+		key: String,
+
+		/// Print values too large to print otherwise
+		#[arg(long)]
+		full: bool,
+	},
+
+	/// - List the keys of a database column starting with a prefix, with the
+	///   size of each key and value and the start of the value
+	DbScan {
+		/// Column name
+		column: String,
+
+		/// Key prefix, as hex after `0x` or a string; empty for all keys
+		prefix: String,
+
+		/// Most keys to list
+		#[arg(long, default_value = "50")]
+		limit: usize,
+
+		/// Print whole values instead of their start
+		#[arg(long)]
+		full: bool,
+	},
+
 	/// - Trim memory usage
 	TrimMemory,
 
@@ -234,6 +314,40 @@ pub(super) enum DebugCommand {
 		level: Option<i32>,
 	},
 
+	/// - Remove short event ID and short state key mappings which are no longer
+	///   referenced by any event, state diff or auth chain
+	///
+	/// Federation and local state changes are held off in every room while the
+	/// mappings are removed.
+	GcShortIds {
+		/// Only count what would be removed
+		#[arg(long)]
+		dry_run: bool,
+	},
+
+	/// - Show how the state of rooms is stored: the diff layers of a room's
+	///   current state and how many full state snapshots were taken
+	StateCompressionInfo {
+		/// Only show this room
+		room: Option<OwnedRoomOrAliasId>,
+	},
+
+	/// - Count the decryption failures clients reported, by room, by the server
+	///   of the events' senders and by why decryption failed
+	UisiStats {
+		/// Only count those in this room
+		#[arg(long)]
+		room: Option<OwnedRoomOrAliasId>,
+
+		/// Only count those reported within this long, e.g. "1d"
+		#[arg(long)]
+		since: Option<String>,
+	},
+
+	/// - Show how many missing database index entries were restored since the
+	///   server started
+	Repairs,
+
 	/// - Developer test stubs
 	#[command(subcommand)]
 	#[allow(non_snake_case)]
@@ -0,0 +1,277 @@
+use std::{fmt::Write, sync::Arc};
+
+use conduwuit::{
+	Err, Result, err, info,
+	utils::{self, IterStream, content_disposition::make_content_disposition},
+};
+use conduwuit_database::Lane;
+use futures::{StreamExt, TryStreamExt};
+use ruma::{
+	Mxc, OwnedEventId, OwnedRoomOrAliasId, UInt,
+	events::room::message::{
+		FileInfo, FileMessageEventContent, MessageType, RoomMessageEventContent,
+	},
+};
+use service::{
+	Services,
+	media::MXC_LENGTH,
+	rooms::{
+		short::{ShortEventId, ShortStateHash, ShortStateKey},
+		state_compressor::{CompressedState, HashSetCompressStateEvent},
+	},
+};
+
+use crate::admin_command;
+
+/// Largest state shown in the admin room's message itself; more is uploaded
+/// as a file.
+const INLINE_MAX: usize = 32 * 1024;
+
+/// A state entry which could not be shown.
+#[derive(Debug, Eq, PartialEq)]
+pub(super) enum Unresolved {
+	/// The short ID of the event is not mapped to an event ID.
+	ShortEventId(ShortStateKey, ShortEventId),
+
+	/// The event is not stored.
+	Pdu(OwnedEventId),
+}
+
+#[admin_command]
+pub(super) async fn room_state(
+	&self,
+	room: OwnedRoomOrAliasId,
+	event_id: Option<OwnedEventId>,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let state_accessor = &self.services.rooms.state_accessor;
+	let shortstatehash = match &event_id {
+		| Some(event_id) => state_accessor
+			.pdu_shortstatehash(event_id)
+			.await
+			.map_err(|e| err!("No state is stored at {event_id}: {e}"))?,
+		| None => self
+			.services
+			.rooms
+			.state
+			.get_room_shortstatehash(&room_id)
+			.await
+			.map_err(|e| err!("No current state is stored of {room_id}: {e}"))?,
+	};
+
+	let full_state = full_state(self.services, shortstatehash).await?;
+	let state_hash = utils::calculate_hash(full_state.iter().map(|entry| &entry[..]));
+
+	let shortids: Vec<(ShortStateKey, ShortEventId)> = state_accessor
+		.state_full_shortids(shortstatehash)
+		.try_collect()
+		.await?;
+
+	// Every event of the state is read, which for large rooms is a long scan.
+	let (events, unresolved) = Lane::Background
+		.scope(async {
+			let event_ids: Vec<Result<OwnedEventId>> = self
+				.services
+				.rooms
+				.short
+				.multi_get_eventid_from_short(shortids.iter().map(|&(_, id)| id).stream())
+				.collect()
+				.await;
+
+			let mut events = Vec::with_capacity(shortids.len());
+			let mut unresolved = Vec::new();
+			for (&(shortstatekey, shorteventid), event_id) in shortids.iter().zip(event_ids) {
+				let Ok(event_id) = event_id else {
+					unresolved.push(Unresolved::ShortEventId(shortstatekey, shorteventid));
+					continue;
+				};
+
+				match self.services.rooms.timeline.get_pdu_json(&event_id).await {
+					| Ok(pdu) => events.push(pdu),
+					| Err(_) => unresolved.push(Unresolved::Pdu(event_id)),
+				}
+			}
+
+			(events, unresolved)
+		})
+		.await;
+
+	let header = header(shortstatehash, &state_hash, events.len(), &unresolved);
+	let json = serde_json::to_string_pretty(&events)?;
+	if json.len() <= INLINE_MAX {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"```json\n{header}{json}\n```"
+		)));
+	}
+
+	let filename = format!("state-{room_id}-{shortstatehash}.json");
+	let file = [header.as_bytes(), json.as_bytes()].concat();
+	let mxc = Mxc {
+		server_name: self.services.globals.server_name(),
+		media_id: &utils::random_string(MXC_LENGTH),
+	};
+
+	let content_type = "application/json";
+	let content_disposition = make_content_disposition(None, Some(content_type), Some(&filename));
+
+	self.services
+		.media
+		.create(
+			&mxc,
+			Some(&self.services.globals.server_user),
+			Some(&content_disposition),
+			Some(content_type),
+			&file,
+		)
+		.await?;
+
+	let mut info = FileInfo::new();
+	info.mimetype = Some(content_type.to_owned());
+	info.size = UInt::try_from(file.len()).ok();
+
+	let mut content = FileMessageEventContent::plain(header, mxc.to_string().into());
+	content.filename = Some(filename);
+	content.info = Some(Box::new(info));
+
+	Ok(RoomMessageEventContent::new(MessageType::File(content)))
+}
+
+#[admin_command]
+pub(super) async fn force_set_room_state(
+	&self,
+	room: OwnedRoomOrAliasId,
+	event_id: OwnedEventId,
+	yes_i_want_to_do_this: bool,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let pdu = self
+		.services
+		.rooms
+		.timeline
+		.get_pdu(&event_id)
+		.await
+		.map_err(|e| err!("Event {event_id} is not stored: {e}"))?;
+
+	if pdu.room_id != room_id {
+		return Err!("Event {event_id} is in {}, not {room_id}.", pdu.room_id);
+	}
+
+	let shortstatehash = self
+		.services
+		.rooms
+		.state_accessor
+		.pdu_shortstatehash(&event_id)
+		.await
+		.map_err(|e| err!("No state is stored at {event_id}: {e}"))?;
+
+	let current = match self
+		.services
+		.rooms
+		.state
+		.get_room_shortstatehash(&room_id)
+		.await
+	{
+		| Ok(current) if current == shortstatehash => {
+			return Ok(RoomMessageEventContent::notice_markdown(format!(
+				"The state at {event_id} is the current state of {room_id} already."
+			)));
+		},
+		| Ok(current) => full_state(self.services, current).await?,
+		| Err(_) => Arc::default(),
+	};
+
+	let target = full_state(self.services, shortstatehash).await?;
+	let (added, removed) = changes(&current, &target);
+	if !yes_i_want_to_do_this {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"Forcing the state of {room_id} to that at {event_id} (shortstatehash \
+			 {shortstatehash}) would add {added} and remove {removed} state entries. Nothing is \
+			 sent to the room. Pass the --yes-i-want-to-do-this flag to do it."
+		)));
+	}
+
+	let state_lock = self.services.rooms.state.mutex.lock(&room_id).await;
+
+	// The diff from the current state is saved by the compressor, so the forced
+	// state is a layer above the current one like any other state change.
+	let HashSetCompressStateEvent {
+		shortstatehash,
+		added: added_diff,
+		removed: removed_diff,
+	} = self
+		.services
+		.rooms
+		.state_compressor
+		.save_state(&room_id, target)
+		.await?;
+
+	let (added, removed) = (added_diff.len(), removed_diff.len());
+	self.services
+		.rooms
+		.state
+		.force_state(&room_id, shortstatehash, added_diff, removed_diff, &state_lock)
+		.await?;
+
+	// force_state drops the hierarchy for the space children it adds, but those
+	// removed change it as well.
+	self.services.rooms.spaces.clear_room_hierarchy(&room_id);
+
+	drop(state_lock);
+	info!(%room_id, %event_id, shortstatehash, added, removed, "Forced room state");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Forced the state of {room_id} to that at {event_id} (shortstatehash {shortstatehash}): \
+		 added {added} and removed {removed} state entries."
+	)))
+}
+
+/// The state entries added and removed changing from `current` to `target`.
+pub(super) fn changes(current: &CompressedState, target: &CompressedState) -> (usize, usize) {
+	(target.difference(current).count(), current.difference(target).count())
+}
+
+async fn full_state(
+	services: &Services,
+	shortstatehash: ShortStateHash,
+) -> Result<Arc<CompressedState>> {
+	Ok(services
+		.rooms
+		.state_compressor
+		.load_shortstatehash_info(shortstatehash)
+		.await?
+		.pop()
+		.expect("at least one layer")
+		.full_state)
+}
+
+/// The comment heading the state, saying which snapshot it is and what of it
+/// could not be shown.
+pub(super) fn header(
+	shortstatehash: ShortStateHash,
+	state_hash: &[u8],
+	events: usize,
+	unresolved: &[Unresolved],
+) -> String {
+	let state_hash: String = state_hash.iter().fold(String::new(), |mut hex, byte| {
+		write!(hex, "{byte:02x}").expect("written to string");
+		hex
+	});
+
+	let mut out = format!(
+		"// shortstatehash: {shortstatehash}\n// state hash: {state_hash}\n// events: {events}\n"
+	);
+
+	for entry in unresolved {
+		let line = match entry {
+			| Unresolved::ShortEventId(shortstatekey, shorteventid) => format!(
+				"// unresolved: shorteventid {shorteventid} of shortstatekey {shortstatekey} \
+				 has no event ID"
+			),
+			| Unresolved::Pdu(event_id) => format!("// unresolved: {event_id} is not stored"),
+		};
+
+		writeln!(out, "{line}").expect("written to string");
+	}
+
+	out
+}
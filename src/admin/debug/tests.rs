@@ -0,0 +1,84 @@
+use ruma::owned_event_id;
+
+use super::{
+	db::{hex, parse_key, render, similar},
+	room_state::{Unresolved, changes, header},
+};
+
+#[test]
+fn room_state_header() {
+	let unresolved = [
+		Unresolved::ShortEventId(7, 42),
+		Unresolved::Pdu(owned_event_id!("$missing:example.org")),
+	];
+
+	let header = header(12, &[0x00, 0xAB, 0x0F], 3, &unresolved);
+	let lines: Vec<_> = header.lines().collect();
+
+	assert_eq!(lines, [
+		"// shortstatehash: 12",
+		"// state hash: 00ab0f",
+		"// events: 3",
+		"// unresolved: shorteventid 42 of shortstatekey 7 has no event ID",
+		"// unresolved: $missing:example.org is not stored",
+	]);
+
+	assert!(header.ends_with('\n'));
+	assert_eq!(header(1, &[], 0, &[]).lines().count(), 3);
+}
+
+#[test]
+fn db_keys_parsed() {
+	assert_eq!(parse_key("@alice:example.org").unwrap(), b"@alice:example.org");
+	assert_eq!(parse_key("0x00ff7a").unwrap(), [0x00, 0xFF, 0x7A]);
+	assert_eq!(parse_key("0xABcd").unwrap(), [0xAB, 0xCD]);
+	assert_eq!(parse_key("").unwrap(), b"");
+	assert_eq!(parse_key("0x").unwrap(), b"");
+	parse_key("0xabc").expect_err("odd number of digits");
+	parse_key("0xzz").expect_err("not hex");
+
+	assert_eq!(hex(&[0x00, 0xFF, 0x7A]), "0x00ff7a");
+	assert_eq!(hex(&[]), "0x");
+}
+
+#[test]
+fn db_values_rendered() {
+	let json = render(br#"{"a":1}"#).expect("rendered");
+	assert_eq!(json, "{\n  \"a\": 1\n}");
+
+	let key = b"@alice:example.org\xFF!room:example.org";
+	assert_eq!(render(key).unwrap(), r#""@alice:example.org" 0xff "!room:example.org""#);
+
+	let count = [b'!', b'r', 0xFF, 0, 0, 0, 0, 0, 0, 0, 42];
+	assert_eq!(render(&count).unwrap(), r#""!r" 0xff 0x000000000000002a"#);
+
+	assert_eq!(render(&[0, 0, 0, 7]), None);
+	assert_eq!(render(b"42").unwrap(), r#""42""#, "JSON scalars are shown as text");
+}
+
+#[test]
+fn db_similar_columns() {
+	let names = ["userid_password", "userid_displayname", "roomid_joinedcount", "pduid_pdu"];
+	assert_eq!(similar("userid_pasword", &names), ["userid_password"]);
+	assert_eq!(similar("userid", &names), ["userid_password", "userid_displayname"]);
+	assert_eq!(similar("pdu", &names), ["pduid_pdu"]);
+	assert!(similar("nothing_like_it", &names).is_empty());
+}
+
+#[test]
+fn force_set_room_state_changes() {
+	let entry = |shortstatekey: u64, shorteventid: u64| -> [u8; 16] {
+		[shortstatekey.to_be_bytes(), shorteventid.to_be_bytes()]
+			.concat()
+			.try_into()
+			.expect("16 bytes")
+	};
+
+	let current = [entry(1, 10), entry(2, 20), entry(3, 30)].into();
+	let target = [entry(1, 10), entry(2, 21)].into();
+
+	assert_eq!(changes(&current, &target), (1, 2));
+	assert_eq!(changes(&target, &current), (2, 1));
+	assert_eq!(changes(&current, &current), (0, 0));
+	assert_eq!(changes(&Default::default(), &target), (2, 0));
+}
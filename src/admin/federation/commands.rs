@@ -1,9 +1,20 @@
-use std::fmt::Write;
+use std::{
+	fmt::Write,
+	time::{Duration, Instant},
+};
 
-use conduwuit::Result;
+use conduwuit::{
+	Err, Result,
+	utils::time::{now_millis, pretty},
+};
 use futures::StreamExt;
 use ruma::{
-	OwnedRoomId, RoomId, ServerName, UserId, events::room::message::RoomMessageEventContent,
+	OwnedRoomId, RoomId, ServerName, UInt, UserId, api::federation::directory::get_public_rooms,
+	events::room::message::RoomMessageEventContent,
+};
+use service::{
+	client::connections::Host,
+	sending::{BEHIND, Queue},
 };
 
 use crate::{admin_command, get_room_info};
@@ -133,3 +144,212 @@ pub(super) async fn remote_user_in_rooms(
 
 	Ok(RoomMessageEventContent::text_markdown(output))
 }
+
+#[admin_command]
+pub(super) async fn lag(&self, limit: usize) -> Result<RoomMessageEventContent> {
+	let lag = self.services.sending.lag();
+	let mut msg = format!(
+		"Destinations with unsent PDUs: {}\nOldest: {:?}\n95th percentile: {:?}\nMore than {:?} \
+		 behind: {}\nDormant: {}\n",
+		lag.destinations, lag.max, lag.p95, BEHIND, lag.behind, lag.dormant,
+	);
+
+	let lagging = self.services.sending.lagging();
+	if !lagging.is_empty() {
+		writeln!(msg, "\n```")?;
+		for (server, age, dormant) in lagging.iter().take(limit) {
+			let dormant = if *dormant { " (dormant)" } else { "" };
+			writeln!(msg, "{server} | {age:?}{dormant}")?;
+		}
+		writeln!(msg, "```")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn queues(
+	&self,
+	server_name: Option<Box<ServerName>>,
+	limit: usize,
+) -> Result<RoomMessageEventContent> {
+	let queues = self.services.sending.queues(server_name.as_deref()).await;
+	let mut msg = format!(
+		"Destinations: {}\nQueued PDUs: {}\nQueued EDUs: {}\nBacking off: {}\n",
+		queues.destinations.len(),
+		queues.pdus(),
+		queues.edus(),
+		queues.backing_off(),
+	);
+
+	let mut destinations: Vec<_> = queues.destinations.iter().collect();
+	destinations.sort_by(|a, b| {
+		let queued = |queue: &Queue| queue.pdus.saturating_add(queue.edus);
+		queued(b.1).cmp(&queued(a.1)).then_with(|| a.0.cmp(b.0))
+	});
+
+	let now = now_millis();
+	let apart = |millis: u64| pretty(Duration::from_millis(now.abs_diff(millis)));
+	for (server, queue) in destinations.into_iter().take(limit) {
+		write!(msg, "\n{server}: {} PDUs, {} EDUs", queue.pdus, queue.edus)?;
+		if let Some(backoff) = &queue.backoff {
+			write!(
+				msg,
+				"; failed {} times ({}), retrying in {}",
+				backoff.tries,
+				backoff.class,
+				apart(backoff.retry_at)
+			)?;
+		}
+		if let Some(last_success) = queue.last_success {
+			write!(msg, "; last sent {} ago", apart(last_success))?;
+		}
+		writeln!(msg)?;
+		if let Some(last_error) = &queue.last_error {
+			writeln!(msg, "```\n{last_error}\n```")?;
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn connections(&self, limit: usize) -> Result<RoomMessageEventContent> {
+	let hosts = self.services.client.connections.hosts();
+	let total = |count: fn(&Host) -> u64| {
+		hosts
+			.iter()
+			.map(|(_, counts)| count(counts))
+			.fold(0_u64, u64::saturating_add)
+	};
+
+	let mut msg = format!(
+		"Hosts: {}\nRequests: {}\nConnections opened: {}\nReused: {}\nOver HTTP/2: {}\n",
+		hosts.len(),
+		total(|host| host.requests),
+		total(|host| host.opened),
+		total(Host::reused),
+		total(|host| host.http2),
+	);
+
+	if !hosts.is_empty() {
+		writeln!(msg, "\n```\nhost | requests | opened | reused | HTTP/2")?;
+		for (host, counts) in hosts.iter().take(limit) {
+			writeln!(
+				msg,
+				"{host} | {} | {} | {} | {}",
+				counts.requests,
+				counts.opened,
+				counts.reused(),
+				counts.http2
+			)?;
+		}
+		writeln!(msg, "```")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn retry(
+	&self,
+	server_name: Box<ServerName>,
+) -> Result<RoomMessageEventContent> {
+	self.services.sending.retry(&server_name)?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Retrying {server_name} now; see `federation queues {server_name}` for how it goes."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn mark_defunct(
+	&self,
+	server_name: Box<ServerName>,
+	hide_heroes: bool,
+) -> Result<RoomMessageEventContent> {
+	if self.services.globals.server_is_ours(&server_name) {
+		return Err!("Not allowed to mark ourselves defunct.");
+	}
+
+	let backoff = self
+		.services
+		.sending
+		.mark_defunct(&server_name, hide_heroes)
+		.await?;
+
+	let heroes = if hide_heroes {
+		" Its users are left out of room summaries."
+	} else {
+		""
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Marked {server_name} defunct; retrying it in {}.{heroes} It counts as healthy again \
+		 once anything is exchanged with it.",
+		pretty(backoff.remaining(now_millis())),
+	)))
+}
+
+#[admin_command]
+pub(super) async fn test_auth(
+	&self,
+	server_name: Box<ServerName>,
+) -> Result<RoomMessageEventContent> {
+	if server_name == self.services.server.name {
+		return Err!("Not allowed to send federation requests to ourselves.");
+	}
+
+	let trust = self.services.client.federation_tls.path(&server_name);
+	let mut request = get_public_rooms::v1::Request::new();
+	request.limit = Some(UInt::from(1_u32));
+
+	let timer = Instant::now();
+	let result = self
+		.services
+		.sending
+		.send_federation_request(&server_name, request)
+		.await;
+
+	let elapsed = pretty(timer.elapsed());
+	let outcome = match result {
+		| Ok(_) => "Accepted our signed request.".to_owned(),
+		| Err(e) => format!("Failed: {e}"),
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"**{server_name}** after {elapsed}\n\nTLS trusted by: {trust}\n\n{outcome}"
+	)))
+}
+
+#[admin_command]
+pub(super) async fn refresh_keys(
+	&self,
+	server_name: Box<ServerName>,
+) -> Result<RoomMessageEventContent> {
+	if server_name == self.services.server.name {
+		return Err!("Our own signing keys are not fetched from anywhere.");
+	}
+
+	let refreshed = self.services.server_keys.refresh_keys(&server_name).await?;
+	let origin = if refreshed.from_origin {
+		"answered"
+	} else {
+		"did not answer"
+	};
+	let mut out = format!(
+		"Refreshed the signing keys of **{server_name}**: it {origin} and {} notaries did; \
+		 forgot {} verified events.\n\nValid until {:?}:\n",
+		refreshed.notaries, refreshed.evicted, refreshed.keys.valid_until_ts,
+	);
+
+	for key_id in refreshed.keys.verify_keys.keys() {
+		writeln!(out, "- `{key_id}`")?;
+	}
+
+	for (key_id, old) in &refreshed.keys.old_verify_keys {
+		writeln!(out, "- `{key_id}` (expired {:?})", old.expired_ts)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
@@ -39,4 +39,83 @@ pub(super) enum FederationCommand {
 	RemoteUserInRooms {
 		user_id: Box<UserId>,
 	},
+
+	/// - Show how far behind outbound federation is
+	///
+	/// Reports the age of the oldest unsent PDU across destinations, followed
+	/// by the destinations furthest behind. Destinations in long-term backoff
+	/// are counted as dormant and excluded from the ages.
+	Lag {
+		/// Number of destinations to list.
+		#[arg(short, long, default_value("10"))]
+		limit: usize,
+	},
+
+	/// - Show the sending queue of each destination
+	///
+	/// Lists how many PDUs and EDUs are queued for each destination, how
+	/// many transactions to it failed in a row, the kind of error which set
+	/// off its backoff and when it is next retried, its last error and when a
+	/// transaction to it last succeeded, after the totals over all
+	/// destinations.
+	Queues {
+		/// Only show this destination.
+		server_name: Option<Box<ServerName>>,
+
+		/// Number of destinations to list.
+		#[arg(short, long, default_value("20"))]
+		limit: usize,
+	},
+
+	/// - Show how often connections to federation hosts are reused
+	///
+	/// Lists for each host the requests answered since the server started,
+	/// the connections opened to it, how many requests went over a connection
+	/// opened before, and how many over HTTP/2. Hosts given by IP address are
+	/// not counted.
+	Connections {
+		/// Number of hosts to list.
+		#[arg(short, long, default_value("20"))]
+		limit: usize,
+	},
+
+	/// - Mark a destination defunct, as if it is gone for good
+	///
+	/// Leaves it dormant in the longest backoff instead of retrying it more
+	/// often, until anything is exchanged with it again; it then counts as
+	/// healthy without being unmarked. Destinations which consistently fail
+	/// with 403, 410 or missing DNS records are reported to this room once.
+	MarkDefunct {
+		server_name: Box<ServerName>,
+
+		/// Also leave its users out of the heroes room summaries are named
+		/// after.
+		#[arg(long)]
+		hide_heroes: bool,
+	},
+
+	/// - Fetch the signing keys of a server again
+	///
+	/// Asks the server itself and the configured trusted key servers for its
+	/// keys even when we have them, such as after it rotated them, and
+	/// forgets which of its events were verified with the keys we had. Keys
+	/// it no longer lists are kept as expired old keys.
+	RefreshKeys {
+		server_name: Box<ServerName>,
+	},
+
+	/// - Retry sending to a destination now, ending its backoff
+	Retry {
+		server_name: Box<ServerName>,
+	},
+
+	/// - Test whether a server accepts our signed federation requests
+	///
+	/// Asks the server for a room of its public room directory, reporting how
+	/// its TLS certificate is trusted and whether the request went through. A
+	/// failed TLS handshake tells a pinned key mismatch apart from other
+	/// certificate errors.
+	TestAuth {
+		server_name: Box<ServerName>,
+	},
 }
@@ -3,12 +3,13 @@
 use conduwuit::{
 	Result, debug, debug_info, debug_warn, error, info, trace, utils::time::parse_timepoint_ago,
 };
-use conduwuit_service::media::Dim;
+use conduwuit_service::{Services, media::Dim};
 use ruma::{
 	EventId, Mxc, MxcUri, OwnedMxcUri, OwnedServerName, ServerName,
 	events::room::message::RoomMessageEventContent,
 };
 
+use super::list::{code_block, pretty_bytes};
 use crate::{admin_command, utils::parse_local_user_id};
 
 #[admin_command]
@@ -25,14 +26,16 @@ pub(super) async fn delete(
 
 	if let Some(mxc) = mxc {
 		trace!("Got MXC URL: {mxc}");
-		self.services
+		let freed = self
+			.services
 			.media
-			.delete(&mxc.as_str().try_into()?)
+			.delete_freed(&mxc.as_str().try_into()?)
 			.await?;
 
-		return Ok(RoomMessageEventContent::text_plain(
-			"Deleted the MXC from our database and on our filesystem.",
-		));
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"Deleted the MXC from our database and on our filesystem, freeing {}.",
+			pretty_bytes(freed)
+		)));
 	}
 
 	if let Some(event_id) = event_id {
@@ -150,56 +153,43 @@ pub(super) async fn delete(
 			));
 		}
 
-		let mut mxc_deletion_count: usize = 0;
-
-		for mxc_url in mxc_urls {
-			match self
-				.services
-				.media
-				.delete(&mxc_url.as_str().try_into()?)
-				.await
-			{
-				| Ok(()) => {
-					debug_info!("Successfully deleted {mxc_url} from filesystem and database");
-					mxc_deletion_count = mxc_deletion_count.saturating_add(1);
-				},
-				| Err(e) => {
-					debug_warn!("Failed to delete {mxc_url}, ignoring error and skipping: {e}");
-					continue;
-				},
-			}
-		}
+		let mxc_urls = mxc_urls
+			.iter()
+			.map(|mxc_url| mxc_url.as_str().try_into())
+			.collect::<Result<Vec<Mxc<'_>>, _>>()?;
+
+		let (mxc_deletion_count, freed) = delete_mxcs(self.services, &mxc_urls).await;
 
 		return Ok(RoomMessageEventContent::text_plain(format!(
 			"Deleted {mxc_deletion_count} total MXCs from our database and the filesystem from \
-			 event ID {event_id}."
+			 event ID {event_id}, freeing {}.",
+			pretty_bytes(freed)
 		)));
 	}
 
+	if code_block(self.body).is_some() {
+		return self.delete_list().await;
+	}
+
 	Ok(RoomMessageEventContent::text_plain(
-		"Please specify either an MXC using --mxc or an event ID using --event-id of the \
-		 message containing an image. See --help for details.",
+		"Please specify either an MXC using --mxc, an event ID using --event-id of the message \
+		 containing an image, or a code block of MXCs. See --help for details.",
 	))
 }
 
 #[admin_command]
 pub(super) async fn delete_list(&self) -> Result<RoomMessageEventContent> {
-	if self.body.len() < 2
-		|| !self.body[0].trim().starts_with("```")
-		|| self.body.last().unwrap_or(&"").trim() != "```"
-	{
+	let Some(lines) = code_block(self.body) else {
 		return Ok(RoomMessageEventContent::text_plain(
 			"Expected code block in command body. Add --help for details.",
 		));
-	}
+	};
 
 	let mut failed_parsed_mxcs: usize = 0;
 
-	let mxc_list = self
-		.body
-		.to_vec()
-		.drain(1..self.body.len().checked_sub(1).unwrap())
-		.filter_map(|mxc_s| {
+	let mxc_list = lines
+		.iter()
+		.filter_map(|&mxc_s| {
 			mxc_s
 				.try_into()
 				.inspect_err(|e| {
@@ -211,26 +201,37 @@ pub(super) async fn delete_list(&self) -> Result<RoomMessageEventContent> {
 		})
 		.collect::<Vec<Mxc<'_>>>();
 
+	let (mxc_deletion_count, freed) = delete_mxcs(self.services, &mxc_list).await;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Finished bulk MXC deletion, deleted {mxc_deletion_count} total MXCs from our database \
+		 and the filesystem, freeing {}. {failed_parsed_mxcs} MXCs failed to be parsed from the \
+		 database.",
+		pretty_bytes(freed)
+	)))
+}
+
+/// Deletes the files, ignoring those failing, and returns how many were
+/// deleted and the bytes freed.
+async fn delete_mxcs(services: &Services, mxcs: &[Mxc<'_>]) -> (usize, u64) {
 	let mut mxc_deletion_count: usize = 0;
+	let mut freed: u64 = 0;
 
-	for mxc in &mxc_list {
-		trace!(%failed_parsed_mxcs, %mxc_deletion_count, "Deleting MXC {mxc} in bulk");
-		match self.services.media.delete(mxc).await {
-			| Ok(()) => {
+	for mxc in mxcs {
+		trace!(%mxc_deletion_count, "Deleting MXC {mxc} in bulk");
+		match services.media.delete_freed(mxc).await {
+			| Ok(bytes) => {
 				debug_info!("Successfully deleted {mxc} from filesystem and database");
 				mxc_deletion_count = mxc_deletion_count.saturating_add(1);
+				freed = freed.saturating_add(bytes);
 			},
 			| Err(e) => {
 				debug_warn!("Failed to delete {mxc}, ignoring error and skipping: {e}");
-				continue;
 			},
 		}
 	}
 
-	Ok(RoomMessageEventContent::text_plain(format!(
-		"Finished bulk MXC deletion, deleted {mxc_deletion_count} total MXCs from our database \
-		 and the filesystem. {failed_parsed_mxcs} MXCs failed to be parsed from the database.",
-	)))
+	(mxc_deletion_count, freed)
 }
 
 #[admin_command]
@@ -272,10 +273,15 @@ pub(super) async fn delete_all_from_user(
 ) -> Result<RoomMessageEventContent> {
 	let user_id = parse_local_user_id(self.services, &username)?;
 
-	let deleted_count = self.services.media.delete_from_user(&user_id).await?;
+	let (deleted_count, freed) = self
+		.services
+		.media
+		.delete_from_user_before(&user_id, None)
+		.await?;
 
 	Ok(RoomMessageEventContent::text_plain(format!(
-		"Deleted {deleted_count} total files.",
+		"Deleted {deleted_count} total files, freeing {}.",
+		pretty_bytes(freed)
 	)))
 }
 
@@ -345,8 +351,12 @@ pub(super) async fn delete_all_from_server(
 pub(super) async fn get_file_info(&self, mxc: OwnedMxcUri) -> Result<RoomMessageEventContent> {
 	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
 	let metadata = self.services.media.get_metadata(&mxc).await;
+	let uploader = self.services.media.get_uploader(&mxc).await;
+	let info = self.services.media.get_info(&mxc).await;
 
-	Ok(RoomMessageEventContent::notice_markdown(format!("```\n{metadata:#?}\n```")))
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"```\n{metadata:#?}\nuploader: {uploader:?}\n{info:#?}\n```"
+	)))
 }
 
 #[admin_command]
@@ -0,0 +1,120 @@
+use std::{
+	fmt::Write,
+	time::{Duration, UNIX_EPOCH},
+};
+
+use conduwuit::{
+	Result,
+	utils::{bytes::pretty, time},
+};
+use conduwuit_service::media::{Upload, page};
+use ruma::{Mxc, OwnedRoomOrAliasId, events::room::message::RoomMessageEventContent};
+
+use crate::{
+	admin_command,
+	utils::{parse_local_user_id, parse_timestamp},
+};
+
+#[admin_command]
+pub(super) async fn list_user(
+	&self,
+	user_id: String,
+	from: usize,
+	limit: usize,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let uploads = self.services.media.get_uploads(&user_id).await;
+	let (uploads, next) = page(uploads, from, limit);
+
+	Ok(RoomMessageEventContent::notice_markdown(uploads_table(&uploads, next)))
+}
+
+#[admin_command]
+pub(super) async fn list_room(
+	&self,
+	room: OwnedRoomOrAliasId,
+	from: usize,
+	limit: usize,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let mut uploads = Vec::new();
+	for mxc in self.services.rooms.usage.media(&room_id).await {
+		let Ok(mxc) = <Mxc<'_>>::try_from(mxc.as_str()) else {
+			continue;
+		};
+
+		if let Some(upload) = self.services.media.get_upload(&mxc).await {
+			uploads.push(upload);
+		}
+	}
+
+	uploads.sort_by(|a, b| b.uploaded_ts.cmp(&a.uploaded_ts));
+	let (uploads, next) = page(uploads, from, limit);
+
+	Ok(RoomMessageEventContent::notice_markdown(uploads_table(&uploads, next)))
+}
+
+#[admin_command]
+pub(super) async fn delete_user(
+	&self,
+	user_id: String,
+	before: String,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let before = parse_timestamp(&before)?;
+	let (deleted_count, freed) = self
+		.services
+		.media
+		.delete_from_user_before(&user_id, Some(before))
+		.await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Deleted {deleted_count} total files, freeing {}.",
+		pretty_bytes(freed)
+	)))
+}
+
+/// Renders a page of files as a markdown table, with how to get the next.
+pub(super) fn uploads_table(uploads: &[Upload], next: Option<usize>) -> String {
+	if uploads.is_empty() {
+		return "No media.".to_owned();
+	}
+
+	let mut out =
+		String::from("| MXC URI | Uploaded | Size | Content type |\n| --- | --- | --- | --- |\n");
+
+	for upload in uploads {
+		let uploaded = upload.uploaded_ts.map_or_else(
+			|| "unknown".to_owned(),
+			|ts| time::format(UNIX_EPOCH + Duration::from_millis(ts), "%Y-%m-%d %H:%M:%S"),
+		);
+
+		let content_type = upload.content_type.as_deref().unwrap_or("unknown");
+		writeln!(
+			out,
+			"| {} | {uploaded} | {} | {content_type} |",
+			upload.mxc,
+			pretty_bytes(upload.size),
+		)
+		.expect("written to string");
+	}
+
+	if let Some(next) = next {
+		write!(out, "\nMore media; pass `--from {next}` for the next page.")
+			.expect("written to string");
+	}
+
+	out
+}
+
+/// The lines inside the code block a command body consists of.
+pub(super) fn code_block<'a>(body: &'a [&'a str]) -> Option<&'a [&'a str]> {
+	let (first, rest) = body.split_first()?;
+	let (last, lines) = rest.split_last()?;
+
+	(first.trim().starts_with("```") && last.trim() == "```").then_some(lines)
+}
+
+pub(super) fn pretty_bytes(bytes: u64) -> String {
+	pretty(bytes.try_into().unwrap_or(usize::MAX))
+}
@@ -1,9 +1,12 @@
 #![allow(rustdoc::broken_intra_doc_links)]
 mod commands;
+mod list;
+#[cfg(test)]
+mod tests;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::{EventId, MxcUri, OwnedMxcUri, OwnedServerName, ServerName};
+use ruma::{EventId, MxcUri, OwnedMxcUri, OwnedRoomOrAliasId, OwnedServerName, ServerName};
 
 use crate::admin_command_dispatch;
 
@@ -11,7 +14,8 @@
 #[derive(Debug, Subcommand)]
 pub(super) enum MediaCommand {
 	/// - Deletes a single media file from our database and on the filesystem
-	///   via a single MXC URL or event ID (not redacted)
+	///   via a single MXC URL or event ID (not redacted), or those of a
+	///   codeblock list of MXC URLs
 	Delete {
 		/// The MXC URL to delete
 		#[arg(long)]
@@ -54,6 +58,43 @@ pub(super) enum MediaCommand {
 		username: String,
 	},
 
+	/// - Deletes the media a local user uploaded before a time, given in
+	///   milliseconds since the unix epoch or as a relative time (e.g. 30d)
+	DeleteUser {
+		user_id: String,
+
+		/// - Only delete media uploaded before this time
+		#[arg(long)]
+		before: String,
+	},
+
+	/// - Lists the media a local user uploaded, most recent first
+	ListUser {
+		user_id: String,
+
+		/// - Where the page of media listed starts
+		#[arg(long, default_value("0"))]
+		from: usize,
+
+		/// - The most media listed
+		#[arg(long, default_value("50"))]
+		limit: usize,
+	},
+
+	/// - Lists the stored media the recent messages and stickers of a room
+	///   reference, most recently uploaded first
+	ListRoom {
+		room: OwnedRoomOrAliasId,
+
+		/// - Where the page of media listed starts
+		#[arg(long, default_value("0"))]
+		from: usize,
+
+		/// - The most media listed
+		#[arg(long, default_value("50"))]
+		limit: usize,
+	},
+
 	/// - Deletes all remote media from the specified remote server. This will
 	///   always ignore errors by default.
 	DeleteAllFromServer {
@@ -64,6 +105,8 @@ pub(super) enum MediaCommand {
 		yes_i_want_to_delete_local_media: bool,
 	},
 
+	/// - Show the metadata, uploader and media info of a file
+	#[clap(alias = "info")]
 	GetFileInfo {
 		/// The MXC URL to lookup info for.
 		mxc: OwnedMxcUri,
@@ -0,0 +1,56 @@
+use conduwuit::utils::time::now_millis;
+use conduwuit_service::media::Upload;
+
+use super::list::{code_block, uploads_table};
+use crate::utils::parse_timestamp;
+
+fn upload(mxc: &str, size: u64, uploaded_ts: Option<u64>) -> Upload {
+	Upload {
+		mxc: mxc.into(),
+		size,
+		content_type: Some("image/png".to_owned()),
+		uploaded_ts,
+	}
+}
+
+#[test]
+fn media_uploads_table() {
+	let uploads = [
+		upload("mxc://example.org/new", 2048, Some(1_700_000_000_000)),
+		upload("mxc://example.org/old", 10, None),
+	];
+
+	let table = uploads_table(&uploads, Some(2));
+	let rows: Vec<_> = table.lines().collect();
+
+	assert_eq!(rows[0], "| MXC URI | Uploaded | Size | Content type |");
+	assert!(rows[2].starts_with("| mxc://example.org/new | 2023-11-14 22:13:20 | "));
+	assert!(rows[2].ends_with(" | image/png |"));
+	assert!(rows[3].starts_with("| mxc://example.org/old | unknown | "));
+	assert!(table.ends_with("pass `--from 2` for the next page."));
+
+	assert!(!uploads_table(&uploads, None).contains("--from"));
+	assert_eq!(uploads_table(&[], None), "No media.");
+}
+
+#[test]
+fn media_parse_before() {
+	assert_eq!(parse_timestamp("1700000000000").expect("timestamp"), 1_700_000_000_000);
+
+	let day_ago = parse_timestamp("1d").expect("relative time");
+	let expected = now_millis().saturating_sub(24 * 60 * 60 * 1000);
+	assert!(day_ago.abs_diff(expected) < 60 * 1000);
+
+	assert!(parse_timestamp("yesterday").is_err());
+}
+
+#[test]
+fn media_code_block() {
+	let body = ["```", "mxc://example.org/a", "mxc://example.org/b", "```"];
+	assert_eq!(code_block(&body), Some(&body[1..3]));
+
+	assert_eq!(code_block(&["```", "```"]), Some(&[][..]));
+	assert_eq!(code_block(&["mxc://example.org/a"]), None);
+	assert_eq!(code_block(&["```", "mxc://example.org/a"]), None);
+	assert_eq!(code_block(&[]), None);
+}
@@ -10,7 +10,9 @@
 pub(crate) mod utils;
 
 pub(crate) mod appservice;
+pub(crate) mod cache;
 pub(crate) mod check;
+pub(crate) mod database;
 pub(crate) mod debug;
 pub(crate) mod federation;
 pub(crate) mod media;
@@ -0,0 +1,133 @@
+use std::{fmt::Write, path::PathBuf};
+
+use clap::ValueEnum;
+use conduwuit::{Err, Result};
+use conduwuit_database::Lane;
+use ruma::{OwnedRoomId, RoomId, events::room::message::RoomMessageEventContent};
+use serde_json::json;
+use service::rooms::{activity::Activity, state_growth::date};
+
+use crate::admin_command;
+
+/// How room activity is exported.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum Format {
+	/// A header line, then a line per room and day
+	Csv,
+
+	/// An array of an object per room and day
+	Json,
+}
+
+const CSV_HEADER: &str = "date,room_id,messages,senders,joins,leaves,redactions";
+
+#[admin_command]
+pub(super) async fn activity(
+	&self,
+	room_id: OwnedRoomId,
+	days: u64,
+) -> Result<RoomMessageEventContent> {
+	let activity = self.services.rooms.activity.activity(&room_id, days).await;
+
+	if activity.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(
+			"No activity has been counted in this room.",
+		));
+	}
+
+	let mut msg = format!(
+		"Activity in {room_id}:\n```\ndate       | messages | senders | joins | leaves | \
+		 redactions\n"
+	);
+	for (day, activity) in &activity {
+		writeln!(
+			msg,
+			"{} | {:>8} | {:>7} | {:>5} | {:>6} | {:>10}",
+			date(*day),
+			activity.messages,
+			activity.senders,
+			activity.joins,
+			activity.leaves,
+			activity.redactions
+		)?;
+	}
+	msg.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn activity_export(
+	&self,
+	path: PathBuf,
+	days: u64,
+	format: Format,
+	rooms: Vec<OwnedRoomId>,
+) -> Result<RoomMessageEventContent> {
+	let service = &self.services.rooms.activity;
+	if !service.is_enabled() {
+		return Err!("Room activity is not counted, as room_activity_retention_days is 0.");
+	}
+
+	let rows = if rooms.is_empty() {
+		Lane::Background.scope(service.all_activity(days)).await
+	} else {
+		let mut rows = Vec::new();
+		for room_id in rooms {
+			for (day, activity) in service.activity(&room_id, days).await {
+				rows.push((room_id.clone(), day, activity));
+			}
+		}
+
+		rows
+	};
+
+	let export = match format {
+		| Format::Csv => csv(&rows)?.into_bytes(),
+		| Format::Json => serde_json::to_vec_pretty(&json_rows(&rows))?,
+	};
+
+	tokio::fs::write(&path, export).await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Exported the activity of {} room days to {}.",
+		rows.len(),
+		path.display()
+	)))
+}
+
+fn csv(rows: &[(OwnedRoomId, u64, Activity)]) -> Result<String> {
+	let mut out = format!("{CSV_HEADER}\n");
+	for (room_id, day, activity) in rows {
+		writeln!(
+			out,
+			"{},{room_id},{},{},{},{},{}",
+			date(*day),
+			activity.messages,
+			activity.senders,
+			activity.joins,
+			activity.leaves,
+			activity.redactions
+		)?;
+	}
+
+	Ok(out)
+}
+
+fn json_rows(rows: &[(OwnedRoomId, u64, Activity)]) -> serde_json::Value {
+	rows.iter()
+		.map(|(room_id, day, activity)| json_row(room_id, *day, activity))
+		.collect()
+}
+
+fn json_row(room_id: &RoomId, day: u64, activity: &Activity) -> serde_json::Value {
+	json!({
+		"date": date(day),
+		"room_id": room_id,
+		"messages": activity.messages,
+		"senders": activity.senders,
+		"joins": activity.joins,
+		"leaves": activity.leaves,
+		"redactions": activity.redactions,
+	})
+}
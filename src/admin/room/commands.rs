@@ -1,7 +1,10 @@
+use std::fmt::Write;
+
 use conduwuit::Result;
 use futures::StreamExt;
 use ruma::{OwnedRoomId, events::room::message::RoomMessageEventContent};
 
+use super::freeze::describe;
 use crate::{PAGE_SIZE, admin_command, get_room_info};
 
 #[admin_command]
@@ -61,6 +64,49 @@ pub(super) async fn list_rooms(
 	Ok(RoomMessageEventContent::notice_markdown(output_plain))
 }
 
+#[admin_command]
+pub(super) async fn show(&self, room_id: OwnedRoomId) -> Result<RoomMessageEventContent> {
+	if !self.services.rooms.metadata.exists(&room_id).await {
+		return Ok(RoomMessageEventContent::text_plain("We do not know about this room."));
+	}
+
+	let rooms = &self.services.rooms;
+	let (_, members, name) = get_room_info(self.services, &room_id).await;
+	let local_members = rooms
+		.state_cache
+		.local_users_in_room(&room_id)
+		.count()
+		.await;
+
+	let version = rooms
+		.state
+		.get_room_version(&room_id)
+		.await
+		.map_or_else(|_| "unknown".to_owned(), |version| version.to_string());
+
+	let mut out = String::new();
+	if let Ok(replacement) = rooms
+		.state_accessor
+		.get_tombstone_replacement(&room_id)
+		.await
+	{
+		writeln!(out, "**Tombstoned**: replaced by {replacement}\n")?;
+	}
+
+	writeln!(out, "- Room: {room_id}")?;
+	writeln!(out, "- Name: {name}")?;
+	writeln!(out, "- Version: {version}")?;
+	writeln!(out, "- Members: {members} ({local_members} local)")?;
+	writeln!(out, "- Disabled: {}", rooms.metadata.is_disabled(&room_id).await)?;
+	writeln!(out, "- Banned: {}", rooms.metadata.is_banned(&room_id).await)?;
+	match rooms.freeze.get(&room_id).await {
+		| Some(freeze) => writeln!(out, "- Frozen: {}", describe(&freeze))?,
+		| None => writeln!(out, "- Frozen: false")?,
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
 #[admin_command]
 pub(super) async fn exists(&self, room_id: OwnedRoomId) -> Result<RoomMessageEventContent> {
 	let result = self.services.rooms.metadata.exists(&room_id).await;
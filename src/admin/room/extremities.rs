@@ -0,0 +1,270 @@
+use std::{collections::HashSet, fmt::Write};
+
+use conduwuit::{Result, matrix::pdu::PduBuilder, warn};
+use futures::{FutureExt, StreamExt};
+use ruma::{
+	OwnedEventId, OwnedRoomId, OwnedServerName, RoomId, api::federation::event::get_event,
+	events::room::message::RoomMessageEventContent,
+};
+use service::{Services, rooms::event_handler::Delivery};
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn extremities(&self, room_id: OwnedRoomId) -> Result<RoomMessageEventContent> {
+	let extremities: Vec<OwnedEventId> = self
+		.services
+		.rooms
+		.state
+		.get_forward_extremities(&room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	if extremities.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Room has no forward extremities; it is unknown or we are not in it.",
+		));
+	}
+
+	let mut msg = format!("Forward extremities of {room_id} ({}):\n```\n", extremities.len());
+	for event_id in &extremities {
+		let Ok(pdu) = self.services.rooms.timeline.get_pdu(event_id).await else {
+			writeln!(msg, "{event_id} | missing locally")?;
+			continue;
+		};
+
+		let missing = missing_auth_events(self.services, &pdu.auth_events).await;
+		let auth_chain = if missing == 0 {
+			"auth chain complete".to_owned()
+		} else {
+			format!("{missing} auth chain events missing")
+		};
+
+		writeln!(
+			msg,
+			"{event_id} | depth {} | origin_server_ts {} | {auth_chain}",
+			pdu.depth, pdu.origin_server_ts
+		)?;
+	}
+	msg.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn drop_extremity(
+	&self,
+	room_id: OwnedRoomId,
+	event_id: OwnedEventId,
+	force: bool,
+	verify: bool,
+) -> Result<RoomMessageEventContent> {
+	if !force {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Dropping a forward extremity abandons the branch of the room's history it leads; \
+			 events after it will not be referenced by events sent from this server. Only do \
+			 this for an extremity which is wedging the room. Re-run with --force to proceed.",
+		));
+	}
+
+	let state_lock = self.services.rooms.state.mutex.lock(&room_id).await;
+	let extremities: Vec<OwnedEventId> = self
+		.services
+		.rooms
+		.state
+		.get_forward_extremities(&room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	if !extremities.contains(&event_id) {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{event_id} is not a forward extremity of {room_id}."
+		)));
+	}
+
+	if extremities.len() == 1 {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Refusing to drop the only forward extremity; new events would have nothing to \
+			 reference. Try refetching the events it is missing instead.",
+		));
+	}
+
+	warn!(
+		%room_id,
+		%event_id,
+		"Dropping forward extremity by admin command; its branch of the room will no longer be \
+		 referenced by local events."
+	);
+
+	let remaining = extremities.iter().filter(|id| **id != event_id);
+	self.services
+		.rooms
+		.state
+		.set_forward_extremities(&room_id, remaining.map(|id| &**id), &state_lock)
+		.await;
+
+	drop(state_lock);
+	let mut msg = format!(
+		"Dropped forward extremity {event_id}; {} remain.",
+		extremities.len().saturating_sub(1)
+	);
+
+	if verify {
+		writeln!(msg, "\n{}", verify_send(self.services, &room_id).await)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn refetch(
+	&self,
+	room_id: OwnedRoomId,
+	event_id: OwnedEventId,
+	server: OwnedServerName,
+	verify: bool,
+) -> Result<RoomMessageEventContent> {
+	if !self.services.server.config.allow_federation {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Federation is disabled on this homeserver.",
+		));
+	}
+
+	if self.services.globals.server_is_ours(&server) {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to send federation requests to ourselves.",
+		));
+	}
+
+	let response = match self
+		.services
+		.sending
+		.send_federation_request(&server, get_event::v1::Request {
+			event_id: event_id.clone(),
+			include_unredacted_content: None,
+		})
+		.await
+	{
+		| Ok(response) => response,
+		| Err(e) =>
+			return Ok(RoomMessageEventContent::text_plain(format!(
+				"Failed to fetch {event_id} from {server}: {e}"
+			))),
+	};
+
+	let (parsed_room_id, parsed_id, value) = match self
+		.services
+		.rooms
+		.event_handler
+		.parse_incoming_pdu(&response.pdu)
+		.await
+	{
+		| Ok(parsed) => parsed,
+		| Err(e) =>
+			return Ok(RoomMessageEventContent::text_plain(format!(
+				"Failed to parse the PDU {server} sent: {e}"
+			))),
+	};
+
+	if parsed_id != event_id || parsed_room_id != room_id {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{server} sent {parsed_id} in {parsed_room_id} instead of {event_id} in {room_id}."
+		)));
+	}
+
+	let handled = {
+		let _federation_lock = self
+			.services
+			.rooms
+			.event_handler
+			.mutex_federation
+			.lock(&room_id)
+			.await;
+
+		self.services
+			.rooms
+			.event_handler
+			.handle_incoming_pdu(&server, &room_id, &event_id, value, true, Delivery::Admin)
+			.boxed()
+			.await
+	};
+
+	let mut msg = match handled {
+		| Ok(Some(_)) => format!(
+			"Fetched {event_id} from {server} and added it to the timeline; the room's state \
+			 was resolved again."
+		),
+		| Ok(None) => format!("Fetched {event_id} from {server} but it was not accepted."),
+		| Err(e) => format!("Fetched {event_id} from {server} but failed to handle it: {e}"),
+	};
+
+	if verify {
+		writeln!(msg, "\n{}", verify_send(self.services, &room_id).await)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+/// Number of events in the auth chain of `auth_events` which are not present
+/// locally.
+async fn missing_auth_events(services: &Services, auth_events: &[OwnedEventId]) -> usize {
+	let mut todo: Vec<OwnedEventId> = auth_events.to_vec();
+	let mut seen = HashSet::new();
+	let mut missing: usize = 0;
+	while let Some(event_id) = todo.pop() {
+		if !seen.insert(event_id.clone()) {
+			continue;
+		}
+
+		match services.rooms.timeline.get_pdu(&event_id).await {
+			| Ok(pdu) => todo.extend(
+				pdu.auth_events
+					.iter()
+					.filter(|id| !seen.contains(*id))
+					.cloned(),
+			),
+			| Err(_) => missing = missing.saturating_add(1),
+		}
+	}
+
+	missing
+}
+
+/// Builds, authorizes and signs a no-op event from a local member of the room
+/// against its current extremities and state, without persisting or sending
+/// it, to check that new events can be sent.
+async fn verify_send(services: &Services, room_id: &RoomId) -> String {
+	let Some(sender) = services
+		.rooms
+		.state_cache
+		.local_users_in_room(room_id)
+		.map(ToOwned::to_owned)
+		.boxed()
+		.next()
+		.await
+	else {
+		return "Cannot verify: no local user is joined to the room.".to_owned();
+	};
+
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+	let content = RoomMessageEventContent::notice_plain("");
+	let built = services
+		.rooms
+		.timeline
+		.create_hash_and_sign_event(PduBuilder::timeline(&content), &sender, room_id, &state_lock)
+		.await;
+
+	match built {
+		| Ok((pdu, _)) => format!(
+			"Verified: a test event from {sender} is authorized with prev_events {}.",
+			pdu.prev_events
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<_>>()
+				.join(", ")
+		),
+		| Err(e) => format!("Verification failed: a test event from {sender} was refused: {e}"),
+	}
+}
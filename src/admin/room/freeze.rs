@@ -0,0 +1,92 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use conduwuit::{
+	Err, Result, err,
+	matrix::pdu::PduBuilder,
+	utils::time::{format, parse_duration},
+	warn,
+};
+use ruma::{Int, OwnedRoomId, RoomId, events::room::message::RoomMessageEventContent};
+use service::{Services, rooms::freeze::Freeze};
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn freeze(
+	&self,
+	room_id: OwnedRoomId,
+	allow_power_level: i64,
+	duration: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	if !self.services.rooms.metadata.exists(&room_id).await {
+		return Err!("We do not know about this room.");
+	}
+
+	let power_level = Int::new(allow_power_level)
+		.ok_or_else(|| err!("Power level {allow_power_level} is out of range."))?;
+
+	let duration = duration.as_deref().map(parse_duration).transpose()?;
+	let freeze = self
+		.services
+		.rooms
+		.freeze
+		.freeze(&room_id, power_level, duration);
+
+	announce(self.services, &room_id).await;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Froze {room_id} {}.",
+		describe(&freeze)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unfreeze(&self, room_id: OwnedRoomId) -> Result<RoomMessageEventContent> {
+	if !self.services.rooms.freeze.unfreeze(&room_id).await {
+		return Err!("{room_id} is not frozen.");
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("Unfroze {room_id}.")))
+}
+
+/// Sends the configured notice into a room just frozen, if the server user can.
+async fn announce(services: &Services, room_id: &RoomId) {
+	let notice = &services.server.config.room_freeze_notice;
+	let server_user = &services.globals.server_user;
+	if notice.is_empty()
+		|| !services
+			.rooms
+			.state_cache
+			.is_joined(server_user, room_id)
+			.await
+	{
+		return;
+	}
+
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+	let content = RoomMessageEventContent::notice_plain(notice);
+	if let Err(e) = services
+		.rooms
+		.timeline
+		.build_and_append_pdu(PduBuilder::timeline(&content), server_user, room_id, &state_lock)
+		.await
+	{
+		warn!(%room_id, "Failed to announce the room was frozen: {e}");
+	}
+}
+
+/// Who the freeze still lets send and until when, for showing to admins.
+pub(super) fn describe(freeze: &Freeze) -> String {
+	let until = freeze.until.map_or_else(
+		|| "until unfrozen".to_owned(),
+		|until| {
+			let until = UNIX_EPOCH
+				.checked_add(Duration::from_millis(until))
+				.unwrap_or(UNIX_EPOCH);
+
+			format!("until {}", format(until, "%Y-%m-%d %H:%M:%S UTC"))
+		},
+	);
+
+	format!("for members below power level {} {until}", freeze.power_level)
+}
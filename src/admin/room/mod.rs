@@ -1,16 +1,24 @@
+mod activity;
 mod alias;
 mod commands;
 mod directory;
+mod extremities;
+mod freeze;
 mod info;
 mod moderation;
+mod purge;
+mod state_growth;
+mod usage;
+
+use std::path::PathBuf;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::OwnedRoomId;
+use ruma::{OwnedEventId, OwnedRoomId, OwnedServerName};
 
 use self::{
-	alias::RoomAliasCommand, directory::RoomDirectoryCommand, info::RoomInfoCommand,
-	moderation::RoomModerationCommand,
+	activity::Format, alias::RoomAliasCommand, directory::RoomDirectoryCommand,
+	info::RoomInfoCommand, moderation::RoomModerationCommand, usage::By,
 };
 use crate::admin_command_dispatch;
 
@@ -52,8 +60,150 @@ pub(super) enum RoomCommand {
 	/// - Manage the room directory
 	Directory(RoomDirectoryCommand),
 
+	/// - Show a summary of a room
+	///
+	/// Leads with the replacement room when the room has been tombstoned.
+	Show {
+		room_id: OwnedRoomId,
+	},
+
 	/// - Check if we know about a room
 	Exists {
 		room_id: OwnedRoomId,
 	},
+
+	/// - List the forward extremities of a room
+	///
+	/// Shows the depth and timestamp of each, and whether its auth chain is
+	/// fully present locally.
+	Extremities {
+		room_id: OwnedRoomId,
+	},
+
+	/// - Remove an event from the forward extremities of a room
+	///
+	/// Events sent from this server will no longer reference it. This can
+	/// unwedge a room whose extremity references events which cannot be
+	/// fetched, but abandons the branch of history it leads.
+	DropExtremity {
+		room_id: OwnedRoomId,
+
+		event_id: OwnedEventId,
+
+		/// Required to drop the extremity
+		#[arg(long)]
+		force: bool,
+
+		/// Check that new events can be sent afterwards
+		#[arg(long)]
+		verify: bool,
+	},
+
+	/// - Fetch an event from a server and handle it as a timeline event
+	///
+	/// Events it references which are missing are fetched as well, and the
+	/// state of the room is resolved again.
+	Refetch {
+		room_id: OwnedRoomId,
+
+		event_id: OwnedEventId,
+
+		server: OwnedServerName,
+
+		/// Check that new events can be sent afterwards
+		#[arg(long)]
+		verify: bool,
+	},
+
+	/// - Freeze a room so only its moderators can send events
+	///
+	/// Local users below the power level are refused and events from remote
+	/// users below it soft-failed, without changing the room's power levels.
+	/// Anyone may still leave. The server user announces the freeze in the
+	/// room if it is there.
+	Freeze {
+		room_id: OwnedRoomId,
+
+		/// Lowest power level still allowed to send events
+		#[arg(long, default_value = "50")]
+		allow_power_level: i64,
+
+		/// Unfreeze the room by itself after this long, e.g. "30m" or "2h"
+		#[arg(long)]
+		duration: Option<String>,
+	},
+
+	/// - Unfreeze a frozen room
+	Unfreeze {
+		room_id: OwnedRoomId,
+	},
+
+	/// - Delete everything stored about a room
+	///
+	/// Local members are made to leave and forget it, its local aliases and
+	/// directory listing are removed, then its events, state, memberships,
+	/// receipts, notification counts and search index are deleted. Running it
+	/// again finishes an interrupted purge. A ban of the room is kept.
+	Purge {
+		room_id: OwnedRoomId,
+
+		/// Only count what would be deleted, per database column
+		#[arg(long)]
+		dry_run: bool,
+	},
+
+	/// - Show how many state events were sent in a room each day
+	///
+	/// Includes those refused from local users and flagged as flooding from
+	/// remote users, and who sent the most.
+	StateGrowth {
+		room_id: OwnedRoomId,
+
+		/// Number of most recent days to show
+		#[arg(long, default_value = "7")]
+		days: usize,
+	},
+
+	/// - Show what happened in a room each day
+	///
+	/// Counts the messages sent and how many distinct users sent them, and
+	/// the joins, leaves and redactions.
+	Activity {
+		room_id: OwnedRoomId,
+
+		/// Number of most recent days to show
+		#[arg(long, default_value = "30")]
+		days: u64,
+	},
+
+	/// - Export the daily activity of rooms to a file
+	///
+	/// Exports every room with activity counted unless rooms are given.
+	ActivityExport {
+		path: PathBuf,
+
+		/// Number of most recent days to export
+		#[arg(long, default_value = "30")]
+		days: u64,
+
+		#[arg(long, value_enum, default_value = "csv")]
+		format: Format,
+
+		rooms: Vec<OwnedRoomId>,
+	},
+
+	/// - List the rooms costing the server the most
+	///
+	/// Shows each room's members, stored events, current state entries and
+	/// the media its recent messages reference. Measurements are reused for
+	/// an hour.
+	Top {
+		/// What to rank the rooms by
+		#[arg(long, value_enum, default_value = "state")]
+		by: By,
+
+		/// Number of rooms to list
+		#[arg(long, default_value = "20")]
+		limit: usize,
+	},
 }
@@ -184,6 +184,7 @@ async fn ban_room(&self, room: Box<RoomOrAliasId>) -> Result<RoomMessageEventCon
 	self.services.rooms.directory.set_not_public(&room_id);
 
 	self.services.rooms.metadata.disable_room(&room_id, true);
+	self.services.federation.clear_room_state_ids(&room_id);
 
 	Ok(RoomMessageEventContent::text_plain(
 		"Room banned, removed all our local users, and disabled incoming federation with room.",
@@ -354,6 +355,7 @@ async fn ban_list_of_rooms(&self) -> Result<RoomMessageEventContent> {
 		self.services.rooms.directory.set_not_public(&room_id);
 
 		self.services.rooms.metadata.disable_room(&room_id, true);
+		self.services.federation.clear_room_state_ids(&room_id);
 	}
 
 	Ok(RoomMessageEventContent::text_plain(format!(
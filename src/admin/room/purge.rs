@@ -0,0 +1,128 @@
+use std::{
+	fmt::Write,
+	time::{Duration, Instant},
+};
+
+use api::client::leave_room;
+use conduwuit::{Err, Result, utils::ReadyExt, warn};
+use futures::{StreamExt, TryStreamExt, pin_mut};
+use ruma::{OwnedRoomId, RoomId, events::room::message::RoomMessageEventContent};
+use service::Services;
+
+use crate::admin_command;
+
+/// Least time between progress messages while purging.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[admin_command]
+pub(super) async fn purge(
+	&self,
+	room_id: OwnedRoomId,
+	dry_run: bool,
+) -> Result<RoomMessageEventContent> {
+	if self
+		.services
+		.admin
+		.get_admin_room()
+		.await
+		.is_ok_and(|admin_room_id| admin_room_id == room_id)
+	{
+		return Err!("Not allowed to purge the admin room.");
+	}
+
+	if !dry_run {
+		evict_local(self.services, &room_id).await;
+	}
+
+	let room = self.services.rooms.purge.gather(&room_id).await?;
+	if !dry_run {
+		self.services
+			.admin
+			.send_text(&format!(
+				"Purging {room_id}: {} events, {} state groups, {} members.",
+				room.events.len(),
+				room.shortstatehashes.len(),
+				room.members.len(),
+			))
+			.await;
+	}
+
+	let mut out = String::new();
+	let mut progress = String::new();
+	let mut reported = Instant::now();
+	let mut total = 0_usize;
+	let purge = self.services.rooms.purge.purge_room(&room, dry_run);
+	pin_mut!(purge);
+	while let Some((column, rows)) = purge.try_next().await? {
+		total = total.saturating_add(rows);
+		if rows > 0 {
+			writeln!(out, "| {column} | {rows} |")?;
+			writeln!(progress, "- {column}: {rows}")?;
+		}
+
+		if !dry_run && !progress.is_empty() && reported.elapsed() >= PROGRESS_INTERVAL {
+			self.services
+				.admin
+				.send_text(&format!("Purged so far from {room_id}:\n{progress}"))
+				.await;
+
+			progress.clear();
+			reported = Instant::now();
+		}
+	}
+
+	if !dry_run {
+		self.services.rooms.purge.forget_room(&room_id).await;
+	}
+
+	let done = if dry_run { "Would delete" } else { "Deleted" };
+	if total == 0 {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"Nothing is stored about {room_id}."
+		)));
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{done} {total} rows of {room_id}:\n\n| Column | Rows |\n| --- | --- |\n{out}"
+	)))
+}
+
+/// Makes the local members leave the room and forget it, and removes its
+/// local aliases and directory listing, as banning it does.
+async fn evict_local(services: &Services, room_id: &RoomId) {
+	let users: Vec<_> = services
+		.rooms
+		.state_cache
+		.room_members(room_id)
+		.ready_filter(|user| services.globals.user_is_local(user))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for user_id in &users {
+		if let Err(e) = leave_room(services, user_id, room_id, None).await {
+			warn!("Failed to leave {room_id} for {user_id}: {e}");
+		}
+
+		services.rooms.state_cache.forget(room_id, user_id);
+	}
+
+	let aliases: Vec<_> = services
+		.rooms
+		.alias
+		.local_aliases_for_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for alias in &aliases {
+		services
+			.rooms
+			.alias
+			.remove_alias(alias, &services.globals.server_user)
+			.await
+			.ok();
+	}
+
+	services.rooms.directory.set_not_public(room_id);
+}
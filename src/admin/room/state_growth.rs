@@ -0,0 +1,49 @@
+use std::fmt::Write;
+
+use conduwuit::Result;
+use ruma::{OwnedRoomId, events::room::message::RoomMessageEventContent};
+use service::rooms::state_growth::date;
+
+use crate::admin_command;
+
+/// Senders listed for each day.
+const TOP_SENDERS: usize = 5;
+
+#[admin_command]
+pub(super) async fn state_growth(
+	&self,
+	room_id: OwnedRoomId,
+	days: usize,
+) -> Result<RoomMessageEventContent> {
+	let growth = self
+		.services
+		.rooms
+		.state_growth
+		.growth(&room_id, days)
+		.await;
+
+	if growth.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(
+			"No state events have been counted in this room.",
+		));
+	}
+
+	let mut msg = format!("State events sent in {room_id}:\n```\n");
+	for (day, growth) in &growth {
+		writeln!(
+			msg,
+			"{} | {} accepted | {} refused | {} flooded",
+			date(*day),
+			growth.accepted,
+			growth.refused,
+			growth.flooded
+		)?;
+
+		for (sender, sent) in growth.top_senders(TOP_SENDERS) {
+			writeln!(msg, "    {sender}: {sent}")?;
+		}
+	}
+	msg.push_str("```");
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
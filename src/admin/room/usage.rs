@@ -0,0 +1,64 @@
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use conduwuit::{Result, utils::bytes::pretty};
+use conduwuit_database::Lane;
+use ruma::events::room::message::RoomMessageEventContent;
+use service::rooms::usage::Measure;
+
+use crate::admin_command;
+
+/// What to rank rooms by.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum By {
+	/// Entries in the current state
+	State,
+
+	/// Events stored
+	Events,
+
+	/// Joined members
+	Members,
+
+	/// Stored media referenced by recent messages; not seen in encrypted
+	/// rooms
+	Media,
+}
+
+impl From<By> for Measure {
+	fn from(by: By) -> Self {
+		match by {
+			| By::State => Self::State,
+			| By::Events => Self::Events,
+			| By::Members => Self::Members,
+			| By::Media => Self::Media,
+		}
+	}
+}
+
+#[admin_command]
+pub(super) async fn top(&self, by: By, limit: usize) -> Result<RoomMessageEventContent> {
+	let usages = Lane::Background
+		.scope(self.services.rooms.usage.top(by.into(), limit))
+		.await;
+
+	if usages.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No rooms."));
+	}
+
+	let mut out = String::from(
+		"| Room | Members | Local members | Events | State | Media |\n| --- | --- | --- | --- | \
+		 --- | --- |\n",
+	);
+
+	for usage in &usages {
+		let media = pretty(usage.media_bytes.try_into().unwrap_or(usize::MAX));
+		writeln!(
+			out,
+			"| {} | {} | {} | {} | {} | {media} |",
+			usage.room_id, usage.members, usage.local_members, usage.events, usage.state,
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
@@ -1,7 +1,8 @@
 use std::{fmt::Write, path::PathBuf, sync::Arc};
 
-use conduwuit::{Err, Result, info, utils::time, warn};
+use conduwuit::{Err, Result, config::cache, info, utils::time, warn};
 use ruma::events::room::message::RoomMessageEventContent;
+use service::mailer::Context;
 
 use crate::admin_command;
 
@@ -18,6 +19,84 @@ pub(super) async fn uptime(&self) -> Result<RoomMessageEventContent> {
 	Ok(RoomMessageEventContent::notice_plain(format!("{result}.")))
 }
 
+#[admin_command]
+pub(super) async fn status(&self) -> Result<RoomMessageEventContent> {
+	let config = &self.services.server.config;
+	let elapsed = self
+		.services
+		.server
+		.started
+		.elapsed()
+		.expect("standard duration");
+
+	let mut out = format!("Uptime: {}\n\n", time::pretty(elapsed));
+	let auto_size = &config.auto_size;
+	match auto_size {
+		| Some(auto_size) => writeln!(
+			out,
+			"Cache auto-sizing applied: {} of {} bytes available\n",
+			auto_size.budget, auto_size.memory
+		)?,
+		| None if config.cache_auto_size => {
+			writeln!(out, "Cache auto-sizing enabled but available memory is unknown\n")?;
+		},
+		| None => writeln!(out, "Cache auto-sizing not enabled\n")?,
+	}
+
+	writeln!(out, "| Cache | Capacity | Source |")?;
+	writeln!(out, "| ----- | -------- | ------ |")?;
+	for (name, value) in cache::capacities(config) {
+		let source = match auto_size {
+			| Some(auto_size) if auto_size.sizes.iter().any(|(sized, _)| *sized == name) =>
+				"auto",
+			| Some(_) => "configured",
+			| None => "default or configured",
+		};
+
+		writeln!(out, "| {name} | {value} | {source} |")?;
+	}
+
+	writeln!(out, "\ncache_capacity_modifier: {}", config.cache_capacity_modifier)?;
+
+	let shedding = &self.services.shedding;
+	if shedding.is_enabled() {
+		writeln!(out, "\nLoad shedding: {} classes shed\n", shedding.level())?;
+	} else {
+		writeln!(out, "\nLoad shedding not enabled\n")?;
+	}
+
+	writeln!(out, "| Class | State | In flight | p99 | Shed |")?;
+	writeln!(out, "| ----- | ----- | --------- | --- | ---- |")?;
+	for status in shedding.status() {
+		let state = if status.shedding { "shedding" } else { "admitted" };
+		writeln!(
+			out,
+			"| {} | {state} | {} | {:?} | {} |",
+			status.class, status.inflight, status.p99, status.shed
+		)?;
+	}
+
+	match &self.services.server.log.file {
+		| Some(file) => {
+			let stats = file.stats();
+			let last_rotated = stats
+				.last_rotated
+				.map_or_else(|| "never".to_owned(), |at| time::format(at, "%+"));
+
+			writeln!(
+				out,
+				"\nLog file: {} ({} bytes), rotated {} time(s), last {last_rotated}",
+				stats.path.display(),
+				stats.size,
+				stats.rotations,
+			)?;
+		},
+		| None => writeln!(out, "\nLog file not enabled")?,
+	}
+
+	Ok(RoomMessageEventContent::text_markdown(out))
+}
+
 #[admin_command]
 pub(super) async fn show_config(&self) -> Result<RoomMessageEventContent> {
 	// Construct and send the response
@@ -129,6 +208,86 @@ pub(super) async fn admin_notice(&self, message: Vec<String>) -> Result<RoomMess
 	Ok(RoomMessageEventContent::notice_plain("Notice was sent to #admins"))
 }
 
+#[admin_command]
+pub(super) async fn test_email(&self, address: String) -> Result<RoomMessageEventContent> {
+	self.services
+		.mailer
+		.send_now("test", &address, Context::new())
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Test email was sent to {address}"
+	)))
+}
+
+#[admin_command]
+pub(super) async fn email_failures(&self) -> Result<RoomMessageEventContent> {
+	let failures = self.services.mailer.failures();
+	if failures.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No emails were given up on."));
+	}
+
+	let mut out = String::new();
+	writeln!(out, "| Failed at | Template | Recipient | Attempts | Error |")?;
+	writeln!(out, "| --- | --- | --- | --- | --- |")?;
+	for failure in failures {
+		let failed_at = time::format(failure.failed_at, "%+");
+		writeln!(
+			out,
+			"| {failed_at} | {} | {} | {} | {} |",
+			failure.template, failure.recipient, failure.attempts, failure.error
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn reindex_status(&self) -> Result<RoomMessageEventContent> {
+	let mut out = String::new();
+	writeln!(out, "| Task | State | Keys done |")?;
+	writeln!(out, "| --- | --- | --- |")?;
+	for (task, progress) in self.services.reindex.status().await {
+		let state = match (&progress.error, progress.paused, progress.finished) {
+			| (_, _, true) => "finished".to_owned(),
+			| (Some(error), true, _) => format!("paused after an error: {error}"),
+			| (None, true, _) => "paused".to_owned(),
+			| (_, false, false) if progress.done > 0 => "in progress".to_owned(),
+			| (_, false, false) => "pending".to_owned(),
+		};
+
+		writeln!(out, "| {task} | {state} | {} |", progress.done)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn reindex_pause(&self, task: String) -> Result<RoomMessageEventContent> {
+	if !self.services.reindex.pause(&task).await? {
+		return Ok(RoomMessageEventContent::notice_plain(format!(
+			"Reindexing task {task} is already paused or finished."
+		)));
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Reindexing task {task} will pause after its current batch."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn reindex_resume(&self, task: String) -> Result<RoomMessageEventContent> {
+	if !self.services.reindex.resume(&task).await? {
+		return Ok(RoomMessageEventContent::notice_plain(format!(
+			"Reindexing task {task} was not paused."
+		)));
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Resumed reindexing task {task}."
+	)))
+}
+
 #[admin_command]
 pub(super) async fn reload_mods(&self) -> Result<RoomMessageEventContent> {
 	self.services.server.reload()?;
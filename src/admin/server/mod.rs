@@ -13,6 +13,9 @@ pub(super) enum ServerCommand {
 	/// - Time elapsed since startup
 	Uptime,
 
+	/// - Show uptime, the resolved cache capacities and load shedding state
+	Status,
+
 	/// - Show configuration values
 	ShowConfig,
 
@@ -51,6 +54,33 @@ pub(super) enum ServerCommand {
 		message: Vec<String>,
 	},
 
+	/// - Send a test email to verify the email configuration
+	///
+	/// The email is sent at once rather than queued, and any error from the
+	/// SMTP server is shown.
+	TestEmail {
+		address: String,
+	},
+
+	/// - List the emails most recently given up on
+	EmailFailures,
+
+	/// - Show the progress of the background reindexing tasks
+	///
+	/// Tasks run one at a time in the order listed, filling in indexes added
+	/// by upgrades for the data stored before them.
+	ReindexStatus,
+
+	/// - Pause a background reindexing task after its current batch
+	ReindexPause {
+		task: String,
+	},
+
+	/// - Resume a paused background reindexing task, or one which failed
+	ReindexResume {
+		task: String,
+	},
+
 	/// - Hot-reload the server
 	#[clap(alias = "reload")]
 	ReloadMods,
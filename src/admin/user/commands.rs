@@ -1,14 +1,19 @@
-use std::{collections::BTreeMap, fmt::Write as _};
+use std::{
+	collections::BTreeMap,
+	fmt::Write as _,
+	path::{Path, PathBuf},
+};
 
-use api::client::{full_user_deactivate, join_room_by_id_helper, leave_room};
+use api::client::{create_local_user, full_user_deactivate, join_room_by_id_helper, leave_room};
 use conduwuit::{
-	Result, debug, debug_warn, error, info, is_equal_to,
+	Err, Result, debug_warn, err,
 	matrix::pdu::PduBuilder,
 	utils::{self, ReadyExt},
 	warn,
 };
 use conduwuit_api::client::{leave_all_rooms, update_avatar_url, update_displayname};
-use futures::StreamExt;
+use conduwuit_database::Lane;
+use futures::{StreamExt, future::join3};
 use ruma::{
 	EventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId, RoomId, UserId,
 	events::{
@@ -21,7 +26,16 @@
 		tag::{TagEvent, TagEventContent, TagInfo},
 	},
 };
+use service::{
+	Services,
+	rooms::direct::Skip,
+	users::{KeyExport, KeyImport},
+};
 
+use super::{
+	deactivate::{self, Options},
+	force_join,
+};
 use crate::{
 	admin_command, get_room_info,
 	utils::{parse_active_local_user_id, parse_local_user_id},
@@ -31,17 +45,39 @@
 const BULK_JOIN_REASON: &str = "Bulk force joining this room as initiated by the server admin.";
 
 #[admin_command]
-pub(super) async fn list_users(&self) -> Result<RoomMessageEventContent> {
-	let users: Vec<_> = self
+pub(super) async fn list_users(&self, flags: bool) -> Result<RoomMessageEventContent> {
+	let user_ids: Vec<OwnedUserId> = self
 		.services
 		.users
 		.list_local_users()
-		.map(ToString::to_string)
+		.map(ToOwned::to_owned)
 		.collect()
 		.await;
 
-	let mut plain_msg = format!("Found {} local user account(s):\n```\n", users.len());
-	plain_msg += users.join("\n").as_str();
+	let users = &self.services.users;
+	let mut lines = Vec::with_capacity(user_ids.len());
+	for user_id in user_ids {
+		if !flags {
+			lines.push(user_id.to_string());
+			continue;
+		}
+
+		let (admin, suspended, shadow_banned) = join3(
+			users.is_admin(&user_id),
+			users.is_suspended(&user_id),
+			users.is_shadow_banned(&user_id),
+		)
+		.await;
+
+		lines.push(flagged(&user_id, [
+			(admin, "admin"),
+			(suspended, "suspended"),
+			(shadow_banned, "shadow-banned"),
+		]));
+	}
+
+	let mut plain_msg = format!("Found {} local user account(s):\n```\n", lines.len());
+	plain_msg += lines.join("\n").as_str();
 	plain_msg += "\n```";
 
 	self.write_str(plain_msg.as_str()).await?;
@@ -72,131 +108,162 @@ pub(super) async fn create_user(
 
 	let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
 
-	// Create user
-	self.services
-		.users
-		.create(&user_id, Some(password.as_str()))?;
+	create_local_user(self.services, &user_id, &password, false).await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Created user with user_id: {user_id} and password: `{password}`"
+	)))
+}
 
-	// Default to pretty displayname
-	let mut displayname = user_id.localpart().to_owned();
+#[admin_command]
+pub(super) async fn suspend(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
 
-	// If `new_user_displayname_suffix` is set, registration will push whatever
-	// content is set to the user's display name with a space before it
-	if !self
-		.services
-		.server
-		.config
-		.new_user_displayname_suffix
-		.is_empty()
-	{
-		write!(displayname, " {}", self.services.server.config.new_user_displayname_suffix)
-			.expect("should be able to write to string buffer");
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to suspend the server service account.",
+		));
 	}
 
-	self.services
-		.users
-		.set_displayname(&user_id, Some(displayname));
+	if !self.services.users.suspend(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{user_id} is already suspended."
+		)));
+	}
 
-	// Initial account data
-	self.services
-		.account_data
-		.update(
-			None,
-			&user_id,
-			ruma::events::GlobalAccountDataEventType::PushRules
-				.to_string()
-				.into(),
-			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
-				content: ruma::events::push_rules::PushRulesEventContent {
-					global: ruma::push::Ruleset::server_default(&user_id),
-				},
-			})
-			.expect("to json value always works"),
-		)
-		.await?;
+	Ok(RoomMessageEventContent::text_plain(format!("{user_id} has been suspended.")))
+}
 
-	if !self.services.server.config.auto_join_rooms.is_empty() {
-		for room in &self.services.server.config.auto_join_rooms {
-			let Ok(room_id) = self.services.rooms.alias.resolve(room).await else {
-				error!(%user_id, "Failed to resolve room alias to room ID when attempting to auto join {room}, skipping");
-				continue;
-			};
+#[admin_command]
+pub(super) async fn unsuspend(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
 
-			if !self
-				.services
-				.rooms
-				.state_cache
-				.server_in_room(self.services.globals.server_name(), &room_id)
-				.await
-			{
-				warn!(
-					"Skipping room {room} to automatically join as we have never joined before."
-				);
-				continue;
-			}
+	if !self.services.users.unsuspend(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!("{user_id} is not suspended.")));
+	}
 
-			if let Some(room_server_name) = room.server_name() {
-				match join_room_by_id_helper(
-					self.services,
-					&user_id,
-					&room_id,
-					Some("Automatically joining this room upon registration".to_owned()),
-					&[
-						self.services.globals.server_name().to_owned(),
-						room_server_name.to_owned(),
-					],
-					None,
-					&None,
-				)
-				.await
-				{
-					| Ok(_response) => {
-						info!("Automatically joined room {room} for user {user_id}");
-					},
-					| Err(e) => {
-						self.services
-							.admin
-							.send_message(RoomMessageEventContent::text_plain(format!(
-								"Failed to automatically join room {room} for user {user_id}: \
-								 {e}"
-							)))
-							.await
-							.ok();
-						// don't return this error so we don't fail registrations
-						error!(
-							"Failed to automatically join room {room} for user {user_id}: {e}"
-						);
-					},
-				}
-			}
-		}
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"{user_id} is no longer suspended."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn shadow_ban(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
+
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to shadow-ban the server service account.",
+		));
 	}
 
-	// we dont add a device since we're not the user, just the creator
+	if !self.services.users.shadow_ban(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{user_id} is already shadow-banned."
+		)));
+	}
 
-	// if this account creation is from the CLI / --execute, invite the first user
-	// to admin room
-	if let Ok(admin_room) = self.services.admin.get_admin_room().await {
-		if self
-			.services
-			.rooms
-			.state_cache
-			.room_joined_count(&admin_room)
-			.await
-			.is_ok_and(is_equal_to!(1))
-		{
-			self.services.admin.make_user_admin(&user_id).await?;
-			warn!("Granting {user_id} admin privileges as the first user");
-		}
-	} else {
-		debug!("create_user admin command called without an admin room being available");
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"{user_id} has been shadow-banned."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unshadow_ban(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self.services.users.unshadow_ban(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{user_id} is not shadow-banned."
+		)));
 	}
 
 	Ok(RoomMessageEventContent::text_plain(format!(
-		"Created user with user_id: {user_id} and password: `{password}`"
+		"{user_id} is no longer shadow-banned."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn account_validity(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let validity = &self.services.account_validity;
+
+	let expires = match validity.expires_at(&user_id).await {
+		| Some(expires_at) => format!("expires {}", utils::time::format(expires_at, "%+")),
+		| None => "does not expire".to_owned(),
+	};
+
+	let email = validity
+		.email(&user_id)
+		.await
+		.unwrap_or_else(|| "no email address".to_owned());
+
+	Ok(RoomMessageEventContent::text_plain(format!("{user_id} {expires} ({email}).")))
+}
+
+#[admin_command]
+pub(super) async fn renew_account(
+	&self,
+	user_id: String,
+	days: Option<u64>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
+
+	if days.is_none() && !self.services.account_validity.is_enabled() {
+		return Err!(
+			"Account validity is disabled; give the number of days to renew the account for."
+		);
+	}
+
+	let expires_at = self.services.account_validity.renew(&user_id, days).await;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"{user_id} now expires {}.",
+		utils::time::format(expires_at, "%+")
 	)))
 }
 
+#[admin_command]
+pub(super) async fn expire_account(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
+
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to expire the server service account.",
+		));
+	}
+
+	self.services.account_validity.expire(&user_id).await;
+
+	Ok(RoomMessageEventContent::text_plain(format!("{user_id} has expired.")))
+}
+
+#[admin_command]
+pub(super) async fn set_email(
+	&self,
+	user_id: String,
+	address: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if address.as_deref().is_some_and(|address| {
+		address
+			.split_once('@')
+			.is_none_or(|(_, domain)| domain.is_empty())
+	}) {
+		return Err!("Invalid email address.");
+	}
+
+	self.services
+		.account_validity
+		.set_email(&user_id, address.as_deref());
+
+	Ok(RoomMessageEventContent::text_plain(match address {
+		| Some(address) => format!("Renewal links for {user_id} are now sent to {address}."),
+		| None => format!("Removed the email address of {user_id}."),
+	}))
+}
+
 #[admin_command]
 pub(super) async fn deactivate(
 	&self,
@@ -278,7 +345,9 @@ pub(super) async fn reset_password(
 #[admin_command]
 pub(super) async fn deactivate_all(
 	&self,
-	no_leave_rooms: bool,
+	leave_rooms: bool,
+	remove_devices: bool,
+	no_purge_media: bool,
 	force: bool,
 ) -> Result<RoomMessageEventContent> {
 	if self.body.len() < 2
@@ -290,104 +359,25 @@ pub(super) async fn deactivate_all(
 		));
 	}
 
-	let usernames = self
+	let lines = self
 		.body
-		.to_vec()
-		.drain(1..self.body.len().saturating_sub(1))
-		.collect::<Vec<_>>();
-
-	let mut user_ids: Vec<OwnedUserId> = Vec::with_capacity(usernames.len());
-	let mut admins = Vec::new();
-
-	for username in usernames {
-		match parse_active_local_user_id(self.services, username).await {
-			| Ok(user_id) => {
-				if self.services.users.is_admin(&user_id).await && !force {
-					self.services
-						.admin
-						.send_message(RoomMessageEventContent::text_plain(format!(
-							"{username} is an admin and --force is not set, skipping over"
-						)))
-						.await
-						.ok();
-					admins.push(username);
-					continue;
-				}
-
-				// don't deactivate the server service account
-				if user_id == self.services.globals.server_user {
-					self.services
-						.admin
-						.send_message(RoomMessageEventContent::text_plain(format!(
-							"{username} is the server service account, skipping over"
-						)))
-						.await
-						.ok();
-					continue;
-				}
-
-				user_ids.push(user_id);
-			},
-			| Err(e) => {
-				self.services
-					.admin
-					.send_message(RoomMessageEventContent::text_plain(format!(
-						"{username} is not a valid username, skipping over: {e}"
-					)))
-					.await
-					.ok();
-				continue;
-			},
-		}
-	}
-
-	let mut deactivation_count: usize = 0;
+		.get(1..self.body.len().saturating_sub(1))
+		.unwrap_or_default();
+	let listed = deactivate::listed(lines);
+	let options = Options {
+		leave_rooms,
+		remove_devices,
+		purge_media: !no_purge_media,
+	};
 
-	for user_id in user_ids {
-		match self.services.users.deactivate_account(&user_id).await {
-			| Ok(()) => {
-				deactivation_count = deactivation_count.saturating_add(1);
-				if !no_leave_rooms {
-					info!("Forcing user {user_id} to leave all rooms apart of deactivate-all");
-					let all_joined_rooms: Vec<OwnedRoomId> = self
-						.services
-						.rooms
-						.state_cache
-						.rooms_joined(&user_id)
-						.map(Into::into)
-						.collect()
-						.await;
-
-					full_user_deactivate(self.services, &user_id, &all_joined_rooms).await?;
-					update_displayname(self.services, &user_id, None, &all_joined_rooms).await;
-					update_avatar_url(self.services, &user_id, None, None, &all_joined_rooms)
-						.await;
-					leave_all_rooms(self.services, &user_id).await;
-				}
-			},
-			| Err(e) => {
-				self.services
-					.admin
-					.send_message(RoomMessageEventContent::text_plain(format!(
-						"Failed deactivating user: {e}"
-					)))
-					.await
-					.ok();
-			},
-		}
-	}
+	let summary = deactivate::deactivate_listed(
+		&listed,
+		|username| deactivate::check(self.services, username, force),
+		|user_id| deactivate::deactivate(self.services, user_id, options),
+	)
+	.await;
 
-	if admins.is_empty() {
-		Ok(RoomMessageEventContent::text_plain(format!(
-			"Deactivated {deactivation_count} accounts."
-		)))
-	} else {
-		Ok(RoomMessageEventContent::text_plain(format!(
-			"Deactivated {deactivation_count} accounts.\nSkipped admin accounts: {}. Use \
-			 --force to deactivate admin accounts",
-			admins.join(", ")
-		)))
-	}
+	Ok(RoomMessageEventContent::notice_markdown(summary.markdown()?))
 }
 
 #[admin_command]
@@ -661,6 +651,7 @@ pub(super) async fn force_join_room(
 	&self,
 	user_id: String,
 	room_id: OwnedRoomOrAliasId,
+	override_ban: bool,
 ) -> Result<RoomMessageEventContent> {
 	let user_id = parse_local_user_id(self.services, &user_id)?;
 	let (room_id, servers) = self
@@ -674,11 +665,12 @@ pub(super) async fn force_join_room(
 		self.services.globals.user_is_local(&user_id),
 		"Parsed user_id must be a local user"
 	);
-	join_room_by_id_helper(self.services, &user_id, &room_id, None, &servers, None, &None)
-		.await?;
+
+	let event_id =
+		force_join::force_join(self.services, &user_id, &room_id, &servers, override_ban).await?;
 
 	Ok(RoomMessageEventContent::notice_markdown(format!(
-		"{user_id} has been joined to {room_id}.",
+		"{user_id} has been joined to {room_id} with {event_id}.",
 	)))
 }
 
@@ -710,8 +702,13 @@ pub(super) async fn force_leave_room(
 
 	leave_room(self.services, &user_id, &room_id, None).await?;
 
+	let left = match force_join::member_event(self.services, &user_id, &room_id).await {
+		| Ok(event_id) => format!(" with {event_id}"),
+		| Err(_) => String::new(),
+	};
+
 	Ok(RoomMessageEventContent::notice_markdown(format!(
-		"{user_id} has left {room_id}.",
+		"{user_id} has left {room_id}{left}.",
 	)))
 }
 
@@ -955,3 +952,225 @@ pub(super) async fn redact_event(
 
 	Ok(RoomMessageEventContent::text_plain(""))
 }
+
+#[admin_command]
+pub(super) async fn export_keys(
+	&self,
+	user_id: Option<String>,
+	path: Option<PathBuf>,
+	all_users: Option<PathBuf>,
+) -> Result<RoomMessageEventContent> {
+	if let Some(dir) = all_users {
+		tokio::fs::create_dir_all(&dir).await?;
+
+		let user_ids: Vec<OwnedUserId> = self
+			.services
+			.users
+			.list_local_users()
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for user_id in &user_ids {
+			let path = dir.join(format!("{user_id}.json"));
+			write_key_export(self.services, user_id, &path).await?;
+		}
+
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"Exported keys of {} users to {dir:?}.",
+			user_ids.len()
+		)));
+	}
+
+	let (Some(user_id), Some(path)) = (user_id, path) else {
+		return Err!("A user ID and path are required unless --all-users is given.");
+	};
+
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	write_key_export(self.services, &user_id, &path).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Exported keys of {user_id} to {path:?}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn import_keys(
+	&self,
+	user_id: Option<String>,
+	path: Option<PathBuf>,
+	all_users: Option<PathBuf>,
+) -> Result<RoomMessageEventContent> {
+	if let Some(dir) = all_users {
+		let mut imported = 0_usize;
+		let mut failed = String::new();
+		let mut entries = tokio::fs::read_dir(&dir).await?;
+		while let Some(entry) = entries.next_entry().await? {
+			let path = entry.path();
+			if path.extension().is_none_or(|extension| extension != "json") {
+				continue;
+			}
+
+			let Some(user_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+				continue;
+			};
+
+			let result = match parse_local_user_id(self.services, user_id) {
+				| Ok(user_id) => read_key_export(self.services, &user_id, &path)
+					.await
+					.map(|_| ()),
+				| Err(e) => Err(e),
+			};
+
+			match result {
+				| Ok(()) => imported = imported.saturating_add(1),
+				| Err(e) => writeln!(failed, "- {user_id}: {e}")?,
+			}
+		}
+
+		let mut msg = format!("Imported keys of {imported} users from {dir:?}.");
+		if !failed.is_empty() {
+			write!(msg, "\n\nFailed to import:\n{failed}")?;
+		}
+
+		return Ok(RoomMessageEventContent::notice_markdown(msg));
+	}
+
+	let (Some(user_id), Some(path)) = (user_id, path) else {
+		return Err!("A user ID and path are required unless --all-users is given.");
+	};
+
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let import = read_key_export(self.services, &user_id, &path).await?;
+
+	let mut msg = format!(
+		"Imported {} cross-signing keys and the keys of {} devices for {user_id}.",
+		import.cross_signing_keys, import.devices
+	);
+
+	if !import.skipped_devices.is_empty() {
+		let skipped: Vec<_> = import
+			.skipped_devices
+			.iter()
+			.map(ToString::to_string)
+			.collect();
+
+		write!(msg, " Skipped devices not present on this server: {}", skipped.join(", "))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+async fn write_key_export(services: &Services, user_id: &UserId, path: &Path) -> Result {
+	let export = Lane::Background
+		.scope(services.users.export_keys(user_id))
+		.await?;
+	let json = serde_json::to_vec_pretty(&export)?;
+	tokio::fs::write(path, json).await?;
+
+	Ok(())
+}
+
+async fn read_key_export(
+	services: &Services,
+	user_id: &UserId,
+	path: &Path,
+) -> Result<KeyImport> {
+	let json = tokio::fs::read(path).await?;
+	let export: KeyExport = serde_json::from_slice(&json)?;
+
+	services.users.import_keys(user_id, &export).await
+}
+
+#[admin_command]
+pub(super) async fn dedupe_dms(
+	&self,
+	user_id: String,
+	dry_run: bool,
+	merge: bool,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let found = self
+		.services
+		.rooms
+		.direct
+		.duplicates(&user_id)
+		.await
+		.map_err(|e| err!("No direct message rooms found for {user_id}: {e}"))?;
+
+	let mut out = String::new();
+	for duplicates in &found {
+		let other = &duplicates.other;
+		match &duplicates.canonical {
+			| Some(canonical) if !duplicates.extras.is_empty() => {
+				writeln!(out, "With {other}, keeping {canonical} over:")?;
+				for room_id in &duplicates.extras {
+					writeln!(out, "- {room_id}")?;
+				}
+			},
+			| _ => writeln!(out, "With {other}, no duplicates.")?,
+		}
+
+		for (room_id, skip) in &duplicates.skipped {
+			let why = match skip {
+				| Skip::Unknown => "we do not know the room",
+				| Skip::Others => "others were in it",
+				| Skip::Missing => "either of them is not in it",
+			};
+
+			writeln!(out, "- left alone {room_id}: {why}")?;
+		}
+	}
+
+	let extras = found
+		.iter()
+		.map(|duplicates| duplicates.extras.len())
+		.fold(0_usize, usize::saturating_add);
+
+	if extras == 0 {
+		writeln!(out, "\n{user_id} has no duplicate direct message rooms.")?;
+		return Ok(RoomMessageEventContent::notice_markdown(out));
+	}
+
+	if dry_run {
+		let action = if merge { "tombstoned and dropped" } else { "dropped" };
+		writeln!(out, "\nDry run: {extras} rooms would be {action} from m.direct.")?;
+		return Ok(RoomMessageEventContent::notice_markdown(out));
+	}
+
+	let merged = self
+		.services
+		.rooms
+		.direct
+		.merge(&user_id, &found, merge)
+		.await?;
+
+	writeln!(out)?;
+	for room_id in &merged.tombstoned {
+		writeln!(out, "Tombstoned {room_id}.")?;
+	}
+
+	for (room_id, e) in &merged.failed {
+		writeln!(out, "Could not tombstone {room_id}: {e}")?;
+	}
+
+	if merged.rewritten {
+		writeln!(out, "Dropped {extras} rooms from the m.direct of {user_id}.")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+/// A user of `users list --flags`, followed by the flags they have.
+pub(super) fn flagged<const N: usize>(user_id: &UserId, flags: [(bool, &str); N]) -> String {
+	let flags: Vec<_> = flags
+		.into_iter()
+		.filter_map(|(set, flag)| set.then_some(flag))
+		.collect();
+
+	if flags.is_empty() {
+		return user_id.to_string();
+	}
+
+	format!("{user_id} [{}]", flags.join(", "))
+}
@@ -0,0 +1,162 @@
+use std::{collections::BTreeSet, fmt::Write as _, future::Future};
+
+use api::client::{full_user_deactivate, leave_all_rooms};
+use conduwuit::{Result, err, info, warn};
+use futures::StreamExt;
+use ruma::{OwnedRoomId, OwnedUserId};
+use service::Services;
+
+use crate::utils::parse_active_local_user_id;
+
+/// Whether a listed user is to be deactivated.
+#[derive(Debug)]
+pub(super) enum Check {
+	Deactivate(OwnedUserId),
+
+	/// Left active, for the reason given.
+	Skip(&'static str),
+}
+
+/// What became of the listed users, with the reasons of those skipped or
+/// failed.
+#[derive(Debug, Default)]
+pub(super) struct Summary {
+	pub(super) deactivated: Vec<OwnedUserId>,
+	pub(super) skipped: Vec<(String, String)>,
+	pub(super) failed: Vec<(String, String)>,
+}
+
+/// What else is done for each user deactivated.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Options {
+	pub(super) leave_rooms: bool,
+	pub(super) remove_devices: bool,
+	pub(super) purge_media: bool,
+}
+
+/// The users listed in the lines of a code block, once each and in order.
+pub(super) fn listed<'a>(lines: &[&'a str]) -> Vec<&'a str> {
+	let mut seen = BTreeSet::new();
+	lines
+		.iter()
+		.map(|line| line.trim())
+		.filter(|line| !line.is_empty() && seen.insert(*line))
+		.collect()
+}
+
+/// Deactivates each listed user which `check` allows with `deactivate`, one
+/// at a time. A user failing is noted and the rest carry on.
+pub(super) async fn deactivate_listed<'a, C, CF, D, DF>(
+	listed: &[&'a str],
+	check: C,
+	deactivate: D,
+) -> Summary
+where
+	C: Fn(&'a str) -> CF,
+	CF: Future<Output = Result<Check>>,
+	D: Fn(OwnedUserId) -> DF,
+	DF: Future<Output = Result>,
+{
+	let mut summary = Summary::default();
+	for &username in listed {
+		let user_id = match check(username).await {
+			| Ok(Check::Deactivate(user_id)) => user_id,
+			| Ok(Check::Skip(reason)) => {
+				warn!("Not deactivating {username}: {reason}");
+				summary
+					.skipped
+					.push((username.to_owned(), reason.to_owned()));
+				continue;
+			},
+			| Err(e) => {
+				summary.failed.push((username.to_owned(), e.message()));
+				continue;
+			},
+		};
+
+		match deactivate(user_id.clone()).await {
+			| Ok(()) => summary.deactivated.push(user_id),
+			| Err(e) => {
+				warn!("Failed to deactivate {user_id}: {e}");
+				summary.failed.push((user_id.to_string(), e.message()));
+			},
+		}
+	}
+
+	summary
+}
+
+/// Whether the listed user is an active local user to deactivate.
+pub(super) async fn check(services: &Services, username: &str, force: bool) -> Result<Check> {
+	let user_id = parse_active_local_user_id(services, username).await?;
+	if user_id == services.globals.server_user {
+		return Ok(Check::Skip("the server service account"));
+	}
+
+	if !force && services.users.is_admin(&user_id).await {
+		return Ok(Check::Skip("an admin, and --force is not set"));
+	}
+
+	Ok(Check::Deactivate(user_id))
+}
+
+/// Deactivates the user, then does what else the options ask.
+pub(super) async fn deactivate(
+	services: &Services,
+	user_id: OwnedUserId,
+	options: Options,
+) -> Result {
+	services.users.deactivate_account(&user_id).await?;
+	if options.remove_devices {
+		services.users.remove_keys(&user_id).await;
+	}
+
+	if options.leave_rooms {
+		info!("Forcing user {user_id} to leave all rooms apart of deactivate-all");
+		let all_joined_rooms: Vec<OwnedRoomId> = services
+			.rooms
+			.state_cache
+			.rooms_joined(&user_id)
+			.map(Into::into)
+			.collect()
+			.await;
+
+		full_user_deactivate(services, &user_id, &all_joined_rooms).await?;
+		leave_all_rooms(services, &user_id).await;
+	}
+
+	if options.purge_media {
+		services
+			.media
+			.delete_from_user(&user_id)
+			.await
+			.map_err(|e| err!("Deactivated, but failed to delete their media: {e}"))?;
+	}
+
+	Ok(())
+}
+
+impl Summary {
+	/// The summary as markdown, listing the users not deactivated.
+	pub(super) fn markdown(&self) -> Result<String> {
+		let mut out = format!(
+			"Deactivated {} users, skipped {}, failed {}.\n",
+			self.deactivated.len(),
+			self.skipped.len(),
+			self.failed.len(),
+		);
+
+		for (heading, users) in [("Skipped", &self.skipped), ("Failed", &self.failed)] {
+			if users.is_empty() {
+				continue;
+			}
+
+			writeln!(out, "\n{heading}:")?;
+			for (user, reason) in users {
+				writeln!(out, "- {user}: {reason}")?;
+			}
+		}
+
+		Ok(out)
+	}
+}
@@ -0,0 +1,186 @@
+use api::client::{join_room_by_id_helper, unban_member};
+use conduwuit::{Err, Result, info, matrix::pdu::PduBuilder};
+use futures::{FutureExt, StreamExt};
+use ruma::{
+	OwnedEventId, OwnedServerName, OwnedUserId, RoomId, UserId,
+	events::{
+		StateEventType,
+		room::{
+			join_rules::JoinRule,
+			member::{MembershipState, RoomMemberEventContent},
+		},
+	},
+};
+use service::{Services, rooms::state::RoomMutexGuard, users::MembershipEntry};
+
+const REASON: &str = "Force joined by the server admin.";
+
+/// What is done on the user's behalf before they join a room this server is
+/// in, so that their join passes its auth rules.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub(super) struct Plan {
+	/// Lift the user's ban.
+	pub(super) unban: bool,
+
+	/// Invite the user, as the room is not open to all.
+	pub(super) invite: bool,
+}
+
+/// Plans the force join of a user whose current membership of the room is
+/// `membership`. A ban is only lifted when `override_ban` is set.
+pub(super) fn plan(
+	membership: &MembershipState,
+	join_rule: &JoinRule,
+	override_ban: bool,
+) -> Result<Plan> {
+	let unban = match membership {
+		| MembershipState::Ban if !override_ban => {
+			return Err!("The user is banned from the room; pass --override-ban to unban them.");
+		},
+		| MembershipState::Ban => true,
+		| _ => false,
+	};
+
+	let invite = *membership != MembershipState::Invite && *join_rule != JoinRule::Public;
+
+	Ok(Plan { unban, invite })
+}
+
+/// Joins a local user to the room, returning their join event. Rooms this
+/// server is not in are joined over federation as the user would join them.
+pub(super) async fn force_join(
+	services: &Services,
+	user_id: &UserId,
+	room_id: &RoomId,
+	servers: &[OwnedServerName],
+	override_ban: bool,
+) -> Result<OwnedEventId> {
+	services
+		.users
+		.check_membership_entry(user_id, MembershipEntry::ForceJoin)
+		.await?;
+
+	if services
+		.rooms
+		.state_cache
+		.server_in_room(services.globals.server_name(), room_id)
+		.await
+	{
+		let state_lock = services.rooms.state.mutex.lock(room_id).await;
+		let membership = services
+			.rooms
+			.state_accessor
+			.get_member(room_id, user_id)
+			.await
+			.map_or(MembershipState::Leave, |member| member.membership);
+
+		if membership == MembershipState::Join {
+			drop(state_lock);
+			return member_event(services, user_id, room_id).await;
+		}
+
+		let join_rule = services.rooms.state_accessor.get_join_rules(room_id).await;
+		let plan = plan(&membership, &join_rule, override_ban)?;
+		if plan.unban {
+			let sponsor =
+				sponsor(services, user_id, room_id, MembershipState::Leave, &state_lock).await?;
+
+			info!(%user_id, %room_id, %sponsor, "Unbanning to force join");
+			unban_member(
+				services,
+				&sponsor,
+				room_id,
+				user_id,
+				Some(REASON.to_owned()),
+				&state_lock,
+			)
+			.await?;
+		}
+
+		if plan.invite {
+			let sponsor =
+				sponsor(services, user_id, room_id, MembershipState::Invite, &state_lock).await?;
+
+			info!(%user_id, %room_id, %sponsor, "Inviting to force join");
+			services
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
+						reason: Some(REASON.to_owned()),
+						..RoomMemberEventContent::new(MembershipState::Invite)
+					}),
+					&sponsor,
+					room_id,
+					&state_lock,
+				)
+				.await?;
+		}
+	}
+
+	join_room_by_id_helper(
+		services,
+		user_id,
+		room_id,
+		Some(REASON.to_owned()),
+		servers,
+		None,
+		&None,
+	)
+	.await?;
+
+	member_event(services, user_id, room_id).await
+}
+
+/// The event of the user's current membership of the room.
+pub(super) async fn member_event(
+	services: &Services,
+	user_id: &UserId,
+	room_id: &RoomId,
+) -> Result<OwnedEventId> {
+	services
+		.rooms
+		.state_accessor
+		.room_state_get(room_id, &StateEventType::RoomMember, user_id.as_str())
+		.await
+		.map(|pdu| pdu.event_id)
+}
+
+/// Picks a local member of the room the auth rules let set the user's
+/// membership to `membership`.
+async fn sponsor(
+	services: &Services,
+	user_id: &UserId,
+	room_id: &RoomId,
+	membership: MembershipState,
+	state_lock: &RoomMutexGuard,
+) -> Result<OwnedUserId> {
+	let content = RoomMemberEventContent::new(membership.clone());
+	let sponsor = services
+		.rooms
+		.state_cache
+		.local_users_in_room(room_id)
+		.filter(|&sender| {
+			services
+				.rooms
+				.timeline
+				.create_hash_and_sign_event(
+					PduBuilder::state(user_id.to_string(), &content),
+					sender,
+					room_id,
+					state_lock,
+				)
+				.map(|result| result.is_ok())
+		})
+		.boxed()
+		.next()
+		.await;
+
+	match sponsor {
+		| Some(sponsor) => Ok(sponsor.to_owned()),
+		| None => Err!(
+			"No user of this server in the room has the power to set {user_id}'s membership to \
+			 {membership}."
+		),
+	}
+}
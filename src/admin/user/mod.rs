@@ -1,4 +1,11 @@
 mod commands;
+mod deactivate;
+mod force_join;
+mod redact;
+#[cfg(test)]
+mod tests;
+
+use std::path::PathBuf;
 
 use clap::Subcommand;
 use conduwuit::Result;
@@ -40,28 +47,101 @@ pub(super) enum UserCommand {
 	///
 	/// Recommended to use in conjunction with list-local-users.
 	///
-	/// Users will be removed from joined rooms by default.
-	///
-	/// Can be overridden with --no-leave-rooms.
+	/// Each user's access tokens, devices and device keys are deleted and
+	/// their account is marked deactivated. Admins and the server service
+	/// account are skipped with a warning, and a user failing does not stop
+	/// the others; a summary of who was deactivated, skipped or failed and
+	/// why follows.
 	///
-	/// Removing a mass amount of users from a room may cause a significant
+	/// Making a mass amount of users leave their rooms may cause a significant
 	/// amount of leave events. The time to leave rooms may depend significantly
 	/// on joined rooms and servers.
 	///
 	/// This command needs a newline separated list of users provided in a
 	/// Markdown code block below the command.
 	DeactivateAll {
+		/// Make the users leave all rooms they are in
+		#[arg(long)]
+		leave_rooms: bool,
+
+		/// Also delete the users' cross-signing and one-time keys, so nothing
+		/// of their devices is left for others to encrypt to
+		#[arg(long)]
+		remove_devices: bool,
+
+		/// Keep the media the users uploaded instead of deleting it
+		#[arg(long)]
+		no_purge_media: bool,
+
+		/// Also deactivate admin accounts
 		#[arg(short, long)]
-		/// Does not leave any rooms the user is in on deactivation
-		no_leave_rooms: bool,
-		#[arg(short, long)]
-		/// Also deactivate admin accounts and will assume leave all rooms too
 		force: bool,
 	},
 
+	/// - Suspend a local user
+	///
+	/// A suspended user keeps their rooms but cannot join, knock on or be
+	/// invited to new ones until they are unsuspended.
+	Suspend {
+		user_id: String,
+	},
+
+	/// - Lift the suspension of a local user
+	Unsuspend {
+		user_id: String,
+	},
+
+	/// - Shadow-ban a local user
+	///
+	/// The user's messages, redactions, invites and state events other than
+	/// their own joins and leaves are dropped, while they are told these were
+	/// sent. Nothing else about their account changes.
+	ShadowBan {
+		user_id: String,
+	},
+
+	/// - Lift the shadow-ban of a local user
+	UnshadowBan {
+		user_id: String,
+	},
+
+	/// - Show when a local user's account expires and where its renewal link is
+	///   sent
+	AccountValidity {
+		user_id: String,
+	},
+
+	/// - Renew a local user's account
+	///
+	/// The account is extended from now by the configured validity period,
+	/// unless a number of days is given.
+	RenewAccount {
+		user_id: String,
+
+		#[arg(long)]
+		days: Option<u64>,
+	},
+
+	/// - Expire a local user's account at once
+	ExpireAccount {
+		user_id: String,
+	},
+
+	/// - Set or clear the email address a local user's renewal links are sent
+	///   to
+	SetEmail {
+		user_id: String,
+
+		address: Option<String>,
+	},
+
 	/// - List local users in the database
 	#[clap(alias = "list")]
-	ListUsers,
+	ListUsers {
+		/// Also show which users are admins, suspended or shadow-banned
+		#[arg(long)]
+		flags: bool,
+	},
 
 	/// - Lists all the rooms (local and remote) that the specified user is
 	///   joined in
@@ -70,12 +150,23 @@ pub(super) enum UserCommand {
 	},
 
 	/// - Manually join a local user to a room.
+	///
+	/// In a room this server is in, a local member with the power to do so
+	/// invites the user first if the room is not public, so no invite is
+	/// needed. Rooms this server is not in are joined over federation. Bans
+	/// are respected unless overridden.
+	#[clap(alias = "force-join")]
 	ForceJoinRoom {
 		user_id: String,
 		room_id: OwnedRoomOrAliasId,
+
+		/// Have a local member with the power to do so unban the user first
+		#[arg(long)]
+		override_ban: bool,
 	},
 
 	/// - Manually leave a local user from a room.
+	#[clap(alias = "force-leave")]
 	ForceLeaveRoom {
 		user_id: String,
 		room_id: OwnedRoomOrAliasId,
@@ -127,6 +218,31 @@ pub(super) enum UserCommand {
 		event_id: Box<EventId>,
 	},
 
+	/// - Redacts a user's recent messages in a room as the server user
+	///
+	/// Looks back through the room for the user's messages which are not
+	/// redacted yet, newest first, and redacts at most `--limit` of them,
+	/// none older than `--since`. State events such as memberships are left
+	/// alone. The server user needs the power to redact others' events.
+	RedactMessages {
+		user_id: String,
+
+		room_id: OwnedRoomOrAliasId,
+
+		/// Most events to redact
+		#[arg(long, default_value = "100")]
+		limit: usize,
+
+		/// Redact no events sent before this: milliseconds since the Unix
+		/// epoch, or a time ago such as "2h" or "7d"
+		#[arg(long)]
+		since: Option<String>,
+
+		/// Only list the events which would be redacted
+		#[arg(long)]
+		dry_run: bool,
+	},
+
 	/// - Force joins a specified list of local users to join the specified
 	///   room.
 	///
@@ -153,4 +269,61 @@ pub(super) enum UserCommand {
 		#[arg(long)]
 		yes_i_want_to_do_this: bool,
 	},
+
+	/// - Find and merge a user's duplicate direct message rooms
+	///
+	/// Rooms in the user's `m.direct` with the same person are duplicates
+	/// when both of them are in each and nobody else ever was. The most
+	/// recently active one is kept and the others are dropped from the user's
+	/// `m.direct`. With `--merge` they are also tombstoned pointing at the
+	/// kept room, so that both parties' clients move over, where the user has
+	/// the power to.
+	DedupeDms {
+		user_id: String,
+
+		/// Only report what would be done
+		#[arg(long)]
+		dry_run: bool,
+
+		/// Also tombstone the duplicates
+		#[arg(long)]
+		merge: bool,
+	},
+
+	/// - Export a user's public cross-signing and device keys to a JSON file
+	///
+	/// Signatures held on the keys, including those made by other users, are
+	/// kept so verification state survives a migration. Private keys are never
+	/// held by the server and are not exported.
+	///
+	/// With `--all-users <dir>` every local user is exported to
+	/// `<dir>/<user_id>.json`.
+	ExportKeys {
+		#[arg(required_unless_present = "all_users")]
+		user_id: Option<String>,
+
+		#[arg(required_unless_present = "all_users")]
+		path: Option<PathBuf>,
+
+		#[arg(long, value_name = "DIR", conflicts_with_all = ["user_id", "path"])]
+		all_users: Option<PathBuf>,
+	},
+
+	/// - Import a user's public cross-signing and device keys from a JSON file
+	///
+	/// The export must be for the same user ID and its signatures must verify.
+	/// Device keys are only imported for devices which exist on this server.
+	///
+	/// With `--all-users <dir>` every `<user_id>.json` in the directory is
+	/// imported for the respective local user.
+	ImportKeys {
+		#[arg(required_unless_present = "all_users")]
+		user_id: Option<String>,
+
+		#[arg(required_unless_present = "all_users")]
+		path: Option<PathBuf>,
+
+		#[arg(long, value_name = "DIR", conflicts_with_all = ["user_id", "path"])]
+		all_users: Option<PathBuf>,
+	},
 }
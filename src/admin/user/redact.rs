@@ -0,0 +1,191 @@
+use std::{fmt::Write, time::Duration};
+
+use conduwuit::{
+	Err, PduEvent, Result,
+	matrix::pdu::PduBuilder,
+	utils::stream::{ReadyExt, TryIgnore},
+};
+use conduwuit_database::Lane;
+use futures::{StreamExt, pin_mut};
+use ruma::{
+	OwnedEventId, OwnedRoomOrAliasId, UserId,
+	events::room::{message::RoomMessageEventContent, redaction::RoomRedactionEventContent},
+};
+use tokio::time::{MissedTickBehavior, interval};
+
+use crate::{
+	admin_command,
+	utils::{parse_timestamp, parse_user_id},
+};
+
+/// Time between redactions, so the room's other servers are not flooded.
+const REDACTION_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Redactions between progress reports to the admin room.
+const PROGRESS_EVERY: usize = 50;
+
+const REASON: &str = "Redacted by the server admin.";
+
+/// What becomes of an event of the user found in the room.
+#[derive(Debug, Eq, PartialEq)]
+pub(super) enum Found {
+	Redact,
+
+	/// Skipped, as it was redacted before.
+	Redacted,
+
+	/// Skipped, as redacting state events can change much more than a
+	/// message.
+	State,
+}
+
+/// What becomes of the event, when it is the user's.
+pub(super) fn found(pdu: &PduEvent, user_id: &UserId) -> Option<Found> {
+	if pdu.sender != user_id {
+		return None;
+	}
+
+	if pdu.is_redacted() {
+		return Some(Found::Redacted);
+	}
+
+	if pdu.state_key.is_some() {
+		return Some(Found::State);
+	}
+
+	Some(Found::Redact)
+}
+
+#[admin_command]
+pub(super) async fn redact_messages(
+	&self,
+	user_id: String,
+	room_id: OwnedRoomOrAliasId,
+	limit: usize,
+	since: Option<String>,
+	dry_run: bool,
+) -> Result<RoomMessageEventContent> {
+	let services = self.services;
+	let user_id = parse_user_id(services, &user_id)?;
+	let room_id = services.rooms.alias.resolve(&room_id).await?;
+	let since = since.as_deref().map(parse_timestamp).transpose()?;
+
+	let (to_redact, skipped) = Lane::Background
+		.scope(async {
+			let pdus = services
+				.rooms
+				.timeline
+				.pdus_rev(None, &room_id, None)
+				.ignore_err()
+				.ready_take_while(|(_, pdu)| {
+					since.is_none_or(|since| u64::from(pdu.origin_server_ts) >= since)
+				});
+
+			pin_mut!(pdus);
+			let (mut to_redact, mut skipped) = (Vec::new(), 0_usize);
+			while to_redact.len() < limit {
+				let Some((_, pdu)) = pdus.next().await else {
+					break;
+				};
+
+				match found(&pdu, &user_id) {
+					| Some(Found::Redact) => to_redact.push(pdu.event_id),
+					| Some(Found::Redacted | Found::State) => skipped = skipped.saturating_add(1),
+					| None => (),
+				}
+			}
+
+			(to_redact, skipped)
+		})
+		.await;
+
+	if dry_run {
+		let mut out = format!(
+			"Would redact {} events of {user_id} in {room_id}, skipping {skipped}:\n```\n",
+			to_redact.len()
+		);
+		for event_id in &to_redact {
+			writeln!(out, "{event_id}")?;
+		}
+		out.push_str("```");
+
+		return Ok(RoomMessageEventContent::notice_markdown(out));
+	}
+
+	let Some(first) = to_redact.first() else {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"No events of {user_id} to redact in {room_id}; skipped {skipped}."
+		)));
+	};
+
+	let server_user = &services.globals.server_user;
+	if !services
+		.rooms
+		.state_cache
+		.is_joined(server_user, &room_id)
+		.await
+	{
+		return Err!("{server_user} is not in {room_id}, so cannot redact events there.");
+	}
+
+	if !services
+		.rooms
+		.state_accessor
+		.user_can_redact(first, server_user, &room_id, false)
+		.await?
+	{
+		return Err!(
+			"{server_user} lacks the power to redact others' events in {room_id}; found {} \
+			 events of {user_id} to redact.",
+			to_redact.len()
+		);
+	}
+
+	let total = to_redact.len();
+	let (mut redacted, mut failed) = (0_usize, Vec::<(OwnedEventId, String)>::new());
+	let mut ticks = interval(REDACTION_INTERVAL);
+	ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+	for (done, event_id) in to_redact.into_iter().enumerate() {
+		ticks.tick().await;
+		if done > 0 && done % PROGRESS_EVERY == 0 {
+			services
+				.admin
+				.send_text(&format!("Redacted {redacted} of {total} events of {user_id}..."))
+				.await;
+		}
+
+		let state_lock = services.rooms.state.mutex.lock(&room_id).await;
+		let result = services
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					redacts: Some(event_id.clone()),
+					..PduBuilder::timeline(&RoomRedactionEventContent {
+						redacts: Some(event_id.clone()),
+						reason: Some(REASON.to_owned()),
+					})
+				},
+				server_user,
+				&room_id,
+				&state_lock,
+			)
+			.await;
+
+		drop(state_lock);
+		match result {
+			| Ok(_) => redacted = redacted.saturating_add(1),
+			| Err(e) => failed.push((event_id, e.sanitized_message())),
+		}
+	}
+
+	let mut out = format!(
+		"Redacted {redacted} events of {user_id} in {room_id}; skipped {skipped}, failed {}.",
+		failed.len()
+	);
+	for (event_id, error) in &failed {
+		write!(out, "\n- {event_id}: {error}")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
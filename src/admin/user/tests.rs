@@ -0,0 +1,159 @@
+use conduwuit::{Err, PduEvent, Result};
+use ruma::{
+	OwnedUserId,
+	events::room::{
+		join_rules::{AllowRule, JoinRule, Restricted},
+		member::MembershipState,
+	},
+	owned_room_id, owned_user_id, user_id,
+};
+use serde_json::{Value, json};
+
+use super::{
+	commands::flagged,
+	deactivate::{Check, deactivate_listed, listed},
+	force_join::{Plan, plan},
+	redact::{Found, found},
+};
+
+/// Checks as the admin command does, against a server with an admin, a user
+/// whose deactivation fails and users which deactivate fine.
+async fn check(username: &str) -> Result<Check> {
+	match username {
+		| "@conduit:example.com" => Ok(Check::Skip("the server service account")),
+		| "@admin:example.com" => Ok(Check::Skip("an admin, and --force is not set")),
+		| "@ghost:example.com" =>
+			Err!("User \"@ghost:example.com\" does not exist on this server."),
+		| username => Ok(Check::Deactivate(username.try_into()?)),
+	}
+}
+
+async fn deactivate(user_id: OwnedUserId) -> Result {
+	if user_id == "@stuck:example.com" {
+		return Err!("Deactivated, but failed to delete their media: disk full");
+	}
+
+	Ok(())
+}
+
+#[test]
+fn listed_users() {
+	let lines = ["  @a:example.com", "", "@b:example.com", "@a:example.com", "   "];
+	assert_eq!(listed(&lines), ["@a:example.com", "@b:example.com"]);
+}
+
+#[tokio::test]
+async fn deactivate_mixed_list() {
+	let lines = [
+		"@spam1:example.com",
+		"@ghost:example.com",
+		"@admin:example.com",
+		"@stuck:example.com",
+		"not a user",
+		"@conduit:example.com",
+		"@spam2:example.com",
+	];
+
+	let summary = deactivate_listed(&listed(&lines), check, deactivate).await;
+	assert_eq!(summary.deactivated, [
+		owned_user_id!("@spam1:example.com"),
+		owned_user_id!("@spam2:example.com"),
+	]);
+
+	let skipped: Vec<_> = summary
+		.skipped
+		.iter()
+		.map(|(user, _)| user.as_str())
+		.collect();
+	assert_eq!(skipped, ["@admin:example.com", "@conduit:example.com"]);
+
+	let failed: Vec<_> = summary
+		.failed
+		.iter()
+		.map(|(user, _)| user.as_str())
+		.collect();
+	assert_eq!(failed, ["@ghost:example.com", "@stuck:example.com", "not a user"]);
+	assert!(summary.failed[0].1.contains("does not exist"));
+	assert!(summary.failed[1].1.contains("disk full"), "failure after the check");
+
+	let markdown = summary.markdown().expect("formatted");
+	assert!(markdown.starts_with("Deactivated 2 users, skipped 2, failed 3."));
+	assert!(markdown.contains("- @admin:example.com: an admin, and --force is not set"));
+}
+
+#[test]
+fn force_join_invite_only_without_invite() {
+	let planned = plan(&MembershipState::Leave, &JoinRule::Invite, false).expect("planned");
+	assert_eq!(planned, Plan { unban: false, invite: true });
+
+	let knocked = plan(&MembershipState::Knock, &JoinRule::Knock, false).expect("planned");
+	assert_eq!(knocked, Plan { unban: false, invite: true });
+
+	let restricted = JoinRule::Restricted(Restricted::new(vec![AllowRule::room_membership(
+		owned_room_id!("!space:example.com"),
+	)]));
+	let planned = plan(&MembershipState::Leave, &restricted, false).expect("planned");
+	assert!(planned.invite, "the user may not be in the allowed rooms");
+}
+
+#[test]
+fn force_join_needs_nothing() {
+	let public = plan(&MembershipState::Leave, &JoinRule::Public, false).expect("planned");
+	assert_eq!(public, Plan::default());
+
+	let invited = plan(&MembershipState::Invite, &JoinRule::Invite, false).expect("planned");
+	assert_eq!(invited, Plan::default());
+}
+
+#[test]
+fn force_join_banned() {
+	plan(&MembershipState::Ban, &JoinRule::Public, false).expect_err("bans are respected");
+
+	let overridden = plan(&MembershipState::Ban, &JoinRule::Invite, true).expect("planned");
+	assert_eq!(overridden, Plan { unban: true, invite: true });
+
+	let public = plan(&MembershipState::Ban, &JoinRule::Public, true).expect("planned");
+	assert_eq!(public, Plan { unban: true, invite: false });
+}
+
+#[test]
+fn list_users_flagged() {
+	let user_id = user_id!("@alice:example.com");
+	assert_eq!(flagged(user_id, [(false, "admin"), (false, "suspended")]), "@alice:example.com");
+	assert_eq!(
+		flagged(user_id, [(true, "admin"), (false, "suspended"), (true, "shadow-banned")]),
+		"@alice:example.com [admin, shadow-banned]"
+	);
+}
+
+fn pdu(sender: &str, state_key: Option<&str>, unsigned: Value) -> PduEvent {
+	serde_json::from_value(json!({
+		"event_id": "$event",
+		"room_id": "!room:example.com",
+		"sender": sender,
+		"origin_server_ts": 1,
+		"type": if state_key.is_some() { "m.room.member" } else { "m.room.message" },
+		"state_key": state_key,
+		"content": {},
+		"unsigned": unsigned,
+		"prev_events": [],
+		"depth": 1,
+		"auth_events": [],
+		"hashes": { "sha256": "" },
+	}))
+	.expect("valid pdu")
+}
+
+#[test]
+fn redact_messages_found() {
+	let spammer = user_id!("@spam:example.com");
+	let message = pdu("@spam:example.com", None, json!({}));
+	assert_eq!(found(&message, spammer), Some(Found::Redact));
+	assert_eq!(found(&message, user_id!("@alice:example.com")), None);
+
+	let redacted = pdu("@spam:example.com", None, json!({ "redacted_because": {} }));
+	assert_eq!(found(&redacted, spammer), Some(Found::Redacted));
+
+	let join = pdu("@spam:example.com", Some("@spam:example.com"), json!({}));
+	assert_eq!(found(&join, spammer), Some(Found::State));
+}
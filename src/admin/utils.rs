@@ -1,4 +1,6 @@
-use conduwuit_core::{Err, Result, err};
+use std::time::UNIX_EPOCH;
+
+use conduwuit_core::{Err, Result, err, utils::time};
 use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
 use service::Services;
 
@@ -63,3 +65,19 @@ pub(crate) async fn parse_active_local_user_id(
 
 	Ok(user_id)
 }
+
+/// Parses a point in time: milliseconds since the unix epoch, or a relative
+/// time ago such as `30d`.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Result<u64> {
+	if let Ok(ts) = timestamp.parse::<u64>() {
+		return Ok(ts);
+	}
+
+	let ago = time::parse_timepoint_ago(timestamp)
+		.map_err(|e| err!("Expected milliseconds since the epoch or a time ago: {e}"))?;
+
+	ago.duration_since(UNIX_EPOCH)
+		.ok()
+		.and_then(|since| since.as_millis().try_into().ok())
+		.ok_or_else(|| err!("{timestamp:?} is before the epoch."))
+}
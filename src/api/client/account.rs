@@ -9,7 +9,7 @@
 	utils::{ReadyExt, stream::BroadbandExt},
 	warn,
 };
-use conduwuit_service::Services;
+use conduwuit_service::{Services, users::MembershipEntry};
 use futures::{FutureExt, StreamExt};
 use register::RegistrationKind;
 use ruma::{
@@ -343,6 +343,11 @@ pub(crate) async fn register_route(
 	// Create user
 	services.users.create(&user_id, password)?;
 
+	// Appservice users never expire, so their validity is not tracked
+	if body.appservice_info.is_none() {
+		services.account_validity.start(&user_id);
+	}
+
 	// Default to pretty displayname
 	let mut displayname = user_id.localpart().to_owned();
 
@@ -497,6 +502,11 @@ pub(crate) async fn register_route(
 	if body.appservice_info.is_none()
 		&& !services.server.config.auto_join_rooms.is_empty()
 		&& (services.config.allow_guests_auto_join_rooms || !is_guest)
+		&& services
+			.users
+			.check_membership_entry(&user_id, MembershipEntry::AutoJoin)
+			.await
+			.is_ok()
 	{
 		for room in &services.server.config.auto_join_rooms {
 			let Ok(room_id) = services.rooms.alias.resolve(room).await else {
@@ -0,0 +1,54 @@
+use axum::{
+	extract::{RawQuery, State},
+	response::{Html, IntoResponse},
+};
+use conduwuit::utils::{HtmlEscape, time};
+use http::StatusCode;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct RenewQuery {
+	token: String,
+}
+
+/// # `GET /_matrix/client/unstable/account_validity/renew`
+///
+/// Renews the account the emailed renewal link was sent to. The link is
+/// opened in a browser, so this answers with a page rather than JSON, and is
+/// reached without an access token, as the account has likely expired.
+pub(crate) async fn account_validity_renew_route(
+	State(services): State<crate::State>,
+	RawQuery(query): RawQuery,
+) -> impl IntoResponse {
+	let Ok(RenewQuery { token }) =
+		serde_html_form::from_str(query.as_deref().unwrap_or_default())
+	else {
+		return (StatusCode::BAD_REQUEST, page("The renewal link is incomplete."));
+	};
+
+	match services.account_validity.renew_with_token(&token).await {
+		| Ok((user_id, expires_at)) => (
+			StatusCode::OK,
+			page(&format!(
+				"Your account {} has been renewed until {}.",
+				HtmlEscape(user_id.as_str()),
+				time::format(expires_at, "%Y-%m-%d %H:%M UTC"),
+			)),
+		),
+		| Err(_) => (
+			StatusCode::FORBIDDEN,
+			page("This renewal link is invalid or has already been used."),
+		),
+	}
+}
+
+fn page(message: &str) -> Html<String> {
+	Html(format!(
+		r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Account renewal</title></head>
+<body><p>{message}</p></body>
+</html>
+"#
+	))
+}
@@ -0,0 +1,478 @@
+use std::fmt::Write as _;
+
+use axum::extract::State;
+use conduwuit::{Err, Result, debug, err, error, info, is_equal_to, utils, warn};
+use conduwuit_service::{Services, users::MembershipEntry};
+use futures::StreamExt;
+use ruma::{
+	OwnedRoomId, OwnedUserId, UserId,
+	events::{
+		GlobalAccountDataEventType,
+		push_rules::{PushRulesEvent, PushRulesEventContent},
+		room::message::RoomMessageEventContent,
+	},
+	push::Ruleset,
+};
+
+use super::{full_user_deactivate, join_room_by_id_helper, leave_all_rooms};
+use crate::Ruma;
+
+/// Length of the passwords generated for users created or reset without one.
+const GENERATED_PASSWORD_LENGTH: usize = 25;
+
+/// `POST /_conduwuit/admin/v1/users`
+///
+/// conduwuit-specific API for server admins to create a local user.
+pub(crate) mod admin_create_user {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/admin/v1/users",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			pub(crate) localpart: String,
+
+			/// Generated when not given.
+			#[serde(default, skip_serializing_if = "Option::is_none")]
+			pub(crate) password: Option<String>,
+
+			/// Whether to make the user a server admin.
+			#[serde(default)]
+			pub(crate) admin: bool,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			pub(crate) user_id: OwnedUserId,
+
+			/// The password generated, if none was given.
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) password: Option<String>,
+		}
+	}
+}
+
+/// `POST /_conduwuit/admin/v1/users/{userId}/password`
+///
+/// conduwuit-specific API for server admins to reset the password of a local
+/// user.
+pub(crate) mod admin_reset_password {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/admin/v1/users/:user_id/password",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) user_id: OwnedUserId,
+
+			/// Generated when not given.
+			#[serde(default, skip_serializing_if = "Option::is_none")]
+			pub(crate) password: Option<String>,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			/// The password generated, if none was given.
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) password: Option<String>,
+		}
+	}
+}
+
+/// `POST /_conduwuit/admin/v1/users/{userId}/deactivate`
+///
+/// conduwuit-specific API for server admins to deactivate a local user.
+pub(crate) mod admin_deactivate_user {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/admin/v1/users/:user_id/deactivate",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) user_id: OwnedUserId,
+
+			/// Whether to keep the user in their rooms rather than have them
+			/// leave all of them.
+			#[serde(default)]
+			pub(crate) no_leave_rooms: bool,
+		}
+
+		#[response]
+		#[derive(Default)]
+		pub(crate) struct Response {}
+	}
+}
+
+/// `GET /_conduwuit/admin/v1/users`
+///
+/// conduwuit-specific API for server admins to list the local users.
+pub(crate) mod admin_list_users {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: GET,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/admin/v1/users",
+			}
+		};
+
+		#[request]
+		#[derive(Default)]
+		pub(crate) struct Request {}
+
+		#[response]
+		pub(crate) struct Response {
+			pub(crate) users: Vec<OwnedUserId>,
+		}
+	}
+}
+
+/// # `POST /_conduwuit/admin/v1/users`
+///
+/// Creates a local user as the `users create-user` admin command does,
+/// generating a password unless one is given. The sender must be a server
+/// admin.
+pub(crate) async fn admin_create_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<admin_create_user::v1::Request>,
+) -> Result<admin_create_user::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let (user_id, password) =
+		provision_user(&services, &body.localpart, body.password.clone(), body.admin).await?;
+
+	info!(sender = %body.sender_user(), %user_id, "Created user through the admin API");
+
+	Ok(admin_create_user::v1::Response { user_id, password })
+}
+
+/// # `POST /_conduwuit/admin/v1/users/{userId}/password`
+///
+/// Resets the password of a local user, generating one unless it is given,
+/// which logs none of their devices out. The sender must be a server admin.
+pub(crate) async fn admin_reset_password_route(
+	State(services): State<crate::State>,
+	body: Ruma<admin_reset_password::v1::Request>,
+) -> Result<admin_reset_password::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let user_id = &body.user_id;
+	check_managed(&services, user_id).await?;
+
+	let generated = body.password.is_none().then(generated_password);
+	let password = body
+		.password
+		.as_deref()
+		.or(generated.as_deref())
+		.expect("password given or generated");
+
+	services.users.set_password(user_id, Some(password))?;
+	info!(sender = %body.sender_user(), %user_id, "Reset password through the admin API");
+
+	Ok(admin_reset_password::v1::Response { password: generated })
+}
+
+/// # `POST /_conduwuit/admin/v1/users/{userId}/deactivate`
+///
+/// Deactivates a local user, having them leave all their rooms unless asked
+/// not to. The sender must be a server admin.
+pub(crate) async fn admin_deactivate_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<admin_deactivate_user::v1::Request>,
+) -> Result<admin_deactivate_user::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let user_id = &body.user_id;
+	check_managed(&services, user_id).await?;
+
+	deactivate_local_user(&services, user_id, !body.no_leave_rooms).await?;
+	info!(sender = %body.sender_user(), %user_id, "Deactivated user through the admin API");
+
+	Ok(admin_deactivate_user::v1::Response::default())
+}
+
+/// # `GET /_conduwuit/admin/v1/users`
+///
+/// Lists the local users, deactivated ones included. The sender must be a
+/// server admin.
+pub(crate) async fn admin_list_users_route(
+	State(services): State<crate::State>,
+	body: Ruma<admin_list_users::v1::Request>,
+) -> Result<admin_list_users::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let users = services
+		.users
+		.list_local_users()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	Ok(admin_list_users::v1::Response { users })
+}
+
+/// Deactivates a local user, clearing their profile and having them leave all
+/// their rooms when asked to.
+pub(super) async fn deactivate_local_user(
+	services: &Services,
+	user_id: &UserId,
+	leave_rooms: bool,
+) -> Result {
+	services.users.deactivate_account(user_id).await?;
+	if leave_rooms {
+		let all_joined_rooms: Vec<OwnedRoomId> = services
+			.rooms
+			.state_cache
+			.rooms_joined(user_id)
+			.map(Into::into)
+			.collect()
+			.await;
+
+		full_user_deactivate(services, user_id, &all_joined_rooms).await?;
+		leave_all_rooms(services, user_id).await;
+	}
+
+	Ok(())
+}
+
+/// Creates the local user with the localpart, generating a password unless one
+/// is given. Returns the user's ID and the password generated, if any.
+pub async fn provision_user(
+	services: &Services,
+	localpart: &str,
+	password: Option<String>,
+	admin: bool,
+) -> Result<(OwnedUserId, Option<String>)> {
+	let user_id = local_user_id(services, localpart)?;
+	if services.users.exists(&user_id).await {
+		return Err!(Request(UserInUse("User {user_id} already exists.")));
+	}
+
+	let generated = password.is_none().then(generated_password);
+	let password = password
+		.as_deref()
+		.or(generated.as_deref())
+		.expect("password given or generated");
+
+	create_local_user(services, &user_id, password, admin).await?;
+
+	Ok((user_id, generated))
+}
+
+/// Creates a local user with everything a registration sets up: a display
+/// name, the default push rules and the rooms to join automatically. The
+/// first user to join the admin room after it is set up becomes an admin, as
+/// does any user asked to be.
+///
+/// The caller checks the user does not exist yet.
+pub async fn create_local_user(
+	services: &Services,
+	user_id: &UserId,
+	password: &str,
+	admin: bool,
+) -> Result {
+	services.users.create(user_id, Some(password))?;
+	services.account_validity.start(user_id);
+
+	// Default to pretty displayname
+	let mut displayname = user_id.localpart().to_owned();
+
+	// If `new_user_displayname_suffix` is set, registration will push whatever
+	// content is set to the user's display name with a space before it
+	if !services
+		.server
+		.config
+		.new_user_displayname_suffix
+		.is_empty()
+	{
+		write!(displayname, " {}", services.server.config.new_user_displayname_suffix)
+			.expect("should be able to write to string buffer");
+	}
+
+	services.users.set_displayname(user_id, Some(displayname));
+
+	// Initial account data
+	services
+		.account_data
+		.update(
+			None,
+			user_id,
+			GlobalAccountDataEventType::PushRules.to_string().into(),
+			&serde_json::to_value(PushRulesEvent {
+				content: PushRulesEventContent { global: Ruleset::server_default(user_id) },
+			})
+			.expect("to json value always works"),
+		)
+		.await?;
+
+	if !services.server.config.auto_join_rooms.is_empty()
+		&& services
+			.users
+			.check_membership_entry(user_id, MembershipEntry::AutoJoin)
+			.await
+			.is_ok()
+	{
+		for room in &services.server.config.auto_join_rooms {
+			let Ok(room_id) = services.rooms.alias.resolve(room).await else {
+				error!(%user_id, "Failed to resolve room alias to room ID when attempting to auto join {room}, skipping");
+				continue;
+			};
+
+			if !services
+				.rooms
+				.state_cache
+				.server_in_room(services.globals.server_name(), &room_id)
+				.await
+			{
+				warn!(
+					"Skipping room {room} to automatically join as we have never joined before."
+				);
+				continue;
+			}
+
+			if let Some(room_server_name) = room.server_name() {
+				match join_room_by_id_helper(
+					services,
+					user_id,
+					&room_id,
+					Some("Automatically joining this room upon registration".to_owned()),
+					&[services.globals.server_name().to_owned(), room_server_name.to_owned()],
+					None,
+					&None,
+				)
+				.await
+				{
+					| Ok(_response) => {
+						info!("Automatically joined room {room} for user {user_id}");
+					},
+					| Err(e) => {
+						services
+							.admin
+							.send_message(RoomMessageEventContent::text_plain(format!(
+								"Failed to automatically join room {room} for user {user_id}: \
+								 {e}"
+							)))
+							.await
+							.ok();
+						// don't return this error so we don't fail registrations
+						error!(
+							"Failed to automatically join room {room} for user {user_id}: {e}"
+						);
+					},
+				}
+			}
+		}
+	}
+
+	// we dont add a device since we're not the user, just the creator
+
+	// invite the first user to the admin room, or any asked to be an admin
+	if let Ok(admin_room) = services.admin.get_admin_room().await {
+		let first = services
+			.rooms
+			.state_cache
+			.room_joined_count(&admin_room)
+			.await
+			.is_ok_and(is_equal_to!(1));
+
+		if admin || first {
+			services.admin.make_user_admin(user_id).await?;
+		}
+
+		if first {
+			warn!("Granting {user_id} admin privileges as the first user");
+		}
+	} else {
+		debug!("Created user {user_id} without an admin room being available");
+	}
+
+	Ok(())
+}
+
+pub(super) async fn check_admin(services: &Services, sender_user: &UserId) -> Result {
+	if !services.admin.user_is_admin(sender_user).await {
+		return Err!(Request(Forbidden("Only server admins may use the admin API.")));
+	}
+
+	Ok(())
+}
+
+/// Refuses users which are not local or are the server user, whose password
+/// is the emergency password's and which is never deactivated.
+pub(super) async fn check_managed(services: &Services, user_id: &UserId) -> Result {
+	if !services.globals.user_is_local(user_id) {
+		return Err!(Request(InvalidParam("{user_id} is not a local user.")));
+	}
+
+	if *user_id == services.globals.server_user {
+		return Err!(Request(Forbidden(
+			"The server user is managed through the emergency password config option."
+		)));
+	}
+
+	if !services.users.exists(user_id).await {
+		return Err!(Request(NotFound("User {user_id} does not exist.")));
+	}
+
+	Ok(())
+}
+
+fn local_user_id(services: &Services, localpart: &str) -> Result<OwnedUserId> {
+	let user_id =
+		UserId::parse_with_server_name(localpart.to_lowercase(), services.globals.server_name())
+			.map_err(|e| err!(Request(InvalidUsername("Invalid username: {e}"))))?;
+
+	if let Err(e) = user_id.validate_strict() {
+		if services.config.emergency_password.is_none() {
+			return Err!(Request(InvalidUsername(
+				"Username {user_id} contains disallowed characters or spaces: {e}"
+			)));
+		}
+	}
+
+	Ok(user_id)
+}
+
+#[must_use]
+fn generated_password() -> String { utils::random_string(GENERATED_PASSWORD_LENGTH) }
@@ -12,7 +12,7 @@
 use conduwuit_service::rooms::{lazy_loading, lazy_loading::Options, short::ShortStateKey};
 use futures::{
 	FutureExt, StreamExt, TryFutureExt, TryStreamExt,
-	future::{OptionFuture, join, join3, try_join3},
+	future::{OptionFuture, join, join3, try_join},
 };
 use ruma::{OwnedEventId, UserId, api::client::context::get_context, events::StateEventType};
 
@@ -54,22 +54,22 @@ pub(crate) async fn get_context_route(
 	let base_id = services
 		.rooms
 		.timeline
-		.get_pdu_id(event_id)
+		.get_pdu_id_in_room(room_id, event_id)
 		.map_err(|_| err!(Request(NotFound("Event not found."))));
 
-	let base_pdu = services
-		.rooms
-		.timeline
-		.get_pdu(event_id)
-		.map_err(|_| err!(Request(NotFound("Base event not found."))));
-
 	let visible = services
 		.rooms
 		.state_accessor
 		.user_can_see_event(sender_user, room_id, event_id)
 		.map(Ok);
 
-	let (base_id, base_pdu, visible) = try_join3(base_id, base_pdu, visible).await?;
+	let (base_id, visible) = try_join(base_id, visible).await?;
+	let base_pdu = services
+		.rooms
+		.timeline
+		.get_pdu_from_id(&base_id)
+		.await
+		.map_err(|_| err!(Request(NotFound("Base event not found."))))?;
 
 	if base_pdu.room_id != *room_id || base_pdu.event_id != *event_id {
 		return Err!(Request(NotFound("Base event not found.")));
@@ -114,6 +114,7 @@ pub(crate) async fn get_context_route(
 		device_id: sender_device,
 		room_id,
 		token: Some(base_count.into_unsigned()),
+		shortstatehash: None,
 		options: Some(&filter.lazy_load_options),
 	};
 
@@ -179,6 +180,7 @@ pub(crate) async fn get_context_route(
 		.broad_filter_map(|event_id: &OwnedEventId| {
 			services.rooms.timeline.get_pdu(event_id.as_ref()).ok()
 		})
+		.ready_filter(|pdu| pdu.matches(filter))
 		.map(PduEvent::into_state_event)
 		.collect()
 		.await;
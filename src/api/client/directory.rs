@@ -1,15 +1,19 @@
+use std::{cmp::Reverse, fmt};
+
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
 use conduwuit::{
 	Err, Result, err, info,
 	utils::{
 		TryFutureExtExt,
-		math::Expected,
 		result::FlatOk,
 		stream::{ReadyExt, WidebandExt},
 	},
 };
-use conduwuit_service::Services;
+use conduwuit_service::{
+	Services,
+	rooms::directory::{Rank, fold},
+};
 use futures::{
 	FutureExt, StreamExt, TryFutureExt,
 	future::{join, join4, join5},
@@ -43,7 +47,10 @@
 ///
 /// Lists the public rooms on this server.
 ///
-/// - Rooms are ordered by the number of joined members
+/// - Rooms matching the search term are found by their name, topic and
+///   canonical alias regardless of case, accents and punctuation
+/// - Rooms are ordered by how well they match, then by the number of joined
+///   members
 #[tracing::instrument(skip_all, fields(%client), name = "publicrooms")]
 pub(crate) async fn get_public_rooms_filtered_route(
 	State(services): State<crate::State>,
@@ -264,91 +271,135 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 
 	// Use limit or else 10, with maximum 100
 	let limit: usize = limit.map_or(10_u64, u64::from).try_into()?;
-	let mut num_since: usize = 0;
-
-	if let Some(s) = &since {
-		let mut characters = s.chars();
-		let backwards = match characters.next() {
-			| Some('n') => false,
-			| Some('p') => true,
-			| _ => {
-				return Err!(Request(InvalidParam("Invalid `since` token")));
-			},
-		};
-
-		num_since = characters
-			.collect::<String>()
-			.parse()
-			.map_err(|_| err!(Request(InvalidParam("Invalid `since` token."))))?;
-
-		if backwards {
-			num_since = num_since.saturating_sub(limit);
-		}
-	}
-
-	let mut all_rooms: Vec<PublicRoomsChunk> = services
+	let since = since.map(Since::parse).transpose()?;
+	let query = filter
+		.generic_search_term
+		.as_deref()
+		.map(fold)
+		.unwrap_or_default();
+
+	let mut all_rooms: Vec<(Key, PublicRoomsChunk)> = services
 		.rooms
 		.directory
 		.public_rooms()
 		.map(ToOwned::to_owned)
-		.wide_then(|room_id| public_rooms_chunk(services, room_id))
-		.ready_filter_map(|chunk| {
-			if !filter.room_types.is_empty() && !filter.room_types.contains(&RoomTypeFilter::from(chunk.room_type.clone())) {
-				return None;
+		.wide_filter_map(|room_id| {
+			let query = &query;
+			async move {
+				let rank = services.rooms.directory.rank(&room_id, query).await?;
+				Some((rank, room_id))
 			}
-
-			if let Some(query) = filter.generic_search_term.as_ref().map(|q| q.to_lowercase()) {
-				if let Some(name) = &chunk.name {
-					if name.as_str().to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				if let Some(topic) = &chunk.topic {
-					if topic.to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				if let Some(canonical_alias) = &chunk.canonical_alias {
-					if canonical_alias.as_str().to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				return None;
-			}
-
-			// No search term
-			Some(chunk)
 		})
-		// We need to collect all, so we can sort by member count
+		.wide_then(|(rank, room_id)| {
+			public_rooms_chunk(services, room_id).map(move |chunk| (Key::new(rank, &chunk), chunk))
+		})
+		.ready_filter(|(_, chunk)| {
+			filter.room_types.is_empty()
+				|| filter
+					.room_types
+					.contains(&RoomTypeFilter::from(chunk.room_type.clone()))
+		})
+		// We need to collect all, so we can sort by rank and member count
 		.collect()
 		.await;
 
-	all_rooms.sort_by(|l, r| r.num_joined_members.cmp(&l.num_joined_members));
+	all_rooms.sort_by(|(l, _), (r, _)| l.cmp(r));
 
 	let total_room_count_estimate = UInt::try_from(all_rooms.len())
 		.unwrap_or_else(|_| uint!(0))
 		.into();
 
-	let chunk: Vec<_> = all_rooms.into_iter().skip(num_since).take(limit).collect();
+	let (start, end) = match &since {
+		| None => (0, limit.min(all_rooms.len())),
+		| Some(Since::Next(key)) => {
+			let start = all_rooms.partition_point(|(room, _)| room <= key);
+			(start, start.saturating_add(limit).min(all_rooms.len()))
+		},
+		| Some(Since::Prev(key)) => {
+			let end = all_rooms.partition_point(|(room, _)| room < key);
+			(end.saturating_sub(limit), end)
+		},
+	};
+
+	let more = end < all_rooms.len();
+	let chunk: Vec<_> = all_rooms.drain(start..end).collect();
 
-	let prev_batch = num_since.ne(&0).then_some(format!("p{num_since}"));
+	let prev_batch = chunk
+		.first()
+		.filter(|_| start > 0)
+		.map(|(key, _)| format!("p{key}"));
 
 	let next_batch = chunk
-		.len()
-		.ge(&limit)
-		.then_some(format!("n{}", num_since.expected_add(limit)));
+		.last()
+		.filter(|_| more)
+		.map(|(key, _)| format!("n{key}"));
 
 	Ok(get_public_rooms_filtered::v3::Response {
-		chunk,
+		chunk: chunk.into_iter().map(|(_, chunk)| chunk).collect(),
 		prev_batch,
 		next_batch,
 		total_room_count_estimate,
 	})
 }
 
+/// Where a page of the local room directory starts, as the position of the
+/// room either side of it. Rooms are in a total order for a given search, so
+/// paging on from a room carries on where the last page ended even as rooms
+/// are published or their member counts change in between.
+enum Since {
+	Next(Key),
+	Prev(Key),
+}
+
+/// Position of a room in the local room directory: best matches first, then
+/// the most joined members, then by room ID.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct Key {
+	rank: Rank,
+	members: Reverse<UInt>,
+	room_id: OwnedRoomId,
+}
+
+impl Since {
+	fn parse(token: &str) -> Result<Self> {
+		let mut characters = token.chars();
+		let direction = characters.next();
+		let key = Key::parse(characters.as_str())
+			.ok_or_else(|| err!(Request(InvalidParam("Invalid `since` token."))))?;
+
+		match direction {
+			| Some('n') => Ok(Self::Next(key)),
+			| Some('p') => Ok(Self::Prev(key)),
+			| _ => Err!(Request(InvalidParam("Invalid `since` token"))),
+		}
+	}
+}
+
+impl Key {
+	fn new(rank: Rank, chunk: &PublicRoomsChunk) -> Self {
+		Self {
+			rank,
+			members: Reverse(chunk.num_joined_members),
+			room_id: chunk.room_id.clone(),
+		}
+	}
+
+	fn parse(key: &str) -> Option<Self> {
+		let mut parts = key.splitn(3, '_');
+		let rank = parts.next()?.parse().ok().and_then(Rank::from_u8)?;
+		let members = parts.next()?.parse().ok()?;
+		let room_id = parts.next()?.try_into().ok()?;
+
+		Some(Self { rank, members: Reverse(members), room_id })
+	}
+}
+
+impl fmt::Display for Key {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}_{}_{}", self.rank.to_u8(), self.members.0, self.room_id)
+	}
+}
+
 /// Check whether the user can publish to the room directory via power levels of
 /// room history visibility event or room creator
 async fn user_can_publish_room(
@@ -0,0 +1,85 @@
+use std::fmt::Write;
+
+use axum::{
+	Json,
+	extract::State,
+	response::{Html, IntoResponse},
+};
+use http::StatusCode;
+use serde_json::json;
+
+/// # `GET /_conduwuit/health/live`
+///
+/// Liveness probe: answers 200 whenever the process is serving requests.
+pub(crate) async fn health_live_route() -> impl IntoResponse { Json(json!({ "status": "live" })) }
+
+/// # `GET /_conduwuit/health/ready`
+///
+/// Readiness probe: answers 200 when the database and every service are able
+/// to serve requests, otherwise 503 with the failing components and the
+/// reason for each. The server is not ready while shutting down, in read-only
+/// maintenance mode or while shedding load.
+pub(crate) async fn health_ready_route(
+	State(services): State<crate::State>,
+) -> impl IntoResponse {
+	let failing = services.health().await;
+	if failing.is_empty() {
+		return (StatusCode::OK, Json(json!({ "status": "ready" })));
+	}
+
+	(
+		StatusCode::SERVICE_UNAVAILABLE,
+		Json(json!({
+			"status": "not ready",
+			"failing": failing,
+		})),
+	)
+}
+
+/// # `GET /`
+///
+/// Human-readable landing page naming the server and linking to its
+/// well-known documents. Replaced by a plain greeting when `index_page` is
+/// disabled.
+pub(crate) async fn index_route(State(services): State<crate::State>) -> impl IntoResponse {
+	let server_name = services.globals.server_name();
+	let name = conduwuit::version::name();
+	let version = conduwuit::version::version();
+
+	let mut links = vec![
+		("/.well-known/matrix/client", "Client discovery"),
+		("/.well-known/matrix/support", "Support contacts"),
+	];
+
+	if services.server.config.allow_federation {
+		links.push(("/.well-known/matrix/server", "Server discovery"));
+	}
+
+	links.extend([
+		("/_matrix/client/versions", "Supported client API versions"),
+		("/_conduwuit/server_version", "Server version"),
+		("/_conduwuit/health/ready", "Readiness"),
+	]);
+
+	let links = links
+		.into_iter()
+		.fold(String::new(), |mut out, (href, title)| {
+			_ = write!(out, "<li><a href=\"{href}\">{title}</a></li>");
+			out
+		});
+
+	Html(format!(
+		r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>{server_name}</title></head>
+<body>
+<h1>{server_name}</h1>
+<p>This is a Matrix homeserver running {name} {version}.</p>
+<ul>{links}</ul>
+</body>
+</html>
+"#
+	))
+}
+
+pub(crate) async fn it_works() -> &'static str { "hewwo from conduwuit woof!" }
@@ -2,7 +2,7 @@
 
 use axum::extract::State;
 use conduwuit::{Err, Error, Result, debug, debug_warn, err, result::NotFound, utils};
-use conduwuit_service::{Services, users::parse_master_key};
+use conduwuit_service::{Services, sync::Token, users::parse_master_key};
 use futures::{StreamExt, stream::FuturesUnordered};
 use ruma::{
 	OneTimeKeyAlgorithm, OwnedDeviceId, OwnedUserId, UserId,
@@ -379,13 +379,15 @@ pub(crate) async fn get_key_changes_route(
 
 	let from = body
 		.from
-		.parse()
-		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid `from`."))?;
+		.parse::<Token>()
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid `from`."))?
+		.count;
 
 	let to = body
 		.to
-		.parse()
-		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid `to`."))?;
+		.parse::<Token>()
+		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid `to`."))?
+		.count;
 
 	device_list_updates.extend(
 		services
@@ -0,0 +1,88 @@
+use axum::extract::State;
+use conduwuit::{Err, Result};
+use ruma::Mxc;
+
+use crate::Ruma;
+
+/// `GET /_conduwuit/client/v1/media/{serverName}/{mediaId}/info`
+///
+/// conduwuit-specific API to get the properties of a file determined when it
+/// was stored.
+pub(crate) mod get_media_info {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedServerName,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: GET,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/client/v1/media/:server_name/:media_id/info",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) server_name: OwnedServerName,
+
+			#[ruma_api(path)]
+			pub(crate) media_id: String,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) width: Option<u32>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) height: Option<u32>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) duration_ms: Option<u64>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) blurhash: Option<String>,
+		}
+	}
+}
+
+/// # `GET /_conduwuit/client/v1/media/{serverName}/{mediaId}/info`
+///
+/// Width, height, duration and blurhash of a file as far as they could be
+/// determined when it was stored. Only its uploader and server admins may
+/// see them; the rooms a file was posted in are not tracked, so their members
+/// cannot be allowed.
+pub(crate) async fn get_media_info_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_media_info::v1::Request>,
+) -> Result<get_media_info::v1::Response> {
+	let sender_user = body.sender_user();
+	let mxc = Mxc {
+		server_name: &body.server_name,
+		media_id: &body.media_id,
+	};
+
+	if services.media.get_metadata(&mxc).await.is_none() {
+		return Err!(Request(NotFound("Media not found.")));
+	}
+
+	let uploader = services.media.get_uploader(&mxc).await;
+	if uploader.as_deref() != Some(sender_user)
+		&& !services.admin.user_is_admin(sender_user).await
+	{
+		return Err!(Request(Forbidden("Only the uploader of this media may see its info.")));
+	}
+
+	let info = services.media.get_info(&mxc).await;
+
+	Ok(get_media_info::v1::Response {
+		width: info.width,
+		height: info.height,
+		duration_ms: info.duration_ms,
+		blurhash: info.blurhash,
+	})
+}
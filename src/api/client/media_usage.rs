@@ -0,0 +1,162 @@
+use axum::extract::State;
+use conduwuit::{Err, Result, utils::math::usize_from_u64_truncated};
+use conduwuit_service::media::page;
+use ruma::Mxc;
+
+use crate::Ruma;
+
+/// Most uploads listed in one page.
+const MAX_LIMIT: usize = 100;
+
+/// `GET /_conduwuit/client/v1/media/usage`
+///
+/// conduwuit-specific API to list the files the user uploaded.
+pub(crate) mod get_media_usage {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedMxcUri, UInt,
+			api::{Metadata, metadata, request, response},
+		};
+		use serde::{Deserialize, Serialize};
+
+		const METADATA: Metadata = metadata! {
+			method: GET,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/client/v1/media/usage",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			/// Where the page starts, from the `next_batch` of the previous
+			/// one.
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) from: Option<String>,
+
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) limit: Option<UInt>,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			/// Most recently uploaded first.
+			pub(crate) uploads: Vec<Upload>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) next_batch: Option<String>,
+		}
+
+		#[derive(Clone, Debug, Deserialize, Serialize)]
+		pub(crate) struct Upload {
+			pub(crate) content_uri: OwnedMxcUri,
+
+			pub(crate) size: u64,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) content_type: Option<String>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) uploaded_ts: Option<u64>,
+		}
+	}
+}
+
+/// `DELETE /_conduwuit/client/v1/media/{serverName}/{mediaId}`
+///
+/// conduwuit-specific API to delete a file the user uploaded.
+pub(crate) mod delete_media {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedServerName,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: DELETE,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/client/v1/media/:server_name/:media_id",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) server_name: OwnedServerName,
+
+			#[ruma_api(path)]
+			pub(crate) media_id: String,
+		}
+
+		#[response]
+		#[derive(Default)]
+		pub(crate) struct Response {}
+	}
+}
+
+/// # `GET /_conduwuit/client/v1/media/usage`
+///
+/// The files the user uploaded with their size, content type and when they
+/// were uploaded, a page at a time.
+pub(crate) async fn get_media_usage_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_media_usage::v1::Request>,
+) -> Result<get_media_usage::v1::Response> {
+	let from = match body.from.as_deref().map(str::parse) {
+		| None => 0,
+		| Some(Ok(from)) => from,
+		| Some(Err(_)) => return Err!(Request(InvalidParam("Invalid from token."))),
+	};
+
+	let limit = body
+		.limit
+		.map(u64::from)
+		.map_or(MAX_LIMIT, usize_from_u64_truncated)
+		.clamp(1, MAX_LIMIT);
+
+	let uploads = services.media.get_uploads(body.sender_user()).await;
+	let (uploads, next) = page(uploads, from, limit);
+
+	Ok(get_media_usage::v1::Response {
+		uploads: uploads
+			.into_iter()
+			.map(|upload| get_media_usage::v1::Upload {
+				content_uri: upload.mxc,
+				size: upload.size,
+				content_type: upload.content_type,
+				uploaded_ts: upload.uploaded_ts,
+			})
+			.collect(),
+		next_batch: next.as_ref().map(ToString::to_string),
+	})
+}
+
+/// # `DELETE /_conduwuit/client/v1/media/{serverName}/{mediaId}`
+///
+/// Deletes a file the user uploaded and its thumbnails, when the server allows
+/// users to. It is gone for everyone the file was shared with too.
+pub(crate) async fn delete_media_route(
+	State(services): State<crate::State>,
+	body: Ruma<delete_media::v1::Request>,
+) -> Result<delete_media::v1::Response> {
+	if !services.server.config.allow_users_delete_own_media {
+		return Err!(Request(Forbidden("Deleting media is disabled on this server.")));
+	}
+
+	let mxc = Mxc {
+		server_name: &body.server_name,
+		media_id: &body.media_id,
+	};
+
+	services
+		.media
+		.delete_upload(body.sender_user(), &mxc)
+		.await?;
+
+	Ok(delete_media::v1::Response {})
+}
@@ -24,9 +24,12 @@
 	Services,
 	appservice::RegistrationInfo,
 	rooms::{
+		event_handler::Delivery,
 		state::RoomMutexGuard,
+		state_accessor::{RestrictedJoin, knock_event, supports_knocking},
 		state_compressor::{CompressedState, HashSetCompressStateEvent},
 	},
+	users::MembershipEntry,
 };
 use futures::{FutureExt, StreamExt, TryFutureExt, future::join4, join};
 use ruma::{
@@ -50,7 +53,6 @@
 	events::{
 		StateEventType,
 		room::{
-			join_rules::{AllowRule, JoinRule, RoomJoinRulesEventContent},
 			member::{MembershipState, RoomMemberEventContent},
 			message::RoomMessageEventContent,
 		},
@@ -369,6 +371,11 @@ pub(crate) async fn knock_room_route(
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let body = body.body;
 
+	services
+		.users
+		.check_membership_entry(sender_user, MembershipEntry::Knock)
+		.await?;
+
 	let (servers, room_id) = match OwnedRoomId::try_from(body.room_id_or_alias) {
 		| Ok(room_id) => {
 			banned_room_check(
@@ -539,6 +546,10 @@ pub(crate) async fn invite_user_route(
 				return Ok(invite_user::v3::Response {});
 			}
 
+			if services.users.is_shadow_banned(sender_user).await {
+				return Ok(invite_user::v3::Response {});
+			}
+
 			invite_helper(
 				&services,
 				sender_user,
@@ -567,15 +578,88 @@ pub(crate) async fn kick_user_route(
 ) -> Result<kick_user::v3::Response> {
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
+	kick_member(
+		&services,
+		body.sender_user(),
+		&body.room_id,
+		&body.user_id,
+		body.reason.clone(),
+		&state_lock,
+	)
+	.await?;
+
+	drop(state_lock);
+
+	Ok(kick_user::v3::Response::new())
+}
+
+/// # `POST /_matrix/client/r0/rooms/{roomId}/ban`
+///
+/// Tries to send a ban event into the room.
+pub(crate) async fn ban_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<ban_user::v3::Request>,
+) -> Result<ban_user::v3::Response> {
+	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
+
+	ban_member(
+		&services,
+		body.sender_user(),
+		&body.room_id,
+		&body.user_id,
+		body.reason.clone(),
+		&state_lock,
+	)
+	.await?;
+
+	drop(state_lock);
+
+	Ok(ban_user::v3::Response::new())
+}
+
+/// # `POST /_matrix/client/r0/rooms/{roomId}/unban`
+///
+/// Tries to send an unban event into the room.
+pub(crate) async fn unban_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<unban_user::v3::Request>,
+) -> Result<unban_user::v3::Response> {
+	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
+
+	unban_member(
+		&services,
+		body.sender_user(),
+		&body.room_id,
+		&body.user_id,
+		body.reason.clone(),
+		&state_lock,
+	)
+	.await?;
+
+	drop(state_lock);
+
+	Ok(unban_user::v3::Response::new())
+}
+
+/// Kicks `user_id` from the room, returning the kick event, or nothing if
+/// they already left.
+pub(crate) async fn kick_member(
+	services: &Services,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	user_id: &UserId,
+	reason: Option<String>,
+	state_lock: &RoomMutexGuard,
+) -> Result<Option<OwnedEventId>> {
 	let Ok(event) = services
 		.rooms
 		.state_accessor
-		.get_member(&body.room_id, &body.user_id)
+		.get_member(room_id, user_id)
 		.await
 	else {
 		// copy synapse's behaviour of returning 200 without any change to the state
 		// instead of erroring on left users
-		return Ok(kick_user::v3::Response::new());
+		return Ok(None);
 	};
 
 	if !matches!(
@@ -592,44 +676,39 @@ pub(crate) async fn kick_user_route(
 		.rooms
 		.timeline
 		.build_and_append_pdu(
-			PduBuilder::state(body.user_id.to_string(), &RoomMemberEventContent {
+			PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
 				membership: MembershipState::Leave,
-				reason: body.reason.clone(),
+				reason,
 				is_direct: None,
 				join_authorized_via_users_server: None,
 				third_party_invite: None,
 				..event
 			}),
-			body.sender_user(),
-			&body.room_id,
-			&state_lock,
+			sender_user,
+			room_id,
+			state_lock,
 		)
-		.await?;
-
-	drop(state_lock);
-
-	Ok(kick_user::v3::Response::new())
+		.await
+		.map(Some)
 }
 
-/// # `POST /_matrix/client/r0/rooms/{roomId}/ban`
-///
-/// Tries to send a ban event into the room.
-pub(crate) async fn ban_user_route(
-	State(services): State<crate::State>,
-	body: Ruma<ban_user::v3::Request>,
-) -> Result<ban_user::v3::Response> {
-	let sender_user = body.sender_user();
-
-	if sender_user == body.user_id {
+/// Bans `user_id` from the room, returning the ban event.
+pub(crate) async fn ban_member(
+	services: &Services,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	user_id: &UserId,
+	reason: Option<String>,
+	state_lock: &RoomMutexGuard,
+) -> Result<OwnedEventId> {
+	if sender_user == user_id {
 		return Err!(Request(Forbidden("You cannot ban yourself.")));
 	}
 
-	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
-
 	let current_member_content = services
 		.rooms
 		.state_accessor
-		.get_member(&body.room_id, &body.user_id)
+		.get_member(room_id, user_id)
 		.await
 		.unwrap_or_else(|_| RoomMemberEventContent::new(MembershipState::Ban));
 
@@ -637,9 +716,9 @@ pub(crate) async fn ban_user_route(
 		.rooms
 		.timeline
 		.build_and_append_pdu(
-			PduBuilder::state(body.user_id.to_string(), &RoomMemberEventContent {
+			PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
 				membership: MembershipState::Ban,
-				reason: body.reason.clone(),
+				reason,
 				displayname: None, // display name may be offensive
 				avatar_url: None,  // avatar may be offensive
 				is_direct: None,
@@ -648,29 +727,25 @@ pub(crate) async fn ban_user_route(
 				..current_member_content
 			}),
 			sender_user,
-			&body.room_id,
-			&state_lock,
+			room_id,
+			state_lock,
 		)
-		.await?;
-
-	drop(state_lock);
-
-	Ok(ban_user::v3::Response::new())
+		.await
 }
 
-/// # `POST /_matrix/client/r0/rooms/{roomId}/unban`
-///
-/// Tries to send an unban event into the room.
-pub(crate) async fn unban_user_route(
-	State(services): State<crate::State>,
-	body: Ruma<unban_user::v3::Request>,
-) -> Result<unban_user::v3::Response> {
-	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
-
+/// Unbans `user_id` from the room, returning the unban event.
+pub async fn unban_member(
+	services: &Services,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	user_id: &UserId,
+	reason: Option<String>,
+	state_lock: &RoomMutexGuard,
+) -> Result<OwnedEventId> {
 	let current_member_content = services
 		.rooms
 		.state_accessor
-		.get_member(&body.room_id, &body.user_id)
+		.get_member(room_id, user_id)
 		.await
 		.unwrap_or_else(|_| RoomMemberEventContent::new(MembershipState::Leave));
 
@@ -685,23 +760,19 @@ pub(crate) async fn unban_user_route(
 		.rooms
 		.timeline
 		.build_and_append_pdu(
-			PduBuilder::state(body.user_id.to_string(), &RoomMemberEventContent {
+			PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
 				membership: MembershipState::Leave,
-				reason: body.reason.clone(),
+				reason,
 				join_authorized_via_users_server: None,
 				third_party_invite: None,
 				is_direct: None,
 				..current_member_content
 			}),
-			body.sender_user(),
-			&body.room_id,
-			&state_lock,
+			sender_user,
+			room_id,
+			state_lock,
 		)
-		.await?;
-
-	drop(state_lock);
-
-	Ok(unban_user::v3::Response::new())
+		.await
 }
 
 /// # `POST /_matrix/client/v3/rooms/{roomId}/forget`
@@ -903,6 +974,17 @@ pub async fn join_room_by_id_helper(
 	third_party_signed: Option<&ThirdPartySigned>,
 	appservice_info: &Option<RegistrationInfo>,
 ) -> Result<join_room_by_id::v3::Response> {
+	let entry = if appservice_info.is_some() {
+		MembershipEntry::Appservice
+	} else {
+		MembershipEntry::Join
+	};
+
+	services
+		.users
+		.check_membership_entry(sender_user, entry)
+		.await?;
+
 	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
 	let user_is_guest = services
@@ -1103,7 +1185,7 @@ async fn join_room_by_id_helper_remote(
 
 	info!("send_join finished");
 
-	if join_authorized_via_users_server.is_some() {
+	if let Some(authorising_user) = &join_authorized_via_users_server {
 		if let Some(signed_raw) = &send_join_response.room_state.event {
 			debug_info!(
 				"There is a signed event with join_authorized_via_users_server. This room is \
@@ -1123,6 +1205,15 @@ async fn join_room_by_id_helper_remote(
 				)));
 			}
 
+			verify_authorising_signature(
+				services,
+				&remote_server,
+				authorising_user,
+				&signed_value,
+				&room_version_id,
+			)
+			.await?;
+
 			match signed_value["signatures"]
 				.as_object()
 				.ok_or_else(|| {
@@ -1333,61 +1424,21 @@ async fn join_room_by_id_helper_local(
 ) -> Result {
 	debug_info!("We can join locally");
 
-	let join_rules_event_content = services
+	let room_version_id = services.rooms.state.get_room_version(room_id).await?;
+	let restricted = services
 		.rooms
 		.state_accessor
-		.room_state_get_content::<RoomJoinRulesEventContent>(
-			room_id,
-			&StateEventType::RoomJoinRules,
-			"",
-		)
+		.restricted_join(sender_user, room_id, &room_version_id)
 		.await;
 
-	let restriction_rooms = match join_rules_event_content {
-		| Ok(RoomJoinRulesEventContent {
-			join_rule: JoinRule::Restricted(restricted) | JoinRule::KnockRestricted(restricted),
-		}) => restricted
-			.allow
-			.into_iter()
-			.filter_map(|a| match a {
-				| AllowRule::RoomMembership(r) => Some(r.room_id),
-				| _ => None,
-			})
-			.collect(),
-		| _ => Vec::new(),
-	};
-
-	let join_authorized_via_users_server: Option<OwnedUserId> = {
-		if restriction_rooms
-			.iter()
-			.stream()
-			.any(|restriction_room_id| {
-				services
-					.rooms
-					.state_cache
-					.is_joined(sender_user, restriction_room_id)
-			})
-			.await
-		{
+	let join_authorized_via_users_server = match restricted {
+		| RestrictedJoin::Allowed(_) =>
 			services
 				.rooms
-				.state_cache
-				.local_users_in_room(room_id)
-				.filter(|user| {
-					services.rooms.state_accessor.user_can_invite(
-						room_id,
-						user,
-						sender_user,
-						&state_lock,
-					)
-				})
-				.boxed()
-				.next()
-				.await
-				.map(ToOwned::to_owned)
-		} else {
-			None
-		}
+				.state_accessor
+				.restricted_join_authoriser(room_id, sender_user, &state_lock)
+				.await,
+		| _ => None,
 	};
 
 	let content = RoomMemberEventContent {
@@ -1414,7 +1465,7 @@ async fn join_room_by_id_helper_local(
 		return Ok(());
 	};
 
-	if restriction_rooms.is_empty()
+	if restricted == RestrictedJoin::Unrestricted
 		&& (servers.is_empty()
 			|| servers.len() == 1 && services.globals.server_is_ours(&servers[0]))
 	{
@@ -1537,7 +1588,14 @@ async fn join_room_by_id_helper_local(
 		services
 			.rooms
 			.event_handler
-			.handle_incoming_pdu(&remote_server, room_id, &signed_event_id, signed_value, true)
+			.handle_incoming_pdu(
+				&remote_server,
+				room_id,
+				&signed_event_id,
+				signed_value,
+				true,
+				Delivery::Membership,
+			)
 			.boxed()
 			.await?;
 	} else {
@@ -1547,6 +1605,35 @@ async fn join_room_by_id_helper_local(
 	Ok(())
 }
 
+/// Checks that the server we asked to authorise a restricted join is the one of
+/// the authorising user it named, and that it signed the join event.
+async fn verify_authorising_signature(
+	services: &Services,
+	remote_server: &ServerName,
+	authorising_user: &UserId,
+	signed_value: &CanonicalJsonObject,
+	room_version_id: &RoomVersionId,
+) -> Result {
+	if authorising_user.server_name() != remote_server {
+		return Err!(BadServerResponse(warn!(
+			"Server {remote_server} named {authorising_user} of another server to authorise the \
+			 join"
+		)));
+	}
+
+	services
+		.server_keys
+		.verify_event(signed_value, Some(room_version_id))
+		.await
+		.map_err(|e| {
+			err!(BadServerResponse(warn!(
+				"Server {remote_server} sent a join event without a valid signature: {e}"
+			)))
+		})?;
+
+	Ok(())
+}
+
 async fn make_join_request(
 	services: &Services,
 	sender_user: &UserId,
@@ -1637,6 +1724,11 @@ pub(crate) async fn invite_helper(
 		return Err!(Request(Forbidden("Invites are not allowed on this server.")));
 	}
 
+	services
+		.users
+		.check_membership_entry(user_id, MembershipEntry::Invite)
+		.await?;
+
 	if !services.globals.user_is_local(user_id) {
 		let (pdu, pdu_json, invite_room_state) = {
 			let state_lock = services.rooms.state.mutex.lock(room_id).await;
@@ -1716,7 +1808,7 @@ pub(crate) async fn invite_helper(
 		let pdu_id = services
 			.rooms
 			.event_handler
-			.handle_incoming_pdu(&origin, room_id, &event_id, value, true)
+			.handle_incoming_pdu(&origin, room_id, &event_id, value, true, Delivery::Membership)
 			.boxed()
 			.await?
 			.ok_or_else(|| {
@@ -2181,25 +2273,17 @@ async fn knock_room_helper_local(
 ) -> Result {
 	debug_info!("We can knock locally");
 
-	let room_version_id = services.rooms.state.get_room_version(room_id).await?;
-
-	if matches!(
-		room_version_id,
-		RoomVersionId::V1
-			| RoomVersionId::V2
-			| RoomVersionId::V3
-			| RoomVersionId::V4
-			| RoomVersionId::V5
-			| RoomVersionId::V6
-	) {
-		return Err!(Request(Forbidden("This room does not support knocking.")));
-	}
+	services
+		.rooms
+		.state_accessor
+		.check_knockable(room_id)
+		.await?;
 
 	let content = RoomMemberEventContent {
 		displayname: services.users.displayname(sender_user).await.ok(),
 		avatar_url: services.users.avatar_url(sender_user).await.ok(),
 		blurhash: services.users.blurhash(sender_user).await.ok(),
-		reason: reason.clone(),
+		reason,
 		..RoomMemberEventContent::new(MembershipState::Knock)
 	};
 
@@ -2238,12 +2322,14 @@ async fn knock_room_helper_local(
 		));
 	}
 
-	let mut knock_event_stub = serde_json::from_str::<CanonicalJsonObject>(
-		make_knock_response.event.get(),
-	)
-	.map_err(|e| {
-		err!(BadServerResponse("Invalid make_knock event json received from server: {e:?}"))
-	})?;
+	supports_knocking(&room_version_id)?;
+
+	let knock_event_template: CanonicalJsonObject =
+		serde_json::from_str(make_knock_response.event.get()).map_err(|e| {
+			err!(BadServerResponse("Invalid make_knock event json received from server: {e:?}"))
+		})?;
+
+	let mut knock_event_stub = knock_event(knock_event_template, room_id, sender_user, &content)?;
 
 	knock_event_stub.insert(
 		"origin".to_owned(),
@@ -2257,18 +2343,6 @@ async fn knock_room_helper_local(
 				.expect("Timestamp is valid js_int value"),
 		),
 	);
-	knock_event_stub.insert(
-		"content".to_owned(),
-		to_canonical_value(RoomMemberEventContent {
-			displayname: services.users.displayname(sender_user).await.ok(),
-			avatar_url: services.users.avatar_url(sender_user).await.ok(),
-			blurhash: services.users.blurhash(sender_user).await.ok(),
-			reason,
-			..RoomMemberEventContent::new(MembershipState::Knock)
-		})
-		.expect("event is valid, we just created it"),
-	);
-
 	// In order to create a compatible ref hash (EventID) the `hashes` field needs
 	// to be present
 	services
@@ -2368,11 +2442,23 @@ async fn knock_room_helper_remote(
 		));
 	}
 
-	let mut knock_event_stub: CanonicalJsonObject =
+	supports_knocking(&room_version_id)?;
+
+	let knock_event_template: CanonicalJsonObject =
 		serde_json::from_str(make_knock_response.event.get()).map_err(|e| {
 			err!(BadServerResponse("Invalid make_knock event json received from server: {e:?}"))
 		})?;
 
+	let content = RoomMemberEventContent {
+		displayname: services.users.displayname(sender_user).await.ok(),
+		avatar_url: services.users.avatar_url(sender_user).await.ok(),
+		blurhash: services.users.blurhash(sender_user).await.ok(),
+		reason,
+		..RoomMemberEventContent::new(MembershipState::Knock)
+	};
+
+	let mut knock_event_stub = knock_event(knock_event_template, room_id, sender_user, &content)?;
+
 	knock_event_stub.insert(
 		"origin".to_owned(),
 		CanonicalJsonValue::String(services.globals.server_name().as_str().to_owned()),
@@ -2385,18 +2471,6 @@ async fn knock_room_helper_remote(
 				.expect("Timestamp is valid js_int value"),
 		),
 	);
-	knock_event_stub.insert(
-		"content".to_owned(),
-		to_canonical_value(RoomMemberEventContent {
-			displayname: services.users.displayname(sender_user).await.ok(),
-			avatar_url: services.users.avatar_url(sender_user).await.ok(),
-			blurhash: services.users.blurhash(sender_user).await.ok(),
-			reason,
-			..RoomMemberEventContent::new(MembershipState::Knock)
-		})
-		.expect("event is valid, we just created it"),
-	);
-
 	// In order to create a compatible ref hash (EventID) the `hashes` field needs
 	// to be present
 	services
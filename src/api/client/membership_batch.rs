@@ -0,0 +1,170 @@
+use axum::extract::State;
+use conduwuit::{Err, Error, Result};
+use futures::TryFutureExt;
+use ruma::events::{
+	StateEventType,
+	room::power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+};
+
+use self::batch_membership::v1::{Action, Outcome};
+use super::{ban_member, kick_member, unban_member};
+use crate::Ruma;
+
+/// `POST /_conduwuit/client/v1/rooms/{roomId}/members/batch`
+///
+/// conduwuit-specific API to kick, ban and unban several users of a room in
+/// one request.
+pub(crate) mod batch_membership {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedEventId, OwnedRoomId, OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+		use serde::{Deserialize, Serialize};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/client/v1/rooms/:room_id/members/batch",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) room_id: OwnedRoomId,
+
+			pub(crate) operations: Vec<Operation>,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			/// One for each operation, in the order they were given.
+			pub(crate) results: Vec<Outcome>,
+		}
+
+		#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+		#[serde(rename_all = "lowercase")]
+		pub(crate) enum Action {
+			Kick,
+			Ban,
+			Unban,
+		}
+
+		#[derive(Clone, Debug, Deserialize, Serialize)]
+		pub(crate) struct Operation {
+			pub(crate) action: Action,
+			pub(crate) user_id: OwnedUserId,
+
+			#[serde(default, skip_serializing_if = "Option::is_none")]
+			pub(crate) reason: Option<String>,
+		}
+
+		#[derive(Clone, Debug, Deserialize, Serialize)]
+		pub(crate) struct Outcome {
+			pub(crate) action: Action,
+			pub(crate) user_id: OwnedUserId,
+
+			/// The membership event sent, if one was needed.
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) event_id: Option<OwnedEventId>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) errcode: Option<String>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) error: Option<String>,
+		}
+	}
+}
+
+/// # `POST /_conduwuit/client/v1/rooms/{roomId}/members/batch`
+///
+/// Kicks, bans and unbans users of the room, up to `membership_batch_max` of
+/// them in one request, which counts once towards rate limits. The sender's
+/// power level is checked against the strongest action asked for before
+/// anything is sent, refusing the whole batch if it falls short.
+///
+/// The operations are then carried out in order while holding the room's
+/// state lock throughout, so each builds on the state left by the one before
+/// and the parent state stays warm in the state compressor's cache. One
+/// failing does not stop the rest; each result says what became of its
+/// operation.
+pub(crate) async fn batch_membership_route(
+	State(services): State<crate::State>,
+	body: Ruma<batch_membership::v1::Request>,
+) -> Result<batch_membership::v1::Response> {
+	let sender_user = body.sender_user();
+	let room_id = &body.room_id;
+
+	let max = services.server.config.membership_batch_max;
+	if body.operations.len() > max {
+		return Err!(Request(InvalidParam(
+			"At most {max} membership changes may be made in one batch."
+		)));
+	}
+
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+
+	// Rooms without power levels are left to the auth rules
+	if let Ok(power_levels) = services
+		.rooms
+		.state_accessor
+		.room_state_get_content::<RoomPowerLevelsEventContent>(
+			room_id,
+			&StateEventType::RoomPowerLevels,
+			"",
+		)
+		.map_ok(RoomPowerLevels::from)
+		.await
+	{
+		let needed = body
+			.operations
+			.iter()
+			.map(|operation| match operation.action {
+				| Action::Kick => power_levels.kick,
+				| Action::Ban | Action::Unban => power_levels.ban,
+			})
+			.max();
+
+		if needed.is_some_and(|needed| power_levels.for_user(sender_user) < needed) {
+			return Err!(Request(Forbidden(
+				"Your power level is too low for the membership changes in this batch."
+			)));
+		}
+	}
+
+	let mut results = Vec::with_capacity(body.operations.len());
+	for operation in &body.operations {
+		let user_id = &operation.user_id;
+		let reason = operation.reason.clone();
+		let result = match operation.action {
+			| Action::Kick =>
+				kick_member(&services, sender_user, room_id, user_id, reason, &state_lock).await,
+			| Action::Ban =>
+				ban_member(&services, sender_user, room_id, user_id, reason, &state_lock)
+					.await
+					.map(Some),
+			| Action::Unban =>
+				unban_member(&services, sender_user, room_id, user_id, reason, &state_lock)
+					.await
+					.map(Some),
+		};
+
+		let (event_id, error) =
+			result.map_or_else(|e| (None, Some(e)), |event_id| (event_id, None));
+		results.push(Outcome {
+			action: operation.action,
+			user_id: user_id.clone(),
+			event_id,
+			errcode: error.as_ref().map(|e| e.kind().to_string()),
+			error: error.as_ref().map(Error::sanitized_message),
+		});
+	}
+
+	drop(state_lock);
+
+	Ok(batch_membership::v1::Response { results })
+}
@@ -135,6 +135,7 @@ pub(crate) async fn get_message_events_route(
 		device_id: sender_device,
 		room_id,
 		token: Some(from.into_unsigned()),
+		shortstatehash: None,
 		options: Some(&filter.lazy_load_options),
 	};
 
@@ -1,5 +1,7 @@
 pub(super) mod account;
 pub(super) mod account_data;
+pub(super) mod account_validity;
+pub(super) mod admin_users;
 pub(super) mod alias;
 pub(super) mod appservice;
 pub(super) mod backup;
@@ -8,31 +10,40 @@
 pub(super) mod device;
 pub(super) mod directory;
 pub(super) mod filter;
+pub(super) mod health;
 pub(super) mod keys;
 pub(super) mod media;
+pub(super) mod media_info;
 pub(super) mod media_legacy;
+pub(super) mod media_usage;
 pub(super) mod membership;
+pub(super) mod membership_batch;
 pub(super) mod message;
 pub(super) mod openid;
 pub(super) mod presence;
 pub(super) mod profile;
 pub(super) mod push;
+pub(super) mod push_dry_run;
 pub(super) mod read_marker;
 pub(super) mod redact;
 pub(super) mod relations;
 pub(super) mod report;
 pub(super) mod room;
+pub(super) mod room_profile;
 pub(super) mod search;
 pub(super) mod send;
 pub(super) mod session;
 pub(super) mod space;
 pub(super) mod state;
+pub(super) mod state_batch;
+pub(super) mod synapse_admin;
 pub(super) mod sync;
 pub(super) mod tag;
 pub(super) mod thirdparty;
 pub(super) mod threads;
 pub(super) mod to_device;
 pub(super) mod typing;
+pub(super) mod uisi_report;
 pub(super) mod unstable;
 pub(super) mod unversioned;
 pub(super) mod user_directory;
@@ -42,6 +53,9 @@
 pub use account::full_user_deactivate;
 pub(super) use account::*;
 pub(super) use account_data::*;
+pub(super) use account_validity::*;
+pub(super) use admin_users::*;
+pub use admin_users::{create_local_user, provision_user};
 pub(super) use alias::*;
 pub(super) use appservice::*;
 pub(super) use backup::*;
@@ -50,33 +64,42 @@
 pub(super) use device::*;
 pub(super) use directory::*;
 pub(super) use filter::*;
+pub(super) use health::*;
 pub(super) use keys::*;
 pub(super) use media::*;
+pub(super) use media_info::*;
 pub(super) use media_legacy::*;
+pub(super) use media_usage::*;
 pub(super) use membership::*;
-pub use membership::{join_room_by_id_helper, leave_all_rooms, leave_room};
+pub use membership::{join_room_by_id_helper, leave_all_rooms, leave_room, unban_member};
+pub(super) use membership_batch::*;
 pub(super) use message::*;
 pub(super) use openid::*;
 pub(super) use presence::*;
 pub(super) use profile::*;
 pub use profile::{update_all_rooms, update_avatar_url, update_displayname};
 pub(super) use push::*;
+pub(super) use push_dry_run::*;
 pub(super) use read_marker::*;
 pub(super) use redact::*;
 pub(super) use relations::*;
 pub(super) use report::*;
 pub(super) use room::*;
+pub(super) use room_profile::*;
 pub(super) use search::*;
 pub(super) use send::*;
 pub(super) use session::*;
 pub(super) use space::*;
 pub(super) use state::*;
+pub(super) use state_batch::*;
+pub(super) use synapse_admin::*;
 pub(super) use sync::*;
 pub(super) use tag::*;
 pub(super) use thirdparty::*;
 pub(super) use threads::*;
 pub(super) use to_device::*;
 pub(super) use typing::*;
+pub(super) use uisi_report::*;
 pub(super) use unstable::*;
 pub(super) use unversioned::*;
 pub(super) use user_directory::*;
@@ -413,6 +413,10 @@ pub async fn update_all_rooms(
 	user_id: &UserId,
 ) {
 	for (pdu_builder, room_id) in all_joined_rooms {
+		if services.users.has_room_profile(user_id, room_id).await {
+			continue;
+		}
+
 		let state_lock = services.rooms.state.mutex.lock(room_id).await;
 		if let Err(e) = services
 			.rooms
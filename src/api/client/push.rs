@@ -1,6 +1,6 @@
 use axum::extract::State;
 use conduwuit::{Err, Error, Result, err};
-use conduwuit_service::Services;
+use conduwuit_service::{Services, pusher};
 use ruma::{
 	CanonicalJsonObject, CanonicalJsonValue,
 	api::client::{
@@ -235,7 +235,7 @@ pub(crate) async fn set_pushrule_route(
 	body: Ruma<set_pushrule::v3::Request>,
 ) -> Result<set_pushrule::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
-	let body = body.body;
+	let mut body = body.body;
 
 	let mut account_data: PushRulesEvent = services
 		.account_data
@@ -243,6 +243,13 @@ pub(crate) async fn set_pushrule_route(
 		.await
 		.map_err(|_| err!(Request(NotFound("PushRules event not found."))))?;
 
+	pusher::check_new_rule(
+		&mut body.rule,
+		&account_data.content.global,
+		services.server.config.max_push_keyword_rules,
+		services.server.config.push_rule_patterns_lowercase,
+	)?;
+
 	if let Err(error) = account_data.content.global.insert(
 		body.rule.clone(),
 		body.after.as_deref(),
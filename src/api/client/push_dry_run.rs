@@ -0,0 +1,120 @@
+use axum::extract::State;
+use conduwuit::{Err, Result, err};
+use conduwuit_service::pusher;
+use ruma::{
+	events::{StateEventType, room::power_levels::RoomPowerLevelsEventContent},
+	push::FlattenedJson,
+	serde::Raw,
+};
+
+use crate::Ruma;
+
+/// `POST /_conduwuit/client/v1/pushrules/test`
+///
+/// conduwuit-specific API to check whether a push rule would apply to an event.
+pub(crate) mod test_pushrule {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedRoomId,
+			api::{Metadata, metadata, request, response},
+			events::AnySyncTimelineEvent,
+			push::{PushCondition, RuleKind},
+			serde::Raw,
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/client/v1/pushrules/test",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			pub(crate) kind: RuleKind,
+
+			/// The room ID of a room rule or the user ID of a sender rule.
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) rule_id: Option<String>,
+
+			/// The glob of a keyword rule.
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) pattern: Option<String>,
+
+			/// The conditions of an override or underride rule.
+			#[serde(default, skip_serializing_if = "Vec::is_empty")]
+			pub(crate) conditions: Vec<PushCondition>,
+
+			/// The event to test, as it would be sent in the room.
+			pub(crate) event: Raw<AnySyncTimelineEvent>,
+
+			pub(crate) room_id: OwnedRoomId,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			pub(crate) matches: bool,
+
+			/// Index of the first condition the event fails, when it does not
+			/// match.
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) failed_condition: Option<usize>,
+		}
+	}
+}
+
+/// # `POST /_conduwuit/client/v1/pushrules/test`
+///
+/// Evaluates a push rule against an event in a room the user is in, without
+/// storing the rule, and tells which condition stopped it from applying.
+pub(crate) async fn test_pushrule_route(
+	State(services): State<crate::State>,
+	body: Ruma<test_pushrule::v1::Request>,
+) -> Result<test_pushrule::v1::Response> {
+	let sender_user = body.sender_user();
+	if !services
+		.rooms
+		.state_cache
+		.is_joined(sender_user, &body.room_id)
+		.await
+	{
+		return Err!(Request(Forbidden("You are not joined to this room.")));
+	}
+
+	let conditions = pusher::rule_conditions(
+		&body.kind,
+		body.rule_id.as_deref(),
+		body.pattern.as_deref(),
+		body.conditions.clone(),
+	)?;
+
+	let power_levels: RoomPowerLevelsEventContent = services
+		.rooms
+		.state_accessor
+		.room_state_get_content(&body.room_id, &StateEventType::RoomPowerLevels, "")
+		.await
+		.unwrap_or_default();
+
+	let ctx = services
+		.pusher
+		.room_ctx(sender_user, &power_levels, &body.room_id)
+		.await;
+
+	// room rules match on the room of the event, which sync events leave out
+	let mut event = body
+		.event
+		.deserialize_as::<serde_json::Map<String, serde_json::Value>>()
+		.map_err(|e| err!(Request(BadJson("Invalid event: {e}"))))?;
+
+	event.insert("room_id".into(), body.room_id.as_str().into());
+	let event = Raw::new(&event).map_err(|e| err!(Request(BadJson("Invalid event: {e}"))))?;
+	let event = FlattenedJson::from_raw(&event);
+	let failed_condition = pusher::failed_condition(&conditions, &event, &ctx);
+
+	Ok(test_pushrule::v1::Response {
+		matches: failed_condition.is_none(),
+		failed_condition,
+	})
+}
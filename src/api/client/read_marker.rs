@@ -122,7 +122,8 @@ pub(crate) async fn create_receipt_route(
 		services
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id);
+			.reset_thread_notification_counts(sender_user, &body.room_id, &body.thread)
+			.await;
 	}
 
 	// ping presence
@@ -159,7 +160,7 @@ pub(crate) async fn create_receipt_route(
 						sender_user.to_owned(),
 						ruma::events::receipt::Receipt {
 							ts: Some(MilliSecondsSinceUnixEpoch::now()),
-							thread: ReceiptThread::Unthreaded,
+							thread: body.thread.clone(),
 						},
 					)]),
 				)]),
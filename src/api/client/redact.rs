@@ -1,5 +1,6 @@
 use axum::extract::State;
 use conduwuit::{Result, matrix::pdu::PduBuilder};
+use conduwuit_service::users::fake_event_id;
 use ruma::{
 	api::client::redact::redact_event, events::room::redaction::RoomRedactionEventContent,
 };
@@ -18,6 +19,10 @@ pub(crate) async fn redact_event_route(
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let body = body.body;
 
+	if services.users.is_shadow_banned(sender_user).await {
+		return Ok(redact_event::v3::Response { event_id: fake_event_id() });
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	let event_id = services
@@ -2,7 +2,7 @@
 use conduwuit::{
 	Result, at,
 	matrix::pdu::PduCount,
-	utils::{IterStream, ReadyExt, result::FlatOk, stream::WidebandExt},
+	utils::{result::FlatOk, stream::WidebandExt},
 };
 use conduwuit_service::{Services, rooms::timeline::PdusIterItem};
 use futures::StreamExt;
@@ -109,13 +109,7 @@ async fn paginate_relations_with_filter(
 	recurse: bool,
 	dir: Direction,
 ) -> Result<get_relating_events::v1::Response> {
-	let start: PduCount = from
-		.map(str::parse)
-		.transpose()?
-		.unwrap_or_else(|| match dir {
-			| Direction::Forward => PduCount::min(),
-			| Direction::Backward => PduCount::max(),
-		});
+	let from: Option<PduCount> = from.map(str::parse).transpose()?;
 
 	let to: Option<PduCount> = to.map(str::parse).flat_ok();
 
@@ -129,40 +123,36 @@ async fn paginate_relations_with_filter(
 	// Spec (v1.10) recommends depth of at least 3
 	let depth: u8 = if recurse { 3 } else { 1 };
 
-	let events: Vec<PdusIterItem> = services
+	let mut events: Vec<PdusIterItem> = services
 		.rooms
 		.pdu_metadata
-		.get_relations(sender_user, room_id, target, start, limit, depth, dir)
-		.await
-		.into_iter()
-		.filter(|(_, pdu)| {
-			filter_event_type
-				.as_ref()
-				.is_none_or(|kind| *kind == pdu.kind)
-		})
-		.filter(|(_, pdu)| {
-			filter_rel_type
-				.as_ref()
-				.is_none_or(|rel_type| pdu.relation_type_equal(rel_type))
-		})
-		.stream()
-		.ready_take_while(|(count, _)| Some(*count) != to)
+		.get_relations(
+			sender_user,
+			room_id,
+			target,
+			filter_rel_type.as_ref(),
+			filter_event_type.as_ref(),
+			from,
+			to,
+			depth,
+			dir,
+		)
 		.wide_filter_map(|item| visibility_filter(services, sender_user, item))
-		.take(limit)
+		.take(limit.saturating_add(1))
 		.collect()
 		.await;
 
-	let next_batch = match dir {
-		| Direction::Forward => events.last(),
-		| Direction::Backward => events.first(),
-	}
-	.map(at!(0))
-	.as_ref()
-	.map(ToString::to_string);
+	// Only hand out a batch token when there are more results past this page
+	let next_batch = if events.len() > limit {
+		events.truncate(limit);
+		events.last().map(at!(0)).as_ref().map(ToString::to_string)
+	} else {
+		None
+	};
 
 	Ok(get_relating_events::v1::Response {
 		next_batch,
-		prev_batch: from.map(Into::into),
+		prev_batch: from.as_ref().map(ToString::to_string),
 		recursion_depth: recurse.then_some(depth.into()),
 		chunk: events
 			.into_iter()
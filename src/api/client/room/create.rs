@@ -418,12 +418,13 @@ pub(crate) async fn create_room_route(
 
 	// 8. Events implied by invite (and TODO: invite_3pid)
 	drop(state_lock);
+	let shadow_banned = services.users.is_shadow_banned(sender_user).await;
 	for user_id in &body.invite {
 		if services.users.user_is_ignored(sender_user, user_id).await {
 			continue;
-		} else if services.users.user_is_ignored(user_id, sender_user).await {
+		} else if shadow_banned || services.users.user_is_ignored(user_id, sender_user).await {
 			// silently drop the invite to the recipient if they've been ignored by the
-			// sender, pretend it worked
+			// sender or the sender is shadow-banned, pretend it worked
 			continue;
 		}
 
@@ -0,0 +1,96 @@
+use axum::extract::State;
+use conduwuit::{Err, Result, matrix::pdu::PduBuilder};
+use conduwuit_service::users::{Profile, room_profile_content};
+use ruma::events::room::member::MembershipState;
+
+use crate::Ruma;
+
+/// `PUT /_conduwuit/client/v1/rooms/{roomId}/profile`
+///
+/// conduwuit-specific API to show a profile other than the global one in a
+/// room.
+pub(crate) mod set_room_profile {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedEventId, OwnedMxcUri, OwnedRoomId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: PUT,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/client/v1/rooms/:room_id/profile",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) room_id: OwnedRoomId,
+
+			/// The global displayname is shown when not given.
+			#[serde(default, skip_serializing_if = "Option::is_none")]
+			pub(crate) displayname: Option<String>,
+
+			/// The global avatar is shown when not given.
+			#[serde(default, skip_serializing_if = "Option::is_none")]
+			pub(crate) avatar_url: Option<OwnedMxcUri>,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			pub(crate) event_id: OwnedEventId,
+		}
+	}
+}
+
+/// # `PUT /_conduwuit/client/v1/rooms/{roomId}/profile`
+///
+/// Sets the displayname and avatar the user shows in a room, which later
+/// changes of their global profile leave alone. Setting neither clears them,
+/// so the room shows the global profile again and follows its changes.
+pub(crate) async fn set_room_profile_route(
+	State(services): State<crate::State>,
+	body: Ruma<set_room_profile::v1::Request>,
+) -> Result<set_room_profile::v1::Response> {
+	let sender_user = body.sender_user();
+	let room_id = &body.room_id;
+
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+	let member = match services
+		.rooms
+		.state_accessor
+		.get_member(room_id, sender_user)
+		.await
+	{
+		| Ok(member) if member.membership == MembershipState::Join => member,
+		| _ => return Err!(Request(Forbidden("You are not joined to this room."))),
+	};
+
+	let room = Profile {
+		displayname: body.displayname.clone(),
+		avatar_url: body.avatar_url.clone(),
+		blurhash: None,
+	};
+
+	let content = room_profile_content(member, room, services.users.profile(sender_user).await);
+	let event_id = services
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(sender_user.to_string(), &content),
+			sender_user,
+			room_id,
+			&state_lock,
+		)
+		.await?;
+
+	services
+		.users
+		.record_room_profile(sender_user, room_id, &content)
+		.await;
+
+	Ok(set_room_profile::v1::Response { event_id })
+}
@@ -7,7 +7,10 @@
 	result::FlatOk,
 	utils::{IterStream, stream::ReadyExt},
 };
-use conduwuit_service::{Services, rooms::search::RoomQuery};
+use conduwuit_service::{
+	Services,
+	rooms::{search, search::RoomQuery},
+};
 use futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt, future::OptionFuture};
 use ruma::{
 	OwnedRoomId, RoomId, UInt, UserId,
@@ -29,6 +32,10 @@
 const LIMIT_MAX: usize = 100;
 const BATCH_MAX: usize = 20;
 
+/// Unsigned field of results holding the part of a long body around what
+/// matched, for clients to show compact results.
+const SNIPPET_UNSIGNED: &str = "org.conduwuit.snippet";
+
 /// # `POST /_matrix/client/r0/search`
 ///
 /// Searches rooms for messages.
@@ -139,12 +146,21 @@ async fn category_room_events(
 		.collect()
 		.await;
 
+	let results: Vec<_> = results.into_iter().flat_map(at!(2)).collect();
+	let texts: Vec<_> = results.iter().map(search::result_text).collect();
+	let highlights =
+		search::highlights(&criteria.search_term, texts.iter().flatten().map(String::as_str));
+
 	let results: Vec<SearchResult> = results
 		.into_iter()
-		.map(at!(2))
-		.flatten()
-		.stream()
-		.map(PduEvent::into_room_event)
+		.zip(texts)
+		.map(|(mut pdu, text)| {
+			if let Some(snippet) = text.and_then(|text| search::snippet(&text, &highlights)) {
+				pdu.add_unsigned(SNIPPET_UNSIGNED, &snippet).ok();
+			}
+
+			pdu.into_room_event()
+		})
 		.map(|result| SearchResult {
 			rank: None,
 			result: Some(result),
@@ -156,13 +172,6 @@ async fn category_room_events(
 				end: None,                     //TODO
 			},
 		})
-		.collect()
-		.await;
-
-	let highlights = criteria
-		.search_term
-		.split_terminator(|c: char| !c.is_alphanumeric())
-		.map(str::to_lowercase)
 		.collect();
 
 	let next_batch = (results.len() >= limit)
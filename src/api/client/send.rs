@@ -1,12 +1,22 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, convert::Infallible};
 
-use axum::extract::State;
+use async_trait::async_trait;
+use axum::{
+	RequestPartsExt,
+	extract::{FromRequestParts, Path, State},
+};
 use conduwuit::{Err, Result, err, matrix::pdu::PduBuilder, utils};
-use ruma::{api::client::message::send_message_event, events::MessageLikeEventType};
+use http::{header::AUTHORIZATION, request::Parts};
+use ruma::{RoomId, api::client::message::send_message_event, events::MessageLikeEventType};
 use serde_json::from_str;
+use service::{rooms::send_queue::Ticket, users::fake_event_id};
 
 use crate::Ruma;
 
+/// Place of a message in the queue of its sender for the room, taken when the
+/// request arrives and before its body has been received.
+pub(crate) struct Arrival(Option<Ticket>);
+
 /// # `PUT /_matrix/client/v3/rooms/{roomId}/send/{eventType}/{txnId}`
 ///
 /// Send a message event into the room.
@@ -16,8 +26,11 @@
 /// - The only requirement for the content is that it has to be valid json
 /// - Tries to send the event into the room, auth rules will determine if it is
 ///   allowed
+/// - Refuses the event when the room is tombstoned and
+///   `reject_events_in_tombstoned_rooms` is enabled
 pub(crate) async fn send_message_event_route(
 	State(services): State<crate::State>,
+	Arrival(mut ticket): Arrival,
 	body: Ruma<send_message_event::v3::Request>,
 ) -> Result<send_message_event::v3::Response> {
 	let sender_user = body.sender_user();
@@ -30,6 +43,16 @@ pub(crate) async fn send_message_event_route(
 		return Err!(Request(Forbidden("Encryption has been disabled")));
 	}
 
+	services
+		.rooms
+		.state_accessor
+		.check_not_tombstoned(&body.room_id, appservice_info.is_some())
+		.await?;
+
+	if let Some(ticket) = ticket.as_mut() {
+		ticket.turn().await;
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	if body.event_type == MessageLikeEventType::CallInvite
@@ -59,6 +82,19 @@ pub(crate) async fn send_message_event_route(
 		});
 	}
 
+	// the sender is told the event was sent, and retries get the same answer
+	if services.users.is_shadow_banned(sender_user).await {
+		let event_id = fake_event_id();
+		services.transaction_ids.add_txnid(
+			sender_user,
+			sender_device,
+			&body.txn_id,
+			event_id.as_bytes(),
+		);
+
+		return Ok(send_message_event::v3::Response { event_id });
+	}
+
 	let mut unsigned = BTreeMap::new();
 	unsigned.insert("transaction_id".to_owned(), body.txn_id.to_string().into());
 
@@ -93,3 +129,37 @@ pub(crate) async fn send_message_event_route(
 
 	Ok(send_message_event::v3::Response { event_id })
 }
+
+#[async_trait]
+impl FromRequestParts<crate::State> for Arrival {
+	type Rejection = Infallible;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		services: &crate::State,
+	) -> Result<Self, Self::Rejection> {
+		let Some(token) = parts
+			.headers
+			.get(AUTHORIZATION)
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.strip_prefix("Bearer "))
+		else {
+			return Ok(Self(None));
+		};
+
+		let Ok(Path(path)) = parts.extract::<Path<Vec<String>>>().await else {
+			return Ok(Self(None));
+		};
+
+		let Some(room_id) = path.first().and_then(|room_id| RoomId::parse(room_id).ok()) else {
+			return Ok(Self(None));
+		};
+
+		// Appservice tokens are not found, so their messages are never queued.
+		let Ok((sender_user, _)) = services.users.find_from_token(token).await else {
+			return Ok(Self(None));
+		};
+
+		Ok(Self(services.rooms.send_queue.enter(&sender_user, &room_id)))
+	}
+}
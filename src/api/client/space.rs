@@ -11,7 +11,8 @@
 use conduwuit_service::{
 	Services,
 	rooms::spaces::{
-		PaginationToken, SummaryAccessibility, get_parent_children_via, summary_to_chunk,
+		Freshness, PaginationToken, SummaryAccessibility, get_parent_children_via,
+		summary_to_chunk,
 	},
 };
 use futures::{StreamExt, TryFutureExt, future::OptionFuture};
@@ -93,13 +94,26 @@ async fn get_client_hierarchy<'a, ShortRoomIds>(
 	)]
 	.into();
 
+	// later pages walk the hierarchy as cached for the first, even if expired
+	let freshness = if short_room_ids.clone().next().is_some() {
+		Freshness::Snapshot
+	} else {
+		Freshness::Fresh
+	};
+
 	let mut rooms = Vec::with_capacity(limit);
 	let mut parents = BTreeSet::new();
 	while let Some((current_room, via)) = queue.pop_front() {
 		let summary = services
 			.rooms
 			.spaces
-			.get_summary_and_children_client(&current_room, suggested_only, sender_user, &via)
+			.get_summary_and_children_client(
+				&current_room,
+				suggested_only,
+				sender_user,
+				&via,
+				freshness,
+			)
 			.await?;
 
 		match (summary, current_room == room_id) {
@@ -4,7 +4,10 @@
 	matrix::pdu::{PduBuilder, PduEvent},
 	utils::BoolExt,
 };
-use conduwuit_service::Services;
+use conduwuit_service::{
+	Services,
+	users::{fake_event_id, shadow_drops_state},
+};
 use futures::TryStreamExt;
 use ruma::{
 	OwnedEventId, RoomId, UserId,
@@ -171,6 +174,17 @@ async fn send_state_event_for_key_helper(
 	timestamp: Option<ruma::MilliSecondsSinceUnixEpoch>,
 ) -> Result<OwnedEventId> {
 	allowed_to_send_state_event(services, room_id, event_type, state_key, json).await?;
+	if services.users.is_shadow_banned(sender).await {
+		let membership = json
+			.deserialize_as::<RoomMemberEventContent>()
+			.ok()
+			.map(|content| content.membership);
+
+		if shadow_drops_state(event_type, membership.as_ref()) {
+			return Ok(fake_event_id());
+		}
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 	let event_id = services
 		.rooms
@@ -189,10 +203,19 @@ async fn send_state_event_for_key_helper(
 		)
 		.await?;
 
+	if *event_type == StateEventType::RoomMember && state_key == sender.as_str() {
+		if let Ok(content) = json.deserialize_as::<RoomMemberEventContent>() {
+			services
+				.users
+				.record_room_profile(sender, room_id, &content)
+				.await;
+		}
+	}
+
 	Ok(event_id)
 }
 
-async fn allowed_to_send_state_event(
+pub(super) async fn allowed_to_send_state_event(
 	services: &Services,
 	room_id: &RoomId,
 	event_type: &StateEventType,
@@ -0,0 +1,145 @@
+use axum::extract::State;
+use conduwuit::{Err, Error, Result, matrix::pdu::PduBuilder};
+use conduwuit_service::users::{fake_event_id, shadow_drops_state};
+use ruma::events::room::member::RoomMemberEventContent;
+
+use super::state::allowed_to_send_state_event;
+use crate::Ruma;
+
+/// `PUT /_conduwuit/client/v1/rooms/{roomId}/state_batch`
+///
+/// conduwuit-specific API to send several state events into a room as one
+/// change.
+pub(crate) mod send_state_batch {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedEventId, OwnedRoomId,
+			api::{Metadata, metadata, request, response},
+			events::{AnyStateEventContent, StateEventType},
+			serde::Raw,
+		};
+		use serde::{Deserialize, Serialize};
+
+		const METADATA: Metadata = metadata! {
+			method: PUT,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/client/v1/rooms/:room_id/state_batch",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) room_id: OwnedRoomId,
+
+			/// Sent in this order, each seeing the state the ones before it
+			/// leave.
+			pub(crate) events: Vec<StateEvent>,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			/// One for each event, in the order they were given.
+			pub(crate) event_ids: Vec<OwnedEventId>,
+		}
+
+		#[derive(Clone, Debug, Deserialize, Serialize)]
+		pub(crate) struct StateEvent {
+			#[serde(rename = "type")]
+			pub(crate) event_type: StateEventType,
+
+			#[serde(default)]
+			pub(crate) state_key: String,
+
+			pub(crate) content: Raw<AnyStateEventContent>,
+		}
+	}
+}
+
+/// # `PUT /_conduwuit/client/v1/rooms/{roomId}/state_batch`
+///
+/// Sends up to `state_batch_max` state events into the room as one change.
+/// Each is checked and authorized in order against the room's state with the
+/// events before it applied, so a power level change takes effect for the
+/// events after it in the batch. If any fails, none are sent, and the error
+/// names the index of the one which failed. Otherwise the events and the
+/// room's new state are written together, and their event IDs returned.
+pub(crate) async fn send_state_batch_route(
+	State(services): State<crate::State>,
+	body: Ruma<send_state_batch::v1::Request>,
+) -> Result<send_state_batch::v1::Response> {
+	let sender_user = body.sender_user();
+	let room_id = &body.room_id;
+
+	let max = services.server.config.state_batch_max;
+	if body.events.len() > max {
+		return Err!(Request(InvalidParam(
+			"At most {max} state events may be sent in one batch."
+		)));
+	}
+
+	if body.events.is_empty() {
+		return Err!(Request(InvalidParam("The batch has no state events.")));
+	}
+
+	let mut builders = Vec::with_capacity(body.events.len());
+	for (index, event) in body.events.iter().enumerate() {
+		let send_state_batch::v1::StateEvent { event_type, state_key, content } = event;
+		allowed_to_send_state_event(&services, room_id, event_type, state_key, content)
+			.await
+			.map_err(|e| failed_at(index, &e))?;
+
+		let content = serde_json::from_str(content.json().get())
+			.map_err(|e| failed_at(index, &Error::from(e)))?;
+
+		builders.push(PduBuilder {
+			event_type: event_type.to_string().into(),
+			content,
+			state_key: Some(state_key.as_str().into()),
+			..Default::default()
+		});
+	}
+
+	// the batch is dropped whole, as it would have been sent
+	if services.users.is_shadow_banned(sender_user).await
+		&& body.events.iter().any(|event| {
+			let membership = event
+				.content
+				.deserialize_as::<RoomMemberEventContent>()
+				.ok()
+				.map(|content| content.membership);
+
+			shadow_drops_state(&event.event_type, membership.as_ref())
+		}) {
+		let event_ids = body.events.iter().map(|_| fake_event_id()).collect();
+		return Ok(send_state_batch::v1::Response { event_ids });
+	}
+
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+	let batch = services
+		.rooms
+		.timeline
+		.build_state_batch(builders, sender_user, room_id, &state_lock)
+		.await
+		.map_err(|(index, e)| failed_at(index, &e))?;
+
+	let event_ids = services
+		.rooms
+		.timeline
+		.append_state_batch(batch, room_id, &state_lock)
+		.await?;
+
+	drop(state_lock);
+
+	Ok(send_state_batch::v1::Response { event_ids })
+}
+
+/// The error of the batch's event at `index`, which kept the whole batch from
+/// being sent.
+fn failed_at(index: usize, error: &Error) -> Error {
+	let message = format!("State event {index} of the batch: {}", error.sanitized_message());
+
+	Error::Request(error.kind(), message.into(), error.status_code())
+}
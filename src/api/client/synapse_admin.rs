@@ -0,0 +1,832 @@
+//! Synapse admin API
+//!
+//! The most used endpoints of Synapse's HTTP admin API, for tools made for it
+//! such as synapse-admin, served under `/_synapse/admin/` at the same paths as
+//! Synapse. Their JSON is shaped as Synapse's, leaving out what is not kept
+//! here. Quarantining media is not supported and is refused as unrecognized.
+//! Only served when the `synapse_admin_api` config option is enabled.
+
+use std::cmp::Reverse;
+
+use axum::extract::State;
+use conduwuit::{
+	Err, Result, info,
+	utils::{ReadyExt, math::usize_from_u64_truncated},
+	warn,
+};
+use conduwuit_service::Services;
+use futures::{StreamExt, TryStreamExt, future};
+use ruma::{OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId, UserId};
+
+use self::{synapse_list_rooms::v1::Room, synapse_query_user::v2::User};
+use super::{check_admin, check_managed, deactivate_local_user, leave_room};
+use crate::Ruma;
+
+/// Users or rooms listed in a page when no limit is given.
+const DEFAULT_LIMIT: usize = 100;
+
+/// Most users or rooms listed in a page.
+const MAX_LIMIT: usize = 1000;
+
+/// What rooms are listed by; ties are listed by room ID.
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum SortKey {
+	Members(Reverse<u64>),
+
+	/// Whether the room is unnamed, and its lowercase name.
+	Name(bool, String),
+}
+
+/// `GET /_synapse/admin/v2/users`
+///
+/// Synapse's API for server admins to list the local users.
+pub(crate) mod synapse_list_users {
+	pub(crate) mod v2 {
+		use ruma::{
+			UInt,
+			api::{Metadata, metadata, request, response},
+		};
+
+		use super::super::User;
+
+		const METADATA: Metadata = metadata! {
+			method: GET,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_synapse/admin/v2/users",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			/// Where the page starts, from the `next_token` of the previous
+			/// one.
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) from: Option<String>,
+
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) limit: Option<UInt>,
+
+			/// Only users whose ID or display name contains this.
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) name: Option<String>,
+
+			/// Whether to list deactivated users too.
+			#[ruma_api(query)]
+			#[serde(default, skip_serializing_if = "ruma::serde::is_default")]
+			pub(crate) deactivated: bool,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			pub(crate) users: Vec<User>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) next_token: Option<String>,
+
+			/// Users matching, on every page.
+			pub(crate) total: u64,
+		}
+	}
+}
+
+/// `GET /_synapse/admin/v2/users/{userId}`
+///
+/// Synapse's API for server admins to show a user.
+pub(crate) mod synapse_query_user {
+	pub(crate) mod v2 {
+		use ruma::{
+			OwnedMxcUri, OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+		use serde::{Deserialize, Serialize};
+
+		const METADATA: Metadata = metadata! {
+			method: GET,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_synapse/admin/v2/users/:user_id",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) user_id: OwnedUserId,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			#[ruma_api(body)]
+			pub(crate) user: User,
+		}
+
+		#[derive(Clone, Debug, Deserialize, Serialize)]
+		pub(crate) struct User {
+			pub(crate) name: OwnedUserId,
+
+			pub(crate) displayname: Option<String>,
+
+			pub(crate) avatar_url: Option<OwnedMxcUri>,
+
+			pub(crate) admin: bool,
+
+			pub(crate) deactivated: bool,
+
+			pub(crate) shadow_banned: bool,
+
+			/// Guests have no accounts here, so this is always false.
+			pub(crate) is_guest: bool,
+
+			pub(crate) user_type: Option<String>,
+
+			/// Always empty, as third-party IDs are not kept.
+			pub(crate) threepids: Vec<serde_json::Value>,
+
+			/// Always empty, as there is no single sign-on.
+			pub(crate) external_ids: Vec<serde_json::Value>,
+		}
+	}
+}
+
+/// `POST /_synapse/admin/v1/deactivate/{userId}`
+///
+/// Synapse's API for server admins to deactivate a local user.
+pub(crate) mod synapse_deactivate_user {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_synapse/admin/v1/deactivate/:user_id",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) user_id: OwnedUserId,
+
+			/// The user's profile is cleared whether or not this is set;
+			/// their messages are kept.
+			#[serde(default)]
+			pub(crate) erase: bool,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			/// Always `no-support`, as there is no identity server to unbind
+			/// from.
+			pub(crate) id_server_unbind_result: String,
+		}
+	}
+}
+
+/// `POST /_synapse/admin/v1/reset_password/{userId}`
+///
+/// Synapse's API for server admins to reset the password of a local user.
+pub(crate) mod synapse_reset_password {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_synapse/admin/v1/reset_password/:user_id",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) user_id: OwnedUserId,
+
+			pub(crate) new_password: String,
+
+			#[serde(default = "ruma::serde::default_true")]
+			pub(crate) logout_devices: bool,
+		}
+
+		#[response]
+		#[derive(Default)]
+		pub(crate) struct Response {}
+	}
+}
+
+/// `GET /_synapse/admin/v1/rooms`
+///
+/// Synapse's API for server admins to list the rooms known.
+pub(crate) mod synapse_list_rooms {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedRoomAliasId, OwnedRoomId, UInt,
+			api::{Metadata, metadata, request, response},
+		};
+		use serde::{Deserialize, Serialize};
+
+		const METADATA: Metadata = metadata! {
+			method: GET,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_synapse/admin/v1/rooms",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			/// How many rooms the page skips.
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) from: Option<UInt>,
+
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) limit: Option<UInt>,
+
+			/// `joined_members` or `joined_local_members` to order by those,
+			/// or else by name.
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) order_by: Option<String>,
+
+			/// `b` to order backwards.
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) dir: Option<String>,
+
+			/// Only rooms whose name, ID or canonical alias contains this.
+			#[ruma_api(query)]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) search_term: Option<String>,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			pub(crate) rooms: Vec<Room>,
+
+			pub(crate) offset: u64,
+
+			/// Rooms matching, on every page.
+			pub(crate) total_rooms: u64,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) next_batch: Option<u64>,
+
+			#[serde(skip_serializing_if = "Option::is_none")]
+			pub(crate) prev_batch: Option<u64>,
+		}
+
+		#[derive(Clone, Debug, Deserialize, Serialize)]
+		pub(crate) struct Room {
+			pub(crate) room_id: OwnedRoomId,
+
+			pub(crate) name: Option<String>,
+
+			pub(crate) canonical_alias: Option<OwnedRoomAliasId>,
+
+			pub(crate) joined_members: u64,
+
+			pub(crate) joined_local_members: u64,
+
+			pub(crate) version: Option<String>,
+
+			/// The encryption algorithm, if the room is encrypted.
+			pub(crate) encryption: Option<String>,
+
+			/// Whether the room is published in the room directory.
+			pub(crate) public: bool,
+
+			pub(crate) join_rules: String,
+
+			pub(crate) room_type: Option<String>,
+		}
+	}
+}
+
+/// `DELETE /_synapse/admin/v1/rooms/{roomId}`
+///
+/// Synapse's API for server admins to remove the local users from a room and
+/// delete it.
+pub(crate) mod synapse_delete_room {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedRoomAliasId, OwnedRoomId, OwnedUserId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: DELETE,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_synapse/admin/v1/rooms/:room_id",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) room_id: OwnedRoomId,
+
+			/// Whether to ban the room, so local users can not join it again.
+			#[serde(default)]
+			pub(crate) block: bool,
+
+			/// Whether to delete what is stored about the room.
+			#[serde(default = "ruma::serde::default_true")]
+			pub(crate) purge: bool,
+		}
+
+		#[response]
+		pub(crate) struct Response {
+			pub(crate) kicked_users: Vec<OwnedUserId>,
+
+			pub(crate) failed_to_kick_users: Vec<OwnedUserId>,
+
+			/// The local aliases of the room, which were removed.
+			pub(crate) local_aliases: Vec<OwnedRoomAliasId>,
+
+			/// Always null, as no room is made to move the users to.
+			pub(crate) new_room_id: Option<OwnedRoomId>,
+		}
+	}
+}
+
+/// `POST /_synapse/admin/v1/media/quarantine/{serverName}/{mediaId}`
+///
+/// Synapse's API for server admins to quarantine a file, which is refused
+/// here.
+pub(crate) mod synapse_quarantine_media {
+	pub(crate) mod v1 {
+		use ruma::{
+			OwnedServerName,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_synapse/admin/v1/media/quarantine/:server_name/:media_id",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			#[ruma_api(path)]
+			pub(crate) server_name: OwnedServerName,
+
+			#[ruma_api(path)]
+			pub(crate) media_id: String,
+		}
+
+		#[response]
+		#[derive(Default)]
+		pub(crate) struct Response {}
+	}
+}
+
+/// # `GET /_synapse/admin/v2/users`
+///
+/// Lists the local users a page at a time, by user ID, leaving out
+/// deactivated ones unless asked not to. The sender must be a server admin.
+pub(crate) async fn synapse_list_users_route(
+	State(services): State<crate::State>,
+	body: Ruma<synapse_list_users::v2::Request>,
+) -> Result<synapse_list_users::v2::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let from = match body.from.as_deref().map(str::parse) {
+		| None => 0,
+		| Some(Ok(from)) => from,
+		| Some(Err(_)) => return Err!(Request(InvalidParam("Invalid from token."))),
+	};
+
+	let limit = page_limit(body.limit.map(u64::from));
+	let name = body.name.as_deref().map(str::to_lowercase);
+	let deactivated = body.deactivated;
+	let users: Vec<OwnedUserId> = services
+		.users
+		.stream()
+		.ready_filter(|user_id| {
+			services.globals.user_is_local(user_id) && *user_id != services.globals.server_user
+		})
+		.filter_map(|user_id| {
+			let name = name.as_deref();
+			let services = &services;
+			async move {
+				if !deactivated && !services.users.is_active(user_id).await {
+					return None;
+				}
+
+				let Some(name) = name else {
+					return Some(user_id.to_owned());
+				};
+
+				let displayname = services.users.displayname(user_id).await.ok();
+				[Some(user_id.as_str()), displayname.as_deref()]
+					.into_iter()
+					.flatten()
+					.any(|text| text.to_lowercase().contains(name))
+					.then(|| user_id.to_owned())
+			}
+		})
+		.collect()
+		.await;
+
+	let total = users.len();
+	let mut page = Vec::new();
+	for user_id in users.iter().skip(from).take(limit) {
+		page.push(user_details(&services, user_id).await);
+	}
+
+	let next = from.saturating_add(page.len());
+	Ok(synapse_list_users::v2::Response {
+		users: page,
+		next_token: (next < total).then(|| next.to_string()),
+		total: total.try_into().unwrap_or(u64::MAX),
+	})
+}
+
+/// # `GET /_synapse/admin/v2/users/{userId}`
+///
+/// Shows a user's profile and whether they are an admin, deactivated or
+/// shadow-banned. The sender must be a server admin.
+pub(crate) async fn synapse_query_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<synapse_query_user::v2::Request>,
+) -> Result<synapse_query_user::v2::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	if !services.users.exists(&body.user_id).await {
+		return Err!(Request(NotFound("User {} does not exist.", body.user_id)));
+	}
+
+	Ok(synapse_query_user::v2::Response {
+		user: user_details(&services, &body.user_id).await,
+	})
+}
+
+/// # `POST /_synapse/admin/v1/deactivate/{userId}`
+///
+/// Deactivates a local user, clearing their profile and having them leave all
+/// their rooms. The sender must be a server admin.
+pub(crate) async fn synapse_deactivate_user_route(
+	State(services): State<crate::State>,
+	body: Ruma<synapse_deactivate_user::v1::Request>,
+) -> Result<synapse_deactivate_user::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let user_id = &body.user_id;
+	check_managed(&services, user_id).await?;
+
+	deactivate_local_user(&services, user_id, true).await?;
+	info!(sender = %body.sender_user(), %user_id, "Deactivated user through the Synapse admin API");
+
+	Ok(synapse_deactivate_user::v1::Response {
+		id_server_unbind_result: "no-support".to_owned(),
+	})
+}
+
+/// # `POST /_synapse/admin/v1/reset_password/{userId}`
+///
+/// Sets the password of a local user, logging out all their devices unless
+/// asked not to. The sender must be a server admin.
+pub(crate) async fn synapse_reset_password_route(
+	State(services): State<crate::State>,
+	body: Ruma<synapse_reset_password::v1::Request>,
+) -> Result<synapse_reset_password::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let user_id = &body.user_id;
+	check_managed(&services, user_id).await?;
+
+	services
+		.users
+		.set_password(user_id, Some(&body.new_password))?;
+
+	if body.logout_devices {
+		services
+			.users
+			.all_device_ids(user_id)
+			.for_each(|device_id| services.users.remove_device(user_id, device_id))
+			.await;
+	}
+
+	info!(sender = %body.sender_user(), %user_id, "Reset password through the Synapse admin API");
+
+	Ok(synapse_reset_password::v1::Response::default())
+}
+
+/// # `GET /_synapse/admin/v1/rooms`
+///
+/// Lists the rooms known a page at a time, with their member counts. The
+/// sender must be a server admin.
+pub(crate) async fn synapse_list_rooms_route(
+	State(services): State<crate::State>,
+	body: Ruma<synapse_list_rooms::v1::Request>,
+) -> Result<synapse_list_rooms::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let search = body.search_term.as_deref().map(str::to_lowercase);
+	let order_by = body.order_by.as_deref();
+	let mut rooms: Vec<(SortKey, OwnedRoomId)> = services
+		.rooms
+		.metadata
+		.iter_ids()
+		.filter_map(|room_id| {
+			let search = search.as_deref();
+			let services = &services;
+			async move {
+				if let Some(search) = search {
+					if !room_matches(services, room_id, search).await {
+						return None;
+					}
+				}
+
+				Some((sort_key(services, room_id, order_by).await, room_id.to_owned()))
+			}
+		})
+		.collect()
+		.await;
+
+	rooms.sort_unstable();
+	if body.dir.as_deref() == Some("b") {
+		rooms.reverse();
+	}
+
+	let total = rooms.len();
+	let from = body.from.map(u64::from).map_or(0, usize_from_u64_truncated);
+
+	let limit = page_limit(body.limit.map(u64::from));
+	let mut page = Vec::with_capacity(limit.min(rooms.len()));
+	for (_, room_id) in rooms.iter().skip(from).take(limit) {
+		page.push(room_details(&services, room_id).await);
+	}
+
+	let next = from.saturating_add(page.len());
+	let as_u64 = |n: usize| -> u64 { n.try_into().unwrap_or(u64::MAX) };
+
+	Ok(synapse_list_rooms::v1::Response {
+		rooms: page,
+		offset: as_u64(from),
+		total_rooms: as_u64(total),
+		next_batch: (next < total).then(|| as_u64(next)),
+		prev_batch: (from > 0).then(|| as_u64(from.saturating_sub(limit))),
+	})
+}
+
+/// # `DELETE /_synapse/admin/v1/rooms/{roomId}`
+///
+/// Has the local users leave the room and removes its local aliases and
+/// directory listing, then bans the room when asked to block it, and deletes
+/// what is stored about it unless asked not to purge it. The sender must be a
+/// server admin.
+pub(crate) async fn synapse_delete_room_route(
+	State(services): State<crate::State>,
+	body: Ruma<synapse_delete_room::v1::Request>,
+) -> Result<synapse_delete_room::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	let room_id = &body.room_id;
+	if services
+		.admin
+		.get_admin_room()
+		.await
+		.is_ok_and(|admin_room_id| admin_room_id == *room_id)
+	{
+		return Err!(Request(Forbidden("Not allowed to delete the admin room.")));
+	}
+
+	if !services.rooms.metadata.exists(room_id).await {
+		return Err!(Request(NotFound("Room {room_id} is not known.")));
+	}
+
+	if body.block {
+		services.rooms.metadata.ban_room(room_id, true);
+	}
+
+	let (kicked_users, failed_to_kick_users) = evict_local_members(&services, room_id).await;
+	let local_aliases: Vec<OwnedRoomAliasId> = services
+		.rooms
+		.alias
+		.local_aliases_for_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for alias in &local_aliases {
+		services
+			.rooms
+			.alias
+			.remove_alias(alias, &services.globals.server_user)
+			.await
+			.ok();
+	}
+
+	services.rooms.directory.set_not_public(room_id);
+	if body.purge {
+		purge_room(&services, room_id).await?;
+	}
+
+	info!(
+		sender = %body.sender_user(),
+		%room_id,
+		block = body.block,
+		purge = body.purge,
+		"Deleted room through the Synapse admin API"
+	);
+
+	Ok(synapse_delete_room::v1::Response {
+		kicked_users,
+		failed_to_kick_users,
+		local_aliases,
+		new_room_id: None,
+	})
+}
+
+/// # `POST /_synapse/admin/v1/media/quarantine/{serverName}/{mediaId}`
+///
+/// Refused as unrecognized: there is no quarantine to hide a file in while
+/// keeping it, and deleting it instead could not be undone. The sender must be
+/// a server admin.
+pub(crate) async fn synapse_quarantine_media_route(
+	State(services): State<crate::State>,
+	body: Ruma<synapse_quarantine_media::v1::Request>,
+) -> Result<synapse_quarantine_media::v1::Response> {
+	check_admin(&services, body.sender_user()).await?;
+
+	Err!(Request(Unrecognized(
+		"Quarantining media is not supported; delete it with the `media delete` admin command."
+	)))
+}
+
+async fn user_details(services: &Services, user_id: &UserId) -> User {
+	User {
+		name: user_id.to_owned(),
+		displayname: services.users.displayname(user_id).await.ok(),
+		avatar_url: services.users.avatar_url(user_id).await.ok(),
+		admin: services.admin.user_is_admin(user_id).await,
+		deactivated: !services.users.is_active(user_id).await,
+		shadow_banned: services.users.is_shadow_banned(user_id).await,
+		is_guest: false,
+		user_type: None,
+		threepids: Vec::new(),
+		external_ids: Vec::new(),
+	}
+}
+
+async fn room_details(services: &Services, room_id: &RoomId) -> Room {
+	let rooms = &services.rooms;
+	Room {
+		room_id: room_id.to_owned(),
+		name: rooms.state_accessor.get_name(room_id).await.ok(),
+		canonical_alias: rooms.state_accessor.get_canonical_alias(room_id).await.ok(),
+		joined_members: rooms
+			.state_cache
+			.room_joined_count(room_id)
+			.await
+			.unwrap_or(0),
+		joined_local_members: rooms
+			.state_cache
+			.local_users_in_room(room_id)
+			.count()
+			.await
+			.try_into()
+			.unwrap_or(u64::MAX),
+		version: rooms
+			.state
+			.get_room_version(room_id)
+			.await
+			.ok()
+			.map(|version| version.to_string()),
+		encryption: rooms
+			.state_accessor
+			.get_room_encryption(room_id)
+			.await
+			.ok()
+			.map(|algorithm| algorithm.to_string()),
+		public: rooms.directory.is_public_room(room_id).await,
+		join_rules: rooms
+			.state_accessor
+			.get_join_rules(room_id)
+			.await
+			.as_str()
+			.to_owned(),
+		room_type: rooms
+			.state_accessor
+			.get_room_type(room_id)
+			.await
+			.ok()
+			.map(|room_type| room_type.to_string()),
+	}
+}
+
+/// Whether the room's ID, name or canonical alias contains the lowercase
+/// search term.
+async fn room_matches(services: &Services, room_id: &RoomId, search: &str) -> bool {
+	if room_id.as_str().to_lowercase().contains(search) {
+		return true;
+	}
+
+	let state_accessor = &services.rooms.state_accessor;
+	let name = state_accessor.get_name(room_id).await.ok();
+	let alias = state_accessor.get_canonical_alias(room_id).await.ok();
+
+	name.as_deref()
+		.into_iter()
+		.chain(alias.as_ref().map(|alias| alias.as_str()))
+		.any(|text| text.to_lowercase().contains(search))
+}
+
+/// What the room is listed by: the most members first when asked to, or else
+/// its name, with rooms without one last. Only this is looked up for every
+/// room; the rest of their details only for the page listed.
+async fn sort_key(services: &Services, room_id: &RoomId, order_by: Option<&str>) -> SortKey {
+	let state_cache = &services.rooms.state_cache;
+	match order_by {
+		| Some("joined_members") => {
+			let joined = state_cache.room_joined_count(room_id).await.unwrap_or(0);
+			SortKey::Members(Reverse(joined))
+		},
+		| Some("joined_local_members") => {
+			let local = state_cache.local_users_in_room(room_id).count().await;
+			SortKey::Members(Reverse(local.try_into().unwrap_or(u64::MAX)))
+		},
+		| _ => {
+			let name = services.rooms.state_accessor.get_name(room_id).await.ok();
+			SortKey::Name(name.is_none(), name.unwrap_or_default().to_lowercase())
+		},
+	}
+}
+
+fn page_limit(limit: Option<u64>) -> usize {
+	limit
+		.map_or(DEFAULT_LIMIT, usize_from_u64_truncated)
+		.clamp(1, MAX_LIMIT)
+}
+
+/// Has the local members leave the room and forget it, returning those who
+/// left and those who failed to.
+async fn evict_local_members(
+	services: &Services,
+	room_id: &RoomId,
+) -> (Vec<OwnedUserId>, Vec<OwnedUserId>) {
+	let users: Vec<OwnedUserId> = services
+		.rooms
+		.state_cache
+		.room_members(room_id)
+		.ready_filter(|user| services.globals.user_is_local(user))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut kicked = Vec::with_capacity(users.len());
+	let mut failed = Vec::new();
+	for user_id in users {
+		if let Err(e) = leave_room(services, &user_id, room_id, None).await {
+			warn!("Failed to leave {room_id} for {user_id}: {e}");
+			failed.push(user_id);
+			continue;
+		}
+
+		services.rooms.state_cache.forget(room_id, &user_id);
+		kicked.push(user_id);
+	}
+
+	(kicked, failed)
+}
+
+/// Deletes what is stored about the room, as the `rooms purge` admin command
+/// does.
+async fn purge_room(services: &Services, room_id: &RoomId) -> Result {
+	let room = services.rooms.purge.gather(room_id).await?;
+	services
+		.rooms
+		.purge
+		.purge_room(&room, false)
+		.try_for_each(|_| future::ok(()))
+		.await?;
+
+	services.rooms.purge.forget_room(room_id).await;
+
+	Ok(())
+}
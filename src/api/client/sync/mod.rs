@@ -14,6 +14,7 @@
 use futures::{StreamExt, pin_mut};
 use ruma::{
 	RoomId, UserId,
+	api::client::filter::RoomEventFilter,
 	directory::RoomTypeFilter,
 	events::TimelineEventType::{
 		self, Beacon, CallInvite, PollStart, RoomEncrypted, RoomMessage, Sticker,
@@ -34,6 +35,7 @@ async fn load_timeline(
 	roomsincecount: PduCount,
 	next_batch: Option<PduCount>,
 	limit: usize,
+	filter: Option<&RoomEventFilter>,
 ) -> Result<(Vec<(PduCount, PduEvent)>, bool), Error> {
 	let last_timeline_count = services
 		.rooms
@@ -51,7 +53,8 @@ async fn load_timeline(
 		.pdus_rev(Some(sender_user), room_id, None)
 		.ignore_err()
 		.ready_skip_while(|&(pducount, _)| pducount > next_batch.unwrap_or_else(PduCount::max))
-		.ready_take_while(|&(pducount, _)| pducount > roomsincecount);
+		.ready_take_while(|&(pducount, _)| pducount > roomsincecount)
+		.ready_filter(|(_, pdu)| filter.is_none_or(|filter| pdu.matches(filter)));
 
 	// Take the last events for the timeline
 	pin_mut!(non_timeline_pdus);
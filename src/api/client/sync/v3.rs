@@ -9,9 +9,12 @@
 	Result, at, err, error, extract_variant, is_equal_to,
 	matrix::{
 		Event,
-		pdu::{EventHash, PduCount, PduEvent},
+		pdu::{
+			EventHash, PduCount, PduEvent, filter_limit, filter_type_matches, presence_matches,
+			presence_wanted, room_matches,
+		},
 	},
-	pair_of, ref_at,
+	ref_at,
 	result::FlatOk,
 	utils::{
 		self, BoolExt, IterStream, ReadyExt, TryFutureExtExt,
@@ -28,6 +31,7 @@
 		lazy_loading::{Options, Witness},
 		short::ShortStateHash,
 	},
+	sync::{DeviceLists, Token},
 };
 use futures::{
 	FutureExt, StreamExt, TryFutureExt, TryStreamExt,
@@ -36,7 +40,7 @@
 use ruma::{
 	DeviceId, EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
 	api::client::{
-		filter::FilterDefinition,
+		filter::{Filter as EventFilter, FilterDefinition},
 		sync::sync_events::{
 			self, DeviceLists, UnreadNotificationsCount,
 			v3::{
@@ -53,12 +57,13 @@
 		presence::{PresenceEvent, PresenceEventContent},
 		room::member::{MembershipState, RoomMemberEventContent},
 	},
+	presence::PresenceState,
 	serde::Raw,
 	uint,
 };
 use service::rooms::short::{ShortEventId, ShortStateKey};
 
-use super::{load_timeline, share_encrypted_room};
+use super::load_timeline;
 use crate::{Ruma, RumaResponse, client::ignored_filter};
 
 #[derive(Default)]
@@ -67,8 +72,6 @@ struct StateChanges {
 	joined_member_count: Option<u64>,
 	invited_member_count: Option<u64>,
 	state_events: Vec<PduEvent>,
-	device_list_updates: HashSet<OwnedUserId>,
-	left_encrypted_users: HashSet<OwnedUserId>,
 }
 
 type PresenceUpdates = HashMap<OwnedUserId, PresenceEventContent>;
@@ -122,8 +125,8 @@ pub(crate) async fn sync_events_route(
 ) -> Result<sync_events::v3::Response, RumaResponse<UiaaResponse>> {
 	let (sender_user, sender_device) = body.sender();
 
-	// Presence update
-	if services.config.allow_local_presence {
+	// Presence update; a client syncing as offline is not marked online
+	if services.config.allow_local_presence && body.body.set_presence != PresenceState::Offline {
 		services
 			.presence
 			.ping_presence(sender_user, &body.body.set_presence)
@@ -131,7 +134,7 @@ pub(crate) async fn sync_events_route(
 	}
 
 	// Setup watchers, so if there's no response, we can wait for them
-	let watcher = services.sync.watch(sender_user, sender_device);
+	let watcher = services.sync.watch(sender_user, sender_device).await;
 
 	let response = build_sync_events(&services, &body).await?;
 	if body.body.full_state
@@ -161,12 +164,14 @@ pub(crate) async fn build_sync_events(
 	let (sender_user, sender_device) = body.sender();
 
 	let next_batch = services.globals.current_count()?;
-	let since = body
+	let since_token: Token = body
 		.body
 		.since
 		.as_ref()
 		.and_then(|string| string.parse().ok())
-		.unwrap_or(0);
+		.unwrap_or_default();
+
+	let since = since_token.count;
 
 	let full_state = body.body.full_state;
 	let filter = match body.body.filter.as_ref() {
@@ -179,12 +184,18 @@ pub(crate) async fn build_sync_events(
 			.unwrap_or_default(),
 	};
 
+	// Rooms are loaded concurrently and complete in any order; each section is
+	// collected into a map so the response is the same regardless.
+	let width = (services.config.sync_room_concurrency > 0)
+		.then_some(services.config.sync_room_concurrency);
+
 	let joined_rooms = services
 		.rooms
 		.state_cache
 		.rooms_joined(sender_user)
+		.ready_filter(|&room_id| room_matches(&filter.room, room_id))
 		.map(ToOwned::to_owned)
-		.broad_filter_map(|room_id| {
+		.broadn_filter_map(width, |room_id| {
 			load_joined_room(
 				services,
 				sender_user,
@@ -195,20 +206,18 @@ pub(crate) async fn build_sync_events(
 				full_state,
 				&filter,
 			)
-			.map_ok(move |(joined_room, dlu, jeu)| (room_id, joined_room, dlu, jeu))
+			.map_ok(move |(joined_room, dlu)| (room_id, joined_room, dlu))
 			.ok()
 		})
 		.ready_fold(
-			(BTreeMap::new(), HashSet::new(), HashSet::new()),
-			|(mut joined_rooms, mut device_list_updates, mut left_encrypted_users),
-			 (room_id, joined_room, dlu, leu)| {
+			(BTreeMap::new(), HashSet::new()),
+			|(mut joined_rooms, mut device_list_updates), (room_id, joined_room, dlu)| {
 				device_list_updates.extend(dlu);
-				left_encrypted_users.extend(leu);
 				if !joined_room.is_empty() {
 					joined_rooms.insert(room_id, joined_room);
 				}
 
-				(joined_rooms, device_list_updates, left_encrypted_users)
+				(joined_rooms, device_list_updates)
 			},
 		);
 
@@ -216,7 +225,8 @@ pub(crate) async fn build_sync_events(
 		.rooms
 		.state_cache
 		.rooms_left(sender_user)
-		.broad_filter_map(|(room_id, _)| {
+		.ready_filter(|(room_id, _)| room_matches(&filter.room, room_id))
+		.broadn_filter_map(width, |(room_id, _)| {
 			handle_left_room(
 				services,
 				since,
@@ -237,7 +247,8 @@ pub(crate) async fn build_sync_events(
 		.rooms
 		.state_cache
 		.rooms_invited(sender_user)
-		.fold_default(|mut invited_rooms: BTreeMap<_, _>, (room_id, invite_state)| async move {
+		.ready_filter(|(room_id, _)| room_matches(&filter.room, room_id))
+		.broadn_filter_map(width, |(room_id, invite_state)| async move {
 			let invite_count = services
 				.rooms
 				.state_cache
@@ -247,22 +258,23 @@ pub(crate) async fn build_sync_events(
 
 			// Invited before last sync
 			if Some(since) >= invite_count {
-				return invited_rooms;
+				return None;
 			}
 
 			let invited_room = InvitedRoom {
 				invite_state: InviteState { events: invite_state },
 			};
 
-			invited_rooms.insert(room_id, invited_room);
-			invited_rooms
-		});
+			Some((room_id, invited_room))
+		})
+		.collect::<BTreeMap<_, _>>();
 
 	let knocked_rooms = services
 		.rooms
 		.state_cache
 		.rooms_knocked(sender_user)
-		.fold_default(|mut knocked_rooms: BTreeMap<_, _>, (room_id, knock_state)| async move {
+		.ready_filter(|(room_id, _)| room_matches(&filter.room, room_id))
+		.broadn_filter_map(width, |(room_id, knock_state)| async move {
 			let knock_count = services
 				.rooms
 				.state_cache
@@ -272,21 +284,22 @@ pub(crate) async fn build_sync_events(
 
 			// Knocked before last sync
 			if Some(since) >= knock_count {
-				return knocked_rooms;
+				return None;
 			}
 
 			let knocked_room = KnockedRoom {
 				knock_state: KnockState { events: knock_state },
 			};
 
-			knocked_rooms.insert(room_id, knocked_room);
-			knocked_rooms
-		});
+			Some((room_id, knocked_room))
+		})
+		.collect::<BTreeMap<_, _>>();
 
-	let presence_updates: OptionFuture<_> = services
-		.config
-		.allow_local_presence
-		.then(|| process_presence_updates(services, since, sender_user))
+	let presence = services.config.allow_local_presence && presence_wanted(&filter.presence);
+	let presence_updates: OptionFuture<_> = presence
+		.then(|| {
+			process_presence_updates(services, since, next_batch, sender_user, &filter.presence)
+		})
 		.into();
 
 	let account_data = services
@@ -295,6 +308,14 @@ pub(crate) async fn build_sync_events(
 		.ready_filter_map(|e| extract_variant!(e, AnyRawAccountDataEvent::Global))
 		.collect();
 
+	// Users who began or stopped sharing an encrypted room with this account
+	let device_list_changes: OptionFuture<_> = body
+		.body
+		.since
+		.is_some()
+		.then(|| services.sync.device_list_changes(sender_user, since))
+		.into();
+
 	// Look for device list updates of this account
 	let keys_changed = services
 		.users
@@ -302,46 +323,42 @@ pub(crate) async fn build_sync_events(
 		.map(ToOwned::to_owned)
 		.collect::<HashSet<_>>();
 
-	let to_device_events = services
-		.users
-		.get_to_device_events(sender_user, sender_device, Some(since), Some(next_batch))
-		.collect::<Vec<_>>();
+	let to_device_events = services.users.get_to_device_page(
+		sender_user,
+		sender_device,
+		since_token.to_device(),
+		next_batch,
+		services.config.to_device_sync_limit,
+	);
 
 	let device_one_time_keys_count = services
 		.users
 		.count_one_time_keys(sender_user, sender_device);
 
 	// Remove all to-device events the device received *last time*
-	let remove_to_device_events =
-		services
-			.users
-			.remove_to_device_events(sender_user, sender_device, since);
+	let remove_to_device_events = services.users.remove_to_device_events(
+		sender_user,
+		sender_device,
+		since_token.to_device(),
+	);
 
 	let rooms = join4(joined_rooms, left_rooms, invited_rooms, knocked_rooms);
 	let ephemeral = join3(remove_to_device_events, to_device_events, presence_updates);
-	let top = join5(account_data, ephemeral, device_one_time_keys_count, keys_changed, rooms)
+	let device_lists = join(device_list_changes, keys_changed);
+	let top = join5(account_data, ephemeral, device_one_time_keys_count, device_lists, rooms)
 		.boxed()
 		.await;
 
-	let (account_data, ephemeral, device_one_time_keys_count, keys_changed, rooms) = top;
-	let ((), to_device_events, presence_updates) = ephemeral;
+	let (account_data, ephemeral, device_one_time_keys_count, device_lists, rooms) = top;
+	let ((), (to_device_events, to_device), presence_updates) = ephemeral;
 	let (joined_rooms, left_rooms, invited_rooms, knocked_rooms) = rooms;
-	let (joined_rooms, mut device_list_updates, left_encrypted_users) = joined_rooms;
-	device_list_updates.extend(keys_changed);
+	let (joined_rooms, mut device_list_updates) = joined_rooms;
+	let (device_list_changes, keys_changed) = device_lists;
+	let DeviceLists { changed, left: device_list_left } = device_list_changes.unwrap_or_default();
 
-	// If the user doesn't share an encrypted room with the target anymore, we need
-	// to tell them
-	let device_list_left: HashSet<_> = left_encrypted_users
-		.into_iter()
-		.stream()
-		.broad_filter_map(|user_id| async move {
-			share_encrypted_room(services, sender_user, &user_id, None)
-				.await
-				.eq(&false)
-				.then_some(user_id)
-		})
-		.collect()
-		.await;
+	device_list_updates.extend(keys_changed);
+	device_list_updates.extend(changed);
+	device_list_updates.retain(|user_id| !device_list_left.contains(user_id));
 
 	let response = sync_events::v3::Response {
 		account_data: GlobalAccountData { events: account_data },
@@ -352,7 +369,7 @@ pub(crate) async fn build_sync_events(
 		device_one_time_keys_count,
 		// Fallback keys are not yet supported
 		device_unused_fallback_key_types: None,
-		next_batch: next_batch.to_string(),
+		next_batch: Token { count: next_batch, to_device }.to_string(),
 		presence: Presence {
 			events: presence_updates
 				.into_iter()
@@ -378,11 +395,16 @@ pub(crate) async fn build_sync_events(
 async fn process_presence_updates(
 	services: &Services,
 	since: u64,
+	next_batch: u64,
 	syncing_user: &UserId,
+	filter: &EventFilter,
 ) -> PresenceUpdates {
+	// updates after next_batch are left to the next sync
 	services
 		.presence
 		.presence_since(since)
+		.ready_filter(|(_, count, _)| *count <= next_batch)
+		.ready_filter(|(user_id, ..)| presence_matches(filter, user_id))
 		.filter(|(user_id, ..)| {
 			services
 				.rooms
@@ -554,6 +576,10 @@ async fn handle_left_room(
 				continue;
 			}
 
+			if !pdu.matches(&filter.room.state) {
+				continue;
+			}
+
 			left_state_events.push(pdu.into_sync_state_event());
 		}
 	}
@@ -588,7 +614,7 @@ async fn load_joined_room(
 	next_batch: u64,
 	full_state: bool,
 	filter: &FilterDefinition,
-) -> Result<(JoinedRoom, HashSet<OwnedUserId>, HashSet<OwnedUserId>)> {
+) -> Result<(JoinedRoom, HashSet<OwnedUserId>)> {
 	let sincecount = PduCount::Normal(since);
 	let next_batchcount = PduCount::Normal(next_batch);
 
@@ -611,21 +637,39 @@ async fn load_joined_room(
 		room_id,
 		sincecount,
 		Some(next_batchcount),
-		10_usize,
+		filter_limit(
+			&filter.room.timeline,
+			services.config.sync_timeline_limit_default,
+			services.config.sync_timeline_limit_max,
+		),
+		Some(&filter.room.timeline),
 	);
 
-	let receipt_events = services
-		.rooms
-		.read_receipt
-		.readreceipts_since(room_id, since)
-		.filter_map(|(read_user, _, edu)| async move {
+	let receipts = filter_type_matches(&filter.room.ephemeral, "m.receipt");
+	let receipt_events: OptionFuture<_> = receipts
+		.then(|| {
 			services
-				.users
-				.user_is_ignored(read_user, sender_user)
-				.await
-				.or_some((read_user.to_owned(), edu))
+				.rooms
+				.read_receipt
+				.latest_receipts_since(room_id, since, Some(next_batch))
+		})
+		.into();
+
+	let receipt_events = receipt_events
+		.map(Option::unwrap_or_default)
+		.then(|receipt_events| {
+			receipt_events
+				.into_iter()
+				.stream()
+				.filter_map(|(read_user, _, edu)| async move {
+					services
+						.users
+						.user_is_ignored(read_user, sender_user)
+						.await
+						.or_some((read_user.to_owned(), edu))
+				})
+				.collect::<Vec<(OwnedUserId, Raw<AnySyncEphemeralRoomEvent>)>>()
 		})
-		.collect::<HashMap<OwnedUserId, Raw<AnySyncEphemeralRoomEvent>>>()
 		.map(Ok);
 
 	let (current_shortstatehash, since_shortstatehash, timeline, receipt_events) =
@@ -643,11 +687,13 @@ async fn load_joined_room(
 		device_id: sender_device,
 		room_id,
 		token: Some(since),
+		shortstatehash: Some(current_shortstatehash),
 		options: Some(&filter.room.state.lazy_load_options),
 	};
 
-	// Reset lazy loading because this is an initial sync
-	let lazy_load_reset: OptionFuture<_> = initial
+	// Reset lazy loading on an initial sync, and on a gappy one since the client
+	// may drop the members it has when it sees the gap.
+	let lazy_load_reset: OptionFuture<_> = (initial || limited)
 		.then(|| services.rooms.lazy_loading.reset(lazy_loading_context))
 		.into();
 
@@ -659,7 +705,7 @@ async fn load_joined_room(
 				.map(ref_at!(1))
 				.map(Event::sender)
 				.map(Into::into)
-				.chain(receipt_events.keys().map(Into::into))
+				.chain(receipt_events.iter().map(|(read_user, _)| read_user.into()))
 				.collect();
 
 			services
@@ -704,8 +750,6 @@ async fn load_joined_room(
 		joined_member_count,
 		invited_member_count,
 		mut state_events,
-		mut device_list_updates,
-		left_encrypted_users,
 	} = calculate_state_changes(
 		services,
 		sender_user,
@@ -714,12 +758,13 @@ async fn load_joined_room(
 		filter,
 		since_shortstatehash,
 		current_shortstatehash,
-		joined_since_last_sync,
 		witness.as_ref(),
 	)
 	.boxed()
 	.await?;
 
+	state_events.retain(|pdu| pdu.matches(&filter.room.state));
+
 	let is_sender_membership = |pdu: &PduEvent| {
 		pdu.kind == StateEventType::RoomMember.into()
 			&& pdu
@@ -769,25 +814,13 @@ async fn load_joined_room(
 
 	let send_notification_counts = last_notification_read.is_none_or(|count| count > since);
 
-	let notification_count: OptionFuture<_> = send_notification_counts
+	let unread_notifications: OptionFuture<_> = send_notification_counts
 		.then(|| {
-			services
-				.rooms
-				.user
-				.notification_count(sender_user, room_id)
-				.map(TryInto::try_into)
-				.unwrap_or(uint!(0))
-		})
-		.into();
-
-	let highlight_count: OptionFuture<_> = send_notification_counts
-		.then(|| {
-			services
-				.rooms
-				.user
-				.highlight_count(sender_user, room_id)
-				.map(TryInto::try_into)
-				.unwrap_or(uint!(0))
+			services.rooms.user.unread_notifications(
+				sender_user,
+				room_id,
+				filter.room.timeline.unread_thread_notifications,
+			)
 		})
 		.into();
 
@@ -796,7 +829,7 @@ async fn load_joined_room(
 		.typing
 		.last_typing_update(room_id)
 		.and_then(|count| async move {
-			if count <= since {
+			if count <= since || !filter_type_matches(&filter.room.ephemeral, "m.typing") {
 				return Ok(Vec::<Raw<AnySyncEphemeralRoomEvent>>::new());
 			}
 
@@ -810,7 +843,6 @@ async fn load_joined_room(
 		})
 		.unwrap_or(Vec::new());
 
-	let unread_notifications = join(notification_count, highlight_count);
 	let events = join3(room_events, account_data_events, typing_events);
 	let (unread_notifications, events, device_updates) =
 		join3(unread_notifications, events, device_updates)
@@ -818,15 +850,28 @@ async fn load_joined_room(
 			.await;
 
 	let (room_events, account_data_events, typing_events) = events;
-	let (notification_count, highlight_count) = unread_notifications;
+	let (unread_notifications, unread_thread_notifications): (
+		UnreadNotificationsCount,
+		BTreeMap<_, _>,
+	) = unread_notifications
+		.map(|(main, threads)| {
+			let threads = threads
+				.into_iter()
+				.map(|(thread_id, counts)| (thread_id, counts.into()))
+				.collect();
 
-	device_list_updates.extend(device_updates);
+			(main.into(), threads)
+		})
+		.unwrap_or_default();
 
-	let last_privateread_update = services
-		.rooms
-		.read_receipt
-		.last_privateread_update(sender_user, room_id)
-		.await > since;
+	let device_list_updates = device_updates.into_iter().collect();
+
+	let last_privateread_update = receipts
+		&& services
+			.rooms
+			.read_receipt
+			.last_privateread_update(sender_user, room_id)
+			.await > since;
 
 	let private_read_event = if last_privateread_update {
 		services
@@ -840,7 +885,8 @@ async fn load_joined_room(
 	};
 
 	let edus: Vec<Raw<AnySyncEphemeralRoomEvent>> = receipt_events
-		.into_values()
+		.into_iter()
+		.map(at!(1))
 		.chain(typing_events.into_iter())
 		.chain(private_read_event.into_iter())
 		.collect();
@@ -865,7 +911,7 @@ async fn load_joined_room(
 				.filter_map(Result::ok)
 				.collect(),
 		},
-		unread_notifications: UnreadNotificationsCount { highlight_count, notification_count },
+		unread_notifications,
 		timeline: Timeline {
 			limited: limited || joined_since_last_sync,
 			prev_batch: prev_batch.as_ref().map(ToString::to_string),
@@ -878,10 +924,10 @@ async fn load_joined_room(
 				.collect(),
 		},
 		ephemeral: Ephemeral { events: edus },
-		unread_thread_notifications: BTreeMap::new(),
+		unread_thread_notifications,
 	};
 
-	Ok((joined_room, device_list_updates, left_encrypted_users))
+	Ok((joined_room, device_list_updates))
 }
 
 #[tracing::instrument(
@@ -903,7 +949,6 @@ async fn calculate_state_changes(
 	filter: &FilterDefinition,
 	since_shortstatehash: Option<ShortStateHash>,
 	current_shortstatehash: ShortStateHash,
-	joined_since_last_sync: bool,
 	witness: Option<&Witness>,
 ) -> Result<StateChanges> {
 	if since_shortstatehash.is_none() {
@@ -926,7 +971,6 @@ async fn calculate_state_changes(
 			filter,
 			since_shortstatehash,
 			current_shortstatehash,
-			joined_since_last_sync,
 			witness,
 		)
 		.await
@@ -999,7 +1043,6 @@ async fn calculate_state_incremental<'a>(
 	_filter: &FilterDefinition,
 	since_shortstatehash: Option<ShortStateHash>,
 	current_shortstatehash: ShortStateHash,
-	joined_since_last_sync: bool,
 	witness: Option<&'a Witness>,
 ) -> Result<StateChanges> {
 	let since_shortstatehash = since_shortstatehash.unwrap_or(current_shortstatehash);
@@ -1086,34 +1129,6 @@ async fn calculate_state_incremental<'a>(
 		.collect::<Vec<_>>()
 		.await;
 
-	let (device_list_updates, left_encrypted_users) = state_events
-		.iter()
-		.stream()
-		.ready_filter(|_| encrypted_room)
-		.ready_filter(|state_event| state_event.kind == RoomMember)
-		.ready_filter_map(|state_event| {
-			let content: RoomMemberEventContent = state_event.get_content().ok()?;
-			let user_id: OwnedUserId = state_event.state_key.as_ref()?.parse().ok()?;
-
-			Some((content, user_id))
-		})
-		.fold_default(|(mut dlu, mut leu): pair_of!(HashSet<_>), (content, user_id)| async move {
-			use MembershipState::*;
-
-			let shares_encrypted_room =
-				|user_id| share_encrypted_room(services, sender_user, user_id, Some(room_id));
-
-			match content.membership {
-				| Leave => leu.insert(user_id),
-				| Join if joined_since_last_sync || !shares_encrypted_room(&user_id).await =>
-					dlu.insert(user_id),
-				| _ => false,
-			};
-
-			(dlu, leu)
-		})
-		.await;
-
 	let send_member_count = state_events.iter().any(|event| event.kind == RoomMember);
 
 	let (joined_member_count, invited_member_count, heroes) = if send_member_count {
@@ -1127,8 +1142,6 @@ async fn calculate_state_incremental<'a>(
 		joined_member_count,
 		invited_member_count,
 		state_events,
-		device_list_updates,
-		left_encrypted_users,
 	})
 }
 
@@ -1207,7 +1220,7 @@ async fn fold_hero(
 		return heroes;
 	};
 
-	if user_id == sender_user {
+	if user_id == sender_user || services.sending.hidden_hero(user_id) {
 		return heroes;
 	}
 
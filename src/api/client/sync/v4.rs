@@ -53,7 +53,7 @@ pub(crate) async fn sync_events_v4_route(
 	let sender_device = body.sender_device.expect("user is authenticated");
 	let mut body = body.body;
 	// Setup watchers, so if there's no response, we can wait for them
-	let watcher = services.sync.watch(sender_user, &sender_device);
+	let watcher = services.sync.watch(sender_user, &sender_device).await;
 
 	let next_batch = services.globals.next_count()?;
 
@@ -392,8 +392,11 @@ pub(crate) async fn sync_events_v4_route(
 							.room_details
 							.timeline_limit
 							.map(u64::from)
-							.map_or(10, usize_from_u64_truncated)
-							.min(100);
+							.map_or(
+								services.config.sync_timeline_limit_default,
+								usize_from_u64_truncated,
+							)
+							.min(services.config.sync_timeline_limit_max);
 
 						todo_room.0.extend(
 							list.room_details
@@ -452,8 +455,8 @@ pub(crate) async fn sync_events_v4_route(
 		let limit: usize = room
 			.timeline_limit
 			.map(u64::from)
-			.map_or(10, usize_from_u64_truncated)
-			.min(100);
+			.map_or(services.config.sync_timeline_limit_default, usize_from_u64_truncated)
+			.min(services.config.sync_timeline_limit_max);
 
 		todo_room.0.extend(
 			room.required_state
@@ -523,6 +526,7 @@ pub(crate) async fn sync_events_v4_route(
 				roomsincecount,
 				None,
 				*timeline_limit,
+				None,
 			)
 			.await
 			{
@@ -564,7 +568,10 @@ pub(crate) async fn sync_events_v4_route(
 		let mut vector: Vec<Raw<AnySyncEphemeralRoomEvent>> = services
 			.rooms
 			.read_receipt
-			.readreceipts_since(room_id, *roomsince)
+			.latest_receipts_since(room_id, *roomsince, Some(next_batch))
+			.await
+			.into_iter()
+			.stream()
 			.filter_map(|(read_user, _ts, v)| async move {
 				services
 					.users
@@ -648,7 +655,7 @@ pub(crate) async fn sync_events_v4_route(
 			.rooms
 			.state_cache
 			.room_members(room_id)
-			.ready_filter(|member| member != sender_user)
+			.ready_filter(|member| member != sender_user && !services.sending.hidden_hero(member))
 			.filter_map(|user_id| {
 				services
 					.rooms
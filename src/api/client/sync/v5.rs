@@ -21,11 +21,8 @@
 use conduwuit_service::rooms::read_receipt::pack_receipts;
 use futures::{FutureExt, StreamExt, TryFutureExt};
 use ruma::{
-	DeviceId, OwnedEventId, OwnedRoomId, RoomId, UInt, UserId,
-	api::client::{
-		error::ErrorKind,
-		sync::sync_events::{self, DeviceLists, UnreadNotificationsCount},
-	},
+	DeviceId, OwnedEventId, OwnedRoomId, RoomId, UserId,
+	api::client::sync::sync_events::{self, DeviceLists, UnreadNotificationsCount},
 	events::{
 		AnyRawAccountDataEvent, AnySyncEphemeralRoomEvent, StateEventType, TimelineEventType,
 		room::member::{MembershipState, RoomMemberEventContent},
@@ -62,30 +59,28 @@ pub(crate) async fn sync_events_v5_route(
 	let mut body = body.body;
 
 	// Setup watchers, so if there's no response, we can wait for them
-	let watcher = services.sync.watch(sender_user, sender_device);
+	let watcher = services.sync.watch(sender_user, sender_device).await;
 
 	let next_batch = services.globals.next_count()?;
 
 	let conn_id = body.conn_id.clone();
 
-	let globalsince = body
+	let mut globalsince = body
 		.pos
 		.as_ref()
 		.and_then(|string| string.parse().ok())
 		.unwrap_or(0);
 
+	// A connection which expired or was lost to a restart is resynced in full
+	// rather than failed, as the client would only start over anyway.
 	if globalsince != 0
 		&& !services.sync.snake_connection_cached(
 			sender_user.clone(),
 			sender_device.clone(),
 			conn_id.clone(),
 		) {
-		debug!("Restarting sync stream because it was gone from the database");
-		return Err(Error::Request(
-			ErrorKind::UnknownPos,
-			"Connection data lost since last time".into(),
-			http::StatusCode::BAD_REQUEST,
-		));
+		debug!("Resyncing connection unknown since last time in full");
+		globalsince = 0;
 	}
 
 	// Client / User requested an initial sync
@@ -128,15 +123,22 @@ pub(crate) async fn sync_events_v5_route(
 		.collect()
 		.await;
 
-	let all_rooms: Vec<&RoomId> = all_joined_rooms
+	let mut all_rooms: Vec<&RoomId> = all_joined_rooms
 		.iter()
 		.map(AsRef::as_ref)
 		.chain(all_invited_rooms.iter().map(AsRef::as_ref))
 		.chain(all_knocked_rooms.iter().map(AsRef::as_ref))
 		.collect();
 
-	let all_joined_rooms = all_joined_rooms.iter().map(AsRef::as_ref).collect();
-	let all_invited_rooms = all_invited_rooms.iter().map(AsRef::as_ref).collect();
+	// Lists are windows over the rooms ordered by their latest activity.
+	let recency = room_recency(services, &all_rooms).await;
+	let by_recency = |a: &&RoomId, b: &&RoomId| recency.get(b).cmp(&recency.get(a));
+	all_rooms.sort_by(by_recency);
+
+	let mut all_joined_rooms: Vec<_> = all_joined_rooms.iter().map(AsRef::as_ref).collect();
+	let mut all_invited_rooms: Vec<_> = all_invited_rooms.iter().map(AsRef::as_ref).collect();
+	all_joined_rooms.sort_by(by_recency);
+	all_invited_rooms.sort_by(by_recency);
 
 	let pos = next_batch.clone().to_string();
 
@@ -149,7 +151,7 @@ pub(crate) async fn sync_events_v5_route(
 		lists: BTreeMap::new(),
 		rooms: BTreeMap::new(),
 		extensions: sync_events::v5::response::Extensions {
-			account_data: collect_account_data(services, sync_info).await,
+			account_data: collect_account_data(services, sync_info, next_batch).await,
 			e2ee: collect_e2ee(services, sync_info, &all_joined_rooms).await?,
 			to_device: collect_to_device(services, sync_info, next_batch).await,
 			receipts: collect_receipts(services).await,
@@ -182,15 +184,18 @@ pub(crate) async fn sync_events_v5_route(
 	)
 	.await?;
 
+	response.extensions.typing = collect_typing(services, sync_info, &todo_rooms).await;
+
 	if response.rooms.iter().all(|(id, r)| {
 		r.timeline.is_empty()
 			&& r.required_state.is_empty()
 			&& !response.extensions.receipts.rooms.contains_key(id)
-	}) && response
-		.extensions
-		.to_device
-		.clone()
-		.is_none_or(|to| to.events.is_empty())
+	}) && response.extensions.typing.rooms.is_empty()
+		&& response
+			.extensions
+			.to_device
+			.clone()
+			.is_none_or(|to| to.events.is_empty())
 	{
 		// Hang a few seconds so requests are not spammed
 		// Stop hanging if new info arrives
@@ -230,14 +235,15 @@ async fn fetch_subscriptions(
 				.entry(room_id.clone())
 				.or_insert((BTreeSet::new(), 0_usize, u64::MAX));
 
-		let limit: UInt = room.timeline_limit;
+		let limit =
+			usize_from_ruma(room.timeline_limit).min(services.config.sync_timeline_limit_max);
 
 		todo_room.0.extend(
 			room.required_state
 				.iter()
 				.map(|(ty, sk)| (ty.clone(), sk.as_str().into())),
 		);
-		todo_room.1 = todo_room.1.max(usize_from_ruma(limit));
+		todo_room.1 = todo_room.1.max(limit);
 		// 0 means unknown because it got out of date
 		todo_room.2 = todo_room.2.min(
 			known_rooms
@@ -294,14 +300,14 @@ async fn handle_lists<'a>(
 
 		let ranges = list.ranges.clone();
 
-		for mut range in ranges {
-			range.0 = uint!(0);
-			range.1 = range
-				.1
-				.clamp(range.0, UInt::try_from(active_rooms.len()).unwrap_or(UInt::MAX));
+		for (start, end) in ranges {
+			// Ranges are inclusive of both ends.
+			let end = usize_from_ruma(end)
+				.saturating_add(1)
+				.min(active_rooms.len());
+			let start = usize_from_ruma(start).min(end);
 
-			let room_ids =
-				active_rooms[usize_from_ruma(range.0)..usize_from_ruma(range.1)].to_vec();
+			let room_ids = active_rooms[start..end].to_vec();
 
 			let new_rooms: BTreeSet<OwnedRoomId> =
 				room_ids.clone().into_iter().map(From::from).collect();
@@ -314,7 +320,8 @@ async fn handle_lists<'a>(
 					u64::MAX,
 				));
 
-				let limit: usize = usize_from_ruma(list.room_details.timeline_limit).min(100);
+				let limit: usize = usize_from_ruma(list.room_details.timeline_limit)
+					.min(services.config.sync_timeline_limit_max);
 
 				todo_room.0.extend(
 					list.room_details
@@ -389,6 +396,7 @@ async fn process_rooms(
 				roomsincecount,
 				Some(PduCount::from(next_batch)),
 				*timeline_limit,
+				None,
 			)
 			.await
 			{
@@ -432,7 +440,10 @@ async fn process_rooms(
 		let mut receipts: Vec<Raw<AnySyncEphemeralRoomEvent>> = services
 			.rooms
 			.read_receipt
-			.readreceipts_since(room_id, *roomsince)
+			.latest_receipts_since(room_id, *roomsince, Some(next_batch))
+			.await
+			.into_iter()
+			.stream()
 			.filter_map(|(read_user, _ts, v)| async move {
 				services
 					.users
@@ -526,7 +537,9 @@ async fn process_rooms(
 			.rooms
 			.state_cache
 			.room_members(room_id)
-			.ready_filter(|member| *member != sender_user)
+			.ready_filter(|member| {
+				*member != sender_user && !services.sending.hidden_hero(member)
+			})
 			.filter_map(|user_id| {
 				services
 					.rooms
@@ -646,6 +659,7 @@ async fn process_rooms(
 async fn collect_account_data(
 	services: crate::State,
 	(sender_user, _, globalsince, body): (&UserId, &DeviceId, u64, &sync_events::v5::Request),
+	next_batch: u64,
 ) -> sync_events::v5::response::AccountData {
 	let mut account_data = sync_events::v5::response::AccountData {
 		global: Vec::new(),
@@ -658,7 +672,7 @@ async fn collect_account_data(
 
 	account_data.global = services
 		.account_data
-		.changes_since(None, sender_user, globalsince, None)
+		.changes_since(None, sender_user, globalsince, Some(next_batch))
 		.ready_filter_map(|e| extract_variant!(e, AnyRawAccountDataEvent::Global))
 		.collect()
 		.await;
@@ -669,7 +683,7 @@ async fn collect_account_data(
 				room.clone(),
 				services
 					.account_data
-					.changes_since(Some(room), sender_user, globalsince, None)
+					.changes_since(Some(room), sender_user, globalsince, Some(next_batch))
 					.ready_filter_map(|e| extract_variant!(e, AnyRawAccountDataEvent::Room))
 					.collect()
 					.await,
@@ -896,3 +910,64 @@ async fn collect_receipts(_services: crate::State) -> sync_events::v5::response:
 	sync_events::v5::response::Receipts { rooms: BTreeMap::new() }
 	// TODO: get explicitly requested read receipts
 }
+
+/// Position of the latest event of each room in the timeline; rooms without
+/// any are left out and sort last.
+async fn room_recency<'a>(
+	services: crate::State,
+	rooms: &[&'a RoomId],
+) -> HashMap<&'a RoomId, PduCount> {
+	rooms
+		.iter()
+		.stream()
+		.filter_map(|&room_id| async move {
+			services
+				.rooms
+				.timeline
+				.last_timeline_count(None, room_id)
+				.await
+				.ok()
+				.map(|count| (room_id, count))
+		})
+		.collect()
+		.await
+}
+
+async fn collect_typing(
+	services: crate::State,
+	(sender_user, _, globalsince, body): SyncInfo<'_>,
+	todo_rooms: &TodoRooms,
+) -> sync_events::v5::response::Typing {
+	let mut typing = sync_events::v5::response::Typing::default();
+	if !body.extensions.typing.enabled.unwrap_or(false) {
+		return typing;
+	}
+
+	for room_id in todo_rooms.keys() {
+		let updated = services
+			.rooms
+			.typing
+			.last_typing_update(room_id)
+			.await
+			.is_ok_and(|count| count > globalsince);
+
+		if !updated {
+			continue;
+		}
+
+		let Ok(event) = services
+			.rooms
+			.typing
+			.typings_all(room_id, sender_user)
+			.await
+		else {
+			continue;
+		};
+
+		if let Ok(event) = Raw::new(&event) {
+			typing.rooms.insert(room_id.clone(), event);
+		}
+	}
+
+	typing
+}
@@ -0,0 +1,60 @@
+use axum::extract::State;
+use conduwuit::Result;
+
+use crate::Ruma;
+
+/// `PUT /_conduwuit/client/v1/uisi_report`
+///
+/// conduwuit-specific API for clients to report an event they could not
+/// decrypt.
+pub(crate) mod report_uisi {
+	pub(crate) mod v1 {
+		use conduwuit_service::uisi::Class;
+		use ruma::{
+			OwnedEventId, OwnedRoomId,
+			api::{Metadata, metadata, request, response},
+		};
+
+		const METADATA: Metadata = metadata! {
+			method: PUT,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_conduwuit/client/v1/uisi_report",
+			}
+		};
+
+		#[request]
+		pub(crate) struct Request {
+			pub(crate) room_id: OwnedRoomId,
+			pub(crate) event_id: OwnedEventId,
+
+			/// The megolm session the event was encrypted with.
+			pub(crate) session_id: String,
+
+			/// Why it could not be decrypted.
+			pub(crate) error: Class,
+		}
+
+		#[response]
+		#[derive(Default)]
+		pub(crate) struct Response {}
+	}
+}
+
+/// # `PUT /_conduwuit/client/v1/uisi_report`
+///
+/// Records that the user's client could not decrypt an event of a room they
+/// are in, when `uisi_reports` is enabled. Reports are rate limited per user
+/// and only seen by admins, through `!admin debug uisi-stats`.
+pub(crate) async fn report_uisi_route(
+	State(services): State<crate::State>,
+	body: Ruma<report_uisi::v1::Request>,
+) -> Result<report_uisi::v1::Response> {
+	services
+		.uisi
+		.report(body.sender_user(), &body.room_id, &body.event_id, &body.session_id, body.error)
+		.await?;
+
+	Ok(report_uisi::v1::Response {})
+}
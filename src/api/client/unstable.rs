@@ -2,10 +2,10 @@
 
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
-use conduwuit::{Err, Error, Result};
+use conduwuit::{Err, Error, Result, err};
 use futures::StreamExt;
 use ruma::{
-	OwnedRoomId,
+	OwnedRoomId, RoomId,
 	api::{
 		client::{
 			error::ErrorKind,
@@ -23,11 +23,13 @@
 use super::{update_avatar_url, update_displayname};
 use crate::Ruma;
 
+/// Most rooms in common given in one response.
+const MUTUAL_ROOMS_LIMIT: usize = 100;
+
 /// # `GET /_matrix/client/unstable/uk.half-shot.msc2666/user/mutual_rooms`
 ///
-/// Gets all the rooms the sender shares with the specified user.
-///
-/// TODO: Implement pagination, currently this just returns everything
+/// Gets the rooms the sender shares with the specified user, a page at a time.
+/// Users sharing no rooms with the sender get an empty list.
 ///
 /// An implementation of [MSC2666](https://github.com/matrix-org/matrix-spec-proposals/pull/2666)
 #[tracing::instrument(skip_all, fields(%client), name = "mutual_rooms")]
@@ -42,21 +44,22 @@ pub(crate) async fn get_mutual_rooms_route(
 		return Err!(Request(Unknown("You cannot request rooms in common with yourself.")));
 	}
 
-	if !services.users.exists(&body.user_id).await {
-		return Ok(mutual_rooms::unstable::Response { joined: vec![], next_batch_token: None });
-	}
+	let since = body
+		.batch_token
+		.as_deref()
+		.map(RoomId::parse)
+		.transpose()
+		.map_err(|_| err!(Request(InvalidParam("Invalid batch_token."))))?;
 
-	let mutual_rooms: Vec<OwnedRoomId> = services
+	let (joined, next) = services
 		.rooms
 		.state_cache
-		.get_shared_rooms(sender_user, &body.user_id)
-		.map(ToOwned::to_owned)
-		.collect()
+		.mutual_rooms_page(sender_user, &body.user_id, since.as_deref(), MUTUAL_ROOMS_LIMIT)
 		.await;
 
 	Ok(mutual_rooms::unstable::Response {
-		joined: mutual_rooms,
-		next_batch_token: None,
+		joined,
+		next_batch_token: next.as_ref().map(ToString::to_string),
 	})
 }
 
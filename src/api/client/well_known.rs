@@ -1,10 +1,8 @@
 use axum::{Json, extract::State, response::IntoResponse};
+use axum_extra::{TypedHeader, headers::UserAgent};
 use conduwuit::{Error, Result};
 use ruma::api::client::{
-	discovery::{
-		discover_homeserver::{self, HomeserverInfo, SlidingSyncProxyInfo},
-		discover_support::{self, Contact},
-	},
+	discovery::discover_support::{self, Contact},
 	error::ErrorKind,
 };
 
@@ -12,22 +10,23 @@
 
 /// # `GET /.well-known/matrix/client`
 ///
-/// Returns the .well-known URL if it is configured, otherwise returns 404.
+/// Returns the .well-known URL if it is configured, otherwise returns 404. The
+/// sliding sync advertisement depends on the client's User-Agent when
+/// overridden for it.
 pub(crate) async fn well_known_client(
 	State(services): State<crate::State>,
-	_body: Ruma<discover_homeserver::Request>,
-) -> Result<discover_homeserver::Response> {
-	let client_url = match services.server.config.well_known.client.as_ref() {
-		| Some(url) => url.to_string(),
-		| None => return Err(Error::BadRequest(ErrorKind::NotFound, "Not found.")),
-	};
-
-	Ok(discover_homeserver::Response {
-		homeserver: HomeserverInfo { base_url: client_url.clone() },
-		identity_server: None,
-		sliding_sync_proxy: Some(SlidingSyncProxyInfo { url: client_url }),
-		tile_server: None,
-	})
+	user_agent: Option<TypedHeader<UserAgent>>,
+) -> Result<impl IntoResponse> {
+	let user_agent = user_agent.as_ref().map(|TypedHeader(ua)| ua.as_str());
+	match services
+		.server
+		.config
+		.well_known
+		.client_response(user_agent)
+	{
+		| Some(response) => Ok(Json(response)),
+		| None => Err(Error::BadRequest(ErrorKind::NotFound, "Not found.")),
+	}
 }
 
 /// # `GET /.well-known/matrix/support`
@@ -32,6 +32,10 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_supported_versions_route)
 		.ruma_route(&client::get_register_available_route)
 		.ruma_route(&client::register_route)
+		.ruma_route(&client::admin_create_user_route)
+		.ruma_route(&client::admin_reset_password_route)
+		.ruma_route(&client::admin_deactivate_user_route)
+		.ruma_route(&client::admin_list_users_route)
 		.ruma_route(&client::get_login_types_route)
 		.ruma_route(&client::login_route)
 		.ruma_route(&client::login_token_route)
@@ -54,6 +58,8 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_pushrule_actions_route)
 		.ruma_route(&client::set_pushrule_actions_route)
 		.ruma_route(&client::delete_pushrule_route)
+		.ruma_route(&client::test_pushrule_route)
+		.ruma_route(&client::set_room_profile_route)
 		.ruma_route(&client::get_room_event_route)
 		.ruma_route(&client::get_room_aliases_route)
 		.ruma_route(&client::get_filter_route)
@@ -94,6 +100,7 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::redact_event_route)
 		.ruma_route(&client::report_event_route)
 		.ruma_route(&client::report_room_route)
+		.ruma_route(&client::report_uisi_route)
 		.ruma_route(&client::create_alias_route)
 		.ruma_route(&client::delete_alias_route)
 		.ruma_route(&client::get_alias_route)
@@ -107,6 +114,7 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::kick_user_route)
 		.ruma_route(&client::ban_user_route)
 		.ruma_route(&client::unban_user_route)
+		.ruma_route(&client::batch_membership_route)
 		.ruma_route(&client::invite_user_route)
 		.ruma_route(&client::set_room_visibility_route)
 		.ruma_route(&client::get_room_visibility_route)
@@ -119,6 +127,7 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			get(client::get_protocols_route_unstable))
 		.ruma_route(&client::send_message_event_route)
 		.ruma_route(&client::send_state_event_for_key_route)
+		.ruma_route(&client::send_state_batch_route)
 		.ruma_route(&client::get_state_events_route)
 		.ruma_route(&client::get_state_events_for_key_route)
 		// Ruma doesn't have support for multiple paths for a single endpoint yet, and these routes
@@ -158,6 +167,9 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_content_as_filename_route)
 		.ruma_route(&client::get_media_preview_route)
 		.ruma_route(&client::get_media_config_route)
+		.ruma_route(&client::get_media_info_route)
+		.ruma_route(&client::get_media_usage_route)
+		.ruma_route(&client::delete_media_route)
 		.ruma_route(&client::get_devices_route)
 		.ruma_route(&client::get_device_route)
 		.ruma_route(&client::update_device_route)
@@ -184,11 +196,23 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			get(client::get_room_summary_legacy)
 		)
 		.ruma_route(&client::well_known_support)
-		.ruma_route(&client::well_known_client)
+		.route("/.well-known/matrix/client", get(client::well_known_client))
 		.route("/_conduwuit/server_version", get(client::conduwuit_server_version))
+		.route("/_conduwuit/health/live", get(client::health_live_route))
+		.route("/_conduwuit/health/ready", get(client::health_ready_route))
+		.route(
+			"/_matrix/client/unstable/account_validity/renew",
+			get(client::account_validity_renew_route),
+		)
 		.ruma_route(&client::room_initial_sync_route)
 		.route("/client/server.json", get(client::syncv3_client_server_json));
 
+	if config.index_page {
+		router = router.route("/", get(client::index_route));
+	} else {
+		router = router.route("/", get(client::it_works));
+	}
+
 	if config.allow_federation {
 		router = router
 			.ruma_route(&server::get_server_version_route)
@@ -204,6 +228,7 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			.ruma_route(&server::get_backfill_route)
 			.ruma_route(&server::get_missing_events_route)
 			.ruma_route(&server::get_event_authorization_route)
+			.ruma_route(&server::get_event_by_timestamp_route)
 			.ruma_route(&server::get_room_state_route)
 			.ruma_route(&server::get_room_state_ids_route)
 			.ruma_route(&server::create_leave_event_template_route)
@@ -234,6 +259,17 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			.route("/_conduwuit/local_user_count", any(federation_disabled));
 	}
 
+	if config.synapse_admin_api {
+		router = router
+			.ruma_route(&client::synapse_list_users_route)
+			.ruma_route(&client::synapse_query_user_route)
+			.ruma_route(&client::synapse_deactivate_user_route)
+			.ruma_route(&client::synapse_reset_password_route)
+			.ruma_route(&client::synapse_list_rooms_route)
+			.ruma_route(&client::synapse_delete_room_route)
+			.ruma_route(&client::synapse_quarantine_media_route);
+	}
+
 	if config.allow_legacy_media {
 		router = router
 			.ruma_route(&client::get_media_config_legacy_route)
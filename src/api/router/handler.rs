@@ -5,9 +5,10 @@
 	routing::{MethodFilter, on},
 };
 use conduwuit::Result;
+use conduwuit_database::Lane;
 use futures::{Future, TryFutureExt};
 use http::Method;
-use ruma::api::IncomingRequest;
+use ruma::api::{AuthScheme, IncomingRequest};
 
 use super::{Ruma, RumaResponse, State};
 
@@ -51,7 +52,8 @@ fn add_routes(&'static self, router: Router<State>) -> Router<State> {
 			}
 
 			fn add_route(&'static self, router: Router<State>, path: &str) -> Router<State> {
-				let action = |$($tx,)* req| self($($tx,)* req).map_ok(RumaResponse);
+				let lane = lane(Req::METADATA.authentication);
+				let action = move |$($tx,)* req| lane.scope(self($($tx,)* req)).map_ok(RumaResponse);
 				let method = method_to_filter(&Req::METADATA.method);
 				router.route(path, on(method, action))
 			}
@@ -64,6 +66,15 @@ fn add_route(&'static self, router: Router<State>, path: &str) -> Router<State>
 ruma_handler!(T1, T2, T3);
 ruma_handler!(T1, T2, T3, T4);
 
+/// Clients are waiting on the database requests of their own requests; those
+/// of other servers are not given priority over the server's own work.
+const fn lane(authentication: AuthScheme) -> Lane {
+	match authentication {
+		| AuthScheme::ServerSignatures => Lane::Normal,
+		| _ => Lane::Interactive,
+	}
+}
+
 const fn method_to_filter(method: &Method) -> MethodFilter {
 	match *method {
 		| Method::DELETE => MethodFilter::DELETE,
@@ -1,7 +1,12 @@
 use std::{borrow::Borrow, iter::once};
 
 use axum::extract::State;
-use conduwuit::{Error, Result, utils::stream::ReadyExt};
+use conduwuit::{
+	Err, Error, Result,
+	utils::stream::{BroadbandExt, IterStream, ReadyExt},
+	warn,
+};
+use conduwuit_service::rooms::auth_chain::topological_order;
 use futures::StreamExt;
 use ruma::{
 	RoomId,
@@ -16,6 +21,7 @@
 /// Retrieves the auth chain for a given event.
 ///
 /// - This does not include the event itself
+/// - Auth events come before the events they authorise
 pub(crate) async fn get_event_authorization_route(
 	State(services): State<crate::State>,
 	body: Ruma<get_event_authorization::v1::Request>,
@@ -44,13 +50,55 @@ pub(crate) async fn get_event_authorization_route(
 	let room_id = <&RoomId>::try_from(room_id_str)
 		.map_err(|_| Error::bad_database("Invalid room_id in event in database."))?;
 
-	let auth_chain = services
+	// The access check was for the room asked about, which must be the event's.
+	if room_id != &*body.room_id {
+		return Err!(Request(NotFound("Event not found.")));
+	}
+
+	let auth_chain: Vec<_> = services
 		.rooms
 		.auth_chain
 		.event_ids_iter(room_id, once(body.event_id.borrow()))
 		.ready_filter_map(Result::ok)
-		.filter_map(|id| async move { services.rooms.timeline.get_pdu_json(&id).await.ok() })
-		.then(|pdu| services.sending.convert_to_outgoing_federation_event(pdu))
+		.collect()
+		.await;
+
+	let limit = services.server.config.max_auth_chain_response;
+	if auth_chain.len() > limit {
+		warn!(
+			origin = %body.origin(),
+			event_id = %body.event_id,
+			"Refusing auth chain of {} events, over the limit of {limit}",
+			auth_chain.len(),
+		);
+
+		return Err!(Request(TooLarge("Auth chain is too large.")));
+	}
+
+	let len = auth_chain.len();
+	let events: Vec<_> = auth_chain
+		.into_iter()
+		.stream()
+		.broad_filter_map(|event_id| async move {
+			let pdu = services.rooms.timeline.get_pdu_json(&event_id).await.ok()?;
+
+			Some((event_id, pdu))
+		})
+		.collect()
+		.await;
+
+	if events.len() < len {
+		warn!(
+			event_id = %body.event_id,
+			"Auth chain is missing {} of its {len} events",
+			len.saturating_sub(events.len()),
+		);
+	}
+
+	let auth_chain = topological_order(events)
+		.into_iter()
+		.stream()
+		.then(|(_, pdu)| services.sending.convert_to_outgoing_federation_event(pdu))
 		.collect()
 		.await;
 
@@ -4,6 +4,7 @@
 use conduwuit::{
 	Err, Error, PduEvent, Result, err, pdu::gen_event_id, utils, utils::hash::sha256, warn,
 };
+use conduwuit_service::users::MembershipEntry;
 use ruma::{
 	CanonicalJsonValue, OwnedUserId, UserId,
 	api::{client::error::ErrorKind, federation::membership::create_invite},
@@ -46,19 +47,17 @@ pub(crate) async fn create_invite_route(
 		}
 	}
 
-	if services
+	services
 		.config
-		.forbidden_remote_server_names
-		.is_match(body.origin().host())
-	{
-		warn!(
-			"Received federated/remote invite from banned server {} for room ID {}. Rejecting.",
-			body.origin(),
-			body.room_id
-		);
-
-		return Err!(Request(Forbidden("Server is banned on this homeserver.")));
-	}
+		.check_remote_server(body.origin())
+		.inspect_err(|_| {
+			warn!(
+				"Received federated/remote invite from denied server {} for room ID {}. \
+				 Rejecting.",
+				body.origin(),
+				body.room_id
+			);
+		})?;
 
 	let mut signed_event = utils::to_canonical_object(&body.event)
 		.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invite event is invalid."))?;
@@ -73,6 +72,11 @@ pub(crate) async fn create_invite_route(
 		return Err!(Request(InvalidParam("User does not belong to this homeserver.")));
 	}
 
+	services
+		.users
+		.check_membership_entry(&invited_user, MembershipEntry::FederationInvite)
+		.await?;
+
 	// Make sure we're not ACL'ed from their room.
 	services
 		.rooms
@@ -1,19 +1,10 @@
 use axum::extract::State;
-use conduwuit::{
-	Err, Error, Result, debug_info, matrix::pdu::PduBuilder, utils::IterStream, warn,
-};
-use conduwuit_service::Services;
-use futures::StreamExt;
+use conduwuit::{Err, Error, Result, debug_info, matrix::pdu::PduBuilder, warn};
+use conduwuit_service::{Services, rooms::state_accessor::RestrictedJoin};
 use ruma::{
 	CanonicalJsonObject, OwnedUserId, RoomId, RoomVersionId, UserId,
 	api::{client::error::ErrorKind, federation::membership::prepare_join_event},
-	events::{
-		StateEventType,
-		room::{
-			join_rules::{AllowRule, JoinRule, RoomJoinRulesEventContent},
-			member::{MembershipState, RoomMemberEventContent},
-		},
-	},
+	events::room::member::{MembershipState, RoomMemberEventContent},
 };
 use serde_json::value::to_raw_value;
 
@@ -78,12 +69,8 @@ pub(crate) async fn create_join_event_template_route(
 
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
-	let join_authorized_via_users_server: Option<OwnedUserId> = {
-		use RoomVersionId::*;
-		if matches!(room_version_id, V1 | V2 | V3 | V4 | V5 | V6 | V7) {
-			// room version does not support restricted join rules
-			None
-		} else if user_can_perform_restricted_join(
+	let join_authorized_via_users_server: Option<OwnedUserId> =
+		if user_can_perform_restricted_join(
 			&services,
 			&body.user_id,
 			&body.room_id,
@@ -93,30 +80,19 @@ pub(crate) async fn create_join_event_template_route(
 		{
 			let Some(auth_user) = services
 				.rooms
-				.state_cache
-				.local_users_in_room(&body.room_id)
-				.filter(|user| {
-					services.rooms.state_accessor.user_can_invite(
-						&body.room_id,
-						user,
-						&body.user_id,
-						&state_lock,
-					)
-				})
-				.boxed()
-				.next()
+				.state_accessor
+				.restricted_join_authoriser(&body.room_id, &body.user_id, &state_lock)
 				.await
-				.map(ToOwned::to_owned)
 			else {
 				return Err!(Request(UnableToGrantJoin(
 					"No user on this server is able to assist in joining."
 				)));
 			};
+
 			Some(auth_user)
 		} else {
 			None
-		}
-	};
+		};
 
 	let (_pdu, mut pdu_json) = services
 		.rooms
@@ -143,67 +119,35 @@ pub(crate) async fn create_join_event_template_route(
 	})
 }
 
-/// Checks whether the given user can join the given room via a restricted join.
+/// Checks whether the given user can join the given room via a restricted join,
+/// which they need not when they are already joined or invited to it.
 pub(crate) async fn user_can_perform_restricted_join(
 	services: &Services,
 	user_id: &UserId,
 	room_id: &RoomId,
 	room_version_id: &RoomVersionId,
 ) -> Result<bool> {
-	use RoomVersionId::*;
-
-	// restricted rooms are not supported on <=v7
-	if matches!(room_version_id, V1 | V2 | V3 | V4 | V5 | V6 | V7) {
-		return Ok(false);
-	}
-
-	if services.rooms.state_cache.is_joined(user_id, room_id).await {
-		// joining user is already joined, there is nothing we need to do
+	let state_cache = &services.rooms.state_cache;
+	if state_cache.is_joined(user_id, room_id).await
+		|| state_cache.is_invited(user_id, room_id).await
+	{
 		return Ok(false);
 	}
 
-	let Ok(join_rules_event_content) = services
+	match services
 		.rooms
 		.state_accessor
-		.room_state_get_content::<RoomJoinRulesEventContent>(
-			room_id,
-			&StateEventType::RoomJoinRules,
-			"",
-		)
-		.await
-	else {
-		return Ok(false);
-	};
-
-	let (JoinRule::Restricted(r) | JoinRule::KnockRestricted(r)) =
-		join_rules_event_content.join_rule
-	else {
-		return Ok(false);
-	};
-
-	if r.allow.is_empty() {
-		debug_info!("{room_id} is restricted but the allow key is empty");
-		return Ok(false);
-	}
-
-	if r.allow
-		.iter()
-		.filter_map(|rule| {
-			if let AllowRule::RoomMembership(membership) = rule {
-				Some(membership)
-			} else {
-				None
-			}
-		})
-		.stream()
-		.any(|m| services.rooms.state_cache.is_joined(user_id, &m.room_id))
+		.restricted_join(user_id, room_id, room_version_id)
 		.await
 	{
-		Ok(true)
-	} else {
-		Err!(Request(UnableToAuthorizeJoin(
+		| RestrictedJoin::Unrestricted => Ok(false),
+		| RestrictedJoin::Allowed(allow_room) => {
+			debug_info!("{user_id} may join {room_id} as a member of {allow_room}");
+			Ok(true)
+		},
+		| RestrictedJoin::Denied => Err!(Request(UnableToAuthorizeJoin(
 			"Joining user is not known to be in any required room."
-		)))
+		))),
 	}
 }
 
@@ -10,16 +10,16 @@
 	trace,
 	utils::{
 		IterStream, ReadyExt, millis_since_unix_epoch,
-		stream::{BroadbandExt, TryBroadbandExt, automatic_width},
+		stream::{BroadbandExt, automatic_width},
 	},
 	warn,
 };
 use conduwuit_service::{
-	Services,
+	Services, federation,
+	rooms::event_handler::Delivery,
 	sending::{EDU_LIMIT, PDU_LIMIT},
 };
-use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
-use itertools::Itertools;
+use futures::{FutureExt, Stream, StreamExt, TryStreamExt};
 use ruma::{
 	CanonicalJsonObject, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, ServerName, UserId,
 	api::{
@@ -96,15 +96,14 @@ pub(crate) async fn send_transaction_message_route(
 		.inspect_err(|e| debug_warn!("Could not parse PDU: {e}"))
 		.ready_filter_map(Result::ok);
 
-	let edus = body
-		.edus
-		.iter()
-		.map(|edu| edu.json().get())
-		.map(serde_json::from_str)
-		.filter_map(Result::ok)
+	let edus = services
+		.federation
+		.accept_edus(body.origin(), &body.edus)
+		.into_iter()
 		.stream();
 
 	let results = handle(&services, &client, body.origin(), txn_start_time, pdus, edus).await?;
+	services.sending.exchanged(body.origin());
 
 	debug!(
 		pdus = body.pdus.len(),
@@ -138,36 +137,30 @@ async fn handle(
 	pdus: impl Stream<Item = Pdu> + Send,
 	edus: impl Stream<Item = Edu> + Send,
 ) -> Result<ResolvedMap> {
-	// group pdus by room
-	let pdus = pdus
+	// rooms are evaluated concurrently, each room's pdus in order
+	let pdus: Vec<_> = pdus
+		.map(|(room_id, event_id, value)| (room_id, (event_id, value)))
 		.collect()
-		.map(|mut pdus: Vec<_>| {
-			pdus.sort_by(|(room_a, ..), (room_b, ..)| room_a.cmp(room_b));
-			pdus.into_iter()
-				.into_grouping_map_by(|(room_id, ..)| room_id.clone())
-				.collect()
-		})
 		.await;
 
-	// we can evaluate rooms concurrently
-	let results: ResolvedMap = pdus
-		.into_iter()
-		.try_stream()
-		.broad_and_then(|(room_id, pdus): (_, Vec<_>)| {
-			handle_room(services, client, origin, started, room_id, pdus.into_iter())
-				.map_ok(Vec::into_iter)
-				.map_ok(IterStream::try_stream)
+	let (results, slowest_room) =
+		federation::handle_by_room(pdus, automatic_width(), |room_id, pdus| {
+			handle_room(services, client, origin, started, room_id, pdus)
 		})
-		.try_flatten()
-		.try_collect()
 		.boxed()
 		.await?;
 
+	let results: ResolvedMap = results.into_iter().collect();
+
 	// evaluate edus after pdus, at least for now.
 	edus.for_each_concurrent(automatic_width(), |edu| handle_edu(services, client, origin, edu))
 		.boxed()
 		.await;
 
+	services
+		.federation
+		.record_transaction(started.elapsed(), slowest_room);
+
 	Ok(results)
 }
 
@@ -177,7 +170,7 @@ async fn handle_room(
 	origin: &ServerName,
 	txn_start_time: Instant,
 	room_id: OwnedRoomId,
-	pdus: impl Iterator<Item = Pdu> + Send,
+	pdus: Vec<(OwnedEventId, CanonicalJsonObject)>,
 ) -> Result<Vec<(OwnedEventId, Result)>> {
 	let _room_lock = services
 		.rooms
@@ -186,15 +179,34 @@ async fn handle_room(
 		.lock(&room_id)
 		.await;
 
+	// check the signatures of all of the room's events at once before handling
+	// them individually; failures are left to surface per-event below.
+	if let Ok(room_version) = services.rooms.state.get_room_version(&room_id).await {
+		services
+			.server_keys
+			.verify_events(origin, &room_version, pdus.iter().map(|(_, value)| value))
+			.await
+			.log_err()
+			.ok();
+	}
+
 	let room_id = &room_id;
-	pdus.try_stream()
-		.and_then(|(_, event_id, value)| async move {
+	pdus.into_iter()
+		.try_stream()
+		.and_then(|(event_id, value)| async move {
 			services.server.check_running()?;
 			let pdu_start_time = Instant::now();
 			let result = services
 				.rooms
 				.event_handler
-				.handle_incoming_pdu(origin, room_id, &event_id, value, true)
+				.handle_incoming_pdu(
+					origin,
+					room_id,
+					&event_id,
+					value,
+					true,
+					Delivery::Transaction,
+				)
 				.await
 				.map(|_| ());
 
@@ -315,64 +327,60 @@ async fn handle_edu_receipt_room(
 		return;
 	}
 
-	let room_id = &room_id;
-	room_updates
+	if !services
+		.rooms
+		.state_cache
+		.server_in_room(origin, &room_id)
+		.await
+	{
+		debug_warn!(
+			%room_id, %origin,
+			"received read receipt EDU from server who does not have a member in the room",
+		);
+		return;
+	}
+
+	// each receipt replaces the user's previous one, so only the last event of
+	// each user is kept, and the room is written and flushed once.
+	let receipts: Vec<_> = room_updates
 		.read
 		.into_iter()
-		.stream()
-		.for_each_concurrent(automatic_width(), |(user_id, user_updates)| async move {
-			handle_edu_receipt_room_user(services, origin, room_id, &user_id, user_updates).await;
+		.filter_map(|(user_id, user_updates)| {
+			handle_edu_receipt_room_user(origin, &room_id, user_id, user_updates)
 		})
+		.collect();
+
+	services
+		.rooms
+		.read_receipt
+		.readreceipts_update(&room_id, &receipts)
 		.await;
 }
 
-async fn handle_edu_receipt_room_user(
-	services: &Services,
+fn handle_edu_receipt_room_user(
 	origin: &ServerName,
 	room_id: &RoomId,
-	user_id: &UserId,
+	user_id: OwnedUserId,
 	user_updates: ReceiptData,
-) {
+) -> Option<(OwnedUserId, ReceiptEvent)> {
 	if user_id.server_name() != origin {
 		debug_warn!(
 			%user_id, %origin,
 			"received read receipt EDU for user not belonging to origin"
 		);
-		return;
+		return None;
 	}
 
-	if !services
-		.rooms
-		.state_cache
-		.server_in_room(origin, room_id)
-		.await
-	{
-		debug_warn!(
-			%user_id, %room_id, %origin,
-			"received read receipt EDU from server who does not have a member in the room",
-		);
-		return;
-	}
+	let event_id = user_updates.event_ids.into_iter().next_back()?;
+	let user_data = [(user_id.clone(), user_updates.data)];
+	let receipts = [(ReceiptType::Read, BTreeMap::from(user_data))];
+	let content = [(event_id, BTreeMap::from(receipts))];
+	let event = ReceiptEvent {
+		content: ReceiptEventContent(content.into()),
+		room_id: room_id.to_owned(),
+	};
 
-	let data = &user_updates.data;
-	user_updates
-		.event_ids
-		.into_iter()
-		.stream()
-		.for_each_concurrent(automatic_width(), |event_id| async move {
-			let user_data = [(user_id.to_owned(), data.clone())];
-			let receipts = [(ReceiptType::Read, BTreeMap::from(user_data))];
-			let content = [(event_id.clone(), BTreeMap::from(receipts))];
-			services
-				.rooms
-				.read_receipt
-				.readreceipt_update(user_id, room_id, &ReceiptEvent {
-					content: ReceiptEventContent(content.into()),
-					room_id: room_id.to_owned(),
-				})
-				.await;
-		})
-		.await;
+	Some((user_id, event))
 }
 
 async fn handle_edu_typing(
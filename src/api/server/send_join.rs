@@ -9,7 +9,7 @@
 	utils::stream::{IterStream, TryBroadbandExt},
 	warn,
 };
-use conduwuit_service::Services;
+use conduwuit_service::{Services, rooms::event_handler::Delivery};
 use futures::{FutureExt, StreamExt, TryStreamExt};
 use ruma::{
 	CanonicalJsonValue, OwnedEventId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId,
@@ -204,7 +204,14 @@ async fn create_join_event(
 	let pdu_id = services
 		.rooms
 		.event_handler
-		.handle_incoming_pdu(&origin, room_id, &event_id, value.clone(), true)
+		.handle_incoming_pdu(
+			&origin,
+			room_id,
+			&event_id,
+			value.clone(),
+			true,
+			Delivery::Membership,
+		)
 		.boxed()
 		.await?
 		.ok_or_else(|| err!(Request(InvalidParam("Could not accept as timeline event."))))?;
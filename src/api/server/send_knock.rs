@@ -4,10 +4,10 @@
 	matrix::pdu::{PduEvent, gen_event_id_canonical_json},
 	warn,
 };
+use conduwuit_service::rooms::event_handler::Delivery;
 use futures::FutureExt;
 use ruma::{
 	OwnedServerName, OwnedUserId,
-	RoomVersionId::*,
 	api::federation::knock::send_knock,
 	events::{
 		StateEventType,
@@ -66,11 +66,11 @@ pub(crate) async fn create_knock_event_v1_route(
 		.acl_check(body.origin(), &body.room_id)
 		.await?;
 
-	let room_version_id = services.rooms.state.get_room_version(&body.room_id).await?;
-
-	if matches!(room_version_id, V1 | V2 | V3 | V4 | V5 | V6) {
-		return Err!(Request(Forbidden("Room version does not support knocking.")));
-	}
+	let room_version_id = services
+		.rooms
+		.state_accessor
+		.check_knockable(&body.room_id)
+		.await?;
 
 	let Ok((event_id, value)) = gen_event_id_canonical_json(&body.pdu, &room_version_id) else {
 		// Event could not be converted to canonical json
@@ -168,7 +168,14 @@ pub(crate) async fn create_knock_event_v1_route(
 	let pdu_id = services
 		.rooms
 		.event_handler
-		.handle_incoming_pdu(&origin, &body.room_id, &event_id, value.clone(), true)
+		.handle_incoming_pdu(
+			&origin,
+			&body.room_id,
+			&event_id,
+			value.clone(),
+			true,
+			Delivery::Membership,
+		)
 		.boxed()
 		.await?
 		.ok_or_else(|| err!(Request(InvalidParam("Could not accept as timeline event."))))?;
@@ -2,7 +2,7 @@
 
 use axum::extract::State;
 use conduwuit::{Err, Result, err, matrix::pdu::gen_event_id_canonical_json};
-use conduwuit_service::Services;
+use conduwuit_service::{Services, rooms::event_handler::Delivery};
 use futures::FutureExt;
 use ruma::{
 	OwnedRoomId, OwnedUserId, RoomId, ServerName,
@@ -152,7 +152,7 @@ async fn create_leave_event(
 	let pdu_id = services
 		.rooms
 		.event_handler
-		.handle_incoming_pdu(origin, room_id, &event_id, value, true)
+		.handle_incoming_pdu(origin, room_id, &event_id, value, true, Delivery::Membership)
 		.boxed()
 		.await?
 		.ok_or_else(|| err!(Request(InvalidParam("Could not accept as timeline event."))))?;
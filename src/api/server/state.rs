@@ -1,9 +1,7 @@
-use std::{borrow::Borrow, iter::once};
-
 use axum::extract::State;
-use conduwuit::{Result, at, err, utils::IterStream};
-use futures::{FutureExt, StreamExt, TryStreamExt};
-use ruma::{OwnedEventId, api::federation::event::get_room_state};
+use conduwuit::{Result, utils::IterStream};
+use futures::{FutureExt, TryStreamExt};
+use ruma::api::federation::event::get_room_state;
 
 use super::AccessCheck;
 use crate::Ruma;
@@ -24,22 +22,13 @@ pub(crate) async fn get_room_state_route(
 	.check()
 	.await?;
 
-	let shortstatehash = services
-		.rooms
-		.state_accessor
-		.pdu_shortstatehash(&body.event_id)
-		.await
-		.map_err(|_| err!(Request(NotFound("PDU state not found."))))?;
-
-	let state_ids: Vec<OwnedEventId> = services
-		.rooms
-		.state_accessor
-		.state_full_ids(shortstatehash)
-		.map(at!(1))
-		.collect()
-		.await;
+	let state_ids = services
+		.federation
+		.state_ids_at(&body.room_id, &body.event_id)
+		.await?;
 
 	let pdus = state_ids
+		.pdu_ids
 		.iter()
 		.try_stream()
 		.and_then(|id| services.rooms.timeline.get_pdu_json(id))
@@ -52,11 +41,11 @@ pub(crate) async fn get_room_state_route(
 		.try_collect()
 		.await?;
 
-	let auth_chain = services
-		.rooms
-		.auth_chain
-		.event_ids_iter(&body.room_id, once(body.event_id.borrow()))
-		.and_then(|id| async move { services.rooms.timeline.get_pdu_json(&id).await })
+	let auth_chain = state_ids
+		.auth_chain_ids
+		.iter()
+		.try_stream()
+		.and_then(|id| services.rooms.timeline.get_pdu_json(id))
 		.and_then(|pdu| {
 			services
 				.sending
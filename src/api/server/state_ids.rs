@@ -1,9 +1,6 @@
-use std::{borrow::Borrow, iter::once};
-
 use axum::extract::State;
-use conduwuit::{Result, at, err};
-use futures::{StreamExt, TryStreamExt};
-use ruma::{OwnedEventId, api::federation::event::get_room_state_ids};
+use conduwuit::Result;
+use ruma::api::federation::event::get_room_state_ids;
 
 use super::AccessCheck;
 use crate::Ruma;
@@ -25,27 +22,13 @@ pub(crate) async fn get_room_state_ids_route(
 	.check()
 	.await?;
 
-	let shortstatehash = services
-		.rooms
-		.state_accessor
-		.pdu_shortstatehash(&body.event_id)
-		.await
-		.map_err(|_| err!(Request(NotFound("Pdu state not found."))))?;
-
-	let pdu_ids: Vec<OwnedEventId> = services
-		.rooms
-		.state_accessor
-		.state_full_ids(shortstatehash)
-		.map(at!(1))
-		.collect()
-		.await;
-
-	let auth_chain_ids = services
-		.rooms
-		.auth_chain
-		.event_ids_iter(&body.room_id, once(body.event_id.borrow()))
-		.try_collect()
+	let state_ids = services
+		.federation
+		.state_ids_at(&body.room_id, &body.event_id)
 		.await?;
 
+	let pdu_ids = state_ids.pdu_ids.clone();
+	let auth_chain_ids = state_ids.auth_chain_ids.clone();
+
 	Ok(get_room_state_ids::v1::Response { auth_chain_ids, pdu_ids })
 }
@@ -0,0 +1,80 @@
+use axum::extract::State;
+use conduwuit::{
+	Err, Result,
+	utils::stream::{ReadyExt, TryIgnore},
+};
+use futures::{StreamExt, pin_mut};
+use ruma::{
+	MilliSecondsSinceUnixEpoch,
+	api::{Direction, federation::event::get_event_by_timestamp},
+};
+
+use super::AccessCheck;
+use crate::Ruma;
+
+/// Most events of the timeline examined for a request, nearest the end it is
+/// searched from.
+const MAX_EVENTS: usize = 4096;
+
+/// # `GET /_matrix/federation/v1/timestamp_to_event/{roomId}`
+///
+/// Finds the event nearest the given timestamp, either at or before it going
+/// backwards, or at or after it going forwards, among the events the
+/// requesting server may see.
+///
+/// - Events are taken in timeline order, so with clocks out of step the event
+///   found may not be the very nearest
+/// - No more than [`MAX_EVENTS`] are examined from the end of the timeline
+///   searched from, so timestamps further off are not found
+pub(crate) async fn get_event_by_timestamp_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_event_by_timestamp::v1::Request>,
+) -> Result<get_event_by_timestamp::v1::Response> {
+	AccessCheck {
+		services: &services,
+		origin: body.origin(),
+		room_id: &body.room_id,
+		event_id: None,
+	}
+	.check()
+	.await?;
+
+	let ts = body.ts.get();
+	let pdus = match body.dir {
+		| Direction::Backward => services
+			.rooms
+			.timeline
+			.pdus_rev(None, &body.room_id, None)
+			.ignore_err()
+			.take(MAX_EVENTS)
+			.ready_filter(move |(_, pdu)| pdu.origin_server_ts <= ts)
+			.left_stream(),
+		| Direction::Forward => services
+			.rooms
+			.timeline
+			.pdus(None, &body.room_id, None)
+			.ignore_err()
+			.take(MAX_EVENTS)
+			.ready_filter(move |(_, pdu)| pdu.origin_server_ts >= ts)
+			.right_stream(),
+	};
+
+	let visible = pdus.filter_map(|(_, pdu)| async move {
+		services
+			.rooms
+			.state_accessor
+			.server_can_see_event(body.origin(), &body.room_id, &pdu.event_id)
+			.await
+			.then_some(pdu)
+	});
+
+	pin_mut!(visible);
+	let Some(pdu) = visible.next().await else {
+		return Err!(Request(NotFound("No event found near the given timestamp.")));
+	};
+
+	Ok(get_event_by_timestamp::v1::Response {
+		event_id: pdu.event_id,
+		origin_server_ts: MilliSecondsSinceUnixEpoch(pdu.origin_server_ts),
+	})
+}
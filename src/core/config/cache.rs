@@ -0,0 +1,118 @@
+//! Sizing of the in-memory caches from the memory available to the server.
+
+use figment::Figment;
+
+use super::Config;
+use crate::utils::sys::memory;
+
+/// Outcome of sizing the caches from available memory.
+#[derive(Clone, Debug)]
+pub struct AutoSize {
+	/// Memory available to the server in bytes.
+	pub memory: u64,
+
+	/// Share of `memory` divided between the caches, in bytes.
+	pub budget: u64,
+
+	/// Caches which were sized, with the value assigned. Caches configured
+	/// explicitly are absent.
+	pub sizes: Vec<(&'static str, f64)>,
+}
+
+/// A cache which takes part in auto-sizing.
+struct Cache {
+	/// Name of the config option.
+	name: &'static str,
+
+	/// Relative share of the budget.
+	weight: u32,
+
+	/// Estimated bytes per unit of the option's value.
+	unit: u64,
+
+	get: fn(&Config) -> f64,
+	set: fn(&mut Config, f64),
+}
+
+macro_rules! count {
+	($name:ident, $weight:expr, $unit:expr) => {
+		Cache {
+			name: stringify!($name),
+			weight: $weight,
+			unit: $unit,
+			get: |config| f64::from(config.$name),
+			set: |config, value| config.$name = to_u32(value),
+		}
+	};
+}
+
+/// The database row cache and the state info cache absorb most lookups and
+/// are given the largest shares.
+const CACHES: &[Cache] = &[
+	Cache {
+		name: "db_cache_capacity_mb",
+		weight: 40,
+		unit: 1024 * 1024,
+		get: |config| config.db_cache_capacity_mb,
+		set: |config, value| config.db_cache_capacity_mb = value.floor(),
+	},
+	count!(stateinfo_cache_capacity, 20, 64 * 1024),
+	count!(pdu_cache_capacity, 10, 2048),
+	count!(auth_chain_cache_capacity, 8, 512),
+	count!(shorteventid_cache_capacity, 2, 128),
+	count!(eventidshort_cache_capacity, 2, 128),
+	count!(eventid_pdu_cache_capacity, 2, 128),
+	count!(shortstatekey_cache_capacity, 2, 128),
+	count!(statekeyshort_cache_capacity, 2, 128),
+	count!(servernameevent_data_cache_capacity, 2, 256),
+	count!(roomid_spacehierarchy_cache_capacity, 2, 4096),
+	count!(stateids_cache_capacity, 2, 16 * 1024),
+	count!(verified_events_cache_capacity, 1, 128),
+];
+
+/// Replaces the capacities of caches not set in `raw` with a share of the
+/// available memory when `cache_auto_size` is enabled.
+pub(super) fn apply(config: &mut Config, raw: &Figment) {
+	config.auto_size = config
+		.cache_auto_size
+		.then(memory::available)
+		.flatten()
+		.map(|memory| size(config, raw, memory));
+}
+
+fn size(config: &mut Config, raw: &Figment, memory: u64) -> AutoSize {
+	let fraction = config.cache_auto_size_fraction.clamp(0.0, 1.0);
+	let budget = to_f64(memory) * fraction;
+	let total: u32 = CACHES.iter().map(|cache| cache.weight).sum();
+
+	let sizes = CACHES
+		.iter()
+		.filter(|cache| !raw.contains(cache.name))
+		.map(|cache| {
+			let share = budget * f64::from(cache.weight) / f64::from(total);
+			let value = (share / to_f64(cache.unit)).max(1.0);
+			(cache.set)(config, value);
+			(cache.name, (cache.get)(config))
+		})
+		.collect();
+
+	AutoSize { memory, budget: to_u64(budget), sizes }
+}
+
+/// The capacity in effect for each cache taking part in auto-sizing.
+#[must_use]
+pub fn capacities(config: &Config) -> Vec<(&'static str, f64)> {
+	CACHES
+		.iter()
+		.map(|cache| (cache.name, (cache.get)(config)))
+		.collect()
+}
+
+#[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+fn to_f64(value: u64) -> f64 { value as f64 }
+
+#[allow(clippy::as_conversions, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn to_u64(value: f64) -> u64 { value as u64 }
+
+#[allow(clippy::as_conversions, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn to_u32(value: f64) -> u32 { value.min(f64::from(u32::MAX)) as u32 }
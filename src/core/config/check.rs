@@ -4,7 +4,10 @@
 use figment::Figment;
 
 use super::DEPRECATED_KEYS;
-use crate::{Config, Err, Result, Server, debug, debug_info, debug_warn, error, warn};
+use crate::{
+	Config, Err, Result, Server, debug, debug_info, debug_warn, error, info,
+	utils::bytes::pretty, warn,
+};
 
 /// Performs check() with additional checks specific to reloading old config
 /// with new config.
@@ -19,6 +22,13 @@ pub fn reload(old: &Config, new: &Config) -> Result {
 		));
 	}
 
+	if !new.virtual_hosts.keys().eq(old.virtual_hosts.keys()) {
+		return Err!(Config(
+			"virtual_hosts",
+			"Adding or removing virtual hosts requires a restart."
+		));
+	}
+
 	Ok(())
 }
 
@@ -42,6 +52,17 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
+	if !(config.cache_auto_size_fraction > 0.0 && config.cache_auto_size_fraction <= 1.0) {
+		return Err!(Config(
+			"cache_auto_size_fraction",
+			"Must be greater than 0.0 and no more than 1.0."
+		));
+	}
+
+	if config.cache_auto_size {
+		log_auto_size(config);
+	}
+
 	if cfg!(all(feature = "hardened_malloc", feature = "jemalloc", not(target_env = "msvc"))) {
 		debug_warn!(
 			"hardened_malloc and jemalloc compile-time features are both enabled, this causes \
@@ -57,6 +78,13 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
+	if config.email.smtp_host.is_some() && config.email.from.is_none() {
+		return Err!(Config(
+			"email.from",
+			"A sender is required to send email through smtp_host"
+		));
+	}
+
 	if config.unix_socket_path.is_none() && config.get_bind_hosts().is_empty() {
 		return Err!(Config("address", "No TCP addresses were specified to listen on"));
 	}
@@ -114,6 +142,15 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
+	if config.rocksdb_drop_unknown_columns
+		&& (config.rocksdb_read_only || config.rocksdb_secondary)
+	{
+		return Err!(Config(
+			"rocksdb_drop_unknown_columns",
+			"Columns cannot be dropped while rocksdb_read_only or rocksdb_secondary is enabled."
+		));
+	}
+
 	// yeah, unless the user built a debug build hopefully for local testing only
 	if cfg!(not(debug_assertions)) && config.server_name == "your.server.name" {
 		return Err!(Config(
@@ -261,6 +298,8 @@ pub fn check(config: &Config) -> Result {
 		}
 	}
 
+	config.well_known.check_client_extra()?;
+
 	if !Server::available_room_versions()
 		.any(|(version, _)| version == config.default_room_version)
 	{
@@ -323,3 +362,22 @@ pub(super) fn is_dual_listening(raw_config: &Figment) -> Result<()> {
 
 	Ok(())
 }
+
+fn log_auto_size(config: &Config) {
+	let Some(auto_size) = &config.auto_size else {
+		warn!("Could not determine available memory; cache sizes were not adjusted.");
+		return;
+	};
+
+	let budget = auto_size.budget.try_into().unwrap_or(usize::MAX);
+	let memory = auto_size.memory.try_into().unwrap_or(usize::MAX);
+	info!(
+		budget = pretty(budget),
+		memory = pretty(memory),
+		"Sized caches from available memory",
+	);
+
+	for (name, value) in &auto_size.sizes {
+		info!("{name} = {value}");
+	}
+}
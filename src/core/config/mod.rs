@@ -1,6 +1,12 @@
+pub mod cache;
 pub mod check;
 pub mod manager;
 pub mod proxy;
+mod remote_servers;
+#[cfg(test)]
+mod tests;
+mod virtual_hosts;
+mod well_known;
 
 use std::{
 	collections::{BTreeMap, BTreeSet},
@@ -20,7 +26,7 @@
 	OwnedRoomOrAliasId, OwnedServerName, OwnedUserId, RoomVersionId,
 	api::client::discovery::discover_support::ContactRole,
 };
-use serde::{Deserialize, de::IgnoredAny};
+use serde::{Deserialize, Serialize, de::IgnoredAny};
 use url::Url;
 
 use self::proxy::ProxyConfig;
@@ -52,7 +58,7 @@
 ### For more information, see:
 ### https://conduwuit.puppyirl.gay/configuration.html
 "#,
-	ignore = "catchall well_known tls blurhashing allow_invalid_tls_certificates_yes_i_know_what_the_fuck_i_am_doing_with_this_and_i_know_this_is_insecure"
+	ignore = "catchall auto_size well_known tls blurhashing email allow_invalid_tls_certificates_yes_i_know_what_the_fuck_i_am_doing_with_this_and_i_know_this_is_insecure"
 )]
 pub struct Config {
 	/// The server_name is the pretty name of this server. It is used as a
@@ -129,6 +135,30 @@ pub struct Config {
 	/// example: "/var/lib/conduwuit"
 	pub database_path: PathBuf,
 
+	/// Additional server names to host from this process, each with its own
+	/// database, signing key, media and admin room. Requests are routed to a
+	/// virtual host by the destination of their X-Matrix authorization, or
+	/// otherwise by their Host header matching its server_name,
+	/// `well_known.server` or the host of `well_known.client`. Anything else
+	/// is served by the main server_name.
+	///
+	/// Each virtual host is configured by a table of options overriding those
+	/// of the main server; `database_path` is required and must be distinct,
+	/// as must `database_backup_path` when backups are enabled. Listener
+	/// options (address, port, unix_socket_path and tls) are shared by all
+	/// virtual hosts and cannot be overridden. Changes to this option require
+	/// a restart.
+	///
+	/// For example, to host "second.example" alongside the main server:
+	///
+	/// [global.virtual_hosts."second.example"]
+	/// database_path = "/var/lib/conduwuit-second"
+	/// allow_registration = false
+	///
+	/// default: {}
+	#[serde(default)]
+	pub virtual_hosts: BTreeMap<OwnedServerName, BTreeMap<String, FigmentValue>>,
+
 	/// conduwuit supports online database backups using RocksDB's Backup engine
 	/// API. To use this, set a database backup path that conduwuit can write
 	/// to.
@@ -188,6 +218,26 @@ pub struct Config {
 	)]
 	pub cache_capacity_modifier: f64,
 
+	/// Size the in-memory caches and the database cache from the memory
+	/// available to the server instead of the CPU core count. The memory
+	/// limit of the server's cgroup is respected when running in a container.
+	///
+	/// Caches given a value explicitly in the config are left as configured.
+	/// "cache_capacity_modifier" is still applied to the computed sizes.
+	///
+	/// The computed sizes are logged at startup and shown by
+	/// `!admin server status`.
+	#[serde(default)]
+	pub cache_auto_size: bool,
+
+	/// Share of the available memory divided between the caches when
+	/// "cache_auto_size" is enabled. The database row cache and the state info
+	/// cache receive the largest shares.
+	///
+	/// default: 0.25
+	#[serde(default = "default_cache_auto_size_fraction")]
+	pub cache_auto_size_fraction: f64,
+
 	/// Set this to any float value in megabytes for conduwuit to tell the
 	/// database engine that this much memory is available for database read
 	/// caches.
@@ -228,6 +278,17 @@ pub struct Config {
 	#[serde(default = "default_auth_chain_cache_capacity")]
 	pub auth_chain_cache_capacity: u32,
 
+	/// Approximate number of bytes the auth chain cache may hold in memory.
+	/// Auth chains vary from a handful of events to hundreds of thousands, so
+	/// a byte budget bounds its memory far better than a count of entries.
+	/// The least recently used chains are evicted once it is exceeded, and a
+	/// single chain larger than the budget is not cached at all.
+	///
+	/// When unset, only "auth_chain_cache_capacity" limits the cache.
+	///
+	/// example: 268435456
+	pub auth_chain_cache_bytes: Option<usize>,
+
 	/// default: varies by system
 	#[serde(default = "default_shorteventid_cache_capacity")]
 	pub shorteventid_cache_capacity: u32,
@@ -256,10 +317,75 @@ pub struct Config {
 	#[serde(default = "default_stateinfo_cache_capacity")]
 	pub stateinfo_cache_capacity: u32,
 
+	/// Number of state changes in a room after which its next state is saved
+	/// as a full snapshot instead of as a diff to its previous state, so that
+	/// loading the state of busy rooms does not start from an ancient full
+	/// state with many changes replayed on top. 0 disables this.
+	///
+	/// default: 1000
+	#[serde(default = "default_state_snapshot_changes")]
+	pub state_snapshot_changes: usize,
+
+	/// Number of state entries by which a room's state may differ from the
+	/// full state its diffs are based on before its next state is saved as a
+	/// full snapshot. 0 disables this.
+	///
+	/// default: 5000
+	#[serde(default = "default_state_snapshot_drift")]
+	pub state_snapshot_drift: usize,
+
+	/// Minimum time in seconds between two full state snapshots of the same
+	/// room, so that rooms with a lot of state changes are not snapshotted on
+	/// every change.
+	///
+	/// default: 60
+	#[serde(default = "default_state_snapshot_min_interval")]
+	pub state_snapshot_min_interval: u64,
+
 	/// default: varies by system
 	#[serde(default = "default_roomid_spacehierarchy_cache_capacity")]
 	pub roomid_spacehierarchy_cache_capacity: u32,
 
+	/// Time in seconds a room's space hierarchy summary is cached, including
+	/// those of remote rooms fetched over federation and the failure to fetch
+	/// one. Clients paginating through a space keep being served the summaries
+	/// they started with even once expired.
+	///
+	/// default: 300
+	#[serde(default = "default_roomid_spacehierarchy_cache_ttl")]
+	pub roomid_spacehierarchy_cache_ttl: u64,
+
+	/// Maximum number of federation `/state` and `/state_ids` responses to keep
+	/// in memory. Only the event IDs are cached and entries are shared between
+	/// all requesting servers.
+	///
+	/// default: varies by system
+	#[serde(default = "default_stateids_cache_capacity")]
+	pub stateids_cache_capacity: u32,
+
+	/// Time in seconds a cached federation `/state` or `/state_ids` response
+	/// is served before it is recomputed.
+	///
+	/// default: 60
+	#[serde(default = "default_stateids_cache_ttl")]
+	pub stateids_cache_ttl: u64,
+
+	/// Maximum entries in the cache of recently verified event signatures.
+	/// Entries are kept for a short time only and spare re-verifying an
+	/// event received more than once, e.g. in a transaction and again when
+	/// fetched as a missing event.
+	///
+	/// default: varies by system
+	#[serde(default = "default_verified_events_cache_capacity")]
+	pub verified_events_cache_capacity: u32,
+
+	/// Warm the short ID caches at startup by reading the state of the most
+	/// recently changed rooms, up to the capacities of
+	/// "shorteventid_cache_capacity" and "shortstatekey_cache_capacity".
+	/// Warming runs in the background and gives up after a couple of seconds.
+	#[serde(default)]
+	pub cache_warmup: bool,
+
 	/// Maximum entries stored in DNS memory-cache. The size of an entry may
 	/// vary so please take care if raising this value excessively. Only
 	/// decrease this when using an external DNS cache. Please note that
@@ -362,6 +488,89 @@ pub struct Config {
 	#[serde(default = "default_max_fetch_prev_events")]
 	pub max_fetch_prev_events: u16,
 
+	/// Largest auth chain served to another server asking for the auth events
+	/// of one event. Longer chains are refused and logged, as no room should
+	/// have one anywhere near this long.
+	///
+	/// default: 50000
+	#[serde(default = "default_max_auth_chain_response")]
+	pub max_auth_chain_response: usize,
+
+	/// Most distinct state keys a local user may set for one event type in
+	/// one room per hour. Sending state under the same key again is not
+	/// counted. Stops a user with state permission bloating the room's state
+	/// with junk keys. 0 means no limit.
+	///
+	/// default: 100
+	#[serde(default = "default_state_keys_per_sender_hourly")]
+	pub state_keys_per_sender_hourly: usize,
+
+	/// Distinct state keys a remote user may set in one room within
+	/// `federation_state_flood_window` before being taken for flooding the
+	/// room's state. The admin room is notified, and with
+	/// `federation_state_flood_soft_fail` their further state events are
+	/// soft-failed until the window passes. 0 disables the check.
+	///
+	/// default: 1000
+	#[serde(default = "default_federation_state_keys_per_sender")]
+	pub federation_state_keys_per_sender: usize,
+
+	/// Window (seconds) over which `federation_state_keys_per_sender` is
+	/// counted.
+	///
+	/// default: 600
+	#[serde(default = "default_federation_state_flood_window")]
+	pub federation_state_flood_window: u64,
+
+	/// Soft-fail state events from remote users found flooding a room's state,
+	/// rather than only notifying the admin room.
+	#[serde(default)]
+	pub federation_state_flood_soft_fail: bool,
+
+	/// State event types never counted towards the state flood limits, as
+	/// rooms legitimately have many of them.
+	///
+	/// default: ["m.room.member", "m.room.aliases"]
+	#[serde(default = "default_state_flood_allowed_types")]
+	pub state_flood_allowed_types: Vec<String>,
+
+	/// Notice the server user sends in a room when it is frozen with the
+	/// `!admin rooms freeze` command, if the server user is in the room. Set
+	/// to an empty string to freeze rooms silently.
+	///
+	/// default: "This room has been frozen by the server administrators."
+	#[serde(default = "default_room_freeze_notice")]
+	pub room_freeze_notice: String,
+
+	/// Days the daily counts of each room's messages, senders, joins, leaves
+	/// and redactions shown by `!admin rooms activity` are kept. 0 stops
+	/// counting room activity.
+	///
+	/// default: 90
+	#[serde(default = "default_room_activity_retention_days")]
+	pub room_activity_retention_days: u64,
+
+	/// Leave rooms no local user is in out of the room activity counts, so
+	/// nothing is kept about rooms only remote users are in.
+	#[serde(default)]
+	pub room_activity_exclude_remote_rooms: bool,
+
+	/// Most membership changes one request to the batch membership endpoint
+	/// (`/_conduwuit/client/v1/rooms/{roomId}/members/batch`) may make.
+	///
+	/// default: 100
+	#[serde(default = "default_membership_batch_max")]
+	pub membership_batch_max: usize,
+
+	/// Most state events one request to the state batch endpoint
+	/// (`/_conduwuit/client/v1/rooms/{roomId}/state_batch`) may send. The
+	/// whole batch is built and checked under the room's state lock before any
+	/// of it is appended.
+	///
+	/// default: 50
+	#[serde(default = "default_state_batch_max")]
+	pub state_batch_max: usize,
+
 	/// Default/base connection timeout (seconds). This is used only by URL
 	/// previews and update/news endpoint checks.
 	///
@@ -434,6 +643,65 @@ pub struct Config {
 	#[serde(default = "default_federation_idle_per_host")]
 	pub federation_idle_per_host: u16,
 
+	/// Use HTTP/2 with federation destinations which offer it when connecting
+	/// over TLS, so that all requests to a destination share one connection
+	/// instead of each waiting for a connection of its own. Destinations
+	/// which only speak HTTP/1.1 are still talked to over it. Disable to use
+	/// HTTP/1.1 with every destination.
+	#[serde(default = "true_fn")]
+	pub federation_http2: bool,
+
+	/// Interval of the HTTP/2 pings keeping connections to federation
+	/// destinations alive, also while idle, so a connection which broke is
+	/// noticed before a request is sent on it (seconds). 0 disables pings.
+	///
+	/// default: 15
+	#[serde(default = "default_federation_http2_keepalive_interval")]
+	pub federation_http2_keepalive_interval: u64,
+
+	/// Time to wait for the answer to an HTTP/2 keepalive ping before the
+	/// connection is closed (seconds).
+	///
+	/// default: 20
+	#[serde(default = "default_federation_http2_keepalive_timeout")]
+	pub federation_http2_keepalive_timeout: u64,
+
+	/// PEM files of certificate authorities to trust for federation, on top
+	/// of the system's roots. For federating with servers whose certificates
+	/// are issued by a private CA.
+	///
+	/// Changes to this option require a restart.
+	///
+	/// example: ["/etc/conduwuit/private-ca.pem"]
+	///
+	/// default: []
+	#[serde(default)]
+	pub federation_trusted_ca_files: Vec<PathBuf>,
+
+	/// How to trust the certificates of particular federation destinations,
+	/// by their server name. Each may be given a PEM file of a certificate
+	/// authority trusted only for it, and pins: the base64 SHA-256 hashes of
+	/// the public keys (SPKI) its certificate may carry. A certificate with a
+	/// pinned key is accepted whoever issued it.
+	///
+	/// Changes to this option require a restart.
+	///
+	/// For example:
+	///
+	/// [global.federation_tls."internal.example"]
+	/// ca_file = "/etc/conduwuit/internal-ca.pem"
+	/// pins = ["47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="]
+	///
+	/// default: {}
+	#[serde(default)]
+	pub federation_tls: BTreeMap<OwnedServerName, FederationTlsConfig>,
+
+	/// Refuse to connect to pinned federation destinations whose certificate
+	/// does not carry a pinned key. Otherwise such a certificate is still
+	/// accepted if it is trusted the usual way, and a warning is logged.
+	#[serde(default)]
+	pub federation_tls_strict: bool,
+
 	/// Federation sender request timeout (seconds). The time it takes for the
 	/// remote server to process sent transactions can take a while.
 	///
@@ -447,12 +715,56 @@ pub struct Config {
 	#[serde(default = "default_sender_idle_timeout")]
 	pub sender_idle_timeout: u64,
 
-	/// Federation sender transaction retry backoff limit (seconds).
+	/// Retry backoff limit for appservice and push gateway transactions
+	/// (seconds). Federation destinations use `federation_backoff_max`.
 	///
 	/// default: 86400
 	#[serde(default = "default_sender_retry_backoff_limit")]
 	pub sender_retry_backoff_limit: u64,
 
+	/// Delay before retrying a federation destination after its first failed
+	/// transaction (seconds). Each further failure in a row multiplies it by
+	/// `federation_backoff_multiplier`, up to `federation_backoff_max`.
+	///
+	/// Destinations answering with 5xx errors are retried at least hourly, as
+	/// they are usually only busy. Those answering with 4xx errors, or
+	/// refusing our signatures, start further along the curve. The backoff of
+	/// each destination is kept across restarts.
+	///
+	/// default: 30
+	#[serde(default = "default_federation_backoff_initial")]
+	pub federation_backoff_initial: u64,
+
+	/// Longest delay before retrying a federation destination (seconds).
+	///
+	/// default: 86400
+	#[serde(default = "default_federation_backoff_max")]
+	pub federation_backoff_max: u64,
+
+	/// Factor the federation retry delay grows by with each failure in a row.
+	///
+	/// default: 2.0
+	#[serde(default = "default_federation_backoff_multiplier")]
+	pub federation_backoff_multiplier: f64,
+
+	/// Fraction by which each federation retry delay is randomly lengthened
+	/// or shortened, so destinations which failed together are not all
+	/// retried at once.
+	///
+	/// default: 0.2
+	#[serde(default = "default_federation_backoff_jitter")]
+	pub federation_backoff_jitter: f64,
+
+	/// How long every transaction to a federation destination has to fail
+	/// with 403, 410 or missing DNS records before it is reported to the
+	/// admin room as likely defunct (seconds). It is reported once, and counts
+	/// as healthy again as soon as anything is exchanged with it. 0 disables
+	/// this.
+	///
+	/// default: 259200
+	#[serde(default = "default_federation_defunct_window")]
+	pub federation_defunct_window: u64,
+
 	/// Appservice URL request connection timeout. Defaults to 35 seconds as
 	/// generally appservices are hosted within the same network.
 	///
@@ -490,6 +802,47 @@ pub struct Config {
 	#[serde(default = "default_client_response_timeout")]
 	pub client_response_timeout: u64,
 
+	/// Maximum number of messages from one user to one room which are held
+	/// in order of arrival while earlier ones are still being received.
+	/// Messages sent while the queue is full are processed without waiting,
+	/// and may be ordered by when they finish arriving instead. Messages from
+	/// appservices are never queued. Set to 0 to disable.
+	///
+	/// default: 32
+	#[serde(default = "default_send_queue_capacity")]
+	pub send_queue_capacity: usize,
+
+	/// Maximum number of to-device messages in one sync response. A device
+	/// which was offline for long may have many more waiting; the rest are
+	/// sent in the following syncs.
+	///
+	/// default: 100
+	#[serde(default = "default_to_device_sync_limit")]
+	pub to_device_sync_limit: usize,
+
+	/// Number of rooms loaded at once for a sync. An initial sync of an
+	/// account in many rooms is bounded by this. Set to 0 to use the
+	/// "stream_width_default".
+	///
+	/// default: 16
+	#[serde(default = "default_sync_room_concurrency")]
+	pub sync_room_concurrency: usize,
+
+	/// Number of timeline events returned for each room in a sync whose filter
+	/// sets no limit. The spec suggests 10, which clients may rely on.
+	///
+	/// default: 10
+	#[serde(default = "default_sync_timeline_limit_default")]
+	pub sync_timeline_limit_default: usize,
+
+	/// Most timeline events returned for each room in a sync, whatever limit
+	/// the client asks for. A room with more new events is returned as
+	/// limited, and the client can page back through the rest.
+	///
+	/// default: 100
+	#[serde(default = "default_sync_timeline_limit_max")]
+	pub sync_timeline_limit_max: usize,
+
 	/// Grace period for clean shutdown of client requests (seconds).
 	///
 	/// default: 10
@@ -502,6 +855,45 @@ pub struct Config {
 	#[serde(default = "default_sender_shutdown_timeout")]
 	pub sender_shutdown_timeout: u64,
 
+	/// Reject requests of low-priority classes with 503 and a `Retry-After`
+	/// header while the server is overloaded, so that interactive clients and
+	/// inbound federation transactions keep being served. The current state
+	/// is shown by `!admin server status`.
+	#[serde(default)]
+	pub load_shedding: bool,
+
+	/// Number of requests in flight above which the server is considered
+	/// overloaded when `load_shedding` is enabled.
+	///
+	/// default: 2048
+	#[serde(default = "default_load_shedding_max_inflight")]
+	pub load_shedding_max_inflight: usize,
+
+	/// p99 latency in milliseconds of recently completed requests above which
+	/// the server is considered overloaded when `load_shedding` is enabled.
+	/// Syncs are held open on purpose and do not count.
+	///
+	/// default: 5000
+	#[serde(default = "default_load_shedding_p99_ms")]
+	pub load_shedding_p99_ms: u64,
+
+	/// Order in which request classes are shed, lowest priority first. The
+	/// classes are `media`, `federation_read`, `client_sync`,
+	/// `federation_send` and `client_interactive`; unlisted classes are
+	/// ranked above listed ones. The highest-priority class is never shed.
+	/// Empty uses the order shown.
+	///
+	/// default: []
+	#[serde(default)]
+	pub load_shedding_priority: Vec<String>,
+
+	/// Seconds clients are asked to wait in the `Retry-After` header of shed
+	/// requests.
+	///
+	/// default: 5
+	#[serde(default = "default_load_shedding_retry_after_secs")]
+	pub load_shedding_retry_after_secs: u64,
+
 	/// Enables registration. If set to false, no users can register on this
 	/// server.
 	///
@@ -545,11 +937,32 @@ pub struct Config {
 	#[serde(default = "true_fn")]
 	pub allow_encryption: bool,
 
+	/// Refuse message events sent by local users into rooms which have been
+	/// replaced through an `m.room.tombstone`, so that users of clients which
+	/// do not follow upgrades stop splitting the conversation. The error names
+	/// the replacement room for clients to offer joining it. State events,
+	/// including leaving the room, are still allowed.
+	///
+	/// This goes beyond the spec and is disabled by default.
+	#[serde(default)]
+	pub reject_events_in_tombstoned_rooms: bool,
+
+	/// Exempt appservices from "reject_events_in_tombstoned_rooms", for bridges
+	/// which must keep relaying into rooms they have not yet moved on from.
+	#[serde(default)]
+	pub tombstone_exempt_appservices: bool,
+
 	/// Controls whether federation is allowed or not. It is not recommended to
 	/// disable this after the fact due to potential federation breakage.
 	#[serde(default = "true_fn")]
 	pub allow_federation: bool,
 
+	/// Serve a small HTML page at the root of the server naming it, its
+	/// version and linking to its well-known documents. When disabled the
+	/// root only answers with a plain greeting.
+	#[serde(default = "true_fn")]
+	pub index_page: bool,
+
 	/// Allows federation requests to be made to itself
 	///
 	/// This isn't intended and is very likely a bug if federation requests are
@@ -563,6 +976,15 @@ pub struct Config {
 	#[serde(default)]
 	pub forget_forced_upon_leave: bool,
 
+	/// Interval in hours at which the direct message rooms of local users are
+	/// checked for duplicates, several rooms with just the same two people in
+	/// them. Users with duplicates are reported to the admin room, where
+	/// `!admin users dedupe-dms` can merge them. 0 disables this.
+	///
+	/// default: 0
+	#[serde(default)]
+	pub dm_duplicates_check_interval: u64,
+
 	/// Set this to true to require authentication on the normally
 	/// unauthenticated profile retrieval endpoints (GET)
 	/// "/_matrix/client/v3/profile/{userId}".
@@ -795,6 +1217,35 @@ pub struct Config {
 	#[serde(default)]
 	pub log_thread_ids: bool,
 
+	/// Also write logs to this file, with the same filter as the console and
+	/// without colours. The file is reopened on SIGHUP, so it can be rotated
+	/// by logrotate instead of the options below.
+	///
+	/// example: "/var/log/conduwuit/conduwuit.log"
+	pub log_file: Option<PathBuf>,
+
+	/// Rotate the log file once it is larger than this many bytes. Set to 0
+	/// to not rotate by size.
+	///
+	/// default: 104857600
+	#[serde(default = "default_log_file_max_size")]
+	pub log_file_max_size: u64,
+
+	/// Rotate the log file at midnight UTC.
+	#[serde(default)]
+	pub log_file_rotate_daily: bool,
+
+	/// Number of rotated log files to keep, named after the log file with
+	/// ".1" for the newest and so on. Older ones are deleted.
+	///
+	/// default: 7
+	#[serde(default = "default_log_file_keep")]
+	pub log_file_keep: usize,
+
+	/// Compress rotated log files with gzip, adding ".gz" to their names.
+	#[serde(default)]
+	pub log_file_compress: bool,
+
 	/// OpenID token expiration/TTL in seconds.
 	///
 	/// These are the OpenID tokens that are primarily used for Matrix account
@@ -823,6 +1274,74 @@ pub struct Config {
 	#[serde(default = "default_login_token_ttl")]
 	pub login_token_ttl: u64,
 
+	/// Hours for which a deleted device is kept as a tombstone. Its access
+	/// token stops working at once and it is no longer listed, but its queued
+	/// to-device messages and device keys are retained, so that logging in
+	/// again with the same device ID within this time resumes the session
+	/// without losing room keys. Other users are only told the device was
+	/// deleted once it is purged. Set to 0 to delete devices immediately.
+	///
+	/// default: 0
+	#[serde(default)]
+	pub device_deletion_grace_hours: u64,
+
+	/// Days for which an account is valid after registering or renewing.
+	/// Requests of an expired account are refused with
+	/// ORG_MATRIX_EXPIRED_ACCOUNT until it is renewed through the link emailed
+	/// to it, or by an admin. Accounts registered before this was set are valid
+	/// for the full period from the first startup with it set. Set to 0 to
+	/// disable.
+	///
+	/// default: 0
+	#[serde(default)]
+	pub account_validity_days: u64,
+
+	/// Days before an account expires to email its renewal link. The link
+	/// works for 30 days, after which an account still expired is sent a new
+	/// one. Requires email to be configured and the account to have an email
+	/// address set by an admin.
+	///
+	/// default: 7
+	#[serde(default = "default_account_validity_renew_days")]
+	pub account_validity_renew_days: u64,
+
+	/// Never expire the accounts of admins.
+	///
+	/// default: true
+	#[serde(default = "true_fn")]
+	pub account_validity_exempt_admins: bool,
+
+	/// Never expire the accounts of appservice users.
+	///
+	/// default: true
+	#[serde(default = "true_fn")]
+	pub account_validity_exempt_appservices: bool,
+
+	/// Let clients report the encrypted events they fail to decrypt to
+	/// `/_conduwuit/client/v1/uisi_report`, for `!admin debug uisi-stats`.
+	/// Only admins see the reports.
+	#[serde(default)]
+	pub uisi_reports: bool,
+
+	/// Seconds decryption failure reports are kept for.
+	///
+	/// default: 604800
+	#[serde(default = "default_uisi_report_retention")]
+	pub uisi_report_retention: u64,
+
+	/// Most decryption failure reports one user may send per hour. 0 means no
+	/// limit.
+	///
+	/// default: 30
+	#[serde(default = "default_uisi_reports_per_user_hourly")]
+	pub uisi_reports_per_user_hourly: usize,
+
+	/// Most decryption failure reports kept; the oldest are removed past it.
+	///
+	/// default: 100000
+	#[serde(default = "default_uisi_report_max")]
+	pub uisi_report_max: usize,
+
 	/// Static TURN username to provide the client if not using a shared secret
 	/// ("turn_secret"), It is recommended to use a shared secret over static
 	/// credentials.
@@ -1036,6 +1555,35 @@ pub struct Config {
 	#[serde(default = "true_fn")]
 	pub rocksdb_bottommost_compression: bool,
 
+	/// Per-column compression overrides which take precedence over
+	/// `rocksdb_compression_algo` and the compression levels above. Each value
+	/// is an algorithm optionally followed by a level, e.g. "zstd:7"; the level
+	/// applies to the bottommost level as well. Valid algorithms are "zstd",
+	/// "snappy", "zlib", "bz2", "lz4", "lz4hc" and "none". Invalid values
+	/// prevent startup.
+	///
+	/// example: { "pduid_pdu" = "zstd:7", "userroomid_joined" = "none" }
+	///
+	/// default: {}
+	#[serde(default)]
+	pub rocksdb_compression_per_column: BTreeMap<String, String>,
+
+	/// Per-column weights for dividing the block cache of the database, which
+	/// is half of `db_cache_capacity_mb`. A column given shares has a cache of
+	/// its own sized in proportion to them, replacing any cache capacity
+	/// configured for it. The columns without shares which use the common
+	/// cache count as one share each towards its size. These override the
+	/// shares built into the server; set a column to 0 to return it to the
+	/// common cache. Invalid column names are ignored with a warning.
+	///
+	/// The effective sizes are shown by the `database usage` admin command.
+	///
+	/// example: { "eventid_pduid" = 8, "pduid_pdu" = 16 }
+	///
+	/// default: {}
+	#[serde(default)]
+	pub rocksdb_cache_shares_per_column: BTreeMap<String, u32>,
+
 	/// Database recovery mode (for RocksDB WAL corruption).
 	///
 	/// Use this option when the server reports corruption and refuses to start.
@@ -1066,6 +1614,9 @@ pub struct Config {
 	/// For more information on these modes, see:
 	/// https://github.com/facebook/rocksdb/wiki/WAL-Recovery-Modes
 	///
+	/// With mode 2 or 3, a database found corrupt at startup is also repaired
+	/// automatically once, as with `rocksdb_repair`, before opening it again.
+	///
 	/// For more details on recovering a corrupt database, see:
 	/// https://conduwuit.puppyirl.gay/troubleshooting.html#database-corruption
 	///
@@ -1073,6 +1624,21 @@ pub struct Config {
 	#[serde(default = "default_rocksdb_recovery_mode")]
 	pub rocksdb_recovery_mode: u8,
 
+	/// Number of times to retry opening the database while another process
+	/// holds its lock, such as a previous instance still shutting down when
+	/// the service manager restarts the server.
+	///
+	/// default: 5
+	#[serde(default = "default_rocksdb_open_lock_retries")]
+	pub rocksdb_open_lock_retries: u32,
+
+	/// Delay in milliseconds before the first retry of a locked database; it
+	/// doubles for each further retry, up to 30 seconds.
+	///
+	/// default: 1000
+	#[serde(default = "default_rocksdb_open_lock_backoff_ms")]
+	pub rocksdb_open_lock_backoff_ms: u64,
+
 	/// Enables or disables paranoid SST file checks. This can improve RocksDB
 	/// database consistency at a potential performance impact due to further
 	/// safety checks ran.
@@ -1121,6 +1687,34 @@ pub struct Config {
 	#[serde(default)]
 	pub rocksdb_secondary: bool,
 
+	/// Interval in milliseconds at which a `rocksdb_secondary` instance
+	/// catches up with the primary. Reads only see data written by the
+	/// primary as of the last catch-up. Has no effect unless
+	/// `rocksdb_secondary` is enabled.
+	///
+	/// default: 1000
+	#[serde(default = "default_rocksdb_secondary_catchup_interval_ms")]
+	pub rocksdb_secondary_catchup_interval_ms: u64,
+
+	/// Drop column families found in the database which this version of the
+	/// server does not use, such as those left behind by a downgrade, after
+	/// the database is opened. Their data is deleted and cannot be recovered.
+	/// Cannot be combined with `rocksdb_read_only` or `rocksdb_secondary`.
+	///
+	/// One-off cleanup is also possible with the `database drop-column` admin
+	/// command.
+	#[serde(default)]
+	pub rocksdb_drop_unknown_columns: bool,
+
+	/// Look an event up among the most recent events of its room when its
+	/// entry in the event ID index is missing, as old bugs and crashes could
+	/// leave behind, for lookups of events known to be in a room such as those
+	/// relations, receipts and event context refer to. An entry restored is
+	/// logged and counted by the `debug repairs` admin command. The
+	/// `check event-index` admin command restores those of a whole room.
+	#[serde(default = "true_fn")]
+	pub rebuild_missing_event_index: bool,
+
 	/// Enables idle CPU priority for compaction thread. This is not enabled by
 	/// default to prevent compaction from falling too far behind on busy
 	/// systems.
@@ -1159,6 +1753,27 @@ pub struct Config {
 	#[serde(default = "default_rocksdb_stats_level")]
 	pub rocksdb_stats_level: u8,
 
+	/// Interval in seconds at which database statistics are published to the
+	/// server metrics. Block cache, flush, stall and compaction counters are
+	/// only available when "rocksdb_stats_level" enables statistics; memtable
+	/// size and pending compaction bytes are always collected. The hit and
+	/// miss counts of the service caches are published at the same interval,
+	/// as "<cache>_hits", "<cache>_misses" and "<cache>_hit_percent". Set to 0
+	/// to disable collection.
+	///
+	/// default: 60
+	#[serde(default = "default_rocksdb_stats_interval_secs")]
+	pub rocksdb_stats_interval_secs: u64,
+
+	/// Duration in milliseconds after which a single database read, write or
+	/// iterator seek is logged as slow with its column, operation and key
+	/// length, and counted in the "db_slow_ops_<column>" metric. Set to 0 to
+	/// disable.
+	///
+	/// default: 250
+	#[serde(default = "default_db_slow_query_threshold")]
+	pub db_slow_query_threshold: u64,
+
 	/// This is a password that can be configured that will let you login to the
 	/// server bot account (currently `@conduit`) for emergency troubleshooting
 	/// purposes such as recovering/recreating your admin room, or inviting
@@ -1178,6 +1793,20 @@ pub struct Config {
 	#[serde(default = "default_notification_push_path")]
 	pub notification_push_path: String,
 
+	/// Maximum number of keyword (content) push rules a user may have, not
+	/// counting the server-default ones. Each one is matched against every
+	/// message the user receives.
+	///
+	/// default: 100
+	#[serde(default = "default_max_push_keyword_rules")]
+	pub max_push_keyword_rules: usize,
+
+	/// Lower-case the patterns of new push rules when storing them. Patterns
+	/// match case-insensitively either way, this only keeps the stored rules
+	/// uniform for clients which compare them.
+	#[serde(default)]
+	pub push_rule_patterns_lowercase: bool,
+
 	/// Allow local (your server only) presence updates/requests.
 	///
 	/// Note that presence on conduwuit is very fast unlike Synapse's. If using
@@ -1261,6 +1890,42 @@ pub struct Config {
 	#[serde(default = "default_typing_client_timeout_max_s")]
 	pub typing_client_timeout_max_s: u64,
 
+	/// Largest EDU, in bytes, accepted from federation. Larger EDUs are
+	/// dropped one by one; the rest of the transaction is still processed.
+	///
+	/// default: 65536
+	#[serde(default = "default_incoming_edu_max_size")]
+	pub incoming_edu_max_size: usize,
+
+	/// Most read receipts processed from one inbound federation transaction,
+	/// counting each event of each user in each room. Receipts beyond it are
+	/// dropped.
+	///
+	/// default: 1000
+	#[serde(default = "default_incoming_txn_max_receipts")]
+	pub incoming_txn_max_receipts: usize,
+
+	/// Most typing updates processed from one inbound federation transaction.
+	/// Those beyond it are dropped.
+	///
+	/// default: 100
+	#[serde(default = "default_incoming_txn_max_typing")]
+	pub incoming_txn_max_typing: usize,
+
+	/// Most presence updates processed from one inbound federation transaction.
+	/// Those beyond it are dropped.
+	///
+	/// default: 500
+	#[serde(default = "default_incoming_txn_max_presence")]
+	pub incoming_txn_max_presence: usize,
+
+	/// Most device list updates processed from one inbound federation
+	/// transaction. Those beyond it are dropped.
+	///
+	/// default: 500
+	#[serde(default = "default_incoming_txn_max_device_list_updates")]
+	pub incoming_txn_max_device_list_updates: usize,
+
 	/// Set this to true for conduwuit to compress HTTP response bodies using
 	/// zstd. This option does nothing if conduwuit was not built with
 	/// `zstd_compression` feature. Please be aware that enabling HTTP
@@ -1321,6 +1986,14 @@ pub struct Config {
 	#[serde(default = "true_fn")]
 	pub allow_legacy_media: bool,
 
+	/// Allow users to delete the media they uploaded through
+	/// `DELETE /_conduwuit/client/v1/media/{serverName}/{mediaId}`.
+	///
+	/// Deleted media is gone for everyone, including from the history of the
+	/// rooms it was posted in, so this is off by default.
+	#[serde(default)]
+	pub allow_users_delete_own_media: bool,
+
 	#[serde(default = "true_fn")]
 	pub freeze_legacy_media: bool,
 
@@ -1386,6 +2059,21 @@ pub struct Config {
 	#[serde(default, with = "serde_regex")]
 	pub forbidden_remote_server_names: RegexSet,
 
+	/// List of server names via regex patterns that we will exclusively
+	/// federate with. When set, federation with any server not matching it is
+	/// denied in both directions: inbound federation requests and invites are
+	/// refused, and we send nothing to them nor query them, including for
+	/// their signing keys, media, and well-known delegation.
+	///
+	/// `forbidden_remote_server_names` still applies to servers matching it.
+	/// Leave empty to federate with any server.
+	///
+	/// example: ["^matrix\.org$", "\.trusted\.example$"]
+	///
+	/// default: []
+	#[serde(default, with = "serde_regex")]
+	pub allowed_remote_server_names: RegexSet,
+
 	/// List of forbidden server names via regex patterns that we will block all
 	/// outgoing federated room directory requests for. Useful for preventing
 	/// our users from wandering into bad servers or spaces.
@@ -1627,6 +2315,39 @@ pub struct Config {
 	#[serde(default = "default_admin_room_tag")]
 	pub admin_room_tag: String,
 
+	/// List of users to make server admins at startup. Each user not already
+	/// in the admin room is invited to it, and local users are joined
+	/// automatically.
+	///
+	/// example: ["@alice:example.com"]
+	///
+	/// default: []
+	#[serde(default)]
+	pub admin_users: Vec<OwnedUserId>,
+
+	/// Creates a new admin room at startup and points the admin room alias at
+	/// it. Use this to recover when the admin room was lost, e.g. after every
+	/// admin left it. The previous admin room, if the server user is still in
+	/// it, is tombstoned towards the new one. The users in `admin_users` are
+	/// invited to the new room.
+	///
+	/// This is also set by the `--recreate-admin-room` commandline flag.
+	/// Remove it again after recovering, as each startup creates a new room.
+	#[serde(default)]
+	pub recreate_admin_room: bool,
+
+	/// Serve a subset of Synapse's HTTP admin API under `/_synapse/admin/`, so
+	/// tools made for Synapse such as synapse-admin can list, show and
+	/// deactivate users, reset passwords, and list and delete rooms. Only
+	/// server admins' access tokens are accepted. The reverse proxy must pass
+	/// `/_synapse/admin/` through for these to be reachable.
+	///
+	/// Quarantining media is not supported: it is refused as unrecognized, as
+	/// there is no quarantine here to keep it in. Delete the media with the
+	/// `media delete` admin command instead.
+	#[serde(default)]
+	pub synapse_admin_api: bool,
+
 	/// Sentry.io crash/panic reporting, performance monitoring/metrics, etc.
 	/// This is NOT enabled by default. conduwuit's default Sentry reporting
 	/// endpoint domain is `o4506996327251968.ingest.us.sentry.io`.
@@ -1816,10 +2537,16 @@ pub struct Config {
 	// external structure; separate section
 	#[serde(default)]
 	pub blurhashing: BlurhashConfig,
+	#[serde(default)]
+	pub email: EmailConfig,
 	#[serde(flatten)]
 	#[allow(clippy::zero_sized_map_values)]
 	// this is a catchall, the map shouldn't be zero at runtime
 	catchall: BTreeMap<String, IgnoredAny>,
+
+	// outcome of sizing the caches, when `cache_auto_size` took effect
+	#[serde(skip)]
+	pub auto_size: Option<cache::AutoSize>,
 }
 
 #[derive(Clone, Debug, Deserialize, Default)]
@@ -1864,6 +2591,75 @@ pub struct WellKnownConfig {
 	pub support_email: Option<String>,
 
 	pub support_mxid: Option<OwnedUserId>,
+
+	/// The sliding sync advertisement of the client well-known file, replacing
+	/// the default one of a sliding sync proxy at `client`. Only two keys are
+	/// accepted: `org.matrix.msc3575.proxy`, a table with the `url` of the
+	/// sliding sync proxy, and `org.matrix.simplified_msc3575`, whether native
+	/// simplified sliding sync is advertised. Leaving both out advertises
+	/// neither.
+	///
+	/// For example, to advertise a proxy hosted elsewhere:
+	///
+	/// [global.well_known.client_extra]
+	/// "org.matrix.msc3575.proxy" = { url = "https://sliding.example.com" }
+	pub client_extra: Option<ClientWellKnownExtra>,
+
+	/// Sliding sync advertisements of the client well-known file for the
+	/// clients whose User-Agent contains `user_agent`. The first override
+	/// matching a request replaces `client_extra` in its response.
+	///
+	/// For example, to point Element X at native sliding sync while other
+	/// clients keep using the proxy:
+	///
+	/// [[global.well_known.client_overrides]]
+	/// user_agent = "Element X"
+	/// extra = { "org.matrix.simplified_msc3575" = true }
+	///
+	/// default: []
+	#[serde(default)]
+	pub client_overrides: Vec<ClientWellKnownOverride>,
+}
+
+/// Keys added to the client well-known file; see `well_known.client_extra`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientWellKnownExtra {
+	#[serde(rename = "org.matrix.msc3575.proxy", skip_serializing_if = "Option::is_none")]
+	pub sliding_sync_proxy: Option<SlidingSyncProxy>,
+
+	#[serde(rename = "org.matrix.simplified_msc3575", skip_serializing_if = "Option::is_none")]
+	pub simplified_sliding_sync: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlidingSyncProxy {
+	pub url: Url,
+}
+
+/// See `well_known.client_overrides`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientWellKnownOverride {
+	/// Substring of the User-Agent of the clients overridden.
+	pub user_agent: String,
+
+	#[serde(default)]
+	pub extra: ClientWellKnownExtra,
+}
+
+/// How the certificate of a federation destination is trusted; see
+/// `federation_tls`.
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct FederationTlsConfig {
+	/// PEM file of a certificate authority trusted for this destination.
+	#[serde(default)]
+	pub ca_file: Option<PathBuf>,
+
+	/// Base64 SHA-256 hashes of the public keys its certificate may carry.
+	#[serde(default)]
+	pub pins: Vec<String>,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Default)]
@@ -1889,6 +2685,117 @@ pub struct BlurhashConfig {
 	/// default: 33554432
 	#[serde(default = "default_blurhash_max_raw_size")]
 	pub blurhash_max_raw_size: u64,
+	/// Compute a blurhash for every uploaded image, whether or not the client
+	/// asked for one, and store it with the media info returned by
+	/// `/_conduwuit/client/v1/media/{server}/{id}/info`. Failing to blurhash
+	/// an image does not fail its upload.
+	#[serde(default)]
+	pub blurhash_on_upload: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[config_example_generator(filename = "conduwuit-example.toml", section = "global.email")]
+pub struct EmailConfig {
+	/// Hostname of the SMTP server through which email is sent. Email is
+	/// disabled while this is unset, and needs the 'email' feature.
+	///
+	/// example: "smtp.example.com"
+	pub smtp_host: Option<String>,
+
+	/// Port of the SMTP server. Defaults to 465 for "tls", 587 for "starttls"
+	/// and 25 otherwise.
+	///
+	/// example: 587
+	pub smtp_port: Option<u16>,
+
+	/// How the connection to the SMTP server is secured: "tls" for implicit
+	/// TLS, "starttls" to require upgrading the connection, "opportunistic"
+	/// to upgrade it when the server offers to, or "plaintext".
+	///
+	/// default: "starttls"
+	#[serde(default)]
+	pub smtp_tls: SmtpTls,
+
+	/// User to authenticate to the SMTP server as, when it requires it.
+	///
+	/// example: "conduwuit@example.com"
+	pub smtp_username: Option<String>,
+
+	/// Password of "smtp_username".
+	///
+	/// display: sensitive
+	pub smtp_password: Option<String>,
+
+	/// Mailbox email is sent from, which is required to send any.
+	///
+	/// example: "conduwuit <noreply@example.com>"
+	pub from: Option<String>,
+
+	/// Most connections to the SMTP server kept open for reuse.
+	///
+	/// default: 4
+	#[serde(default = "default_email_pool_size")]
+	pub smtp_pool_size: u32,
+
+	/// Seconds after which a command to the SMTP server times out.
+	///
+	/// default: 30
+	#[serde(default = "default_email_timeout")]
+	pub smtp_timeout: u64,
+
+	/// Directory of templates overriding the builtin ones, each as a
+	/// `<name>.subject` and `<name>.txt` file. Placeholders such as
+	/// `{{server_name}}` are replaced with the values given for the email.
+	///
+	/// example: "/etc/conduwuit/email"
+	pub templates_dir: Option<PathBuf>,
+
+	/// Most emails sent to the addresses of any one domain per minute; more
+	/// are held back in the queue. 0 disables the limit.
+	///
+	/// default: 30
+	#[serde(default = "default_email_rate_limit_per_domain")]
+	pub rate_limit_per_domain: u32,
+
+	/// Attempts at sending an email before it is given up on. Emails refused
+	/// permanently by the server are given up on at once.
+	///
+	/// default: 5
+	#[serde(default = "default_email_max_attempts")]
+	pub max_attempts: u32,
+
+	/// Post a notice to the admin room for every email given up on.
+	#[serde(default = "true_fn")]
+	pub notify_admins_on_failure: bool,
+}
+
+impl Default for EmailConfig {
+	fn default() -> Self {
+		Self {
+			smtp_host: None,
+			smtp_port: None,
+			smtp_tls: SmtpTls::default(),
+			smtp_username: None,
+			smtp_password: None,
+			from: None,
+			smtp_pool_size: default_email_pool_size(),
+			smtp_timeout: default_email_timeout(),
+			templates_dir: None,
+			rate_limit_per_domain: default_email_rate_limit_per_domain(),
+			max_attempts: default_email_max_attempts(),
+			notify_admins_on_failure: true,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+	Tls,
+	#[default]
+	Starttls,
+	Opportunistic,
+	Plaintext,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -1939,10 +2846,12 @@ pub fn load<'a, I>(paths: I) -> Result<Figment>
 
 	/// Finalize config
 	pub fn new(raw_config: &Figment) -> Result<Self> {
-		let config = raw_config
+		let mut config = raw_config
 			.extract::<Self>()
 			.map_err(|e| err!("There was a problem with your configuration file: {e}"))?;
 
+		cache::apply(&mut config, raw_config);
+
 		// don't start if we're listening on both UNIX sockets and TCP at same time
 		check::is_dual_listening(raw_config)?;
 
@@ -2004,6 +2913,8 @@ fn default_pdu_cache_capacity() -> u32 { parallelism_scaled_u32(10_000).saturati
 
 fn default_cache_capacity_modifier() -> f64 { 1.0 }
 
+fn default_cache_auto_size_fraction() -> f64 { 0.25 }
+
 fn default_auth_chain_cache_capacity() -> u32 {
 	parallelism_scaled_u32(10_000).saturating_add(100_000)
 }
@@ -2034,8 +2945,22 @@ fn default_servernameevent_data_cache_capacity() -> u32 {
 
 fn default_stateinfo_cache_capacity() -> u32 { parallelism_scaled_u32(100) }
 
+fn default_state_snapshot_changes() -> usize { 1000 }
+
+fn default_state_snapshot_drift() -> usize { 5000 }
+
+fn default_state_snapshot_min_interval() -> u64 { 60 }
+
 fn default_roomid_spacehierarchy_cache_capacity() -> u32 { parallelism_scaled_u32(1000) }
 
+fn default_roomid_spacehierarchy_cache_ttl() -> u64 { 60 * 5 }
+
+fn default_stateids_cache_capacity() -> u32 { parallelism_scaled_u32(100) }
+
+fn default_stateids_cache_ttl() -> u64 { 60 }
+
+fn default_verified_events_cache_capacity() -> u32 { parallelism_scaled_u32(1000) }
+
 fn default_dns_cache_entries() -> u32 { 32768 }
 
 fn default_dns_min_ttl() -> u64 { 60 * 180 }
@@ -2072,12 +2997,26 @@ fn default_federation_idle_timeout() -> u64 { 25 }
 
 fn default_federation_idle_per_host() -> u16 { 1 }
 
+fn default_federation_http2_keepalive_interval() -> u64 { 15 }
+
+fn default_federation_http2_keepalive_timeout() -> u64 { 20 }
+
 fn default_sender_timeout() -> u64 { 180 }
 
 fn default_sender_idle_timeout() -> u64 { 180 }
 
 fn default_sender_retry_backoff_limit() -> u64 { 86400 }
 
+fn default_federation_backoff_initial() -> u64 { 30 }
+
+fn default_federation_backoff_max() -> u64 { 86400 }
+
+fn default_federation_backoff_multiplier() -> f64 { 2.0 }
+
+fn default_federation_backoff_jitter() -> f64 { 0.2 }
+
+fn default_federation_defunct_window() -> u64 { 259200 }
+
 fn default_appservice_timeout() -> u64 { 35 }
 
 fn default_appservice_idle_timeout() -> u64 { 300 }
@@ -2086,6 +3025,28 @@ fn default_pusher_idle_timeout() -> u64 { 15 }
 
 fn default_max_fetch_prev_events() -> u16 { 192_u16 }
 
+fn default_max_auth_chain_response() -> usize { 50_000 }
+
+fn default_state_keys_per_sender_hourly() -> usize { 100 }
+
+fn default_federation_state_keys_per_sender() -> usize { 1000 }
+
+fn default_federation_state_flood_window() -> u64 { 600 }
+
+fn default_state_flood_allowed_types() -> Vec<String> {
+	vec!["m.room.member".to_owned(), "m.room.aliases".to_owned()]
+}
+
+fn default_room_freeze_notice() -> String {
+	"This room has been frozen by the server administrators.".to_owned()
+}
+
+fn default_room_activity_retention_days() -> u64 { 90 }
+
+fn default_membership_batch_max() -> usize { 100 }
+
+fn default_state_batch_max() -> usize { 50 }
+
 fn default_tracing_flame_filter() -> String {
 	cfg!(debug_assertions)
 		.then_some("trace,h2=off")
@@ -2118,8 +3079,14 @@ pub fn default_log() -> String {
 #[must_use]
 pub fn default_log_span_events() -> String { "none".into() }
 
+fn default_log_file_max_size() -> u64 { 100 * 1024 * 1024 }
+
+fn default_log_file_keep() -> usize { 7 }
+
 fn default_notification_push_path() -> String { "/_matrix/push/v1/notify".to_owned() }
 
+fn default_max_push_keyword_rules() -> usize { 100 }
+
 fn default_openid_token_ttl() -> u64 { 60 * 60 }
 
 fn default_login_token_ttl() -> u64 { 2 * 60 * 1000 }
@@ -2136,8 +3103,24 @@ fn default_typing_client_timeout_min_s() -> u64 { 15 }
 
 fn default_typing_client_timeout_max_s() -> u64 { 45 }
 
+fn default_incoming_edu_max_size() -> usize { 64 * 1024 }
+
+fn default_incoming_txn_max_receipts() -> usize { 1000 }
+
+fn default_incoming_txn_max_typing() -> usize { 100 }
+
+fn default_incoming_txn_max_presence() -> usize { 500 }
+
+fn default_incoming_txn_max_device_list_updates() -> usize { 500 }
+
 fn default_rocksdb_recovery_mode() -> u8 { 1 }
 
+fn default_rocksdb_open_lock_retries() -> u32 { 5 }
+
+fn default_rocksdb_open_lock_backoff_ms() -> u64 { 1000 }
+
+fn default_rocksdb_secondary_catchup_interval_ms() -> u64 { 1000 }
+
 fn default_rocksdb_log_level() -> String { "error".to_owned() }
 
 fn default_rocksdb_log_time_to_roll() -> usize { 0 }
@@ -2172,6 +3155,10 @@ fn default_rocksdb_bottommost_compression_level() -> i32 { 32767 }
 
 fn default_rocksdb_stats_level() -> u8 { 1 }
 
+fn default_rocksdb_stats_interval_secs() -> u64 { 60 }
+
+fn default_db_slow_query_threshold() -> u64 { 250 }
+
 // I know, it's a great name
 #[must_use]
 #[inline]
@@ -2262,13 +3249,45 @@ fn default_client_response_timeout() -> u64 { 120 }
 
 fn default_client_shutdown_timeout() -> u64 { 15 }
 
+fn default_send_queue_capacity() -> usize { 32 }
+
+fn default_to_device_sync_limit() -> usize { 100 }
+
+fn default_sync_room_concurrency() -> usize { 16 }
+
+fn default_sync_timeline_limit_default() -> usize { 10 }
+
+fn default_sync_timeline_limit_max() -> usize { 100 }
+
 fn default_sender_shutdown_timeout() -> u64 { 5 }
 
+fn default_load_shedding_max_inflight() -> usize { 2048 }
+
+fn default_load_shedding_p99_ms() -> u64 { 5000 }
+
+fn default_load_shedding_retry_after_secs() -> u64 { 5 }
+
 // blurhashing defaults recommended by https://blurha.sh/
 // 2^25
 pub(super) fn default_blurhash_max_raw_size() -> u64 { 33_554_432 }
 
-pub(super) fn default_blurhash_x_component() -> u32 { 4 }
+pub(super) fn default_email_pool_size() -> u32 { 4 }
+
+fn default_account_validity_renew_days() -> u64 { 7 }
+
+fn default_uisi_report_retention() -> u64 { 7 * 24 * 60 * 60 }
+
+fn default_uisi_reports_per_user_hourly() -> usize { 30 }
+
+fn default_uisi_report_max() -> usize { 100_000 }
+
+fn default_email_timeout() -> u64 { 30 }
+
+fn default_email_rate_limit_per_domain() -> u32 { 30 }
+
+fn default_email_max_attempts() -> u32 { 5 }
+
+fn default_blurhash_x_component() -> u32 { 4 }
 
 pub(super) fn default_blurhash_y_component() -> u32 { 3 }
 
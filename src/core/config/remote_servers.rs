@@ -0,0 +1,39 @@
+use ruma::ServerName;
+
+use super::Config;
+use crate::{Err, Result, implement};
+
+/// Whether we may federate with the server, in either direction. Every
+/// inbound federation request and outbound federation client goes through
+/// this, so that an endpoint cannot skip `allowed_remote_server_names` nor
+/// `forbidden_remote_server_names`.
+#[implement(Config)]
+#[must_use]
+pub fn is_remote_server_allowed(&self, server: &ServerName) -> bool {
+	let host = server.host();
+	if !self.allowed_remote_server_names.is_empty()
+		&& !self.allowed_remote_server_names.is_match(host)
+	{
+		return false;
+	}
+
+	!self.forbidden_remote_server_names.is_match(host)
+}
+
+/// Fails with M_FORBIDDEN, in words the remote server can show its users,
+/// unless we may federate with the server.
+#[implement(Config)]
+pub fn check_remote_server(&self, server: &ServerName) -> Result {
+	if self.is_remote_server_allowed(server) {
+		return Ok(());
+	}
+
+	let ours = &self.server_name;
+	if self.forbidden_remote_server_names.is_match(server.host()) {
+		return Err!(Request(Forbidden("Federation with {server} is forbidden by {ours}.")));
+	}
+
+	Err!(Request(Forbidden(
+		"{ours} only federates with an allowlist of servers, which {server} is not on."
+	)))
+}
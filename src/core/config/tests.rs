@@ -0,0 +1,330 @@
+use figment::{
+	Figment,
+	providers::{Format, Toml},
+};
+use ruma::{api::client::error::ErrorKind, server_name};
+use serde_json::json;
+
+use super::Config;
+
+fn load(toml: &str) -> (Config, Figment) {
+	let raw = Figment::new().merge(Toml::string(toml).nested());
+	let config = Config::new(&raw).expect("valid config");
+
+	(config, raw)
+}
+
+#[test]
+fn virtual_hosts_isolated() {
+	let (config, raw) = load(
+		r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+		port = 8448
+		allow_registration = true
+		max_request_size = 1024
+
+		[global.virtual_hosts."second.example"]
+		database_path = "/var/lib/conduwuit-second"
+		allow_registration = false
+
+		[global.virtual_hosts."third.example"]
+		database_path = "/var/lib/conduwuit-third"
+		"#,
+	);
+
+	let hosts = config.virtual_hosts(&raw).expect("valid virtual hosts");
+	assert_eq!(hosts.len(), 2);
+
+	let (second, third) = (&hosts[0], &hosts[1]);
+	assert_eq!(second.server_name, "second.example");
+	assert_eq!(third.server_name, "third.example");
+	assert_eq!(second.database_path.to_str(), Some("/var/lib/conduwuit-second"));
+	assert_eq!(third.database_path.to_str(), Some("/var/lib/conduwuit-third"));
+
+	assert!(!second.allow_registration, "overridden");
+	assert!(third.allow_registration, "inherited");
+	assert_eq!(third.max_request_size, 1024, "inherited");
+	assert_eq!(second.get_bind_addrs(), config.get_bind_addrs(), "shared listeners");
+	assert!(hosts.iter().all(|host| host.virtual_hosts.is_empty()));
+}
+
+#[test]
+fn virtual_hosts_rejected() {
+	let cases = [
+		r#"
+		[global.virtual_hosts."second.example"]
+		allow_registration = false
+		"#,
+		r#"
+		[global.virtual_hosts."second.example"]
+		database_path = "/var/lib/conduwuit"
+		"#,
+		r#"
+		[global.virtual_hosts."second.example"]
+		database_path = "/var/lib/conduwuit-second"
+		[global.virtual_hosts."third.example"]
+		database_path = "/var/lib/conduwuit-second"
+		"#,
+		r#"
+		[global.virtual_hosts."second.example"]
+		database_path = "/var/lib/conduwuit-second"
+		port = 8449
+		"#,
+		r#"
+		[global.virtual_hosts."main.example"]
+		database_path = "/var/lib/conduwuit-second"
+		"#,
+		r#"
+		database_backup_path = "/var/backups/conduwuit"
+		[global.virtual_hosts."second.example"]
+		database_path = "/var/lib/conduwuit-second"
+		"#,
+		r#"
+		[global.virtual_hosts."second.example"]
+		database_path = "/var/lib/conduwuit-second"
+		database_backup_path = "/var/backups/conduwuit"
+		[global.virtual_hosts."third.example"]
+		database_path = "/var/lib/conduwuit-third"
+		database_backup_path = "/var/backups/conduwuit"
+		"#,
+	];
+
+	for case in cases {
+		let toml = format!(
+			"[global]\nserver_name = \"main.example\"\ndatabase_path = \
+			 \"/var/lib/conduwuit\"\n{case}"
+		);
+
+		let (config, raw) = load(&toml);
+		assert!(config.virtual_hosts(&raw).is_err(), "{case}");
+	}
+}
+
+#[test]
+fn remote_servers_allowlist() {
+	let (config, _) = load(
+		r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+		allowed_remote_server_names = ['^trusted\.example$', '\.partner\.example$']
+		forbidden_remote_server_names = ['^bad\.partner\.example$']
+		"#,
+	);
+
+	// inbound, checking the X-Matrix origin of a request or invite
+	let origin = server_name!("trusted.example");
+	assert!(config.check_remote_server(origin).is_ok());
+
+	let origin = server_name!("stranger.example:8448");
+	let error = config
+		.check_remote_server(origin)
+		.expect_err("not on the allowlist");
+	assert!(matches!(error.kind(), ErrorKind::Forbidden { .. }), "{error}");
+	assert!(error.message().contains("allowlist"), "{error}");
+
+	// outbound, building a federation client or resolving a destination
+	assert!(config.is_remote_server_allowed(server_name!("matrix.partner.example")));
+	assert!(config.is_remote_server_allowed(server_name!("trusted.example:8448")));
+	assert!(!config.is_remote_server_allowed(server_name!("untrusted.example")));
+	assert!(!config.is_remote_server_allowed(server_name!("bad.partner.example")));
+
+	let error = config
+		.check_remote_server(server_name!("bad.partner.example"))
+		.expect_err("forbidden");
+	assert!(error.message().contains("forbidden"), "{error}");
+}
+
+#[test]
+fn remote_servers_without_allowlist() {
+	let (config, _) = load(
+		r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+		forbidden_remote_server_names = ['^bad\.example$']
+		"#,
+	);
+
+	assert!(config.is_remote_server_allowed(server_name!("anyone.example")));
+	assert!(
+		config
+			.check_remote_server(server_name!("anyone.example"))
+			.is_ok()
+	);
+	assert!(
+		config
+			.check_remote_server(server_name!("bad.example"))
+			.is_err()
+	);
+}
+
+#[test]
+fn well_known_client_default() {
+	let (config, _) = load(
+		r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+
+		[global.well_known]
+		client = "https://matrix.main.example"
+		"#,
+	);
+
+	let expected = json!({
+		"m.homeserver": { "base_url": "https://matrix.main.example/" },
+		"org.matrix.msc3575.proxy": { "url": "https://matrix.main.example/" },
+	});
+
+	let well_known = &config.well_known;
+	assert_eq!(well_known.client_response(None), Some(expected.clone()));
+	assert_eq!(well_known.client_response(Some("Element X/1.0")), Some(expected));
+}
+
+#[test]
+fn well_known_client_overrides() {
+	let (config, _) = load(
+		r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+
+		[global.well_known]
+		client = "https://matrix.main.example"
+
+		[global.well_known.client_extra]
+		"org.matrix.msc3575.proxy" = { url = "https://sliding.main.example" }
+
+		[[global.well_known.client_overrides]]
+		user_agent = "Element X"
+		extra = { "org.matrix.simplified_msc3575" = true }
+
+		[[global.well_known.client_overrides]]
+		user_agent = "Element"
+		extra = {}
+		"#,
+	);
+
+	let well_known = &config.well_known;
+	let response = |user_agent| well_known.client_response(Some(user_agent));
+
+	assert_eq!(
+		response("Element X/25.01.0 (iPhone; iOS 18.2)"),
+		Some(json!({
+			"m.homeserver": { "base_url": "https://matrix.main.example/" },
+			"org.matrix.simplified_msc3575": true,
+		}))
+	);
+
+	assert_eq!(
+		response("Element/1.11.90 (Android 14)"),
+		Some(json!({
+			"m.homeserver": { "base_url": "https://matrix.main.example/" },
+		}))
+	);
+
+	let proxied = Some(json!({
+		"m.homeserver": { "base_url": "https://matrix.main.example/" },
+		"org.matrix.msc3575.proxy": { "url": "https://sliding.main.example/" },
+	}));
+
+	assert_eq!(response("FluffyChat/1.22"), proxied);
+	assert_eq!(well_known.client_response(None), proxied);
+}
+
+#[test]
+fn well_known_client_extra_rejected() {
+	let unknown = r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+
+		[global.well_known.client_extra]
+		"m.tile_server" = { map_style_url = "https://tiles.example/style.json" }
+		"#;
+
+	let raw = Figment::new().merge(Toml::string(unknown).nested());
+	assert!(Config::new(&raw).is_err());
+
+	let (config, _) = load(
+		r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+
+		[global.well_known]
+		client = "https://matrix.main.example"
+
+		[[global.well_known.client_overrides]]
+		user_agent = ""
+		"#,
+	);
+
+	assert!(config.well_known.check_client_extra().is_err());
+
+	let (config, _) = load(
+		r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+
+		[global.well_known.client_extra]
+		"org.matrix.simplified_msc3575" = true
+		"#,
+	);
+
+	assert!(config.well_known.check_client_extra().is_err());
+}
+
+#[test]
+fn virtual_hosts_backup_paths() {
+	let (config, raw) = load(
+		r#"
+		[global]
+		server_name = "main.example"
+		database_path = "/var/lib/conduwuit"
+		database_backup_path = "/var/backups/conduwuit"
+
+		[global.virtual_hosts."second.example"]
+		database_path = "/var/lib/conduwuit-second"
+		database_backup_path = "/var/backups/conduwuit-second"
+		"#,
+	);
+
+	let hosts = config.virtual_hosts(&raw).expect("valid virtual hosts");
+	assert_eq!(
+		hosts[0]
+			.database_backup_path
+			.as_deref()
+			.and_then(|path| path.to_str()),
+		Some("/var/backups/conduwuit-second")
+	);
+}
+
+#[test]
+fn cache_auto_size_kept_per_config() {
+	let (sized, _) = load(
+		r#"
+		[global]
+		server_name = "example.com"
+		cache_auto_size = true
+		"#,
+	);
+
+	let (configured, _) = load(
+		r#"
+		[global]
+		server_name = "example.com"
+		"#,
+	);
+
+	assert!(configured.auto_size.is_none(), "auto-sizing not enabled");
+	assert_eq!(
+		sized.auto_size.is_some(),
+		crate::utils::sys::memory::available().is_some(),
+		"outcome kept after loading another config"
+	);
+}
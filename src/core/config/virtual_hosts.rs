@@ -0,0 +1,72 @@
+use std::collections::BTreeSet;
+
+use figment::{Figment, providers::Serialized};
+
+use super::Config;
+use crate::{Err, Result, implement};
+
+/// Options configuring the listeners, which every virtual host shares with the
+/// main server.
+const SHARED: [&str; 5] = ["address", "port", "unix_socket_path", "unix_socket_perms", "tls"];
+
+/// Configuration of each of the `virtual_hosts`, derived by applying its
+/// overrides to the raw configuration of the main server.
+#[implement(Config)]
+pub fn virtual_hosts(&self, raw: &Figment) -> Result<Vec<Self>> {
+	let mut database_paths = BTreeSet::from([self.database_path.clone()]);
+	let mut backup_paths: BTreeSet<_> = self.database_backup_path.iter().cloned().collect();
+	self.virtual_hosts
+		.iter()
+		.map(|(name, overrides)| {
+			if *name == self.server_name {
+				return Err!(Config(
+					"virtual_hosts",
+					"{name:?} is already the main server_name."
+				));
+			}
+
+			if let Some(key) = SHARED.iter().find(|key| overrides.contains_key(**key)) {
+				return Err!(Config(
+					"virtual_hosts",
+					"{key:?} cannot be overridden for {name:?}; listeners are shared by all \
+					 virtual hosts."
+				));
+			}
+
+			if !overrides.contains_key("database_path") {
+				return Err!(Config(
+					"virtual_hosts",
+					"{name:?} requires a database_path of its own."
+				));
+			}
+
+			let raw = raw
+				.clone()
+				.merge(Serialized::globals(overrides))
+				.merge(Serialized::global("server_name", name));
+
+			let mut config = Self::new(&raw)?;
+			config.virtual_hosts.clear();
+			if !database_paths.insert(config.database_path.clone()) {
+				return Err!(Config(
+					"virtual_hosts",
+					"The database_path of {name:?} is already used by another server."
+				));
+			}
+
+			if config
+				.database_backup_path
+				.as_ref()
+				.is_some_and(|path| !backup_paths.insert(path.clone()))
+			{
+				return Err!(Config(
+					"virtual_hosts",
+					"The database_backup_path of {name:?} is already used by another server; it \
+					 requires one of its own."
+				));
+			}
+
+			Ok(config)
+		})
+		.collect()
+}
@@ -0,0 +1,61 @@
+use serde_json::{Value, json};
+
+use super::{ClientWellKnownExtra, SlidingSyncProxy, WellKnownConfig};
+use crate::{Err, Result, implement};
+
+/// The client well-known file served to a client sending `user_agent`, if
+/// `client` is set. The sliding sync advertisement is that of the first
+/// override matching the User-Agent, else `client_extra`, else a sliding sync
+/// proxy at `client`.
+#[implement(WellKnownConfig)]
+#[must_use]
+pub fn client_response(&self, user_agent: Option<&str>) -> Option<Value> {
+	let client = self.client.as_ref()?;
+	let default = ClientWellKnownExtra {
+		sliding_sync_proxy: Some(SlidingSyncProxy { url: client.clone() }),
+		..Default::default()
+	};
+
+	let extra = user_agent
+		.and_then(|user_agent| {
+			self.client_overrides
+				.iter()
+				.find(|over| user_agent.contains(&over.user_agent))
+		})
+		.map(|over| &over.extra)
+		.or(self.client_extra.as_ref())
+		.unwrap_or(&default);
+
+	let Ok(Value::Object(mut response)) = serde_json::to_value(extra) else {
+		return None;
+	};
+
+	response.insert("m.homeserver".to_owned(), json!({ "base_url": client }));
+
+	Some(Value::Object(response))
+}
+
+/// Fails on a sliding sync advertisement which cannot be served as intended.
+#[implement(WellKnownConfig)]
+pub(super) fn check_client_extra(&self) -> Result {
+	if self
+		.client_overrides
+		.iter()
+		.any(|over| over.user_agent.is_empty())
+	{
+		return Err!(Config(
+			"well_known.client_overrides",
+			"An empty user_agent would match every client."
+		));
+	}
+
+	if self.client.is_none() && (self.client_extra.is_some() || !self.client_overrides.is_empty())
+	{
+		return Err!(Config(
+			"well_known.client",
+			"Needed to serve the client well-known file with client_extra or client_overrides."
+		));
+	}
+
+	Ok(())
+}
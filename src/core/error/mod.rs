@@ -3,6 +3,8 @@
 mod panic;
 mod response;
 mod serde;
+#[cfg(test)]
+mod tests;
 
 use std::{any::Any, borrow::Cow, convert::Infallible, sync::PoisonError};
 
@@ -114,8 +116,18 @@ pub enum Error {
 	Mxc(#[from] ruma::MxcUriError),
 	#[error(transparent)]
 	Mxid(#[from] ruma::IdParseError),
+	#[error("{what} not found")]
+	NotFound {
+		what: Cow<'static, str>,
+	},
 	#[error("from {0}: {1}")]
 	Redaction(ruma::OwnedServerName, ruma::canonical_json::RedactionError),
+	#[error("Fetching {endpoint} from {server} failed{}", with_status(.status.as_ref()))]
+	RemoteFetchFailed {
+		server: ruma::OwnedServerName,
+		endpoint: &'static str,
+		status: Option<http::StatusCode>,
+	},
 	#[error("{0}: {1}")]
 	Request(ruma::api::client::error::ErrorKind, Cow<'static, str>, http::StatusCode),
 	#[error(transparent)]
@@ -124,6 +136,15 @@ pub enum Error {
 	Signatures(#[from] ruma::signatures::Error),
 	#[error(transparent)]
 	StateRes(#[from] crate::state_res::Error),
+	#[error("State {shortstatehash} is not available")]
+	StateUnavailable {
+		shortstatehash: u64,
+	},
+	#[error("State diff {shortstatehash} is corrupt ({len} bytes)")]
+	CorruptStateDiff {
+		shortstatehash: u64,
+		len: usize,
+	},
 	#[error("uiaa")]
 	Uiaa(ruma::api::client::uiaa::UiaaInfo),
 
@@ -137,6 +158,33 @@ impl Error {
 	#[must_use]
 	pub fn from_errno() -> Self { Self::Io(std::io::Error::last_os_error()) }
 
+	#[inline]
+	#[must_use]
+	pub fn not_found(what: impl Into<Cow<'static, str>>) -> Self {
+		Self::NotFound { what: what.into() }
+	}
+
+	/// A request to a remote server which failed with `error`, keeping the
+	/// status it answered with, if any.
+	#[must_use]
+	pub fn remote_fetch_failed(
+		server: &ruma::ServerName,
+		endpoint: &'static str,
+		error: &Self,
+	) -> Self {
+		let status = match error {
+			| Self::Federation(_, error) => Some(error.status_code),
+			| Self::Reqwest(error) => error.status(),
+			| _ => None,
+		};
+
+		Self::RemoteFetchFailed {
+			server: server.to_owned(),
+			endpoint,
+			status,
+		}
+	}
+
 	//#[deprecated]
 	pub fn bad_database(message: &'static str) -> Self {
 		crate::err!(Database(error!("{message}")))
@@ -145,8 +193,10 @@ pub fn bad_database(message: &'static str) -> Self {
 	/// Sanitizes public-facing errors that can leak sensitive information.
 	pub fn sanitized_message(&self) -> String {
 		match self {
-			| Self::Database(..) => String::from("Database error occurred."),
+			| Self::Database(..) | Self::CorruptStateDiff { .. } =>
+				String::from("Database error occurred."),
 			| Self::Io(..) => String::from("I/O error occurred."),
+			| Self::StateUnavailable { .. } => String::from("State not available."),
 			| _ => self.message(),
 		}
 	}
@@ -163,13 +213,14 @@ pub fn message(&self) -> String {
 	/// Returns the Matrix error code / error kind
 	#[inline]
 	pub fn kind(&self) -> ruma::api::client::error::ErrorKind {
-		use ruma::api::client::error::ErrorKind::{FeatureDisabled, Unknown};
+		use ruma::api::client::error::ErrorKind::{FeatureDisabled, NotFound, Unknown};
 
 		match self {
 			| Self::Federation(_, error) | Self::Ruma(error) =>
 				response::ruma_error_kind(error).clone(),
 			| Self::BadRequest(kind, ..) | Self::Request(kind, ..) => kind.clone(),
 			| Self::FeatureDisabled(..) => FeatureDisabled,
+			| Self::NotFound { .. } | Self::StateUnavailable { .. } => NotFound,
 			| _ => Unknown,
 		}
 	}
@@ -186,6 +237,8 @@ pub fn status_code(&self) -> http::StatusCode {
 			| Self::FeatureDisabled(..) => response::bad_request_code(&self.kind()),
 			| Self::Reqwest(error) => error.status().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
 			| Self::Conflict(_) => StatusCode::CONFLICT,
+			| Self::NotFound { .. } | Self::StateUnavailable { .. } => StatusCode::NOT_FOUND,
+			| Self::RemoteFetchFailed { .. } => StatusCode::BAD_GATEWAY,
 			| Self::Io(error) => response::io_error_code(error.kind()),
 			| _ => StatusCode::INTERNAL_SERVER_ERROR,
 		}
@@ -226,6 +279,12 @@ pub fn infallible(_e: &Infallible) {
 	panic!("infallible error should never exist");
 }
 
+fn with_status(status: Option<&http::StatusCode>) -> String {
+	status
+		.map(|status| format!(" with {status}"))
+		.unwrap_or_default()
+}
+
 /// Convenience functor for fundamental Error::sanitized_message(); see member.
 #[inline]
 #[must_use]
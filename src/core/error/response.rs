@@ -28,9 +28,13 @@ fn into_response(self) -> axum::response::Response {
 impl From<Error> for UiaaResponse {
 	#[inline]
 	fn from(error: Error) -> Self {
-		if let Error::Uiaa(uiaainfo) = error {
-			return Self::AuthResponse(uiaainfo);
-		}
+		let error = match error {
+			| Error::Uiaa(uiaainfo) => return Self::AuthResponse(uiaainfo),
+			// Bodies carrying fields besides the errcode and message are sent as built.
+			| Error::Ruma(error) if matches!(error.body, ErrorBody::Json(_)) =>
+				return Self::MatrixError(error),
+			| error => error,
+		};
 
 		let body = ErrorBody::Standard {
 			kind: error.kind(),
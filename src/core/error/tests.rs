@@ -0,0 +1,73 @@
+use http::StatusCode;
+use ruma::{
+	api::client::{
+		error::{ErrorBody, ErrorKind},
+		uiaa::UiaaResponse,
+	},
+	server_name,
+};
+
+use super::Error;
+
+/// The status and errcode a client is sent for the error.
+fn response(error: Error) -> (StatusCode, ErrorKind) {
+	let UiaaResponse::MatrixError(error) = error.into() else {
+		panic!("not a Matrix error");
+	};
+
+	let ErrorBody::Standard { kind, .. } = error.body else {
+		panic!("not a standard error body");
+	};
+
+	(error.status_code, kind)
+}
+
+#[test]
+fn unknown_state_not_found() {
+	let (status, kind) = response(Error::StateUnavailable { shortstatehash: 42 });
+	assert_eq!(status, StatusCode::NOT_FOUND);
+	assert_eq!(kind, ErrorKind::NotFound);
+
+	let (status, kind) = response(Error::not_found("Room"));
+	assert_eq!(status, StatusCode::NOT_FOUND);
+	assert_eq!(kind, ErrorKind::NotFound);
+	assert!(Error::not_found("Room").is_not_found());
+}
+
+#[test]
+fn remote_fetch_failed_bad_gateway() {
+	let error = Error::RemoteFetchFailed {
+		server: server_name!("example.com").to_owned(),
+		endpoint: "/state_ids",
+		status: Some(StatusCode::NOT_FOUND),
+	};
+
+	assert_eq!(
+		error.to_string(),
+		"Fetching /state_ids from example.com failed with 404 Not Found"
+	);
+	assert!(!error.is_not_found(), "remote status taken as our own");
+
+	let (status, kind) = response(error);
+	assert_eq!(status, StatusCode::BAD_GATEWAY);
+	assert_eq!(kind, ErrorKind::Unknown);
+}
+
+#[test]
+fn state_unavailable_detail_sanitized() {
+	let error = Error::StateUnavailable { shortstatehash: 42 };
+	assert_eq!(error.message(), "State 42 is not available");
+	assert_eq!(error.sanitized_message(), "State not available.");
+}
+
+#[test]
+fn corrupt_state_diff_internal() {
+	let error = Error::CorruptStateDiff { shortstatehash: 42, len: 13 };
+	assert_eq!(error.message(), "State diff 42 is corrupt (13 bytes)");
+	assert_eq!(error.sanitized_message(), "Database error occurred.");
+	assert!(!error.is_not_found());
+
+	let (status, kind) = response(error);
+	assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+	assert_eq!(kind, ErrorKind::Unknown);
+}
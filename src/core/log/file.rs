@@ -0,0 +1,286 @@
+//! Log file
+//!
+//! Logs are also written to the configured file, which is rotated once it
+//! grows past a size or at midnight, and reopened on SIGHUP for rotation by
+//! logrotate instead. Rotated files are renamed and compressed in the
+//! background.
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+	ffi::OsString,
+	fs::{self, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	thread,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{Compression, write::GzEncoder};
+
+use crate::{Config, Result, err};
+
+/// Each log line is written whole under the lock, which is also held while
+/// the file is swapped for a new one, so rotating neither loses nor splits a
+/// line.
+pub struct File {
+	path: PathBuf,
+	max_size: u64,
+	daily: bool,
+	keep: usize,
+	compress: bool,
+	current: Mutex<Current>,
+
+	/// The next rotation to archive, held while rotated files are renamed and
+	/// compressed so they are shifted along one rotation at a time.
+	archive: Arc<Mutex<u64>>,
+}
+
+struct Current {
+	file: fs::File,
+	size: u64,
+	day: u64,
+	rotations: u64,
+	last_rotated: Option<SystemTime>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stats {
+	pub path: PathBuf,
+	pub size: u64,
+	pub rotations: u64,
+	pub last_rotated: Option<SystemTime>,
+}
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+impl File {
+	/// Opens the configured log file, if there is one.
+	pub fn open(config: &Config) -> Result<Option<Arc<Self>>> {
+		let Some(path) = &config.log_file else {
+			return Ok(None);
+		};
+
+		let file = Self::new(
+			path.clone(),
+			config.log_file_max_size,
+			config.log_file_rotate_daily,
+			config.log_file_keep,
+			config.log_file_compress,
+		)
+		.map_err(|e| err!(Config("log_file", "Failed to open {path:?}: {e}")))?;
+
+		Ok(Some(Arc::new(file)))
+	}
+
+	pub(super) fn new(
+		path: PathBuf,
+		max_size: u64,
+		daily: bool,
+		keep: usize,
+		compress: bool,
+	) -> io::Result<Self> {
+		let (file, size) = open(&path)?;
+		let current = Current {
+			file,
+			size,
+			day: day(SystemTime::now()),
+			rotations: 0,
+			last_rotated: None,
+		};
+
+		Ok(Self {
+			path,
+			max_size,
+			daily,
+			keep,
+			compress,
+			current: Mutex::new(current),
+			archive: Arc::default(),
+		})
+	}
+
+	/// Reopens the file at its path, after it was moved away to be rotated by
+	/// something else.
+	pub fn reopen(&self) -> io::Result<()> {
+		let (file, size) = open(&self.path)?;
+		let mut current = self.lock()?;
+		current.file.flush()?;
+		current.file = file;
+		current.size = size;
+
+		Ok(())
+	}
+
+	#[must_use]
+	pub fn stats(&self) -> Stats {
+		let current = self.current.lock().expect("locked");
+
+		Stats {
+			path: self.path.clone(),
+			size: current.size,
+			rotations: current.rotations,
+			last_rotated: current.last_rotated,
+		}
+	}
+
+	/// Moves the file aside and opens a new one in its place, leaving the file
+	/// moved aside to be archived in the background.
+	fn rotate(&self, current: &mut Current, now: SystemTime) -> io::Result<()> {
+		let rotation = current.rotations;
+		current.file.flush()?;
+		fs::rename(&self.path, staging(&self.path, rotation))?;
+
+		let (file, size) = open(&self.path)?;
+		current.file = file;
+		current.size = size;
+		current.rotations = current.rotations.saturating_add(1);
+		current.last_rotated = Some(now);
+
+		let next = self.archive.clone();
+		let (path, keep, compress) = (self.path.clone(), self.keep, self.compress);
+		thread::Builder::new()
+			.name("conduwuit:log".into())
+			.spawn(move || {
+				// Archive in order of rotation, whichever thread gets here first.
+				let mut next = next.lock().expect("locked");
+				while *next <= rotation {
+					let staging = staging(&path, *next);
+					if let Err(e) = archive(&path, &staging, keep, compress) {
+						crate::error!(?staging, "Failed to archive rotated log file: {e}");
+					}
+
+					*next = next.saturating_add(1);
+				}
+			})?;
+
+		Ok(())
+	}
+
+	fn lock(&self) -> io::Result<std::sync::MutexGuard<'_, Current>> {
+		self.current
+			.lock()
+			.map_err(|_| io::Error::other("log file lock poisoned"))
+	}
+}
+
+impl io::Write for &'_ File {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let now = SystemTime::now();
+		let today = day(now);
+		let len = u64::try_from(buf.len()).unwrap_or(u64::MAX);
+
+		let mut current = self.lock()?;
+		if due(current.size, len, self.max_size, current.day, today, self.daily) {
+			// On failure keep writing to the file as it is, and try again only
+			// after as much again has been written, or the next day.
+			if self.rotate(&mut current, now).is_err() {
+				current.size = 0;
+			}
+
+			current.day = today;
+		}
+
+		current.file.write_all(buf)?;
+		current.size = current.size.saturating_add(len);
+
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> { self.lock()?.file.flush() }
+}
+
+/// Whether writing `len` more bytes to a file of `size`, opened on the day
+/// `opened`, needs it rotated first. A file is never rotated empty.
+pub(super) fn due(
+	size: u64,
+	len: u64,
+	max_size: u64,
+	opened: u64,
+	today: u64,
+	daily: bool,
+) -> bool {
+	if size == 0 {
+		return false;
+	}
+
+	(daily && today != opened) || (max_size > 0 && size.saturating_add(len) > max_size)
+}
+
+/// Makes the file moved aside at `staging` the newest kept, shifting along
+/// the older ones and deleting any beyond `keep`.
+pub(super) fn archive(
+	path: &Path,
+	staging: &Path,
+	keep: usize,
+	compress: bool,
+) -> io::Result<()> {
+	if keep == 0 {
+		return fs::remove_file(staging);
+	}
+
+	for gz in [false, true] {
+		remove_if_exists(&archived(path, keep, gz))?;
+	}
+
+	for n in (1..keep).rev() {
+		for gz in [false, true] {
+			let from = archived(path, n, gz);
+			if from.exists() {
+				fs::rename(&from, archived(path, n.saturating_add(1), gz))?;
+			}
+		}
+	}
+
+	if !compress {
+		return fs::rename(staging, archived(path, 1, false));
+	}
+
+	let mut input = fs::File::open(staging)?;
+	let mut output =
+		GzEncoder::new(fs::File::create(archived(path, 1, true))?, Compression::default());
+	io::copy(&mut input, &mut output)?;
+	output.finish()?.sync_all()?;
+
+	fs::remove_file(staging)
+}
+
+/// Path of the `n`th newest rotated file.
+pub(super) fn archived(path: &Path, n: usize, gz: bool) -> PathBuf {
+	let suffix = if gz { format!(".{n}.gz") } else { format!(".{n}") };
+
+	suffixed(path, &suffix)
+}
+
+/// Path the file is moved to by its `rotation`, until archived.
+fn staging(path: &Path, rotation: u64) -> PathBuf {
+	suffixed(path, &format!(".rotating.{rotation}"))
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = OsString::from(path.as_os_str());
+	name.push(suffix);
+
+	name.into()
+}
+
+fn open(path: &Path) -> io::Result<(fs::File, u64)> {
+	let file = OpenOptions::new().create(true).append(true).open(path)?;
+	let size = file.metadata()?.len();
+
+	Ok((file, size))
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+	match fs::remove_file(path) {
+		| Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+		| result => result,
+	}
+}
+
+fn day(now: SystemTime) -> u64 {
+	now.duration_since(UNIX_EPOCH)
+		.map_or(0, |elapsed| elapsed.as_secs() / DAY_SECS)
+}
@@ -0,0 +1,141 @@
+use std::{
+	fs,
+	io::{Read, Write},
+	path::Path,
+	sync::Arc,
+	thread,
+	time::{Duration, Instant},
+};
+
+use flate2::read::GzDecoder;
+
+use super::{File, archive, archived, due};
+use crate::utils::TempDir;
+
+fn read(path: &Path) -> String {
+	let mut out = String::new();
+	if path.extension().is_some_and(|ext| ext == "gz") {
+		GzDecoder::new(fs::File::open(path).expect("opened"))
+			.read_to_string(&mut out)
+			.expect("decompressed");
+	} else {
+		fs::File::open(path)
+			.expect("opened")
+			.read_to_string(&mut out)
+			.expect("read");
+	}
+
+	out
+}
+
+/// Waits for the rotated files still being archived in the background.
+fn settle(dir: &Path) {
+	let deadline = Instant::now()
+		.checked_add(Duration::from_secs(10))
+		.expect("instant in range");
+
+	while fs::read_dir(dir)
+		.expect("read dir")
+		.filter_map(Result::ok)
+		.any(|entry| entry.file_name().to_string_lossy().contains(".rotating."))
+	{
+		assert!(Instant::now() < deadline, "rotated files archived in time");
+		thread::sleep(Duration::from_millis(10));
+	}
+}
+
+#[test]
+fn rotation_due() {
+	assert!(!due(0, 100, 10, 1, 2, true), "empty file is not rotated");
+	assert!(!due(5, 5, 10, 1, 1, false));
+	assert!(due(5, 6, 10, 1, 1, false), "over the size");
+	assert!(!due(5, 6, 0, 1, 1, false), "size not limited");
+	assert!(due(5, 1, 0, 1, 2, true), "next day");
+	assert!(!due(5, 1, 0, 1, 2, false), "not rotated daily");
+}
+
+#[test]
+fn archive_keeps_newest() {
+	let dir = TempDir::new("log_keep");
+	let path = dir.join("conduwuit.log");
+
+	for n in 1..=4 {
+		let staging = dir.join(format!("staging{n}"));
+		fs::write(&staging, format!("rotation {n}\n")).expect("written");
+		archive(&path, &staging, 2, false).expect("archived");
+		assert!(!staging.exists());
+	}
+
+	assert_eq!(read(&archived(&path, 1, false)), "rotation 4\n");
+	assert_eq!(read(&archived(&path, 2, false)), "rotation 3\n");
+	assert!(!archived(&path, 3, false).exists());
+
+	let staging = dir.join("staging");
+	fs::write(&staging, "compressed\n").expect("written");
+	archive(&path, &staging, 2, true).expect("archived");
+	assert_eq!(read(&archived(&path, 1, true)), "compressed\n");
+	assert_eq!(read(&archived(&path, 2, false)), "rotation 4\n");
+}
+
+#[test]
+fn rotation_keeps_every_line() {
+	const THREADS: usize = 8;
+	const LINES: usize = 500;
+
+	let dir = TempDir::new("log_lines");
+	let path = dir.join("conduwuit.log");
+	let file = Arc::new(File::new(path.clone(), 4096, false, 1000, false).expect("opened"));
+
+	let writers: Vec<_> = (0..THREADS)
+		.map(|t| {
+			let file = file.clone();
+			thread::spawn(move || {
+				for i in 0..LINES {
+					(&*file)
+						.write_all(format!("thread {t} line {i}\n").as_bytes())
+						.expect("written");
+				}
+			})
+		})
+		.collect();
+
+	for writer in writers {
+		writer.join().expect("writer finished");
+	}
+
+	settle(&dir);
+	let rotations = file.stats().rotations;
+	assert!(rotations > 0, "rotated by size");
+
+	let mut lines: Vec<String> = (1..=usize::try_from(rotations).expect("fits"))
+		.map(|n| archived(&path, n, false))
+		.chain([path.clone()])
+		.flat_map(|path| {
+			read(&path)
+				.lines()
+				.map(ToOwned::to_owned)
+				.collect::<Vec<_>>()
+		})
+		.collect();
+
+	assert_eq!(lines.len(), THREADS.saturating_mul(LINES), "no line lost");
+	lines.sort_unstable();
+	lines.dedup();
+	assert_eq!(lines.len(), THREADS.saturating_mul(LINES), "no line split or repeated");
+}
+
+#[test]
+fn reopen_after_move() {
+	let dir = TempDir::new("log_reopen");
+	let path = dir.join("conduwuit.log");
+	let file = File::new(path.clone(), 0, false, 1, false).expect("opened");
+
+	(&file).write_all(b"before\n").expect("written");
+	fs::rename(&path, dir.join("moved.log")).expect("moved");
+	file.reopen().expect("reopened");
+	(&file).write_all(b"after\n").expect("written");
+
+	assert_eq!(read(&dir.join("moved.log")), "before\n");
+	assert_eq!(read(&path), "after\n");
+	assert_eq!(file.stats().size, 6);
+}
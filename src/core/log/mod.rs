@@ -3,6 +3,7 @@
 pub mod capture;
 pub mod color;
 pub mod console;
+pub mod file;
 pub mod fmt;
 pub mod fmt_span;
 mod reload;
@@ -19,12 +20,16 @@
 /// Logging subsystem. This is a singleton member of super::Server which holds
 /// all logging and tracing related state rather than shoving it all in
 /// super::Server directly.
+#[derive(Clone, Default)]
 pub struct Log {
 	/// General log level reload handles.
 	pub reload: LogLevelReloadHandles,
 
 	/// Tracing capture state for ephemeral/oneshot uses.
 	pub capture: std::sync::Arc<capture::State>,
+
+	/// The log file, when logs are also written to one.
+	pub file: Option<std::sync::Arc<file::File>>,
 }
 
 // Wraps for logging macros. Use these macros rather than extern tracing:: or
@@ -27,6 +27,9 @@
 	builder::{Builder, Builder as PduBuilder},
 	count::Count,
 	event_id::*,
+	filter::{
+		filter_limit, filter_type_matches, presence_matches, presence_wanted, room_matches,
+	},
 	id::*,
 	raw_id::*,
 	state_key::{ShortStateKey, StateKey},
@@ -1,7 +1,69 @@
-use ruma::api::client::filter::{RoomEventFilter, UrlFilter};
+use ruma::{
+	RoomId, UserId,
+	api::client::filter::{Filter, RoomEventFilter, RoomFilter, UrlFilter},
+};
 use serde_json::Value;
 
-use crate::{implement, is_equal_to};
+use crate::implement;
+
+/// Whether the room passes the `rooms` and `not_rooms` of a room filter.
+#[must_use]
+pub fn room_matches(filter: &RoomFilter, room_id: &RoomId) -> bool {
+	if filter.not_rooms.iter().any(|not_room| not_room == room_id) {
+		return false;
+	}
+
+	filter
+		.rooms
+		.as_ref()
+		.is_none_or(|rooms| rooms.iter().any(|room| room == room_id))
+}
+
+/// Most events to return under the filter, or `default` when it sets no limit,
+/// but never more than `max`.
+#[must_use]
+pub fn filter_limit(filter: &RoomEventFilter, default: usize, max: usize) -> usize {
+	filter
+		.limit
+		.map(u64::from)
+		.map_or(default, |limit| usize::try_from(limit).unwrap_or(usize::MAX))
+		.min(max)
+}
+
+/// Whether events of the type pass the `types` and `not_types` of a room event
+/// filter, such as the `ephemeral` filter of a room.
+#[must_use]
+pub fn filter_type_matches(filter: &RoomEventFilter, event_type: &str) -> bool {
+	types_match(filter.types.as_deref(), &filter.not_types, event_type)
+}
+
+/// Whether presence of the user passes a presence filter.
+#[must_use]
+pub fn presence_matches(filter: &Filter, user_id: &UserId) -> bool {
+	if filter.not_senders.iter().any(|sender| sender == user_id) {
+		return false;
+	}
+
+	if filter
+		.senders
+		.as_ref()
+		.is_some_and(|senders| !senders.iter().any(|sender| sender == user_id))
+	{
+		return false;
+	}
+
+	types_match(filter.types.as_deref(), &filter.not_types, "m.presence")
+}
+
+/// Whether the presence filter lets any presence through at all.
+#[must_use]
+pub fn presence_wanted(filter: &Filter) -> bool {
+	filter
+		.senders
+		.as_ref()
+		.is_none_or(|senders| !senders.is_empty())
+		&& types_match(filter.types.as_deref(), &filter.not_types, "m.presence")
+}
 
 #[implement(super::Pdu)]
 #[must_use]
@@ -57,15 +119,46 @@ fn matches_sender(&self, filter: &RoomEventFilter) -> bool {
 
 #[implement(super::Pdu)]
 fn matches_type(&self, filter: &RoomEventFilter) -> bool {
-	let event_type = &self.kind.to_cow_str();
-	if filter.not_types.iter().any(is_equal_to!(event_type)) {
+	filter_type_matches(filter, &self.kind.to_cow_str())
+}
+
+fn types_match(types: Option<&[String]>, not_types: &[String], event_type: &str) -> bool {
+	if not_types
+		.iter()
+		.any(|pattern| type_matches(pattern, event_type))
+	{
 		return false;
 	}
 
-	if let Some(types) = filter.types.as_ref() {
-		if !types.iter().any(is_equal_to!(event_type)) {
-			return false;
+	types.is_none_or(|types| {
+		types
+			.iter()
+			.any(|pattern| type_matches(pattern, event_type))
+	})
+}
+
+/// Matches an event type against a pattern of a filter, in which each `*`
+/// stands for any sequence of characters.
+pub(super) fn type_matches(pattern: &str, event_type: &str) -> bool {
+	let Some((prefix, rest)) = pattern.split_once('*') else {
+		return pattern == event_type;
+	};
+
+	let Some(mut remaining) = event_type.strip_prefix(prefix) else {
+		return false;
+	};
+
+	let mut parts = rest.split('*').peekable();
+	while let Some(part) = parts.next() {
+		if parts.peek().is_none() {
+			return remaining.ends_with(part);
 		}
+
+		let Some((_, after)) = remaining.split_once(part) else {
+			return false;
+		};
+
+		remaining = after;
 	}
 
 	true
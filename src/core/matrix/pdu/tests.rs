@@ -1,4 +1,12 @@
-use super::Count;
+use ruma::{
+	api::client::filter::{Filter, FilterDefinition, RoomEventFilter, RoomFilter},
+	owned_room_id, owned_user_id, room_id, uint, user_id,
+};
+
+use super::{
+	Count, filter::type_matches, filter_limit, filter_type_matches, presence_matches,
+	presence_wanted, room_matches,
+};
 
 #[test]
 fn backfilled_parse() {
@@ -15,3 +23,92 @@ fn normal_parse() {
 
 	assert!(!backfilled, "backfilled variant");
 }
+
+#[test]
+fn filter_excludes_room() {
+	let mut filter = RoomFilter::default();
+	assert!(room_matches(&filter, room_id!("!a:example.com")), "no filter excludes nothing");
+
+	filter.not_rooms = vec![owned_room_id!("!a:example.com")];
+	assert!(!room_matches(&filter, room_id!("!a:example.com")), "excluded room matched");
+	assert!(room_matches(&filter, room_id!("!b:example.com")), "other room excluded");
+
+	filter.rooms = Some(vec![owned_room_id!("!a:example.com"), owned_room_id!("!c:example.com")]);
+	assert!(
+		!room_matches(&filter, room_id!("!a:example.com")),
+		"not_rooms must win over rooms"
+	);
+	assert!(!room_matches(&filter, room_id!("!b:example.com")), "unlisted room matched");
+	assert!(room_matches(&filter, room_id!("!c:example.com")), "listed room excluded");
+}
+
+#[test]
+fn filter_limit_caps_timeline() {
+	let mut filter = RoomEventFilter::default();
+	assert_eq!(filter_limit(&filter, 10, 100), 10, "default limit not applied");
+	assert_eq!(filter_limit(&filter, 10, 5), 5, "default limit not clamped");
+
+	filter.limit = Some(uint!(1));
+	assert_eq!(filter_limit(&filter, 10, 100), 1, "filter limit not applied");
+
+	filter.limit = Some(uint!(5000));
+	assert_eq!(filter_limit(&filter, 10, 100), 100, "filter limit not clamped");
+	assert_eq!(filter_limit(&filter, 10, 5000), 5000, "filter limit clamped below max");
+}
+
+#[test]
+fn filter_type_wildcards() {
+	assert!(type_matches("m.room.message", "m.room.message"), "exact type");
+	assert!(!type_matches("m.room.message", "m.room.member"), "other type");
+	assert!(type_matches("*", "m.room.member"), "lone wildcard");
+	assert!(type_matches("m.room.*", "m.room.member"), "trailing wildcard");
+	assert!(!type_matches("m.room.*", "m.call.invite"), "trailing wildcard prefix");
+	assert!(type_matches("*.member", "m.room.member"), "leading wildcard");
+	assert!(type_matches("m.*.m*r", "m.room.member"), "inner wildcards");
+	assert!(!type_matches("m.*.x*r", "m.room.member"), "inner wildcards mismatch");
+	assert!(!type_matches("m.room.message*e", "m.room.message"), "overlapping suffix");
+}
+
+#[test]
+fn ephemeral_filter_excludes_typing() {
+	let filter: FilterDefinition =
+		serde_json::from_str(r#"{"room": {"ephemeral": {"not_types": ["m.typing"]}}}"#)
+			.expect("valid filter");
+
+	let ephemeral = &filter.room.ephemeral;
+	assert!(!filter_type_matches(ephemeral, "m.typing"), "typing not excluded");
+	assert!(filter_type_matches(ephemeral, "m.receipt"), "receipts excluded");
+
+	let ephemeral = RoomEventFilter::default();
+	assert!(filter_type_matches(&ephemeral, "m.typing"), "no filter excludes nothing");
+	assert!(filter_type_matches(&ephemeral, "m.receipt"), "no filter excludes nothing");
+}
+
+#[test]
+fn presence_filter() {
+	let alice = user_id!("@alice:example.com");
+	let bob = user_id!("@bob:example.com");
+
+	let mut filter = Filter::default();
+	assert!(presence_wanted(&filter));
+	assert!(presence_matches(&filter, alice));
+
+	filter.types = Some(Vec::new());
+	assert!(!presence_wanted(&filter), "no types wanted");
+	assert!(!presence_matches(&filter, alice));
+
+	filter.types = None;
+	filter.not_types = vec!["*".to_owned()];
+	assert!(!presence_wanted(&filter), "every type excluded");
+
+	filter.not_types = Vec::new();
+	filter.senders = Some(vec![owned_user_id!("@alice:example.com")]);
+	assert!(presence_wanted(&filter));
+	assert!(presence_matches(&filter, alice));
+	assert!(!presence_matches(&filter, bob), "unlisted sender matched");
+
+	filter.senders = None;
+	filter.not_senders = vec![owned_user_id!("@alice:example.com")];
+	assert!(!presence_matches(&filter, alice), "excluded sender matched");
+	assert!(presence_matches(&filter, bob));
+}
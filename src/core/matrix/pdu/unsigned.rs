@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
 
 use ruma::MilliSecondsSinceUnixEpoch;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::value::{RawValue as RawJsonValue, Value as JsonValue, to_raw_value};
 
 use super::Pdu;
@@ -48,6 +48,23 @@ pub fn add_age(&mut self) -> Result {
 	Ok(())
 }
 
+#[implement(Pdu)]
+pub fn add_unsigned<T: Serialize>(&mut self, name: &str, value: &T) -> Result {
+	use BTreeMap as Map;
+
+	let mut unsigned: Map<&str, Box<RawJsonValue>> = self
+		.unsigned
+		.as_deref()
+		.map(RawJsonValue::get)
+		.map_or_else(|| Ok(Map::new()), serde_json::from_str)
+		.map_err(|e| err!(Database("Invalid unsigned in pdu event: {e}")))?;
+
+	unsigned.insert(name, to_raw_value(value)?);
+	self.unsigned = Some(to_raw_value(&unsigned)?);
+
+	Ok(())
+}
+
 #[implement(Pdu)]
 pub fn add_relation(&mut self, name: &str, pdu: Option<&Pdu>) -> Result {
 	use serde_json::Map;
@@ -1,4 +1,7 @@
-use std::sync::atomic::AtomicU32;
+use std::{
+	collections::BTreeMap,
+	sync::{RwLock, atomic::AtomicU32},
+};
 
 use tokio::runtime;
 use tokio_metrics::TaskMonitor;
@@ -22,6 +25,9 @@ pub struct Metrics {
 	pub requests_handle_active: AtomicU32,
 	pub requests_handle_finished: AtomicU32,
 	pub requests_panic: AtomicU32,
+
+	/// Gauges and counters published by other subsystems, by name.
+	values: RwLock<BTreeMap<&'static str, u64>>,
 }
 
 impl Metrics {
@@ -49,6 +55,8 @@ pub fn new(runtime: Option<runtime::Handle>) -> Self {
 			requests_handle_active: AtomicU32::new(0),
 			requests_handle_finished: AtomicU32::new(0),
 			requests_panic: AtomicU32::new(0),
+
+			values: RwLock::default(),
 		}
 	}
 
@@ -62,6 +70,25 @@ pub fn runtime_interval(&self) -> Option<tokio_metrics::RuntimeMetrics> {
 			.expect("next interval")
 	}
 
+	/// Records the latest value of a named gauge or counter.
+	pub fn set(&self, name: &'static str, value: u64) {
+		self.values
+			.write()
+			.expect("locked for writing")
+			.insert(name, value);
+	}
+
+	/// The latest value of every recorded gauge and counter.
+	#[must_use]
+	pub fn values(&self) -> Vec<(&'static str, u64)> {
+		self.values
+			.read()
+			.expect("locked for reading")
+			.iter()
+			.map(|(&name, &value)| (name, value))
+			.collect()
+	}
+
 	#[inline]
 	pub fn task_root(&self) -> Option<&TaskMonitor> { self.task_monitor.as_ref() }
 
@@ -45,6 +45,10 @@ pub struct Server {
 
 	/// Metrics subsystem state
 	pub metrics: Metrics,
+
+	/// Servers for the other server names hosted by this process. Each has its
+	/// own configuration derived from this one.
+	pub virtual_hosts: Vec<Arc<Self>>,
 }
 
 impl Server {
@@ -61,6 +65,7 @@ pub fn new(config: Config, runtime: Option<runtime::Handle>, log: Log) -> Self {
 			signal: broadcast::channel::<&'static str>(1).0,
 			log,
 			metrics: Metrics::new(runtime),
+			virtual_hosts: Vec::new(),
 		}
 	}
 
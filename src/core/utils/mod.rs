@@ -16,6 +16,7 @@
 pub mod stream;
 pub mod string;
 pub mod sys;
+pub mod temp;
 #[cfg(test)]
 mod tests;
 pub mod time;
@@ -38,6 +39,7 @@
 	stream::{IterStream, ReadyExt, Tools as StreamTools, TryReadyExt},
 	string::{str_from_bytes, string_from_bytes},
 	sys::compute::available_parallelism,
+	temp::TempDir,
 	time::{
 		exponential_backoff::{continue_exponential_backoff, continue_exponential_backoff_secs},
 		now_millis as millis_since_unix_epoch, timepoint_ago, timepoint_from_now,
@@ -1,5 +1,7 @@
 use std::{
 	cmp::{Eq, Ord},
+	collections::HashSet,
+	hash::Hash,
 	pin::Pin,
 	sync::Arc,
 };
@@ -77,3 +79,23 @@ pub fn intersection_sorted_stream2<Item, S>(a: S, b: S) -> impl Stream<Item = It
 			None
 		})
 }
+
+/// Latest of each key
+///
+/// Collapses a log of changes, oldest first, to the last change of each key.
+/// The changes kept stay in the order they were made.
+pub fn latest_by_key<Item, Key, F>(items: Vec<Item>, key: F) -> Vec<Item>
+where
+	F: Fn(&Item) -> Key,
+	Key: Eq + Hash,
+{
+	let mut seen = HashSet::with_capacity(items.len());
+	let mut latest: Vec<Item> = items
+		.into_iter()
+		.rev()
+		.filter(|item| seen.insert(key(item)))
+		.collect();
+
+	latest.reverse();
+	latest
+}
@@ -0,0 +1,65 @@
+//! System utilities related to memory limits
+
+use std::{fs::read_to_string, path::Path};
+
+/// Where the cgroup hierarchy of this process is mounted. Inside a container
+/// with its own cgroup namespace this is the container's own group.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// cgroup v1 reports the absence of a limit as a very large value near
+/// `i64::MAX` rounded down to the page size.
+const CGROUP_V1_UNLIMITED: u64 = 1 << 62;
+
+/// Memory available to the server in bytes: the lesser of the physical memory
+/// and any cgroup limit imposed on the process.
+#[must_use]
+pub fn available() -> Option<u64> {
+	let total = total();
+	let limit = cgroup_limit(Path::new(CGROUP_ROOT));
+
+	match (total, limit) {
+		| (Some(total), Some(limit)) => Some(total.min(limit)),
+		| (total, limit) => total.or(limit),
+	}
+}
+
+/// Total physical memory in bytes.
+#[must_use]
+pub fn total() -> Option<u64> {
+	read_to_string("/proc/meminfo")
+		.ok()
+		.as_deref()
+		.and_then(meminfo_total)
+}
+
+/// Memory limit in bytes of the cgroup mounted at `root`, trying the cgroup v2
+/// layout then v1. None when neither is present or no limit is set.
+#[must_use]
+pub fn cgroup_limit(root: &Path) -> Option<u64> {
+	let v2 = root.join("memory.max");
+	let v1 = root.join("memory").join("memory.limit_in_bytes");
+
+	[v2, v1]
+		.iter()
+		.filter_map(|path| read_to_string(path).ok())
+		.find_map(|limit| parse_cgroup_limit(&limit))
+}
+
+fn parse_cgroup_limit(limit: &str) -> Option<u64> {
+	match limit.trim() {
+		| "max" => None,
+		| limit => limit
+			.parse()
+			.ok()
+			.filter(|&limit| limit < CGROUP_V1_UNLIMITED),
+	}
+}
+
+fn meminfo_total(meminfo: &str) -> Option<u64> {
+	meminfo
+		.lines()
+		.find_map(|line| line.strip_prefix("MemTotal:"))
+		.and_then(|total| total.trim().strip_suffix("kB"))
+		.and_then(|total| total.trim().parse::<u64>().ok())
+		.map(|total| total.saturating_mul(1024))
+}
@@ -0,0 +1,50 @@
+use std::{
+	fs,
+	ops::Deref,
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A fresh directory under the system's temporary directory, removed with
+/// everything in it when dropped.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+	/// Creates the directory, named after `name` and the current time so that
+	/// concurrent users do not collide.
+	///
+	/// # Panics
+	/// If the directory cannot be created.
+	#[must_use]
+	pub fn new(name: &str) -> Self {
+		let nanos = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.expect("time after epoch")
+			.as_nanos();
+
+		let path = std::env::temp_dir().join(format!("conduwuit_{name}_{nanos}"));
+		fs::create_dir_all(&path).expect("created temp dir");
+		Self(path)
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn path(&self) -> &Path { &self.0 }
+}
+
+impl Deref for TempDir {
+	type Target = Path;
+
+	#[inline]
+	fn deref(&self) -> &Self::Target { self.path() }
+}
+
+impl AsRef<Path> for TempDir {
+	#[inline]
+	fn as_ref(&self) -> &Path { self.path() }
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) { fs::remove_dir_all(&self.0).ok(); }
+}
@@ -276,3 +276,42 @@ async fn set_intersection_sorted_stream2() {
 		.await;
 	assert!(r.eq(&["ccc", "ggg", "iii"]));
 }
+
+#[test]
+fn set_latest_by_key() {
+	use utils::set::latest_by_key;
+
+	let log = vec![("a", 1), ("b", 2), ("a", 3), ("c", 4), ("b", 5)];
+	assert_eq!(latest_by_key(log, |(key, _)| *key), [("a", 3), ("c", 4), ("b", 5)]);
+
+	let log: Vec<(&str, u64)> = Vec::new();
+	assert!(latest_by_key(log, |(key, _)| *key).is_empty());
+
+	let log = vec![("a", 1), ("a", 2), ("a", 3)];
+	assert_eq!(latest_by_key(log, |(key, _)| *key), [("a", 3)]);
+}
+
+#[test]
+fn cgroup_memory_limit() {
+	use std::fs::{create_dir_all, write};
+
+	use utils::{TempDir, sys::memory::cgroup_limit};
+
+	let root = TempDir::new("cgroup");
+	create_dir_all(root.join("memory")).expect("created cgroup dir");
+	assert_eq!(cgroup_limit(&root), None);
+
+	let v1 = root.join("memory").join("memory.limit_in_bytes");
+	write(&v1, "9223372036854771712\n").expect("wrote v1 limit");
+	assert_eq!(cgroup_limit(&root), None, "v1 sentinel means unlimited");
+
+	write(&v1, "536870912\n").expect("wrote v1 limit");
+	assert_eq!(cgroup_limit(&root), Some(536_870_912));
+
+	let v2 = root.join("memory.max");
+	write(&v2, "max\n").expect("wrote v2 limit");
+	assert_eq!(cgroup_limit(&root), Some(536_870_912), "falls back to v1");
+
+	write(&v2, "1073741824\n").expect("wrote v2 limit");
+	assert_eq!(cgroup_limit(&root), Some(1_073_741_824), "v2 takes precedence");
+}
@@ -1,5 +1,8 @@
 mod backup;
-mod cf_opts;
+pub(crate) mod batch;
+pub(crate) mod cf_opts;
+pub(crate) mod check;
+mod columns;
 pub(crate) mod context;
 mod db_opts;
 pub(crate) mod descriptor;
@@ -8,6 +11,10 @@
 mod memory_usage;
 mod open;
 mod repair;
+pub(crate) mod retry;
+pub(crate) mod shares;
+mod stats;
+pub(crate) mod transfer;
 
 use std::{
 	ffi::CStr,
@@ -19,13 +26,18 @@
 
 use conduwuit::{Err, Result, debug, info, warn};
 use rocksdb::{
-	AsColumnFamilyRef, BoundColumnFamily, DBCommon, DBWithThreadMode, MultiThreaded,
+	AsColumnFamilyRef, BoundColumnFamily, DBCommon, DBWithThreadMode, MultiThreaded, Options,
 	WaitForCompactOptions,
 };
 
+pub use self::{
+	batch::Batch,
+	stats::{Stats, Tickers},
+	transfer::Import,
+};
 use crate::{
 	Context,
-	pool::Pool,
+	pool::{LaneStats, Pool},
 	util::{map_err, result},
 };
 
@@ -37,6 +49,7 @@ pub struct Engine {
 	pub(super) secondary: bool,
 	pub(crate) checksums: bool,
 	corks: AtomicU32,
+	statistics: Option<Options>,
 }
 
 pub(crate) type Db = DBWithThreadMode<MultiThreaded>;
@@ -70,6 +83,8 @@ pub fn sort(&self) -> Result {
 		result(DBCommon::flush_opt(&self.db, &flushoptions))
 	}
 
+	/// Catches a secondary instance up with the primary, returning how many
+	/// sequence numbers were applied.
 	#[tracing::instrument(
 		level = "debug",
 		skip_all,
@@ -77,7 +92,16 @@ pub fn sort(&self) -> Result {
 			sequence = ?self.current_sequence(),
 		),
 	)]
-	pub fn update(&self) -> Result { self.db.try_catch_up_with_primary().map_err(map_err) }
+	pub fn update(&self) -> Result<u64> {
+		let ours = self.current_sequence();
+		self.db.try_catch_up_with_primary().map_err(map_err)?;
+
+		Ok(self.current_sequence().saturating_sub(ours))
+	}
+
+	/// What the requests of each lane of the frontend pool waited for a worker.
+	#[must_use]
+	pub fn lane_stats(&self) -> [LaneStats; 3] { self.pool.lane_stats() }
 
 	#[tracing::instrument(level = "info", skip_all)]
 	pub fn sync(&self) -> Result { result(DBCommon::flush_wal(&self.db, true)) }
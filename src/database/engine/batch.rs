@@ -0,0 +1,149 @@
+//! Atomic writes spanning several columns.
+//!
+//! Writes staged on a [`Batch`] become visible together when it is committed,
+//! or not at all if it is dropped first. Inside a cork the commit is not
+//! flushed, so batches coalesce with the other writes of the corked section.
+
+use std::{fmt::Debug, sync::Arc};
+
+use conduwuit::implement;
+use rocksdb::{WriteBatchWithTransaction, WriteOptions};
+use serde::Serialize;
+
+use super::{Db, Engine};
+use crate::{
+	Map,
+	map::{remove::stage_delete_prefix, write_options_default},
+	ser,
+	util::or_else,
+};
+
+pub struct Batch<'a> {
+	engine: &'a Arc<Engine>,
+	batch: WriteBatchWithTransaction<false>,
+	woken: Vec<(&'a Map, Vec<u8>)>,
+}
+
+/// Starts an empty batch of writes against any of this engine's maps.
+#[implement(Engine)]
+#[must_use]
+pub fn write_batch(self: &Arc<Self>) -> Batch<'_> {
+	Batch {
+		engine: self,
+		batch: WriteBatchWithTransaction::default(),
+		woken: Vec::new(),
+	}
+}
+
+impl<'a> Batch<'a> {
+	/// Insert Key/Value
+	///
+	/// - Key is serialized
+	/// - Val is serialized
+	pub fn put<K, V>(&mut self, map: &'a Map, key: K, val: V)
+	where
+		K: Serialize + Debug,
+		V: Serialize,
+	{
+		let key = ser::serialize_to_vec(key).expect("failed to serialize insertion key");
+		let val = ser::serialize_to_vec(val).expect("failed to serialize insertion val");
+		self.insert(map, &key, val);
+	}
+
+	/// Insert Key/Value
+	///
+	/// - Key is raw
+	/// - Val is serialized
+	pub fn raw_put<K, V>(&mut self, map: &'a Map, key: K, val: V)
+	where
+		K: AsRef<[u8]>,
+		V: Serialize,
+	{
+		let val = ser::serialize_to_vec(val).expect("failed to serialize insertion val");
+		self.insert(map, &key, val);
+	}
+
+	/// Insert Key/Value
+	///
+	/// - Key is raw
+	/// - Val is raw
+	pub fn insert<K, V>(&mut self, map: &'a Map, key: &K, val: V)
+	where
+		K: AsRef<[u8]> + ?Sized,
+		V: AsRef<[u8]>,
+	{
+		self.check(map);
+		self.batch.put_cf(&map.cf(), key, val);
+		self.woken.push((map, key.as_ref().to_vec()));
+	}
+
+	pub fn del<K>(&mut self, map: &'a Map, key: K)
+	where
+		K: Serialize + Debug,
+	{
+		let key = ser::serialize_to_vec(key).expect("failed to serialize deletion key");
+		self.remove(map, &key);
+	}
+
+	pub fn remove<K>(&mut self, map: &'a Map, key: &K)
+	where
+		K: AsRef<[u8]> + ?Sized,
+	{
+		self.check(map);
+		self.batch.delete_cf(&map.cf(), key);
+		self.woken.push((map, key.as_ref().to_vec()));
+	}
+
+	/// Delete all keys in the range `[from, to)`, as [`Map::delete_range`]
+	/// does. Watchers are not woken by range deletions.
+	pub fn delete_range(&mut self, map: &'a Map, from: &[u8], to: &[u8]) {
+		self.check(map);
+		self.batch.delete_range_cf(&map.cf(), from, to);
+	}
+
+	/// Delete all keys starting with `prefix`, as [`Map::delete_prefix`] does.
+	/// A prefix of only 0xFF bytes is bounded by the last key present when
+	/// staged.
+	pub fn delete_prefix(&mut self, map: &'a Map, prefix: &[u8]) {
+		self.check(map);
+		stage_delete_prefix(&mut self.batch, &self.engine.db, &map.cf(), prefix);
+	}
+
+	/// Writes everything staged so far in one atomic write.
+	#[tracing::instrument(skip(self), fields(len = self.len()), level = "trace")]
+	pub fn commit(self) {
+		let Self { engine, batch, woken } = self;
+		write(&engine.db, batch, &write_options_default(engine));
+
+		if !engine.corked() {
+			engine.flush().expect("database flush error");
+		}
+
+		for (map, key) in &woken {
+			map.wake(key);
+		}
+	}
+
+	/// Number of staged writes.
+	#[inline]
+	#[must_use]
+	pub fn len(&self) -> usize { self.batch.len() }
+
+	#[inline]
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.batch.is_empty() }
+
+	#[inline]
+	fn check(&self, map: &Map) {
+		debug_assert!(
+			Arc::ptr_eq(self.engine, map.db()),
+			"map {map} staged on a batch of another database"
+		);
+	}
+}
+
+pub(crate) fn write(db: &Db, batch: WriteBatchWithTransaction<false>, options: &WriteOptions) {
+	db.write_opt(batch, options)
+		.or_else(or_else)
+		.expect("database write batch error");
+}
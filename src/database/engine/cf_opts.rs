@@ -5,11 +5,17 @@
 	LruCacheOptions, Options, UniversalCompactOptions, UniversalCompactionStopStyle,
 };
 
-use super::descriptor::{CacheDisp, Descriptor};
+use super::{
+	descriptor::{CacheDisp, Descriptor},
+	shares,
+};
 use crate::{Context, util::map_err};
 
 pub(super) const SENTINEL_COMPRESSION_LEVEL: i32 = 32767;
 
+/// Compression algorithm names accepted in the configuration.
+const COMPRESSION_ALGOS: &[&str] = &["zstd", "snappy", "zlib", "bz2", "lz4", "lz4hc", "none"];
+
 /// Adjust options for the specific column by name. Provide the result of
 /// db_options() as the argument to this function and use the return value in
 /// the arguments to open the specific column.
@@ -19,13 +25,13 @@ pub(crate) fn cf_options(ctx: &Context, opts: Options, desc: &Descriptor) -> Res
 	descriptor_cf_options(opts, *desc, config, cache.as_ref())
 }
 
-fn descriptor_cf_options(
+pub(crate) fn descriptor_cf_options(
 	mut opts: Options,
 	mut desc: Descriptor,
 	config: &Config,
 	cache: Option<&Cache>,
 ) -> Result<Options> {
-	set_compression(&mut desc, config);
+	set_compression(&mut desc, config)?;
 	set_table_options(&mut opts, &desc, cache)?;
 
 	opts.set_min_write_buffer_number(1);
@@ -59,7 +65,8 @@ fn descriptor_cf_options(
 	opts.set_compression_per_level(compression_shape.as_slice());
 	opts.set_compression_options(-14, desc.compression_level, 0, 0); // -14 w_bits used by zlib.
 	if let Some(&bottommost_level) = desc.bottommost_level.as_ref() {
-		opts.set_bottommost_compression_type(desc.compression);
+		let bottommost_compression = desc.bottommost_compression.unwrap_or(desc.compression);
+		opts.set_bottommost_compression_type(bottommost_compression);
 		opts.set_bottommost_zstd_max_train_bytes(0, true);
 		opts.set_bottommost_compression_options(
 			-14, // -14 w_bits is only read by zlib.
@@ -112,16 +119,13 @@ fn set_table_options(opts: &mut Options, desc: &Descriptor, cache: Option<&Cache
 	Ok(())
 }
 
-fn set_compression(desc: &mut Descriptor, config: &Config) {
-	desc.compression = match config.rocksdb_compression_algo.as_ref() {
-		| "snappy" => CompressionType::Snappy,
-		| "zlib" => CompressionType::Zlib,
-		| "bz2" => CompressionType::Bz2,
-		| "lz4" => CompressionType::Lz4,
-		| "lz4hc" => CompressionType::Lz4hc,
-		| "none" => CompressionType::None,
-		| _ => CompressionType::Zstd,
-	};
+fn set_compression(desc: &mut Descriptor, config: &Config) -> Result {
+	desc.compression =
+		parse_compression_algo(&config.rocksdb_compression_algo).unwrap_or(CompressionType::Zstd);
+
+	if let Some(compression) = desc.column_compression {
+		desc.compression = compression;
+	}
 
 	let can_override_level = config.rocksdb_compression_level == SENTINEL_COMPRESSION_LEVEL
 		&& desc.compression == CompressionType::Zstd;
@@ -138,9 +142,64 @@ fn set_compression(desc: &mut Descriptor, config: &Config) {
 		desc.bottommost_level = Some(config.rocksdb_bottommost_compression_level);
 	}
 
+	if let Some(level) = desc.column_compression_level {
+		desc.compression_level = level;
+		desc.bottommost_level = Some(level);
+	}
+
+	if let Some(spec) = config.rocksdb_compression_per_column.get(desc.name) {
+		let (compression, level) = parse_column_compression(spec)?;
+		desc.compression = compression;
+		desc.bottommost_compression = None;
+		if let Some(level) = level {
+			desc.compression_level = level;
+			desc.bottommost_level = Some(level);
+		}
+	}
+
 	if !config.rocksdb_bottommost_compression {
 		desc.bottommost_level = None;
 	}
+
+	Ok(())
+}
+
+/// Parses a per-column compression override of the form `algo[:level]`.
+pub(crate) fn parse_column_compression(spec: &str) -> Result<(CompressionType, Option<i32>)> {
+	let (algo, level) = match spec.split_once(':') {
+		| Some((algo, level)) => (algo.trim(), Some(level.trim())),
+		| None => (spec.trim(), None),
+	};
+
+	let compression = parse_compression_algo(algo).ok_or_else(|| {
+		let valid = COMPRESSION_ALGOS.join(", ");
+		err!(Config(
+			"rocksdb_compression_per_column",
+			"Unknown compression algorithm {algo:?}; valid values are: {valid}"
+		))
+	})?;
+
+	let level = level.map(str::parse::<i32>).transpose().map_err(|e| {
+		err!(Config(
+			"rocksdb_compression_per_column",
+			"Invalid compression level in {spec:?}: {e}"
+		))
+	})?;
+
+	Ok((compression, level))
+}
+
+fn parse_compression_algo(algo: &str) -> Option<CompressionType> {
+	match algo {
+		| "zstd" => Some(CompressionType::Zstd),
+		| "snappy" => Some(CompressionType::Snappy),
+		| "zlib" => Some(CompressionType::Zlib),
+		| "bz2" => Some(CompressionType::Bz2),
+		| "lz4" => Some(CompressionType::Lz4),
+		| "lz4hc" => Some(CompressionType::Lz4hc),
+		| "none" => Some(CompressionType::None),
+		| _ => None,
+	}
 }
 
 fn fifo_options(desc: &Descriptor) -> FifoCompactOptions {
@@ -216,7 +275,11 @@ fn get_cache(ctx: &Context, desc: &Descriptor) -> Option<Cache> {
 		.unwrap_or_default()
 		.expected_add(desc.val_size_hint.unwrap_or_default());
 
+	let shares = shares::cache_shares(config, desc);
 	let size = match cap {
+		| _ if shares > 0 => ctx
+			.col_cache_shares
+			.size(ctx.col_cache_capacity, shares.into()),
 		| Some(cap) => cache_size(config, cap, ent_size),
 		| _ => desc.cache_size,
 	};
@@ -233,34 +296,31 @@ fn get_cache(ctx: &Context, desc: &Descriptor) -> Option<Cache> {
 	cache_opts.set_capacity(size);
 
 	let mut caches = ctx.col_cache.lock().expect("locked");
-	match desc.cache_disp {
-		| CacheDisp::Unique if desc.cache_size == 0 => None,
-		| CacheDisp::Unique => {
-			let cache = Cache::new_lru_cache_opts(&cache_opts);
-			caches.insert(desc.name.into(), cache.clone());
-			Some(cache)
-		},
-
-		| CacheDisp::SharedWith(other) if !caches.contains_key(other) => {
-			let cache = Cache::new_lru_cache_opts(&cache_opts);
-			caches.insert(desc.name.into(), cache.clone());
-			Some(cache)
-		},
-
-		| CacheDisp::SharedWith(other) => Some(
-			caches
-				.get(other)
-				.cloned()
-				.expect("caches.contains_key(other) must be true"),
-		),
-
+	let existing = match desc.cache_disp {
+		| CacheDisp::Unique if desc.cache_size == 0 && shares == 0 => return None,
+		| CacheDisp::Unique => None,
+		| CacheDisp::Shared if shares > 0 => None,
+		| CacheDisp::SharedWith(other) => caches.get(other).cloned(),
 		| CacheDisp::Shared => Some(
 			caches
 				.get("Shared")
 				.cloned()
 				.expect("shared cache must already exist"),
 		),
+	};
+
+	if existing.is_some() {
+		return existing;
 	}
+
+	let cache = Cache::new_lru_cache_opts(&cache_opts);
+	caches.insert(desc.name.into(), cache.clone());
+	ctx.col_cache_sizes
+		.lock()
+		.expect("locked")
+		.insert(desc.name.into(), size);
+
+	Some(cache)
 }
 
 pub(crate) fn cache_size(config: &Config, base_size: u32, entity_size: usize) -> usize {
@@ -0,0 +1,108 @@
+use conduwuit::{Result, debug, implement, warn};
+use rocksdb::{AsColumnFamilyRef, LiveFile as SstFile, ReadOptions};
+
+use super::{Db, Engine};
+use crate::util::map_err;
+
+/// Outcome of verifying the checksums of one column.
+#[derive(Debug, Default)]
+pub struct Check {
+	/// Number of keys read successfully.
+	pub keys: u64,
+
+	/// Number of table files (sampled) or ranges which failed to read.
+	pub errors: usize,
+
+	/// The first error encountered, if any.
+	pub first_error: Option<String>,
+}
+
+/// Reads every key in the column with checksum verification enabled. When a
+/// `sample` fraction less than one is given only that share of the column's
+/// table files are read, each over the key range the file covers. Errors are
+/// collected rather than aborting so the whole column is always visited.
+#[implement(Engine)]
+#[tracing::instrument(level = "info", skip(self))]
+pub fn check(&self, name: &str, sample: Option<f64>) -> Result<Check> {
+	let cf = self.cf(name);
+	let files: Vec<SstFile> = match sample {
+		| Some(sample) if sample < 1.0 => self
+			.db
+			.live_files()
+			.map_err(map_err)?
+			.into_iter()
+			.filter(|file| file.column_family_name == name)
+			.collect(),
+		| _ => Vec::new(),
+	};
+
+	Ok(check_column(&self.db, &cf, &files, sample))
+}
+
+pub(crate) fn check_column(
+	db: &Db,
+	cf: &impl AsColumnFamilyRef,
+	files: &[SstFile],
+	sample: Option<f64>,
+) -> Check {
+	let mut check = Check::default();
+	let Some(sample) = sample.filter(|&sample| sample < 1.0) else {
+		check_range(db, cf, None, None, &mut check);
+		return check;
+	};
+
+	let mut files: Vec<_> = files.iter().collect();
+	files.sort_by(|a, b| a.name.cmp(&b.name));
+
+	let stride = sample_stride(sample);
+	debug!(files = files.len(), ?stride, "Sampling table files");
+	for file in files.into_iter().step_by(stride) {
+		check_range(db, cf, file.start_key.as_deref(), file.end_key.as_deref(), &mut check);
+	}
+
+	check
+}
+
+fn check_range(
+	db: &Db,
+	cf: &impl AsColumnFamilyRef,
+	start: Option<&[u8]>,
+	end: Option<&[u8]>,
+	check: &mut Check,
+) {
+	let mut opts = ReadOptions::default();
+	opts.set_verify_checksums(true);
+	opts.fill_cache(false);
+
+	let mut it = db.raw_iterator_cf_opt(cf, opts);
+	match start {
+		| Some(start) => it.seek(start),
+		| None => it.seek_to_first(),
+	}
+
+	while let Some(key) = it.key() {
+		if end.is_some_and(|end| key > end) {
+			break;
+		}
+
+		check.keys = check.keys.saturating_add(1);
+		it.next();
+	}
+
+	if let Err(e) = it.status() {
+		warn!(?start, ?end, "Column check failed: {e}");
+		check.errors = check.errors.saturating_add(1);
+		check.first_error.get_or_insert_with(|| e.into_string());
+	}
+}
+
+/// Converts a sampling fraction into the step between sampled files; at least
+/// one file is always read.
+#[allow(clippy::as_conversions, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn sample_stride(sample: f64) -> usize {
+	if sample <= 0.0 || !sample.is_finite() {
+		return usize::MAX;
+	}
+
+	(1.0 / sample).ceil().max(1.0) as usize
+}
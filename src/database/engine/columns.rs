@@ -0,0 +1,71 @@
+use conduwuit::{Err, Result, implement, info, utils::bytes::pretty};
+use rocksdb::Options;
+
+use super::{Db, Engine};
+use crate::{maps::MAPS, util::map_err};
+
+/// Columns present in the database which this version of the server does not
+/// describe. They are opened, but never read or written.
+#[implement(Engine)]
+#[must_use]
+pub fn unknown_columns(&self) -> Vec<String> {
+	Db::list_cf(&Options::default(), self.db.path())
+		.unwrap_or_default()
+		.into_iter()
+		.filter(|name| !is_described(name))
+		.collect()
+}
+
+/// Drops an unknown column and all of its data, returning the estimated size
+/// of its table files. Columns in use by the server cannot be dropped.
+#[implement(Engine)]
+#[tracing::instrument(level = "info", skip(self))]
+pub fn drop_column(&self, name: &str) -> Result<u64> {
+	if self.is_read_only() {
+		return Err!("Columns cannot be dropped in read-only or secondary mode.");
+	}
+
+	if is_described(name) {
+		return Err!("Column {name:?} is in use by the server and cannot be dropped.");
+	}
+
+	let Some(cf) = self.db.cf_handle(name) else {
+		return Err!("Column {name:?} does not exist.");
+	};
+
+	let size = self
+		.property_integer(&cf, c"rocksdb.total-sst-files-size")
+		.unwrap_or(0);
+
+	drop(cf);
+	self.db.drop_cf(name).map_err(map_err)?;
+
+	Ok(size)
+}
+
+/// Drops every unknown column, as for `rocksdb_drop_unknown_columns`.
+#[implement(Engine)]
+#[tracing::instrument(level = "info", skip(self))]
+pub(crate) fn drop_unknown_columns(&self) -> Result {
+	let mut total: u64 = 0;
+	let columns = self.unknown_columns();
+	for name in &columns {
+		let size = self.drop_column(name)?;
+		total = total.saturating_add(size);
+		info!(size = %pretty(size.try_into()?), "Dropped unknown column {name:?}");
+	}
+
+	if !columns.is_empty() {
+		info!(
+			columns = columns.len(),
+			"Reclaimed an estimated {} from unknown columns.",
+			pretty(total.try_into()?)
+		);
+	}
+
+	Ok(())
+}
+
+fn is_described(name: &str) -> bool {
+	name == "default" || MAPS.iter().any(|desc| desc.name == name)
+}
@@ -6,6 +6,7 @@
 use conduwuit::{Result, Server, debug, utils::math::usize_from_f64};
 use rocksdb::{Cache, Env, LruCacheOptions};
 
+use super::shares::{self, Shares};
 use crate::{or_else, pool::Pool};
 
 /// Some components are constructed prior to opening the database and must
@@ -15,6 +16,9 @@
 pub(crate) struct Context {
 	pub(crate) pool: Arc<Pool>,
 	pub(crate) col_cache: Mutex<BTreeMap<String, Cache>>,
+	pub(crate) col_cache_sizes: Mutex<BTreeMap<String, usize>>,
+	pub(crate) col_cache_capacity: usize,
+	pub(crate) col_cache_shares: Shares,
 	pub(crate) row_cache: Mutex<Cache>,
 	pub(crate) env: Mutex<Env>,
 	pub(crate) server: Arc<Server>,
@@ -28,6 +32,14 @@ pub(crate) fn new(server: &Arc<Server>) -> Result<Arc<Self>> {
 		let col_shard_bits = 7;
 		let col_cache_capacity_bytes = usize_from_f64(cache_capacity_bytes * 0.50)?;
 
+		shares::check(config);
+		let col_cache_shares = shares::shares(config);
+		let shared_cache_capacity_bytes = if col_cache_shares.partitioned() {
+			col_cache_shares.size(col_cache_capacity_bytes, col_cache_shares.common)
+		} else {
+			col_cache_capacity_bytes
+		};
+
 		let row_shard_bits = 7;
 		let row_cache_capacity_bytes = usize_from_f64(cache_capacity_bytes * 0.50)?;
 
@@ -38,9 +50,11 @@ pub(crate) fn new(server: &Arc<Server>) -> Result<Arc<Self>> {
 
 		let mut col_cache_opts = LruCacheOptions::default();
 		col_cache_opts.set_num_shard_bits(col_shard_bits);
-		col_cache_opts.set_capacity(col_cache_capacity_bytes);
+		col_cache_opts.set_capacity(shared_cache_capacity_bytes);
 		let col_cache = Cache::new_lru_cache_opts(&col_cache_opts);
 		let col_cache: BTreeMap<_, _> = [("Shared".to_owned(), col_cache)].into();
+		let col_cache_sizes: BTreeMap<_, _> =
+			[("Shared".to_owned(), shared_cache_capacity_bytes)].into();
 
 		let mut env = Env::new().or_else(or_else)?;
 
@@ -55,6 +69,9 @@ pub(crate) fn new(server: &Arc<Server>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			pool: Pool::new(server)?,
 			col_cache: col_cache.into(),
+			col_cache_sizes: col_cache_sizes.into(),
+			col_cache_capacity: col_cache_capacity_bytes,
+			col_cache_shares,
 			row_cache: row_cache.into(),
 			env: env.into(),
 			server: server.clone(),
@@ -5,6 +5,16 @@
 
 use super::{cf_opts::cache_size_f64, logger::handle as handle_log};
 
+/// Whether `rocksdb_stats_level` enables the statistics object; the options
+/// of an open database then hold a handle to it.
+pub(crate) fn statistics_enabled(config: &Config) -> bool {
+	match config.rocksdb_stats_level {
+		| 0 => false,
+		| 1 => cfg!(debug_assertions),
+		| 2_u8..=u8::MAX => true,
+	}
+}
+
 /// Create database-wide options suitable for opening the database. This also
 /// sets our default column options in case of opening a column with the same
 /// resulting value. Note that we require special per-column options on some
@@ -29,12 +29,16 @@ pub(crate) struct Descriptor {
 	pub(crate) compaction: CompactionStyle,
 	pub(crate) compaction_pri: CompactionPri,
 	pub(crate) compression: CompressionType,
+	pub(crate) column_compression: Option<CompressionType>,
+	pub(crate) bottommost_compression: Option<CompressionType>,
+	pub(crate) column_compression_level: Option<i32>,
 	pub(crate) compressed_index: bool,
 	pub(crate) compression_shape: [i32; 7],
 	pub(crate) compression_level: i32,
 	pub(crate) bottommost_level: Option<i32>,
 	pub(crate) block_index_hashing: Option<bool>,
 	pub(crate) cache_shards: u32,
+	pub(crate) cache_shares: u32,
 	pub(crate) write_to_cache: bool,
 	pub(crate) auto_readahead_thresh: u32,
 	pub(crate) auto_readahead_init: usize,
@@ -71,12 +75,16 @@ pub(crate) enum CacheDisp {
 	compaction: CompactionStyle::Level,
 	compaction_pri: CompactionPri::MinOverlappingRatio,
 	compression: CompressionType::Zstd,
+	column_compression: None,
+	bottommost_compression: None,
+	column_compression_level: None,
 	compressed_index: true,
 	compression_shape: [0, 0, 0, 1, 1, 1, 1],
 	compression_level: SENTINEL_COMPRESSION_LEVEL,
 	bottommost_level: Some(SENTINEL_COMPRESSION_LEVEL),
 	block_index_hashing: None,
 	cache_shards: 64,
+	cache_shares: 0,
 	write_to_cache: false,
 	auto_readahead_thresh: 0,
 	auto_readahead_init: 1024 * 16,
@@ -22,8 +22,17 @@ pub fn memory_usage(&self) -> Result<String> {
 		mibs(u64::try_from(self.ctx.row_cache.lock()?.get_usage())?),
 	)?;
 
+	let sizes = self.ctx.col_cache_sizes.lock()?.clone();
 	for (name, cache) in &*self.ctx.col_cache.lock()? {
-		writeln!(res, "{name} cache: {:.2} MiB", mibs(u64::try_from(cache.get_usage())?))?;
+		let usage = mibs(u64::try_from(cache.get_usage())?);
+		match sizes.get(name) {
+			| Some(&size) => writeln!(
+				res,
+				"{name} cache: {usage:.2} of {:.2} MiB",
+				mibs(u64::try_from(size)?)
+			)?,
+			| None => writeln!(res, "{name} cache: {usage:.2} MiB")?,
+		}
 	}
 
 	Ok(res)
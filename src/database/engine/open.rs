@@ -2,6 +2,7 @@
 	collections::BTreeSet,
 	path::Path,
 	sync::{Arc, atomic::AtomicU32},
+	time::Duration,
 };
 
 use conduwuit::{Result, debug, implement, info, warn};
@@ -9,12 +10,13 @@
 
 use super::{
 	Db, Engine,
-	cf_opts::cf_options,
-	db_opts::db_options,
+	cf_opts::{cf_options, parse_column_compression},
+	db_opts::{db_options, statistics_enabled},
 	descriptor::{self, Descriptor},
 	repair::repair,
+	retry,
 };
-use crate::{Context, or_else};
+use crate::Context;
 
 #[implement(Engine)]
 #[tracing::instrument(skip_all)]
@@ -29,8 +31,7 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 		&ctx.row_cache.lock().expect("row cache locked"),
 	)?;
 
-	let cfds = Self::configure_cfds(&ctx, &db_opts, desc)?;
-	let num_cfds = cfds.len();
+	let num_cfds = Self::configure_cfds(&ctx, &db_opts, desc)?.len();
 	debug!("Configured {num_cfds} column descriptors...");
 
 	let load_time = std::time::Instant::now();
@@ -38,15 +39,28 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 		repair(&db_opts, &config.database_path)?;
 	}
 
-	debug!("Opening database...");
-	let db = if config.rocksdb_read_only {
-		Db::open_cf_descriptors_read_only(&db_opts, path, cfds, false)
-	} else if config.rocksdb_secondary {
-		Db::open_cf_descriptors_as_secondary(&db_opts, path, path, cfds)
-	} else {
-		Db::open_cf_descriptors(&db_opts, path, cfds)
-	}
-	.or_else(or_else)?;
+	let policy = retry::Policy {
+		repair: !config.rocksdb_read_only
+			&& !config.rocksdb_secondary
+			&& config.rocksdb_recovery_mode >= 2,
+		lock_retries: config.rocksdb_open_lock_retries,
+		lock_backoff: Duration::from_millis(config.rocksdb_open_lock_backoff_ms),
+	};
+
+	let open = || {
+		debug!("Opening database...");
+		let cfds = Self::configure_cfds(&ctx, &db_opts, desc)?;
+
+		Ok(if config.rocksdb_read_only {
+			Db::open_cf_descriptors_read_only(&db_opts, path, cfds, false)
+		} else if config.rocksdb_secondary {
+			Db::open_cf_descriptors_as_secondary(&db_opts, path, path, cfds)
+		} else {
+			Db::open_cf_descriptors(&db_opts, path, cfds)
+		})
+	};
+
+	let db = retry::open(&policy, open, || repair(&db_opts, path)).await?;
 
 	info!(
 		columns = num_cfds,
@@ -55,7 +69,7 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 		"Opened database."
 	);
 
-	Ok(Arc::new(Self {
+	let engine = Arc::new(Self {
 		db,
 		pool: ctx.pool.clone(),
 		ctx: ctx.clone(),
@@ -63,7 +77,14 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 		secondary: config.rocksdb_secondary,
 		checksums: config.rocksdb_checksums,
 		corks: AtomicU32::new(0),
-	}))
+		statistics: statistics_enabled(config).then_some(db_opts),
+	});
+
+	if config.rocksdb_drop_unknown_columns {
+		engine.drop_unknown_columns()?;
+	}
+
+	Ok(engine)
 }
 
 #[implement(Engine)]
@@ -101,6 +122,13 @@ fn configure_cfds(
 		debug!("Creating new column {name:?} not previously found in existing database.");
 	});
 
+	for (name, spec) in &config.rocksdb_compression_per_column {
+		parse_column_compression(spec)?;
+		if !desc.iter().any(|desc| desc.name == name) {
+			warn!("Compression override for unknown column {name:?} has no effect.");
+		}
+	}
+
 	let missing_descriptors = missing.clone().map(|_| descriptor::DROPPED);
 
 	let cfopts: Vec<_> = desc
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use conduwuit::{Err, Result, err, error, warn};
+use rocksdb::ErrorKind;
+use tokio::time::sleep;
+
+use super::Db;
+use crate::util::map_err;
+
+/// How failures to open the database are retried.
+#[derive(Clone, Debug)]
+pub(crate) struct Policy {
+	/// Repair the database once and retry when it is found corrupt.
+	pub(crate) repair: bool,
+
+	/// Retries while another process holds the database lock.
+	pub(crate) lock_retries: u32,
+
+	/// Delay before the first lock retry; doubled for each further retry.
+	pub(crate) lock_backoff: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Failure {
+	Corruption,
+	Locked,
+	Other,
+}
+
+/// Upper bound on the delay between lock retries.
+const MAX_LOCK_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Calls `open` until it succeeds or fails in a way the policy does not retry.
+/// A corrupt database is repaired with `repair` at most once. Errors other
+/// than those from RocksDB are returned immediately.
+pub(crate) async fn open<O, R>(policy: &Policy, mut open: O, mut repair: R) -> Result<Db>
+where
+	O: FnMut() -> Result<Result<Db, rocksdb::Error>>,
+	R: FnMut() -> Result,
+{
+	let mut errors: Vec<rocksdb::Error> = Vec::new();
+	let (mut repaired, mut retries) = (false, 0_u32);
+	loop {
+		let e = match open()? {
+			| Ok(db) => return Ok(db),
+			| Err(e) => e,
+		};
+
+		let failure = classify(&e);
+		errors.push(e);
+		match failure {
+			| Failure::Corruption if policy.repair && !repaired => {
+				error!(
+					"Database is corrupt: {}. Attempting automatic repair as permitted by \
+					 rocksdb_recovery_mode; back up the database directory if this fails.",
+					errors.last().expect("error recorded")
+				);
+
+				repaired = true;
+				if let Err(e) = repair() {
+					return Err!(Database("{}; automatic repair failed: {e}", summary(&errors)));
+				}
+			},
+			| Failure::Locked if retries < policy.lock_retries => {
+				let delay = backoff(policy.lock_backoff, retries);
+				retries = retries.saturating_add(1);
+				warn!(
+					?delay,
+					retry = retries,
+					of = policy.lock_retries,
+					"Database is locked, possibly by a previous instance still shutting down."
+				);
+
+				sleep(delay).await;
+			},
+			| _ => break,
+		}
+	}
+
+	if errors.len() == 1 {
+		return Err(map_err(errors.pop().expect("error recorded")));
+	}
+
+	Err(err!(Database(
+		"Failed to open database after {} attempts: {}",
+		errors.len(),
+		summary(&errors)
+	)))
+}
+
+pub(crate) fn classify(e: &rocksdb::Error) -> Failure {
+	match e.kind() {
+		| ErrorKind::Corruption => Failure::Corruption,
+		| ErrorKind::IOError | ErrorKind::Busy
+			if e.to_string().to_ascii_lowercase().contains("lock") =>
+			Failure::Locked,
+		| _ => Failure::Other,
+	}
+}
+
+pub(crate) fn backoff(base: Duration, retry: u32) -> Duration {
+	base.saturating_mul(2_u32.saturating_pow(retry))
+		.min(MAX_LOCK_BACKOFF)
+}
+
+/// The first error, which is usually the most telling, and the last.
+fn summary(errors: &[rocksdb::Error]) -> String {
+	match errors {
+		| [] => String::new(),
+		| [only] => only.to_string(),
+		| [first, .., last] => format!("first error: {first}; last error: {last}"),
+	}
+}
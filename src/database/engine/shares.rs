@@ -0,0 +1,83 @@
+use conduwuit::{Config, warn};
+
+use super::descriptor::{CacheDisp, Descriptor};
+use crate::maps::MAPS;
+
+/// Division of the column block cache between the columns given shares of it
+/// and the common cache.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Shares {
+	/// Shares of the common cache; one for each column using it.
+	pub(crate) common: u64,
+
+	/// Shares of the common cache and of every column given shares.
+	pub(crate) total: u64,
+}
+
+impl Shares {
+	/// Size of a cache with `shares` of the column cache `capacity`.
+	pub(crate) fn size(&self, capacity: usize, shares: u64) -> usize {
+		let capacity: u64 = capacity.try_into().unwrap_or(u64::MAX);
+		let size = u128::from(capacity)
+			.saturating_mul(u128::from(shares))
+			.checked_div(u128::from(self.total))
+			.unwrap_or(u128::from(capacity));
+
+		size.try_into().unwrap_or(usize::MAX)
+	}
+
+	/// Whether any column has a cache of its own carved out of the column
+	/// cache.
+	pub(crate) fn partitioned(&self) -> bool { self.total > self.common }
+}
+
+pub(crate) fn shares(config: &Config) -> Shares {
+	let mut shares = Shares::default();
+	for desc in MAPS.iter().filter(|desc| !desc.dropped) {
+		let weight = u64::from(cache_shares(config, desc));
+		match desc.cache_disp {
+			| CacheDisp::Shared if weight == 0 => shares.common = shares.common.saturating_add(1),
+			| CacheDisp::SharedWith(other) if other < desc.name && described(other) => continue,
+			| _ => shares.total = shares.total.saturating_add(weight),
+		}
+	}
+
+	shares.total = shares.total.saturating_add(shares.common);
+	shares
+}
+
+/// Shares of the cache used by a column. Columns sharing a cache with another
+/// take the larger shares of the two.
+pub(crate) fn cache_shares(config: &Config, desc: &Descriptor) -> u32 {
+	let shares = column_shares(config, desc);
+	match desc.cache_disp {
+		| CacheDisp::SharedWith(other) => MAPS
+			.iter()
+			.find(|desc| desc.name == other)
+			.map_or(shares, |other| shares.max(column_shares(config, other))),
+		| _ => shares,
+	}
+}
+
+/// Warns of overrides in `rocksdb_cache_shares_per_column` which have no
+/// effect.
+pub(crate) fn check(config: &Config) {
+	for name in config.rocksdb_cache_shares_per_column.keys() {
+		if !described(name) {
+			warn!(
+				"Ignoring cache shares for an unknown column {name:?} in \
+				 rocksdb_cache_shares_per_column."
+			);
+		}
+	}
+}
+
+fn column_shares(config: &Config, desc: &Descriptor) -> u32 {
+	config
+		.rocksdb_cache_shares_per_column
+		.get(desc.name)
+		.copied()
+		.unwrap_or(desc.cache_shares)
+}
+
+fn described(name: &str) -> bool { MAPS.iter().any(|desc| desc.name == name && !desc.dropped) }
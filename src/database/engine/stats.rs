@@ -0,0 +1,88 @@
+use conduwuit::implement;
+use rocksdb::statistics::{Histogram, Ticker};
+
+use super::Engine;
+
+/// Snapshot of the engine's statistics and live properties.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+	/// Counters from the statistics object; None when `rocksdb_stats_level`
+	/// disables collection.
+	pub tickers: Option<Tickers>,
+
+	/// Size of all memtables across columns in bytes.
+	pub memtables_size: u64,
+
+	/// Estimated bytes compaction must rewrite to settle every column.
+	pub pending_compaction_bytes: u64,
+
+	/// Metric name and count of slow operations for each column which had
+	/// any; see `db_slow_query_threshold`.
+	pub slow_ops: Vec<(&'static str, u64)>,
+}
+
+/// Cumulative counters since the database was opened.
+#[derive(Clone, Debug, Default)]
+pub struct Tickers {
+	pub block_cache_hit: u64,
+	pub block_cache_miss: u64,
+	pub flushes: u64,
+	pub flush_write_bytes: u64,
+	pub stall_micros: u64,
+	pub compaction_read_bytes: u64,
+	pub compaction_write_bytes: u64,
+}
+
+impl Stats {
+	/// The snapshot as named values, suitable for a metrics registry.
+	pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + Send + '_ {
+		let tickers = self.tickers.iter().flat_map(|tickers| {
+			[
+				("rocksdb_block_cache_hit", tickers.block_cache_hit),
+				("rocksdb_block_cache_miss", tickers.block_cache_miss),
+				("rocksdb_flushes", tickers.flushes),
+				("rocksdb_flush_write_bytes", tickers.flush_write_bytes),
+				("rocksdb_stall_micros", tickers.stall_micros),
+				("rocksdb_compaction_read_bytes", tickers.compaction_read_bytes),
+				("rocksdb_compaction_write_bytes", tickers.compaction_write_bytes),
+			]
+		});
+
+		tickers
+			.chain([
+				("rocksdb_memtables_size", self.memtables_size),
+				("rocksdb_pending_compaction_bytes", self.pending_compaction_bytes),
+			])
+			.chain(self.slow_ops.iter().copied())
+	}
+}
+
+impl std::fmt::Display for Stats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		for (name, value) in self.iter() {
+			writeln!(f, "{name}: {value}")?;
+		}
+
+		if self.tickers.is_none() {
+			writeln!(f, "(statistics disabled by rocksdb_stats_level)")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Reads the counters of the statistics object, when enabled.
+#[implement(Engine)]
+pub(crate) fn tickers(&self) -> Option<Tickers> {
+	let opts = self.statistics.as_ref()?;
+
+	Some(Tickers {
+		block_cache_hit: opts.get_ticker_count(Ticker::BlockCacheHit),
+		block_cache_miss: opts.get_ticker_count(Ticker::BlockCacheMiss),
+		flushes: opts.get_histogram_data(Histogram::FlushTime).count(),
+		flush_write_bytes: opts.get_ticker_count(Ticker::FlushWriteBytes),
+		stall_micros: opts.get_ticker_count(Ticker::StallMicros),
+		compaction_read_bytes: opts.get_ticker_count(Ticker::CompactReadBytes),
+		compaction_write_bytes: opts.get_ticker_count(Ticker::CompactWriteBytes),
+	})
+}
@@ -0,0 +1,244 @@
+use std::{
+	fs::File,
+	io,
+	io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+	path::Path,
+};
+
+use conduwuit::{Err, Result, implement};
+use rocksdb::{AsColumnFamilyRef, ReadOptions, WriteBatchWithTransaction, WriteOptions};
+
+use super::{Db, Engine, batch};
+use crate::util::map_err;
+
+/// Outcome of importing a column from a file.
+#[derive(Debug, Default)]
+pub struct Import {
+	/// Number of keys written to the column.
+	pub written: u64,
+
+	/// Number of keys already present in the column and left unchanged.
+	pub skipped: u64,
+}
+
+/// Files start with this and a format version, followed by the name of the
+/// exported column. Each key and value is then prefixed by its big-endian u32
+/// length. The records end with `END` in place of a length and the number of
+/// records as a big-endian u64, so truncated files are detected.
+const MAGIC: &[u8; 4] = b"CWCF";
+const VERSION: u8 = 1;
+const END: u32 = u32::MAX;
+
+/// Number of keys written by each batch during import.
+const BATCH_KEYS: usize = 1024;
+
+/// Largest key or value exported or imported, so a corrupt length cannot make
+/// an import allocate without bound.
+const MAX_CHUNK: u32 = 64 * 1024 * 1024;
+
+/// Writes every key and value of a column to a new file at `path`, returning
+/// the number of keys. The column is read from a snapshot, so this is
+/// permitted in read-only and secondary modes.
+#[implement(Engine)]
+#[tracing::instrument(level = "info", skip(self))]
+pub fn export_column(&self, name: &str, path: &Path) -> Result<u64> {
+	let Some(cf) = self.db.cf_handle(name) else {
+		return Err!("Column {name:?} does not exist.");
+	};
+
+	let mut out = BufWriter::new(File::create_new(path)?);
+	let keys = export_column(&self.db, &cf, name, &mut out)?;
+	out.into_inner()
+		.map_err(io::IntoInnerError::into_error)?
+		.sync_all()?;
+
+	Ok(keys)
+}
+
+/// Writes the keys and values from a file at `path` made by `export_column`
+/// into the column it was exported from. Keys already present are overwritten
+/// only when `overwrite` is true. The whole file is checked before any key is
+/// written, so a file of another column or a truncated or corrupt one changes
+/// nothing. Caches of the column held by services are not refreshed.
+#[implement(Engine)]
+#[tracing::instrument(level = "info", skip(self))]
+pub fn import_column(&self, name: &str, path: &Path, overwrite: bool) -> Result<Import> {
+	if self.is_read_only() {
+		return Err!("Columns cannot be imported in read-only or secondary mode.");
+	}
+
+	let Some(cf) = self.db.cf_handle(name) else {
+		return Err!("Column {name:?} does not exist.");
+	};
+
+	let mut input = BufReader::new(File::open(path)?);
+	let import = import_column(&self.db, &cf, name, &mut input, overwrite)?;
+	if !self.corked() {
+		self.flush()?;
+	}
+
+	Ok(import)
+}
+
+pub(crate) fn export_column(
+	db: &Db,
+	cf: &impl AsColumnFamilyRef,
+	name: &str,
+	out: &mut impl Write,
+) -> Result<u64> {
+	out.write_all(MAGIC)?;
+	out.write_all(&[VERSION])?;
+	write_chunk(out, name.as_bytes())?;
+
+	let snapshot = db.snapshot();
+	let mut opts = ReadOptions::default();
+	opts.set_snapshot(&snapshot);
+	opts.fill_cache(false);
+
+	let mut keys: u64 = 0;
+	let mut it = db.raw_iterator_cf_opt(cf, opts);
+	it.seek_to_first();
+	while let Some((key, val)) = it.item() {
+		write_chunk(out, key)?;
+		write_chunk(out, val)?;
+		keys = keys.saturating_add(1);
+		it.next();
+	}
+
+	if let Err(e) = it.status() {
+		return Err!(Database("Failed to read column {name:?}: {e}"));
+	}
+
+	out.write_all(&END.to_be_bytes())?;
+	out.write_all(&keys.to_be_bytes())?;
+	out.flush()?;
+
+	Ok(keys)
+}
+
+pub(crate) fn import_column(
+	db: &Db,
+	cf: &impl AsColumnFamilyRef,
+	name: &str,
+	input: &mut (impl Read + Seek),
+	overwrite: bool,
+) -> Result<Import> {
+	let start = input.stream_position()?;
+	check_file(input, name)?;
+	input.seek(SeekFrom::Start(start))?;
+	read_header(input)?;
+
+	let mut import = Import::default();
+	let (mut batch, mut batched) = (WriteBatchWithTransaction::<false>::default(), 0_usize);
+	let options = WriteOptions::default();
+	while let Some(key) = read_chunk(input)? {
+		let Some(val) = read_chunk(input)? else {
+			return Err!("Column export file has a key without a value.");
+		};
+
+		if !overwrite && db.get_pinned_cf(cf, &key).map_err(map_err)?.is_some() {
+			import.skipped = import.skipped.saturating_add(1);
+			continue;
+		}
+
+		batch.put_cf(cf, key, val);
+		batched = batched.saturating_add(1);
+		import.written = import.written.saturating_add(1);
+		if batched >= BATCH_KEYS {
+			batch::write(db, std::mem::take(&mut batch), &options);
+			batched = 0;
+		}
+	}
+
+	batch::write(db, batch, &options);
+
+	Ok(import)
+}
+
+/// Reads through a file without keeping its records, failing unless it was
+/// exported from the column `name` and holds every record it accounts for.
+fn check_file(input: &mut impl Read, name: &str) -> Result {
+	let column = read_header(input)?;
+	if column != name {
+		return Err!("Column export file is of column {column:?}, not {name:?}.");
+	}
+
+	let mut records: u64 = 0;
+	while skip_chunk(input)? {
+		if !skip_chunk(input)? {
+			return Err!("Column export file has a key without a value.");
+		}
+
+		records = records.saturating_add(1);
+	}
+
+	let mut count = [0_u8; 8];
+	input.read_exact(&mut count)?;
+	let expected = u64::from_be_bytes(count);
+	if expected != records {
+		return Err!("Column export file records {expected} keys but contains {records}.");
+	}
+
+	Ok(())
+}
+
+/// Reads the format and the name of the exported column.
+fn read_header(input: &mut impl Read) -> Result<String> {
+	let mut magic = [0_u8; 5];
+	input.read_exact(&mut magic)?;
+	if magic[..4] != *MAGIC || magic[4] != VERSION {
+		return Err!("Not a column export file, or one of an unsupported version.");
+	}
+
+	Ok(String::from_utf8(read_chunk(input)?.unwrap_or_default())?)
+}
+
+fn write_chunk(out: &mut impl Write, chunk: &[u8]) -> Result {
+	let len: u32 = chunk.len().try_into()?;
+	if len > MAX_CHUNK {
+		return Err!("Key or value of {len} bytes is too large to export.");
+	}
+
+	out.write_all(&len.to_be_bytes())?;
+	out.write_all(chunk)?;
+
+	Ok(())
+}
+
+/// Reads the next length-prefixed chunk, or `None` at the end of the records.
+fn read_chunk(input: &mut impl Read) -> Result<Option<Vec<u8>>> {
+	let Some(len) = read_len(input)? else {
+		return Ok(None);
+	};
+
+	let mut chunk = vec![0_u8; len.try_into()?];
+	input.read_exact(&mut chunk)?;
+
+	Ok(Some(chunk))
+}
+
+/// Reads past the next length-prefixed chunk, returning false at the end of
+/// the records.
+fn skip_chunk(input: &mut impl Read) -> Result<bool> {
+	let Some(len) = read_len(input)? else {
+		return Ok(false);
+	};
+
+	let skipped = io::copy(&mut input.by_ref().take(len.into()), &mut io::sink())?;
+	if skipped != u64::from(len) {
+		return Err!("Column export file ends within a record.");
+	}
+
+	Ok(true)
+}
+
+fn read_len(input: &mut impl Read) -> Result<Option<u32>> {
+	let mut len = [0_u8; 4];
+	input.read_exact(&mut len)?;
+	match u32::from_be_bytes(len) {
+		| END => Ok(None),
+		| len if len > MAX_CHUNK =>
+			Err!("Column export file has a record of {len} bytes, more than {MAX_CHUNK}."),
+		| len => Ok(Some(len)),
+	}
+}
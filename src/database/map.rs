@@ -12,13 +12,14 @@
 mod options;
 mod qry;
 mod qry_batch;
-mod remove;
+pub(crate) mod remove;
 mod rev_keys;
 mod rev_keys_from;
 mod rev_keys_prefix;
 mod rev_stream;
 mod rev_stream_from;
 mod rev_stream_prefix;
+pub(crate) mod slow;
 mod stream;
 mod stream_from;
 mod stream_prefix;
@@ -40,6 +41,7 @@
 	cache_iter_options_default, cache_read_options_default, iter_options_default,
 	read_options_default, write_options_default,
 };
+use self::slow::Slow;
 pub use self::{get_batch::Get, qry_batch::Qry};
 use crate::{Engine, watchers::Watchers};
 
@@ -51,6 +53,7 @@ pub struct Map {
 	read_options: ReadOptions,
 	cache_read_options: ReadOptions,
 	write_options: WriteOptions,
+	slow: Slow,
 }
 
 impl Map {
@@ -63,6 +66,7 @@ pub(crate) fn open(db: &Arc<Engine>, name: &'static str) -> Result<Arc<Self>> {
 			read_options: read_options_default(db),
 			cache_read_options: cache_read_options_default(db),
 			write_options: write_options_default(db),
+			slow: Slow::new(name, db.ctx.server.config.db_slow_query_threshold),
 		}))
 	}
 
@@ -77,6 +81,9 @@ pub fn watch_prefix<'a, K>(
 		self.watchers.watch(prefix.as_ref())
 	}
 
+	#[inline]
+	pub(crate) fn wake(&self, key: &[u8]) { self.watchers.wake(key); }
+
 	#[inline]
 	pub fn property_integer(&self, name: &CStr) -> Result<u64> {
 		self.db.property_integer(&self.cf(), name)
@@ -88,6 +95,14 @@ pub fn property(&self, name: &str) -> Result<String> { self.db.property(&self.cf
 	#[inline]
 	pub fn name(&self) -> &str { self.name }
 
+	/// Number of operations on this column which exceeded
+	/// `db_slow_query_threshold` since it was opened.
+	#[inline]
+	pub fn slow_ops(&self) -> u64 { self.slow.count() }
+
+	#[inline]
+	pub(crate) fn slow(&self) -> &Slow { &self.slow }
+
 	#[inline]
 	pub(crate) fn db(&self) -> &Arc<Engine> { &self.db }
 
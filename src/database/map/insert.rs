@@ -198,9 +198,10 @@ pub fn insert<K, V>(&self, key: &K, val: V)
 	V: AsRef<[u8]>,
 {
 	let write_options = &self.write_options;
-	self.db
-		.db
-		.put_cf_opt(&self.cf(), key, val, write_options)
+	self.slow
+		.time("insert", key.as_ref().len(), || {
+			self.db.db.put_cf_opt(&self.cf(), key, val, write_options)
+		})
 		.or_else(or_else)
 		.expect("database insert error");
 
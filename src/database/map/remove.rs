@@ -1,9 +1,10 @@
 use std::{convert::AsRef, fmt::Debug, io::Write};
 
 use conduwuit::{arrayvec::ArrayVec, implement};
+use rocksdb::{AsColumnFamilyRef, WriteBatchWithTransaction};
 use serde::Serialize;
 
-use crate::{keyval::KeyBuf, ser, util::or_else};
+use crate::{engine::Db, keyval::KeyBuf, ser, util::or_else};
 
 #[implement(super::Map)]
 #[inline]
@@ -53,3 +54,69 @@ pub fn remove<K>(&self, key: &K)
 		self.db.flush().expect("database flush error");
 	}
 }
+
+/// Delete all keys in the range `[from, to)` with a single range tombstone
+/// rather than one tombstone per key.
+#[implement(super::Map)]
+#[tracing::instrument(skip(self), fields(%self), level = "trace")]
+pub fn delete_range(&self, from: &[u8], to: &[u8]) {
+	let mut batch = WriteBatchWithTransaction::<false>::default();
+	batch.delete_range_cf(&self.cf(), from, to);
+	self.write_deletions(batch);
+}
+
+/// Delete all keys starting with `prefix`. See delete_range().
+#[implement(super::Map)]
+#[tracing::instrument(skip(self), fields(%self), level = "trace")]
+pub fn delete_prefix(&self, prefix: &[u8]) {
+	let mut batch = WriteBatchWithTransaction::<false>::default();
+	stage_delete_prefix(&mut batch, &self.db.db, &self.cf(), prefix);
+	self.write_deletions(batch);
+}
+
+#[implement(super::Map)]
+fn write_deletions(&self, batch: WriteBatchWithTransaction<false>) {
+	let write_options = &self.write_options;
+	self.db
+		.db
+		.write_opt(batch, write_options)
+		.or_else(or_else)
+		.expect("database delete range error");
+
+	if !self.db.corked() {
+		self.db.flush().expect("database flush error");
+	}
+}
+
+/// Stages on `batch` the deletion of all keys starting with `prefix`.
+pub(crate) fn stage_delete_prefix(
+	batch: &mut WriteBatchWithTransaction<false>,
+	db: &Db,
+	cf: &impl AsColumnFamilyRef,
+	prefix: &[u8],
+) {
+	if let Some(upper) = prefix_upper_bound(prefix) {
+		batch.delete_range_cf(cf, prefix, upper);
+		return;
+	}
+
+	// A prefix of only 0xFF bytes has no exclusive upper bound; every key from
+	// the prefix onward matches it, so the range ends at the last key which is
+	// then deleted on its own.
+	let mut it = db.raw_iterator_cf(cf);
+	it.seek_to_last();
+	if let Some(last) = it.key().filter(|last| last.starts_with(prefix)) {
+		batch.delete_range_cf(cf, prefix, last);
+		batch.delete_cf(cf, last);
+	}
+}
+
+/// The smallest key greater than every key starting with `prefix`, or None
+/// when the prefix consists only of 0xFF bytes.
+pub(crate) fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+	let end = prefix.iter().rposition(|&byte| byte != u8::MAX)?;
+	let mut upper = prefix[..=end].to_vec();
+	upper[end] = upper[end].saturating_add(1);
+
+	Some(upper)
+}
@@ -0,0 +1,62 @@
+use std::{
+	sync::atomic::{AtomicU64, Ordering},
+	time::{Duration, Instant},
+};
+
+use conduwuit::warn;
+
+/// Detects single operations on a column taking longer than the configured
+/// `db_slow_query_threshold`. When disabled the timed paths cost one branch.
+pub(crate) struct Slow {
+	column: &'static str,
+	threshold: Option<Duration>,
+	count: AtomicU64,
+	metric: &'static str,
+}
+
+impl Slow {
+	pub(crate) fn new(column: &'static str, threshold_ms: u64) -> Self {
+		Self {
+			column,
+			threshold: (threshold_ms > 0).then(|| Duration::from_millis(threshold_ms)),
+			count: AtomicU64::new(0),
+			// Metric names are static; one is leaked for each column opened.
+			metric: format!("db_slow_ops_{column}").leak(),
+		}
+	}
+
+	/// Runs `f`, recording it as a slow `op` when it exceeds the threshold.
+	#[inline]
+	pub(crate) fn time<T, F>(&self, op: &'static str, key_len: usize, f: F) -> T
+	where
+		F: FnOnce() -> T,
+	{
+		if self.threshold.is_none() {
+			return f();
+		}
+
+		let started = Instant::now();
+		let ret = f();
+		self.observe(op, key_len, started.elapsed());
+		ret
+	}
+
+	/// Records an operation which took `elapsed`, returning true when it was
+	/// logged and counted as slow.
+	pub(crate) fn observe(&self, op: &'static str, key_len: usize, elapsed: Duration) -> bool {
+		if self.threshold.is_none_or(|threshold| elapsed < threshold) {
+			return false;
+		}
+
+		self.count.fetch_add(1, Ordering::Relaxed);
+		warn!(column = self.column, op, key_len, ?elapsed, "Slow database operation");
+		true
+	}
+
+	/// Number of slow operations since the column was opened.
+	#[inline]
+	pub(crate) fn count(&self) -> u64 { self.count.load(Ordering::Relaxed) }
+
+	#[inline]
+	pub(crate) fn metric(&self) -> &'static str { self.metric }
+}
@@ -104,6 +104,10 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "mediaid_file",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "mediaid_info",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "mediaid_user",
 		..descriptor::RANDOM_SMALL
@@ -121,6 +125,10 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		index_size: 512,
 		..descriptor::SEQUENTIAL
 	},
+	Descriptor {
+		name: "publicroomid_folded",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "publicroomids",
 		..descriptor::RANDOM_SMALL
@@ -141,6 +149,18 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "referencedevents",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "reindex_progress",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "renewaltoken_userid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "roomid_freeze",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_invitedcount",
 		..descriptor::RANDOM_SMALL
@@ -167,6 +187,18 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		val_size_hint: Some(8),
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "roomday_activity",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "roomday_stategrowth",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "roomdaysender_activity",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomserverids",
 		..descriptor::RANDOM_SMALL
@@ -231,6 +263,14 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "servercurrentevent_data",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "servername_backoff",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "servername_defunct",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "servername_destination",
 		..descriptor::RANDOM_SMALL_CACHE
@@ -243,6 +283,12 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "servername_override",
 		..descriptor::RANDOM_SMALL_CACHE
 	},
+	Descriptor {
+		name: "servername_shortservername",
+		cache_disp: CacheDisp::Unique,
+		val_size_hint: Some(8),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "servernameevent_data",
 		cache_disp: CacheDisp::Unique,
@@ -266,6 +312,12 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		val_size_hint: Some(48),
 		..descriptor::SEQUENTIAL_SMALL
 	},
+	Descriptor {
+		name: "shorteventid_provenance",
+		key_size_hint: Some(8),
+		val_size_hint: Some(17),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "shorteventid_shortstatehash",
 		key_size_hint: Some(8),
@@ -274,6 +326,12 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		index_size: 512,
 		..descriptor::SEQUENTIAL
 	},
+	Descriptor {
+		name: "shortservername_servername",
+		cache_disp: CacheDisp::Unique,
+		key_size_hint: Some(8),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "shortstatehash_statediff",
 		key_size_hint: Some(8),
@@ -326,6 +384,10 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		block_size: 512,
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "uisiid_report",
+		..descriptor::SEQUENTIAL_SMALL
+	},
 	Descriptor {
 		name: "url_previews",
 		..descriptor::RANDOM
@@ -338,6 +400,10 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "userdeviceid_token",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userdeviceid_tombstone",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userdevicesessionid_uiaainfo",
 		..descriptor::RANDOM_SMALL
@@ -350,6 +416,10 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "userfilterid_filter",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_accountvalidity",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_avatarurl",
 		..descriptor::RANDOM_SMALL
@@ -358,6 +428,10 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "userid_blurhash",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_deactivated",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_devicelistversion",
 		..descriptor::RANDOM_SMALL
@@ -366,6 +440,10 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "userid_displayname",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_email",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_lastonetimekeyupdate",
 		..descriptor::RANDOM_SMALL
@@ -374,6 +452,10 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "userid_masterkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_mediaid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_password",
 		..descriptor::RANDOM
@@ -382,10 +464,22 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "userid_presenceid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_renewaltoken",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_selfsigningkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_shadowbanned",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_suspended",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_usersigningkeyid",
 		..descriptor::RANDOM_SMALL
@@ -426,4 +520,16 @@ pub(super) fn open_list(db: &Arc<Engine>, maps: &[Descriptor]) -> Result<Maps> {
 		name: "userroomid_notificationcount",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "userroomid_profileoverride",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userroomthreadid_highlightcount",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userroomthreadid_notificationcount",
+		..descriptor::RANDOM_SMALL
+	},
 ];
@@ -32,9 +32,11 @@
 pub use self::{
 	de::{Ignore, IgnoreAll},
 	deserialized::Deserialized,
+	engine::{Batch, Import, Stats, Tickers, check::Check},
 	handle::Handle,
 	keyval::{KeyVal, Slice, serialize_key, serialize_val},
 	map::{Get, Map, Qry, compact},
+	pool::{Lane, LaneStats},
 	ser::{Cbor, Interfix, Json, SEP, Separator, serialize, serialize_to, serialize_to_vec},
 };
 pub(crate) use self::{
@@ -73,6 +75,29 @@ pub fn iter(&self) -> impl Iterator<Item = (&MapsKey, &MapsVal)> + Send + '_ {
 		self.maps.iter()
 	}
 
+	/// Snapshot of the engine statistics with the live properties summed over
+	/// all columns.
+	pub fn stats(&self) -> Result<Stats> {
+		let sum = |name| {
+			self.maps
+				.values()
+				.map(|map| map.property_integer(name))
+				.try_fold(0_u64, |total, value| value.map(|value| total.saturating_add(value)))
+		};
+
+		Ok(Stats {
+			tickers: self.db.tickers(),
+			memtables_size: sum(c"rocksdb.cur-size-all-mem-tables")?,
+			pending_compaction_bytes: sum(c"rocksdb.estimate-pending-compaction-bytes")?,
+			slow_ops: self
+				.maps
+				.values()
+				.map(|map| (map.slow().metric(), map.slow_ops()))
+				.filter(|&(_, count)| count > 0)
+				.collect(),
+		})
+	}
+
 	#[inline]
 	pub fn keys(&self) -> impl Iterator<Item = &MapsKey> + Send + '_ { self.maps.keys() }
 
@@ -1,4 +1,5 @@
 mod configure;
+pub(crate) mod lane;
 
 use std::{
 	mem::take,
@@ -8,6 +9,7 @@
 	},
 	thread,
 	thread::JoinHandle,
+	time::Instant,
 };
 
 use async_channel::{QueueStrategy, Receiver, RecvError, Sender};
@@ -22,7 +24,8 @@
 use oneshot::Sender as ResultSender;
 use rocksdb::Direction;
 
-use self::configure::configure;
+pub use self::lane::{Lane, LaneStats};
+use self::{configure::configure, lane::Meter};
 use crate::{Handle, Map, keyval::KeyBuf, stream};
 
 /// Frontend thread-pool. Operating system threads are used to make database
@@ -30,13 +33,27 @@
 /// from the tokio async workers and executed on this threadpool.
 pub(crate) struct Pool {
 	server: Arc<Server>,
-	queues: Vec<Sender<Cmd>>,
+	queues: Vec<Queue>,
+	meters: [Meter; 3],
 	workers: Mutex<Vec<JoinHandle<()>>>,
 	topology: Vec<usize>,
 	busy: AtomicUsize,
 	queued_max: AtomicUsize,
 }
 
+/// A group's queue of requests, one channel per lane. A token is sent on
+/// `ready` for each request queued, which is what workers wait on.
+struct Queue {
+	lanes: [(Sender<Queued>, Receiver<Queued>); 3],
+	ready: (Sender<()>, Receiver<()>),
+	since_background: AtomicUsize,
+}
+
+struct Queued {
+	cmd: Cmd,
+	queued_at: Instant,
+}
+
 /// Operations which can be submitted to the pool.
 pub(crate) enum Cmd {
 	Get(Get),
@@ -78,21 +95,29 @@ pub(crate) fn new(server: &Arc<Server>) -> Result<Arc<Self>> {
 
 	let (total_workers, queue_sizes, topology) = configure(server);
 
-	let (senders, receivers): (Vec<_>, Vec<_>) = queue_sizes
+	let queues = queue_sizes
 		.into_iter()
-		.map(|cap| async_channel::bounded_with_queue_strategy(cap, CHAN_SCHED))
-		.unzip();
+		.map(|cap| Queue {
+			lanes: Lane::ALL.map(|_| async_channel::bounded(cap)),
+			ready: async_channel::bounded_with_queue_strategy(
+				cap.saturating_mul(Lane::ALL.len()),
+				CHAN_SCHED,
+			),
+			since_background: AtomicUsize::default(),
+		})
+		.collect();
 
 	let pool = Arc::new(Self {
 		server: server.clone(),
-		queues: senders,
+		queues,
+		meters: Default::default(),
 		workers: Vec::new().into(),
 		topology,
 		busy: AtomicUsize::default(),
 		queued_max: AtomicUsize::default(),
 	});
 
-	pool.spawn_until(&receivers, total_workers)?;
+	pool.spawn_until(total_workers)?;
 
 	Ok(pool)
 }
@@ -102,13 +127,10 @@ fn drop(&mut self) {
 		self.close();
 
 		debug_assert!(
-			self.queues.iter().all(Sender::is_empty),
+			self.lanes().all(Sender::is_empty),
 			"channel must should not have requests queued on drop"
 		);
-		debug_assert!(
-			self.queues.iter().all(Sender::is_closed),
-			"channel should be closed on drop"
-		);
+		debug_assert!(self.lanes().all(Sender::is_closed), "channel should be closed on drop");
 	}
 }
 
@@ -117,16 +139,19 @@ fn drop(&mut self) {
 pub(crate) fn close(&self) {
 	let workers = take(&mut *self.workers.lock().expect("locked"));
 
-	let senders = self.queues.iter().map(Sender::sender_count).sum::<usize>();
+	let senders = self.lanes().map(Sender::sender_count).sum::<usize>();
 
 	let receivers = self
 		.queues
 		.iter()
-		.map(Sender::receiver_count)
+		.map(|queue| queue.ready.1.receiver_count())
 		.sum::<usize>();
 
 	for queue in &self.queues {
-		queue.close();
+		queue.ready.0.close();
+		for (lane, _) in &queue.lanes {
+			lane.close();
+		}
 	}
 
 	if workers.is_empty() {
@@ -152,11 +177,33 @@ pub(crate) fn close(&self) {
 		});
 }
 
+/// What the requests of each lane waited, with how many are queued now across
+/// all queues.
+#[implement(Pool)]
+pub(crate) fn lane_stats(&self) -> [LaneStats; 3] {
+	Lane::ALL.map(|lane| {
+		let queued = self
+			.queues
+			.iter()
+			.map(|queue| queue.lanes[lane.index()].0.len())
+			.sum();
+
+		self.meters[lane.index()].stats(lane, queued)
+	})
+}
+
+#[implement(Pool)]
+fn lanes(&self) -> impl Iterator<Item = &Sender<Queued>> + '_ {
+	self.queues
+		.iter()
+		.flat_map(|queue| queue.lanes.iter().map(|(lane, _)| lane))
+}
+
 #[implement(Pool)]
-fn spawn_until(self: &Arc<Self>, recv: &[Receiver<Cmd>], count: usize) -> Result {
+fn spawn_until(self: &Arc<Self>, count: usize) -> Result {
 	let mut workers = self.workers.lock().expect("locked");
 	while workers.len() < count {
-		self.clone().spawn_one(&mut workers, recv)?;
+		self.clone().spawn_one(&mut workers)?;
 	}
 
 	Ok(())
@@ -169,22 +216,16 @@ fn spawn_until(self: &Arc<Self>, recv: &[Receiver<Cmd>], count: usize) -> Result
 	skip_all,
 	fields(id = %workers.len())
 )]
-fn spawn_one(
-	self: Arc<Self>,
-	workers: &mut Vec<JoinHandle<()>>,
-	recv: &[Receiver<Cmd>],
-) -> Result {
+fn spawn_one(self: Arc<Self>, workers: &mut Vec<JoinHandle<()>>) -> Result {
 	debug_assert!(!self.queues.is_empty(), "Must have at least one queue");
-	debug_assert!(!recv.is_empty(), "Must have at least one receiver");
 
 	let id = workers.len();
 	let group = id.overflowing_rem(self.queues.len()).0;
-	let recv = recv[group].clone();
 
 	let handle = thread::Builder::new()
 		.name(WORKER_NAME.into())
 		.stack_size(WORKER_STACK_SIZE)
-		.spawn(move || self.worker(id, recv))?;
+		.spawn(move || self.worker(id, group))?;
 
 	workers.push(handle);
 
@@ -222,7 +263,7 @@ pub(crate) async fn execute_iter(self: &Arc<Self>, mut cmd: Seek) -> Result<stre
 }
 
 #[implement(Pool)]
-fn select_queue(&self) -> &Sender<Cmd> {
+fn select_queue(&self) -> &Queue {
 	let core_id = get_affinity().next().unwrap_or(0);
 	let chan_id = self.topology[core_id];
 	self.queues.get(chan_id).unwrap_or_else(|| &self.queues[0])
@@ -235,19 +276,28 @@ fn select_queue(&self) -> &Sender<Cmd> {
 	skip(self, cmd),
 	fields(
 		task = ?tokio::task::try_id(),
-		receivers = queue.receiver_count(),
-		queued = queue.len(),
+		lane = Lane::current().name(),
+		receivers = queue.ready.1.receiver_count(),
+		queued = queue.ready.0.len(),
 		queued_max = self.queued_max.load(Ordering::Relaxed),
 	),
 )]
-async fn execute(&self, queue: &Sender<Cmd>, cmd: Cmd) -> Result {
+async fn execute(&self, queue: &Queue, cmd: Cmd) -> Result {
+	let (lane, _) = &queue.lanes[Lane::current().index()];
 	if cfg!(debug_assertions) {
-		self.queued_max.fetch_max(queue.len(), Ordering::Relaxed);
+		self.queued_max.fetch_max(lane.len(), Ordering::Relaxed);
 	}
 
-	queue
-		.send(cmd)
+	lane.send(Queued { cmd, queued_at: Instant::now() })
 		.await
+		.map_err(|e| err!(error!("send failed {e:?}")))?;
+
+	// The token follows the request without an await between them, so a request
+	// is never queued without one even if this future is dropped.
+	queue
+		.ready
+		.0
+		.try_send(())
 		.map_err(|e| err!(error!("send failed {e:?}")))
 }
 
@@ -255,14 +305,14 @@ async fn execute(&self, queue: &Sender<Cmd>, cmd: Cmd) -> Result {
 #[tracing::instrument(
 	parent = None,
 	level = "debug",
-	skip(self, recv),
+	skip(self),
 	fields(
 		tid = ?thread::current().id(),
 	),
 )]
-fn worker(self: Arc<Self>, id: usize, recv: Receiver<Cmd>) {
+fn worker(self: Arc<Self>, id: usize, group: usize) {
 	self.worker_init(id);
-	self.worker_loop(&recv);
+	self.worker_loop(&self.queues[group]);
 }
 
 #[implement(Pool)]
@@ -304,11 +354,11 @@ fn worker_init(&self, id: usize) {
 }
 
 #[implement(Pool)]
-fn worker_loop(self: &Arc<Self>, recv: &Receiver<Cmd>) {
+fn worker_loop(self: &Arc<Self>, queue: &Queue) {
 	// initial +1 needed prior to entering wait
 	self.busy.fetch_add(1, Ordering::Relaxed);
 
-	while let Ok(cmd) = self.worker_wait(recv) {
+	while let Ok(cmd) = self.worker_wait(queue) {
 		self.worker_handle(cmd);
 	}
 }
@@ -319,15 +369,27 @@ fn worker_loop(self: &Arc<Self>, recv: &Receiver<Cmd>) {
 	level = "trace",
 	skip_all,
 	fields(
-		receivers = recv.receiver_count(),
-		queued = recv.len(),
+		receivers = queue.ready.1.receiver_count(),
+		queued = queue.ready.1.len(),
 		busy = self.busy.fetch_sub(1, Ordering::Relaxed) - 1,
 	),
 )]
-fn worker_wait(self: &Arc<Self>, recv: &Receiver<Cmd>) -> Result<Cmd, RecvError> {
-	recv.recv_blocking().debug_inspect(|_| {
+fn worker_wait(self: &Arc<Self>, queue: &Queue) -> Result<Cmd, RecvError> {
+	queue.ready.1.recv_blocking().debug_inspect(|_| {
 		self.busy.fetch_add(1, Ordering::Relaxed);
+	})?;
+
+	// Each token was sent after its request was queued, so with one taken a
+	// request is queued for this worker in one of the lanes.
+	let queued = queue.lanes.each_ref().map(|(_, recv)| recv.len());
+	let (lane, Queued { cmd, queued_at }) = lane::take(queued, &queue.since_background, |lane| {
+		queue.lanes[lane.index()].1.try_recv().ok()
 	})
+	.expect("a request queued for each token");
+
+	self.meters[lane.index()].record(queued_at.elapsed());
+
+	Ok(cmd)
 }
 
 #[implement(Pool)]
@@ -0,0 +1,153 @@
+//! Priority lanes
+//!
+//! Requests are queued in the lane of the task making them: requests of
+//! clients waiting on them are interactive, those of long scans and other
+//! jobs no one waits on are background, and everything else is normal. Workers
+//! take from the interactive lane first, then normal, then background, but
+//! take from the background lane at least once in every [`BACKGROUND_SHARE`]
+//! requests while it has any queued so background jobs still progress.
+
+use std::{
+	future::Future,
+	sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+	time::Duration,
+};
+
+tokio::task_local! {
+	static LANE: Lane;
+}
+
+/// Which lane a task's database requests are queued in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Lane {
+	/// Requests a client is waiting on.
+	Interactive,
+
+	#[default]
+	Normal,
+
+	/// Requests of jobs no one waits on, such as scans of the whole database.
+	Background,
+}
+
+/// What the requests of a lane waited to be taken by a worker.
+#[derive(Clone, Debug, Default)]
+pub struct LaneStats {
+	pub lane: Lane,
+
+	/// Requests queued now.
+	pub queued: usize,
+
+	/// Requests taken since the database was opened.
+	pub requests: u64,
+
+	/// Total and longest time requests were queued.
+	pub waited: Duration,
+	pub waited_max: Duration,
+}
+
+/// Counters of the requests taken from a lane.
+#[derive(Debug, Default)]
+pub(super) struct Meter {
+	requests: AtomicU64,
+	waited_micros: AtomicU64,
+	waited_max_micros: AtomicU64,
+}
+
+/// Of the requests taken from a pool queue, at least one in this many is a
+/// background request while any is queued.
+pub(crate) const BACKGROUND_SHARE: usize = 8;
+
+impl Lane {
+	pub(super) const ALL: [Self; 3] = [Self::Interactive, Self::Normal, Self::Background];
+
+	/// Runs the future with its database requests queued in this lane. Tasks
+	/// it spawns do not inherit the lane.
+	pub async fn scope<F: Future>(self, future: F) -> F::Output { LANE.scope(self, future).await }
+
+	/// The lane of the current task.
+	#[must_use]
+	pub fn current() -> Self { LANE.try_with(|&lane| lane).unwrap_or_default() }
+
+	pub(crate) const fn index(self) -> usize {
+		match self {
+			| Self::Interactive => 0,
+			| Self::Normal => 1,
+			| Self::Background => 2,
+		}
+	}
+
+	#[must_use]
+	pub const fn name(self) -> &'static str {
+		match self {
+			| Self::Interactive => "interactive",
+			| Self::Normal => "normal",
+			| Self::Background => "background",
+		}
+	}
+}
+
+impl LaneStats {
+	/// Mean time requests were queued.
+	#[must_use]
+	pub fn waited_mean(&self) -> Duration {
+		let micros = self
+			.waited
+			.as_micros()
+			.checked_div(self.requests.into())
+			.unwrap_or(0);
+
+		Duration::from_micros(micros.try_into().unwrap_or(u64::MAX))
+	}
+}
+
+impl Meter {
+	pub(super) fn record(&self, waited: Duration) {
+		let micros = waited.as_micros().try_into().unwrap_or(u64::MAX);
+		self.requests.fetch_add(1, Ordering::Relaxed);
+		self.waited_micros.fetch_add(micros, Ordering::Relaxed);
+		self.waited_max_micros.fetch_max(micros, Ordering::Relaxed);
+	}
+
+	pub(super) fn stats(&self, lane: Lane, queued: usize) -> LaneStats {
+		LaneStats {
+			lane,
+			queued,
+			requests: self.requests.load(Ordering::Relaxed),
+			waited: Duration::from_micros(self.waited_micros.load(Ordering::Relaxed)),
+			waited_max: Duration::from_micros(self.waited_max_micros.load(Ordering::Relaxed)),
+		}
+	}
+}
+
+/// Takes the next request of a pool queue, trying its lanes in the order the
+/// policy gives given how many requests are `queued` in each, and counting
+/// the requests taken since the last background one in `since_background`.
+pub(crate) fn take<T>(
+	queued: [usize; 3],
+	since_background: &AtomicUsize,
+	mut try_take: impl FnMut(Lane) -> Option<T>,
+) -> Option<(Lane, T)> {
+	let since = since_background.load(Ordering::Relaxed);
+	let (lane, item) = order(queued, since)
+		.into_iter()
+		.find_map(|lane| try_take(lane).map(|item| (lane, item)))?;
+
+	if lane == Lane::Background {
+		since_background.store(0, Ordering::Relaxed);
+	} else {
+		since_background.fetch_add(1, Ordering::Relaxed);
+	}
+
+	Some((lane, item))
+}
+
+/// The order in which to try the lanes for the next request.
+fn order(queued: [usize; 3], since_background: usize) -> [Lane; 3] {
+	let [interactive, normal, background] = Lane::ALL;
+	if queued[background.index()] > 0 && since_background.saturating_add(1) >= BACKGROUND_SHARE {
+		return [background, interactive, normal];
+	}
+
+	[interactive, normal, background]
+}
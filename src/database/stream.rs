@@ -18,6 +18,7 @@
 
 pub(crate) struct State<'a> {
 	inner: Inner<'a>,
+	map: &'a Map,
 	seek: bool,
 	init: bool,
 }
@@ -51,6 +52,7 @@ impl<'a> State<'a> {
 	pub(super) fn new(map: &'a Arc<Map>, opts: ReadOptions) -> Self {
 		Self {
 			inner: map.db().db.raw_iterator_cf_opt(&map.cf(), opts),
+			map,
 			init: true,
 			seek: false,
 		}
@@ -62,11 +64,16 @@ pub(super) fn init_fwd(mut self, from: From<'_>) -> Self {
 		debug_assert!(self.init, "init must be set to make this call");
 		debug_assert!(!self.seek, "seek must not be set to make this call");
 
-		if let Some(key) = from {
-			self.inner.seek(key);
-		} else {
-			self.inner.seek_to_first();
-		}
+		let inner = &mut self.inner;
+		self.map
+			.slow()
+			.time("iter", from.map_or(0, <[u8]>::len), || {
+				if let Some(key) = from {
+					inner.seek(key);
+				} else {
+					inner.seek_to_first();
+				}
+			});
 
 		self.seek = true;
 		self
@@ -78,11 +85,16 @@ pub(super) fn init_rev(mut self, from: From<'_>) -> Self {
 		debug_assert!(self.init, "init must be set to make this call");
 		debug_assert!(!self.seek, "seek must not be set to make this call");
 
-		if let Some(key) = from {
-			self.inner.seek_for_prev(key);
-		} else {
-			self.inner.seek_to_last();
-		}
+		let inner = &mut self.inner;
+		self.map
+			.slow()
+			.time("iter", from.map_or(0, <[u8]>::len), || {
+				if let Some(key) = from {
+					inner.seek_for_prev(key);
+				} else {
+					inner.seek_to_last();
+				}
+			});
 
 		self.seek = true;
 		self
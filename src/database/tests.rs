@@ -1,18 +1,70 @@
 #![allow(clippy::needless_borrows_for_generic_args)]
 
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::Deref, sync::Arc};
 
 use conduwuit::{
+	Config, Server,
 	arrayvec::ArrayVec,
+	config::Figment,
+	log::Log,
 	ruma::{EventId, RoomId, UserId, serde::Raw},
+	utils::TempDir,
 };
+use rocksdb::Options;
 use serde::Serialize;
+use tokio::runtime::Handle;
 
 use crate::{
-	Ignore, Interfix, de, ser,
+	Database, Ignore, Interfix, de,
+	engine::Db,
+	ser,
 	ser::{Json, serialize_to_vec},
 };
 
+/// A database of the given columns in a temporary directory, which is removed
+/// once the database is dropped.
+struct TempDb {
+	// dropped before the directory it is in
+	db: Db,
+	dir: TempDir,
+}
+
+impl TempDb {
+	fn new(name: &str, columns: &[&str]) -> Self { Self::open(TempDir::new(name), columns) }
+
+	fn open(dir: TempDir, columns: &[&str]) -> Self {
+		let mut opts = Options::default();
+		opts.create_if_missing(true);
+		opts.create_missing_column_families(true);
+
+		let db = Db::open_cf(&opts, &dir, columns).expect("opened database");
+		Self { db, dir }
+	}
+
+	/// Closes the database, keeping its files to be opened again.
+	fn close(self) -> TempDir { self.dir }
+}
+
+impl Deref for TempDb {
+	type Target = Db;
+
+	fn deref(&self) -> &Self::Target { &self.db }
+}
+
+/// The database of a server of its own, opened in a temporary directory.
+async fn temp_database(dir: &TempDir) -> Arc<Database> {
+	let config = Figment::new()
+		.join(("server_name", "example.com"))
+		.join(("database_path", dir.path()))
+		.join(("rocksdb_direct_io", false));
+
+	let config = Config::new(&config).expect("valid config");
+	let server = Server::new(config, Some(Handle::current()), Log::default());
+	Database::open(&Arc::new(server))
+		.await
+		.expect("opened database")
+}
+
 #[test]
 #[cfg_attr(debug_assertions, should_panic(expected = "serializing string at the top-level"))]
 fn ser_str() {
@@ -547,3 +599,566 @@ fn serde_tuple_option_none_none_none() {
 	assert_eq!(None, cc.0);
 	assert_eq!(bb, cc);
 }
+
+#[test]
+fn check_reports_corrupt_column() {
+	use std::fs;
+
+	use crate::engine::check::check_column;
+
+	const COLUMN: &str = "corrupt";
+
+	let db = TempDb::new("check", &[COLUMN]);
+	let cf = db.cf_handle(COLUMN).expect("column exists");
+	for i in 0_u32..1024 {
+		db.put_cf(&cf, i.to_be_bytes(), [0xAA; 128])
+			.expect("written");
+	}
+
+	db.flush_cf(&cf).expect("flushed");
+	drop(cf);
+	let dir = db.close();
+
+	let sst = fs::read_dir(&dir)
+		.expect("database directory")
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.find(|path| path.extension().is_some_and(|ext| ext == "sst"))
+		.expect("table file written");
+
+	// flip a byte inside the first data block
+	let mut bytes = fs::read(&sst).expect("read table file");
+	bytes[64] ^= 0xFF;
+	fs::write(&sst, bytes).expect("wrote table file");
+
+	let db = TempDb::open(dir, &[COLUMN]);
+	let cf = db.cf_handle(COLUMN).expect("column exists");
+	let check = check_column(&db, &cf, &[], None);
+
+	assert!(check.errors > 0, "corruption not detected");
+	assert!(check.first_error.is_some(), "first error not recorded");
+}
+
+#[test]
+fn column_compression_override() {
+	use std::{collections::BTreeMap, fs};
+
+	use rocksdb::ColumnFamilyDescriptor;
+
+	use crate::engine::{cf_opts::descriptor_cf_options, descriptor};
+
+	const COLUMN: &str = "compressed";
+
+	let path = TempDir::new("compression");
+	let overrides: BTreeMap<_, _> = [(COLUMN, "lz4:7")].into();
+	let config = Figment::new()
+		.join(("server_name", "example.com"))
+		.join(("database_path", path.path()))
+		.join(("rocksdb_compression_algo", "zstd"))
+		.join(("rocksdb_compression_per_column", overrides));
+
+	let config = Config::new(&config).expect("valid config");
+	let desc = descriptor::Descriptor { name: COLUMN, ..descriptor::RANDOM };
+	let cf_opts =
+		descriptor_cf_options(Options::default(), desc, &config, None).expect("column options");
+
+	let mut opts = Options::default();
+	opts.create_if_missing(true);
+	opts.create_missing_column_families(true);
+	let cfds = [ColumnFamilyDescriptor::new(COLUMN, cf_opts)];
+	let db = Db::open_cf_descriptors(&opts, &path, cfds).expect("opened database");
+	drop(db);
+
+	let options = fs::read_dir(&path)
+		.expect("database directory")
+		.filter_map(Result::ok)
+		.filter(|entry| entry.file_name().to_string_lossy().starts_with("OPTIONS-"))
+		.max_by_key(|entry| entry.file_name())
+		.map(|entry| fs::read_to_string(entry.path()).expect("read options file"))
+		.expect("options file written");
+
+	let section = options
+		.split_once(&format!("[CFOptions \"{COLUMN}\"]"))
+		.map(|(_, section)| section)
+		.expect("column options section");
+
+	let section = section.split("\n[").next().unwrap_or(section);
+	assert!(section.contains("compression=kLZ4Compression"), "column algorithm applied");
+	assert!(
+		section.contains("bottommost_compression=kLZ4Compression"),
+		"bottommost algorithm"
+	);
+	assert!(section.contains("level=7;"), "column level applied");
+
+	let invalid: BTreeMap<_, _> = [(COLUMN, "brotli")].into();
+	let config = Figment::new()
+		.join(("server_name", "example.com"))
+		.join(("database_path", path.path()))
+		.join(("rocksdb_compression_per_column", invalid));
+
+	let config = Config::new(&config).expect("valid config");
+	let error = descriptor_cf_options(Options::default(), desc, &config, None)
+		.expect_err("unknown algorithm rejected");
+
+	assert!(error.to_string().contains("zstd, snappy"), "valid values listed: {error}");
+}
+
+#[test]
+fn delete_prefix_range() {
+	use rocksdb::WriteBatchWithTransaction;
+
+	use crate::map::remove::{prefix_upper_bound, stage_delete_prefix};
+
+	const COLUMN: &str = "ranged";
+
+	assert_eq!(prefix_upper_bound(b"\x01\x02"), Some(b"\x01\x03".to_vec()));
+	assert_eq!(prefix_upper_bound(b"\x01\xFF"), Some(b"\x02".to_vec()));
+	assert_eq!(prefix_upper_bound(b"\xFF\xFF"), None);
+
+	let db = TempDb::new("delete_range", &[COLUMN]);
+	let cf = db.cf_handle(COLUMN).expect("column exists");
+	let prefixes: [&[u8]; 3] = [b"\x01", b"\x02", b"\xFF\xFF"];
+	for prefix in prefixes {
+		for i in 0_u32..10_000 {
+			let key = [prefix, &i.to_be_bytes()].concat();
+			db.put_cf(&cf, key, b"").expect("written");
+		}
+	}
+
+	let count = |prefix: &[u8]| {
+		db.prefix_iterator_cf(&cf, prefix)
+			.map(|kv| kv.expect("read"))
+			.take_while(|(key, _)| key.starts_with(prefix))
+			.count()
+	};
+
+	let delete_prefix = |prefix: &[u8]| {
+		let mut batch = WriteBatchWithTransaction::<false>::default();
+		stage_delete_prefix(&mut batch, &db, &cf, prefix);
+		db.write(batch).expect("deleted prefix");
+	};
+
+	delete_prefix(b"\x01");
+
+	assert_eq!(count(b"\x01"), 0, "targeted prefix deleted");
+	assert_eq!(count(b"\x02"), 10_000, "other prefix untouched");
+	assert_eq!(count(b"\xFF\xFF"), 10_000, "other prefix untouched");
+
+	delete_prefix(b"\xFF\xFF");
+
+	assert_eq!(count(b"\xFF\xFF"), 0, "unbounded prefix deleted");
+	assert_eq!(count(b"\x02"), 10_000, "preceding prefix untouched");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_batch_atomic() {
+	use std::time::Duration;
+
+	use tokio::time::timeout;
+
+	let dir = TempDir::new("write_batch");
+	let db = temp_database(&dir).await;
+	let (pdus, index) = (&db["pduid_pdu"], &db["eventid_pduid"]);
+	index.insert(b"$stale", b"old");
+
+	let stage = || {
+		let mut batch = db.db.write_batch();
+		batch.insert(pdus, b"\x01", b"{}");
+		batch.insert(index, b"$event", b"\x01");
+		batch.remove(index, b"$stale");
+		batch
+	};
+
+	// An interrupted writer never commits its batch.
+	let batch = stage();
+	assert_eq!(batch.len(), 3);
+	drop(batch);
+	assert!(pdus.get(b"\x01").await.is_err(), "nothing persisted");
+	assert!(index.get(b"$event").await.is_err(), "nothing persisted");
+	assert!(index.get(b"$stale").await.is_ok(), "nothing removed");
+
+	let inserted = pdus.watch_prefix(b"\x01");
+	let removed = index.watch_prefix(b"$stale");
+	stage().commit();
+	assert!(pdus.get(b"\x01").await.is_ok(), "first column written");
+	assert!(index.get(b"$event").await.is_ok(), "second column written");
+	assert!(index.get(b"$stale").await.is_err(), "removal applied");
+
+	let wait = Duration::from_secs(5);
+	timeout(wait, inserted).await.expect("inserted key woken");
+	timeout(wait, removed).await.expect("removed key woken");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_batch_range_deletions() {
+	use futures::StreamExt;
+
+	use crate::Map;
+
+	async fn count(map: &Arc<Map>) -> usize { map.raw_keys().count().await }
+
+	let dir = TempDir::new("write_batch_ranges");
+	let db = temp_database(&dir).await;
+	let (pdus, tokens) = (&db["pduid_pdu"], &db["tokenids"]);
+	for i in 0_u8..4 {
+		pdus.insert(&[1, i], b"{}");
+		pdus.insert(&[2, i], b"{}");
+		tokens.insert(&[i], b"");
+	}
+
+	let stage = || {
+		let mut batch = db.db.write_batch();
+		batch.delete_prefix(pdus, &[1]);
+		batch.delete_range(tokens, &[1], &[3]);
+		batch.insert(pdus, b"\x03\x00", b"{}");
+		batch
+	};
+
+	drop(stage());
+	assert_eq!(count(pdus).await, 8, "nothing deleted");
+	assert_eq!(count(tokens).await, 4, "nothing deleted");
+
+	stage().commit();
+	assert!(pdus.get(b"\x01\x00").await.is_err(), "prefix deleted");
+	assert!(pdus.get(b"\x02\x00").await.is_ok(), "other prefix untouched");
+	assert!(pdus.get(b"\x03\x00").await.is_ok(), "written with the deletions");
+	assert_eq!(count(pdus).await, 5);
+	assert!(tokens.get(b"\x00").await.is_ok(), "before the range");
+	assert!(tokens.get(b"\x03").await.is_ok(), "end of the range excluded");
+	assert_eq!(count(tokens).await, 2);
+}
+
+#[test]
+fn open_retries_locked_database() {
+	use std::{
+		cell::{Cell, RefCell},
+		time::{Duration, Instant},
+	};
+
+	use crate::engine::retry::{self, Failure, Policy};
+
+	let path = TempDir::new("open_retry");
+	let mut opts = Options::default();
+	opts.create_if_missing(true);
+
+	let holder = Db::open(&opts, &path).expect("opened database");
+	let locked = Db::open(&opts, &path).expect_err("database is locked");
+	assert_eq!(retry::classify(&locked), Failure::Locked, "{locked}");
+
+	let runtime = tokio::runtime::Builder::new_current_thread()
+		.enable_time()
+		.build()
+		.expect("runtime");
+
+	let policy = Policy {
+		repair: true,
+		lock_retries: 2,
+		lock_backoff: Duration::from_millis(20),
+	};
+
+	let attempts = Cell::new(0_u32);
+	let repairs = Cell::new(0_u32);
+	let open = || {
+		attempts.set(attempts.get().saturating_add(1));
+		Ok(Db::open(&opts, &path))
+	};
+
+	let repair = || {
+		repairs.set(repairs.get().saturating_add(1));
+		Ok(())
+	};
+
+	let started = Instant::now();
+	let error = runtime
+		.block_on(retry::open(&policy, open, repair))
+		.expect_err("lock never released");
+
+	assert_eq!(attempts.get(), 3, "first attempt and two retries");
+	assert_eq!(repairs.get(), 0, "locked database not repaired");
+	assert!(started.elapsed() >= Duration::from_millis(60), "backed off 20ms then 40ms");
+	assert!(error.to_string().contains("after 3 attempts"), "{error}");
+	assert!(error.to_string().contains("first error"), "{error}");
+
+	// The lock is released by the previous instance while retrying.
+	let holder = RefCell::new(Some(holder));
+	attempts.set(0);
+	let open = || {
+		attempts.set(attempts.get().saturating_add(1));
+		if attempts.get() == 2 {
+			holder.borrow_mut().take();
+		}
+
+		Ok(Db::open(&opts, &path))
+	};
+
+	let db = runtime
+		.block_on(retry::open(&policy, open, || Ok(())))
+		.expect("opened once unlocked");
+
+	assert_eq!(attempts.get(), 2, "opened on the first retry");
+}
+
+#[test]
+fn column_export_import_round_trip() {
+	use std::io::Cursor;
+
+	use rocksdb::{AsColumnFamilyRef, IteratorMode};
+
+	use crate::engine::transfer;
+
+	fn contents(db: &Db, cf: &impl AsColumnFamilyRef) -> Vec<(Box<[u8]>, Box<[u8]>)> {
+		db.iterator_cf(cf, IteratorMode::Start)
+			.map(|item| item.expect("read"))
+			.collect()
+	}
+
+	let db = TempDb::new("transfer", &["source", "target", "empty"]);
+	let source = db.cf_handle("source").expect("column exists");
+	let target = db.cf_handle("target").expect("column exists");
+	for i in 0_u32..3000 {
+		let len: usize = (i % 300).try_into().expect("small");
+		db.put_cf(&source, i.to_be_bytes(), vec![0x5A; len])
+			.expect("written");
+	}
+
+	let mut file = Vec::new();
+	let keys = transfer::export_column(&db, &source, "source", &mut file).expect("exported");
+	assert_eq!(keys, 3000);
+
+	let import = |cf, name, file: &[u8], overwrite| {
+		transfer::import_column(&db, cf, name, &mut Cursor::new(file), overwrite)
+	};
+
+	assert!(import(&target, "target", &file, false).is_err(), "other column refused");
+	assert!(contents(&db, &target).is_empty(), "nothing written");
+
+	// the file as exported from a column named after the target
+	let mut renamed = Vec::new();
+	transfer::export_column(&db, &source, "target", &mut renamed).expect("exported");
+
+	let imported = import(&target, "target", &renamed, false).expect("imported");
+	assert_eq!((imported.written, imported.skipped), (3000, 0));
+	assert_eq!(contents(&db, &target), contents(&db, &source), "byte-identical");
+
+	db.put_cf(&target, 7_u32.to_be_bytes(), b"changed")
+		.expect("written");
+
+	let imported = import(&target, "target", &renamed, false).expect("imported");
+	assert_eq!((imported.written, imported.skipped), (0, 3000));
+	assert_eq!(
+		db.get_cf(&target, 7_u32.to_be_bytes())
+			.expect("read")
+			.as_deref(),
+		Some(&b"changed"[..]),
+		"existing keys skipped"
+	);
+
+	let imported = import(&target, "target", &renamed, true).expect("imported");
+	assert_eq!((imported.written, imported.skipped), (3000, 0));
+	assert_eq!(contents(&db, &target), contents(&db, &source), "existing keys overwritten");
+
+	let empty = db.cf_handle("empty").expect("column exists");
+	let truncated = &file[..file.len().saturating_sub(12)];
+	assert!(import(&empty, "source", truncated, true).is_err(), "truncated file refused");
+	assert!(contents(&db, &empty).is_empty(), "nothing written from a truncated file");
+
+	// a record of length just under the end marker
+	let mut oversized = file[..file.len().saturating_sub(12)].to_vec();
+	oversized.extend((u32::MAX - 1).to_be_bytes());
+	oversized.extend([0_u8; 16]);
+	assert!(import(&empty, "source", &oversized, true).is_err(), "oversized record refused");
+	assert!(contents(&db, &empty).is_empty(), "nothing written from a corrupt file");
+}
+
+#[test]
+fn column_cache_shares() {
+	use std::collections::BTreeMap;
+
+	use crate::{
+		engine::{
+			descriptor::CacheDisp,
+			shares::{self, Shares},
+		},
+		maps::MAPS,
+	};
+
+	let config = |overrides: &[(&str, u32)]| {
+		let overrides: BTreeMap<_, _> = overrides.iter().copied().collect();
+		let config = Figment::new()
+			.join(("server_name", "example.com"))
+			.join(("database_path", "/tmp/conduwuit_shares"))
+			.join(("rocksdb_cache_shares_per_column", overrides));
+
+		Config::new(&config).expect("valid config")
+	};
+
+	let common: u64 = MAPS
+		.iter()
+		.filter(|desc| !desc.dropped && matches!(desc.cache_disp, CacheDisp::Shared))
+		.filter(|desc| desc.cache_shares == 0)
+		.count()
+		.try_into()
+		.expect("small");
+
+	let unpartitioned = shares::shares(&config(&[]));
+	assert!(!unpartitioned.partitioned(), "no shares built in");
+	assert_eq!((unpartitioned.common, unpartitioned.total), (common, common));
+
+	let shared = MAPS
+		.iter()
+		.find(|desc| !desc.dropped && matches!(desc.cache_disp, CacheDisp::Shared))
+		.expect("a column using the common cache");
+
+	let overridden = shares::shares(&config(&[
+		(shared.name, 4),
+		("eventid_pduid", 8),
+		("pduid_pdu", 2),
+		("eventid_outlierpdu", 6),
+		("no_such_column", 100),
+	]));
+
+	assert_eq!(overridden.common, common.saturating_sub(1), "moved out of the common cache");
+	assert_eq!(
+		overridden.total,
+		overridden.common.saturating_add(18),
+		"columns sharing a cache count once with the larger shares; unknown columns ignored"
+	);
+
+	let shares = Shares { common: 3, total: 10 };
+	assert!(shares.partitioned());
+	assert_eq!(shares.size(1000, 3), 300);
+	assert_eq!(shares.size(1000, 7), 700);
+	assert_eq!(Shares::default().size(1000, 0), 1000, "undivided without shares");
+}
+
+#[test]
+fn slow_operation_threshold() {
+	use std::time::Duration;
+
+	use crate::map::slow::Slow;
+
+	let slow = Slow::new("testcol", 10);
+	assert_eq!(slow.metric(), "db_slow_ops_testcol");
+
+	assert!(!slow.observe("get", 8, Duration::from_millis(9)), "under the threshold");
+	assert_eq!(slow.count(), 0);
+
+	assert!(slow.observe("get", 8, Duration::from_millis(10)), "at the threshold");
+	assert!(slow.observe("insert", 0, Duration::from_secs(1)));
+	assert_eq!(slow.count(), 2);
+
+	let value = slow.time("iter", 4, || {
+		std::thread::sleep(Duration::from_millis(20));
+		7_u8
+	});
+	assert_eq!(value, 7, "result passed through");
+	assert_eq!(slow.count(), 3, "artificially slow operation counted");
+
+	slow.time("iter", 4, || ());
+	assert_eq!(slow.count(), 3, "fast operation not counted");
+
+	let disabled = Slow::new("testcol", 0);
+	assert!(!disabled.observe("get", 8, Duration::from_secs(60)), "disabled by zero");
+	disabled.time("get", 8, || std::thread::sleep(Duration::from_millis(2)));
+	assert_eq!(disabled.count(), 0);
+}
+
+#[test]
+fn watch_room_receipt() {
+	use futures::FutureExt;
+
+	use crate::watchers::Watchers;
+
+	let room_id: &RoomId = "!room:example.com".try_into().unwrap();
+	let other_room_id: &RoomId = "!other:example.com".try_into().unwrap();
+	let user_b: &UserId = "@b:example.com".try_into().unwrap();
+	let receipt =
+		|room_id: &RoomId, count: u64| serialize_to_vec((room_id, count, user_b)).unwrap();
+
+	let mut roomid_prefix = room_id.as_bytes().to_vec();
+	roomid_prefix.push(0xFF);
+
+	let watchers = Watchers::default();
+
+	// user A's long-poll is set up before their sync response is built, and
+	// only waited on once it turned out empty
+	let mut room = watchers.watch(&roomid_prefix);
+	let everything = watchers.watch(&[]);
+
+	watchers.wake(&receipt(other_room_id, 1));
+	assert!((&mut room).now_or_never().is_none(), "receipt in another room");
+	assert!(everything.now_or_never().is_some(), "coarse watch woken by any room");
+
+	// user B's receipt lands while user A's response is still being built
+	watchers.wake(&receipt(room_id, 2));
+	assert!(room.now_or_never().is_some(), "woken though not yet polled");
+
+	let again = watchers.watch(&roomid_prefix);
+	assert!(again.now_or_never().is_none(), "later watches wait for later receipts");
+}
+
+#[test]
+fn pool_lane_scope() {
+	use futures::FutureExt;
+
+	use crate::Lane;
+
+	assert_eq!(Lane::current(), Lane::Normal, "outside of any scope");
+
+	let inner = Lane::Background.scope(async { Lane::current() });
+	let outer = Lane::Interactive.scope(async { (Lane::current(), inner.await) });
+	assert_eq!(outer.now_or_never(), Some((Lane::Interactive, Lane::Background)));
+}
+
+#[test]
+fn pool_lanes_background_scan() {
+	use std::{collections::VecDeque, sync::atomic::AtomicUsize};
+
+	use crate::{
+		Lane,
+		pool::lane::{BACKGROUND_SHARE, take},
+	};
+
+	// One worker takes one request per tick, with a scan of the whole database
+	// queued ahead of clients' requests; returns the longest a client waited and
+	// how many of the scan's requests were taken.
+	let simulate = |ticks: usize, every: usize| {
+		let since_background = AtomicUsize::default();
+		let mut lanes: [VecDeque<usize>; 3] = Default::default();
+		lanes[Lane::Background.index()].extend(std::iter::repeat(0).take(ticks));
+
+		let (mut waited_max, mut scanned) = (0, 0);
+		for tick in 0..ticks {
+			if tick % every == 0 {
+				lanes[Lane::Interactive.index()].push_back(tick);
+			}
+
+			let queued = lanes.each_ref().map(VecDeque::len);
+			let (lane, queued_at) =
+				take(queued, &since_background, |lane| lanes[lane.index()].pop_front())
+					.expect("requests queued");
+
+			match lane {
+				| Lane::Interactive =>
+					waited_max = waited_max.max(tick.saturating_sub(queued_at)),
+				| _ => scanned = scanned.saturating_add(1),
+			}
+		}
+
+		(waited_max, scanned)
+	};
+
+	// clients are served as their requests arrive, and the scan proceeds
+	// meanwhile
+	let (waited_max, scanned) = simulate(10_000, 2);
+	assert!(waited_max <= 1, "interactive waited {waited_max} ticks");
+	assert!(scanned >= 10_000 / 2, "scanned {scanned}");
+
+	// while clients want every tick, the scan still gets its share
+	let (waited_max, scanned) = simulate(10_000, 1);
+	assert_eq!(scanned, 10_000 / BACKGROUND_SHARE);
+	assert!(
+		waited_max <= 10_000 / BACKGROUND_SHARE,
+		"interactive lags only by the scan's share"
+	);
+}
@@ -10,6 +10,8 @@
 	utils::available_parallelism,
 };
 
+use crate::cli::Command;
+
 /// Commandline arguments
 #[derive(Parser, Debug)]
 #[clap(
@@ -40,6 +42,10 @@ pub(crate) struct Args {
 	#[arg(long, num_args(0))]
 	pub(crate) console: bool,
 
+	/// Create a new admin room at startup, replacing the current one.
+	#[arg(long)]
+	pub(crate) recreate_admin_room: bool,
+
 	/// Execute console command automatically after startup.
 	#[arg(long)]
 	pub(crate) execute: Vec<String>,
@@ -121,6 +127,9 @@ pub(crate) struct Args {
 		require_equals(false),
 	)]
 	pub(crate) gc_muzzy: Option<bool>,
+
+	#[command(subcommand)]
+	pub(crate) command: Option<Command>,
 }
 
 /// Parse commandline arguments into structured data
@@ -133,6 +142,13 @@ pub(crate) fn update(mut config: Figment, args: &Args) -> Result<Figment> {
 		config = config.join(("rocksdb_read_only", true));
 	}
 
+	// Servers started for a command only open the database
+	if args.command.is_some() {
+		config = config.merge(("listening", false));
+		config = config.merge(("startup_netburst", false));
+		config = config.merge(("log", "error"));
+	}
+
 	if args.maintenance || args.read_only {
 		config = config.join(("startup_netburst", false));
 		config = config.join(("listening", false));
@@ -145,6 +161,10 @@ pub(crate) fn update(mut config: Figment, args: &Args) -> Result<Figment> {
 		config = config.join(("admin_console_automatic", true));
 	}
 
+	if args.recreate_admin_room {
+		config = config.join(("recreate_admin_room", true));
+	}
+
 	// Execute commands after any commands listed in configuration file
 	config = config.adjoin(("admin_execute", &args.execute));
 
@@ -0,0 +1,340 @@
+//! Commands run against a server rather than running one
+
+use std::{io::BufRead, process::ExitCode};
+
+use clap::Subcommand;
+use conduwuit_core::{Error, Result};
+use reqwest::{Method, StatusCode, Url};
+use serde_json::{Value, json};
+
+use crate::clap::Args;
+
+/// Commands to run instead of the server
+///
+/// They exit with 0 once done, 1 when the server refuses, 2 when used wrongly
+/// and 3 when the server cannot be reached.
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+	/// Manage the local users of a running server through its admin API.
+	User(UserArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct UserArgs {
+	/// URL the server's client API is reachable at.
+	#[arg(long, env = "CONDUWUIT_ADMIN_URL", default_value = "http://localhost:8008")]
+	pub(crate) url: String,
+
+	/// Access token of a server admin.
+	#[arg(long, env = "CONDUWUIT_ADMIN_TOKEN", hide_env_values = true)]
+	pub(crate) token: Option<String>,
+
+	/// Print the results as JSON.
+	#[arg(long, global = true)]
+	pub(crate) json: bool,
+
+	#[command(subcommand)]
+	pub(crate) command: UserCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum UserCommand {
+	/// Create a local user, with a generated password unless one is given.
+	Create {
+		/// Localpart of the user to create.
+		localpart: String,
+
+		/// Read the password from the first line of stdin.
+		#[arg(long)]
+		password_stdin: bool,
+
+		/// Make the user a server admin.
+		#[arg(long)]
+		admin: bool,
+
+		/// Create the user in the database directly when the server is not
+		/// running. It must not be started until this has finished.
+		#[arg(long)]
+		offline: bool,
+	},
+
+	/// Reset the password of a local user, generating one unless it is given.
+	ResetPassword {
+		/// Full user ID of the user.
+		user_id: String,
+
+		/// Read the password from the first line of stdin.
+		#[arg(long)]
+		password_stdin: bool,
+	},
+
+	/// Deactivate a local user.
+	Deactivate {
+		/// Full user ID of the user.
+		user_id: String,
+
+		/// Keep the user in their rooms rather than have them leave all of
+		/// them.
+		#[arg(long)]
+		no_leave_rooms: bool,
+	},
+
+	/// List the local users.
+	List,
+}
+
+/// Why a command failed, deciding its exit code.
+#[derive(Debug)]
+pub(crate) enum Failure {
+	/// The server answered with an error.
+	Refused(StatusCode, Value),
+
+	/// The command was given wrongly.
+	Usage(String),
+
+	/// The server could not be reached.
+	Unreachable(Error),
+
+	/// Anything else which went wrong.
+	Failed(Error),
+}
+
+const EXIT_REFUSED: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_UNREACHABLE: u8 = 3;
+
+/// Runs the command, printing its results, and returns what to exit with.
+pub(crate) async fn run(args: &Args, command: &Command) -> ExitCode {
+	let Command::User(user) = command;
+	match run_user(args, user).await {
+		| Ok(result) => {
+			print(user.json, &result);
+			ExitCode::SUCCESS
+		},
+		| Err(failure) => {
+			eprintln!("{}", failure.describe(user.json));
+			failure.exit_code()
+		},
+	}
+}
+
+pub(crate) async fn run_user(args: &Args, user: &UserArgs) -> Result<Value, Failure> {
+	match &user.command {
+		| UserCommand::Create {
+			localpart,
+			password_stdin,
+			admin,
+			offline,
+		} => {
+			let password = password_stdin.then(read_password).transpose()?;
+			let body = json!({
+				"localpart": localpart,
+				"password": password,
+				"admin": admin,
+			});
+
+			match request(user, Method::POST, &["users"], Some(body)).await {
+				| Err(Failure::Unreachable(e)) if *offline => {
+					eprintln!("Server unreachable ({e}), creating the user offline.");
+					create_offline(args, localpart, password, *admin)
+						.await
+						.map_err(Failure::Failed)
+				},
+				| result => result,
+			}
+		},
+		| UserCommand::ResetPassword { user_id, password_stdin } => {
+			let password = password_stdin.then(read_password).transpose()?;
+			let body = json!({ "password": password });
+			request(user, Method::POST, &["users", user_id.as_str(), "password"], Some(body))
+				.await
+				.map(|result| with_user_id(result, user_id))
+		},
+		| UserCommand::Deactivate { user_id, no_leave_rooms } => {
+			let body = json!({ "no_leave_rooms": no_leave_rooms });
+			request(user, Method::POST, &["users", user_id.as_str(), "deactivate"], Some(body))
+				.await
+				.map(|result| with_user_id(result, user_id))
+		},
+		| UserCommand::List => request(user, Method::GET, &["users"], None).await,
+	}
+}
+
+/// Calls the admin API endpoint at the `path` segments below its base.
+async fn request(
+	user: &UserArgs,
+	method: Method,
+	path: &[&str],
+	body: Option<Value>,
+) -> Result<Value, Failure> {
+	let token = user.token.as_deref().ok_or_else(|| {
+		Failure::Usage("An access token is needed; give --token or CONDUWUIT_ADMIN_TOKEN.".into())
+	})?;
+
+	let url = admin_url(&user.url, path)?;
+	let mut request = reqwest::Client::new()
+		.request(method, url)
+		.bearer_auth(token);
+
+	if let Some(body) = body {
+		let body = serde_json::to_vec(&body).map_err(|e| Failure::Failed(e.into()))?;
+		request = request
+			.header(reqwest::header::CONTENT_TYPE, "application/json")
+			.body(body);
+	}
+
+	let response = request.send().await.map_err(|e| {
+		if e.is_connect() || e.is_timeout() {
+			Failure::Unreachable(e.into())
+		} else {
+			Failure::Failed(e.into())
+		}
+	})?;
+
+	let status = response.status();
+	let body = response
+		.bytes()
+		.await
+		.map_err(|e| Failure::Failed(e.into()))?;
+
+	let body: Value = serde_json::from_slice(&body).unwrap_or_else(|_| {
+		json!({
+			"errcode": "M_UNKNOWN",
+			"error": String::from_utf8_lossy(&body),
+		})
+	});
+
+	if !status.is_success() {
+		return Err(Failure::Refused(status, body));
+	}
+
+	Ok(body)
+}
+
+/// The URL of the admin API endpoint at the `path` segments below the client
+/// API at `base`, each segment percent-encoded.
+pub(crate) fn admin_url(base: &str, path: &[&str]) -> Result<Url, Failure> {
+	let invalid = || Failure::Usage(format!("The URL {base:?} cannot be used for the API."));
+	let mut url = Url::parse(base).map_err(|_| invalid())?;
+	url.path_segments_mut()
+		.map_err(|()| invalid())?
+		.pop_if_empty()
+		.extend(["_conduwuit", "admin", "v1"])
+		.extend(path);
+
+	Ok(url)
+}
+
+/// Creates the user in the database of a server which is not running.
+#[cfg(any(not(conduwuit_mods), not(feature = "conduwuit_mods")))]
+async fn create_offline(
+	args: &Args,
+	localpart: &str,
+	password: Option<String>,
+	admin: bool,
+) -> Result<Value> {
+	extern crate conduwuit_router as router;
+
+	let server = crate::server::Server::new(args, Some(&tokio::runtime::Handle::current()))?;
+	let services = router::start(&server.server).await?;
+
+	let result =
+		conduwuit_api::client::provision_user(&services, localpart, password, admin).await;
+
+	router::stop(services).await?;
+
+	let (user_id, password) = result?;
+
+	Ok(json!({
+		"user_id": user_id,
+		"password": password,
+	}))
+}
+
+#[cfg(all(conduwuit_mods, feature = "conduwuit_mods"))]
+async fn create_offline(
+	_args: &Args,
+	_localpart: &str,
+	_password: Option<String>,
+	_admin: bool,
+) -> Result<Value> {
+	conduwuit_core::Err!("Users cannot be created offline by builds with hot-reloadable modules.")
+}
+
+fn read_password() -> Result<String, Failure> {
+	let mut password = String::new();
+	std::io::stdin()
+		.lock()
+		.read_line(&mut password)
+		.map_err(|e| Failure::Failed(e.into()))?;
+
+	let password = password.trim_end_matches(['\r', '\n']);
+	if password.is_empty() {
+		return Err(Failure::Usage("No password was given on stdin.".into()));
+	}
+
+	Ok(password.to_owned())
+}
+
+fn with_user_id(mut result: Value, user_id: &str) -> Value {
+	if let Value::Object(result) = &mut result {
+		result.insert("user_id".into(), user_id.into());
+	}
+
+	result
+}
+
+/// Prints the result of a command, as it is for `--json` and otherwise in
+/// lines for people to read.
+fn print(json: bool, result: &Value) {
+	if json {
+		println!("{result}");
+		return;
+	}
+
+	if let Some(users) = result.get("users").and_then(Value::as_array) {
+		users
+			.iter()
+			.filter_map(Value::as_str)
+			.for_each(|user_id| println!("{user_id}"));
+
+		return;
+	}
+
+	if let Some(user_id) = result.get("user_id").and_then(Value::as_str) {
+		println!("{user_id}");
+	}
+
+	if let Some(password) = result.get("password").and_then(Value::as_str) {
+		println!("password: {password}");
+	}
+}
+
+impl Failure {
+	pub(crate) fn exit_code(&self) -> ExitCode {
+		ExitCode::from(match self {
+			| Self::Refused(..) | Self::Failed(_) => EXIT_REFUSED,
+			| Self::Usage(_) => EXIT_USAGE,
+			| Self::Unreachable(_) => EXIT_UNREACHABLE,
+		})
+	}
+
+	pub(crate) fn describe(&self, json: bool) -> String {
+		match self {
+			| Self::Refused(_, body) if json => body.to_string(),
+			| Self::Refused(status, body) => {
+				let errcode = body.get("errcode").and_then(Value::as_str);
+				let error = body.get("error").and_then(Value::as_str);
+				format!(
+					"The server refused ({status}): {} {}",
+					errcode.unwrap_or("M_UNKNOWN"),
+					error.unwrap_or_default()
+				)
+			},
+			| failure if json => json!({ "error": failure.describe(false) }).to_string(),
+			| Self::Usage(message) => message.clone(),
+			| Self::Unreachable(e) => format!("The server could not be reached: {e}"),
+			| Self::Failed(e) => e.to_string(),
+		}
+	}
+}
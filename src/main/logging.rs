@@ -4,7 +4,7 @@
 	Result,
 	config::Config,
 	debug_warn, err,
-	log::{ConsoleFormat, ConsoleWriter, LogLevelReloadHandles, capture, fmt_span},
+	log::{ConsoleFormat, ConsoleWriter, LogLevelReloadHandles, capture, file, fmt_span},
 	result::UnwrapOrErr,
 };
 use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, layer::SubscriberExt, reload};
@@ -18,7 +18,12 @@
 #[allow(clippy::redundant_clone)]
 pub(crate) fn init(
 	config: &Config,
-) -> Result<(LogLevelReloadHandles, TracingFlameGuard, Arc<capture::State>)> {
+) -> Result<(
+	LogLevelReloadHandles,
+	TracingFlameGuard,
+	Arc<capture::State>,
+	Option<Arc<file::File>>,
+)> {
 	let reload_handles = LogLevelReloadHandles::default();
 
 	let console_span_events = fmt_span::from_str(&config.log_span_events).unwrap_or_err();
@@ -36,11 +41,25 @@ pub(crate) fn init(
 		reload::Layer::new(console_filter.clone());
 	reload_handles.add("console", Box::new(console_reload_handle));
 
+	let log_file = file::File::open(config)?;
+	let file_layer = log_file.clone().map(|log_file| {
+		let (file_reload_filter, file_reload_handle) = reload::Layer::new(console_filter.clone());
+		reload_handles.add("file", Box::new(file_reload_handle));
+
+		fmt::Layer::new()
+			.with_ansi(false)
+			.with_thread_ids(config.log_thread_ids)
+			.with_span_events(fmt_span::from_str(&config.log_span_events).unwrap_or_err())
+			.with_writer(log_file)
+			.with_filter(file_reload_filter)
+	});
+
 	let cap_state = Arc::new(capture::State::new());
 	let cap_layer = capture::Layer::new(&cap_state);
 
 	let subscriber = Registry::default()
 		.with(console_layer.with_filter(console_reload_filter))
+		.with(file_layer)
 		.with(cap_layer);
 
 	#[cfg(feature = "sentry_telemetry")]
@@ -95,7 +114,7 @@ pub(crate) fn init(
 	#[cfg_attr(not(feature = "perf_measurements"), allow(clippy::let_unit_value))]
 	let flame_guard = ();
 
-	let ret = (reload_handles, flame_guard, cap_state);
+	let ret = (reload_handles, flame_guard, cap_state, log_file);
 
 	// Enable the tokio console. This is slightly kludgy because we're judggling
 	// compile-time and runtime conditions to elide it, each of those changing the
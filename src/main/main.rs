@@ -1,6 +1,7 @@
 #![type_length_limit = "49152"] //TODO: reduce me
 
 pub(crate) mod clap;
+mod cli;
 mod logging;
 mod mods;
 mod restart;
@@ -8,17 +9,25 @@
 mod sentry;
 mod server;
 mod signal;
+mod tests;
 
-use std::sync::{Arc, atomic::Ordering};
+use std::{
+	process::ExitCode,
+	sync::{Arc, atomic::Ordering},
+};
 
 use conduwuit_core::{Error, Result, debug_info, error, rustc_flags_capture};
 use server::Server;
 
 rustc_flags_capture! {}
 
-fn main() -> Result {
+fn main() -> Result<ExitCode> {
 	let args = clap::parse();
 	let runtime = runtime::new(&args)?;
+	if let Some(command) = &args.command {
+		return Ok(runtime.block_on(cli::run(&args, command)));
+	}
+
 	let server = Server::new(&args, Some(runtime.handle()))?;
 
 	runtime.spawn(signal::signal(server.clone()));
@@ -31,7 +40,7 @@ fn main() -> Result {
 	}
 
 	debug_info!("Exit");
-	Ok(())
+	Ok(ExitCode::SUCCESS)
 }
 
 /// Operate the server normally in release-mode static builds. This will start,
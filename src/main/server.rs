@@ -42,14 +42,17 @@ pub(crate) fn new(
 			.flat_map(<[_]>::iter)
 			.map(PathBuf::as_path);
 
-		let config = Config::load(config_paths)
-			.and_then(|raw| crate::clap::update(raw, args))
-			.and_then(|raw| Config::new(&raw))?;
+		let raw_config =
+			Config::load(config_paths).and_then(|raw| crate::clap::update(raw, args))?;
 
-		let (tracing_reload_handle, tracing_flame_guard, capture) =
+		let config = Config::new(&raw_config)?;
+		let virtual_hosts = config.virtual_hosts(&raw_config)?;
+
+		let (tracing_reload_handle, tracing_flame_guard, capture, file) =
 			crate::logging::init(&config)?;
 
 		config.check()?;
+		virtual_hosts.iter().try_for_each(Config::check)?;
 
 		#[cfg(feature = "sentry_telemetry")]
 		let sentry_guard = crate::sentry::init(&config);
@@ -69,11 +72,30 @@ pub(crate) fn new(
 			conduwuit_core::version(),
 		);
 
+		let log = Log {
+			reload: tracing_reload_handle,
+			capture,
+			file,
+		};
+
+		let virtual_hosts = virtual_hosts
+			.into_iter()
+			.inspect(|config| {
+				info!(
+					server_name = %config.server_name,
+					database_path = ?config.database_path,
+					"Hosting virtual host",
+				);
+			})
+			.map(|config| conduwuit_core::Server::new(config, runtime.cloned(), log.clone()))
+			.map(Arc::new)
+			.collect();
+
+		let mut server = conduwuit_core::Server::new(config, runtime.cloned(), log);
+		server.virtual_hosts = virtual_hosts;
+
 		Ok(Arc::new(Self {
-			server: Arc::new(conduwuit_core::Server::new(config, runtime.cloned(), Log {
-				reload: tracing_reload_handle,
-				capture,
-			})),
+			server: Arc::new(server),
 
 			services: None.into(),
 
@@ -18,6 +18,7 @@ pub(super) async fn signal(server: Arc<Server>) {
 	let mut term = unix::signal(SignalKind::terminate()).expect("SIGTERM handler");
 	let mut usr1 = unix::signal(SignalKind::user_defined1()).expect("SIGUSR1 handler");
 	let mut usr2 = unix::signal(SignalKind::user_defined2()).expect("SIGUSR2 handler");
+	let mut hup = unix::signal(SignalKind::hangup()).expect("SIGHUP handler");
 	loop {
 		trace!("Installed signal handlers");
 		let sig: &'static str;
@@ -27,9 +28,14 @@ pub(super) async fn signal(server: Arc<Server>) {
 			_ = term.recv() => { sig = "SIGTERM"; },
 			_ = usr1.recv() => { sig = "SIGUSR1"; },
 			_ = usr2.recv() => { sig = "SIGUSR2"; },
+			_ = hup.recv() => { sig = "SIGHUP"; },
 		}
 
 		warn!("Received {sig}");
+		if sig == "SIGHUP" {
+			reopen_log_file(&server);
+		}
+
 		let result = if RELOADING && sig == "SIGINT" {
 			server.server.reload()
 		} else if matches!(sig, "SIGQUIT" | "SIGTERM") || (!CONSOLE && sig == "SIGINT") {
@@ -44,6 +50,18 @@ pub(super) async fn signal(server: Arc<Server>) {
 	}
 }
 
+/// Reopens the log file at its path, after logrotate moved it away.
+#[cfg(unix)]
+fn reopen_log_file(server: &Server) {
+	let Some(file) = &server.server.log.file else {
+		return;
+	};
+
+	if let Err(e) = file.reopen() {
+		conduwuit_core::error!("Failed to reopen the log file: {e}");
+	}
+}
+
 #[cfg(not(unix))]
 #[tracing::instrument(skip_all)]
 pub(super) async fn signal(server: Arc<Server>) {
@@ -0,0 +1,270 @@
+#![cfg(test)]
+
+use std::process::ExitCode;
+
+use clap::Parser;
+use conduwuit_core::{err, utils::TempDir};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::TcpListener,
+};
+
+use crate::{
+	clap::Args,
+	cli::{Command, Failure, UserArgs, UserCommand, admin_url, run_user},
+};
+
+fn parse(args: &[&str]) -> Args {
+	Args::try_parse_from(std::iter::once("conduwuit").chain(args.iter().copied()))
+		.expect("arguments parse")
+}
+
+fn user_args(args: &Args) -> &UserArgs {
+	let Some(Command::User(user)) = &args.command else {
+		panic!("no user command");
+	};
+
+	user
+}
+
+/// Answers one request with `response`, returning the head and body of the
+/// request.
+async fn serve_once(listener: TcpListener, response: Value) -> (String, Vec<u8>) {
+	let (mut stream, _) = listener.accept().await.expect("accepted");
+	let mut request = Vec::new();
+	let mut chunk = [0_u8; 4096];
+	let (head, length) = loop {
+		let read = stream.read(&mut chunk).await.expect("read request");
+		assert_ne!(read, 0, "request ended early");
+		request.extend_from_slice(&chunk[..read]);
+
+		let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") else {
+			continue;
+		};
+
+		let head = String::from_utf8_lossy(&request[..end]).into_owned();
+		let length = head
+			.lines()
+			.filter_map(|line| line.split_once(':'))
+			.find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+			.map_or(0, |(_, value)| value.trim().parse().expect("content length"));
+
+		request.drain(..end.saturating_add(4));
+		break (head, length);
+	};
+
+	while request.len() < length {
+		let read = stream.read(&mut chunk).await.expect("read body");
+		assert_ne!(read, 0, "body ended early");
+		request.extend_from_slice(&chunk[..read]);
+	}
+
+	let response = response.to_string();
+	let response = format!(
+		"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: \
+		 close\r\n\r\n{response}",
+		response.len()
+	);
+
+	stream
+		.write_all(response.as_bytes())
+		.await
+		.expect("wrote response");
+
+	(head, request)
+}
+
+#[test]
+fn server_without_command() {
+	assert!(parse(&["--maintenance"]).command.is_none());
+}
+
+#[test]
+fn user_create() {
+	let args = parse(&["user", "--token", "secret", "create", "alice", "--admin", "--json"]);
+	let Some(Command::User(user)) = args.command else {
+		panic!("no user command");
+	};
+
+	assert_eq!(user.token.as_deref(), Some("secret"));
+	assert!(user.json, "--json after the subcommand");
+	let UserCommand::Create {
+		localpart,
+		password_stdin,
+		admin,
+		offline,
+	} = user.command
+	else {
+		panic!("not user create");
+	};
+
+	assert_eq!(localpart, "alice");
+	assert!(admin);
+	assert!(!password_stdin);
+	assert!(!offline);
+}
+
+#[test]
+fn user_subcommands() {
+	let args = parse(&["user", "reset-password", "@alice:example.org", "--password-stdin"]);
+	assert!(matches!(
+		args.command,
+		Some(Command::User(user)) if matches!(
+			&user.command,
+			UserCommand::ResetPassword { user_id, password_stdin: true } if user_id == "@alice:example.org"
+		)
+	));
+
+	let args = parse(&["user", "deactivate", "@bob:example.org", "--no-leave-rooms"]);
+	assert!(matches!(
+		args.command,
+		Some(Command::User(user)) if matches!(
+			&user.command,
+			UserCommand::Deactivate { no_leave_rooms: true, .. }
+		)
+	));
+
+	let args = parse(&["user", "list"]);
+	assert!(matches!(
+		args.command,
+		Some(Command::User(user)) if matches!(user.command, UserCommand::List)
+	));
+
+	assert!(
+		Args::try_parse_from(["conduwuit", "user", "create"]).is_err(),
+		"localpart needed"
+	);
+}
+
+#[test]
+fn exit_codes() {
+	let refused = Failure::Refused(
+		StatusCode::BAD_REQUEST,
+		json!({"errcode": "M_USER_IN_USE", "error": "User @alice:example.org already exists."}),
+	);
+
+	let cases = [
+		(refused, ExitCode::from(1)),
+		(Failure::Failed(err!("broken")), ExitCode::from(1)),
+		(Failure::Usage("no token".into()), ExitCode::from(2)),
+		(Failure::Unreachable(err!("connection refused")), ExitCode::from(3)),
+	];
+
+	for (failure, code) in cases {
+		assert_eq!(failure.exit_code(), code, "{failure:?}");
+	}
+}
+
+#[test]
+fn failure_output() {
+	let refused = Failure::Refused(
+		StatusCode::BAD_REQUEST,
+		json!({"errcode": "M_USER_IN_USE", "error": "User @alice:example.org already exists."}),
+	);
+
+	assert_eq!(
+		refused.describe(false),
+		"The server refused (400 Bad Request): M_USER_IN_USE User @alice:example.org already \
+		 exists."
+	);
+	assert_eq!(
+		serde_json::from_str::<serde_json::Value>(&refused.describe(true)).ok(),
+		Some(
+			json!({"errcode": "M_USER_IN_USE", "error": "User @alice:example.org already exists."})
+		)
+	);
+
+	let usage = Failure::Usage("no token".into());
+	assert_eq!(usage.describe(true), r#"{"error":"no token"}"#);
+}
+
+#[test]
+fn admin_urls() {
+	let url = admin_url("http://localhost:8008", &["users"]).expect("valid URL");
+	assert_eq!(url.as_str(), "http://localhost:8008/_conduwuit/admin/v1/users");
+
+	let url = admin_url("https://example.org/matrix/", &["users", "@a/b?c#d:example.org"])
+		.expect("valid URL");
+	assert_eq!(
+		url.as_str(),
+		"https://example.org/matrix/_conduwuit/admin/v1/users/@a%2Fb%3Fc%23d:example.org",
+		"user ID kept to its path segment"
+	);
+
+	assert!(matches!(admin_url("localhost:8008", &[]), Err(Failure::Usage(_))));
+}
+
+#[tokio::test]
+async fn user_reset_password_over_api() {
+	let listener = TcpListener::bind("127.0.0.1:0").await.expect("bound");
+	let url = format!("http://{}", listener.local_addr().expect("address"));
+	let args = parse(&[
+		"user",
+		"--url",
+		&url,
+		"--token",
+		"secret",
+		"reset-password",
+		"@al?ice/x:example.org",
+	]);
+
+	let (result, (head, body)) = tokio::join!(
+		run_user(&args, user_args(&args)),
+		serve_once(listener, json!({ "password": "generated" })),
+	);
+
+	let request_line = head.lines().next().unwrap_or_default();
+	assert_eq!(
+		request_line,
+		"POST /_conduwuit/admin/v1/users/@al%3Fice%2Fx:example.org/password HTTP/1.1"
+	);
+	assert!(
+		head.to_ascii_lowercase()
+			.contains("authorization: bearer secret"),
+		"{head}"
+	);
+	assert_eq!(serde_json::from_slice::<Value>(&body).ok(), Some(json!({ "password": null })));
+
+	assert_eq!(
+		result.expect("password reset"),
+		json!({ "password": "generated", "user_id": "@al?ice/x:example.org" })
+	);
+}
+
+#[cfg(any(not(conduwuit_mods), not(feature = "conduwuit_mods")))]
+#[tokio::test(flavor = "multi_thread")]
+async fn user_create_offline() {
+	let dir = TempDir::new("cli_offline");
+	let database_path = format!("database_path={:?}", dir.display().to_string());
+	let args = parse(&[
+		"-O",
+		"server_name=\"example.org\"",
+		"-O",
+		&database_path,
+		"-O",
+		"rocksdb_direct_io=false",
+		"-O",
+		"allow_federation=false",
+		"user",
+		"--url",
+		"http://127.0.0.1:1",
+		"--token",
+		"secret",
+		"create",
+		"alice",
+		"--offline",
+	]);
+
+	let result = run_user(&args, user_args(&args))
+		.await
+		.expect("created offline");
+
+	assert_eq!(result["user_id"], "@alice:example.org");
+	assert!(
+		result["password"]
+			.as_str()
+			.is_some_and(|password| !password.is_empty())
+	);
+}
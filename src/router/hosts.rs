@@ -0,0 +1,116 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+	Router,
+	extract::{Request, State},
+	response::Response,
+};
+use conduwuit::config::Config;
+use http::{HeaderMap, Uri, header};
+use tower::ServiceExt;
+
+/// Routers of the servers hosted by this process, the main server's first.
+pub(crate) struct Hosts {
+	pub(crate) routers: Vec<Router>,
+	pub(crate) names: HashMap<String, usize>,
+}
+
+/// Router dispatching each request to the router of the server it is
+/// addressed to; `routers` are paired with the configuration of their server.
+pub(crate) fn build<I>(routers: I) -> Router
+where
+	I: IntoIterator<Item = (Arc<Config>, Router)>,
+{
+	let mut hosts = Hosts {
+		routers: Vec::new(),
+		names: HashMap::new(),
+	};
+
+	for (config, router) in routers {
+		let index = hosts.routers.len();
+		hosts.insert(config.server_name.as_str(), index);
+		if let Some(server) = &config.well_known.server {
+			hosts.insert(server.as_str(), index);
+		}
+
+		if let Some(host) = config
+			.well_known
+			.client
+			.as_ref()
+			.and_then(|url| url.host_str())
+		{
+			hosts.insert(host, index);
+		}
+
+		hosts.routers.push(router);
+	}
+
+	Router::new().fallback(dispatch).with_state(Arc::new(hosts))
+}
+
+async fn dispatch(State(hosts): State<Arc<Hosts>>, request: Request) -> Response {
+	let index = hosts.select(request.headers(), request.uri());
+	hosts.routers[index]
+		.clone()
+		.oneshot(request)
+		.await
+		.unwrap_or_else(|e| match e {})
+}
+
+impl Hosts {
+	/// Names are claimed by the first server to configure them.
+	pub(crate) fn insert(&mut self, name: &str, index: usize) {
+		let name = name.to_ascii_lowercase();
+		if let Some(host) = strip_port(&name) {
+			self.names.entry(host.to_owned()).or_insert(index);
+		}
+
+		self.names.entry(name).or_insert(index);
+	}
+
+	/// Index of the router for a request. Federation requests are addressed by
+	/// their X-Matrix destination, falling back to the Host for everything
+	/// else, and then to the main server.
+	pub(crate) fn select(&self, headers: &HeaderMap, uri: &Uri) -> usize {
+		destination(headers)
+			.into_iter()
+			.chain(host(headers, uri))
+			.find_map(|name| self.find(name))
+			.unwrap_or(0)
+	}
+
+	fn find(&self, name: &str) -> Option<usize> {
+		let name = name.to_ascii_lowercase();
+		self.names
+			.get(&name)
+			.or_else(|| strip_port(&name).and_then(|host| self.names.get(host)))
+			.copied()
+	}
+}
+
+fn destination(headers: &HeaderMap) -> Option<&str> {
+	let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+	let (scheme, params) = value.split_once(' ')?;
+	if !scheme.eq_ignore_ascii_case("X-Matrix") {
+		return None;
+	}
+
+	params
+		.split(',')
+		.filter_map(|param| param.split_once('='))
+		.find(|(key, _)| key.trim().eq_ignore_ascii_case("destination"))
+		.map(|(_, value)| value.trim().trim_matches('"'))
+}
+
+fn host<'a>(headers: &'a HeaderMap, uri: &'a Uri) -> Option<&'a str> {
+	headers
+		.get(header::HOST)
+		.and_then(|host| host.to_str().ok())
+		.or_else(|| uri.authority().map(http::uri::Authority::as_str))
+}
+
+fn strip_port(name: &str) -> Option<&str> {
+	name.rsplit_once(':')
+		.filter(|(_, port)| !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()))
+		.map(|(host, _)| host)
+}
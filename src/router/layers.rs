@@ -23,7 +23,7 @@
 };
 use tracing::Level;
 
-use crate::{request, router};
+use crate::{hosts, request, router};
 
 const CONDUWUIT_CSP: &[&str; 5] = &[
 	"default-src 'none'",
@@ -35,7 +35,24 @@
 
 const CONDUWUIT_PERMISSIONS_POLICY: &[&str; 2] = &["interest-cohort=()", "browsing-topics=()"];
 
-pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
+pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Vec<Guard>)> {
+	let (router, guard) = build_host(services)?;
+	if services.virtual_hosts.is_empty() {
+		return Ok((router, vec![guard]));
+	}
+
+	let mut guards = vec![guard];
+	let mut routers = vec![(services.server.config.clone(), router)];
+	for host in &services.virtual_hosts {
+		let (router, guard) = build_host(host)?;
+		routers.push((host.server.config.clone(), router));
+		guards.push(guard);
+	}
+
+	Ok((hosts::build(routers), guards))
+}
+
+fn build_host(services: &Arc<Services>) -> Result<(Router, Guard)> {
 	let server = &services.server;
 	let layers = ServiceBuilder::new();
 
@@ -1,10 +1,13 @@
 #![type_length_limit = "32768"] //TODO: reduce me
 
+mod hosts;
 mod layers;
 mod request;
 mod router;
 mod run;
 mod serve;
+#[cfg(test)]
+mod tests;
 
 extern crate conduwuit_core as conduwuit;
 
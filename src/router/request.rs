@@ -8,10 +8,11 @@
 	extract::State,
 	response::{IntoResponse, Response},
 };
-use conduwuit::{Result, debug, debug_error, debug_warn, err, error, trace};
-use conduwuit_service::Services;
+use conduwuit::{Error, Result, debug, debug_error, debug_warn, err, error, trace};
+use conduwuit_service::{Services, shedding};
 use futures::FutureExt;
-use http::{Method, StatusCode, Uri};
+use http::{HeaderValue, Method, StatusCode, Uri, header};
+use ruma::api::client::error::{ErrorKind, RetryAfter};
 use tokio::time::sleep;
 use tracing::Span;
 
@@ -31,11 +32,27 @@ pub(crate) async fn handle(
 		return Err(StatusCode::SERVICE_UNAVAILABLE);
 	}
 
+	let class = shedding::classify(req.method(), req.uri().path());
+	let admission = match services.shedding.admit(class) {
+		| Ok(admission) => admission,
+		| Err(retry_after) => {
+			debug_warn!(
+				method = %req.method(),
+				uri = %req.uri(),
+				%class,
+				"shed under load"
+			);
+
+			return Ok(shed(retry_after));
+		},
+	};
+
 	let uri = req.uri().clone();
 	let method = req.method().clone();
 	let services_ = services.clone();
 	let parent = Span::current();
 	let task = services.server.runtime().spawn(async move {
+		let _admission = admission;
 		tokio::select! {
 			response = execute(&services_, req, next, &parent) => response,
 			response = services_.server.until_shutdown()
@@ -118,6 +135,25 @@ fn handle_result(method: &Method, uri: &Uri, result: Response) -> Result<Respons
 	Ok(result)
 }
 
+/// Response to a request rejected by load shedding, asking the client to back
+/// off for `retry_after`.
+fn shed(retry_after: Duration) -> Response {
+	let kind = ErrorKind::LimitExceeded {
+		retry_after: Some(RetryAfter::Delay(retry_after)),
+	};
+
+	let message = "Server is overloaded; try again later.";
+	let mut response =
+		Error::Request(kind, message.into(), StatusCode::SERVICE_UNAVAILABLE).into_response();
+
+	response
+		.headers_mut()
+		.entry(header::RETRY_AFTER)
+		.or_insert_with(|| HeaderValue::from(retry_after.as_secs().max(1)));
+
+	response
+}
+
 #[cold]
 fn unhandled<Error: Debug>(e: Error) -> StatusCode {
 	error!("unhandled error or panic during request: {e:?}");
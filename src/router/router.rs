@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use axum::{Router, response::IntoResponse, routing::get};
+use axum::{Router, response::IntoResponse};
 use conduwuit::Error;
 use conduwuit_api::router::{state, state::Guard};
 use conduwuit_service::Services;
@@ -11,7 +11,6 @@ pub(crate) fn build(services: &Arc<Services>) -> (Router, Guard) {
 	let router = Router::<state::State>::new();
 	let (state, guard) = state::create(services.clone());
 	let router = conduwuit_api::router::build(router, &services.server)
-		.route("/", get(it_works))
 		.fallback(not_found)
 		.with_state(state);
 
@@ -21,5 +20,3 @@ pub(crate) fn build(services: &Arc<Services>) -> (Router, Guard) {
 async fn not_found(_uri: Uri) -> impl IntoResponse {
 	Error::Request(ErrorKind::Unrecognized, "Not Found".into(), StatusCode::NOT_FOUND)
 }
-
-async fn it_works() -> &'static str { "hewwo from conduwuit woof!" }
@@ -26,6 +26,9 @@ pub(crate) async fn run(services: Arc<Services>) -> Result<()> {
 
 	// Install the admin room callback here for now
 	admin::init(&services.admin).await;
+	for host in &services.virtual_hosts {
+		admin::init(&host.admin).await;
+	}
 
 	// Setup shutdown/signal handling
 	let handle = ServerHandle::new();
@@ -51,6 +54,9 @@ pub(crate) async fn run(services: Arc<Services>) -> Result<()> {
 	_ = sigs.await;
 
 	// Remove the admin room callback
+	for host in &services.virtual_hosts {
+		admin::fini(&host.admin).await;
+	}
 	admin::fini(&services.admin).await;
 
 	debug_info!("Finish");
@@ -122,6 +128,14 @@ async fn handle_shutdown(server: Arc<Server>, tx: Sender<()>, handle: axum_serve
 		error!("failed sending shutdown transaction to channel: {e}");
 	}
 
+	for host in &server.virtual_hosts {
+		if host.running() {
+			_ = host
+				.shutdown()
+				.inspect_err(|e| error!("Failed to shut down {}: {e}", host.name));
+		}
+	}
+
 	let timeout = server.config.client_shutdown_timeout;
 	let timeout = Duration::from_secs(timeout);
 	debug!(
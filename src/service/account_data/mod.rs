@@ -2,12 +2,14 @@
 
 use conduwuit::{
 	Err, Result, err, implement,
-	utils::{ReadyExt, result::LogErr, stream::TryIgnore},
+	utils::{
+		IterStream, MutexMap, ReadyExt, result::LogErr, set::latest_by_key, stream::TryIgnore,
+	},
 };
-use database::{Deserialized, Handle, Ignore, Json, Map};
-use futures::{Stream, StreamExt, TryFutureExt};
+use database::{Deserialized, Handle, Json, Map};
+use futures::{FutureExt, Stream, StreamExt, TryFutureExt};
 use ruma::{
-	RoomId, UserId,
+	OwnedUserId, RoomId, UserId,
 	events::{
 		AnyGlobalAccountDataEvent, AnyRawAccountDataEvent, AnyRoomAccountDataEvent,
 		GlobalAccountDataEventType, RoomAccountDataEventType,
@@ -21,6 +23,10 @@
 pub struct Service {
 	services: Services,
 	db: Data,
+
+	/// Held updating a user's account data, so that of two updates of the same
+	/// type at once the later is the one kept.
+	mutex: MutexMap<OwnedUserId, ()>,
 }
 
 struct Data {
@@ -42,6 +48,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				roomuserdataid_accountdata: args.db["roomuserdataid_accountdata"].clone(),
 				roomusertype_roomuserdataid: args.db["roomusertype_roomuserdataid"].clone(),
 			},
+			mutex: MutexMap::new(),
 		}))
 	}
 
@@ -63,6 +70,7 @@ pub async fn update(
 		return Err!(Request(InvalidParam("Account data doesn't have all required fields.")));
 	}
 
+	let lock = self.mutex.lock(user_id).await;
 	let count = self.services.globals.next_count().unwrap();
 	let roomuserdataid = (room_id, user_id, count, &event_type);
 	self.db
@@ -78,6 +86,8 @@ pub async fn update(
 		self.db.roomuserdataid_accountdata.remove(&prev);
 	}
 
+	drop(lock);
+
 	Ok(())
 }
 
@@ -123,7 +133,26 @@ pub async fn get_raw(
 		.await
 }
 
-/// Returns all changes to the account data that happened after `since`.
+/// Identifies the current entry of a kind of the user's account data, which
+/// changes with every update of it.
+#[implement(Service)]
+pub async fn get_entry_id(
+	&self,
+	room_id: Option<&RoomId>,
+	user_id: &UserId,
+	kind: &str,
+) -> Result<Vec<u8>> {
+	let key = (room_id, user_id, kind.to_owned());
+	self.db
+		.roomusertype_roomuserdataid
+		.qry(&key)
+		.await
+		.map(|roomuserdataid| roomuserdataid.to_vec())
+}
+
+/// Returns the latest account data of each type changed after `since` and up
+/// to `to`. An entry replaced while they are read is not returned alongside
+/// the one replacing it.
 #[implement(Service)]
 pub fn changes_since<'a>(
 	&'a self,
@@ -132,7 +161,7 @@ pub fn changes_since<'a>(
 	since: u64,
 	to: Option<u64>,
 ) -> impl Stream<Item = AnyRawAccountDataEvent> + Send + 'a {
-	type Key<'a> = (Option<&'a RoomId>, &'a UserId, u64, Ignore);
+	type Key<'a> = (Option<&'a RoomId>, &'a UserId, u64, &'a str);
 
 	// Skip the data that's exactly at since, because we sent that last time
 	let first_possible = (room_id, user_id, since.saturating_add(1));
@@ -141,18 +170,24 @@ pub fn changes_since<'a>(
 		.roomuserdataid_accountdata
 		.stream_from(&first_possible)
 		.ignore_err()
-		.ready_take_while(move |((room_id_, user_id_, count, _), _): &(Key<'_>, _)| {
+		.ready_take_while(move |((room_id_, user_id_, count, _), _): &(Key<'_>, &[u8])| {
 			room_id == *room_id_ && user_id == *user_id_ && to.is_none_or(|to| *count <= to)
 		})
-		.map(move |(_, v)| {
-			match room_id {
+		.map(move |((.., kind), v)| {
+			let event = match room_id {
 				| Some(_) => serde_json::from_slice::<Raw<AnyRoomAccountDataEvent>>(v)
 					.map(AnyRawAccountDataEvent::Room),
 				| None => serde_json::from_slice::<Raw<AnyGlobalAccountDataEvent>>(v)
 					.map(AnyRawAccountDataEvent::Global),
 			}
 			.map_err(|e| err!(Database("Database contains invalid account data: {e}")))
-			.log_err()
+			.log_err();
+
+			(kind.to_owned(), event)
 		})
+		.collect::<Vec<_>>()
+		.map(|changes| latest_by_key(changes, |(kind, _)| kind.clone()))
+		.map(|changes| changes.into_iter().map(|(_, event)| event).stream())
+		.flatten_stream()
 		.ignore_err()
 }
@@ -0,0 +1,408 @@
+//! Account Validity
+//!
+//! Accounts expire a configured number of days after registering or their
+//! last renewal, and their requests are refused until renewed. Before expiry
+//! the worker emails each account a link which renews it, and a new link
+//! whenever the last stopped working; admins may renew or expire accounts
+//! themselves.
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Error, Result, Server, debug, debug_info, debug_warn, implement, info,
+	utils::{self, ReadyExt, stream::TryIgnore, time},
+};
+use database::{Deserialized, Lane, Map};
+use futures::StreamExt;
+use http::StatusCode;
+use ruma::{
+	OwnedUserId, UserId,
+	api::client::error::{Error as RumaError, ErrorBody},
+};
+use serde_json::json;
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, admin, appservice, globals, mailer, users};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+	interrupt: Notify,
+}
+
+struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
+	appservice: Dep<appservice::Service>,
+	globals: Dep<globals::Service>,
+	mailer: Dep<mailer::Service>,
+	users: Dep<users::Service>,
+}
+
+struct Data {
+	renewaltoken_userid: Arc<Map>,
+	userid_accountvalidity: Arc<Map>,
+	userid_email: Arc<Map>,
+	userid_renewaltoken: Arc<Map>,
+}
+
+/// When an account expires and when it was sent its renewal link, zero until
+/// it is; both in milliseconds since the epoch.
+type Validity = (u64, u64);
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const TOKEN_LENGTH: usize = 32;
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// How long a renewal link works after it was sent.
+const TOKEN_LIFETIME: u64 = 30 * DAY_MILLIS;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+				appservice: args.depend::<appservice::Service>("appservice"),
+				globals: args.depend::<globals::Service>("globals"),
+				mailer: args.depend::<mailer::Service>("mailer"),
+				users: args.depend::<users::Service>("users"),
+			},
+			db: Data {
+				renewaltoken_userid: args.db["renewaltoken_userid"].clone(),
+				userid_accountvalidity: args.db["userid_accountvalidity"].clone(),
+				userid_email: args.db["userid_email"].clone(),
+				userid_renewaltoken: args.db["userid_renewaltoken"].clone(),
+			},
+			interrupt: Notify::new(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		if !self.is_enabled() {
+			return Ok(());
+		}
+
+		let started = self.backfill().await;
+		if started > 0 {
+			info!(%started, "Started the validity of existing accounts");
+		}
+
+		let mut i = interval(SWEEP_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			let (reminded, expired) = Lane::Background.scope(self.sweep()).await;
+			if reminded > 0 || expired > 0 {
+				debug_info!(%reminded, %expired, "Sent renewal links");
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether accounts expire at all.
+#[implement(Service)]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.services.server.config.account_validity_days > 0 }
+
+/// Starts the validity of a newly registered account.
+#[implement(Service)]
+pub fn start(&self, user_id: &UserId) {
+	if self.is_enabled() {
+		let expires_at = utils::millis_since_unix_epoch().saturating_add(self.period());
+		self.db
+			.userid_accountvalidity
+			.put(user_id, (expires_at, 0_u64));
+	}
+}
+
+/// Starts the validity of the local accounts which have none, such as those
+/// registered before accounts expired, returning how many were started.
+#[implement(Service)]
+pub async fn backfill(&self) -> usize {
+	let missing: Vec<OwnedUserId> = self
+		.services
+		.users
+		.list_local_users()
+		.filter_map(|user_id| async move {
+			(!self.db.userid_accountvalidity.contains(user_id).await).then(|| user_id.to_owned())
+		})
+		.collect()
+		.await;
+
+	for user_id in &missing {
+		self.start(user_id);
+	}
+
+	missing.len()
+}
+
+/// When the account expires, if it does.
+#[implement(Service)]
+pub async fn expires_at(&self, user_id: &UserId) -> Option<SystemTime> {
+	self.validity(user_id)
+		.await
+		.map(|(expires_at, _)| system_time(expires_at))
+}
+
+/// Extends the account from now by `days`, or the configured period,
+/// returning when it now expires. Any renewal link already sent stops
+/// working.
+#[implement(Service)]
+pub async fn renew(&self, user_id: &UserId, days: Option<u64>) -> SystemTime {
+	let period = days.map_or_else(|| self.period(), |days| days.saturating_mul(DAY_MILLIS));
+	let expires_at = utils::millis_since_unix_epoch().saturating_add(period);
+
+	self.db
+		.userid_accountvalidity
+		.put(user_id, (expires_at, 0_u64));
+	self.forget_tokens(user_id).await;
+	info!(%user_id, "Renewed account");
+
+	system_time(expires_at)
+}
+
+/// Expires the account at once. It is sent a renewal link at the next sweep.
+#[implement(Service)]
+pub async fn expire(&self, user_id: &UserId) {
+	let now = utils::millis_since_unix_epoch();
+	self.db.userid_accountvalidity.put(user_id, (now, 0_u64));
+	self.forget_tokens(user_id).await;
+	info!(%user_id, "Expired account");
+}
+
+/// Renews the account the renewal link with `token` was sent to, unless the
+/// link stopped working.
+#[implement(Service)]
+pub async fn renew_with_token(&self, token: &str) -> Result<(OwnedUserId, SystemTime)> {
+	let found = self
+		.db
+		.renewaltoken_userid
+		.get(token)
+		.await
+		.deserialized::<(OwnedUserId, u64)>();
+
+	let now = utils::millis_since_unix_epoch();
+	let Some((user_id, _)) = found
+		.ok()
+		.filter(|&(_, sent_at)| !token_expired(sent_at, now))
+	else {
+		return Err!(Request(Forbidden("Invalid or expired renewal token.")));
+	};
+
+	let expires_at = self.renew(&user_id, None).await;
+
+	Ok((user_id, expires_at))
+}
+
+/// Refuses a request of an account which has expired, unless it is exempt.
+#[implement(Service)]
+pub async fn check(&self, user_id: &UserId, appservice: bool) -> Result {
+	if !self.is_enabled() {
+		return Ok(());
+	}
+
+	let Some((expires_at, _)) = self.validity(user_id).await else {
+		return Ok(());
+	};
+
+	if !expired(expires_at, utils::millis_since_unix_epoch()) {
+		return Ok(());
+	}
+
+	let config = &self.services.server.config;
+	if config.account_validity_exempt_appservices
+		&& (appservice || self.services.appservice.is_exclusive_user_id(user_id).await)
+	{
+		return Ok(());
+	}
+
+	if config.account_validity_exempt_admins && self.services.admin.user_is_admin(user_id).await {
+		return Ok(());
+	}
+
+	Err(expired_error())
+}
+
+/// The address account emails such as the renewal link are sent to.
+#[implement(Service)]
+pub async fn email(&self, user_id: &UserId) -> Option<String> {
+	self.db.userid_email.get(user_id).await.deserialized().ok()
+}
+
+#[implement(Service)]
+pub fn set_email(&self, user_id: &UserId, email: Option<&str>) {
+	match email {
+		| Some(email) => self.db.userid_email.insert(user_id, email),
+		| None => self.db.userid_email.remove(user_id),
+	}
+}
+
+/// Sends renewal links to the accounts expiring soon or since expired, once
+/// each, returning how many were sent and how many of the accounts expired.
+#[implement(Service)]
+async fn sweep(&self) -> (usize, usize) {
+	let now = utils::millis_since_unix_epoch();
+	let lead = self
+		.services
+		.server
+		.config
+		.account_validity_renew_days
+		.saturating_mul(DAY_MILLIS);
+
+	let due: Vec<(OwnedUserId, Validity)> = self
+		.db
+		.userid_accountvalidity
+		.stream()
+		.ignore_err()
+		.ready_filter(|(_, validity): &(&UserId, Validity)| reminder_due(*validity, now, lead))
+		.map(|(user_id, validity)| (user_id.to_owned(), validity))
+		.collect()
+		.await;
+
+	let (mut reminded, mut expired_count) = (0_usize, 0_usize);
+	for (user_id, (expires_at, _)) in due {
+		if expired(expires_at, now) {
+			expired_count = expired_count.saturating_add(1);
+		}
+
+		match self.send_renewal(&user_id, expires_at).await {
+			| Ok(()) => reminded = reminded.saturating_add(1),
+			| Err(e) => debug_warn!(%user_id, "Not sending renewal link: {e}"),
+		}
+
+		self.db
+			.userid_accountvalidity
+			.put(&user_id, (expires_at, now));
+	}
+
+	(reminded, expired_count)
+}
+
+#[implement(Service)]
+async fn send_renewal(&self, user_id: &UserId, expires_at: u64) -> Result {
+	let Some(email) = self.email(user_id).await else {
+		return Err!("the account has no email address");
+	};
+
+	let token = utils::random_string(TOKEN_LENGTH);
+	let link = format!(
+		"{}/_matrix/client/unstable/account_validity/renew?token={token}",
+		self.base_url()
+	);
+
+	let expires = time::format(system_time(expires_at), "%Y-%m-%d %H:%M UTC");
+	let context = [("user_id", user_id.to_string()), ("expires", expires), ("link", link)]
+		.into_iter()
+		.map(|(key, value)| (key.to_owned(), value))
+		.collect();
+
+	self.services
+		.mailer
+		.send("account_renewal", &email, context)
+		.await?;
+
+	self.forget_tokens(user_id).await;
+	let now = utils::millis_since_unix_epoch();
+	self.db.renewaltoken_userid.put(&token, (user_id, now));
+	self.db.userid_renewaltoken.insert(user_id, &token);
+	debug!(%user_id, "Queued renewal link");
+
+	Ok(())
+}
+
+#[implement(Service)]
+async fn validity(&self, user_id: &UserId) -> Option<Validity> {
+	self.db
+		.userid_accountvalidity
+		.get(user_id)
+		.await
+		.deserialized()
+		.ok()
+}
+
+#[implement(Service)]
+async fn forget_tokens(&self, user_id: &UserId) {
+	if let Ok(token) = self.db.userid_renewaltoken.get(user_id).await {
+		self.db.renewaltoken_userid.remove(&*token);
+		self.db.userid_renewaltoken.remove(user_id);
+	}
+}
+
+#[implement(Service)]
+fn period(&self) -> u64 {
+	self.services
+		.server
+		.config
+		.account_validity_days
+		.saturating_mul(DAY_MILLIS)
+}
+
+/// Where clients reach this server, for links in emails.
+#[implement(Service)]
+fn base_url(&self) -> String {
+	self.services
+		.server
+		.config
+		.well_known
+		.client
+		.as_ref()
+		.map_or_else(
+			|| format!("https://{}", self.services.globals.server_name()),
+			|url| url.as_str().trim_end_matches('/').to_owned(),
+		)
+}
+
+fn system_time(millis: u64) -> SystemTime {
+	UNIX_EPOCH
+		.checked_add(Duration::from_millis(millis))
+		.unwrap_or(UNIX_EPOCH)
+}
+
+pub(super) fn expired(expires_at: u64, now: u64) -> bool { now >= expires_at }
+
+/// Whether the renewal link is due: within `lead` of expiry, or after it, and
+/// not yet sent or sent so long ago it stopped working.
+pub(super) fn reminder_due((expires_at, reminded_at): Validity, now: u64, lead: u64) -> bool {
+	(reminded_at == 0 || token_expired(reminded_at, now))
+		&& now.saturating_add(lead) >= expires_at
+}
+
+pub(super) fn token_expired(sent_at: u64, now: u64) -> bool {
+	now >= sent_at.saturating_add(TOKEN_LIFETIME)
+}
+
+/// 403 ORG_MATRIX_EXPIRED_ACCOUNT, distinct from the codes of deactivated
+/// accounts so clients can point to renewal.
+pub(super) fn expired_error() -> Error {
+	let body = json!({
+		"errcode": "ORG_MATRIX_EXPIRED_ACCOUNT",
+		"error": "This account has expired. Renew it through the link sent to your email address.",
+	});
+
+	Error::Ruma(RumaError {
+		status_code: StatusCode::FORBIDDEN,
+		body: ErrorBody::Json(body),
+	})
+}
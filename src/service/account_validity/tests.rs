@@ -0,0 +1,114 @@
+use conduwuit::{config::Figment, utils};
+use http::StatusCode;
+use ruma::{
+	api::{OutgoingResponse, client::uiaa::UiaaResponse},
+	owned_user_id,
+};
+use serde_json::Value;
+
+use super::{DAY_MILLIS, TOKEN_LIFETIME, expired, expired_error, reminder_due, token_expired};
+use crate::testing::Test;
+
+const NOW: u64 = 1_700_000_000_000;
+
+#[test]
+fn expired_at_expiry() {
+	assert!(!expired(NOW.saturating_add(1), NOW));
+	assert!(expired(NOW, NOW));
+	assert!(expired(NOW.saturating_sub(1), NOW));
+}
+
+#[test]
+fn reminder_within_lead() {
+	let lead = DAY_MILLIS.saturating_mul(7);
+	let soon = NOW.saturating_add(DAY_MILLIS);
+	let later = NOW.saturating_add(DAY_MILLIS.saturating_mul(30));
+
+	assert!(reminder_due((soon, 0), NOW, lead));
+	assert!(!reminder_due((later, 0), NOW, lead));
+	assert!(reminder_due((NOW.saturating_sub(DAY_MILLIS), 0), NOW, lead), "since expired");
+}
+
+#[test]
+fn reminder_sent_once() {
+	let lead = DAY_MILLIS.saturating_mul(7);
+	let soon = NOW.saturating_add(DAY_MILLIS);
+
+	assert!(!reminder_due((soon, NOW.saturating_sub(1)), NOW, lead));
+	assert!(!reminder_due((NOW.saturating_sub(DAY_MILLIS), NOW), NOW, 0));
+}
+
+#[test]
+fn reminder_resent_once_link_stops_working() {
+	let expired_at = NOW.saturating_sub(DAY_MILLIS.saturating_mul(60));
+	let sent_at = NOW.saturating_sub(TOKEN_LIFETIME);
+
+	assert!(token_expired(sent_at, NOW));
+	assert!(!token_expired(sent_at.saturating_add(1), NOW));
+	assert!(reminder_due((expired_at, sent_at), NOW, 0));
+	assert!(!reminder_due((expired_at, sent_at.saturating_add(1)), NOW, 0));
+}
+
+#[test]
+fn reminder_without_lead_after_expiry() {
+	assert!(!reminder_due((NOW.saturating_add(1), 0), NOW, 0));
+	assert!(reminder_due((NOW, 0), NOW, 0));
+}
+
+#[test]
+fn expired_error_response() {
+	let response: UiaaResponse = expired_error().into();
+	let response = response
+		.try_into_http_response::<Vec<u8>>()
+		.expect("serializable response");
+
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+	let body: Value = serde_json::from_slice(response.body()).expect("json body");
+	assert_eq!(body["errcode"], "ORG_MATRIX_EXPIRED_ACCOUNT");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn existing_accounts_and_stale_links() {
+	let config = Figment::new()
+		.join(("server_name", "example.com"))
+		.join(("account_validity_days", 30));
+
+	let test = Test::start(config).await.expect("started");
+	let services = &test.services;
+	let validity = &services.account_validity;
+	let alice = owned_user_id!("@alice:example.com");
+	services
+		.users
+		.create(&alice, Some("password"))
+		.expect("created");
+
+	validity.backfill().await;
+	assert!(validity.expires_at(&alice).await.is_some(), "existing account expires");
+	assert_eq!(validity.backfill().await, 0, "validity started once");
+
+	let now = utils::millis_since_unix_epoch();
+	for (token, sent_at) in [("stale", now.saturating_sub(TOKEN_LIFETIME)), ("fresh", now)] {
+		validity.forget_tokens(&alice).await;
+		validity
+			.db
+			.renewaltoken_userid
+			.put(token, (&alice, sent_at));
+		validity.db.userid_renewaltoken.insert(&alice, token);
+	}
+
+	assert!(validity.renew_with_token("fresh").await.is_ok(), "link renews");
+	assert!(validity.renew_with_token("fresh").await.is_err(), "link used up");
+	assert!(
+		validity.db.renewaltoken_userid.get("stale").await.is_err(),
+		"replaced link forgotten"
+	);
+
+	validity
+		.db
+		.renewaltoken_userid
+		.put("stale", (&alice, now.saturating_sub(TOKEN_LIFETIME)));
+	assert!(validity.renew_with_token("stale").await.is_err(), "stale link refused");
+
+	test.stop().await;
+}
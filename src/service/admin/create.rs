@@ -2,7 +2,7 @@
 
 use conduwuit::{Result, pdu::PduBuilder};
 use ruma::{
-	RoomId, RoomVersionId,
+	OwnedRoomId, RoomId, RoomVersionId,
 	events::room::{
 		canonical_alias::RoomCanonicalAliasEventContent,
 		create::RoomCreateEventContent,
@@ -23,7 +23,7 @@
 ///
 /// Users in this room are considered admins by conduwuit, and the room can be
 /// used to issue admin commands by talking to the server user inside it.
-pub async fn create_admin_room(services: &Services) -> Result {
+pub async fn create_admin_room(services: &Services) -> Result<OwnedRoomId> {
 	let room_id = RoomId::new(services.globals.server_name());
 	let room_version = &services.config.default_room_version;
 
@@ -199,5 +199,5 @@ pub async fn create_admin_room(services: &Services) -> Result {
 		)
 		.await?;
 
-	Ok(())
+	Ok(room_id)
 }
@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
 
-use conduwuit::{Err, Result, debug_info, debug_warn, error, implement, matrix::pdu::PduBuilder};
+use conduwuit::{
+	Err, Result, debug_info, debug_warn, error, implement, matrix::pdu::PduBuilder, warn,
+};
 use ruma::{
 	RoomId, UserId,
 	events::{
@@ -144,6 +146,38 @@ pub async fn make_user_admin(&self, user_id: &UserId) -> Result {
 	Ok(())
 }
 
+/// Grant server admin to each of the configured `admin_users` not yet in the
+/// admin room.
+#[implement(super::Service)]
+pub async fn grant_configured_admins(&self) {
+	let Ok(room_id) = self.get_admin_room().await else {
+		return;
+	};
+
+	for user_id in &self.services.server.config.admin_users {
+		if self.services.globals.user_is_local(user_id)
+			&& !self.services.users.exists(user_id).await
+		{
+			warn!(%user_id, "Configured admin user does not exist");
+			continue;
+		}
+
+		if self.services.state_cache.is_joined(user_id, &room_id).await
+			|| self
+				.services
+				.state_cache
+				.is_invited(user_id, &room_id)
+				.await
+		{
+			continue;
+		}
+
+		if let Err(e) = self.make_user_admin(user_id).await {
+			warn!(%user_id, "Failed to grant admin to configured user: {e}");
+		}
+	}
+}
+
 #[implement(super::Service)]
 async fn set_room_tag(&self, room_id: &RoomId, user_id: &UserId, tag: &str) -> Result {
 	let mut event = self
@@ -2,6 +2,9 @@
 mod create;
 mod execute;
 mod grant;
+mod recover;
+#[cfg(test)]
+mod tests;
 
 use std::{
 	future::Future,
@@ -16,13 +19,14 @@
 pub use create::create_admin_room;
 use futures::{FutureExt, TryFutureExt};
 use loole::{Receiver, Sender};
+pub use recover::recreate_admin_room;
 use ruma::{
 	OwnedEventId, OwnedRoomId, RoomId, UserId,
 	events::room::message::{Relation, RoomMessageEventContent},
 };
 use tokio::sync::RwLock;
 
-use crate::{Dep, account_data, globals, rooms, rooms::state::RoomMutexGuard};
+use crate::{Dep, account_data, globals, rooms, rooms::state::RoomMutexGuard, users};
 
 pub struct Service {
 	services: Services,
@@ -42,6 +46,7 @@ struct Services {
 	state_cache: Dep<rooms::state_cache::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	account_data: Dep<account_data::Service>,
+	users: Dep<users::Service>,
 	services: StdRwLock<Option<Weak<crate::Services>>>,
 }
 
@@ -89,6 +94,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				account_data: args.depend::<account_data::Service>("account_data"),
+				users: args.depend::<users::Service>("users"),
 				services: None.into(),
 			},
 			channel: loole::bounded(COMMAND_QUEUE_LIMIT),
@@ -103,6 +109,10 @@ async fn worker(self: Arc<Self>) -> Result<()> {
 		let mut signals = self.services.server.signal.subscribe();
 		let receiver = self.channel.1.clone();
 
+		if !self.services.globals.is_read_only() {
+			self.grant_configured_admins().await;
+		}
+
 		self.startup_execute().await?;
 		self.console_auto_start().await;
 
@@ -0,0 +1,76 @@
+use conduwuit::{Result, info, pdu::PduBuilder, warn};
+use ruma::{OwnedRoomId, RoomId, events::room::tombstone::RoomTombstoneEventContent};
+
+use super::create_admin_room;
+use crate::Services;
+
+/// Replace the admin room with a newly created one.
+///
+/// The admin room alias is repointed to the new room as part of its creation;
+/// until then the old room, if any, remains the admin room. The old room is
+/// tombstoned when the server user can still send to it.
+pub async fn recreate_admin_room(services: &Services) -> Result<OwnedRoomId> {
+	let admin_alias = &services.globals.admin_alias;
+	let previous = services
+		.rooms
+		.alias
+		.resolve_local_alias(admin_alias)
+		.await
+		.ok();
+
+	let room_id = create_admin_room(services).await?;
+	info!(%room_id, ?previous, "Created new admin room");
+
+	let Some(previous) = superseded(previous, &room_id) else {
+		return Ok(room_id);
+	};
+
+	services
+		.rooms
+		.alias
+		.unpublish_alias(admin_alias, &previous)
+		.await;
+
+	if let Err(e) = tombstone(services, &previous, &room_id).await {
+		warn!(%previous, "Failed to tombstone previous admin room: {e}");
+	}
+
+	Ok(room_id)
+}
+
+async fn tombstone(services: &Services, room_id: &RoomId, replacement: &RoomId) -> Result {
+	let server_user = &services.globals.server_user;
+	if !services
+		.rooms
+		.state_cache
+		.is_joined(server_user, room_id)
+		.await
+	{
+		return Ok(());
+	}
+
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+	let content = RoomTombstoneEventContent::new(
+		"The admin room has been replaced.".to_owned(),
+		replacement.to_owned(),
+	);
+
+	services
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(String::new(), &content),
+			server_user,
+			room_id,
+			&state_lock,
+		)
+		.await?;
+
+	Ok(())
+}
+
+/// The previous admin room which needs retiring, if the alias resolved to one
+/// other than the replacement.
+pub(super) fn superseded(previous: Option<OwnedRoomId>, room_id: &RoomId) -> Option<OwnedRoomId> {
+	previous.filter(|previous| previous != room_id)
+}
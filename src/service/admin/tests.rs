@@ -0,0 +1,96 @@
+use conduwuit::config::Figment;
+use ruma::{
+	events::{StateEventType, room::tombstone::RoomTombstoneEventContent},
+	owned_room_id, owned_user_id, room_id,
+};
+
+use super::{recover::superseded, recreate_admin_room};
+use crate::testing::Test;
+
+#[test]
+fn recreate_without_previous_room() {
+	let room_id = room_id!("!new:example.com");
+
+	assert_eq!(superseded(None, room_id), None, "nothing to tombstone when alias is unresolved");
+}
+
+#[test]
+fn recreate_supersedes_previous_room() {
+	let room_id = room_id!("!new:example.com");
+	let previous = owned_room_id!("!old:example.com");
+
+	assert_eq!(superseded(Some(previous.clone()), room_id), Some(previous));
+	assert_eq!(superseded(Some(room_id.to_owned()), room_id), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recreate_unresolved_admin_room() {
+	let alice = owned_user_id!("@alice:example.com");
+	let config = Figment::new()
+		.join(("server_name", "example.com"))
+		.join(("admin_users", [alice.as_str()]));
+
+	let test = Test::start(config).await.expect("started");
+	let services = &test.services;
+	let (admin_alias, server_user) =
+		(&services.globals.admin_alias, &services.globals.server_user);
+	services.users.create(&alice, None).expect("created alice");
+
+	// the alias still names the admin room, which is gone
+	let gone = room_id!("!gone:example.com");
+	services
+		.rooms
+		.alias
+		.set_alias(admin_alias, gone, server_user)
+		.expect("alias repointed");
+
+	assert!(services.admin.get_admin_room().await.is_err(), "admin room unresolved");
+
+	let room_id = recreate_admin_room(services).await.expect("recreated");
+	assert_ne!(room_id, gone);
+	assert_eq!(services.admin.get_admin_room().await.ok().as_ref(), Some(&room_id));
+	assert_eq!(
+		services
+			.rooms
+			.alias
+			.resolve_local_alias(admin_alias)
+			.await
+			.ok(),
+		Some(room_id.clone()),
+		"alias repointed to the new room"
+	);
+
+	let state_cache = &services.rooms.state_cache;
+	services.admin.grant_configured_admins().await;
+	assert!(state_cache.is_joined(&alice, &room_id).await, "configured admin joined");
+
+	// granting again leaves them be
+	services.admin.grant_configured_admins().await;
+	assert!(state_cache.is_joined(&alice, &room_id).await);
+
+	test.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recreate_tombstones_previous_admin_room() {
+	let test = Test::start(Figment::new().join(("server_name", "example.com")))
+		.await
+		.expect("started");
+
+	let services = &test.services;
+	let previous = services.admin.get_admin_room().await.expect("admin room");
+	let room_id = recreate_admin_room(services).await.expect("recreated");
+	assert_ne!(room_id, previous);
+	assert_eq!(services.admin.get_admin_room().await.ok().as_ref(), Some(&room_id));
+
+	let tombstone: RoomTombstoneEventContent = services
+		.rooms
+		.state_accessor
+		.room_state_get_content(&previous, &StateEventType::RoomTombstone, "")
+		.await
+		.expect("previous room tombstoned");
+
+	assert_eq!(tombstone.replacement_room, room_id);
+
+	test.stop().await;
+}
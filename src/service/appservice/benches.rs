@@ -0,0 +1,74 @@
+#[cfg(conduwuit_bench)]
+extern crate test;
+
+#[cfg(conduwuit_bench)]
+fn registrations() -> std::collections::BTreeMap<String, super::RegistrationInfo> {
+	use ruma::api::appservice::{Namespace, Registration};
+
+	(0..10)
+		.map(|bridge| {
+			let yaml = format!(
+				"id: bridge{bridge}\nurl: null\nas_token: as{bridge}\nhs_token: \
+				 hs{bridge}\nsender_localpart: bridge{bridge}\nnamespaces: {{users: [], \
+				 aliases: [], rooms: []}}\n"
+			);
+
+			let mut registration: Registration =
+				serde_yaml::from_str(&yaml).expect("valid registration");
+
+			registration.namespaces.users = (0..50)
+				.map(|ns| {
+					Namespace::new(true, format!("@_bridge{bridge}_ns{ns}_.*:example\\.org"))
+				})
+				.collect();
+
+			let info: super::RegistrationInfo =
+				registration.try_into().expect("valid namespaces");
+
+			(info.registration.id.clone(), info)
+		})
+		.collect()
+}
+
+#[cfg(conduwuit_bench)]
+const USERS: [&str; 4] = [
+	"@_bridge9_ns49_user:example.org",
+	"@_bridge0_ns0_user:example.org",
+	"@alice:example.org",
+	"@_bridge5_nsx_user:example.org",
+];
+
+#[cfg(conduwuit_bench)]
+#[cfg_attr(conduwuit_bench, bench)]
+fn exclusive_user_naive(b: &mut test::Bencher) {
+	let infos = registrations();
+
+	b.iter(|| {
+		for user in USERS {
+			test::black_box(
+				infos
+					.values()
+					.any(|info| info.users.is_exclusive_match(user)),
+			);
+		}
+	});
+}
+
+#[cfg(conduwuit_bench)]
+#[cfg_attr(conduwuit_bench, bench)]
+fn exclusive_user_indexed(b: &mut test::Bencher) {
+	let infos = registrations();
+	let index = super::NamespaceIndex::new(
+		infos
+			.iter()
+			.map(|(id, info)| (id.as_str(), info.registration.namespaces.users.as_slice())),
+	);
+
+	b.iter(|| {
+		for user in USERS {
+			test::black_box(super::any_exclusive(&infos, &index, user, |info| {
+				info.users.is_exclusive_match(user)
+			}));
+		}
+	});
+}
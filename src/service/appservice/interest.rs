@@ -0,0 +1,50 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use ruma::{OwnedRoomId, RoomId};
+
+use super::RegistrationInfo;
+
+/// Per-room cache of whether each appservice is interested in a room for one
+/// reason, such as its members or aliases. Owners invalidate a room when the
+/// state the answer depends on changes; answers for a superseded registration
+/// are ignored.
+#[derive(Debug, Default)]
+pub struct InterestCache {
+	rooms: RwLock<HashMap<OwnedRoomId, HashMap<String, (u64, bool)>>>,
+}
+
+impl InterestCache {
+	#[must_use]
+	pub fn get(&self, room_id: &RoomId, appservice: &RegistrationInfo) -> Option<bool> {
+		self.rooms
+			.read()
+			.expect("locked")
+			.get(room_id)
+			.and_then(|room| room.get(&appservice.registration.id))
+			.filter(|(serial, _)| *serial == appservice.serial)
+			.map(|(_, interested)| *interested)
+	}
+
+	pub fn insert(&self, room_id: &RoomId, appservice: &RegistrationInfo, interested: bool) {
+		self.rooms
+			.write()
+			.expect("locked")
+			.entry(room_id.into())
+			.or_default()
+			.insert(appservice.registration.id.clone(), (appservice.serial, interested));
+	}
+
+	pub fn invalidate(&self, room_id: &RoomId) {
+		self.rooms.write().expect("locked").remove(room_id);
+	}
+
+	pub fn clear(&self) { self.rooms.write().expect("locked").clear(); }
+
+	/// Number of rooms cached and the capacity of the cache.
+	#[must_use]
+	pub fn usage(&self) -> (usize, usize) {
+		let rooms = self.rooms.read().expect("locked");
+
+		(rooms.len(), rooms.capacity())
+	}
+}
@@ -1,24 +1,44 @@
+#[cfg(test)]
+mod benches;
+mod interest;
+mod namespace_index;
 mod namespace_regex;
 mod registration_info;
+#[cfg(test)]
+mod tests;
 
 use std::{collections::BTreeMap, sync::Arc};
 
 use async_trait::async_trait;
-use conduwuit::{Result, err, utils::stream::TryIgnore};
+use conduwuit::{Result, err, error, utils::stream::TryIgnore};
 use database::Map;
 use futures::{Future, StreamExt, TryStreamExt};
-use ruma::{RoomAliasId, RoomId, UserId, api::appservice::Registration};
+use ruma::{
+	RoomAliasId, RoomId, UserId,
+	api::appservice::{Namespace, Registration},
+};
 use tokio::sync::RwLock;
 
-pub use self::{namespace_regex::NamespaceRegex, registration_info::RegistrationInfo};
+pub use self::{
+	interest::InterestCache, namespace_index::NamespaceIndex, namespace_regex::NamespaceRegex,
+	registration_info::RegistrationInfo,
+};
 use crate::{Dep, sending};
 
 pub struct Service {
 	registration_info: RwLock<BTreeMap<String, RegistrationInfo>>,
+	exclusive: std::sync::RwLock<Exclusive>,
 	services: Services,
 	db: Data,
 }
 
+/// Indexes of the exclusive namespaces of all registrations.
+#[derive(Default)]
+struct Exclusive {
+	users: NamespaceIndex,
+	aliases: NamespaceIndex,
+}
+
 struct Services {
 	sending: Dep<sending::Service>,
 }
@@ -32,6 +52,7 @@ impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			registration_info: RwLock::new(BTreeMap::new()),
+			exclusive: std::sync::RwLock::default(),
 			services: Services {
 				sending: args.depend::<sending::Service>("sending"),
 			},
@@ -42,15 +63,9 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 	}
 
 	async fn worker(self: Arc<Self>) -> Result<()> {
-		// Inserting registrations into cache
-		for appservice in self.iter_db_ids().await? {
-			self.registration_info.write().await.insert(
-				appservice.0,
-				appservice
-					.1
-					.try_into()
-					.expect("Should be validated on registration"),
-			);
+		let (_, failed) = self.reload().await;
+		for (id, e) in failed {
+			error!("Appservice {id:?} is not registered as its registration is invalid: {e}");
 		}
 
 		Ok(())
@@ -67,10 +82,10 @@ pub async fn register_appservice(
 		appservice_config_body: &str,
 	) -> Result {
 		//TODO: Check for collisions between exclusive appservice namespaces
-		self.registration_info
-			.write()
-			.await
-			.insert(registration.id.clone(), registration.clone().try_into()?);
+		let mut registration_info = self.registration_info.write().await;
+		registration_info.insert(registration.id.clone(), registration.clone().try_into()?);
+		self.reindex(&registration_info);
+		drop(registration_info);
 
 		self.db
 			.id_appserviceregistrations
@@ -86,12 +101,14 @@ pub async fn register_appservice(
 	/// * `service_name` - the registration ID of the appservice
 	pub async fn unregister_appservice(&self, appservice_id: &str) -> Result<()> {
 		// removes the appservice registration info
-		self.registration_info
-			.write()
-			.await
+		let mut registration_info = self.registration_info.write().await;
+		registration_info
 			.remove(appservice_id)
 			.ok_or_else(|| err!("Appservice not found"))?;
 
+		self.reindex(&registration_info);
+		drop(registration_info);
+
 		// remove the appservice from the database
 		self.db.id_appserviceregistrations.del(appservice_id);
 
@@ -103,6 +120,37 @@ pub async fn unregister_appservice(&self, appservice_id: &str) -> Result<()> {
 			.await
 	}
 
+	/// Reads every registration from the database again and uses those instead
+	/// of the registrations in use, returning how many there are, with any
+	/// which could not be used and why.
+	pub async fn reload(&self) -> (usize, Vec<(String, String)>) {
+		let ids: Vec<String> = self
+			.db
+			.id_appserviceregistrations
+			.keys()
+			.ignore_err()
+			.collect()
+			.await;
+
+		let mut registrations = Vec::with_capacity(ids.len());
+		let mut failed = Vec::new();
+		for id in ids {
+			match self.get_db_registration(&id).await {
+				| Ok(registration) => registrations.push(registration),
+				| Err(e) => failed.push((id, e.to_string())),
+			}
+		}
+
+		let (infos, invalid) = compile(registrations);
+		failed.extend(invalid);
+
+		let mut registration_info = self.registration_info.write().await;
+		*registration_info = infos;
+		self.reindex(&registration_info);
+
+		(registration_info.len(), failed)
+	}
+
 	pub async fn get_registration(&self, id: &str) -> Option<Registration> {
 		self.registration_info
 			.read()
@@ -131,18 +179,27 @@ pub async fn find_from_token(&self, token: &str) -> Option<RegistrationInfo> {
 
 	/// Checks if a given user id matches any exclusive appservice regex
 	pub async fn is_exclusive_user_id(&self, user_id: &UserId) -> bool {
-		self.read()
-			.await
+		let infos = self.read().await;
+		if infos
 			.values()
-			.any(|info| info.is_exclusive_user_match(user_id))
+			.any(|info| info.registration.sender_localpart == user_id.localpart())
+		{
+			return true;
+		}
+
+		let exclusive = self.exclusive.read().expect("locked");
+		any_exclusive(&infos, &exclusive.users, user_id.as_str(), |info| {
+			info.users.is_exclusive_match(user_id.as_str())
+		})
 	}
 
 	/// Checks if a given room alias matches any exclusive appservice regex
 	pub async fn is_exclusive_alias(&self, alias: &RoomAliasId) -> bool {
-		self.read()
-			.await
-			.values()
-			.any(|info| info.aliases.is_exclusive_match(alias.as_str()))
+		let infos = self.read().await;
+		let exclusive = self.exclusive.read().expect("locked");
+		any_exclusive(&infos, &exclusive.aliases, alias.as_str(), |info| {
+			info.aliases.is_exclusive_match(alias.as_str())
+		})
 	}
 
 	/// Checks if a given room id matches any exclusive appservice regex
@@ -175,6 +232,21 @@ pub async fn get_db_registration(&self, id: &str) -> Result<Registration> {
 			.map_err(|e| err!(Database("Invalid appservice {id:?} registration: {e:?}")))
 	}
 
+	fn reindex(&self, infos: &BTreeMap<String, RegistrationInfo>) {
+		let namespaces = |select: fn(&RegistrationInfo) -> &[Namespace]| {
+			infos
+				.iter()
+				.map(move |(id, info)| (id.as_str(), select(info)))
+		};
+
+		*self.exclusive.write().expect("locked") = Exclusive {
+			users: NamespaceIndex::new(namespaces(|info| &info.registration.namespaces.users)),
+			aliases: NamespaceIndex::new(namespaces(|info| {
+				&info.registration.namespaces.aliases
+			})),
+		};
+	}
+
 	async fn iter_db_ids(&self) -> Result<Vec<(String, Registration)>> {
 		self.db
 			.id_appserviceregistrations
@@ -188,3 +260,41 @@ async fn iter_db_ids(&self) -> Result<Vec<(String, Registration)>> {
 			.await
 	}
 }
+
+/// The registrations with their namespaces compiled, by ID, and those whose
+/// namespaces are not valid regular expressions, with why.
+fn compile<I>(registrations: I) -> (BTreeMap<String, RegistrationInfo>, Vec<(String, String)>)
+where
+	I: IntoIterator<Item = Registration>,
+{
+	let mut infos = BTreeMap::new();
+	let mut invalid = Vec::new();
+	for registration in registrations {
+		let id = registration.id.clone();
+		match RegistrationInfo::try_from(registration) {
+			| Ok(info) => {
+				infos.insert(id, info);
+			},
+			| Err(e) => invalid.push((id, e.to_string())),
+		}
+	}
+
+	(infos, invalid)
+}
+
+/// Whether `is_match` holds for any registration, evaluating only those which
+/// `index` finds could have an exclusive namespace matching `haystack`.
+pub fn any_exclusive<F>(
+	infos: &BTreeMap<String, RegistrationInfo>,
+	index: &NamespaceIndex,
+	haystack: &str,
+	is_match: F,
+) -> bool
+where
+	F: Fn(&RegistrationInfo) -> bool,
+{
+	match index.candidates(haystack) {
+		| Some(ids) => ids.into_iter().filter_map(|id| infos.get(id)).any(is_match),
+		| None => infos.values().any(is_match),
+	}
+}
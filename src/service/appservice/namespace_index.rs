@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ruma::api::appservice::Namespace;
+
+/// Literal prefixes of the exclusive namespaces of every appservice, so that
+/// only the registrations which could match an ID have their regexes run.
+///
+/// A prefix is taken from each regex up to its first metacharacter. It is only
+/// usable when the regex is anchored with `^`, or starts with the sigil of the
+/// ID: regexes are searched for anywhere in an ID, but a sigil which appears
+/// once at the start of an ID cannot begin a match anywhere else. Registrations
+/// with a namespace lacking a usable prefix are always evaluated.
+#[derive(Debug, Default)]
+pub struct NamespaceIndex {
+	nodes: Vec<Node>,
+	fallback: BTreeSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+	next: BTreeMap<u8, usize>,
+	ids: BTreeSet<String>,
+}
+
+const SIGILS: [char; 4] = ['@', '#', '!', '$'];
+
+impl NamespaceIndex {
+	pub fn new<'a, I>(registrations: I) -> Self
+	where
+		I: IntoIterator<Item = (&'a str, &'a [Namespace])>,
+	{
+		let mut index = Self {
+			nodes: vec![Node::default()],
+			..Self::default()
+		};
+		for (id, namespaces) in registrations {
+			for namespace in namespaces.iter().filter(|namespace| namespace.exclusive) {
+				match literal_prefix(&namespace.regex) {
+					| Some(prefix) => index.insert(&prefix, id),
+					| None => {
+						index.fallback.insert(id.to_owned());
+					},
+				}
+			}
+		}
+
+		index
+	}
+
+	/// IDs of the registrations which could have an exclusive namespace
+	/// matching `haystack`, or `None` when all of them have to be evaluated.
+	#[must_use]
+	pub fn candidates(&self, haystack: &str) -> Option<BTreeSet<&str>> {
+		let mut chars = haystack.chars();
+		if chars
+			.next()
+			.is_some_and(|sigil| SIGILS.contains(&sigil) && chars.as_str().contains(sigil))
+		{
+			return None;
+		}
+
+		let mut candidates: BTreeSet<&str> = self.fallback.iter().map(String::as_str).collect();
+		let mut node = self.nodes.first();
+		for byte in haystack.bytes() {
+			let Some(current) = node else {
+				break;
+			};
+
+			candidates.extend(current.ids.iter().map(String::as_str));
+			node = current.next.get(&byte).map(|&next| &self.nodes[next]);
+		}
+
+		if let Some(last) = node {
+			candidates.extend(last.ids.iter().map(String::as_str));
+		}
+
+		Some(candidates)
+	}
+
+	fn insert(&mut self, prefix: &str, id: &str) {
+		let mut current = 0;
+		for byte in prefix.bytes() {
+			current = match self.nodes[current].next.get(&byte) {
+				| Some(&next) => next,
+				| None => {
+					let next = self.nodes.len();
+					self.nodes.push(Node::default());
+					self.nodes[current].next.insert(byte, next);
+					next
+				},
+			};
+		}
+
+		self.nodes[current].ids.insert(id.to_owned());
+	}
+}
+
+/// The literal text every match of `regex` starts with, when it is known where
+/// in the haystack the match begins.
+pub(super) fn literal_prefix(regex: &str) -> Option<String> {
+	if regex.contains('|') {
+		return None;
+	}
+
+	let (anchored, pattern) = match regex.strip_prefix('^') {
+		| Some(pattern) => (true, pattern),
+		| None => (false, regex),
+	};
+
+	let mut prefix = String::new();
+	let mut chars = pattern.chars().peekable();
+	while let Some(c) = chars.next() {
+		let literal = match c {
+			| '\\' => match chars.next() {
+				| Some(escaped) if escaped.is_ascii_punctuation() => escaped,
+				| _ => break,
+			},
+			| '.' | '+' | '*' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '^' | '$' => break,
+			| c => c,
+		};
+
+		// A quantifier following makes the character optional or repeated.
+		if chars
+			.peek()
+			.is_some_and(|next| matches!(next, '*' | '?' | '{' | '+'))
+		{
+			if matches!(chars.peek(), Some('+')) {
+				prefix.push(literal);
+			}
+
+			break;
+		}
+
+		prefix.push(literal);
+	}
+
+	let sigil_led = prefix.chars().next().is_some_and(|c| SIGILS.contains(&c));
+	(!prefix.is_empty() && (anchored || sigil_led)).then_some(prefix)
+}
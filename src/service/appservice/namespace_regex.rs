@@ -37,6 +37,14 @@ pub fn is_exclusive_match(&self, heystack: &str) -> bool {
 		}
 		false
 	}
+
+	/// The patterns compiled into the exclusive and the non-exclusive sets.
+	#[must_use]
+	pub fn patterns(&self) -> (&[String], &[String]) {
+		let patterns = |set: &Option<RegexSet>| set.as_ref().map_or(&[][..], RegexSet::patterns);
+
+		(patterns(&self.exclusive), patterns(&self.non_exclusive))
+	}
 }
 
 impl TryFrom<Vec<Namespace>> for NamespaceRegex {
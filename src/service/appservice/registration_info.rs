@@ -1,5 +1,7 @@
-use conduwuit::Result;
-use ruma::{UserId, api::appservice::Registration};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use conduwuit::{PduEvent, Result};
+use ruma::{UserId, api::appservice::Registration, events::TimelineEventType};
 
 use super::NamespaceRegex;
 
@@ -10,8 +12,14 @@ pub struct RegistrationInfo {
 	pub users: NamespaceRegex,
 	pub aliases: NamespaceRegex,
 	pub rooms: NamespaceRegex,
+
+	/// Distinguishes this registration from earlier ones with the same ID, so
+	/// cached answers about a replaced registration are not reused.
+	pub serial: u64,
 }
 
+static SERIAL: AtomicU64 = AtomicU64::new(0);
+
 impl RegistrationInfo {
 	#[must_use]
 	pub fn is_user_match(&self, user_id: &UserId) -> bool {
@@ -25,6 +33,24 @@ pub fn is_exclusive_user_match(&self, user_id: &UserId) -> bool {
 		self.users.is_exclusive_match(user_id.as_str())
 			|| self.registration.sender_localpart == user_id.localpart()
 	}
+
+	/// Whether the event is in one of the appservice's rooms, is sent by one of
+	/// its users, or is the membership of one of them, as far as its namespaces
+	/// tell. Which rooms its users are in and its aliases are not considered.
+	#[must_use]
+	pub fn is_event_match(&self, pdu: &PduEvent) -> bool {
+		let member = pdu
+			.state_key
+			.as_deref()
+			.filter(|_| pdu.kind == TimelineEventType::RoomMember);
+
+		self.rooms.is_match(pdu.room_id.as_str())
+			|| self.users.is_match(pdu.sender.as_str())
+			|| member.is_some_and(|state_key| {
+				self.users.is_match(state_key)
+					|| UserId::parse(state_key).is_ok_and(|user_id| self.is_user_match(&user_id))
+			})
+	}
 }
 
 impl TryFrom<Registration> for RegistrationInfo {
@@ -35,6 +61,7 @@ fn try_from(value: Registration) -> Result<Self, regex::Error> {
 			users: value.namespaces.users.clone().try_into()?,
 			aliases: value.namespaces.aliases.clone().try_into()?,
 			rooms: value.namespaces.rooms.clone().try_into()?,
+			serial: SERIAL.fetch_add(1, Ordering::Relaxed),
 			registration: value,
 		})
 	}
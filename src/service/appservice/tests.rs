@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use conduwuit::PduEvent;
+use ruma::api::appservice::Namespace;
+use serde_json::json;
+
+use super::{
+	NamespaceIndex, RegistrationInfo, any_exclusive, compile, namespace_index::literal_prefix,
+};
+
+fn namespace(regex: &str, exclusive: bool) -> Namespace {
+	Namespace::new(exclusive, regex.to_owned())
+}
+
+fn registration(id: &str, users: &[Namespace]) -> RegistrationInfo {
+	let yaml = format!(
+		"id: {id}\nurl: null\nas_token: {id}_as\nhs_token: {id}_hs\nsender_localpart: \
+		 {id}\nnamespaces: {{users: [], aliases: [], rooms: []}}\n"
+	);
+
+	let mut registration: ruma::api::appservice::Registration =
+		serde_yaml::from_str(&yaml).expect("valid registration");
+
+	registration.namespaces.users = users.to_vec();
+	registration.try_into().expect("valid namespaces")
+}
+
+#[test]
+fn namespace_literal_prefix() {
+	let cases = [
+		("@_discord_.*:example\\.org", Some("@_discord_")),
+		("^@_irc_.*", Some("@_irc_")),
+		("^_irc_.*", Some("_irc_")),
+		("@telegram_\\d+:example\\.org", Some("@telegram_")),
+		("@bot\\.x:example\\.org", Some("@bot.x:example.org")),
+		("@ab?c", Some("@a")),
+		("@ab+c", Some("@ab")),
+		("_discord_.*", None),
+		("(?i)@_discord_.*", None),
+		("@_a_.*|@_b_.*", None),
+		(".*", None),
+	];
+
+	for (regex, prefix) in cases {
+		assert_eq!(literal_prefix(regex).as_deref(), prefix, "{regex}");
+	}
+}
+
+#[test]
+fn namespace_index_candidates() {
+	let infos: BTreeMap<_, _> = [
+		registration("discord", &[namespace("@_discord_.*", true)]),
+		registration("irc", &[namespace("^@irc_.*", true), namespace("@irc\\.", false)]),
+		registration("anything", &[namespace(".*_bot:example\\.org", true)]),
+		registration("open", &[namespace("@_open_.*", false)]),
+	]
+	.into_iter()
+	.map(|info| (info.registration.id.clone(), info))
+	.collect();
+
+	let namespaces: Vec<_> = infos
+		.iter()
+		.map(|(id, info)| (id.as_str(), info.registration.namespaces.users.as_slice()))
+		.collect();
+
+	let index = NamespaceIndex::new(namespaces);
+
+	let candidates = index
+		.candidates("@_discord_123:example.org")
+		.expect("indexed");
+	assert!(candidates.contains("discord"));
+	assert!(candidates.contains("anything"), "unindexable namespaces always evaluated");
+	assert!(!candidates.contains("irc"));
+	assert!(!candidates.contains("open"), "non-exclusive namespaces not indexed");
+
+	assert!(index.candidates("@a@_discord_1:example.org").is_none(), "repeated sigil");
+
+	let users = [
+		"@_discord_123:example.org",
+		"@irc_nick:example.org",
+		"@irc.nick:example.org",
+		"@my_bot:example.org",
+		"@_open_1:example.org",
+		"@alice:example.org",
+		"@a@_discord_1:example.org",
+	];
+
+	for user in users {
+		let is_match = |info: &RegistrationInfo| info.users.is_exclusive_match(user);
+		let naive = infos.values().any(is_match);
+
+		assert_eq!(any_exclusive(&infos, &index, user, is_match), naive, "{user}");
+	}
+}
+
+fn pdu(sender: &str, member: Option<&str>) -> PduEvent {
+	serde_json::from_value(json!({
+		"event_id": "$event",
+		"room_id": "!room:example.org",
+		"sender": sender,
+		"origin_server_ts": 1,
+		"type": if member.is_some() { "m.room.member" } else { "m.room.message" },
+		"state_key": member,
+		"content": {},
+		"prev_events": [],
+		"depth": 1,
+		"auth_events": [],
+		"hashes": { "sha256": "" },
+	}))
+	.expect("valid pdu")
+}
+
+#[test]
+fn registration_matches_events_once_compiled() {
+	let message = pdu("@_bridge_alice:example.org", None);
+	let invite = pdu("@bob:example.org", Some("@_bridge_carol:example.org"));
+	let bot_invite = pdu("@bob:example.org", Some("@bridge:example.org"));
+	let unrelated = pdu("@bob:example.org", None);
+
+	let (infos, invalid) =
+		compile([registration("other", &[namespace("@_other_.*", true)]).registration]);
+	assert!(invalid.is_empty());
+	assert!(!infos.values().any(|info| info.is_event_match(&message)));
+
+	let bridge = registration("bridge", &[namespace("@_bridge_.*", false)]);
+	let (infos, invalid) = compile(
+		infos
+			.into_values()
+			.map(|info| info.registration)
+			.chain([bridge.registration]),
+	);
+	assert!(invalid.is_empty());
+
+	let bridge = infos.get("bridge").expect("registered");
+	assert!(bridge.is_event_match(&message), "sent by a user of the new namespace");
+	assert!(bridge.is_event_match(&invite), "membership of a user of the new namespace");
+	assert!(bridge.is_event_match(&bot_invite), "membership of the sender_localpart user");
+	assert!(!bridge.is_event_match(&unrelated));
+}
+
+#[test]
+fn registration_invalid_namespace_reported() {
+	let valid = registration("valid", &[]).registration;
+	let mut broken = registration("broken", &[]).registration;
+	broken.namespaces.users = vec![namespace("@_broken_(.*", true)];
+
+	let (infos, invalid) = compile([valid, broken]);
+	assert!(infos.contains_key("valid"));
+	assert!(!infos.contains_key("broken"));
+	assert_eq!(invalid.len(), 1);
+	assert_eq!(invalid[0].0, "broken");
+}
@@ -0,0 +1,332 @@
+use std::{
+	borrow::Borrow,
+	fmt,
+	hash::Hash,
+	ops::Deref,
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+use async_trait::async_trait;
+use lru_cache::LruCache;
+
+use crate::service::Map;
+
+/// A size-bounded cache held by a service whose capacity can be changed while
+/// the server runs. Capacities changed this way are not persisted.
+#[async_trait]
+pub trait Resizable: Send + Sync {
+	async fn usage(&self) -> Usage;
+
+	/// Changes the number of entries the cache holds, evicting the least
+	/// recently used entries immediately when shrinking.
+	async fn set_capacity(&self, capacity: usize);
+
+	/// Zeroes the hit and miss counters, for caches which count them.
+	fn reset_counters(&self) {}
+}
+
+/// Snapshot of the size and effectiveness of a cache.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+	pub len: usize,
+	pub capacity: usize,
+
+	/// Lookups which found an entry and those which did not, for caches which
+	/// count them.
+	pub hits: Option<u64>,
+	pub misses: Option<u64>,
+
+	/// Approximate bytes held and the budget for them, for caches bounded by
+	/// size; the budget is absent while only the entry count is bounded.
+	pub bytes: Option<usize>,
+	pub byte_budget: Option<usize>,
+}
+
+impl Usage {
+	/// Fraction of lookups which found an entry, when counted and any were
+	/// made.
+	#[must_use]
+	pub fn hit_rate(&self) -> Option<f64> { hit_rate(self.hits?, self.misses?) }
+}
+
+/// Counts the lookups made on a cache. The counters are relaxed atomics
+/// updated next to the existing lookup, so counting takes no further lock.
+#[derive(Debug, Default)]
+pub struct Counters {
+	hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+impl Counters {
+	/// Counts a lookup as a hit or a miss, passing its result through.
+	#[inline]
+	pub fn tally<T>(&self, found: Option<T>) -> Option<T> {
+		let counter = if found.is_some() { &self.hits } else { &self.misses };
+		counter.fetch_add(1, Ordering::Relaxed);
+		found
+	}
+
+	#[inline]
+	pub fn hits(&self) -> u64 { self.hits.load(Ordering::Relaxed) }
+
+	#[inline]
+	pub fn misses(&self) -> u64 { self.misses.load(Ordering::Relaxed) }
+
+	pub fn reset(&self) {
+		self.hits.store(0, Ordering::Relaxed);
+		self.misses.store(0, Ordering::Relaxed);
+	}
+}
+
+impl fmt::Display for Counters {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let (hits, misses) = (self.hits(), self.misses());
+		write!(f, "hits: {hits}, misses: {misses}, hit rate: ")?;
+		match hit_rate(hits, misses) {
+			| Some(rate) => write!(f, "{:.1}%", rate * 100.0),
+			| None => write!(f, "n/a"),
+		}
+	}
+}
+
+/// A cache together with the counters of lookups made on it. Dereferences to
+/// the cache so that existing accesses are unchanged; lookups are passed
+/// through [`Counters::tally`].
+#[derive(Debug, Default)]
+pub struct Counted<C> {
+	cache: C,
+	counters: Counters,
+}
+
+impl<C> Counted<C> {
+	#[must_use]
+	pub fn new(cache: C) -> Self { Self { cache, counters: Counters::default() } }
+
+	#[inline]
+	pub fn counters(&self) -> &Counters { &self.counters }
+}
+
+impl<C> From<C> for Counted<C> {
+	fn from(cache: C) -> Self { Self::new(cache) }
+}
+
+impl<C> Deref for Counted<C> {
+	type Target = C;
+
+	#[inline]
+	fn deref(&self) -> &C { &self.cache }
+}
+
+#[async_trait]
+impl<C: Resizable> Resizable for Counted<C> {
+	async fn usage(&self) -> Usage {
+		Usage {
+			hits: Some(self.counters.hits()),
+			misses: Some(self.counters.misses()),
+			..self.cache.usage().await
+		}
+	}
+
+	async fn set_capacity(&self, capacity: usize) { self.cache.set_capacity(capacity).await; }
+
+	fn reset_counters(&self) { self.counters.reset(); }
+}
+
+#[async_trait]
+impl<K, V> Resizable for std::sync::Mutex<LruCache<K, V>>
+where
+	K: Eq + Hash + Send,
+	V: Send,
+{
+	async fn usage(&self) -> Usage { usage(&self.lock().expect("locked")) }
+
+	async fn set_capacity(&self, capacity: usize) {
+		self.lock().expect("locked").set_capacity(capacity);
+	}
+}
+
+#[async_trait]
+impl<K, V> Resizable for tokio::sync::Mutex<LruCache<K, V>>
+where
+	K: Eq + Hash + Send,
+	V: Send,
+{
+	async fn usage(&self) -> Usage { usage(&*self.lock().await) }
+
+	async fn set_capacity(&self, capacity: usize) { self.lock().await.set_capacity(capacity); }
+}
+
+#[async_trait]
+impl<K, V> Resizable for std::sync::Mutex<Bounded<K, V>>
+where
+	K: Eq + Hash + Send,
+	V: Send,
+{
+	async fn usage(&self) -> Usage {
+		let cache = self.lock().expect("locked");
+
+		Usage {
+			len: cache.len(),
+			capacity: cache.capacity(),
+			bytes: Some(cache.bytes()),
+			byte_budget: cache.budget(),
+			..Usage::default()
+		}
+	}
+
+	async fn set_capacity(&self, capacity: usize) {
+		self.lock().expect("locked").set_capacity(capacity);
+	}
+}
+
+/// An LRU cache bounded by the approximate size of its entries in bytes as
+/// well as by their number, for entries whose sizes vary widely. Without a
+/// budget only the number of entries is bounded.
+pub struct Bounded<K, V> {
+	cache: LruCache<K, V>,
+	bytes: usize,
+	budget: Option<usize>,
+	weigh: fn(&V) -> usize,
+}
+
+impl<K: Eq + Hash, V> Bounded<K, V> {
+	/// Creates a cache of at most `capacity` entries and, with a `budget`, at
+	/// most that many bytes as estimated from each value by `weigh`.
+	#[must_use]
+	pub fn new(capacity: usize, budget: Option<usize>, weigh: fn(&V) -> usize) -> Self {
+		Self {
+			cache: LruCache::new(capacity),
+			bytes: 0,
+			budget,
+			weigh,
+		}
+	}
+
+	pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+	where
+		K: Borrow<Q>,
+		Q: Eq + Hash + ?Sized,
+	{
+		self.cache.get_mut(key)
+	}
+
+	#[must_use]
+	pub fn contains_key<Q>(&mut self, key: &Q) -> bool
+	where
+		K: Borrow<Q>,
+		Q: Eq + Hash + ?Sized,
+	{
+		self.cache.contains_key(key)
+	}
+
+	/// Inserts an entry, evicting the least recently used entries until both
+	/// bounds are met. An entry larger than the whole budget is not kept.
+	pub fn insert(&mut self, key: K, value: V) {
+		let size = (self.weigh)(&value);
+		if self.budget.is_some_and(|budget| size > budget) || self.cache.capacity() == 0 {
+			self.remove(&key);
+			return;
+		}
+
+		self.remove(&key);
+		while self.cache.len() >= self.cache.capacity() || self.over_budget(size) {
+			if self.remove_lru().is_none() {
+				break;
+			}
+		}
+
+		self.bytes = self.bytes.saturating_add(size);
+		self.cache.insert(key, value);
+	}
+
+	pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+	where
+		K: Borrow<Q>,
+		Q: Eq + Hash + ?Sized,
+	{
+		let value = self.cache.remove(key)?;
+		self.bytes = self.bytes.saturating_sub((self.weigh)(&value));
+
+		Some(value)
+	}
+
+	pub fn clear(&mut self) {
+		self.cache.clear();
+		self.bytes = 0;
+	}
+
+	/// Changes the number of entries held, evicting the least recently used
+	/// entries when shrinking.
+	pub fn set_capacity(&mut self, capacity: usize) {
+		while self.cache.len() > capacity {
+			self.remove_lru();
+		}
+
+		self.cache.set_capacity(capacity);
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> { self.cache.iter() }
+
+	#[must_use]
+	pub fn len(&self) -> usize { self.cache.len() }
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.cache.is_empty() }
+
+	#[must_use]
+	pub fn capacity(&self) -> usize { self.cache.capacity() }
+
+	/// Approximate bytes held by the entries.
+	#[must_use]
+	pub fn bytes(&self) -> usize { self.bytes }
+
+	#[must_use]
+	pub fn budget(&self) -> Option<usize> { self.budget }
+
+	fn over_budget(&self, additional: usize) -> bool {
+		self.budget
+			.is_some_and(|budget| self.bytes.saturating_add(additional) > budget)
+	}
+
+	fn remove_lru(&mut self) -> Option<(K, V)> {
+		let (key, value) = self.cache.remove_lru()?;
+		self.bytes = self.bytes.saturating_sub((self.weigh)(&value));
+
+		Some((key, value))
+	}
+}
+
+#[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+fn hit_rate(hits: u64, misses: u64) -> Option<f64> {
+	let lookups = hits.saturating_add(misses);
+
+	(lookups > 0).then(|| hits as f64 / lookups as f64)
+}
+
+/// Usage of the caches of every service in the map, by name.
+pub(crate) async fn collect(services: &Map) -> Vec<(&'static str, Usage)> {
+	let services: Vec<_> = services
+		.read()
+		.expect("locked for reading")
+		.values()
+		.filter_map(|val| val.0.upgrade())
+		.collect();
+
+	let mut usage = Vec::new();
+	for service in &services {
+		for (name, cache) in service.caches() {
+			usage.push((name, cache.usage().await));
+		}
+	}
+
+	usage.sort_unstable_by_key(|(name, _)| *name);
+	usage
+}
+
+pub(crate) fn usage<K: Eq + Hash, V>(cache: &LruCache<K, V>) -> Usage {
+	Usage {
+		len: cache.len(),
+		capacity: cache.capacity(),
+		..Usage::default()
+	}
+}
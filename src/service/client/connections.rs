@@ -0,0 +1,103 @@
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::{Arc, Mutex},
+};
+
+use reqwest::{
+	Response, Version,
+	dns::{Name, Resolve, Resolving},
+};
+
+/// Counts of the connections opened to each federation host and the requests
+/// sent to it, showing how well connections are reused. New connections are
+/// counted as their host is resolved, which pooled ones skip, so hosts named
+/// by IP address are left out.
+#[derive(Default)]
+pub struct Connections {
+	hosts: Mutex<HashMap<String, Host>>,
+}
+
+/// The counts of a host since the server started.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Host {
+	/// Connections opened, or attempted.
+	pub opened: u64,
+
+	/// Requests answered.
+	pub requests: u64,
+
+	/// Requests answered over HTTP/2.
+	pub http2: u64,
+}
+
+/// A resolver counting the hosts it resolves as connections opened.
+pub(super) struct Counting {
+	pub(super) resolver: Arc<dyn Resolve>,
+	pub(super) connections: Arc<Connections>,
+}
+
+impl Connections {
+	/// Counts the request answered by the response.
+	pub fn answered(&self, response: &Response) {
+		let Some(host) = response.url().host_str().filter(|host| is_named(host)) else {
+			return;
+		};
+
+		let mut hosts = self.hosts.lock().expect("locked");
+		let counts = hosts.entry(host.to_owned()).or_default();
+		counts.requests = counts.requests.saturating_add(1);
+		if response.version() == Version::HTTP_2 {
+			counts.http2 = counts.http2.saturating_add(1);
+		}
+	}
+
+	fn opened(&self, host: &str) {
+		let mut hosts = self.hosts.lock().expect("locked");
+		let counts = hosts.entry(host.to_owned()).or_default();
+		counts.opened = counts.opened.saturating_add(1);
+	}
+
+	/// The counts of every host, by the most requests first.
+	#[must_use]
+	pub fn hosts(&self) -> Vec<(String, Host)> {
+		let mut hosts: Vec<_> = self
+			.hosts
+			.lock()
+			.expect("locked")
+			.iter()
+			.map(|(host, counts)| (host.clone(), *counts))
+			.collect();
+
+		hosts.sort_by(|(a, a_counts), (b, b_counts)| {
+			b_counts
+				.requests
+				.cmp(&a_counts.requests)
+				.then_with(|| a.cmp(b))
+		});
+
+		hosts
+	}
+}
+
+impl Host {
+	/// Requests sent on a connection opened before.
+	#[must_use]
+	pub fn reused(&self) -> u64 { self.requests.saturating_sub(self.opened) }
+}
+
+impl Resolve for Counting {
+	fn resolve(&self, name: Name) -> Resolving {
+		self.connections.opened(name.as_str());
+		self.resolver.resolve(name)
+	}
+}
+
+/// Whether the URL host is a name rather than an IP address, with or without
+/// the brackets of IPv6.
+fn is_named(host: &str) -> bool {
+	host.trim_start_matches('[')
+		.trim_end_matches(']')
+		.parse::<IpAddr>()
+		.is_err()
+}
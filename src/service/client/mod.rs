@@ -1,10 +1,24 @@
-use std::{sync::Arc, time::Duration};
+pub mod connections;
+#[cfg(test)]
+mod tests;
+pub mod tls;
 
-use conduwuit::{Config, Result, err, implement, trace};
+use std::{
+	collections::HashMap,
+	sync::{Arc, RwLock},
+	time::Duration,
+};
+
+use conduwuit::{Config, Result, Server, err, implement, trace};
 use either::Either;
 use ipaddress::IPAddress;
 use reqwest::redirect;
+use ruma::{OwnedServerName, ServerName};
 
+use self::{
+	connections::{Connections, Counting},
+	tls::Trust,
+};
 use crate::{resolver, service};
 
 pub struct Service {
@@ -19,6 +33,32 @@ pub struct Service {
 	pub pusher: reqwest::Client,
 
 	pub cidr_range_denylist: Vec<IPAddress>,
+
+	/// How the certificates of federation destinations are trusted.
+	pub federation_tls: Trust,
+
+	/// Connections to federation hosts and their reuse.
+	pub connections: Arc<Connections>,
+
+	/// Federation clients of destinations trusted otherwise than the others,
+	/// built the first time each is needed.
+	federation_destinations: RwLock<HashMap<(Federation, OwnedServerName), reqwest::Client>>,
+
+	server: Arc<Server>,
+	resolver: Arc<resolver::Service>,
+}
+
+/// The clients for requests to federation destinations.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Federation {
+	/// Requests in general; `federation`.
+	Request,
+
+	/// Requests which can take very long; `synapse`.
+	Synapse,
+
+	/// Transactions of the sender; `sender`.
+	Sender,
 }
 
 impl crate::Service for Service {
@@ -26,6 +66,10 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		let config = &args.server.config;
 		let resolver = args.require::<resolver::Service>("resolver");
 
+		let federation_tls = Trust::new(config)?;
+		let federation_base_tls = federation_tls.base()?;
+		let connections = Arc::new(Connections::default());
+
 		let url_preview_bind_addr = config
 			.url_preview_bound_interface
 			.clone()
@@ -55,38 +99,41 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				.redirect(redirect::Policy::limited(3))
 				.build()?,
 
-			well_known: base(config)?
-				.dns_resolver(resolver.resolver.clone())
-				.connect_timeout(Duration::from_secs(config.well_known_conn_timeout))
-				.read_timeout(Duration::from_secs(config.well_known_timeout))
-				.timeout(Duration::from_secs(config.well_known_timeout))
-				.pool_max_idle_per_host(0)
-				.redirect(redirect::Policy::limited(4))
-				.build()?,
+			well_known: with_tls(
+				base(config)?
+					.dns_resolver(resolver.resolver.clone())
+					.connect_timeout(Duration::from_secs(config.well_known_conn_timeout))
+					.read_timeout(Duration::from_secs(config.well_known_timeout))
+					.timeout(Duration::from_secs(config.well_known_timeout))
+					.pool_max_idle_per_host(0)
+					.redirect(redirect::Policy::limited(4)),
+				federation_base_tls.clone(),
+			)
+			.build()?,
 
-			federation: base(config)?
-				.dns_resolver(resolver.resolver.hooked.clone())
-				.read_timeout(Duration::from_secs(config.federation_timeout))
-				.pool_max_idle_per_host(config.federation_idle_per_host.into())
-				.pool_idle_timeout(Duration::from_secs(config.federation_idle_timeout))
-				.redirect(redirect::Policy::limited(3))
-				.build()?,
+			federation: federation(
+				config,
+				&resolver,
+				&connections,
+				Federation::Request,
+				federation_base_tls.clone(),
+			)?,
 
-			synapse: base(config)?
-				.dns_resolver(resolver.resolver.hooked.clone())
-				.read_timeout(Duration::from_secs(305))
-				.pool_max_idle_per_host(0)
-				.redirect(redirect::Policy::limited(3))
-				.build()?,
+			synapse: federation(
+				config,
+				&resolver,
+				&connections,
+				Federation::Synapse,
+				federation_base_tls.clone(),
+			)?,
 
-			sender: base(config)?
-				.dns_resolver(resolver.resolver.hooked.clone())
-				.read_timeout(Duration::from_secs(config.sender_timeout))
-				.timeout(Duration::from_secs(config.sender_timeout))
-				.pool_max_idle_per_host(1)
-				.pool_idle_timeout(Duration::from_secs(config.sender_idle_timeout))
-				.redirect(redirect::Policy::limited(2))
-				.build()?,
+			sender: federation(
+				config,
+				&resolver,
+				&connections,
+				Federation::Sender,
+				federation_base_tls,
+			)?,
 
 			appservice: base(config)?
 				.dns_resolver(resolver.resolver.clone())
@@ -112,12 +159,129 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				.inspect(|cidr| trace!("Denied CIDR range: {cidr:?}"))
 				.collect::<Result<_, String>>()
 				.map_err(|e| err!(Config("ip_range_denylist", e)))?,
+
+			federation_tls,
+			connections,
+			federation_destinations: RwLock::default(),
+			server: args.server.clone(),
+			resolver,
 		}))
 	}
 
 	fn name(&self) -> &str { service::make_name(std::module_path!()) }
 }
 
+/// The client for federation requests of the kind to the destination. Those
+/// of destinations trusted otherwise than the others are built the first time
+/// they are needed and kept.
+#[implement(Service)]
+pub fn federation_for(&self, kind: Federation, dest: &ServerName) -> Result<reqwest::Client> {
+	self.server.config.check_remote_server(dest)?;
+
+	if !self.federation_tls.overridden(dest) {
+		return Ok(match kind {
+			| Federation::Request => self.federation.clone(),
+			| Federation::Synapse => self.synapse.clone(),
+			| Federation::Sender => self.sender.clone(),
+		});
+	}
+
+	let key = (kind, dest.to_owned());
+	if let Some(client) = self
+		.federation_destinations
+		.read()
+		.expect("locked for reading")
+		.get(&key)
+	{
+		return Ok(client.clone());
+	}
+
+	let tls = self.federation_tls.destination(dest)?;
+	let client = federation(&self.server.config, &self.resolver, &self.connections, kind, tls)?;
+
+	Ok(self
+		.federation_destinations
+		.write()
+		.expect("locked for writing")
+		.entry(key)
+		.or_insert(client)
+		.clone())
+}
+
+fn federation(
+	config: &Config,
+	resolver: &resolver::Service,
+	connections: &Arc<Connections>,
+	kind: Federation,
+	tls: Option<rustls::ClientConfig>,
+) -> Result<reqwest::Client> {
+	let counting = Counting {
+		resolver: resolver.resolver.hooked.clone(),
+		connections: connections.clone(),
+	};
+
+	let builder = with_http2(config, base(config)?.dns_resolver(Arc::new(counting)));
+	let builder = match kind {
+		| Federation::Request => builder
+			.read_timeout(Duration::from_secs(config.federation_timeout))
+			.pool_max_idle_per_host(config.federation_idle_per_host.into())
+			.pool_idle_timeout(Duration::from_secs(config.federation_idle_timeout))
+			.redirect(redirect::Policy::limited(3)),
+
+		| Federation::Synapse => builder
+			.read_timeout(Duration::from_secs(305))
+			.pool_max_idle_per_host(0)
+			.redirect(redirect::Policy::limited(3)),
+
+		| Federation::Sender => builder
+			.read_timeout(Duration::from_secs(config.sender_timeout))
+			.timeout(Duration::from_secs(config.sender_timeout))
+			.pool_max_idle_per_host(1)
+			.pool_idle_timeout(Duration::from_secs(config.sender_idle_timeout))
+			.redirect(redirect::Policy::limited(2)),
+	};
+
+	// Preconfigured TLS offers HTTP/2 to destinations; with it disabled, those
+	// accepting it could not be talked to at all.
+	let tls = tls.map(|mut tls| {
+		if !config.federation_http2 {
+			tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+		}
+
+		tls
+	});
+
+	Ok(with_tls(builder, tls).build()?)
+}
+
+/// HTTP/2 is negotiated with destinations offering it over TLS, falling back
+/// to HTTP/1.1 with the others.
+fn with_http2(config: &Config, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+	if !config.federation_http2 {
+		return builder.http1_only();
+	}
+
+	let interval = config.federation_http2_keepalive_interval;
+	if interval == 0 {
+		return builder;
+	}
+
+	builder
+		.http2_keep_alive_interval(Duration::from_secs(interval))
+		.http2_keep_alive_timeout(Duration::from_secs(config.federation_http2_keepalive_timeout))
+		.http2_keep_alive_while_idle(true)
+}
+
+fn with_tls(
+	builder: reqwest::ClientBuilder,
+	tls: Option<rustls::ClientConfig>,
+) -> reqwest::ClientBuilder {
+	match tls {
+		| Some(tls) => builder.use_preconfigured_tls(tls),
+		| None => builder,
+	}
+}
+
 fn base(config: &Config) -> Result<reqwest::ClientBuilder> {
 	let mut builder = reqwest::Client::builder()
 		.hickory_dns(true)
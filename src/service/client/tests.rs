@@ -0,0 +1,257 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use conduwuit::{Config, config::Figment};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use ruma::{ServerName, owned_server_name};
+use rustls::{
+	OtherError, RootCertStore,
+	client::danger::ServerCertVerifier,
+	pki_types::{CertificateDer, ServerName as TlsServerName, UnixTime, pem::PemObject},
+};
+use serde_json::json;
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream},
+	task::JoinSet,
+};
+
+use super::{
+	connections::{Connections, Counting, Host},
+	tls::{PinMismatch, Pinned, Trust, TrustPath, parse_pin, pin, verifier},
+};
+
+/// Self-signed certificate of "internal.example".
+const CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBjTCCATOgAwIBAgIUCgd0V5TWc8XVOwYuYONzspZsIzYwCgYIKoZIzj0EAwIw
+GzEZMBcGA1UEAwwQaW50ZXJuYWwuZXhhbXBsZTAgFw0yNjEwMTQxNjE3MjlaGA8y
+MTI2MDkyMDE2MTcyOVowGzEZMBcGA1UEAwwQaW50ZXJuYWwuZXhhbXBsZTBZMBMG
+ByqGSM49AgEGCCqGSM49AwEHA0IABORWE9SQ1qPeQYSf4f+qsBkbfHCVYOkj6k9D
+MyubVh0fCzGeF68CVyPVlrnreum5FaT9T20XEh62ivzsPuF3if6jUzBRMB0GA1Ud
+DgQWBBR7oiziWmTosG7pBHxLKPfTrCrGDDAfBgNVHSMEGDAWgBR7oiziWmTosG7p
+BHxLKPfTrCrGDDAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0gAMEUCIQCt
+Jd1hLMWon+C78hJsH6oF1J8OTFh4jaXKdGdNoPdyjgIgZ9IX6s0EHDfVrYtlS7CX
+R6i4KbRj2yjnactLUbI1RQs=
+-----END CERTIFICATE-----
+";
+
+/// Its pin, from `openssl x509 -pubkey | openssl pkey -pubin -outform der |
+/// openssl dgst -sha256 -binary | base64`.
+const PIN: &str = "Qu/R/zTFb+Q4jdZlj561W+jlgJe3HjkvB+J4aFq4BdU=";
+
+const OTHER_PIN: &str = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+
+fn cert() -> CertificateDer<'static> {
+	CertificateDer::from_pem_slice(CERT.as_bytes()).expect("valid certificate")
+}
+
+fn config(options: serde_json::Value) -> Config {
+	let mut config = Figment::new()
+		.join(("server_name", "example.com"))
+		.join(("database_path", "/tmp/conduwuit_client"));
+
+	for (key, value) in options.as_object().expect("options") {
+		config = config.join((key.as_str(), value.clone()));
+	}
+
+	Config::new(&config).expect("valid config")
+}
+
+fn pinned(pins: &[&str], strict: bool) -> Pinned {
+	let mut roots = RootCertStore::empty();
+	roots.add(cert()).expect("added");
+
+	Pinned {
+		server: owned_server_name!("internal.example"),
+		pins: pins
+			.iter()
+			.map(|pin| parse_pin(pin).expect("valid pin"))
+			.collect(),
+		strict,
+		roots: verifier(roots).expect("verifier"),
+	}
+}
+
+fn verify(pinned: &Pinned) -> Result<(), rustls::Error> {
+	let name = TlsServerName::try_from("internal.example").expect("valid name");
+	pinned
+		.verify_server_cert(&cert(), &[], &name, &[], UnixTime::now())
+		.map(|_| ())
+}
+
+fn mismatch(error: &rustls::Error) -> Option<&PinMismatch> {
+	let rustls::Error::Other(OtherError(other)) = error else {
+		return None;
+	};
+
+	other.downcast_ref::<PinMismatch>()
+}
+
+#[test]
+fn pin_of_certificate() {
+	assert_eq!(pin(&cert()), parse_pin(PIN));
+	assert_eq!(pin(&CertificateDer::from(vec![0x30, 0x03, 0x02, 0x01])), None, "truncated");
+	assert_eq!(pin(&CertificateDer::from(Vec::new())), None);
+}
+
+#[test]
+fn pin_parsing() {
+	assert!(parse_pin(PIN).is_some());
+	assert!(parse_pin("Qu/R/zTFb+Q4jdZl").is_none(), "too short for SHA-256");
+	assert!(parse_pin("not base64!").is_none());
+}
+
+#[test]
+fn pinned_key_trusted() {
+	verify(&pinned(&[OTHER_PIN, PIN], true)).expect("pinned key accepted");
+}
+
+#[test]
+fn pin_mismatch_strict() {
+	let error = verify(&pinned(&[OTHER_PIN], true)).expect_err("refused");
+	let mismatch = mismatch(&error).expect("a pin mismatch");
+
+	assert_eq!(mismatch.server.as_str(), "internal.example");
+	assert_eq!(mismatch.found.as_deref(), Some(PIN));
+}
+
+#[test]
+fn pin_mismatch_falls_back() {
+	// not strict, so the certificate is checked by the roots, which refuse it
+	// for being a CA itself rather than for its key
+	let error = verify(&pinned(&[OTHER_PIN], false)).expect_err("refused by the roots");
+	assert!(mismatch(&error).is_none(), "{error}");
+}
+
+#[test]
+fn trust_paths() {
+	let dir = std::env::temp_dir().join("conduwuit_client_trust_paths");
+	std::fs::create_dir_all(&dir).expect("created");
+
+	let ca_file = dir.join("internal-ca.pem");
+	std::fs::write(&ca_file, CERT).expect("written");
+
+	let config = config(json!({
+		"federation_trusted_ca_files": [ca_file],
+		"federation_tls": {
+			"internal.example": { "pins": [PIN] },
+			"private.example": { "ca_file": ca_file },
+		},
+		"federation_tls_strict": true,
+	}));
+
+	let trust = Trust::new(&config).expect("trust");
+	let path = |server: &str| trust.path(<&ServerName>::try_from(server).expect("valid"));
+
+	assert_eq!(path("internal.example"), TrustPath::Pinned { ca_file: None, strict: true });
+	assert_eq!(path("private.example"), TrustPath::DestinationCa(ca_file.clone()));
+	assert_eq!(path("matrix.org"), TrustPath::TrustedCas);
+
+	let server = <&ServerName>::try_from("private.example").expect("valid");
+	assert!(trust.overridden(server));
+	assert!(trust.destination(server).expect("built").is_some());
+	assert!(trust.base().expect("built").is_some());
+}
+
+#[test]
+fn trust_default() {
+	let trust = Trust::new(&config(json!({}))).expect("trust");
+	let server = <&ServerName>::try_from("matrix.org").expect("valid");
+
+	assert_eq!(trust.path(server), TrustPath::System);
+	assert!(!trust.overridden(server));
+	assert!(trust.base().expect("built").is_none(), "reqwest's own configuration");
+}
+
+#[test]
+fn trust_invalid() {
+	let bad_pin = config(json!({
+		"federation_tls": { "internal.example": { "pins": ["short"] } },
+	}));
+
+	assert!(Trust::new(&bad_pin).is_err());
+
+	let missing = config(json!({
+		"federation_trusted_ca_files": [PathBuf::from("/nonexistent/ca.pem")],
+	}));
+
+	assert!(Trust::new(&missing).is_err());
+}
+
+/// Resolves every name to the address.
+struct Fixed(SocketAddr);
+
+impl Resolve for Fixed {
+	fn resolve(&self, _name: Name) -> Resolving {
+		let addrs: Addrs = Box::new(std::iter::once(self.0));
+		Box::pin(async move { Ok(addrs) })
+	}
+}
+
+/// Answers every request with a small body, keeping each connection open.
+async fn serve(listener: TcpListener) {
+	let mut connections = JoinSet::new();
+	while let Ok((stream, _)) = listener.accept().await {
+		connections.spawn(answer(stream));
+	}
+}
+
+async fn answer(mut stream: TcpStream) {
+	let mut request = Vec::new();
+	let mut buf = [0_u8; 1024];
+	while let Ok(read) = stream.read(&mut buf).await {
+		if read == 0 {
+			return;
+		}
+
+		request.extend_from_slice(buf.get(..read).unwrap_or_default());
+		if !request.windows(4).any(|end| end == b"\r\n\r\n") {
+			continue;
+		}
+
+		request.clear();
+		let response = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+		if stream.write_all(response).await.is_err() {
+			return;
+		}
+	}
+}
+
+#[tokio::test]
+async fn connection_reused() {
+	let listener = TcpListener::bind("127.0.0.1:0").await.expect("bound");
+	let addr = listener.local_addr().expect("address");
+	let connections = Arc::new(Connections::default());
+	let client = reqwest::Client::builder()
+		.no_proxy()
+		.dns_resolver(Arc::new(Counting {
+			resolver: Arc::new(Fixed(addr)),
+			connections: connections.clone(),
+		}))
+		.build()
+		.expect("client");
+
+	let requests = async {
+		for url in [
+			format!("http://federation.test:{}/", addr.port()),
+			format!("http://federation.test:{}/", addr.port()),
+			format!("http://{addr}/"),
+		] {
+			let response = client.get(url).send().await.expect("answered");
+			connections.answered(&response);
+			assert_eq!(response.text().await.expect("body"), "ok");
+		}
+	};
+
+	tokio::select! {
+		() = serve(listener) => panic!("server stopped"),
+		() = requests => (),
+	}
+
+	let hosts = connections.hosts();
+	assert_eq!(hosts, [(String::from("federation.test"), Host {
+		opened: 1,
+		requests: 2,
+		http2: 0,
+	})]);
+	assert_eq!(hosts[0].1.reused(), 1, "second request on the first connection");
+}
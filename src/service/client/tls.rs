@@ -0,0 +1,425 @@
+use std::{
+	collections::BTreeMap,
+	error::Error as StdError,
+	fmt, io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use conduwuit::{Config, Err, Result, config::FederationTlsConfig, debug_warn, err, warn};
+use ruma::{OwnedServerName, ServerName};
+use rustls::{
+	ClientConfig, DigitallySignedStruct, OtherError, RootCertStore, SignatureScheme,
+	client::{
+		WebPkiServerVerifier,
+		danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+	},
+	crypto::{CryptoProvider, aws_lc_rs},
+	pki_types::{CertificateDer, ServerName as TlsServerName, UnixTime, pem::PemObject},
+};
+use sha2::{Digest, Sha256};
+
+/// A SHA-256 hash of the DER of a public key (SPKI).
+pub type Pin = [u8; 32];
+
+/// How the certificates of federation destinations are trusted, from
+/// `federation_trusted_ca_files`, `federation_tls` and
+/// `federation_tls_strict`.
+pub struct Trust {
+	/// The system's roots and those of `federation_trusted_ca_files`; empty
+	/// when neither option is used, as nothing is built with them then.
+	roots: RootCertStore,
+	destinations: BTreeMap<OwnedServerName, Destination>,
+	trusted_cas: bool,
+	strict: bool,
+	insecure: bool,
+}
+
+struct Destination {
+	ca_file: Option<PathBuf>,
+	pins: Vec<Pin>,
+}
+
+/// How the certificate of a federation destination is trusted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TrustPath {
+	/// By the system's roots.
+	System,
+
+	/// By the system's roots and those of `federation_trusted_ca_files`.
+	TrustedCas,
+
+	/// By the roots and the certificate authority of the destination.
+	DestinationCa(PathBuf),
+
+	/// By carrying a pinned key, or otherwise by the roots unless strict.
+	Pinned {
+		ca_file: Option<PathBuf>,
+		strict: bool,
+	},
+
+	/// Not at all, as checking certificates is disabled.
+	Unchecked,
+}
+
+/// Why the TLS handshake of a failed request failed, if it did.
+#[derive(Debug)]
+pub enum Failure<'a> {
+	/// The certificate of a pinned destination carried none of its pins.
+	Pin(&'a PinMismatch),
+
+	/// Anything else, usually a certificate no root vouches for.
+	Tls(&'a rustls::Error),
+}
+
+/// The certificate of a destination carried none of the keys pinned for it,
+/// which refuses connecting to it in strict mode.
+#[derive(Debug)]
+pub struct PinMismatch {
+	pub server: OwnedServerName,
+
+	/// Pin of the key the certificate carried.
+	pub found: Option<String>,
+}
+
+/// Verifies certificates by the pins of a destination, falling back to the
+/// roots unless strict.
+#[derive(Debug)]
+pub(super) struct Pinned {
+	pub(super) server: OwnedServerName,
+	pub(super) pins: Vec<Pin>,
+	pub(super) strict: bool,
+	pub(super) roots: Arc<WebPkiServerVerifier>,
+}
+
+impl Trust {
+	pub(super) fn new(config: &Config) -> Result<Self> {
+		let destinations = config
+			.federation_tls
+			.iter()
+			.map(|(server, tls)| Ok((server.clone(), Destination::new(server, tls)?)))
+			.collect::<Result<_>>()?;
+
+		let mut trust = Self {
+			roots: RootCertStore::empty(),
+			destinations,
+			trusted_cas: !config.federation_trusted_ca_files.is_empty(),
+			strict: config.federation_tls_strict,
+			insecure: config.allow_invalid_tls_certificates_yes_i_know_what_the_fuck_i_am_doing_with_this_and_i_know_this_is_insecure,
+		};
+
+		if trust.insecure || (!trust.trusted_cas && trust.destinations.is_empty()) {
+			return Ok(trust);
+		}
+
+		let native = rustls_native_certs::load_native_certs();
+		for error in native.errors {
+			debug_warn!("Failed to load system root certificates: {error}");
+		}
+
+		trust.roots.add_parsable_certificates(native.certs);
+		for path in &config.federation_trusted_ca_files {
+			let certs = load_certs(path)
+				.map_err(|e| err!(Config("federation_trusted_ca_files", "{e}")))?;
+
+			trust.roots.add_parsable_certificates(certs);
+		}
+
+		Ok(trust)
+	}
+
+	/// How the certificate of the destination is trusted.
+	#[must_use]
+	pub fn path(&self, server: &ServerName) -> TrustPath {
+		if self.insecure {
+			return TrustPath::Unchecked;
+		}
+
+		match self.destinations.get(server) {
+			| Some(Destination { ca_file, pins }) if !pins.is_empty() => TrustPath::Pinned {
+				ca_file: ca_file.clone(),
+				strict: self.strict,
+			},
+			| Some(Destination { ca_file: Some(ca_file), .. }) =>
+				TrustPath::DestinationCa(ca_file.clone()),
+			| _ if self.trusted_cas => TrustPath::TrustedCas,
+			| _ => TrustPath::System,
+		}
+	}
+
+	/// Whether the destination is trusted otherwise than all the others.
+	#[must_use]
+	pub fn overridden(&self, server: &ServerName) -> bool {
+		!self.insecure && self.destinations.contains_key(server)
+	}
+
+	/// The TLS configuration of federation clients for destinations which are
+	/// not overridden, or None when the default configuration will do.
+	pub(super) fn base(&self) -> Result<Option<ClientConfig>> {
+		if self.insecure || !self.trusted_cas {
+			return Ok(None);
+		}
+
+		let roots = verifier(self.roots.clone())?;
+		client_config(|builder| builder.with_webpki_verifier(roots)).map(Some)
+	}
+
+	/// The TLS configuration of federation clients for an overridden
+	/// destination.
+	pub(super) fn destination(&self, server: &ServerName) -> Result<Option<ClientConfig>> {
+		let Some(Destination { ca_file, pins }) =
+			self.destinations.get(server).filter(|_| !self.insecure)
+		else {
+			return self.base();
+		};
+
+		let mut roots = self.roots.clone();
+		if let Some(path) = ca_file {
+			let certs = load_certs(path).map_err(|e| {
+				err!(Config("federation_tls", "CA file of {server} cannot be loaded: {e}"))
+			})?;
+
+			roots.add_parsable_certificates(certs);
+		}
+
+		let roots = verifier(roots)?;
+		if pins.is_empty() {
+			return client_config(|builder| builder.with_webpki_verifier(roots)).map(Some);
+		}
+
+		let pinned = Arc::new(Pinned {
+			server: server.to_owned(),
+			pins: pins.clone(),
+			strict: self.strict,
+			roots,
+		});
+
+		client_config(|builder| builder.dangerous().with_custom_certificate_verifier(pinned))
+			.map(Some)
+	}
+}
+
+impl Destination {
+	fn new(server: &ServerName, tls: &FederationTlsConfig) -> Result<Self> {
+		let pins = tls
+			.pins
+			.iter()
+			.map(|pin| {
+				parse_pin(pin).ok_or_else(|| {
+					err!(Config(
+						"federation_tls",
+						"Pin {pin:?} of {server} is not the base64 of a SHA-256 hash."
+					))
+				})
+			})
+			.collect::<Result<_>>()?;
+
+		Ok(Self { ca_file: tls.ca_file.clone(), pins })
+	}
+}
+
+impl ServerCertVerifier for Pinned {
+	fn verify_server_cert(
+		&self,
+		end_entity: &CertificateDer<'_>,
+		intermediates: &[CertificateDer<'_>],
+		server_name: &TlsServerName<'_>,
+		ocsp_response: &[u8],
+		now: UnixTime,
+	) -> Result<ServerCertVerified, rustls::Error> {
+		let found = pin(end_entity);
+		if found.is_some_and(|found| self.pins.contains(&found)) {
+			return Ok(ServerCertVerified::assertion());
+		}
+
+		let mismatch = PinMismatch {
+			server: self.server.clone(),
+			found: found.map(|found| STANDARD.encode(found)),
+		};
+
+		if self.strict {
+			return Err(rustls::Error::Other(OtherError(Arc::new(mismatch))));
+		}
+
+		warn!("{mismatch}; trusting it by its CA instead.");
+		self.roots
+			.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+	}
+
+	fn verify_tls12_signature(
+		&self,
+		message: &[u8],
+		cert: &CertificateDer<'_>,
+		dss: &DigitallySignedStruct,
+	) -> Result<HandshakeSignatureValid, rustls::Error> {
+		self.roots.verify_tls12_signature(message, cert, dss)
+	}
+
+	fn verify_tls13_signature(
+		&self,
+		message: &[u8],
+		cert: &CertificateDer<'_>,
+		dss: &DigitallySignedStruct,
+	) -> Result<HandshakeSignatureValid, rustls::Error> {
+		self.roots.verify_tls13_signature(message, cert, dss)
+	}
+
+	fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+		self.roots.supported_verify_schemes()
+	}
+}
+
+/// Finds why the TLS handshake of a failed request failed, if it did.
+#[must_use]
+pub fn failure(error: &reqwest::Error) -> Option<Failure<'_>> {
+	let mut source: Option<&(dyn StdError + 'static)> = error.source();
+	while let Some(error) = source {
+		// io::Error skips itself in the chain of sources, so the error it
+		// wraps is looked at directly.
+		let inner = error
+			.downcast_ref::<io::Error>()
+			.and_then(io::Error::get_ref)
+			.map(|inner| -> &(dyn StdError + 'static) { inner });
+
+		if let Some(tls) = inner.unwrap_or(error).downcast_ref::<rustls::Error>() {
+			return Some(match tls {
+				| rustls::Error::Other(OtherError(other)) => other
+					.downcast_ref::<PinMismatch>()
+					.map_or(Failure::Tls(tls), Failure::Pin),
+				| _ => Failure::Tls(tls),
+			});
+		}
+
+		source = inner.or_else(|| error.source());
+	}
+
+	None
+}
+
+/// The pin of the key a certificate carries, if it can be parsed.
+#[must_use]
+pub fn pin(cert: &CertificateDer<'_>) -> Option<Pin> {
+	spki(cert).map(|spki| Sha256::digest(spki).into())
+}
+
+pub(super) fn parse_pin(pin: &str) -> Option<Pin> { STANDARD.decode(pin).ok()?.try_into().ok() }
+
+/// The DER of the public key (SubjectPublicKeyInfo) of a DER certificate.
+fn spki(cert: &[u8]) -> Option<&[u8]> {
+	const SEQUENCE: u8 = 0x30;
+	const VERSION: u8 = 0xA0;
+
+	let (SEQUENCE, cert, _) = split(cert)? else {
+		return None;
+	};
+
+	let (SEQUENCE, tbs, _) = split(cert)? else {
+		return None;
+	};
+
+	// the version is optional; the serial number, signature algorithm,
+	// issuer, validity and subject come before the key
+	let mut rest = tbs;
+	if rest.first() == Some(&VERSION) {
+		(_, _, rest) = split(rest)?;
+	}
+
+	for _ in 0..5 {
+		(_, _, rest) = split(rest)?;
+	}
+
+	let (SEQUENCE, _, after) = split(rest)? else {
+		return None;
+	};
+
+	rest.get(..rest.len().checked_sub(after.len())?)
+}
+
+/// Splits the first element off DER: its tag, its content and what follows.
+fn split(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+	let (&tag, der) = der.split_first()?;
+	let (&len, der) = der.split_first()?;
+
+	let (len, der) = if len < 0x80 {
+		(usize::from(len), der)
+	} else {
+		let count = usize::from(len & 0x7F);
+		let bytes = der.get(..count).filter(|bytes| !bytes.is_empty())?;
+		let len = bytes.iter().try_fold(0_usize, |len, &byte| {
+			len.checked_mul(0x100)?.checked_add(usize::from(byte))
+		})?;
+
+		(len, der.get(count..)?)
+	};
+
+	Some((tag, der.get(..len)?, der.get(len..)?))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+	let certs = CertificateDer::pem_file_iter(path)
+		.and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+		.map_err(|e| err!("{}: {e}", path.display()))?;
+
+	if certs.is_empty() {
+		return Err!("{}: no certificates found", path.display());
+	}
+
+	Ok(certs)
+}
+
+pub(super) fn verifier(roots: RootCertStore) -> Result<Arc<WebPkiServerVerifier>> {
+	WebPkiServerVerifier::builder_with_provider(roots.into(), provider())
+		.build()
+		.map_err(|e| err!("Failed to build the federation TLS verifier: {e}"))
+}
+
+fn client_config<F>(with_verifier: F) -> Result<ClientConfig>
+where
+	F: FnOnce(
+		rustls::ConfigBuilder<ClientConfig, rustls::WantsVerifier>,
+	) -> rustls::ConfigBuilder<ClientConfig, rustls::client::WantsClientCert>,
+{
+	let builder = ClientConfig::builder_with_provider(provider())
+		.with_safe_default_protocol_versions()
+		.map_err(|e| err!("Failed to configure federation TLS: {e}"))?;
+
+	let mut config = with_verifier(builder).with_no_client_auth();
+	config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+	Ok(config)
+}
+
+fn provider() -> Arc<CryptoProvider> {
+	CryptoProvider::get_default()
+		.cloned()
+		.unwrap_or_else(|| aws_lc_rs::default_provider().into())
+}
+
+impl fmt::Display for TrustPath {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::System => write!(f, "system roots"),
+			| Self::TrustedCas => write!(f, "system roots and federation_trusted_ca_files"),
+			| Self::DestinationCa(ca_file) => write!(f, "its CA {}", ca_file.display()),
+			| Self::Pinned { strict: true, .. } => write!(f, "pinned keys (strict)"),
+			| Self::Pinned { ca_file: Some(ca_file), .. } =>
+				write!(f, "pinned keys, or else its CA {}", ca_file.display()),
+			| Self::Pinned { ca_file: None, .. } => write!(f, "pinned keys, or else the roots"),
+			| Self::Unchecked => write!(f, "none; certificates are not checked"),
+		}
+	}
+}
+
+impl fmt::Display for PinMismatch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "The certificate of {} carries none of its pinned keys", self.server)?;
+		if let Some(found) = &self.found {
+			write!(f, " (its key is {found})")?;
+		}
+
+		Ok(())
+	}
+}
+
+impl StdError for PinMismatch {}
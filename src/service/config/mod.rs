@@ -64,8 +64,18 @@ pub fn reload<'a, I>(&self, paths: I) -> Result<Arc<Config>>
 	I: Iterator<Item = &'a Path>,
 {
 	let old = self.server.config.clone();
-	let new = Config::load(paths).and_then(|raw| Config::new(&raw))?;
+	let raw = Config::load(paths)?;
+	let new = Config::new(&raw)?;
 
 	check::reload(&old, &new)?;
+	let virtual_hosts = new.virtual_hosts(&raw)?;
+	for (server, new) in self.server.virtual_hosts.iter().zip(&virtual_hosts) {
+		check::reload(&server.config, new)?;
+	}
+
+	for (server, new) in self.server.virtual_hosts.iter().zip(virtual_hosts) {
+		server.config.update(new)?;
+	}
+
 	self.server.config.update(new)
 }
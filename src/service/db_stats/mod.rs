@@ -0,0 +1,140 @@
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{Result, Server, debug_warn};
+use database::{Database, Stats};
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{cache, service::Map};
+
+/// Periodically publishes database engine statistics and the hit and miss
+/// counters of the service caches to the server metrics.
+pub struct Service {
+	interval: Option<Duration>,
+	interrupt: Notify,
+	db: Arc<Database>,
+	server: Arc<Server>,
+	services: Arc<Map>,
+
+	/// Metric names of each cache; names are static, so they are leaked once
+	/// per cache.
+	cache_metrics: Mutex<HashMap<&'static str, CacheMetrics>>,
+}
+
+#[derive(Clone, Copy)]
+struct CacheMetrics {
+	hits: &'static str,
+	misses: &'static str,
+	hit_percent: &'static str,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let interval = args.server.config.rocksdb_stats_interval_secs;
+
+		Ok(Arc::new(Self {
+			interval: (interval > 0).then(|| Duration::from_secs(interval)),
+			interrupt: Notify::new(),
+			db: args.db.clone(),
+			server: args.server.clone(),
+			services: args.service.clone(),
+			cache_metrics: Mutex::default(),
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "db_stats", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let Some(period) = self.interval else {
+			return Ok(());
+		};
+
+		let mut i = interval(period);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		while self.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			match self.stats().await {
+				| Ok(stats) => self.publish(&stats),
+				| Err(e) => debug_warn!("Failed to collect database statistics: {e}"),
+			}
+
+			self.publish_caches().await;
+		}
+
+		Ok(())
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		for (name, value) in self.server.metrics.values() {
+			writeln!(out, "{name}: {value}")?;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Collects a snapshot of the database statistics.
+	pub async fn stats(&self) -> Result<Stats> {
+		let db = Arc::clone(&self.db);
+
+		self.server
+			.runtime()
+			.spawn_blocking(move || db.stats())
+			.await?
+	}
+
+	fn publish(&self, stats: &Stats) {
+		for (name, value) in stats.iter() {
+			self.server.metrics.set(name, value);
+		}
+	}
+
+	async fn publish_caches(&self) {
+		for (name, usage) in cache::collect(&self.services).await {
+			let (Some(hits), Some(misses)) = (usage.hits, usage.misses) else {
+				continue;
+			};
+
+			let metrics = *self
+				.cache_metrics
+				.lock()
+				.expect("locked")
+				.entry(name)
+				.or_insert_with(|| CacheMetrics {
+					hits: format!("{name}_hits").leak(),
+					misses: format!("{name}_misses").leak(),
+					hit_percent: format!("{name}_hit_percent").leak(),
+				});
+
+			#[allow(
+				clippy::as_conversions,
+				clippy::cast_possible_truncation,
+				clippy::cast_sign_loss
+			)]
+			let hit_percent = usage
+				.hit_rate()
+				.map_or(0, |rate| (rate * 100.0).round() as u64);
+
+			self.server.metrics.set(metrics.hits, hits);
+			self.server.metrics.set(metrics.misses, misses);
+			self.server.metrics.set(metrics.hit_percent, hit_percent);
+		}
+	}
+}
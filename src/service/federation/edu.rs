@@ -0,0 +1,176 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use conduwuit::{Config, implement, warn};
+use ruma::{
+	OwnedServerName, ServerName,
+	api::federation::transactions::edu::{Edu, ReceiptContent},
+	serde::Raw,
+};
+
+/// How many entries of each kind of EDU one inbound transaction may have
+/// processed.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+	/// Largest EDU in bytes.
+	pub max_size: usize,
+
+	/// Read receipts, counting each event of each user in each room.
+	pub receipts: usize,
+
+	pub typing: usize,
+	pub presence: usize,
+	pub device_lists: usize,
+}
+
+/// The EDUs of an inbound transaction which are to be processed.
+#[derive(Debug, Default)]
+pub struct Accepted {
+	pub edus: Vec<Edu>,
+
+	/// EDUs dropped for being larger than allowed.
+	pub oversized: usize,
+
+	/// EDUs which could not be parsed.
+	pub invalid: usize,
+
+	/// Entries dropped for being beyond the limit of their kind.
+	pub truncated: usize,
+}
+
+#[derive(Default)]
+struct Budget {
+	receipts: usize,
+	typing: usize,
+	presence: usize,
+	device_lists: usize,
+}
+
+/// Dropped EDU entries by origin, since startup.
+pub(super) type Truncations = Mutex<HashMap<OwnedServerName, u64>>;
+
+impl Limits {
+	#[must_use]
+	pub fn from_config(config: &Config) -> Self {
+		Self {
+			max_size: config.incoming_edu_max_size,
+			receipts: config.incoming_txn_max_receipts,
+			typing: config.incoming_txn_max_typing,
+			presence: config.incoming_txn_max_presence,
+			device_lists: config.incoming_txn_max_device_list_updates,
+		}
+	}
+}
+
+/// Picks the EDUs of a transaction from the origin to process within the
+/// configured limits, noting what was dropped against the origin.
+#[implement(super::Service)]
+pub fn accept_edus(&self, origin: &ServerName, edus: &[Raw<Edu>]) -> Vec<Edu> {
+	let accepted = accept(&self.edu_limits, edus);
+	let dropped = accepted.oversized.saturating_add(accepted.truncated);
+	if dropped > 0 {
+		let total = {
+			let mut truncations = self.edu_truncations.lock().expect("locked");
+			let total = truncations.entry(origin.to_owned()).or_default();
+			*total = total.saturating_add(dropped.try_into().unwrap_or(u64::MAX));
+			*total
+		};
+
+		warn!(
+			%origin,
+			oversized = accepted.oversized,
+			truncated = accepted.truncated,
+			total,
+			"Dropped EDUs beyond the limits of an inbound transaction"
+		);
+	}
+
+	accepted.edus
+}
+
+/// How many EDU entries from the origin were dropped for exceeding the
+/// limits.
+#[implement(super::Service)]
+#[must_use]
+pub fn edus_truncated(&self, origin: &ServerName) -> u64 {
+	self.edu_truncations
+		.lock()
+		.expect("locked")
+		.get(origin)
+		.copied()
+		.unwrap_or(0)
+}
+
+/// Parses the EDUs of a transaction, dropping those larger than allowed and
+/// truncating each kind to its limit across the transaction.
+#[must_use]
+pub fn accept(limits: &Limits, edus: &[Raw<Edu>]) -> Accepted {
+	let mut accepted = Accepted::default();
+	let mut budget = Budget::default();
+	for edu in edus {
+		let json = edu.json().get();
+		if json.len() > limits.max_size {
+			accepted.oversized = accepted.oversized.saturating_add(1);
+			continue;
+		}
+
+		let Ok(mut edu) = serde_json::from_str::<Edu>(json) else {
+			accepted.invalid = accepted.invalid.saturating_add(1);
+			continue;
+		};
+
+		let keep = match &mut edu {
+			| Edu::Receipt(content) => {
+				let dropped = truncate_receipts(content, &mut budget.receipts, limits.receipts);
+				accepted.truncated = accepted.truncated.saturating_add(dropped);
+				!content.receipts.is_empty()
+			},
+			| Edu::Presence(content) => {
+				let left = limits.presence.saturating_sub(budget.presence);
+				let dropped = content.push.len().saturating_sub(left);
+				content.push.truncate(left);
+				budget.presence = budget.presence.saturating_add(content.push.len());
+				accepted.truncated = accepted.truncated.saturating_add(dropped);
+				!content.push.is_empty()
+			},
+			| Edu::Typing(_) => take(&mut budget.typing, limits.typing, &mut accepted.truncated),
+			| Edu::DeviceListUpdate(_) =>
+				take(&mut budget.device_lists, limits.device_lists, &mut accepted.truncated),
+			| _ => true,
+		};
+
+		if keep {
+			accepted.edus.push(edu);
+		}
+	}
+
+	accepted
+}
+
+fn take(used: &mut usize, limit: usize, truncated: &mut usize) -> bool {
+	if *used >= limit {
+		*truncated = truncated.saturating_add(1);
+		return false;
+	}
+
+	*used = used.saturating_add(1);
+	true
+}
+
+/// Keeps receipts while within the limit, dropping users and rooms left with
+/// none; returns how many were dropped.
+fn truncate_receipts(content: &mut ReceiptContent, used: &mut usize, limit: usize) -> usize {
+	let mut dropped = 0_usize;
+	content.receipts.retain(|_, room| {
+		room.read.retain(|_, user| {
+			let left = limit.saturating_sub(*used);
+			dropped = dropped.saturating_add(user.event_ids.len().saturating_sub(left));
+			user.event_ids.truncate(left);
+			*used = used.saturating_add(user.event_ids.len());
+			!user.event_ids.is_empty()
+		});
+
+		!room.read.is_empty()
+	});
+
+	dropped
+}
@@ -3,7 +3,7 @@
 use bytes::Bytes;
 use conduwuit::{
 	Err, Error, Result, debug, debug::INFO_SPAN_LEVEL, debug_error, debug_warn, err,
-	error::inspect_debug_log, implement, trace, utils::string::EMPTY,
+	error::inspect_debug_log, implement, trace, utils::string::EMPTY, warn,
 };
 use http::{HeaderValue, header::AUTHORIZATION};
 use ipaddress::IPAddress;
@@ -17,7 +17,10 @@
 	serde::Base64,
 };
 
-use crate::resolver::actual::ActualDest;
+use crate::{
+	client::{Federation, tls},
+	resolver::actual::ActualDest,
+};
 
 /// Sends a request to a federation server
 #[implement(super::Service)]
@@ -26,8 +29,7 @@ pub async fn execute<T>(&self, dest: &ServerName, request: T) -> Result<T::Incom
 where
 	T: OutgoingRequest + Debug + Send,
 {
-	let client = &self.services.client.federation;
-	self.execute_on(client, dest, request).await
+	self.execute_on(Federation::Request, dest, request).await
 }
 
 /// Like execute() but with a very large timeout
@@ -41,19 +43,18 @@ pub async fn execute_synapse<T>(
 where
 	T: OutgoingRequest + Debug + Send,
 {
-	let client = &self.services.client.synapse;
-	self.execute_on(client, dest, request).await
+	self.execute_on(Federation::Synapse, dest, request).await
 }
 
 #[implement(super::Service)]
 #[tracing::instrument(
 		name = "fed",
 		level = INFO_SPAN_LEVEL,
-		skip(self, client, request),
+		skip(self, request),
 	)]
 pub async fn execute_on<T>(
 	&self,
-	client: &Client,
+	client: Federation,
 	dest: &ServerName,
 	request: T,
 ) -> Result<T::IncomingResponse>
@@ -64,20 +65,11 @@ pub async fn execute_on<T>(
 		return Err!(Config("allow_federation", "Federation is disabled."));
 	}
 
-	if self
-		.services
-		.server
-		.config
-		.forbidden_remote_server_names
-		.is_match(dest.host())
-	{
-		return Err!(Request(Forbidden(debug_warn!("Federation with {dest} is not allowed."))));
-	}
-
+	let client = self.services.client.federation_for(client, dest)?;
 	let actual = self.services.resolver.get_actual_dest(dest).await?;
 	let request = into_http_request::<T>(&actual, request)?;
 	let request = self.prepare(dest, request)?;
-	self.perform::<T>(dest, &actual, request, client).await
+	self.perform::<T>(dest, &actual, request, &client).await
 }
 
 #[implement(super::Service)]
@@ -96,9 +88,15 @@ async fn perform<T>(
 
 	debug!(?method, ?url, "Sending request");
 	match client.execute(request).await {
-		| Ok(response) => handle_response::<T>(dest, actual, &method, &url, response).await,
-		| Err(error) =>
-			Err(handle_error(actual, &method, &url, error).expect_err("always returns error")),
+		| Ok(response) => {
+			self.services.client.connections.answered(&response);
+			handle_response::<T>(dest, actual, &method, &url, response).await
+		},
+		| Err(error) => {
+			let trust = &self.services.client.federation_tls;
+			Err(handle_error(trust, dest, actual, &method, &url, error)
+				.expect_err("always returns error"))
+		},
 	}
 }
 
@@ -192,11 +190,27 @@ async fn into_http_response(
 }
 
 fn handle_error(
+	trust: &tls::Trust,
+	dest: &ServerName,
 	actual: &ActualDest,
 	method: &Method,
 	url: &Url,
 	mut e: reqwest::Error,
 ) -> Result {
+	match tls::failure(&e) {
+		| Some(tls::Failure::Pin(mismatch)) => {
+			return Err!(BadServerResponse(warn!("{mismatch}; refusing to connect to it.")));
+		},
+		| Some(tls::Failure::Tls(tls)) => {
+			return Err!(BadServerResponse(debug_warn!(
+				"TLS handshake with {dest} ({}) failed, trusting it by {}: {tls}",
+				actual.host,
+				trust.path(dest),
+			)));
+		},
+		| None => {},
+	}
+
 	if e.is_timeout() || e.is_connect() {
 		e = e.without_url();
 		debug_warn!("{e:?}");
@@ -1,13 +1,28 @@
+mod edu;
 mod execute;
+mod state_ids;
+#[cfg(test)]
+mod tests;
+mod transaction;
 
-use std::sync::Arc;
+use std::{fmt::Write, sync::Arc, time::Duration};
 
-use conduwuit::{Result, Server};
+use async_trait::async_trait;
+use conduwuit::{Result, Server, utils::math::usize_from_f64};
 
-use crate::{Dep, client, resolver, server_keys};
+pub use self::{
+	edu::{Accepted, Limits as EduLimits},
+	state_ids::StateIds,
+	transaction::{group_by_room, handle_by_room},
+};
+use crate::{Dep, cache::Resizable, client, resolver, rooms, server_keys};
 
 pub struct Service {
 	services: Services,
+	state_ids_cache: state_ids::Cache,
+	edu_limits: EduLimits,
+	edu_truncations: edu::Truncations,
+	txn_timings: transaction::Timings,
 }
 
 struct Services {
@@ -15,19 +30,43 @@ struct Services {
 	client: Dep<client::Service>,
 	resolver: Dep<resolver::Service>,
 	server_keys: Dep<server_keys::Service>,
+	auth_chain: Dep<rooms::auth_chain::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		let cache_size = f64::from(config.stateids_cache_capacity);
+		let cache_size = cache_size * config.cache_capacity_modifier;
+		let cache_ttl = Duration::from_secs(config.stateids_cache_ttl);
 		Ok(Arc::new(Self {
 			services: Services {
 				server: args.server.clone(),
 				client: args.depend::<client::Service>("client"),
 				resolver: args.depend::<resolver::Service>("resolver"),
 				server_keys: args.depend::<server_keys::Service>("server_keys"),
+				auth_chain: args.depend::<rooms::auth_chain::Service>("rooms::auth_chain"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 			},
+			state_ids_cache: state_ids::Cache::new(usize_from_f64(cache_size)?, cache_ttl),
+			edu_limits: EduLimits::from_config(config),
+			edu_truncations: edu::Truncations::default(),
+			txn_timings: transaction::Timings::default(),
 		}))
 	}
 
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		self.state_ids_cache.memory_usage(out)
+	}
+
+	async fn clear_cache(&self) { self.state_ids_cache.clear(); }
+
+	fn caches(&self) -> Vec<(&'static str, &dyn Resizable)> {
+		vec![("state_ids_cache", &self.state_ids_cache)]
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
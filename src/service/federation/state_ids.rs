@@ -0,0 +1,152 @@
+use std::{
+	borrow::Borrow,
+	fmt::Write,
+	iter::once,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Result, at, err, implement};
+use futures::{StreamExt, TryStreamExt};
+use lru_cache::LruCache;
+use ruma::{EventId, OwnedEventId, OwnedRoomId, RoomId};
+
+use super::Service;
+use crate::cache::{Counted, Resizable, Usage};
+
+/// Computed response for federation `/state` and `/state_ids` at an event.
+/// Only the event IDs are retained; the full events are fetched from the
+/// timeline at serving time.
+#[derive(Debug)]
+pub struct StateIds {
+	pub pdu_ids: Vec<OwnedEventId>,
+	pub auth_chain_ids: Vec<OwnedEventId>,
+}
+
+/// Size-bounded and time-bounded cache of `StateIds` shared across all
+/// requesting servers.
+pub(super) struct Cache {
+	entries: Counted<Mutex<LruCache<Key, Entry>>>,
+	ttl: Duration,
+}
+
+type Key = (OwnedRoomId, OwnedEventId);
+type Entry = (Instant, Arc<StateIds>);
+
+/// Retrieves the state and auth chain event IDs at an event, computing them
+/// only on a cache miss. Access checks are the responsibility of the caller.
+#[implement(Service)]
+#[tracing::instrument(level = "debug", skip(self))]
+pub async fn state_ids_at(&self, room_id: &RoomId, event_id: &EventId) -> Result<Arc<StateIds>> {
+	if let Some(cached) = self.state_ids_cache.get(room_id, event_id) {
+		return Ok(cached);
+	}
+
+	let shortstatehash = self
+		.services
+		.state_accessor
+		.pdu_shortstatehash(event_id)
+		.await
+		.map_err(|_| err!(Request(NotFound("PDU state not found."))))?;
+
+	let pdu_ids: Vec<OwnedEventId> = self
+		.services
+		.state_accessor
+		.state_full_ids(shortstatehash)
+		.map(at!(1))
+		.collect()
+		.await;
+
+	let auth_chain_ids: Vec<OwnedEventId> = self
+		.services
+		.auth_chain
+		.event_ids_iter(room_id, once(event_id.borrow()))
+		.try_collect()
+		.await?;
+
+	let state_ids = Arc::new(StateIds { pdu_ids, auth_chain_ids });
+	self.state_ids_cache
+		.insert(room_id, event_id, Arc::clone(&state_ids));
+
+	Ok(state_ids)
+}
+
+/// Drops all cached `/state` and `/state_ids` responses for a room.
+#[implement(Service)]
+pub fn clear_room_state_ids(&self, room_id: &RoomId) {
+	self.state_ids_cache.remove_room(room_id);
+}
+
+impl Cache {
+	pub(super) fn new(capacity: usize, ttl: Duration) -> Self {
+		Self {
+			entries: Mutex::new(LruCache::new(capacity)).into(),
+			ttl,
+		}
+	}
+
+	pub(super) fn get(&self, room_id: &RoomId, event_id: &EventId) -> Option<Arc<StateIds>> {
+		let key = (room_id.to_owned(), event_id.to_owned());
+		let mut entries = self.entries.lock().expect("locked");
+		let found = match entries.get_mut(&key) {
+			| Some((inserted, state_ids)) if inserted.elapsed() < self.ttl =>
+				Some(Arc::clone(state_ids)),
+			| Some(_) => {
+				entries.remove(&key);
+				None
+			},
+			| None => None,
+		};
+
+		self.entries.counters().tally(found)
+	}
+
+	pub(super) fn insert(&self, room_id: &RoomId, event_id: &EventId, state_ids: Arc<StateIds>) {
+		let key = (room_id.to_owned(), event_id.to_owned());
+		self.entries
+			.lock()
+			.expect("locked")
+			.insert(key, (Instant::now(), state_ids));
+	}
+
+	pub(super) fn remove_room(&self, room_id: &RoomId) {
+		let mut entries = self.entries.lock().expect("locked");
+		let keys: Vec<Key> = entries
+			.iter()
+			.map(at!(0))
+			.filter(|(cached_room_id, _)| *cached_room_id == room_id)
+			.cloned()
+			.collect();
+
+		for key in keys {
+			entries.remove(&key);
+		}
+	}
+
+	pub(super) fn clear(&self) { self.entries.lock().expect("locked").clear(); }
+
+	pub(super) fn len(&self) -> usize { self.entries.lock().expect("locked").len() }
+
+	pub(super) fn hits(&self) -> u64 { self.entries.counters().hits() }
+
+	pub(super) fn misses(&self) -> u64 { self.entries.counters().misses() }
+
+	pub(super) fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let len = self.len();
+		let counters = self.entries.counters();
+
+		writeln!(out, "state_ids_cache: {len} ({counters})")?;
+
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl Resizable for Cache {
+	async fn usage(&self) -> Usage { self.entries.usage().await }
+
+	async fn set_capacity(&self, capacity: usize) { self.entries.set_capacity(capacity).await; }
+
+	fn reset_counters(&self) { self.entries.reset_counters(); }
+}
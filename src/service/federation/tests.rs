@@ -0,0 +1,321 @@
+use std::{
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduwuit::Err;
+use ruma::{
+	api::federation::transactions::edu::Edu, event_id, owned_event_id, owned_room_id, room_id,
+	serde::Raw,
+};
+use serde_json::{Value, json, value::to_raw_value};
+
+use super::{
+	edu::{Limits, accept},
+	state_ids::{Cache, StateIds},
+	transaction::{group_by_room, handle_by_room},
+};
+
+fn state_ids() -> Arc<StateIds> {
+	Arc::new(StateIds {
+		pdu_ids: vec![owned_event_id!("$create:example.org")],
+		auth_chain_ids: vec![owned_event_id!("$power:example.org")],
+	})
+}
+
+#[test]
+fn state_ids_cache_sequential_hit() {
+	let cache = Cache::new(16, Duration::from_secs(60));
+	let room_id = room_id!("!room:example.org");
+	let event_id = event_id!("$event:example.org");
+
+	assert!(cache.get(room_id, event_id).is_none(), "first request misses");
+	cache.insert(room_id, event_id, state_ids());
+
+	let first = cache.get(room_id, event_id).expect("second request hits");
+	let second = cache.get(room_id, event_id).expect("third request hits");
+	assert!(Arc::ptr_eq(&first, &second), "entries shared between requests");
+	assert_eq!(cache.hits(), 2);
+	assert_eq!(cache.misses(), 1);
+}
+
+#[test]
+fn state_ids_cache_expires() {
+	let cache = Cache::new(16, Duration::ZERO);
+	let room_id = room_id!("!room:example.org");
+	let event_id = event_id!("$event:example.org");
+
+	cache.insert(room_id, event_id, state_ids());
+	assert!(cache.get(room_id, event_id).is_none(), "expired entry misses");
+	assert_eq!(cache.len(), 0, "expired entry evicted");
+}
+
+#[test]
+fn state_ids_cache_remove_room() {
+	let cache = Cache::new(16, Duration::from_secs(60));
+	let room_a = room_id!("!a:example.org");
+	let room_b = room_id!("!b:example.org");
+	let event_id = event_id!("$event:example.org");
+
+	cache.insert(room_a, event_id, state_ids());
+	cache.insert(room_b, event_id, state_ids());
+	cache.remove_room(room_a);
+
+	assert!(cache.get(room_a, event_id).is_none());
+	assert!(cache.get(room_b, event_id).is_some());
+}
+
+#[tokio::test]
+async fn state_ids_cache_resize() {
+	use crate::cache::Resizable;
+
+	let cache = Cache::new(4, Duration::from_secs(60));
+	let room_id = room_id!("!room:example.org");
+	let event_ids = [
+		owned_event_id!("$a:example.org"),
+		owned_event_id!("$b:example.org"),
+		owned_event_id!("$c:example.org"),
+		owned_event_id!("$d:example.org"),
+	];
+
+	for event_id in &event_ids {
+		cache.insert(room_id, event_id, state_ids());
+	}
+
+	assert!(cache.get(room_id, &event_ids[0]).is_some(), "most recently used");
+	assert!(cache.get(room_id, event_id!("$e:example.org")).is_none());
+
+	cache.set_capacity(2).await;
+	let usage = cache.usage().await;
+	assert_eq!((usage.len, usage.capacity), (2, 2), "evicted on shrink");
+	assert_eq!(usage.hit_rate(), Some(0.5));
+
+	assert!(cache.get(room_id, &event_ids[0]).is_some(), "recently used entry kept");
+	assert!(cache.get(room_id, &event_ids[1]).is_none(), "least recently used evicted");
+
+	cache.set_capacity(8).await;
+	assert_eq!(cache.usage().await.capacity, 8);
+}
+
+#[tokio::test]
+async fn state_ids_cache_counters_reset() {
+	use crate::cache::Resizable;
+
+	let cache = Cache::new(4, Duration::from_secs(60));
+	let room_id = room_id!("!room:example.org");
+	let event_id = event_id!("$event:example.org");
+
+	cache.insert(room_id, event_id, state_ids());
+	assert!(cache.get(room_id, event_id).is_some());
+	assert!(
+		cache
+			.get(room_id, event_id!("$other:example.org"))
+			.is_none()
+	);
+	assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+	cache.reset_counters();
+	let usage = cache.usage().await;
+	assert_eq!((usage.hits, usage.misses), (Some(0), Some(0)));
+	assert_eq!(usage.hit_rate(), None, "no lookups since the reset");
+	assert_eq!(usage.len, 1, "entries kept");
+}
+
+const LIMITS: Limits = Limits {
+	max_size: 64 * 1024,
+	receipts: 1000,
+	typing: 100,
+	presence: 500,
+	device_lists: 500,
+};
+
+fn edu(json: &Value) -> Raw<Edu> { Raw::from_json(to_raw_value(json).expect("valid json")) }
+
+fn receipts_edu(edu_num: usize, users: usize, events: usize) -> Raw<Edu> {
+	let read: serde_json::Map<_, _> = (0..users)
+		.map(|user| {
+			let event_ids: Vec<_> = (0..events)
+				.map(|event| format!("$event{edu_num}_{user}_{event}:remote.example"))
+				.collect();
+
+			let receipt = json!({ "data": { "ts": 1 }, "event_ids": event_ids });
+			(format!("@user{user:04}:remote.example"), receipt)
+		})
+		.collect();
+
+	edu(&json!({
+		"edu_type": "m.receipt",
+		"content": { format!("!room{edu_num:03}:remote.example"): { "m.read": read } },
+	}))
+}
+
+fn receipts(edus: &[Edu]) -> usize {
+	edus.iter()
+		.filter_map(|edu| match edu {
+			| Edu::Receipt(content) => Some(content),
+			| _ => None,
+		})
+		.flat_map(|content| content.receipts.values())
+		.flat_map(|room| room.read.values())
+		.map(|user| user.event_ids.len())
+		.sum()
+}
+
+#[test]
+fn edu_pathological_receipts() {
+	let mut edus: Vec<_> = (0..100).map(|i| receipts_edu(i, 50, 3)).collect();
+	edus.insert(0, receipts_edu(999, 1000, 10));
+
+	let started = Instant::now();
+	let accepted = accept(&LIMITS, &edus);
+
+	assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+	assert_eq!(accepted.oversized, 1, "oversized EDU dropped alone");
+	assert_eq!(receipts(&accepted.edus), LIMITS.receipts);
+	assert_eq!(accepted.truncated, 14_000, "15000 sent");
+
+	// the first receipts are kept whole, then those beyond the limit dropped
+	assert_eq!(accepted.edus.len(), 7);
+	let first = accepted.edus.first().expect("receipts kept");
+	assert_eq!(receipts(std::slice::from_ref(first)), 150);
+	let Edu::Receipt(first) = first else {
+		panic!("receipt EDU kept");
+	};
+	assert!(
+		first
+			.receipts
+			.contains_key(room_id!("!room000:remote.example"))
+	);
+}
+
+#[test]
+fn edu_limits_per_type() {
+	let typing = |i: usize| {
+		edu(&json!({
+			"edu_type": "m.typing",
+			"content": {
+				"room_id": "!room:remote.example",
+				"user_id": format!("@user{i}:remote.example"),
+				"typing": true,
+			},
+		}))
+	};
+
+	let presence = |updates: usize| {
+		let push: Vec<_> = (0..updates)
+			.map(|i| {
+				json!({
+					"user_id": format!("@user{i}:remote.example"),
+					"presence": "online",
+					"last_active_ago": 1,
+					"currently_active": true,
+				})
+			})
+			.collect();
+
+		edu(&json!({ "edu_type": "m.presence", "content": { "push": push } }))
+	};
+
+	let device_list = |i: usize| {
+		edu(&json!({
+			"edu_type": "m.device_list_update",
+			"content": {
+				"user_id": format!("@user{i}:remote.example"),
+				"device_id": "DEVICE",
+				"stream_id": 1,
+				"deleted": false,
+			},
+		}))
+	};
+
+	let mut edus: Vec<_> = (0..150).map(typing).collect();
+	edus.extend([presence(300), presence(300)]);
+	edus.extend((0..600).map(device_list));
+	edus.push(edu(&json!({ "edu_type": "m.receipt", "content": "invalid" })));
+
+	let accepted = accept(&LIMITS, &edus);
+	let count = |f: fn(&Edu) -> bool| accepted.edus.iter().filter(|edu| f(edu)).count();
+
+	assert_eq!(count(|edu| matches!(edu, Edu::Typing(_))), LIMITS.typing);
+	assert_eq!(count(|edu| matches!(edu, Edu::DeviceListUpdate(_))), LIMITS.device_lists);
+
+	let presence: usize = accepted
+		.edus
+		.iter()
+		.filter_map(|edu| match edu {
+			| Edu::Presence(content) => Some(content.push.len()),
+			| _ => None,
+		})
+		.sum();
+
+	assert_eq!(presence, LIMITS.presence, "second presence EDU truncated");
+	assert_eq!(accepted.truncated, 250, "typing, presence and device lists");
+	assert_eq!(accepted.invalid, 1);
+	assert_eq!(accepted.oversized, 0);
+}
+
+#[test]
+fn transaction_grouped_in_order() {
+	let rooms = group_by_room([
+		(owned_room_id!("!b:example.org"), 1),
+		(owned_room_id!("!a:example.org"), 2),
+		(owned_room_id!("!b:example.org"), 3),
+		(owned_room_id!("!a:example.org"), 4),
+		(owned_room_id!("!b:example.org"), 5),
+	]);
+
+	assert_eq!(rooms.len(), 2);
+	assert_eq!(rooms.get(room_id!("!a:example.org")), Some(&vec![2, 4]));
+	assert_eq!(rooms.get(room_id!("!b:example.org")), Some(&vec![1, 3, 5]));
+}
+
+#[tokio::test]
+async fn transaction_slow_room_does_not_block() {
+	let slow = owned_room_id!("!slow:example.org");
+	let pdus = [
+		(slow.clone(), "$slow1"),
+		(owned_room_id!("!fast:example.org"), "$fast1"),
+		(slow.clone(), "$slow2"),
+		(owned_room_id!("!fast:example.org"), "$fast2"),
+	];
+
+	let handled = Mutex::new(Vec::new());
+	let (results, slowest) = handle_by_room(pdus, 4, |room_id, pdus| {
+		let handled = &handled;
+		let slow = slow.clone();
+		async move {
+			for pdu in &pdus {
+				if room_id == slow {
+					tokio::time::sleep(Duration::from_millis(50)).await;
+				}
+
+				handled.lock().expect("locked").push(*pdu);
+			}
+
+			Ok(pdus)
+		}
+	})
+	.await
+	.expect("all rooms handled");
+
+	let handled = handled.into_inner().expect("not poisoned");
+	assert_eq!(handled, ["$fast1", "$fast2", "$slow1", "$slow2"]);
+	assert_eq!(results.len(), 4);
+	assert!(slowest >= Duration::from_millis(100), "slowest room timed");
+}
+
+#[tokio::test]
+async fn transaction_room_failure_surfaces() {
+	let pdus = [(owned_room_id!("!a:example.org"), 1), (owned_room_id!("!b:example.org"), 2)];
+
+	let result = handle_by_room(pdus, 2, |room_id, pdus| async move {
+		if room_id == room_id!("!b:example.org") {
+			return Err!("shutting down");
+		}
+
+		Ok(pdus)
+	})
+	.await;
+
+	assert!(result.is_err());
+}
@@ -0,0 +1,88 @@
+//! Inbound transactions
+//!
+//! The PDUs of a transaction are handled a room at a time, in the order they
+//! were sent, but rooms are independent of each other: one whose auth chain
+//! takes long to fetch must not hold up the others until the sender times the
+//! transaction out and sends it again.
+
+use std::{
+	collections::BTreeMap,
+	sync::atomic::{AtomicU64, Ordering},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{Result, implement};
+use futures::{Future, StreamExt, TryFutureExt, stream};
+use ruma::OwnedRoomId;
+
+/// Slowest inbound transaction handled since startup.
+#[derive(Debug, Default)]
+pub(super) struct Timings {
+	max: AtomicU64,
+}
+
+/// Groups the items by room, keeping their order within each room.
+pub fn group_by_room<T, I>(items: I) -> BTreeMap<OwnedRoomId, Vec<T>>
+where
+	I: IntoIterator<Item = (OwnedRoomId, T)>,
+{
+	items
+		.into_iter()
+		.fold(BTreeMap::new(), |mut rooms, (room_id, item)| {
+			rooms.entry(room_id).or_insert_with(Vec::new).push(item);
+
+			rooms
+		})
+}
+
+/// Handles the items of each room in turn with `handle`, up to `width` rooms
+/// at once. Returns the results of all rooms, in no particular order between
+/// rooms, and how long the slowest room took.
+pub async fn handle_by_room<T, R, I, F, Fut>(
+	items: I,
+	width: usize,
+	handle: F,
+) -> Result<(Vec<R>, Duration)>
+where
+	I: IntoIterator<Item = (OwnedRoomId, T)>,
+	F: Fn(OwnedRoomId, Vec<T>) -> Fut + Send + Sync,
+	Fut: Future<Output = Result<Vec<R>>> + Send,
+	T: Send,
+	R: Send,
+{
+	let mut handled = stream::iter(group_by_room(items))
+		.map(|(room_id, items)| {
+			let started = Instant::now();
+			handle(room_id, items).map_ok(move |results| (results, started.elapsed()))
+		})
+		.buffer_unordered(width.max(1));
+
+	let mut results = Vec::new();
+	let mut slowest = Duration::ZERO;
+	while let Some(room) = handled.next().await {
+		let (room_results, elapsed) = room?;
+		results.extend(room_results);
+		slowest = slowest.max(elapsed);
+	}
+
+	Ok((results, slowest))
+}
+
+/// Publishes how long an inbound transaction took to handle, and its slowest
+/// room, to the server metrics.
+#[implement(super::Service)]
+pub fn record_transaction(&self, elapsed: Duration, slowest_room: Duration) {
+	let millis = |duration: Duration| duration.as_millis().try_into().unwrap_or(u64::MAX);
+
+	let elapsed = millis(elapsed);
+	let max = self
+		.txn_timings
+		.max
+		.fetch_max(elapsed, Ordering::Relaxed)
+		.max(elapsed);
+
+	let metrics = &self.services.server.metrics;
+	metrics.set("federation_txn_elapsed_ms", elapsed);
+	metrics.set("federation_txn_elapsed_max_ms", max);
+	metrics.set("federation_txn_slowest_room_ms", millis(slowest_room));
+}
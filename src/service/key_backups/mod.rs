@@ -4,7 +4,7 @@
 	Err, Result, err, implement,
 	utils::stream::{ReadyExt, TryIgnore},
 };
-use database::{Deserialized, Ignore, Interfix, Json, Map};
+use database::{Deserialized, Ignore, Interfix, Json, Map, serialize_key};
 use futures::StreamExt;
 use ruma::{
 	OwnedRoomId, RoomId, UserId,
@@ -257,25 +257,15 @@ pub async fn get_session(
 #[implement(Service)]
 pub async fn delete_all_keys(&self, user_id: &UserId, version: &str) {
 	let key = (user_id, version, Interfix);
-	self.db
-		.backupkeyid_backup
-		.keys_prefix_raw(&key)
-		.ignore_err()
-		.ready_for_each(|outdated_key| self.db.backupkeyid_backup.remove(outdated_key))
-		.await;
+	let prefix = serialize_key(key).expect("failed to serialize prefix");
+	self.db.backupkeyid_backup.delete_prefix(&prefix);
 }
 
 #[implement(Service)]
 pub async fn delete_room_keys(&self, user_id: &UserId, version: &str, room_id: &RoomId) {
 	let key = (user_id, version, room_id, Interfix);
-	self.db
-		.backupkeyid_backup
-		.keys_prefix_raw(&key)
-		.ignore_err()
-		.ready_for_each(|outdated_key| {
-			self.db.backupkeyid_backup.remove(outdated_key);
-		})
-		.await;
+	let prefix = serialize_key(key).expect("failed to serialize prefix");
+	self.db.backupkeyid_backup.delete_prefix(&prefix);
 }
 
 #[implement(Service)]
@@ -0,0 +1,63 @@
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits the emails sent to the addresses of each domain per minute, so a
+/// burst to one provider is not taken for spam.
+pub(super) struct Limiter {
+	per_minute: u32,
+	windows: HashMap<String, Window>,
+}
+
+struct Window {
+	start: Instant,
+	sent: u32,
+}
+
+impl Limiter {
+	/// A limit of zero allows any number of emails.
+	pub(super) fn new(per_minute: u32) -> Self { Self { per_minute, windows: HashMap::new() } }
+
+	/// Counts an email to the domain, or returns when the next one may be sent
+	/// when its limit is reached.
+	pub(super) fn acquire(&mut self, domain: &str, now: Instant) -> Result<(), Instant> {
+		if self.per_minute == 0 {
+			return Ok(());
+		}
+
+		let window = self
+			.windows
+			.entry(domain.to_ascii_lowercase())
+			.or_insert(Window { start: now, sent: 0 });
+
+		if now.saturating_duration_since(window.start) >= WINDOW {
+			*window = Window { start: now, sent: 0 };
+		}
+
+		if window.sent >= self.per_minute {
+			return Err(window.start.checked_add(WINDOW).unwrap_or(now));
+		}
+
+		window.sent = window.sent.saturating_add(1);
+
+		Ok(())
+	}
+
+	/// Forgets the domains whose window has passed.
+	pub(super) fn prune(&mut self, now: Instant) {
+		self.windows
+			.retain(|_, window| now.saturating_duration_since(window.start) < WINDOW);
+	}
+
+	pub(super) fn len(&self) -> usize { self.windows.len() }
+}
+
+/// Domain of an address, which must have a non-empty local part.
+pub(super) fn domain(address: &str) -> Option<&str> {
+	let (local, domain) = address.trim().rsplit_once('@')?;
+
+	(!local.is_empty() && !domain.is_empty()).then_some(domain)
+}
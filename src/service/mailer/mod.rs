@@ -0,0 +1,301 @@
+//! Email
+//!
+//! The one way the other features send email: each is rendered from a named
+//! template and queued, then sent over SMTP by the worker, which holds back
+//! emails over the limit of their recipient's domain and retries those which
+//! failed transiently. Emails given up on are kept in a short log for the
+//! admin room. Sending needs the 'email' feature; without it, or without an
+//! SMTP server configured, queueing email fails.
+
+mod limit;
+mod templates;
+#[cfg(test)]
+mod tests;
+mod transport;
+
+use std::{
+	collections::VecDeque,
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result, Server, debug, debug_warn, err, error};
+use tokio::{sync::Notify, time::sleep_until};
+
+pub use self::templates::Context;
+use self::{
+	limit::Limiter,
+	templates::{Email, Templates},
+	transport::{SendError, Transport},
+};
+use crate::{Dep, admin};
+
+pub struct Service {
+	services: Services,
+	templates: Templates,
+	transport: Option<Transport>,
+	queue: Mutex<Vec<Job>>,
+	failures: Mutex<VecDeque<Failure>>,
+	limiter: Mutex<Limiter>,
+	queued: Notify,
+}
+
+struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
+}
+
+struct Job {
+	template: String,
+	recipient: String,
+	email: Email,
+	attempts: u32,
+	not_before: Instant,
+}
+
+/// An email which was given up on.
+#[derive(Clone, Debug)]
+pub struct Failure {
+	pub template: String,
+	pub recipient: String,
+	pub attempts: u32,
+	pub error: String,
+	pub failed_at: SystemTime,
+}
+
+/// Most emails waiting to be sent; more are refused.
+const QUEUE_LIMIT: usize = 1024;
+
+/// Most emails given up on which are remembered.
+const FAILURES_KEPT: usize = 100;
+
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+const LAST_RETRY: Duration = Duration::from_secs(60 * 60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config.email;
+
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+			},
+			templates: Templates::load(config.templates_dir.as_deref())?,
+			transport: Transport::new(config)?,
+			queue: Mutex::new(Vec::new()),
+			failures: Mutex::new(VecDeque::new()),
+			limiter: Mutex::new(Limiter::new(config.rate_limit_per_domain)),
+			queued: Notify::new(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		while self.services.server.running() {
+			let next = self.send_queued().await;
+			let due = async {
+				match next {
+					| Some(at) => sleep_until(at.into()).await,
+					| None => std::future::pending().await,
+				}
+			};
+
+			tokio::select! {
+				() = self.queued.notified() => (),
+				() = due => (),
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.queued.notify_one(); }
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let queued = self.queue.lock().expect("locked").len();
+		let failures = self.failures.lock().expect("locked").len();
+		let domains = self.limiter.lock().expect("locked").len();
+		writeln!(out, "email_queue: {queued}")?;
+		writeln!(out, "email_failures: {failures}")?;
+		writeln!(out, "email_limited_domains: {domains}")?;
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Whether email can be sent at all.
+	#[inline]
+	#[must_use]
+	pub fn is_enabled(&self) -> bool { self.transport.is_some() }
+
+	/// Renders the template for the recipient and queues it to be sent. The
+	/// server name is always available to templates as `server_name`.
+	pub async fn send(&self, template: &str, recipient: &str, context: Context) -> Result {
+		self.check_enabled()?;
+		let email = self.render(template, recipient, context)?;
+
+		{
+			let mut queue = self.queue.lock().expect("locked");
+			if queue.len() >= QUEUE_LIMIT {
+				return Err!("Too many emails are waiting to be sent");
+			}
+
+			queue.push(Job {
+				template: template.to_owned(),
+				recipient: recipient.to_owned(),
+				email,
+				attempts: 0,
+				not_before: Instant::now(),
+			});
+		}
+
+		self.queued.notify_one();
+
+		Ok(())
+	}
+
+	/// Renders and sends an email at once, bypassing the queue and its limits,
+	/// so the caller learns whether it was accepted by the SMTP server.
+	pub async fn send_now(&self, template: &str, recipient: &str, context: Context) -> Result {
+		let transport = self.check_enabled()?;
+		let email = self.render(template, recipient, context)?;
+
+		transport
+			.deliver(recipient, &email)
+			.await
+			.map_err(|e| match e {
+				| SendError::Transient(e) | SendError::Permanent(e) =>
+					err!("Failed to send email to {recipient}: {e}"),
+			})
+	}
+
+	/// The emails most recently given up on, latest first.
+	#[must_use]
+	pub fn failures(&self) -> Vec<Failure> {
+		self.failures
+			.lock()
+			.expect("locked")
+			.iter()
+			.cloned()
+			.collect()
+	}
+
+	/// Names of the templates which can be sent.
+	pub fn templates(&self) -> impl Iterator<Item = &str> + '_ { self.templates.names() }
+
+	fn check_enabled(&self) -> Result<&Transport> {
+		let Some(transport) = &self.transport else {
+			return Err!(FeatureDisabled("email"));
+		};
+
+		Ok(transport)
+	}
+
+	fn render(&self, template: &str, recipient: &str, mut context: Context) -> Result<Email> {
+		if limit::domain(recipient).is_none() {
+			return Err!(Request(InvalidParam("Invalid email address {recipient:?}")));
+		}
+
+		context
+			.entry("server_name".to_owned())
+			.or_insert_with(|| self.services.server.name.to_string());
+
+		self.templates.render(template, &context)
+	}
+
+	/// Sends the emails which are due, returning when the next one is.
+	async fn send_queued(&self) -> Option<Instant> {
+		let transport = self.transport.as_ref()?;
+		let now = Instant::now();
+		let due: Vec<_> = {
+			let mut queue = self.queue.lock().expect("locked");
+			let (due, later) = queue.drain(..).partition(|job| job.not_before <= now);
+			*queue = later;
+			due
+		};
+
+		self.limiter.lock().expect("locked").prune(now);
+		for mut job in due {
+			let domain = limit::domain(&job.recipient).unwrap_or_default();
+			if let Err(at) = self.limiter.lock().expect("locked").acquire(domain, now) {
+				job.not_before = at;
+				self.requeue(job);
+				continue;
+			}
+
+			job.attempts = job.attempts.saturating_add(1);
+			match transport.deliver(&job.recipient, &job.email).await {
+				| Ok(()) => {
+					debug!(template = %job.template, recipient = %job.recipient, "Sent email");
+				},
+				| Err(SendError::Transient(e))
+					if job.attempts < self.services.server.config.email.max_attempts =>
+				{
+					let retry = retry_after(job.attempts);
+					debug_warn!(
+						template = %job.template,
+						recipient = %job.recipient,
+						"Failed to send email, retrying in {retry:?}: {e}"
+					);
+
+					job.not_before = Instant::now().checked_add(retry).unwrap_or(now);
+					self.requeue(job);
+				},
+				| Err(SendError::Transient(e) | SendError::Permanent(e)) => {
+					self.give_up(job, e).await;
+				},
+			}
+		}
+
+		self.queue
+			.lock()
+			.expect("locked")
+			.iter()
+			.map(|job| job.not_before)
+			.min()
+	}
+
+	fn requeue(&self, job: Job) { self.queue.lock().expect("locked").push(job); }
+
+	async fn give_up(&self, job: Job, error: String) {
+		let Job { template, recipient, attempts, .. } = job;
+		error!(%template, %recipient, attempts, "Gave up sending email: {error}");
+
+		let notice = format!(
+			"Gave up sending the {template} email to {recipient} after {attempts} attempt(s): \
+			 {error}"
+		);
+
+		{
+			let mut failures = self.failures.lock().expect("locked");
+			failures.truncate(FAILURES_KEPT.saturating_sub(1));
+			failures.push_front(Failure {
+				template,
+				recipient,
+				attempts,
+				error,
+				failed_at: SystemTime::now(),
+			});
+		}
+
+		if self.services.server.config.email.notify_admins_on_failure {
+			self.services.admin.send_text(&notice).await;
+		}
+	}
+}
+
+/// Delay before the next attempt at an email after `attempts` failed ones,
+/// doubling from [`FIRST_RETRY`] up to [`LAST_RETRY`].
+fn retry_after(attempts: u32) -> Duration {
+	let doublings = attempts.saturating_sub(1).min(16);
+
+	FIRST_RETRY
+		.saturating_mul(2_u32.saturating_pow(doublings))
+		.min(LAST_RETRY)
+}
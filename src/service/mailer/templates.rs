@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use conduwuit::{Err, Result, debug, err};
+
+/// Values for the placeholders of a template, by name.
+pub type Context = BTreeMap<String, String>;
+
+/// An email rendered from a template, ready to be sent.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct Email {
+	pub(super) subject: String,
+	pub(super) body: String,
+}
+
+/// The templates email is rendered from, by name.
+pub(super) struct Templates {
+	templates: BTreeMap<String, Email>,
+}
+
+/// Templates available without any configured; each can be overridden from
+/// "templates_dir".
+const BUILTIN: &[(&str, &str, &str)] = &[
+	(
+		"test",
+		"Test email from {{server_name}}",
+		"This email was sent by {{server_name}} to verify its email configuration.\n\nIf you \
+		 did not expect it, you can ignore it.\n",
+	),
+	(
+		"password_reset",
+		"Reset your password on {{server_name}}",
+		"Someone asked to reset the password of your account on {{server_name}}. To choose a \
+		 new password, open:\n\n{{link}}\n\nIf this was not you, ignore this email and your \
+		 password stays unchanged.\n",
+	),
+	(
+		"threepid_validation",
+		"Confirm your email address on {{server_name}}",
+		"Someone asked to add this email address to their account on {{server_name}}. To \
+		 confirm it, open:\n\n{{link}}\n\nIf this was not you, ignore this email.\n",
+	),
+	(
+		"account_renewal",
+		"Renew your account on {{server_name}}",
+		"Your account {{user_id}} on {{server_name}} expires on {{expires}}. To keep using it, \
+		 open:\n\n{{link}}\n",
+	),
+	("admin_alert", "[{{server_name}}] {{subject}}", "{{message}}\n"),
+];
+
+impl Templates {
+	/// Loads the builtin templates, overridden by or added to from `dir`.
+	pub(super) fn load(dir: Option<&Path>) -> Result<Self> {
+		let mut templates: BTreeMap<_, _> = BUILTIN
+			.iter()
+			.map(|&(name, subject, body)| {
+				(name.to_owned(), Email {
+					subject: subject.to_owned(),
+					body: body.to_owned(),
+				})
+			})
+			.collect();
+
+		let Some(dir) = dir else {
+			return Ok(Self { templates });
+		};
+
+		for entry in fs::read_dir(dir)? {
+			let path = entry?.path();
+			if path.extension().is_none_or(|extension| extension != "txt") {
+				continue;
+			}
+
+			let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+				continue;
+			};
+
+			let subject = fs::read_to_string(path.with_extension("subject")).map_err(|e| {
+				err!(Config("email.templates_dir", "Template {name} has no subject: {e}"))
+			})?;
+
+			debug!(?path, "Loaded email template {name}");
+			templates.insert(name.to_owned(), Email {
+				subject: subject.trim().to_owned(),
+				body: fs::read_to_string(&path)?,
+			});
+		}
+
+		Ok(Self { templates })
+	}
+
+	/// Renders the template, failing when it is unknown or a placeholder has
+	/// no value in `context`.
+	pub(super) fn render(&self, name: &str, context: &Context) -> Result<Email> {
+		let Some(template) = self.templates.get(name) else {
+			return Err!(Request(NotFound("No email template named {name}")));
+		};
+
+		Ok(Email {
+			subject: substitute(&template.subject, context)?,
+			body: substitute(&template.body, context)?,
+		})
+	}
+
+	pub(super) fn names(&self) -> impl Iterator<Item = &str> + '_ {
+		self.templates.keys().map(String::as_str)
+	}
+}
+
+/// Replaces each `{{name}}` in `text` with its value in `context`.
+pub(super) fn substitute(text: &str, context: &Context) -> Result<String> {
+	let mut out = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some((before, after)) = rest.split_once("{{") {
+		out.push_str(before);
+		let Some((name, after)) = after.split_once("}}") else {
+			out.push_str("{{");
+			rest = after;
+			break;
+		};
+
+		let name = name.trim();
+		let value = context
+			.get(name)
+			.ok_or_else(|| err!("No value for placeholder {name} of email template"))?;
+
+		out.push_str(value);
+		rest = after;
+	}
+
+	out.push_str(rest);
+
+	Ok(out)
+}
@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use super::{
+	FIRST_RETRY, LAST_RETRY,
+	limit::{Limiter, domain},
+	retry_after,
+	templates::{Context, Templates, substitute},
+};
+
+fn context(pairs: &[(&str, &str)]) -> Context {
+	pairs
+		.iter()
+		.map(|&(name, value)| (name.to_owned(), value.to_owned()))
+		.collect()
+}
+
+#[test]
+fn substitute_placeholders() {
+	let context = context(&[("server_name", "example.com"), ("link", "https://x")]);
+
+	let text = substitute("Hi from {{server_name}}: {{ link }}", &context).expect("rendered");
+	assert_eq!(text, "Hi from example.com: https://x");
+
+	let text = substitute("no placeholders {{ unclosed", &context).expect("rendered");
+	assert_eq!(text, "no placeholders {{ unclosed");
+
+	substitute("{{missing}}", &context).expect_err("placeholder without value");
+}
+
+#[test]
+fn builtin_templates_render() {
+	let templates = Templates::load(None).expect("builtin templates");
+	let context = context(&[
+		("server_name", "example.com"),
+		("link", "https://example.com/reset"),
+		("subject", "Disk full"),
+		("message", "The database disk is full."),
+		("user_id", "@alice:example.com"),
+		("expires", "2026-01-01 00:00:00 UTC"),
+	]);
+
+	for name in [
+		"test",
+		"password_reset",
+		"threepid_validation",
+		"account_renewal",
+		"admin_alert",
+	] {
+		let email = templates.render(name, &context).expect("renders");
+		assert!(!email.subject.contains("{{"), "{name} subject fully rendered");
+		assert!(!email.body.contains("{{"), "{name} body fully rendered");
+	}
+
+	let reset = templates
+		.render("password_reset", &context)
+		.expect("renders");
+	assert!(reset.body.contains("https://example.com/reset"));
+
+	templates
+		.render("unknown", &context)
+		.expect_err("unknown template");
+}
+
+#[test]
+fn limit_per_domain() {
+	let now = Instant::now();
+	let mut limiter = Limiter::new(2);
+
+	assert_eq!(limiter.acquire("example.com", now), Ok(()));
+	assert_eq!(limiter.acquire("EXAMPLE.com", now), Ok(()));
+	let next = limiter
+		.acquire("example.com", now)
+		.expect_err("limit reached");
+
+	assert!(next > now);
+	assert_eq!(limiter.acquire("example.org", now), Ok(()), "other domains are not limited");
+	assert_eq!(limiter.acquire("example.com", next), Ok(()), "limit resets after a window");
+
+	let later = next.checked_add(Duration::from_secs(120)).expect("instant");
+	limiter.prune(later);
+	assert_eq!(limiter.len(), 0);
+
+	let mut unlimited = Limiter::new(0);
+	for _ in 0..100 {
+		assert_eq!(unlimited.acquire("example.com", now), Ok(()));
+	}
+}
+
+#[test]
+fn recipient_domain() {
+	assert_eq!(domain("alice@example.com"), Some("example.com"));
+	assert_eq!(domain("\"a@b\"@example.com"), Some("example.com"));
+	assert_eq!(domain("@example.com"), None);
+	assert_eq!(domain("alice@"), None);
+	assert_eq!(domain("alice"), None);
+}
+
+#[test]
+fn retry_backoff() {
+	assert_eq!(retry_after(1), FIRST_RETRY);
+	assert_eq!(retry_after(2), FIRST_RETRY.saturating_mul(2));
+	assert_eq!(retry_after(3), FIRST_RETRY.saturating_mul(4));
+	assert_eq!(retry_after(20), LAST_RETRY);
+	assert_eq!(retry_after(u32::MAX), LAST_RETRY);
+}
@@ -0,0 +1,120 @@
+#[cfg(feature = "email")]
+use std::time::Duration;
+
+use conduwuit::{Result, config::EmailConfig};
+#[cfg(feature = "email")]
+use conduwuit::{config::SmtpTls, err};
+#[cfg(feature = "email")]
+use lettre::{
+	AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+	message::{Mailbox, header::ContentType},
+	transport::smtp::{
+		PoolConfig,
+		authentication::Credentials,
+		client::{Tls, TlsParameters},
+	},
+};
+
+use super::templates::Email;
+
+/// Why an email could not be sent.
+#[derive(Debug)]
+pub(super) enum SendError {
+	/// Worth trying again later, such as a lost connection.
+	Transient(String),
+	/// Refused by the server, or the email itself is invalid.
+	Permanent(String),
+}
+
+/// Connections to the SMTP server, pooled for reuse.
+#[cfg(feature = "email")]
+pub(super) struct Transport {
+	smtp: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+}
+
+/// Uninhabited; email support was not compiled in.
+#[cfg(not(feature = "email"))]
+pub(super) enum Transport {}
+
+#[cfg(feature = "email")]
+impl Transport {
+	/// Builds the transport from the configuration, or None when email is not
+	/// configured. Connections are only made once email is sent.
+	pub(super) fn new(config: &EmailConfig) -> Result<Option<Self>> {
+		let Some(host) = config.smtp_host.as_deref() else {
+			return Ok(None);
+		};
+
+		let from = config
+			.from
+			.as_deref()
+			.unwrap_or_default()
+			.parse()
+			.map_err(|e| err!(Config("email.from", "Invalid sender mailbox: {e}")))?;
+
+		let tls = |host: &str| {
+			TlsParameters::new(host.to_owned())
+				.map_err(|e| err!(Config("email.smtp_tls", "Failed to set up TLS: {e}")))
+		};
+
+		let builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host);
+		let (builder, port) = match config.smtp_tls {
+			| SmtpTls::Tls => (builder.tls(Tls::Wrapper(tls(host)?)), 465),
+			| SmtpTls::Starttls => (builder.tls(Tls::Required(tls(host)?)), 587),
+			| SmtpTls::Opportunistic => (builder.tls(Tls::Opportunistic(tls(host)?)), 25),
+			| SmtpTls::Plaintext => (builder.tls(Tls::None), 25),
+		};
+
+		let mut builder = builder
+			.port(config.smtp_port.unwrap_or(port))
+			.timeout(Some(Duration::from_secs(config.smtp_timeout)))
+			.pool_config(PoolConfig::new().max_size(config.smtp_pool_size));
+
+		if let Some(username) = &config.smtp_username {
+			let password = config.smtp_password.clone().unwrap_or_default();
+			builder = builder.credentials(Credentials::new(username.clone(), password));
+		}
+
+		Ok(Some(Self { smtp: builder.build(), from }))
+	}
+
+	pub(super) async fn deliver(&self, recipient: &str, email: &Email) -> Result<(), SendError> {
+		let to: Mailbox = recipient
+			.parse()
+			.map_err(|e| SendError::Permanent(format!("Invalid recipient: {e}")))?;
+
+		let message = Message::builder()
+			.from(self.from.clone())
+			.to(to)
+			.subject(&email.subject)
+			.header(ContentType::TEXT_PLAIN)
+			.body(email.body.clone())
+			.map_err(|e| SendError::Permanent(format!("Invalid email: {e}")))?;
+
+		match self.smtp.send(message).await {
+			| Ok(_) => Ok(()),
+			| Err(e) if e.is_permanent() => Err(SendError::Permanent(e.to_string())),
+			| Err(e) => Err(SendError::Transient(e.to_string())),
+		}
+	}
+}
+
+#[cfg(not(feature = "email"))]
+impl Transport {
+	pub(super) fn new(config: &EmailConfig) -> Result<Option<Self>> {
+		if config.smtp_host.is_some() {
+			conduwuit::warn!("Email is configured but support for it was not compiled in");
+		}
+
+		Ok(None)
+	}
+
+	pub(super) async fn deliver(
+		&self,
+		_recipient: &str,
+		_email: &Email,
+	) -> Result<(), SendError> {
+		match *self {}
+	}
+}
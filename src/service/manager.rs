@@ -1,4 +1,12 @@
-use std::{panic::AssertUnwindSafe, sync::Arc, time::Duration};
+use std::{
+	collections::BTreeSet,
+	panic::AssertUnwindSafe,
+	sync::{
+		Arc,
+		atomic::{AtomicBool, Ordering},
+	},
+	time::Duration,
+};
 
 use conduwuit::{Err, Error, Result, Server, debug, debug_warn, error, trace, utils::time, warn};
 use futures::{FutureExt, TryFutureExt};
@@ -15,6 +23,12 @@ pub(crate) struct Manager {
 	workers: Mutex<Workers>,
 	server: Arc<Server>,
 	service: Arc<service::Map>,
+
+	/// Services whose worker panicked and is waiting to be restarted.
+	restarting: std::sync::Mutex<BTreeSet<String>>,
+
+	/// Set once the manager no longer supervises the workers.
+	stopped: AtomicBool,
 }
 
 type Workers = JoinSet<WorkerResult>;
@@ -30,6 +44,8 @@ pub(super) fn new(services: &Services) -> Arc<Self> {
 			workers: Mutex::new(JoinSet::new()),
 			server: services.server.clone(),
 			service: services.service.clone(),
+			restarting: std::sync::Mutex::new(BTreeSet::new()),
+			stopped: AtomicBool::new(false),
 		})
 	}
 
@@ -47,11 +63,15 @@ pub(super) async fn start(self: Arc<Self>) -> Result<()> {
 
 		debug!("Starting service manager...");
 		let self_ = self.clone();
-		_ = self.manager.lock().await.insert(
-			self.server
-				.runtime()
-				.spawn(async move { self_.worker().await }),
-		);
+		_ = self
+			.manager
+			.lock()
+			.await
+			.insert(self.server.runtime().spawn(async move {
+				let result = self_.worker().await;
+				self_.stopped.store(true, Ordering::Release);
+				result
+			}));
 
 		// we can't hold the lock during the iteration with start_worker so the values
 		// are snapshotted here
@@ -81,6 +101,20 @@ pub(super) async fn stop(&self) {
 		}
 	}
 
+	/// Names of the services whose worker is waiting to be restarted.
+	pub(super) fn restarting(&self) -> Vec<String> {
+		self.restarting
+			.lock()
+			.expect("locked")
+			.iter()
+			.cloned()
+			.collect()
+	}
+
+	/// Whether the manager has stopped supervising the workers, i.e. during
+	/// shutdown or after a worker failed with an error.
+	pub(super) fn is_stopped(&self) -> bool { self.stopped.load(Ordering::Acquire) }
+
 	async fn worker(&self) -> Result<()> {
 		loop {
 			let mut workers = self.workers.lock().await;
@@ -143,6 +177,11 @@ async fn handle_error(
 
 		let delay = Duration::from_millis(RESTART_DELAY_MS);
 		warn!("service {name:?} worker restarting after {} delay", time::pretty(delay));
+		self.restarting
+			.lock()
+			.expect("locked")
+			.insert(name.to_owned());
+
 		sleep(delay).await;
 
 		self.start_worker(workers, service).await
@@ -163,6 +202,10 @@ async fn start_worker(
 
 		debug!("Service {:?} worker starting...", service.name());
 		workers.spawn_on(worker(service.clone()), self.server.runtime());
+		self.restarting
+			.lock()
+			.expect("locked")
+			.remove(service.name());
 
 		Ok(())
 	}
@@ -4,16 +4,18 @@
 	Err, Result, debug, debug_info, err,
 	utils::{ReadyExt, str_from_bytes, stream::TryIgnore, string_from_bytes},
 };
-use database::{Database, Interfix, Map};
-use futures::StreamExt;
-use ruma::{Mxc, OwnedMxcUri, UserId, http_headers::ContentDisposition};
+use database::{Database, Deserialized, Interfix, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{Mxc, OwnedMxcUri, OwnedUserId, UserId, http_headers::ContentDisposition};
 
-use super::{preview::UrlPreviewData, thumbnail::Dim};
+use super::{info::MediaInfo, preview::UrlPreviewData, thumbnail::Dim};
 
 pub(crate) struct Data {
 	mediaid_file: Arc<Map>,
+	mediaid_info: Arc<Map>,
 	mediaid_user: Arc<Map>,
 	url_previews: Arc<Map>,
+	userid_mediaid: Arc<Map>,
 }
 
 #[derive(Debug)]
@@ -27,8 +29,10 @@ impl Data {
 	pub(super) fn new(db: &Arc<Database>) -> Self {
 		Self {
 			mediaid_file: db["mediaid_file"].clone(),
+			mediaid_info: db["mediaid_info"].clone(),
 			mediaid_user: db["mediaid_user"].clone(),
 			url_previews: db["url_previews"].clone(),
+			userid_mediaid: db["userid_mediaid"].clone(),
 		}
 	}
 
@@ -55,6 +59,8 @@ pub(super) fn create_file_metadata(
 	pub(super) async fn delete_file_mxc(&self, mxc: &Mxc<'_>) {
 		debug!("MXC URI: {mxc}");
 
+		self.mediaid_info.del(mxc);
+
 		let prefix = (mxc, Interfix);
 		self.mediaid_file
 			.keys_prefix_raw(&prefix)
@@ -75,10 +81,30 @@ pub(super) async fn delete_file_mxc(&self, mxc: &Mxc<'_>) {
 				debug_info!("Deleting key {key:?} which was uploaded by user {user}");
 
 				self.mediaid_user.remove(key);
+				self.userid_mediaid.del((user, mxc));
 			})
 			.await;
 	}
 
+	pub(super) fn set_media_info(&self, mxc: &Mxc<'_>, info: &MediaInfo) {
+		self.mediaid_info.put(mxc, Json(info));
+	}
+
+	pub(super) async fn get_media_info(&self, mxc: &Mxc<'_>) -> Result<MediaInfo> {
+		self.mediaid_info.qry(mxc).await.deserialized()
+	}
+
+	/// Gets the local user who uploaded the file, if any
+	pub(super) async fn get_uploader(&self, mxc: &Mxc<'_>) -> Option<OwnedUserId> {
+		let prefix = (mxc, Interfix);
+		self.mediaid_user
+			.stream_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_filter_map(|(_, user)| UserId::parse(str_from_bytes(user).ok()?).ok())
+			.next()
+			.await
+	}
+
 	/// Searches for all files with the given MXC
 	pub(super) async fn search_mxc_metadata_prefix(&self, mxc: &Mxc<'_>) -> Result<Vec<Vec<u8>>> {
 		debug!("MXC URI: {mxc}");
@@ -141,18 +167,43 @@ pub(super) async fn search_file_metadata(
 		Ok(Metadata { content_disposition, content_type, key })
 	}
 
-	/// Gets all the MXCs associated with a user
-	pub(super) async fn get_all_user_mxcs(&self, user_id: &UserId) -> Vec<OwnedMxcUri> {
-		self.mediaid_user
-			.stream()
+	/// Records that the user uploaded the file at `uploaded_ts`, in
+	/// milliseconds since the unix epoch.
+	pub(super) fn index_upload(&self, user: &UserId, mxc: &Mxc<'_>, uploaded_ts: u64) {
+		self.userid_mediaid.put((user, mxc), uploaded_ts);
+	}
+
+	/// Gets all the MXCs uploaded by a user, with when each was uploaded. A
+	/// time of zero is unknown.
+	pub(super) async fn get_all_user_mxcs(&self, user_id: &UserId) -> Vec<(OwnedMxcUri, u64)> {
+		let prefix = (user_id, Interfix);
+		self.userid_mediaid
+			.stream_prefix(&prefix)
 			.ignore_err()
-			.ready_filter_map(|(key, user): (&str, &UserId)| {
-				(user == user_id).then(|| key.into())
-			})
+			.map(|((_, mxc), uploaded_ts): ((&UserId, &str), u64)| (mxc.into(), uploaded_ts))
 			.collect()
 			.await
 	}
 
+	/// When the user uploaded the file, if recorded.
+	pub(super) async fn get_upload_ts(&self, user_id: &UserId, mxc: &Mxc<'_>) -> Option<u64> {
+		self.userid_mediaid
+			.qry(&(user_id, mxc))
+			.await
+			.deserialized()
+			.ok()
+			.filter(|&uploaded_ts| uploaded_ts > 0)
+	}
+
+	/// The keys of the files uploaded by local users, from the raw key `from`
+	/// on.
+	pub(super) fn upload_keys_from<'a>(
+		&'a self,
+		from: &'a [u8],
+	) -> impl Stream<Item = &'a [u8]> + Send + 'a {
+		self.mediaid_user.raw_keys_from(from).ignore_err()
+	}
+
 	/// Gets all the media keys in our database (this includes all the metadata
 	/// associated with it such as width, height, content-type, etc)
 	pub(crate) async fn get_all_media_keys(&self) -> Vec<Vec<u8>> {
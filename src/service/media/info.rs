@@ -0,0 +1,97 @@
+//! Media Info
+//!
+//! Dimensions, duration and optionally a blurhash of each file, extracted
+//! once when it is stored so that clients need not download it to learn
+//! them. Image dimensions need the 'media_thumbnail' feature and blurhashes
+//! the 'blurhashing' feature; the other properties are read from container
+//! headers by [`super::probe`].
+
+use conduwuit::{debug_warn, implement};
+use ruma::{Mxc, OwnedUserId};
+use serde::{Deserialize, Serialize};
+
+use super::probe;
+
+/// Properties of a file which could be determined when it was stored.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MediaInfo {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub width: Option<u32>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub height: Option<u32>,
+
+	/// Duration of audio or video in milliseconds.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub duration_ms: Option<u64>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub blurhash: Option<String>,
+}
+
+impl MediaInfo {
+	#[must_use]
+	pub fn is_empty(&self) -> bool { *self == Self::default() }
+}
+
+/// Extracts and stores the info of a newly stored file. Nothing here can fail
+/// the upload; whatever cannot be determined is left out.
+#[implement(super::Service)]
+#[tracing::instrument(name = "info", level = "debug", skip(self, file), fields(bytes = file.len()))]
+pub(super) fn create_info(
+	&self,
+	mxc: &Mxc<'_>,
+	content_type: Option<&str>,
+	file_name: Option<&str>,
+	file: &[u8],
+) {
+	let mut info = MediaInfo::default();
+	if let Some((width, height)) = image_dimensions(file) {
+		info.width = Some(width);
+		info.height = Some(height);
+
+		if self.services.server.config.blurhashing.blurhash_on_upload {
+			info.blurhash = self
+				.create_blurhash(file, content_type, file_name)
+				.inspect_err(|e| debug_warn!(%mxc, "Failed to blurhash upload: {e}"))
+				.ok()
+				.flatten();
+		}
+	} else if let Some(probed) = probe::probe(file) {
+		info.width = probed.width;
+		info.height = probed.height;
+		info.duration_ms = probed
+			.duration
+			.and_then(|duration| duration.as_millis().try_into().ok());
+	}
+
+	if !info.is_empty() {
+		self.db.set_media_info(mxc, &info);
+	}
+}
+
+/// Gets the info of a file, which is empty when nothing could be determined
+/// or the file was stored before info was kept.
+#[implement(super::Service)]
+pub async fn get_info(&self, mxc: &Mxc<'_>) -> MediaInfo {
+	self.db.get_media_info(mxc).await.unwrap_or_default()
+}
+
+/// Gets the local user who uploaded a file.
+#[implement(super::Service)]
+pub async fn get_uploader(&self, mxc: &Mxc<'_>) -> Option<OwnedUserId> {
+	self.db.get_uploader(mxc).await
+}
+
+/// Reads the dimensions from the image header without decoding the image.
+#[cfg(feature = "media_thumbnail")]
+fn image_dimensions(file: &[u8]) -> Option<(u32, u32)> {
+	image::ImageReader::new(std::io::Cursor::new(file))
+		.with_guessed_format()
+		.ok()?
+		.into_dimensions()
+		.ok()
+}
+
+#[cfg(not(feature = "media_thumbnail"))]
+fn image_dimensions(_file: &[u8]) -> Option<(u32, u32)> { None }
@@ -1,10 +1,14 @@
 pub mod blurhash;
 mod data;
+mod info;
 pub(super) mod migrations;
 mod preview;
+mod probe;
+mod reindex;
 mod remote;
 mod tests;
 mod thumbnail;
+mod usage;
 use std::{path::PathBuf, sync::Arc, time::SystemTime};
 
 use async_trait::async_trait;
@@ -21,7 +25,12 @@
 };
 
 use self::data::{Data, Metadata};
-pub use self::thumbnail::Dim;
+pub(crate) use self::reindex::{REINDEX_USER_MEDIA, Reindex};
+pub use self::{
+	info::MediaInfo,
+	thumbnail::Dim,
+	usage::{Upload, check_uploader, page},
+};
 use crate::{Dep, client, globals, sending};
 
 #[derive(Debug)]
@@ -100,26 +109,46 @@ pub async fn create(
 		let mut f = self.create_media_file(&key).await?;
 		f.write_all(file).await?;
 
+		let file_name = content_disposition.and_then(|cd| cd.filename.as_deref());
+		self.create_info(mxc, content_type, file_name, file);
+
+		if let Some(user) = user {
+			self.db
+				.index_upload(user, mxc, utils::millis_since_unix_epoch());
+		}
+
 		Ok(())
 	}
 
 	/// Deletes a file in the database and from the media directory via an MXC
 	pub async fn delete(&self, mxc: &Mxc<'_>) -> Result<()> {
+		self.delete_freed(mxc).await.map(|_| ())
+	}
+
+	/// Deletes a file and its thumbnails as [`Self::delete`] does, returning
+	/// how many bytes were freed in the media directory.
+	pub async fn delete_freed(&self, mxc: &Mxc<'_>) -> Result<u64> {
 		match self.db.search_mxc_metadata_prefix(mxc).await {
 			| Ok(keys) => {
+				let mut freed: u64 = 0;
 				for key in keys {
 					trace!(?mxc, "MXC Key: {key:?}");
 					debug_info!(?mxc, "Deleting from filesystem");
 
-					if let Err(e) = self.remove_media_file(&key).await {
-						debug_error!(?mxc, "Failed to remove media file: {e}");
+					let size = fs::metadata(self.get_media_file(&key))
+						.await
+						.map_or(0, |file| file.len());
+
+					match self.remove_media_file(&key).await {
+						| Ok(()) => freed = freed.saturating_add(size),
+						| Err(e) => debug_error!(?mxc, "Failed to remove media file: {e}"),
 					}
 
 					debug_info!(?mxc, "Deleting from database");
 					self.db.delete_file_mxc(mxc).await;
 				}
 
-				Ok(())
+				Ok(freed)
 			},
 			| _ => {
 				Err!(Database(error!(
@@ -133,10 +162,29 @@ pub async fn delete(&self, mxc: &Mxc<'_>) -> Result<()> {
 	///
 	/// currently, this is only practical for local users
 	pub async fn delete_from_user(&self, user: &UserId) -> Result<usize> {
+		self.delete_from_user_before(user, None)
+			.await
+			.map(|(deletion_count, _)| deletion_count)
+	}
+
+	/// Deletes the media the user uploaded before `before`, in milliseconds
+	/// since the unix epoch, or all of it. Media of which the upload time is
+	/// unknown is only deleted with all of it. Returns how many files were
+	/// deleted and the bytes freed.
+	pub async fn delete_from_user_before(
+		&self,
+		user: &UserId,
+		before: Option<u64>,
+	) -> Result<(usize, u64)> {
 		let mxcs = self.db.get_all_user_mxcs(user).await;
 		let mut deletion_count: usize = 0;
+		let mut freed: u64 = 0;
+
+		for (mxc, uploaded_ts) in mxcs {
+			if before.is_some_and(|before| uploaded_ts == 0 || uploaded_ts >= before) {
+				continue;
+			}
 
-		for mxc in mxcs {
 			let Ok(mxc) = mxc.as_str().try_into().inspect_err(|e| {
 				debug_error!(?mxc, "Failed to parse MXC URI from database: {e}");
 			}) else {
@@ -144,9 +192,10 @@ pub async fn delete_from_user(&self, user: &UserId) -> Result<usize> {
 			};
 
 			debug_info!(%deletion_count, "Deleting MXC {mxc} by user {user} from database and filesystem");
-			match self.delete(&mxc).await {
-				| Ok(()) => {
+			match self.delete_freed(&mxc).await {
+				| Ok(bytes) => {
 					deletion_count = deletion_count.saturating_add(1);
+					freed = freed.saturating_add(bytes);
 				},
 				| Err(e) => {
 					debug_error!(%deletion_count, "Failed to delete {mxc} from user {user}, ignoring error: {e}");
@@ -154,7 +203,7 @@ pub async fn delete_from_user(&self, user: &UserId) -> Result<usize> {
 			}
 		}
 
-		Ok(deletion_count)
+		Ok((deletion_count, freed))
 	}
 
 	/// Downloads a file.
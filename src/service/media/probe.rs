@@ -0,0 +1,337 @@
+//! Media Container Probing
+//!
+//! Reads the dimensions and duration of audio and video from the headers of
+//! MP4, Matroska/WebM and Ogg containers. Only the structure needed to find
+//! those few fields is parsed; streams are neither decoded nor validated.
+
+use std::time::Duration;
+
+/// Properties of an audio or video file read from its container.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(super) struct Probe {
+	pub(super) width: Option<u32>,
+	pub(super) height: Option<u32>,
+	pub(super) duration: Option<Duration>,
+}
+
+/// Probes a file for a supported container, returning None for anything else
+/// or a container too malformed to read.
+pub(super) fn probe(data: &[u8]) -> Option<Probe> {
+	if data.get(4..8) == Some(b"ftyp") {
+		mp4(data)
+	} else if data.starts_with(&EBML_HEADER) {
+		matroska(data)
+	} else if data.starts_with(OGG_PAGE) {
+		ogg(data)
+	} else {
+		None
+	}
+}
+
+/// Reads the `mvhd` for the duration and the first `tkhd` with a size for
+/// the dimensions.
+fn mp4(data: &[u8]) -> Option<Probe> {
+	let moov = boxes(data).find(|(kind, _)| kind == b"moov")?.1;
+
+	let mut probe = Probe::default();
+	for (kind, body) in boxes(moov) {
+		match &kind {
+			| b"mvhd" => probe.duration = mvhd_duration(body),
+			| b"trak" if probe.width.is_none() => {
+				let Some((_, tkhd)) = boxes(body).find(|(kind, _)| kind == b"tkhd") else {
+					continue;
+				};
+
+				if let Some((width, height)) = tkhd_dimensions(tkhd) {
+					probe.width = Some(width);
+					probe.height = Some(height);
+				}
+			},
+			| _ => (),
+		}
+	}
+
+	Some(probe)
+}
+
+/// Iterates the ISO BMFF boxes directly within `data` as their type and body.
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+	std::iter::from_fn(move || {
+		let size = u64::from(be_u32(data, 0)?);
+		let kind: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+		let (header, size) = match size {
+			| 0 => (8, u64::try_from(data.len()).ok()?),
+			| 1 => (16, be_u64(data, 8)?),
+			| size => (8, size),
+		};
+
+		let size = usize::try_from(size).ok()?;
+		let body = data.get(header..size)?;
+		data = data.get(size..)?;
+
+		Some((kind, body))
+	})
+}
+
+fn mvhd_duration(body: &[u8]) -> Option<Duration> {
+	let (timescale, duration) = match body.first()? {
+		| 0 => (be_u32(body, 12)?, u64::from(be_u32(body, 16)?)),
+		| 1 => (be_u32(body, 20)?, be_u64(body, 24)?),
+		| _ => return None,
+	};
+
+	// All ones marks an unknown duration.
+	if timescale == 0 || duration == u64::from(u32::MAX) || duration == u64::MAX {
+		return None;
+	}
+
+	let millis = u128::from(duration)
+		.saturating_mul(1000)
+		.checked_div(u128::from(timescale))?;
+
+	Some(Duration::from_millis(u64::try_from(millis).ok()?))
+}
+
+fn tkhd_dimensions(body: &[u8]) -> Option<(u32, u32)> {
+	// Width and height follow the matrix as 16.16 fixed point.
+	let offset = match body.first()? {
+		| 0 => 76,
+		| 1 => 88,
+		| _ => return None,
+	};
+
+	let width = u32::from(be_u16(body, offset)?);
+	let height = u32::from(be_u16(body, offset.saturating_add(4))?);
+
+	(width > 0 && height > 0).then_some((width, height))
+}
+
+const EBML_HEADER: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3];
+const MKV_SEGMENT: u32 = 0x1853_8067;
+const MKV_INFO: u32 = 0x1549_A966;
+const MKV_TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const MKV_DURATION: u32 = 0x4489;
+const MKV_TRACKS: u32 = 0x1654_AE6B;
+const MKV_TRACK_ENTRY: u32 = 0xAE;
+const MKV_VIDEO: u32 = 0xE0;
+const MKV_PIXEL_WIDTH: u32 = 0xB0;
+const MKV_PIXEL_HEIGHT: u32 = 0xBA;
+
+/// Default `TimestampScale`: one millisecond in nanoseconds.
+const MKV_DEFAULT_SCALE: u64 = 1_000_000;
+
+/// Reads the `Info` element for the duration and the first video track for
+/// the dimensions.
+fn matroska(data: &[u8]) -> Option<Probe> {
+	let segment = elements(data).find(|(id, _)| *id == MKV_SEGMENT)?.1;
+
+	let mut probe = Probe::default();
+	for (id, body) in elements(segment) {
+		match id {
+			| MKV_INFO => probe.duration = matroska_duration(body),
+			| MKV_TRACKS if probe.width.is_none() => {
+				let video = elements(body)
+					.filter(|(id, _)| *id == MKV_TRACK_ENTRY)
+					.find_map(|(_, entry)| elements(entry).find(|(id, _)| *id == MKV_VIDEO));
+
+				if let Some((_, video)) = video {
+					for (id, body) in elements(video) {
+						match id {
+							| MKV_PIXEL_WIDTH => probe.width = ebml_uint(body),
+							| MKV_PIXEL_HEIGHT => probe.height = ebml_uint(body),
+							| _ => (),
+						}
+					}
+				}
+			},
+			| _ => (),
+		}
+
+		// The clusters holding the media follow; stop once both are known.
+		if probe.duration.is_some() && probe.width.is_some() {
+			break;
+		}
+	}
+
+	Some(probe)
+}
+
+#[allow(
+	clippy::as_conversions,
+	clippy::cast_possible_truncation,
+	clippy::cast_precision_loss,
+	clippy::cast_sign_loss
+)]
+fn matroska_duration(info: &[u8]) -> Option<Duration> {
+	let mut scale = MKV_DEFAULT_SCALE;
+	let mut duration = None;
+	for (id, body) in elements(info) {
+		match id {
+			| MKV_TIMESTAMP_SCALE => scale = ebml_uint(body)?.into(),
+			| MKV_DURATION => duration = ebml_float(body),
+			| _ => (),
+		}
+	}
+
+	let nanos = duration? * scale as f64;
+	(nanos.is_finite() && nanos >= 0.0).then(|| Duration::from_nanos(nanos as u64))
+}
+
+/// Iterates the EBML elements directly within `data` as their ID and body.
+/// An element of unknown size extends to the end of `data`.
+fn elements(mut data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+	std::iter::from_fn(move || {
+		let id_len = vint_len(data, 4)?;
+		let id = be_uint(data.get(..id_len)?)?;
+
+		let size = data.get(id_len..)?;
+		let size_len = vint_len(size, 8)?;
+		let size = size.get(..size_len)?;
+		let rest = data.get(id_len.saturating_add(size_len)..)?;
+
+		// The length marker is kept in IDs but not in sizes; a size of all ones
+		// after the marker is unknown.
+		let marker = 0x80_u8.checked_shr(u32::try_from(size_len).ok()?.saturating_sub(1))?;
+		let first = size.first()? & !marker;
+		let size = if first == marker.saturating_sub(1) && size[1..].iter().all(|&b| b == 0xFF) {
+			rest.len()
+		} else {
+			let mut value = size.to_vec();
+			*value.first_mut()? = first;
+			usize::try_from(be_uint(&value)?).ok()?.min(rest.len())
+		};
+
+		let body = rest.get(..size)?;
+		data = rest.get(size..)?;
+
+		Some((u32::try_from(id).ok()?, body))
+	})
+}
+
+/// Length in bytes of the EBML variable-length integer at the start of
+/// `data`, from the position of its marker bit.
+fn vint_len(data: &[u8], max: usize) -> Option<usize> {
+	let len = usize::try_from(data.first()?.leading_zeros())
+		.ok()?
+		.saturating_add(1);
+
+	(len <= max).then_some(len)
+}
+
+/// Big-endian unsigned integer of up to eight bytes.
+fn be_uint(bytes: &[u8]) -> Option<u64> {
+	let mut buf = [0_u8; 8];
+	buf.get_mut(8_usize.checked_sub(bytes.len())?..)?
+		.copy_from_slice(bytes);
+
+	Some(u64::from_be_bytes(buf))
+}
+
+fn ebml_uint(body: &[u8]) -> Option<u32> {
+	if body.is_empty() {
+		return None;
+	}
+
+	u32::try_from(be_uint(body)?).ok()
+}
+
+fn ebml_float(body: &[u8]) -> Option<f64> {
+	match body.len() {
+		| 4 => Some(f32::from_be_bytes(body.try_into().ok()?).into()),
+		| 8 => Some(f64::from_be_bytes(body.try_into().ok()?)),
+		| _ => None,
+	}
+}
+
+const OGG_PAGE: &[u8] = b"OggS";
+
+/// Opus granule positions always count 48kHz samples.
+const OPUS_RATE: u64 = 48_000;
+
+/// Reads the sample rate from the identification header of the first stream
+/// and the duration from the granule position of its last page. Only Opus and
+/// Vorbis streams are recognised.
+fn ogg(data: &[u8]) -> Option<Probe> {
+	let (serial, _, packet) = ogg_page(data)?;
+	let (rate, pre_skip) = if packet.starts_with(b"OpusHead") {
+		(OPUS_RATE, u64::from(le_u16(packet, 10)?))
+	} else if packet.starts_with(b"\x01vorbis") {
+		(u64::from(le_u32(packet, 12)?), 0)
+	} else {
+		return Some(Probe::default());
+	};
+
+	let granule = ogg_last_granule(data, serial)?;
+	let samples = granule.checked_sub(pre_skip)?;
+	let millis = samples.saturating_mul(1000).checked_div(rate)?;
+
+	Some(Probe {
+		duration: Some(Duration::from_millis(millis)),
+		..Probe::default()
+	})
+}
+
+/// Parses the Ogg page at the start of `data` into its stream serial number,
+/// granule position and the body of its first packet.
+fn ogg_page(data: &[u8]) -> Option<(u32, u64, &[u8])> {
+	if !data.starts_with(OGG_PAGE) {
+		return None;
+	}
+
+	let granule = le_u64(data, 6)?;
+	let serial = le_u32(data, 14)?;
+	let segments = usize::from(*data.get(26)?);
+	let table = data.get(27..27_usize.saturating_add(segments))?;
+	let body = data.get(27_usize.saturating_add(segments)..)?;
+
+	// A packet ends at the first lacing value short of 255.
+	let first_len = table
+		.iter()
+		.position(|&lace| lace < 255)
+		.map_or(table.len(), |end| end.saturating_add(1));
+
+	let packet_len = table
+		.get(..first_len)?
+		.iter()
+		.map(|&lace| usize::from(lace))
+		.sum::<usize>()
+		.min(body.len());
+
+	Some((serial, granule, body.get(..packet_len)?))
+}
+
+/// Granule position of the last complete page of the stream.
+fn ogg_last_granule(data: &[u8], serial: u32) -> Option<u64> {
+	data.windows(OGG_PAGE.len())
+		.enumerate()
+		.rev()
+		.filter(|(_, window)| *window == OGG_PAGE)
+		.filter_map(|(offset, _)| ogg_page(data.get(offset..)?))
+		// -1 marks a page on which no packet ends.
+		.find(|&(page_serial, granule, _)| page_serial == serial && granule != u64::MAX)
+		.map(|(_, granule, _)| granule)
+}
+
+fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+	Some(u16::from_be_bytes(data.get(at..at.checked_add(2)?)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+	Some(u32::from_be_bytes(data.get(at..at.checked_add(4)?)?.try_into().ok()?))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+	Some(u64::from_be_bytes(data.get(at..at.checked_add(8)?)?.try_into().ok()?))
+}
+
+fn le_u16(data: &[u8], at: usize) -> Option<u16> {
+	Some(u16::from_le_bytes(data.get(at..at.checked_add(2)?)?.try_into().ok()?))
+}
+
+fn le_u32(data: &[u8], at: usize) -> Option<u32> {
+	Some(u32::from_le_bytes(data.get(at..at.checked_add(4)?)?.try_into().ok()?))
+}
+
+fn le_u64(data: &[u8], at: usize) -> Option<u64> {
+	Some(u64::from_le_bytes(data.get(at..at.checked_add(8)?)?.try_into().ok()?))
+}
@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use conduwuit::{Result, debug_warn, utils::str_from_bytes};
+use database::SEP;
+use futures::{StreamExt, stream::BoxStream};
+use ruma::{Mxc, UserId};
+
+use super::{Dim, data::Metadata, usage::modified_ts};
+use crate::{Dep, reindex::Task};
+
+/// Indexes by user the files local users uploaded before uploads were.
+pub(crate) struct Reindex {
+	media: Dep<super::Service>,
+}
+
+/// Name of the task indexing uploads by user.
+pub(crate) const REINDEX_USER_MEDIA: &str = "user_media";
+
+impl Reindex {
+	pub(crate) fn new(args: &crate::Args<'_>) -> Self {
+		Self {
+			media: args.depend::<super::Service>("media"),
+		}
+	}
+}
+
+#[async_trait]
+impl Task for Reindex {
+	fn name(&self) -> &'static str { REINDEX_USER_MEDIA }
+
+	fn keys<'a>(&'a self, from: &'a [u8]) -> BoxStream<'a, &'a [u8]> {
+		self.media.db.upload_keys_from(from).boxed()
+	}
+
+	async fn process(&self, keys: &[Vec<u8>]) -> Result {
+		for key in keys {
+			let Some((mxc, user)) = parse_upload_key(key) else {
+				debug_warn!(?key, "Invalid key of an uploaded file");
+				continue;
+			};
+
+			self.media.index_stored_upload(user, &mxc).await;
+		}
+
+		Ok(())
+	}
+}
+
+impl super::Service {
+	/// Indexes a file uploaded before uploads were indexed by user, dated by
+	/// when the media directory last saw it modified.
+	async fn index_stored_upload(&self, user: &UserId, mxc: &Mxc<'_>) {
+		let uploaded_ts = match self.db.search_file_metadata(mxc, &Dim::default()).await {
+			| Ok(Metadata { key, .. }) => tokio::fs::metadata(self.get_media_file(&key))
+				.await
+				.ok()
+				.as_ref()
+				.and_then(modified_ts),
+			| Err(_) => None,
+		};
+
+		self.db.index_upload(user, mxc, uploaded_ts.unwrap_or(0));
+	}
+}
+
+/// The file and its uploader in a key of the files uploaded by local users.
+pub(super) fn parse_upload_key(key: &[u8]) -> Option<(Mxc<'_>, &UserId)> {
+	let mut parts = key.split(|&byte| byte == SEP);
+	let mxc = str_from_bytes(parts.next()?).ok()?;
+	let user = str_from_bytes(parts.next()?).ok()?;
+
+	Some((mxc.try_into().ok()?, user.try_into().ok()?))
+}
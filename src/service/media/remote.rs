@@ -427,12 +427,11 @@ fn check_fetch_authorized(&self, mxc: &Mxc<'_>) -> Result<()> {
 		.config
 		.prevent_media_downloads_from
 		.is_match(mxc.server_name.host())
-		|| self
+		|| !self
 			.services
 			.server
 			.config
-			.forbidden_remote_server_names
-			.is_match(mxc.server_name.host())
+			.is_remote_server_allowed(mxc.server_name)
 	{
 		// we'll lie to the client and say the blocked server's media was not found and
 		// log. the client has no way of telling anyways so this is a security bonus.
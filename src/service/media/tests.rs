@@ -103,3 +103,179 @@ fn get_url_preview(&self, _url: &str) -> Option<UrlPreviewData> { todo!() }
 		r.to_str().unwrap().len()
 	);
 }
+
+fn mp4_box(kind: [u8; 4], body: &[u8]) -> Vec<u8> {
+	let size = u32::try_from(body.len().saturating_add(8)).expect("small box");
+	[&size.to_be_bytes()[..], &kind, body].concat()
+}
+
+fn ogg_page(serial: u32, granule: u64, packet: &[u8]) -> Vec<u8> {
+	let len = u8::try_from(packet.len()).expect("single segment packet");
+	[
+		b"OggS\0\0".as_slice(),
+		&granule.to_le_bytes(),
+		&serial.to_le_bytes(),
+		&[0; 8],
+		&[1, len],
+		packet,
+	]
+	.concat()
+}
+
+#[test]
+fn probe_mp4() {
+	use std::time::Duration;
+
+	use super::probe::{Probe, probe};
+
+	// mvhd v0: version and flags, two times, timescale, duration.
+	let mvhd = [&[0_u8; 12][..], &1000_u32.to_be_bytes(), &2500_u32.to_be_bytes()].concat();
+
+	// tkhd v0: 76 bytes up to the 16.16 width and height.
+	let tkhd = [&[0_u8; 76][..], &(640_u32 << 16).to_be_bytes(), &(360_u32 << 16).to_be_bytes()]
+		.concat();
+
+	let moov = [mp4_box(*b"mvhd", &mvhd), mp4_box(*b"trak", &mp4_box(*b"tkhd", &tkhd))].concat();
+	let file = [
+		mp4_box(*b"ftyp", b"isom"),
+		mp4_box(*b"mdat", &[0; 32]),
+		mp4_box(*b"moov", &moov),
+	]
+	.concat();
+
+	assert_eq!(
+		probe(&file),
+		Some(Probe {
+			width: Some(640),
+			height: Some(360),
+			duration: Some(Duration::from_millis(2500)),
+		})
+	);
+}
+
+#[test]
+fn probe_matroska() {
+	use std::time::Duration;
+
+	use super::probe::{Probe, probe};
+
+	let info = [
+		&[0x2A, 0xD7, 0xB1, 0x83, 0x0F, 0x42, 0x40][..],
+		&[0x44, 0x89, 0x88],
+		&1500.0_f64.to_be_bytes(),
+	]
+	.concat();
+
+	let video = [0xB0, 0x82, 0x01, 0x40, 0xBA, 0x81, 0xF0];
+	let entry = [&[0xE0, 0x87][..], &video].concat();
+	let tracks = [&[0xAE, 0x89][..], &entry].concat();
+
+	let segment = [
+		&[0x15, 0x49, 0xA9, 0x66, 0x92][..],
+		&info,
+		&[0x16, 0x54, 0xAE, 0x6B, 0x8B],
+		&tracks,
+	]
+	.concat();
+
+	// The segment is of unknown size, as when streamed.
+	let file = [
+		&[0x1A, 0x45, 0xDF, 0xA3, 0x84, 0x42, 0x82, 0x81, 0x77][..],
+		&[0x18, 0x53, 0x80, 0x67, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+		&segment,
+	]
+	.concat();
+
+	assert_eq!(
+		probe(&file),
+		Some(Probe {
+			width: Some(320),
+			height: Some(240),
+			duration: Some(Duration::from_millis(1500)),
+		})
+	);
+}
+
+#[test]
+fn probe_ogg_opus() {
+	use std::time::Duration;
+
+	use super::probe::probe;
+
+	let head = [b"OpusHead".as_slice(), &[1, 2], &312_u16.to_le_bytes(), &[0; 7]].concat();
+	let file = [
+		ogg_page(7, 0, &head),
+		ogg_page(7, 48_000, &[0; 16]),
+		ogg_page(9, 96_000 * 10, &[0; 16]),
+		ogg_page(7, 48_000 * 3 + 312, &[0; 16]),
+		ogg_page(7, u64::MAX, &[0; 16]),
+	]
+	.concat();
+
+	let probed = probe(&file).expect("ogg recognised");
+	assert_eq!(probed.duration, Some(Duration::from_secs(3)), "last complete page of stream");
+	assert_eq!((probed.width, probed.height), (None, None));
+}
+
+#[test]
+fn probe_unrecognised() {
+	use super::probe::probe;
+
+	assert_eq!(probe(b""), None);
+	assert_eq!(probe(b"GIF89a not a container"), None);
+
+	// Truncated boxes are not read past.
+	let file = [mp4_box(*b"ftyp", b"isom"), b"\0\0\x10\0moov".to_vec()].concat();
+	assert_eq!(probe(&file), None);
+}
+
+#[test]
+fn delete_upload_of_someone_else() {
+	use ruma::user_id;
+
+	use super::check_uploader;
+
+	let alice = user_id!("@alice:example.com");
+	let bob = user_id!("@bob:example.com");
+
+	assert!(check_uploader(alice, Some(alice)).is_ok());
+
+	let error = check_uploader(alice, Some(bob)).expect_err("not hers");
+	assert_eq!(error.status_code(), http::StatusCode::FORBIDDEN);
+
+	let error = check_uploader(alice, None).expect_err("uploaded by nobody local");
+	assert_eq!(error.status_code(), http::StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn uploads_paginated() {
+	use super::page;
+
+	let uploads: Vec<usize> = (0..105).collect();
+	let mut from = 0;
+	let mut seen = Vec::new();
+	let mut pages = 0_usize;
+	loop {
+		let (items, next) = page(uploads.clone(), from, 20);
+		assert!(items.len() <= 20);
+		seen.extend(items);
+		pages = pages.saturating_add(1);
+
+		let Some(next) = next else {
+			break;
+		};
+
+		from = next;
+	}
+
+	assert_eq!(pages, 6);
+	assert_eq!(seen, uploads, "every upload listed once, in order");
+
+	let (items, next) = page(uploads.clone(), 100, 20);
+	assert_eq!(items, [100, 101, 102, 103, 104]);
+	assert_eq!(next, None);
+
+	let (items, next) = page(uploads, 200, 20);
+	assert!(items.is_empty());
+	assert_eq!(next, None);
+}
@@ -0,0 +1,139 @@
+//! Uploads of a user
+//!
+//! Lists the files a local user uploaded, as recorded at upload in
+//! `userid_mediaid`, and lets them delete their own.
+
+use std::time::UNIX_EPOCH;
+
+use conduwuit::{Err, Result, debug_warn, implement};
+use ruma::{Mxc, OwnedMxcUri, UserId};
+use tokio::fs;
+
+use super::{Dim, data::Metadata};
+
+/// A file uploaded by a user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Upload {
+	pub mxc: OwnedMxcUri,
+
+	/// Size of the file in bytes.
+	pub size: u64,
+
+	pub content_type: Option<String>,
+
+	/// When the file was stored, in milliseconds since the unix epoch, as far
+	/// as the media directory tells.
+	pub uploaded_ts: Option<u64>,
+}
+
+/// Lists the files the user uploaded, most recently uploaded first.
+#[implement(super::Service)]
+pub async fn get_uploads(&self, user_id: &UserId) -> Vec<Upload> {
+	let mut uploads = Vec::new();
+	for (mxc, uploaded_ts) in self.db.get_all_user_mxcs(user_id).await {
+		let Ok(parsed) = <Mxc<'_>>::try_from(mxc.as_str()) else {
+			debug_warn!(%mxc, "Invalid MXC URI uploaded by {user_id}");
+			continue;
+		};
+
+		let uploaded_ts = (uploaded_ts > 0).then_some(uploaded_ts);
+		if let Some(upload) = self.describe_upload(&parsed, uploaded_ts).await {
+			uploads.push(upload);
+		}
+	}
+
+	uploads.sort_by(|a, b| {
+		b.uploaded_ts
+			.cmp(&a.uploaded_ts)
+			.then_with(|| a.mxc.cmp(&b.mxc))
+	});
+
+	uploads
+}
+
+/// Describes a stored file, whoever uploaded it.
+#[implement(super::Service)]
+pub async fn get_upload(&self, mxc: &Mxc<'_>) -> Option<Upload> {
+	let uploaded_ts = match self.db.get_uploader(mxc).await {
+		| Some(uploader) => self.db.get_upload_ts(&uploader, mxc).await,
+		| None => None,
+	};
+
+	self.describe_upload(mxc, uploaded_ts).await
+}
+
+/// Describes a stored file; one of which the upload time was not recorded is
+/// dated by when the media directory last saw it modified.
+#[implement(super::Service)]
+async fn describe_upload(&self, mxc: &Mxc<'_>, uploaded_ts: Option<u64>) -> Option<Upload> {
+	let Metadata { content_type, key, .. } = self
+		.db
+		.search_file_metadata(mxc, &Dim::default())
+		.await
+		.ok()?;
+
+	let file = fs::metadata(self.get_media_file(&key)).await.ok();
+	let uploaded_ts = uploaded_ts.or_else(|| file.as_ref().and_then(modified_ts));
+
+	Some(Upload {
+		mxc: mxc.to_string().into(),
+		size: file.map_or(0, |file| file.len()),
+		content_type,
+		uploaded_ts,
+	})
+}
+
+/// Size in bytes of a stored file, without its thumbnails.
+#[implement(super::Service)]
+pub async fn get_size(&self, mxc: &Mxc<'_>) -> Option<u64> {
+	let Metadata { key, .. } = self
+		.db
+		.search_file_metadata(mxc, &Dim::default())
+		.await
+		.ok()?;
+
+	fs::metadata(self.get_media_file(&key))
+		.await
+		.ok()
+		.map(|file| file.len())
+}
+
+/// Deletes a file the user uploaded, with its thumbnails, as an admin deleting
+/// it would.
+#[implement(super::Service)]
+pub async fn delete_upload(&self, user_id: &UserId, mxc: &Mxc<'_>) -> Result {
+	if self.get_metadata(mxc).await.is_none() {
+		return Err!(Request(NotFound("Media not found.")));
+	}
+
+	check_uploader(user_id, self.get_uploader(mxc).await.as_deref())?;
+	self.delete(mxc).await
+}
+
+/// Fails with M_FORBIDDEN unless the user uploaded the file.
+pub fn check_uploader(user_id: &UserId, uploader: Option<&UserId>) -> Result {
+	if uploader != Some(user_id) {
+		return Err!(Request(Forbidden("You may only delete media you uploaded.")));
+	}
+
+	Ok(())
+}
+
+/// When a file was last modified, in milliseconds since the unix epoch.
+pub(super) fn modified_ts(file: &std::fs::Metadata) -> Option<u64> {
+	file.modified()
+		.ok()
+		.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+		.and_then(|since| since.as_millis().try_into().ok())
+}
+
+/// Takes a page of at most `limit` items starting at `from`, with where the
+/// next page starts if there are more.
+#[must_use]
+pub fn page<T>(items: Vec<T>, from: usize, limit: usize) -> (Vec<T>, Option<usize>) {
+	let total = items.len();
+	let page: Vec<T> = items.into_iter().skip(from).take(limit).collect();
+	let next = from.saturating_add(page.len());
+
+	(page, (next < total).then_some(next))
+}
@@ -63,6 +63,9 @@ async fn fresh(services: &Services) -> Result<()> {
 	db["global"].insert(b"fix_referencedevents_missing_sep", []);
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
 
+	// Nothing was stored before the indexes the reindex service backfills
+	services.reindex.skip_all().await;
+
 	// Create the admin room and server user on first run
 	crate::admin::create_admin_room(services).boxed().await?;
 
@@ -99,6 +102,12 @@ async fn migrate(services: &Services) -> Result<()> {
 		media::migrations::checkup_sha256_media(services).await?;
 	}
 
+	// Uploads were indexed by user at startup before the reindex service did so.
+	if db["global"].get(b"feat_userid_mediaid").await.is_ok() {
+		services.reindex.skip(media::REINDEX_USER_MEDIA).await;
+		db["global"].remove(b"feat_userid_mediaid");
+	}
+
 	if db["global"]
 		.get(b"fix_bad_double_separator_in_state_cache")
 		.await
@@ -6,25 +6,36 @@
 mod service;
 pub mod services;
 
+pub mod cache;
+
 pub mod account_data;
+pub mod account_validity;
 pub mod admin;
 pub mod appservice;
 pub mod client;
 pub mod config;
+pub mod db_stats;
 pub mod emergency;
 pub mod federation;
 pub mod globals;
 pub mod key_backups;
+pub mod mailer;
 pub mod media;
 pub mod presence;
 pub mod pusher;
+pub mod reindex;
 pub mod resolver;
 pub mod rooms;
+pub mod secondary;
 pub mod sending;
 pub mod server_keys;
+pub mod shedding;
 pub mod sync;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod transaction_ids;
 pub mod uiaa;
+pub mod uisi;
 pub mod updates;
 pub mod users;
 
@@ -1,4 +1,14 @@
-use std::{fmt::Debug, mem, sync::Arc};
+mod muted;
+mod rules;
+#[cfg(test)]
+mod tests;
+
+use std::{
+	collections::HashMap,
+	fmt::Debug,
+	mem,
+	sync::{Arc, Mutex},
+};
 
 use bytes::BytesMut;
 use conduwuit::{
@@ -10,7 +20,7 @@
 use futures::{Stream, StreamExt};
 use ipaddress::IPAddress;
 use ruma::{
-	DeviceId, OwnedDeviceId, RoomId, UInt, UserId,
+	DeviceId, OwnedDeviceId, OwnedUserId, RoomId, UInt, UserId,
 	api::{
 		IncomingResponse, MatrixVersion, OutgoingRequest, SendAccessToken,
 		client::push::{Pusher, PusherKind, set_pusher},
@@ -30,14 +40,26 @@
 	uint,
 };
 
-use crate::{Dep, client, globals, rooms, sending, users};
+pub use self::{
+	muted::{MAX_MUTED_WORD_LEN, MAX_MUTED_WORDS, MUTED_WORDS, MutedWords, MutedWordsContent},
+	rules::{
+		check_new_rule, failed_condition, keyword_rules, normalize_keyword, normalize_pattern,
+		rule_conditions,
+	},
+};
+use crate::{Dep, account_data, client, globals, rooms, sending, users};
 
 pub struct Service {
 	db: Data,
 	services: Services,
+
+	/// Compiled muted words of each user, with the ID of the account data
+	/// entry they were compiled from.
+	muted_words: Mutex<HashMap<OwnedUserId, (Vec<u8>, Option<Arc<MutedWords>>)>>,
 }
 
 struct Services {
+	account_data: Dep<account_data::Service>,
 	globals: Dep<globals::Service>,
 	client: Dep<client::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
@@ -59,6 +81,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				pushkey_deviceid: args.db["pushkey_deviceid"].clone(),
 			},
 			services: Services {
+				account_data: args.depend::<account_data::Service>("account_data"),
 				globals: args.depend::<globals::Service>("globals"),
 				client: args.depend::<client::Service>("client"),
 				state_accessor: args
@@ -67,6 +90,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				users: args.depend::<users::Service>("users"),
 				sending: args.depend::<sending::Service>("sending"),
 			},
+			muted_words: Mutex::default(),
 		}))
 	}
 
@@ -318,7 +342,7 @@ pub async fn send_push_notice(
 			notify = Some(n);
 		}
 
-		if notify == Some(true) {
+		if notify == Some(true) && self.muted(user, pdu).await.is_none() {
 			self.send_notice(unread, pusher, tweaks, pdu).await?;
 		}
 		// Else the event triggered no actions
@@ -335,6 +359,18 @@ pub async fn get_actions<'a>(
 		pdu: &Raw<AnySyncTimelineEvent>,
 		room_id: &RoomId,
 	) -> &'a [Action] {
+		let ctx = self.room_ctx(user, power_levels, room_id).await;
+
+		ruleset.get_actions(pdu, &ctx)
+	}
+
+	/// The room context push conditions of the user's rules are evaluated in.
+	pub async fn room_ctx(
+		&self,
+		user: &UserId,
+		power_levels: &RoomPowerLevelsEventContent,
+		room_id: &RoomId,
+	) -> PushConditionRoomCtx {
 		let power_levels = PushConditionPowerLevelsCtx {
 			users: power_levels.users.clone(),
 			users_default: power_levels.users_default,
@@ -357,15 +393,13 @@ pub async fn get_actions<'a>(
 			.await
 			.unwrap_or_else(|_| user.localpart().to_owned());
 
-		let ctx = PushConditionRoomCtx {
+		PushConditionRoomCtx {
 			room_id: room_id.to_owned(),
 			member_count: room_joined_count,
 			user_id: user.to_owned(),
 			user_display_name,
 			power_levels: Some(power_levels),
-		};
-
-		ruleset.get_actions(pdu, &ctx)
+		}
 	}
 
 	#[tracing::instrument(skip(self, unread, pusher, tweaks, event))]
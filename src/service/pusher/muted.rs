@@ -0,0 +1,130 @@
+//! Muted words
+//!
+//! Users list words and phrases in their account data which no notification
+//! is pushed for, so the mutes hold while their clients are closed. They are
+//! matched case-insensitively as whole words of a message's body, after the
+//! push rules decide to notify. The compiled words of each user are cached
+//! until their account data of the kind is updated.
+
+use std::sync::Arc;
+
+use conduwuit::{PduEvent, debug_warn};
+use ruma::{UserId, events::GlobalAccountDataEventType};
+use serde::Deserialize;
+
+use crate::rooms::directory;
+
+/// Account data type of the muted words.
+pub const MUTED_WORDS: &str = "dev.conduwuit.muted_words";
+
+/// Most words of a user which are matched; the rest are ignored.
+pub const MAX_MUTED_WORDS: usize = 100;
+
+/// Longest word matched, in characters; longer ones are ignored.
+pub const MAX_MUTED_WORD_LEN: usize = 100;
+
+/// The content of the account data.
+#[derive(Debug, Default, Deserialize)]
+pub struct MutedWordsContent {
+	#[serde(default)]
+	pub words: Vec<String>,
+
+	/// Also leave muted events out of the unread notification counts.
+	#[serde(default)]
+	pub exclude_from_unread: bool,
+}
+
+#[derive(Deserialize)]
+struct MutedWordsEvent {
+	content: MutedWordsContent,
+}
+
+/// The muted words of a user, folded for matching.
+#[derive(Debug)]
+pub struct MutedWords {
+	/// Each word folded, between spaces.
+	words: Vec<String>,
+	pub exclude_from_unread: bool,
+}
+
+#[derive(Deserialize)]
+struct ExtractBody {
+	body: Option<String>,
+}
+
+impl super::Service {
+	/// The user's muted words when they mute the event.
+	pub async fn muted(&self, user_id: &UserId, pdu: &PduEvent) -> Option<Arc<MutedWords>> {
+		let body = pdu.get_content::<ExtractBody>().ok()?.body?;
+		let muted = self.muted_words(user_id).await?;
+
+		muted.mutes(&body).then_some(muted)
+	}
+
+	/// The user's muted words, compiled when their account data changed since
+	/// they were last needed.
+	pub async fn muted_words(&self, user_id: &UserId) -> Option<Arc<MutedWords>> {
+		let account_data = &self.services.account_data;
+		let Ok(entry_id) = account_data.get_entry_id(None, user_id, MUTED_WORDS).await else {
+			self.muted_words.lock().expect("locked").remove(user_id);
+			return None;
+		};
+
+		if let Some((cached_id, cached)) = self.muted_words.lock().expect("locked").get(user_id) {
+			if *cached_id == entry_id {
+				return cached.clone();
+			}
+		}
+
+		let content = account_data
+			.get_global::<MutedWordsEvent>(user_id, GlobalAccountDataEventType::from(MUTED_WORDS))
+			.await
+			.inspect_err(|e| debug_warn!(%user_id, "Invalid muted words: {e}"))
+			.map(|event| event.content)
+			.unwrap_or_default();
+
+		let compiled = MutedWords::new(&content);
+		let compiled = (!compiled.words.is_empty()).then(|| Arc::new(compiled));
+		self.muted_words
+			.lock()
+			.expect("locked")
+			.insert(user_id.to_owned(), (entry_id, compiled.clone()));
+
+		compiled
+	}
+}
+
+impl MutedWords {
+	/// Folds the first [`MAX_MUTED_WORDS`] words, leaving out those longer
+	/// than [`MAX_MUTED_WORD_LEN`] and those which fold to nothing, such as
+	/// punctuation.
+	#[must_use]
+	pub fn new(content: &MutedWordsContent) -> Self {
+		let words = content
+			.words
+			.iter()
+			.take(MAX_MUTED_WORDS)
+			.filter(|word| word.chars().count() <= MAX_MUTED_WORD_LEN)
+			.map(|word| fold(word))
+			.filter(|word| !word.is_empty())
+			.map(|word| format!(" {word} "))
+			.collect();
+
+		Self {
+			words,
+			exclude_from_unread: content.exclude_from_unread,
+		}
+	}
+
+	/// Whether any of the words or phrases is in the text as whole words.
+	#[must_use]
+	pub fn mutes(&self, text: &str) -> bool {
+		let text = format!(" {} ", fold(text));
+
+		self.words.iter().any(|word| text.contains(word.as_str()))
+	}
+}
+
+/// Folds text as the room directory does, and the final form of sigma, which
+/// is lowercased by its place in a word, to the other.
+fn fold(text: &str) -> String { directory::fold(text).replace('ς', "σ") }
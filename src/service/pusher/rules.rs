@@ -0,0 +1,148 @@
+//! Push rule checks
+//!
+//! Patterns are matched as globs when an event is pushed, so one which can
+//! never match only shows as missing notifications. New rules are checked and
+//! their patterns normalized before being stored instead.
+
+use conduwuit::{Err, Result};
+use ruma::{
+	RoomId, UserId,
+	push::{FlattenedJson, NewPushRule, PushCondition, PushConditionRoomCtx, RuleKind, Ruleset},
+};
+
+/// Longest pattern accepted, in characters.
+pub const MAX_PATTERN_LEN: usize = 256;
+
+/// Checks a new push rule and normalizes its patterns, refusing keyword rules
+/// beyond `max_keyword_rules` unless the rule replaces one.
+pub fn check_new_rule(
+	rule: &mut NewPushRule,
+	ruleset: &Ruleset,
+	max_keyword_rules: usize,
+	case_fold: bool,
+) -> Result {
+	match rule {
+		| NewPushRule::Content(rule) => {
+			rule.pattern = normalize_keyword(&rule.pattern, case_fold)?;
+
+			let replaces = ruleset
+				.content
+				.iter()
+				.any(|existing| existing.rule_id == rule.rule_id);
+
+			if !replaces && keyword_rules(ruleset) >= max_keyword_rules {
+				return Err!(Request(InvalidParam(
+					"Too many keyword rules, at most {max_keyword_rules} are allowed."
+				)));
+			}
+
+			Ok(())
+		},
+		| NewPushRule::Override(rule) | NewPushRule::Underride(rule) =>
+			normalize_conditions(&mut rule.conditions, case_fold),
+		| _ => Ok(()),
+	}
+}
+
+/// Trims the pattern of a glob condition, and lower-cases it if asked to, as
+/// globs match case-insensitively anyway.
+pub fn normalize_pattern(pattern: &str, case_fold: bool) -> Result<String> {
+	let pattern = pattern.trim();
+	if pattern.is_empty() {
+		return Err!(Request(InvalidParam("Push rule pattern is empty.")));
+	}
+
+	if pattern.chars().count() > MAX_PATTERN_LEN {
+		return Err!(Request(InvalidParam(
+			"Push rule pattern is longer than {MAX_PATTERN_LEN} characters."
+		)));
+	}
+
+	if pattern.chars().any(char::is_control) {
+		return Err!(Request(InvalidParam("Push rule pattern contains control characters.")));
+	}
+
+	Ok(if case_fold {
+		pattern.to_lowercase()
+	} else {
+		pattern.to_owned()
+	})
+}
+
+/// As [`normalize_pattern`], refusing keywords of wildcards only which would
+/// match every message.
+pub fn normalize_keyword(pattern: &str, case_fold: bool) -> Result<String> {
+	let pattern = normalize_pattern(pattern, case_fold)?;
+	if pattern.chars().all(|c| c == '*') {
+		return Err!(Request(InvalidParam(
+			"Keyword pattern matches every message; use an override rule instead."
+		)));
+	}
+
+	Ok(pattern)
+}
+
+fn normalize_conditions(conditions: &mut [PushCondition], case_fold: bool) -> Result {
+	for condition in conditions {
+		if let PushCondition::EventMatch { pattern, .. } = condition {
+			*pattern = normalize_pattern(pattern, case_fold)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Keyword rules the user added, not counting server-default ones.
+#[must_use]
+pub fn keyword_rules(ruleset: &Ruleset) -> usize {
+	ruleset.content.iter().filter(|rule| !rule.default).count()
+}
+
+/// The conditions a rule of the kind applies on: its own for override and
+/// underride rules, or those implied by the keyword, room or sender of others.
+pub fn rule_conditions(
+	kind: &RuleKind,
+	rule_id: Option<&str>,
+	pattern: Option<&str>,
+	conditions: Vec<PushCondition>,
+) -> Result<Vec<PushCondition>> {
+	let event_match =
+		|key: &str, pattern: String| PushCondition::EventMatch { key: key.to_owned(), pattern };
+
+	match kind {
+		| RuleKind::Override | RuleKind::Underride => {
+			let mut conditions = conditions;
+			normalize_conditions(&mut conditions, false)?;
+			Ok(conditions)
+		},
+		| RuleKind::Content => {
+			let Some(pattern) = pattern else {
+				return Err!(Request(MissingParam("Keyword rules need a pattern.")));
+			};
+
+			Ok(vec![event_match("content.body", normalize_keyword(pattern, false)?)])
+		},
+		| RuleKind::Room => match rule_id.map(RoomId::parse) {
+			| Some(Ok(room_id)) => Ok(vec![event_match("room_id", room_id.into())]),
+			| _ => Err!(Request(InvalidParam("Room rules need a room ID as rule ID."))),
+		},
+		| RuleKind::Sender => match rule_id.map(UserId::parse) {
+			| Some(Ok(user_id)) => Ok(vec![event_match("sender", user_id.into())]),
+			| _ => Err!(Request(InvalidParam("Sender rules need a user ID as rule ID."))),
+		},
+		| _ => Err!(Request(InvalidParam("Unknown push rule kind."))),
+	}
+}
+
+/// The first of the conditions the event fails, if any; a rule applies when
+/// it fails none.
+#[must_use]
+pub fn failed_condition(
+	conditions: &[PushCondition],
+	event: &FlattenedJson,
+	ctx: &PushConditionRoomCtx,
+) -> Option<usize> {
+	conditions
+		.iter()
+		.position(|condition| !condition.applies(event, ctx))
+}
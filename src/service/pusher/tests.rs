@@ -0,0 +1,199 @@
+use ruma::{
+	push::{
+		FlattenedJson, NewConditionalPushRule, NewPatternedPushRule, NewPushRule, PushCondition,
+		PushConditionRoomCtx, RuleKind, Ruleset, ScalarJsonValue,
+	},
+	room_id,
+	serde::Raw,
+	uint, user_id,
+};
+use serde_json::json;
+
+use super::{
+	MAX_MUTED_WORD_LEN, MAX_MUTED_WORDS, MutedWords, MutedWordsContent, check_new_rule,
+	failed_condition, keyword_rules, normalize_pattern, rule_conditions,
+};
+
+fn keyword(rule_id: &str, pattern: &str) -> NewPushRule {
+	NewPushRule::Content(NewPatternedPushRule::new(rule_id.into(), pattern.into(), Vec::new()))
+}
+
+fn ruleset_with_keywords(count: usize) -> Ruleset {
+	let mut ruleset = Ruleset::server_default(user_id!("@alice:example.com"));
+	for i in 0..count {
+		ruleset
+			.insert(keyword(&format!("keyword{i}"), &format!("word{i}")), None, None)
+			.expect("valid rule");
+	}
+
+	ruleset
+}
+
+fn ctx() -> PushConditionRoomCtx {
+	PushConditionRoomCtx {
+		room_id: room_id!("!room:example.com").to_owned(),
+		member_count: uint!(2),
+		user_id: user_id!("@alice:example.com").to_owned(),
+		user_display_name: "Alice".to_owned(),
+		power_levels: None,
+	}
+}
+
+fn event() -> FlattenedJson {
+	let event: Raw<serde_json::Value> = Raw::new(&json!({
+		"type": "m.room.message",
+		"sender": "@bob:example.com",
+		"event_id": "$event",
+		"room_id": "!room:example.com",
+		"origin_server_ts": 1,
+		"content": {
+			"msgtype": "m.text",
+			"body": "lunch at noon?",
+		},
+	}))
+	.expect("valid json");
+
+	FlattenedJson::from_raw(&event)
+}
+
+#[test]
+fn pattern_trimmed_and_folded() {
+	assert_eq!(normalize_pattern("  Lunch* ", false).expect("valid"), "Lunch*");
+	assert_eq!(normalize_pattern("  Lunch* ", true).expect("valid"), "lunch*");
+}
+
+#[test]
+fn invalid_patterns_refused() {
+	assert!(normalize_pattern("   ", false).is_err());
+	assert!(normalize_pattern("lun\nch", false).is_err());
+	assert!(normalize_pattern(&"a".repeat(257), false).is_err());
+	assert!(normalize_pattern(&"a".repeat(256), false).is_ok());
+
+	let ruleset = ruleset_with_keywords(0);
+	assert!(check_new_rule(&mut keyword("all", "**"), &ruleset, 10, false).is_err());
+	assert!(check_new_rule(&mut keyword("all", " * "), &ruleset, 10, false).is_err());
+}
+
+#[test]
+fn rule_stored_normalized() {
+	let ruleset = ruleset_with_keywords(0);
+	let mut rule = keyword("lunch", " Lunch ");
+	check_new_rule(&mut rule, &ruleset, 10, true).expect("valid rule");
+
+	let NewPushRule::Content(rule) = rule else {
+		panic!("still a keyword rule");
+	};
+
+	assert_eq!(rule.pattern, "lunch");
+}
+
+#[test]
+fn override_conditions_checked() {
+	let ruleset = ruleset_with_keywords(0);
+	let conditions = vec![PushCondition::EventMatch {
+		key: "content.body".into(),
+		pattern: "\t".into(),
+	}];
+
+	let mut rule = NewPushRule::Override(NewConditionalPushRule::new(
+		"blank".into(),
+		conditions,
+		Vec::new(),
+	));
+
+	assert!(check_new_rule(&mut rule, &ruleset, 10, false).is_err());
+}
+
+#[test]
+fn keyword_rules_capped() {
+	let ruleset = ruleset_with_keywords(3);
+	assert_eq!(keyword_rules(&ruleset), 3);
+
+	assert!(check_new_rule(&mut keyword("new", "new"), &ruleset, 3, false).is_err());
+	assert!(check_new_rule(&mut keyword("new", "new"), &ruleset, 4, false).is_ok());
+
+	// replacing a rule does not add one
+	assert!(check_new_rule(&mut keyword("keyword1", "new"), &ruleset, 3, false).is_ok());
+}
+
+#[test]
+fn dry_run_event_property_is() {
+	let conditions = vec![
+		PushCondition::EventPropertyIs {
+			key: "type".into(),
+			value: ScalarJsonValue::String("m.room.message".into()),
+		},
+		PushCondition::EventPropertyIs {
+			key: "content.msgtype".into(),
+			value: ScalarJsonValue::String("m.notice".into()),
+		},
+	];
+
+	let conditions =
+		rule_conditions(&RuleKind::Override, None, None, conditions).expect("valid conditions");
+
+	assert_eq!(failed_condition(&conditions, &event(), &ctx()), Some(1));
+	assert_eq!(
+		failed_condition(conditions.get(..1).expect("first condition"), &event(), &ctx()),
+		None
+	);
+}
+
+#[test]
+fn dry_run_implied_conditions() {
+	let keyword = rule_conditions(&RuleKind::Content, None, Some("lunch"), Vec::new())
+		.expect("valid keyword");
+	assert_eq!(failed_condition(&keyword, &event(), &ctx()), None);
+
+	let sender = rule_conditions(&RuleKind::Sender, Some("@carol:example.com"), None, Vec::new())
+		.expect("valid sender");
+	assert_eq!(failed_condition(&sender, &event(), &ctx()), Some(0));
+
+	let room = rule_conditions(&RuleKind::Room, Some("!room:example.com"), None, Vec::new())
+		.expect("valid room");
+	assert_eq!(failed_condition(&room, &event(), &ctx()), None);
+
+	assert!(rule_conditions(&RuleKind::Room, Some("not a room"), None, Vec::new()).is_err());
+	assert!(rule_conditions(&RuleKind::Content, None, None, Vec::new()).is_err());
+}
+
+fn muted(words: &[&str]) -> MutedWords {
+	MutedWords::new(&MutedWordsContent {
+		words: words.iter().map(ToString::to_string).collect(),
+		exclude_from_unread: false,
+	})
+}
+
+#[test]
+fn muted_words_fold_case() {
+	let muted = muted(&["straße", "ΣΊΣΥΦΟΣ"]);
+	assert!(muted.mutes("Meet me on the STRASSE!"));
+	assert!(muted.mutes("ο σίσυφος"));
+	assert!(muted.mutes("Σίσυφος."));
+	assert!(!muted.mutes("strasse42"));
+}
+
+#[test]
+fn muted_words_whole_words() {
+	let muted = muted(&["cat", "spoiler alert"]);
+	assert!(muted.mutes("my cat, again"));
+	assert!(muted.mutes("SPOILER   alert: he dies"));
+	assert!(!muted.mutes("a category"));
+	assert!(!muted.mutes("spoiler, no alert"));
+}
+
+#[test]
+fn muted_words_capped() {
+	let long = "a".repeat(MAX_MUTED_WORD_LEN.saturating_add(1));
+	let mut words: Vec<String> = (0..MAX_MUTED_WORDS).map(|i| format!("word{i}")).collect();
+	words.push("beyond".to_owned());
+	words.insert(0, long.clone());
+	words.insert(0, "!!!".to_owned());
+
+	let muted = MutedWords::new(&MutedWordsContent { words, exclude_from_unread: false });
+	assert!(muted.mutes("word0"));
+	assert!(!muted.mutes(&long));
+	assert!(!muted.mutes("beyond"));
+	assert!(!muted.mutes("!!!"));
+	assert!(!muted.mutes(""));
+}
@@ -0,0 +1,299 @@
+//! Background reindexing
+//!
+//! Indexes added by upgrades are filled in for the data stored before them by
+//! tasks run in the background once the server is up, instead of by startup
+//! migrations, which on large servers would keep it down for hours. Each task
+//! walks a range of keys in order, processing them in small batches at
+//! background database priority, and persists the last key it processed, so
+//! after a restart it resumes from there. Tasks run one at a time in the order
+//! they are listed; an admin may pause and resume each of them.
+
+#[cfg(test)]
+mod tests;
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result, Server, debug_info, implement, info, utils::ReadyExt, warn};
+use database::{Deserialized, Json, Lane, Map};
+use futures::{StreamExt, stream::BoxStream};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	sync::{Mutex, Notify},
+	time::sleep,
+};
+
+use crate::{Dep, globals, media, rooms};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+	tasks: Vec<Arc<dyn Task>>,
+
+	/// Held while a task's progress is read and written again.
+	update: Mutex<()>,
+
+	/// Woken when a paused task is resumed.
+	resumed: Notify,
+	interrupt: Notify,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+}
+
+struct Data {
+	reindex_progress: Arc<Map>,
+}
+
+/// A backfill of an index over the data stored before it existed.
+#[async_trait]
+pub trait Task: Send + Sync {
+	/// Names the task to admins and keys its persisted progress.
+	fn name(&self) -> &'static str;
+
+	/// The keys to process in ascending order, starting at `from`; the empty
+	/// key starts at the first.
+	fn keys<'a>(&'a self, from: &'a [u8]) -> BoxStream<'a, &'a [u8]>;
+
+	/// Indexes the data of the keys. A batch may be processed again after a
+	/// restart during it, so this must be idempotent.
+	async fn process(&self, keys: &[Vec<u8>]) -> Result;
+}
+
+/// How far a task got, as persisted.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Progress {
+	/// The last key processed; the task resumes after it.
+	pub cursor: Vec<u8>,
+
+	/// Keys processed so far.
+	pub done: u64,
+
+	pub finished: bool,
+
+	/// Paused by an admin, or by a batch failing with `error`.
+	pub paused: bool,
+	pub error: Option<String>,
+}
+
+/// Keys processed at a time.
+const BATCH_SIZE: usize = 256;
+
+/// Time between batches, leaving the database to other work.
+const BATCH_PAUSE: Duration = Duration::from_millis(100);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+			},
+			db: Data {
+				reindex_progress: args.db["reindex_progress"].clone(),
+			},
+			tasks: vec![
+				Arc::new(rooms::directory::Reindex::new(&args)),
+				Arc::new(media::Reindex::new(&args)),
+			],
+			update: Mutex::new(()),
+			resumed: Notify::new(),
+			interrupt: Notify::new(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		if self.services.globals.is_read_only() {
+			return Ok(());
+		}
+
+		while self.services.server.running() {
+			for task in &self.tasks {
+				Lane::Background.scope(self.run(task.as_ref())).await;
+			}
+
+			let status = self.status().await;
+			if status.iter().all(|(_, progress)| progress.finished) {
+				break;
+			}
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.resumed.notified() => (),
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Runs the task from where it got until it finishes, fails, is paused or the
+/// server stops.
+#[implement(Service)]
+async fn run(&self, task: &dyn Task) {
+	let name = task.name();
+	let mut announced = false;
+	while self.services.server.running() {
+		let mut progress = self.progress(name).await;
+		if progress.finished || progress.paused {
+			return;
+		}
+
+		if !announced {
+			announced = true;
+			info!(task = name, done = progress.done, "Reindexing in the background");
+		}
+
+		let result = step(task, &mut progress, BATCH_SIZE).await;
+		self.update(name, |stored| match &result {
+			| Ok(_) => {
+				stored.cursor.clone_from(&progress.cursor);
+				stored.done = progress.done;
+				stored.finished = progress.finished;
+			},
+			| Err(e) => {
+				stored.paused = true;
+				stored.error = Some(e.to_string());
+			},
+		})
+		.await;
+
+		match result {
+			| Ok(true) => (),
+			| Ok(false) => {
+				info!(task = name, done = progress.done, "Finished reindexing");
+				return;
+			},
+			| Err(e) => {
+				warn!(task = name, "Paused reindexing after an error: {e}");
+				return;
+			},
+		}
+
+		tokio::select! {
+			() = self.interrupt.notified() => return,
+			() = sleep(BATCH_PAUSE) => (),
+		}
+	}
+}
+
+/// Processes the next batch of the task's keys after its cursor, advancing the
+/// cursor past them. Returns whether there were any left.
+async fn step(task: &dyn Task, progress: &mut Progress, batch_size: usize) -> Result<bool> {
+	let cursor = progress.cursor.as_slice();
+	let batch: Vec<Vec<u8>> = task
+		.keys(cursor)
+		.ready_filter(|key| *key > cursor)
+		.take(batch_size)
+		.map(<[u8]>::to_vec)
+		.collect()
+		.await;
+
+	let Some(last) = batch.last() else {
+		progress.finished = true;
+		return Ok(false);
+	};
+
+	task.process(&batch).await?;
+	progress.cursor.clone_from(last);
+	progress.done = progress
+		.done
+		.saturating_add(batch.len().try_into().unwrap_or(u64::MAX));
+
+	Ok(true)
+}
+
+/// Every task with its progress, in the order they run.
+#[implement(Service)]
+pub async fn status(&self) -> Vec<(&'static str, Progress)> {
+	let mut status = Vec::with_capacity(self.tasks.len());
+	for task in &self.tasks {
+		status.push((task.name(), self.progress(task.name()).await));
+	}
+
+	status
+}
+
+/// Pauses the task after the batch it is processing, returning whether it was
+/// not paused or finished already.
+#[implement(Service)]
+pub async fn pause(&self, name: &str) -> Result<bool> {
+	let name = self.task_name(name)?;
+	let mut paused = false;
+	self.update(name, |progress| {
+		paused = !progress.paused && !progress.finished;
+		progress.paused = true;
+	})
+	.await;
+
+	Ok(paused)
+}
+
+/// Resumes the task, returning whether it was paused.
+#[implement(Service)]
+pub async fn resume(&self, name: &str) -> Result<bool> {
+	let name = self.task_name(name)?;
+	let mut resumed = false;
+	self.update(name, |progress| {
+		resumed = progress.paused;
+		progress.paused = false;
+		progress.error = None;
+	})
+	.await;
+
+	self.resumed.notify_one();
+	debug_info!(task = name, "Resumed reindexing");
+
+	Ok(resumed)
+}
+
+/// Marks the task finished without running it, when there is nothing for it to
+/// backfill.
+#[implement(Service)]
+pub(crate) async fn skip(&self, name: &str) {
+	if let Ok(name) = self.task_name(name) {
+		self.update(name, |progress| progress.finished = true).await;
+	}
+}
+
+/// Marks every task finished, for a new database.
+#[implement(Service)]
+pub(crate) async fn skip_all(&self) {
+	for task in &self.tasks {
+		self.skip(task.name()).await;
+	}
+}
+
+#[implement(Service)]
+fn task_name(&self, name: &str) -> Result<&'static str> {
+	let Some(task) = self.tasks.iter().find(|task| task.name() == name) else {
+		return Err!("No reindexing task named {name:?}.");
+	};
+
+	Ok(task.name())
+}
+
+#[implement(Service)]
+async fn progress(&self, name: &str) -> Progress {
+	self.db
+		.reindex_progress
+		.get(name)
+		.await
+		.deserialized()
+		.unwrap_or_default()
+}
+
+#[implement(Service)]
+async fn update(&self, name: &str, f: impl FnOnce(&mut Progress) + Send) {
+	let _lock = self.update.lock().await;
+	let mut progress = self.progress(name).await;
+	f(&mut progress);
+	self.db.reindex_progress.raw_put(name, Json(&progress));
+}
@@ -0,0 +1,112 @@
+use std::{collections::BTreeSet, sync::Mutex};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result};
+use futures::{StreamExt, stream::BoxStream};
+
+use super::{Progress, Task, step};
+
+/// Indexes a fixed set of keys, recording each batch, and fails the batch
+/// containing `fail_at` once.
+struct Keys {
+	keys: BTreeSet<Vec<u8>>,
+	processed: Mutex<Vec<Vec<Vec<u8>>>>,
+	fail_at: Mutex<Option<Vec<u8>>>,
+}
+
+impl Keys {
+	fn new(count: u8, fail_at: Option<u8>) -> Self {
+		Self {
+			keys: (0..count).map(|key| vec![b'k', key]).collect(),
+			processed: Mutex::default(),
+			fail_at: Mutex::new(fail_at.map(|key| vec![b'k', key])),
+		}
+	}
+
+	fn processed(&self) -> Vec<Vec<Vec<u8>>> { self.processed.lock().unwrap().clone() }
+}
+
+#[async_trait]
+impl Task for Keys {
+	fn name(&self) -> &'static str { "keys" }
+
+	fn keys<'a>(&'a self, from: &'a [u8]) -> BoxStream<'a, &'a [u8]> {
+		futures::stream::iter(self.keys.iter().map(Vec::as_slice))
+			.skip_while(move |key| futures::future::ready(*key < from))
+			.boxed()
+	}
+
+	async fn process(&self, keys: &[Vec<u8>]) -> Result {
+		let mut fail_at = self.fail_at.lock().unwrap();
+		if fail_at.as_ref().is_some_and(|fail| keys.contains(fail)) {
+			*fail_at = None;
+			return Err!("disk full");
+		}
+
+		self.processed.lock().unwrap().push(keys.to_vec());
+		Ok(())
+	}
+}
+
+/// The progress as it is persisted and read again after a restart.
+fn persisted(progress: &Progress) -> Progress {
+	serde_json::from_slice(&serde_json::to_vec(progress).unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn reindex_resumes_after_restart() {
+	let before = Keys::new(10, None);
+	let mut progress = Progress::default();
+	assert!(step(&before, &mut progress, 4).await.unwrap());
+	assert!(step(&before, &mut progress, 4).await.unwrap());
+	assert_eq!(progress.cursor, [b'k', 7]);
+	assert_eq!(progress.done, 8);
+
+	let after = Keys::new(10, None);
+	let mut progress = persisted(&progress);
+	assert!(step(&after, &mut progress, 4).await.unwrap());
+	assert!(!step(&after, &mut progress, 4).await.unwrap());
+	assert!(progress.finished);
+	assert_eq!(progress.done, 10);
+
+	let processed: Vec<_> = before
+		.processed()
+		.into_iter()
+		.chain(after.processed())
+		.flatten()
+		.collect();
+
+	let keys: Vec<_> = before.keys.into_iter().collect();
+	assert_eq!(processed, keys, "every key is processed once, in order");
+	assert_eq!(after.processed().len(), 1);
+}
+
+#[tokio::test]
+async fn reindex_failed_batch_retried() {
+	let task = Keys::new(6, Some(4));
+	let mut progress = Progress::default();
+	assert!(step(&task, &mut progress, 3).await.unwrap());
+	step(&task, &mut progress, 3)
+		.await
+		.expect_err("the second batch fails");
+
+	assert_eq!(progress.cursor, [b'k', 2], "the cursor stays before the failed batch");
+	assert_eq!(progress.done, 3);
+
+	assert!(step(&task, &mut progress, 3).await.unwrap());
+	assert!(!step(&task, &mut progress, 3).await.unwrap());
+	assert_eq!(task.processed(), [vec![vec![b'k', 0], vec![b'k', 1], vec![b'k', 2]], vec![
+		vec![b'k', 3],
+		vec![b'k', 4],
+		vec![b'k', 5]
+	],]);
+}
+
+#[tokio::test]
+async fn reindex_nothing_to_do() {
+	let task = Keys::new(0, None);
+	let mut progress = Progress::default();
+	assert!(!step(&task, &mut progress, 3).await.unwrap());
+	assert!(progress.finished);
+	assert_eq!(progress.done, 0);
+}
@@ -366,6 +366,8 @@ fn validate_dest(&self, dest: &ServerName) -> Result<()> {
 			return Err!("Won't send federation request to ourselves");
 		}
 
+		self.services.server.config.check_remote_server(dest)?;
+
 		if dest.is_ip_literal() || IPAddress::is_valid(dest.host()) {
 			self.validate_dest_ip_literal(dest)?;
 		}
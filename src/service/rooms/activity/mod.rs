@@ -0,0 +1,357 @@
+//! Room activity
+//!
+//! Counts what happens in each room by day for community dashboards: the
+//! messages sent, how many distinct users sent them, and the joins, leaves and
+//! redactions. Only the counts are exported. Events are counted in memory as
+//! they are appended to the timeline, and the counts added to those in the
+//! database every few minutes; days older than the configured retention are
+//! deleted.
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+	collections::{HashMap, HashSet},
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	PduEvent, Result, Server, debug, implement,
+	utils::{ReadyExt, millis_since_unix_epoch, stream::TryIgnore},
+};
+use database::{Deserialized, Interfix, Json, Lane, Map};
+use futures::StreamExt;
+use ruma::{
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
+	events::{TimelineEventType, room::member::MembershipState},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, globals, rooms};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+	daily: Mutex<HashMap<(OwnedRoomId, u64), Day>>,
+	interrupt: Notify,
+}
+
+struct Data {
+	roomday_activity: Arc<Map>,
+	roomdaysender_activity: Arc<Map>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+}
+
+/// What happened in a room over one day.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Activity {
+	/// Messages, stickers and encrypted events sent.
+	pub messages: u64,
+
+	/// Distinct users who sent them.
+	pub senders: u64,
+
+	/// Users joining who were not joined.
+	pub joins: u64,
+
+	/// Joined users leaving, being kicked or banned.
+	pub leaves: u64,
+
+	pub redactions: u64,
+}
+
+/// What an event counts as.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Counted {
+	Message,
+	Join,
+	Leave,
+	Redaction,
+}
+
+/// The activity of a room and day counted since the last write, with who sent
+/// the messages.
+#[derive(Debug, Default)]
+struct Day {
+	activity: Activity,
+	senders: HashSet<OwnedUserId>,
+}
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// How often the daily counts are written to the database.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+			},
+			db: Data {
+				roomday_activity: args.db["roomday_activity"].clone(),
+				roomdaysender_activity: args.db["roomdaysender_activity"].clone(),
+			},
+			daily: Mutex::default(),
+			interrupt: Notify::new(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		if !self.is_enabled() || self.services.globals.is_read_only() {
+			return Ok(());
+		}
+
+		let mut pruned = None;
+		let mut i = interval(PERSIST_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.persist().await;
+
+			let today = today();
+			if pruned != Some(today) {
+				Lane::Background.scope(self.prune(today)).await;
+				pruned = Some(today);
+			}
+		}
+
+		self.persist().await;
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let daily = self.daily.lock().expect("locked").len();
+		writeln!(out, "room_activity_unsaved_days: {daily}")?;
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether room activity is counted at all.
+#[implement(Service)]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.services.server.config.room_activity_retention_days > 0 }
+
+/// Counts a message or redaction just appended to the room's timeline.
+/// Memberships are counted by [`Service::record_membership`] instead.
+#[implement(Service)]
+pub fn record(&self, pdu: &PduEvent) {
+	if let Some(counted) = counted(&pdu.kind) {
+		self.count(&pdu.room_id, counted, &pdu.sender);
+	}
+}
+
+/// Counts a membership event of the user just appended to the room's
+/// timeline, given whether they were joined before it.
+#[implement(Service)]
+pub fn record_membership(
+	&self,
+	room_id: &RoomId,
+	user_id: &UserId,
+	membership: &MembershipState,
+	was_joined: bool,
+) {
+	if let Some(counted) = membership_counted(membership, was_joined) {
+		self.count(room_id, counted, user_id);
+	}
+}
+
+#[implement(Service)]
+fn count(&self, room_id: &RoomId, counted: Counted, user_id: &UserId) {
+	if !self.is_enabled() {
+		return;
+	}
+
+	let mut daily = self.daily.lock().expect("locked");
+	daily
+		.entry((room_id.to_owned(), today()))
+		.or_default()
+		.count(counted, user_id);
+}
+
+/// The daily activity of the room over the last `days` days, oldest first.
+/// Days nothing was counted are left out.
+#[implement(Service)]
+pub async fn activity(&self, room_id: &RoomId, days: u64) -> Vec<(u64, Activity)> {
+	self.persist().await;
+
+	let since = first_day(today(), days);
+	let prefix = (room_id, Interfix);
+	self.db
+		.roomday_activity
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.ready_filter_map(|((_, day), activity): ((&RoomId, u64), Activity)| {
+			(day >= since).then_some((day, activity))
+		})
+		.collect()
+		.await
+}
+
+/// The daily activity of every room counted over the last `days` days, by
+/// room and oldest first.
+#[implement(Service)]
+pub async fn all_activity(&self, days: u64) -> Vec<(OwnedRoomId, u64, Activity)> {
+	self.persist().await;
+
+	let since = first_day(today(), days);
+	self.db
+		.roomday_activity
+		.stream()
+		.ignore_err()
+		.ready_filter_map(|((room_id, day), activity): ((&RoomId, u64), Activity)| {
+			(day >= since).then(|| (room_id.to_owned(), day, activity))
+		})
+		.collect()
+		.await
+}
+
+/// Adds the daily counts gathered since last time to those in the database.
+/// Senders are counted once a day each, by noting who sent messages in the
+/// room that day.
+#[implement(Service)]
+async fn persist(&self) {
+	let daily = std::mem::take(&mut *self.daily.lock().expect("locked"));
+	let exclude_remote = self
+		.services
+		.server
+		.config
+		.room_activity_exclude_remote_rooms;
+
+	for ((room_id, day), counted) in daily {
+		if exclude_remote
+			&& !self
+				.services
+				.state_cache
+				.server_in_room(self.services.globals.server_name(), &room_id)
+				.await
+		{
+			continue;
+		}
+
+		let key = (&room_id, day);
+		let mut saved: Activity = self
+			.db
+			.roomday_activity
+			.qry(&key)
+			.await
+			.deserialized()
+			.unwrap_or_default();
+
+		saved.merge(&counted.activity);
+		for sender in &counted.senders {
+			let key = (&room_id, day, sender);
+			if self.db.roomdaysender_activity.qry(&key).await.is_err() {
+				self.db.roomdaysender_activity.put_raw(key, []);
+				saved.senders = saved.senders.saturating_add(1);
+			}
+		}
+
+		self.db.roomday_activity.put(key, Json(saved));
+	}
+}
+
+/// Deletes the counts of the days past the retention.
+#[implement(Service)]
+async fn prune(&self, today: u64) {
+	let since = first_day(today, self.services.server.config.room_activity_retention_days);
+	let activity = &self.db.roomday_activity;
+	activity
+		.keys()
+		.ignore_err()
+		.ready_filter(|&(_, day): &(&RoomId, u64)| day < since)
+		.ready_for_each(|key| activity.del(key))
+		.await;
+
+	let senders = &self.db.roomdaysender_activity;
+	senders
+		.keys()
+		.ignore_err()
+		.ready_filter(|&(_, day, _): &(&RoomId, u64, &UserId)| day < since)
+		.ready_for_each(|key| senders.del(key))
+		.await;
+
+	debug!(since, "Pruned room activity");
+}
+
+impl Activity {
+	pub(super) fn merge(&mut self, other: &Self) {
+		self.messages = self.messages.saturating_add(other.messages);
+		self.senders = self.senders.saturating_add(other.senders);
+		self.joins = self.joins.saturating_add(other.joins);
+		self.leaves = self.leaves.saturating_add(other.leaves);
+		self.redactions = self.redactions.saturating_add(other.redactions);
+	}
+}
+
+impl Day {
+	/// Counts the event; message senders are counted when written.
+	fn count(&mut self, counted: Counted, user_id: &UserId) {
+		let activity = &mut self.activity;
+		match counted {
+			| Counted::Message => {
+				activity.messages = activity.messages.saturating_add(1);
+				if !self.senders.contains(user_id) {
+					self.senders.insert(user_id.to_owned());
+				}
+			},
+			| Counted::Join => activity.joins = activity.joins.saturating_add(1),
+			| Counted::Leave => activity.leaves = activity.leaves.saturating_add(1),
+			| Counted::Redaction => activity.redactions = activity.redactions.saturating_add(1),
+		}
+	}
+}
+
+/// What an event of the kind counts as, other than memberships.
+#[must_use]
+pub fn counted(kind: &TimelineEventType) -> Option<Counted> {
+	match kind {
+		| TimelineEventType::RoomMessage
+		| TimelineEventType::RoomEncrypted
+		| TimelineEventType::Sticker => Some(Counted::Message),
+		| TimelineEventType::RoomRedaction => Some(Counted::Redaction),
+		| _ => None,
+	}
+}
+
+/// What a membership counts as, given whether the user was joined before.
+/// Changes of a joined user's profile count as nothing.
+#[must_use]
+pub fn membership_counted(membership: &MembershipState, was_joined: bool) -> Option<Counted> {
+	match membership {
+		| MembershipState::Join if !was_joined => Some(Counted::Join),
+		| MembershipState::Leave | MembershipState::Ban if was_joined => Some(Counted::Leave),
+		| _ => None,
+	}
+}
+
+/// The first of the last `days` days up to `today`.
+fn first_day(today: u64, days: u64) -> u64 { today.saturating_sub(days.saturating_sub(1)) }
+
+/// Today, in days since the epoch.
+fn today() -> u64 { millis_since_unix_epoch() / DAY_MILLIS }
@@ -0,0 +1,65 @@
+use ruma::{
+	events::{TimelineEventType, room::member::MembershipState},
+	user_id,
+};
+
+use super::{Activity, Counted, Day, counted, first_day, membership_counted};
+
+#[test]
+fn room_activity_counted_kinds() {
+	assert_eq!(counted(&TimelineEventType::RoomMessage), Some(Counted::Message));
+	assert_eq!(counted(&TimelineEventType::RoomEncrypted), Some(Counted::Message));
+	assert_eq!(counted(&TimelineEventType::Sticker), Some(Counted::Message));
+	assert_eq!(counted(&TimelineEventType::RoomRedaction), Some(Counted::Redaction));
+	assert_eq!(counted(&TimelineEventType::Reaction), None);
+	assert_eq!(counted(&TimelineEventType::RoomMember), None, "counted with the membership");
+}
+
+#[test]
+fn room_activity_membership_changes() {
+	assert_eq!(membership_counted(&MembershipState::Join, false), Some(Counted::Join));
+	assert_eq!(membership_counted(&MembershipState::Join, true), None, "profile change");
+	assert_eq!(membership_counted(&MembershipState::Leave, true), Some(Counted::Leave));
+	assert_eq!(membership_counted(&MembershipState::Ban, true), Some(Counted::Leave));
+	assert_eq!(membership_counted(&MembershipState::Leave, false), None, "rejected invite");
+	assert_eq!(membership_counted(&MembershipState::Ban, false), None);
+	assert_eq!(membership_counted(&MembershipState::Invite, false), None);
+}
+
+#[test]
+fn room_activity_day_counts() {
+	let (alice, bob) = (user_id!("@alice:example.org"), user_id!("@bob:example.org"));
+	let mut day = Day::default();
+	day.count(Counted::Message, alice);
+	day.count(Counted::Message, alice);
+	day.count(Counted::Message, bob);
+	day.count(Counted::Join, bob);
+	day.count(Counted::Leave, alice);
+	day.count(Counted::Redaction, bob);
+
+	assert_eq!(day.activity, Activity {
+		messages: 3,
+		senders: 0,
+		joins: 1,
+		leaves: 1,
+		redactions: 1,
+	});
+	assert_eq!(day.senders.len(), 2, "senders are counted once each when written");
+
+	let mut saved = Activity {
+		messages: 1,
+		senders: 1,
+		..Activity::default()
+	};
+	saved.merge(&day.activity);
+	assert_eq!(saved.messages, 4);
+	assert_eq!(saved.senders, 1);
+}
+
+#[test]
+fn room_activity_days_back() {
+	assert_eq!(first_day(20_000, 1), 20_000);
+	assert_eq!(first_day(20_000, 30), 19_971);
+	assert_eq!(first_day(20_000, 0), 20_000);
+	assert_eq!(first_day(5, 30), 0);
+}
@@ -16,9 +16,14 @@
 	},
 };
 
-use crate::{Dep, admin, appservice, appservice::RegistrationInfo, globals, rooms, sending};
+use crate::{
+	Dep, admin, appservice,
+	appservice::{InterestCache, RegistrationInfo},
+	globals, rooms, sending,
+};
 
 pub struct Service {
+	appservice_alias_cache: InterestCache,
 	db: Data,
 	services: Services,
 }
@@ -41,6 +46,7 @@ struct Services {
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			appservice_alias_cache: InterestCache::default(),
 			db: Data {
 				alias_userid: args.db["alias_userid"].clone(),
 				alias_roomid: args.db["alias_roomid"].clone(),
@@ -88,6 +94,7 @@ pub fn set_alias(
 		aliasid.push(0xFF);
 		aliasid.extend_from_slice(&self.services.globals.next_count()?.to_be_bytes());
 		self.db.aliasid_alias.insert(&aliasid, alias.as_bytes());
+		self.appservice_alias_cache.invalidate(room_id);
 
 		Ok(())
 	}
@@ -113,10 +120,55 @@ pub async fn remove_alias(&self, alias: &RoomAliasId, user_id: &UserId) -> Resul
 
 		self.db.alias_roomid.remove(alias.as_bytes());
 		self.db.alias_userid.remove(alias.as_bytes());
+		if let Some(room_id) = str::from_utf8(&room_id)
+			.ok()
+			.and_then(|room_id| RoomId::parse(room_id).ok())
+		{
+			self.appservice_alias_cache.invalidate(&room_id);
+		}
 
 		Ok(())
 	}
 
+	/// Forgets that a room published `alias` without changing which room the
+	/// alias resolves to.
+	#[tracing::instrument(skip(self))]
+	pub async fn unpublish_alias(&self, alias: &RoomAliasId, room_id: &RoomId) {
+		let prefix = (room_id, Interfix);
+		self.db
+			.aliasid_alias
+			.stream_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_filter(|(_, val)| *val == alias.as_bytes())
+			.ready_for_each(|(key, _)| self.db.aliasid_alias.remove(key))
+			.await;
+
+		self.appservice_alias_cache.invalidate(room_id);
+	}
+
+	/// Whether any local alias of the room is in the appservice's namespace.
+	#[tracing::instrument(level = "trace", skip_all)]
+	pub async fn appservice_alias_match(
+		&self,
+		room_id: &RoomId,
+		appservice: &RegistrationInfo,
+	) -> bool {
+		if let Some(cached) = self.appservice_alias_cache.get(room_id, appservice) {
+			return cached;
+		}
+
+		let aliases = &appservice.aliases;
+		let matched = self
+			.local_aliases_for_room(room_id)
+			.ready_any(|alias| aliases.is_match(alias.as_str()))
+			.await;
+
+		self.appservice_alias_cache
+			.insert(room_id, appservice, matched);
+
+		matched
+	}
+
 	#[inline]
 	pub async fn resolve(&self, room: &RoomOrAliasId) -> Result<OwnedRoomId> {
 		self.resolve_with_servers(room, None)
@@ -5,15 +5,23 @@
 
 use conduwuit::{Err, Result, err, utils, utils::math::usize_from_f64};
 use database::Map;
-use lru_cache::LruCache;
 
-use crate::rooms::short::ShortEventId;
+use crate::{
+	cache::{Bounded, Counted},
+	rooms::short::{ShortEventId, ShortId},
+};
 
 pub(super) struct Data {
 	shorteventid_authchain: Arc<Map>,
-	pub(super) auth_chain_cache: Mutex<LruCache<Vec<u64>, Arc<[ShortEventId]>>>,
+	pub(super) auth_chain_cache: Counted<Mutex<Cache>>,
 }
 
+pub(super) type Cache = Bounded<Vec<u64>, Arc<[ShortEventId]>>;
+
+/// Estimated bytes of an entry besides its events: the key, the allocation
+/// of the chain and the bookkeeping of the cache.
+const ENTRY_OVERHEAD: usize = 96;
+
 impl Data {
 	pub(super) fn new(args: &crate::Args<'_>) -> Self {
 		let db = &args.db;
@@ -23,7 +31,12 @@ pub(super) fn new(args: &crate::Args<'_>) -> Self {
 			.expect("valid cache size");
 		Self {
 			shorteventid_authchain: db["shorteventid_authchain"].clone(),
-			auth_chain_cache: Mutex::new(LruCache::new(cache_size)),
+			auth_chain_cache: Mutex::new(Bounded::new(
+				cache_size,
+				config.auth_chain_cache_bytes,
+				weigh,
+			))
+			.into(),
 		}
 	}
 
@@ -34,13 +47,15 @@ pub(super) async fn get_cached_eventid_authchain(
 		debug_assert!(!key.is_empty(), "auth_chain key must not be empty");
 
 		// Check RAM cache
-		if let Some(result) = self
+		let cached = self
 			.auth_chain_cache
 			.lock()
 			.expect("cache locked")
 			.get_mut(key)
-		{
-			return Ok(Arc::clone(result));
+			.map(Arc::clone);
+
+		if let Some(result) = self.auth_chain_cache.counters().tally(cached) {
+			return Ok(result);
 		}
 
 		// We only save auth chains for single events in the db
@@ -69,6 +84,15 @@ pub(super) async fn get_cached_eventid_authchain(
 		Ok(chain)
 	}
 
+	/// Removes every chain cached in RAM whose key includes the event,
+	/// returning how many were removed. Chains persisted in the database are
+	/// kept.
+	pub(super) fn evict_auth_chains(&self, shorteventid: ShortEventId) -> usize {
+		let mut cache = self.auth_chain_cache.lock().expect("cache locked");
+
+		evict_containing(&mut cache, shorteventid)
+	}
+
 	pub(super) fn cache_auth_chain(&self, key: Vec<u64>, auth_chain: Arc<[ShortEventId]>) {
 		debug_assert!(!key.is_empty(), "auth_chain key must not be empty");
 
@@ -90,3 +114,26 @@ pub(super) fn cache_auth_chain(&self, key: Vec<u64>, auth_chain: Arc<[ShortEvent
 			.insert(key, auth_chain);
 	}
 }
+
+/// Approximate bytes held by a cached auth chain.
+pub(super) fn weigh(chain: &Arc<[ShortEventId]>) -> usize {
+	chain
+		.len()
+		.saturating_mul(size_of::<ShortId>())
+		.saturating_add(ENTRY_OVERHEAD)
+}
+
+pub(super) fn evict_containing(cache: &mut Cache, shorteventid: ShortEventId) -> usize {
+	let keys: Vec<_> = cache
+		.iter()
+		.map(|(key, _)| key)
+		.filter(|key| key.contains(&shorteventid))
+		.cloned()
+		.collect();
+
+	for key in &keys {
+		cache.remove(key);
+	}
+
+	keys.len()
+}
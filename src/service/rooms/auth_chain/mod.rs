@@ -1,25 +1,28 @@
 mod data;
+#[cfg(test)]
+mod tests;
 
 use std::{
-	collections::{BTreeSet, HashSet, VecDeque},
-	fmt::Debug,
+	collections::{BTreeSet, HashMap, HashSet, VecDeque},
+	fmt::{Debug, Write},
 	sync::Arc,
 	time::Instant,
 };
 
+use async_trait::async_trait;
 use conduwuit::{
-	Err, Result, at, debug, debug_error, implement, trace,
+	Err, Result, at, debug, debug_error, err, implement, trace,
 	utils::{
-		IterStream,
+		IterStream, bytes,
 		stream::{ReadyExt, TryBroadbandExt},
 	},
 	validated, warn,
 };
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
-use ruma::{EventId, OwnedEventId, RoomId};
+use ruma::{CanonicalJsonObject, CanonicalJsonValue, EventId, OwnedEventId, RoomId};
 
 use self::data::Data;
-use crate::{Dep, rooms, rooms::short::ShortEventId};
+use crate::{Dep, cache::Resizable, rooms, rooms::short::ShortEventId};
 
 pub struct Service {
 	services: Services,
@@ -33,6 +36,7 @@ struct Services {
 
 type Bucket<'a> = BTreeSet<(u64, &'a EventId)>;
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
@@ -44,6 +48,43 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		}))
 	}
 
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let (len, bytes, budget) = {
+			let cache = self.db.auth_chain_cache.lock().expect("locked");
+			(cache.len(), cache.bytes(), cache.budget())
+		};
+
+		let bytes = match budget {
+			| Some(budget) => format!("{} of {}", bytes::pretty(bytes), bytes::pretty(budget)),
+			| None => bytes::pretty(bytes),
+		};
+
+		let counters = self.db.auth_chain_cache.counters();
+		writeln!(out, "auth_chain_cache: {len} ({bytes}) ({counters})")?;
+
+		Ok(())
+	}
+
+	/// Evicts the auth chains of the event given by its ID as `key`, along
+	/// with those of any bucket of events including it.
+	async fn clear_cache_entry(&self, key: &str) -> Result<bool> {
+		let event_id = EventId::parse(key)
+			.map_err(|e| err!(Request(InvalidParam("Invalid event ID {key:?}: {e}"))))?;
+
+		let shorteventid = self
+			.services
+			.short
+			.get_shorteventid(&event_id)
+			.await
+			.map_err(|_| err!(Request(NotFound("Unknown event {event_id}"))))?;
+
+		Ok(self.db.evict_auth_chains(shorteventid) > 0)
+	}
+
+	fn caches(&self) -> Vec<(&'static str, &dyn Resizable)> {
+		vec![("auth_chain_cache", &self.db.auth_chain_cache)]
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -255,3 +296,107 @@ pub fn get_cache_usage(&self) -> (usize, usize) {
 
 #[implement(Service)]
 pub fn clear_cache(&self) { self.db.auth_chain_cache.lock().expect("locked").clear(); }
+
+/// Orders the events of an auth chain so each comes after its auth events.
+/// Events ready at the same time are taken by depth, then event ID, so the
+/// order is the same however the chain was gathered. Auth events outside the
+/// chain are not waited on. Should the events refer to each other in a cycle,
+/// those left are appended in the same order.
+pub fn topological_order(
+	events: Vec<(OwnedEventId, CanonicalJsonObject)>,
+) -> Vec<(OwnedEventId, CanonicalJsonObject)> {
+	let key = |event_id: &OwnedEventId, pdu: &CanonicalJsonObject| {
+		let depth = match pdu.get("depth") {
+			| Some(CanonicalJsonValue::Integer(depth)) => i64::from(*depth),
+			| _ => 0,
+		};
+
+		(depth, event_id.clone())
+	};
+
+	let mut pending: HashMap<OwnedEventId, (usize, CanonicalJsonObject)> = HashMap::new();
+	let mut dependents: HashMap<OwnedEventId, Vec<OwnedEventId>> = HashMap::new();
+	let ids: HashSet<OwnedEventId> = events
+		.iter()
+		.map(|(event_id, _)| event_id.clone())
+		.collect();
+
+	for (event_id, pdu) in events {
+		let auth_events: HashSet<_> = auth_event_ids(&pdu)
+			.filter(|auth_event| {
+				ids.contains(*auth_event) && auth_event.as_str() != event_id.as_str()
+			})
+			.collect();
+
+		for auth_event in &auth_events {
+			dependents
+				.entry((*auth_event).to_owned())
+				.or_default()
+				.push(event_id.clone());
+		}
+
+		let waiting = auth_events.len();
+		pending.insert(event_id, (waiting, pdu));
+	}
+
+	let mut ready: BTreeSet<_> = pending
+		.iter()
+		.filter(|(_, (waiting, _))| *waiting == 0)
+		.map(|(event_id, (_, pdu))| key(event_id, pdu))
+		.collect();
+
+	let mut ordered = Vec::with_capacity(pending.len());
+	loop {
+		if ready.is_empty() {
+			// Only a cycle leaves events waiting; take the first of them anyway.
+			ready.extend(
+				pending
+					.iter()
+					.map(|(event_id, (_, pdu))| key(event_id, pdu))
+					.min(),
+			);
+		}
+
+		let Some((_, event_id)) = ready.pop_first() else {
+			break;
+		};
+
+		let Some((_, pdu)) = pending.remove(&event_id) else {
+			continue;
+		};
+
+		for dependent in dependents.remove(&event_id).into_iter().flatten() {
+			if let Some((waiting, pdu)) = pending.get_mut(&dependent) {
+				*waiting = waiting.saturating_sub(1);
+				if *waiting == 0 {
+					ready.insert(key(&dependent, pdu));
+				}
+			}
+		}
+
+		ordered.push((event_id, pdu));
+	}
+
+	ordered
+}
+
+/// IDs of the auth events of a PDU, whether listed alone as in current room
+/// versions or paired with their hashes as in room versions 1 and 2.
+fn auth_event_ids(pdu: &CanonicalJsonObject) -> impl Iterator<Item = &EventId> + '_ {
+	let auth_events = match pdu.get("auth_events") {
+		| Some(CanonicalJsonValue::Array(auth_events)) => auth_events.as_slice(),
+		| _ => &[],
+	};
+
+	auth_events
+		.iter()
+		.filter_map(|auth_event| match auth_event {
+			| CanonicalJsonValue::String(event_id) => Some(event_id),
+			| CanonicalJsonValue::Array(pair) => match pair.first() {
+				| Some(CanonicalJsonValue::String(event_id)) => Some(event_id),
+				| _ => None,
+			},
+			| _ => None,
+		})
+		.filter_map(|event_id| <&EventId>::try_from(event_id.as_str()).ok())
+}
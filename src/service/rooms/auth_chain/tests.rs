@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use ruma::{CanonicalJsonObject, OwnedEventId};
+use serde_json::json;
+
+use super::{
+	auth_event_ids,
+	data::{Cache, evict_containing, weigh},
+	topological_order,
+};
+
+fn chain(len: u64) -> Arc<[u64]> { (0..len).collect() }
+
+#[test]
+fn evict_auth_chains_of_event() {
+	let mut cache = Cache::new(8, None, weigh);
+	cache.insert(vec![1], chain(1));
+	cache.insert(vec![2], chain(1));
+	cache.insert(vec![1, 3], chain(1));
+	cache.insert(vec![2, 3], chain(1));
+
+	assert_eq!(evict_containing(&mut cache, 1), 2);
+	assert!(!cache.contains_key([1].as_slice()));
+	assert!(!cache.contains_key([1, 3].as_slice()));
+	assert!(cache.contains_key([2].as_slice()));
+	assert!(cache.contains_key([2, 3].as_slice()));
+
+	assert_eq!(evict_containing(&mut cache, 4), 0);
+	assert_eq!(cache.len(), 2);
+	assert_eq!(cache.bytes(), weigh(&chain(1)).saturating_mul(2));
+}
+
+#[test]
+fn auth_chain_cache_byte_budget() {
+	let budget = weigh(&chain(10_000));
+	let mut cache = Cache::new(1_000, Some(budget), weigh);
+
+	// Small chains fill far fewer bytes than entries.
+	for i in 0..100 {
+		cache.insert(vec![i], chain(2));
+		assert!(cache.bytes() <= budget);
+	}
+	assert_eq!(cache.len(), 100);
+
+	// A large chain evicts the least recently used small ones, but no more
+	// than it needs room for.
+	assert!(cache.get_mut([99].as_slice()).is_some());
+	cache.insert(vec![1_000], chain(9_000));
+	assert!(cache.bytes() <= budget);
+	assert!(cache.contains_key([1_000].as_slice()));
+	assert!(cache.contains_key([99].as_slice()));
+	assert!(!cache.contains_key([0].as_slice()));
+	assert!(cache.len() > 2);
+
+	// Another large chain cannot fit beside the first.
+	cache.insert(vec![1_001], chain(5_000));
+	assert!(cache.bytes() <= budget);
+	assert!(!cache.contains_key([1_000].as_slice()));
+	assert!(cache.contains_key([1_001].as_slice()));
+
+	// A chain larger than the whole budget is not cached, nor replaces one.
+	cache.insert(vec![1_001], chain(20_000));
+	assert!(!cache.contains_key([1_001].as_slice()));
+	assert!(cache.bytes() <= budget);
+
+	let total: usize = cache.iter().map(|(_, chain)| weigh(chain)).sum();
+	assert_eq!(cache.bytes(), total);
+
+	cache.clear();
+	assert_eq!(cache.bytes(), 0);
+}
+
+#[test]
+fn auth_chain_cache_without_budget() {
+	let mut cache = Cache::new(4, None, weigh);
+	for i in 0..8 {
+		cache.insert(vec![i], chain(i.saturating_mul(10_000)));
+	}
+
+	assert_eq!(cache.len(), 4);
+	assert!(!cache.contains_key([3].as_slice()));
+	assert!(cache.contains_key([7].as_slice()));
+
+	let total: usize = cache.iter().map(|(_, chain)| weigh(chain)).sum();
+	assert_eq!(cache.bytes(), total);
+}
+
+/// Auth events of a room with a second power levels, a restricted join rule,
+/// an invite, and a kick, as other servers expect them: each after all of its
+/// auth events, then by depth and event ID.
+const ROOM_AUTH_CHAIN: &[(&str, u64, &[&str])] = &[
+	("$create", 1, &[]),
+	("$alice_join", 2, &["$create"]),
+	("$power_levels", 3, &["$create", "$alice_join"]),
+	("$join_rules", 4, &["$create", "$alice_join", "$power_levels"]),
+	("$history_visibility", 5, &["$create", "$alice_join", "$power_levels"]),
+	("$bob_invite", 6, &["$create", "$alice_join", "$power_levels", "$join_rules"]),
+	("$bob_join", 7, &["$create", "$power_levels", "$join_rules", "$bob_invite"]),
+	("$power_levels_2", 8, &["$create", "$alice_join", "$power_levels"]),
+	("$carol_join", 8, &["$create", "$join_rules", "$power_levels_2"]),
+	("$carol_kick", 9, &["$create", "$alice_join", "$power_levels_2", "$carol_join"]),
+];
+
+fn pdu(event_id: &str, depth: u64, auth_events: &[&str]) -> (OwnedEventId, CanonicalJsonObject) {
+	let pdu = json!({
+		"type": "m.room.member",
+		"room_id": "!room:example.com",
+		"depth": depth,
+		"auth_events": auth_events,
+	});
+
+	let event_id = format!("{event_id}:example.com")
+		.try_into()
+		.expect("valid event ID");
+	let pdu = serde_json::from_value(pdu).expect("canonical JSON");
+
+	(event_id, pdu)
+}
+
+fn ordered(events: Vec<(OwnedEventId, CanonicalJsonObject)>) -> Vec<String> {
+	topological_order(events)
+		.into_iter()
+		.map(|(event_id, _)| {
+			event_id
+				.as_str()
+				.trim_end_matches(":example.com")
+				.to_owned()
+		})
+		.collect()
+}
+
+#[test]
+fn auth_chain_in_topological_order() {
+	let expected: Vec<_> = ROOM_AUTH_CHAIN
+		.iter()
+		.map(|(event_id, ..)| (*event_id).to_owned())
+		.collect();
+
+	let mut events: Vec<_> = ROOM_AUTH_CHAIN
+		.iter()
+		.map(|(event_id, depth, auth_events)| {
+			let auth_events: Vec<_> = auth_events
+				.iter()
+				.map(|auth_event| format!("{auth_event}:example.com"))
+				.collect();
+
+			let auth_events: Vec<_> = auth_events.iter().map(String::as_str).collect();
+			pdu(event_id, *depth, &auth_events)
+		})
+		.collect();
+
+	events.reverse();
+	assert_eq!(ordered(events.clone()), expected);
+
+	events.rotate_left(3);
+	assert_eq!(ordered(events), expected, "same order however gathered");
+}
+
+#[test]
+fn auth_chain_order_before_depth() {
+	// An auth event deeper than the event it authorises still comes first.
+	let events = vec![
+		pdu("$create", 1, &[]),
+		pdu("$member", 2, &["$create:example.com", "$power_levels:example.com"]),
+		pdu("$power_levels", 5, &["$create:example.com"]),
+		pdu("$topic", 3, &["$create:example.com"]),
+	];
+
+	assert_eq!(ordered(events), ["$create", "$topic", "$power_levels", "$member"]);
+}
+
+#[test]
+fn auth_chain_order_outside_and_cyclic() {
+	let events = vec![
+		pdu("$b", 2, &["$a:example.com", "$missing:example.com"]),
+		pdu("$a", 1, &["$b:example.com"]),
+		pdu("$c", 3, &["$b:example.com"]),
+	];
+
+	assert_eq!(ordered(events), ["$a", "$b", "$c"]);
+}
+
+#[test]
+fn auth_event_ids_with_hashes() {
+	let pdu: CanonicalJsonObject = serde_json::from_value(json!({
+		"auth_events": [
+			["$create:example.com", { "sha256": "abc" }],
+			["$join:example.com", { "sha256": "def" }],
+		],
+	}))
+	.expect("canonical JSON");
+
+	let ids: Vec<_> = auth_event_ids(&pdu)
+		.map(|event_id| event_id.as_str())
+		.collect();
+	assert_eq!(ids, ["$create:example.com", "$join:example.com"]);
+}
@@ -0,0 +1,387 @@
+//! Direct messages
+//!
+//! Finds the direct message rooms a user has with the same person more than
+//! once, as some clients create a new one instead of reusing the existing
+//! one. Only rooms which nobody but the two of them were ever in are taken
+//! for duplicates; the most recently active of them is kept, and the others
+//! can be tombstoned pointing at it and dropped from the user's `m.direct`.
+
+#[cfg(test)]
+mod tests;
+
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{Result, Server, debug_warn, implement, pdu::PduBuilder};
+use futures::StreamExt;
+use ruma::{
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
+	events::{
+		GlobalAccountDataEventType, direct::DirectEvent,
+		room::tombstone::RoomTombstoneEventContent,
+	},
+};
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use crate::{Dep, account_data, admin, rooms, users};
+
+pub struct Service {
+	services: Services,
+	interrupt: Notify,
+}
+
+struct Services {
+	server: Arc<Server>,
+	account_data: Dep<account_data::Service>,
+	admin: Dep<admin::Service>,
+	metadata: Dep<rooms::metadata::Service>,
+	state: Dep<rooms::state::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+	users: Dep<users::Service>,
+}
+
+/// What is known of a room marked as a direct message with someone.
+#[derive(Clone, Debug, Default)]
+pub struct Candidate {
+	pub room_id: OwnedRoomId,
+
+	/// Everyone who ever joined the room or is invited to it.
+	pub members: BTreeSet<OwnedUserId>,
+
+	/// Everyone joined to the room or invited to it.
+	pub present: BTreeSet<OwnedUserId>,
+
+	/// Count of the latest event in the room; `None` if we have no timeline
+	/// for it.
+	pub last_active: Option<u64>,
+}
+
+/// The direct message rooms of a user with the same person, when the user has
+/// more than one with them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Duplicates {
+	pub other: OwnedUserId,
+
+	/// The room kept, if any of them were taken for duplicates.
+	pub canonical: Option<OwnedRoomId>,
+
+	/// The rooms superseded by it; empty unless the person has more than one
+	/// duplicate.
+	pub extras: Vec<OwnedRoomId>,
+
+	/// The rooms left alone, and why.
+	pub skipped: Vec<(OwnedRoomId, Skip)>,
+}
+
+/// Why a room marked as a direct message is not taken for a duplicate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Skip {
+	/// We know nothing of the room.
+	Unknown,
+
+	/// Someone other than the two was in the room, or is invited to it.
+	Others,
+
+	/// Either of the two is not in the room.
+	Missing,
+}
+
+/// Outcome of merging the duplicates of a user.
+#[derive(Debug, Default)]
+pub struct Merged {
+	/// Rooms tombstoned pointing at their canonical room.
+	pub tombstoned: Vec<OwnedRoomId>,
+
+	/// Rooms which could not be tombstoned, and why.
+	pub failed: Vec<(OwnedRoomId, String)>,
+
+	/// Whether the user's `m.direct` was rewritten.
+	pub rewritten: bool,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				account_data: args.depend::<account_data::Service>("account_data"),
+				admin: args.depend::<admin::Service>("admin"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				users: args.depend::<users::Service>("users"),
+			},
+			interrupt: Notify::new(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let hours = self.services.server.config.dm_duplicates_check_interval;
+		if hours == 0 {
+			return Ok(());
+		}
+
+		let mut i = interval(Duration::from_secs(hours.saturating_mul(60 * 60)));
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		i.tick().await;
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.report().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Sorts out the direct message rooms the user has with the same person more
+/// than once, from the user's `m.direct`.
+#[implement(Service)]
+pub async fn duplicates(&self, user_id: &UserId) -> Result<Vec<Duplicates>> {
+	let direct = self.direct(user_id).await?;
+
+	let mut found = Vec::new();
+	for (other, room_ids) in &direct.content.0 {
+		// m.direct may also list third-party identifiers such as emails
+		let Ok(other) = <&UserId>::try_from(other.as_str()) else {
+			continue;
+		};
+
+		let room_ids: BTreeSet<_> = room_ids.iter().collect();
+		if room_ids.len() < 2 || other == user_id {
+			continue;
+		}
+
+		let mut candidates = Vec::with_capacity(room_ids.len());
+		for room_id in room_ids {
+			candidates.push(self.candidate(room_id).await);
+		}
+
+		found.push(plan(user_id, other, candidates));
+	}
+
+	Ok(found)
+}
+
+/// Tombstones each of the extra rooms pointing at its canonical room when
+/// `tombstone`, as the user, where the user may; then rewrites the user's
+/// `m.direct` to list only the canonical rooms.
+#[implement(Service)]
+pub async fn merge(
+	&self,
+	user_id: &UserId,
+	duplicates: &[Duplicates],
+	tombstone: bool,
+) -> Result<Merged> {
+	let mut merged = Merged::default();
+	if tombstone {
+		for duplicates in duplicates {
+			let Some(canonical) = &duplicates.canonical else {
+				continue;
+			};
+
+			for room_id in &duplicates.extras {
+				match self.tombstone(user_id, room_id, canonical).await {
+					| Ok(()) => merged.tombstoned.push(room_id.clone()),
+					| Err(e) => merged.failed.push((room_id.clone(), e.to_string())),
+				}
+			}
+		}
+	}
+
+	let mut direct = self.direct(user_id).await?;
+	for duplicates in duplicates {
+		let room_ids = direct
+			.content
+			.0
+			.iter_mut()
+			.filter(|(other, _)| other.as_str() == duplicates.other.as_str())
+			.map(|(_, room_ids)| room_ids);
+
+		for room_ids in room_ids {
+			let before = room_ids.len();
+			room_ids.retain(|room_id| !duplicates.extras.contains(room_id));
+			merged.rewritten |= room_ids.len() != before;
+		}
+	}
+
+	if merged.rewritten {
+		self.services
+			.account_data
+			.update(
+				None,
+				user_id,
+				GlobalAccountDataEventType::Direct.to_string().into(),
+				&serde_json::to_value(&direct)?,
+			)
+			.await?;
+	}
+
+	Ok(merged)
+}
+
+#[implement(Service)]
+async fn direct(&self, user_id: &UserId) -> Result<DirectEvent> {
+	self.services
+		.account_data
+		.get_global(user_id, GlobalAccountDataEventType::Direct)
+		.await
+}
+
+#[implement(Service)]
+async fn candidate(&self, room_id: &RoomId) -> Candidate {
+	let state_cache = &self.services.state_cache;
+	let members = state_cache
+		.room_useroncejoined(room_id)
+		.chain(state_cache.room_members_invited(room_id))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let present = state_cache
+		.room_members(room_id)
+		.chain(state_cache.room_members_invited(room_id))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let last_active = if self.services.metadata.exists(room_id).await {
+		self.services
+			.timeline
+			.last_timeline_count(None, room_id)
+			.await
+			.ok()
+			.map(|count| count.into_unsigned())
+	} else {
+		None
+	};
+
+	Candidate {
+		room_id: room_id.to_owned(),
+		members,
+		present,
+		last_active,
+	}
+}
+
+#[implement(Service)]
+async fn tombstone(&self, user_id: &UserId, room_id: &RoomId, replacement: &RoomId) -> Result {
+	let content = RoomTombstoneEventContent::new(
+		"This conversation continues in another room.".to_owned(),
+		replacement.to_owned(),
+	);
+
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(String::new(), &content),
+			user_id,
+			room_id,
+			&state_lock,
+		)
+		.await?;
+
+	Ok(())
+}
+
+/// Tells the admin room which local users have duplicate direct message
+/// rooms.
+#[implement(Service)]
+async fn report(&self) {
+	let users: Vec<OwnedUserId> = self
+		.services
+		.users
+		.list_local_users()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut report = Vec::new();
+	for user_id in &users {
+		match self.duplicates(user_id).await {
+			| Ok(found) => {
+				let (people, extras) = found
+					.iter()
+					.map(|duplicates| duplicates.extras.len())
+					.filter(|&extras| extras > 0)
+					.fold((0_usize, 0_usize), |(people, total), extras| {
+						(people.saturating_add(1), total.saturating_add(extras))
+					});
+
+				if people > 0 {
+					report.push(format!("{user_id}: {extras} extra rooms with {people} people"));
+				}
+			},
+			| Err(e) if e.is_not_found() => {},
+			| Err(e) => debug_warn!(%user_id, "Failed to check direct messages: {e}"),
+		}
+	}
+
+	if report.is_empty() {
+		return;
+	}
+
+	self.services
+		.admin
+		.send_text(&format!(
+			"Local users with duplicate direct message rooms, which `!admin users dedupe-dms \
+			 <user_id>` can merge:\n{}",
+			report.join("\n")
+		))
+		.await;
+}
+
+/// Picks out the duplicate direct message rooms of the user with the other
+/// person among the candidates: those which both are in and nobody else was
+/// ever in, keeping the most recently active.
+#[must_use]
+pub fn plan(user_id: &UserId, other: &UserId, candidates: Vec<Candidate>) -> Duplicates {
+	let pair: BTreeSet<&UserId> = [user_id, other].into();
+
+	let mut skipped = Vec::new();
+	let mut rooms = Vec::new();
+	for candidate in candidates {
+		let members: BTreeSet<&UserId> = candidate.members.iter().map(AsRef::as_ref).collect();
+		let present: BTreeSet<&UserId> = candidate.present.iter().map(AsRef::as_ref).collect();
+		let skip = if candidate.last_active.is_none() {
+			Some(Skip::Unknown)
+		} else if !members.is_subset(&pair) || !present.is_subset(&pair) {
+			Some(Skip::Others)
+		} else if present != pair {
+			Some(Skip::Missing)
+		} else {
+			None
+		};
+
+		match skip {
+			| Some(skip) => skipped.push((candidate.room_id, skip)),
+			| None => rooms.push((candidate.last_active, candidate.room_id)),
+		}
+	}
+
+	rooms.sort_by(|(a_active, a_room), (b_active, b_room)| {
+		b_active.cmp(a_active).then_with(|| a_room.cmp(b_room))
+	});
+
+	let mut rooms = rooms.into_iter().map(|(_, room_id)| room_id);
+	Duplicates {
+		other: other.to_owned(),
+		canonical: rooms.next(),
+		extras: rooms.collect(),
+		skipped,
+	}
+}
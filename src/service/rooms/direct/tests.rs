@@ -0,0 +1,95 @@
+use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId, room_id, user_id};
+
+use super::{Candidate, Skip, plan};
+
+const ALICE: &UserId = user_id!("@alice:example.org");
+const BOB: &UserId = user_id!("@bob:remote.example");
+const EVE: &UserId = user_id!("@eve:example.org");
+
+fn candidate(
+	room_id: &RoomId,
+	members: &[&UserId],
+	present: &[&UserId],
+	last_active: Option<u64>,
+) -> Candidate {
+	let users = |users: &[&UserId]| users.iter().copied().map(ToOwned::to_owned).collect();
+
+	Candidate {
+		room_id: room_id.to_owned(),
+		members: users(members),
+		present: users(present),
+		last_active,
+	}
+}
+
+fn pair(room_id: &RoomId, last_active: u64) -> Candidate {
+	candidate(room_id, &[ALICE, BOB], &[ALICE, BOB], Some(last_active))
+}
+
+fn ids(room_ids: &[&RoomId]) -> Vec<OwnedRoomId> {
+	room_ids.iter().copied().map(ToOwned::to_owned).collect()
+}
+
+#[test]
+fn most_recently_active_kept() {
+	let duplicates = plan(ALICE, BOB, vec![
+		pair(room_id!("!old:example.org"), 10),
+		pair(room_id!("!new:example.org"), 30),
+		pair(room_id!("!mid:example.org"), 20),
+	]);
+
+	let other: OwnedUserId = BOB.to_owned();
+	assert_eq!(duplicates.other, other);
+	assert_eq!(duplicates.canonical.as_deref(), Some(room_id!("!new:example.org")));
+	assert_eq!(
+		duplicates.extras,
+		ids(&[room_id!("!mid:example.org"), room_id!("!old:example.org")])
+	);
+	assert!(duplicates.skipped.is_empty());
+}
+
+#[test]
+fn third_party_history_skipped() {
+	let duplicates = plan(ALICE, BOB, vec![
+		pair(room_id!("!a:example.org"), 10),
+		pair(room_id!("!b:example.org"), 20),
+		candidate(room_id!("!left:example.org"), &[ALICE, BOB, EVE], &[ALICE, BOB], Some(30)),
+		candidate(room_id!("!invited:example.org"), &[ALICE, BOB], &[ALICE, BOB, EVE], Some(40)),
+	]);
+
+	assert_eq!(duplicates.canonical.as_deref(), Some(room_id!("!b:example.org")));
+	assert_eq!(duplicates.extras, ids(&[room_id!("!a:example.org")]));
+	assert_eq!(duplicates.skipped, [
+		(room_id!("!left:example.org").to_owned(), Skip::Others),
+		(room_id!("!invited:example.org").to_owned(), Skip::Others),
+	]);
+}
+
+#[test]
+fn rooms_without_both_skipped() {
+	let duplicates = plan(ALICE, BOB, vec![
+		pair(room_id!("!a:example.org"), 10),
+		candidate(room_id!("!bob_left:example.org"), &[ALICE, BOB], &[ALICE], Some(20)),
+		candidate(room_id!("!alone:example.org"), &[ALICE], &[ALICE], Some(30)),
+		candidate(room_id!("!unknown:example.org"), &[], &[], None),
+	]);
+
+	assert_eq!(duplicates.canonical.as_deref(), Some(room_id!("!a:example.org")));
+	assert!(duplicates.extras.is_empty(), "a single room is no duplicate");
+	assert_eq!(duplicates.skipped, [
+		(room_id!("!bob_left:example.org").to_owned(), Skip::Missing),
+		(room_id!("!alone:example.org").to_owned(), Skip::Missing),
+		(room_id!("!unknown:example.org").to_owned(), Skip::Unknown),
+	]);
+}
+
+#[test]
+fn ties_broken_by_room_id() {
+	let duplicates = plan(ALICE, BOB, vec![
+		pair(room_id!("!b:example.org"), 10),
+		pair(room_id!("!a:example.org"), 10),
+	]);
+
+	assert_eq!(duplicates.canonical.as_deref(), Some(room_id!("!a:example.org")));
+	assert_eq!(duplicates.extras, ids(&[room_id!("!b:example.org")]));
+}
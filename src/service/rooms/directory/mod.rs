@@ -1,24 +1,57 @@
+mod reindex;
+mod search;
+#[cfg(test)]
+mod tests;
+
 use std::sync::Arc;
 
-use conduwuit::{Result, implement, utils::stream::TryIgnore};
-use database::Map;
-use futures::Stream;
-use ruma::{RoomId, api::client::room::Visibility};
+use conduwuit::{
+	PduEvent, Result, implement,
+	utils::{TryFutureExtExt, stream::TryIgnore},
+};
+use database::{Deserialized, Json, Map};
+use futures::{FutureExt, Stream, TryFutureExt, future::join3};
+use ruma::{
+	RoomId,
+	api::client::room::Visibility,
+	events::{
+		TimelineEventType,
+		room::{
+			canonical_alias::RoomCanonicalAliasEventContent, name::RoomNameEventContent,
+			topic::RoomTopicEventContent,
+		},
+	},
+};
+
+pub(crate) use self::reindex::Reindex;
+pub use self::search::{Folded, Rank, fold};
+use crate::{Dep, rooms};
 
 pub struct Service {
 	db: Data,
+	services: Services,
 }
 
 struct Data {
+	publicroomid_folded: Arc<Map>,
 	publicroomids: Arc<Map>,
 }
 
+struct Services {
+	state_accessor: Dep<rooms::state_accessor::Service>,
+}
+
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			db: Data {
+				publicroomid_folded: args.db["publicroomid_folded"].clone(),
 				publicroomids: args.db["publicroomids"].clone(),
 			},
+			services: Services {
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+			},
 		}))
 	}
 
@@ -29,7 +62,10 @@ fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 pub fn set_public(&self, room_id: &RoomId) { self.db.publicroomids.insert(room_id, []); }
 
 #[implement(Service)]
-pub fn set_not_public(&self, room_id: &RoomId) { self.db.publicroomids.remove(room_id); }
+pub fn set_not_public(&self, room_id: &RoomId) {
+	self.db.publicroomids.remove(room_id);
+	self.db.publicroomid_folded.remove(room_id);
+}
 
 #[implement(Service)]
 pub fn public_rooms(&self) -> impl Stream<Item = &RoomId> + Send {
@@ -49,3 +85,98 @@ pub async fn visibility(&self, room_id: &RoomId) -> Visibility {
 		Visibility::Private
 	}
 }
+
+/// How well the public room matches the search term, which is already folded,
+/// if at all.
+#[implement(Service)]
+pub async fn rank(&self, room_id: &RoomId, query: &str) -> Option<Rank> {
+	if query.is_empty() {
+		return Some(Rank::Exact);
+	}
+
+	self.folded(room_id).await.rank(query)
+}
+
+/// The folded searchable fields of the public room. They are kept as the
+/// room's name, topic and canonical alias change, and folded from its state
+/// the first time the room is searched otherwise.
+#[implement(Service)]
+pub async fn folded(&self, room_id: &RoomId) -> Folded {
+	if let Ok(folded) = self
+		.db
+		.publicroomid_folded
+		.get(room_id)
+		.await
+		.deserialized()
+	{
+		return folded;
+	}
+
+	let state_accessor = &self.services.state_accessor;
+	let (name, topic, alias) = join3(
+		state_accessor.get_name(room_id).ok(),
+		state_accessor.get_room_topic(room_id).ok(),
+		state_accessor
+			.get_canonical_alias(room_id)
+			.map_ok(|alias| alias.to_string())
+			.ok(),
+	)
+	.boxed()
+	.await;
+
+	let folded = Folded {
+		name: name.as_deref().map(fold),
+		topic: topic.as_deref().map(fold),
+		alias: alias.as_deref().map(fold),
+	};
+
+	self.db.publicroomid_folded.raw_put(room_id, Json(&folded));
+
+	folded
+}
+
+/// Refolds the searchable field a new state event in a public room changes.
+/// Rooms not yet folded are left to be folded from their state when searched.
+#[implement(Service)]
+pub async fn update_folded(&self, pdu: &PduEvent) {
+	if pdu.state_key.as_deref() != Some("") {
+		return;
+	}
+
+	let Ok(mut folded) = self
+		.db
+		.publicroomid_folded
+		.get(&pdu.room_id)
+		.await
+		.deserialized::<Folded>()
+	else {
+		return;
+	};
+
+	match pdu.kind {
+		| TimelineEventType::RoomName => {
+			folded.name = pdu
+				.get_content::<RoomNameEventContent>()
+				.ok()
+				.map(|content| fold(&content.name));
+		},
+		| TimelineEventType::RoomTopic => {
+			folded.topic = pdu
+				.get_content::<RoomTopicEventContent>()
+				.ok()
+				.map(|content| fold(&content.topic));
+		},
+		| TimelineEventType::RoomCanonicalAlias => {
+			folded.alias = pdu
+				.get_content::<RoomCanonicalAliasEventContent>()
+				.ok()
+				.and_then(|content| content.alias)
+				.map(|alias| fold(alias.as_str()));
+		},
+		| _ => return,
+	}
+
+	self.db
+		.publicroomid_folded
+		.raw_put(&pdu.room_id, Json(&folded));
+}
@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+use conduwuit::{
+	Result, debug_warn,
+	utils::{str_from_bytes, stream::TryIgnore},
+};
+use futures::{StreamExt, stream::BoxStream};
+use ruma::RoomId;
+
+use crate::{Dep, reindex::Task, rooms};
+
+/// Folds the searchable fields of the rooms published before searches were
+/// run over folded fields, so the first searches need not fold them all.
+pub(crate) struct Reindex {
+	directory: Dep<rooms::directory::Service>,
+}
+
+impl Reindex {
+	pub(crate) fn new(args: &crate::Args<'_>) -> Self {
+		Self {
+			directory: args.depend::<rooms::directory::Service>("rooms::directory"),
+		}
+	}
+}
+
+#[async_trait]
+impl Task for Reindex {
+	fn name(&self) -> &'static str { "public_room_search" }
+
+	fn keys<'a>(&'a self, from: &'a [u8]) -> BoxStream<'a, &'a [u8]> {
+		self.directory
+			.db
+			.publicroomids
+			.raw_keys_from(from)
+			.ignore_err()
+			.boxed()
+	}
+
+	async fn process(&self, keys: &[Vec<u8>]) -> Result {
+		for key in keys {
+			let room_id = str_from_bytes(key).ok();
+			let Some(room_id) = room_id.and_then(|room_id| <&RoomId>::try_from(room_id).ok())
+			else {
+				debug_warn!(?key, "Invalid key of a public room");
+				continue;
+			};
+
+			self.directory.folded(room_id).await;
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+/// The searchable fields of a public room, folded by [`fold`].
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Folded {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub name: Option<String>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub topic: Option<String>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub alias: Option<String>,
+}
+
+/// How well a room matches a search, best first.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Rank {
+	/// The name is the search term.
+	Exact,
+
+	/// The name starts with the search term.
+	Prefix,
+
+	/// A word of the name starts with the search term.
+	Word,
+
+	/// The search term is elsewhere in the name.
+	Name,
+
+	/// The canonical alias contains the search term.
+	Alias,
+
+	/// The topic contains the search term.
+	Topic,
+}
+
+impl Folded {
+	/// How well the room matches `query`, which is already folded, if at all.
+	#[must_use]
+	pub fn rank(&self, query: &str) -> Option<Rank> {
+		if query.is_empty() {
+			return Some(Rank::Exact);
+		}
+
+		if let Some(name) = self.name.as_deref() {
+			if name == query {
+				return Some(Rank::Exact);
+			}
+
+			if name.starts_with(query) {
+				return Some(Rank::Prefix);
+			}
+
+			if name.contains(&format!(" {query}")) {
+				return Some(Rank::Word);
+			}
+
+			if name.contains(query) {
+				return Some(Rank::Name);
+			}
+		}
+
+		if self
+			.alias
+			.as_deref()
+			.is_some_and(|alias| alias.contains(query))
+		{
+			return Some(Rank::Alias);
+		}
+
+		if self
+			.topic
+			.as_deref()
+			.is_some_and(|topic| topic.contains(query))
+		{
+			return Some(Rank::Topic);
+		}
+
+		None
+	}
+}
+
+impl Rank {
+	#[must_use]
+	pub fn to_u8(self) -> u8 {
+		match self {
+			| Self::Exact => 0,
+			| Self::Prefix => 1,
+			| Self::Word => 2,
+			| Self::Name => 3,
+			| Self::Alias => 4,
+			| Self::Topic => 5,
+		}
+	}
+
+	#[must_use]
+	pub fn from_u8(rank: u8) -> Option<Self> {
+		Some(match rank {
+			| 0 => Self::Exact,
+			| 1 => Self::Prefix,
+			| 2 => Self::Word,
+			| 3 => Self::Name,
+			| 4 => Self::Alias,
+			| 5 => Self::Topic,
+			| _ => return None,
+		})
+	}
+}
+
+/// Folds text for searching: lowercased, with accents taken off Latin letters
+/// and runs of anything other than letters and digits made a single space, so
+/// "Rust-Lang: Café" and "rust lang cafe" fold alike.
+#[must_use]
+pub fn fold(text: &str) -> String {
+	let mut folded = String::with_capacity(text.len());
+	let mut space = false;
+	for c in text.chars().flat_map(char::to_lowercase) {
+		if !c.is_alphanumeric() {
+			space = !folded.is_empty();
+			continue;
+		}
+
+		if space {
+			folded.push(' ');
+			space = false;
+		}
+
+		match unaccent(c) {
+			| Some(base) => folded.push_str(base),
+			| None => folded.push(c),
+		}
+	}
+
+	folded
+}
+
+/// The unaccented form of a lowercase Latin letter with diacritics.
+fn unaccent(c: char) -> Option<&'static str> {
+	Some(match c {
+		| 'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+		| 'æ' => "ae",
+		| 'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+		| 'ď' | 'đ' | 'ð' => "d",
+		| 'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+		| 'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+		| 'ĥ' | 'ħ' => "h",
+		| 'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+		| 'ĵ' => "j",
+		| 'ķ' => "k",
+		| 'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+		| 'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+		| 'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+		| 'œ' => "oe",
+		| 'ŕ' | 'ŗ' | 'ř' => "r",
+		| 'ś' | 'ŝ' | 'ş' | 'š' => "s",
+		| 'ß' => "ss",
+		| 'ţ' | 'ť' | 'ŧ' => "t",
+		| 'þ' => "th",
+		| 'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+		| 'ŵ' => "w",
+		| 'ý' | 'ÿ' | 'ŷ' => "y",
+		| 'ź' | 'ż' | 'ž' => "z",
+		| _ => return None,
+	})
+}
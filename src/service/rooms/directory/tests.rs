@@ -0,0 +1,72 @@
+use super::{Folded, Rank, fold};
+
+fn room(name: &str, topic: &str, alias: &str) -> Folded {
+	Folded {
+		name: Some(fold(name)),
+		topic: Some(fold(topic)),
+		alias: Some(fold(alias)),
+	}
+}
+
+#[test]
+fn fold_case_accents_punctuation() {
+	assert_eq!(fold("The Rust Programming Language"), "the rust programming language");
+	assert_eq!(fold("  Rust-Lang: Café!  "), "rust lang cafe");
+	assert_eq!(fold("#rust:matrix.org"), "rust matrix org");
+	assert_eq!(fold("Straße Ærø"), "strasse aero");
+	assert_eq!(fold("ÉCOLE"), "ecole");
+	assert_eq!(fold("日本語 チャット"), "日本語 チャット");
+	assert_eq!(fold("!!!"), "");
+}
+
+#[test]
+fn rank_by_where_it_matches() {
+	let rust = room("Rust", "", "#rust:example.org");
+	let prefix = room("Rustaceans", "", "");
+	let word = room("The Rust Programming Language", "", "");
+	let inner = room("Trusted", "", "");
+	let alias = room("Crabs", "", "#rust-crabs:example.org");
+	let topic = room("Crabs", "All about RUST", "");
+	let none = room("Python", "snakes", "#python:example.org");
+
+	let query = fold("rust");
+	assert_eq!(rust.rank(&query), Some(Rank::Exact));
+	assert_eq!(prefix.rank(&query), Some(Rank::Prefix));
+	assert_eq!(word.rank(&query), Some(Rank::Word));
+	assert_eq!(inner.rank(&query), Some(Rank::Name));
+	assert_eq!(alias.rank(&query), Some(Rank::Alias));
+	assert_eq!(topic.rank(&query), Some(Rank::Topic));
+	assert_eq!(none.rank(&query), None);
+}
+
+#[test]
+fn rank_folded_query() {
+	let cafe = room("Café Society", "", "");
+
+	assert_eq!(cafe.rank(&fold("CAFÉ")), Some(Rank::Prefix));
+	assert_eq!(cafe.rank(&fold("cafe-society")), Some(Rank::Exact));
+	assert_eq!(cafe.rank(&fold("society")), Some(Rank::Word));
+}
+
+#[test]
+fn rank_without_fields() {
+	let unnamed = Folded::default();
+
+	assert_eq!(unnamed.rank(""), Some(Rank::Exact), "empty search term matches all");
+	assert_eq!(unnamed.rank("rust"), None);
+}
+
+#[test]
+fn rank_order() {
+	assert!(Rank::Exact < Rank::Prefix);
+	assert!(Rank::Prefix < Rank::Word);
+	assert!(Rank::Word < Rank::Name);
+	assert!(Rank::Name < Rank::Alias);
+	assert!(Rank::Alias < Rank::Topic);
+
+	for rank in [Rank::Exact, Rank::Prefix, Rank::Word, Rank::Name, Rank::Alias, Rank::Topic] {
+		assert_eq!(Rank::from_u8(rank.to_u8()), Some(rank));
+	}
+
+	assert_eq!(Rank::from_u8(6), None);
+}
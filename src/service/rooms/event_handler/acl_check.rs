@@ -1,42 +1,141 @@
+use std::sync::Arc;
+
 use conduwuit::{Err, Result, debug, implement, trace, warn};
+use regex::RegexSet;
 use ruma::{
 	RoomId, ServerName,
 	events::{StateEventType, room::server_acl::RoomServerAclEventContent},
 };
 
+/// The server ACL of a room compiled for matching servers against it.
+#[derive(Debug)]
+pub struct Acl {
+	allow: RegexSet,
+	deny: RegexSet,
+	allow_ip_literals: bool,
+}
+
 /// Returns Ok if the acl allows the server
 #[implement(super::Service)]
 #[tracing::instrument(skip_all, level = "debug")]
 pub async fn acl_check(&self, server_name: &ServerName, room_id: &RoomId) -> Result {
-	let Ok(acl_event_content) = self
+	let Some(acl) = self.room_acl(room_id).await else {
+		trace!(%room_id, "No ACL in room");
+		return Ok(());
+	};
+
+	acl.check(server_name)
+		.inspect(|()| trace!("server {server_name} is allowed by ACL"))
+		.inspect_err(|_| debug!("Server {server_name} was denied by room ACL in {room_id}"))
+}
+
+/// The current server ACL of the room, compiled the first time it is needed
+/// after each change to the room's state. Rooms without one, or with one which
+/// is broken and so ignored, have none.
+#[implement(super::Service)]
+pub async fn room_acl(&self, room_id: &RoomId) -> Option<Arc<Acl>> {
+	let shortstatehash = self
+		.services
+		.state
+		.get_room_shortstatehash(room_id)
+		.await
+		.ok()?;
+
+	if let Some((cached, acl)) = self
+		.acl_cache
+		.read()
+		.expect("locked for reading")
+		.get(room_id)
+	{
+		if *cached == shortstatehash {
+			return acl.clone();
+		}
+	}
+
+	let acl = self
 		.services
 		.state_accessor
-		.room_state_get_content(room_id, &StateEventType::RoomServerAcl, "")
+		.state_get_content(shortstatehash, &StateEventType::RoomServerAcl, "")
 		.await
-		.map(|c: RoomServerAclEventContent| c)
 		.inspect(|acl| trace!(%room_id, "ACL content found: {acl:?}"))
-		.inspect_err(|e| trace!(%room_id, "No ACL content found: {e:?}"))
-	else {
-		return Ok(());
-	};
+		.ok()
+		.and_then(|content: RoomServerAclEventContent| Acl::new(room_id, &content))
+		.map(Arc::new);
 
-	if acl_event_content.allow.is_empty() {
-		warn!(%room_id, "Ignoring broken ACL event (allow key is empty)");
-		return Ok(());
+	self.acl_cache
+		.write()
+		.expect("locked for writing")
+		.insert(room_id.to_owned(), (shortstatehash, acl.clone()));
+
+	acl
+}
+
+impl Acl {
+	/// Compiles the ACL, unless it is broken and so ignored.
+	#[must_use]
+	pub fn new(room_id: &RoomId, content: &RoomServerAclEventContent) -> Option<Self> {
+		if content.allow.is_empty() {
+			warn!(%room_id, "Ignoring broken ACL event (allow key is empty)");
+			return None;
+		}
+
+		if content.deny.iter().any(|deny| deny == "*")
+			&& content.allow.iter().any(|allow| allow == "*")
+		{
+			warn!(%room_id, "Ignoring broken ACL event (allow key and deny key both contain wildcard \"*\"");
+			return None;
+		}
+
+		let compile = |globs: &[String]| RegexSet::new(globs.iter().map(|glob| glob_regex(glob)));
+		match (compile(&content.allow), compile(&content.deny)) {
+			| (Ok(allow), Ok(deny)) => Some(Self {
+				allow,
+				deny,
+				allow_ip_literals: content.allow_ip_literals,
+			}),
+			| (Err(e), _) | (_, Err(e)) => {
+				warn!(%room_id, "Ignoring ACL event which cannot be compiled: {e}");
+				None
+			},
+		}
 	}
 
-	if acl_event_content.deny.contains(&String::from("*"))
-		&& acl_event_content.allow.contains(&String::from("*"))
-	{
-		warn!(%room_id, "Ignoring broken ACL event (allow key and deny key both contain wildcard \"*\"");
-		return Ok(());
+	/// Whether the server is allowed: it must match an allowed glob and none
+	/// of the denied ones, and not be an IP literal unless those are allowed.
+	/// Ports are not matched.
+	#[must_use]
+	pub fn is_allowed(&self, server_name: &ServerName) -> bool {
+		if !self.allow_ip_literals && server_name.is_ip_literal() {
+			return false;
+		}
+
+		let host = server_name.host();
+		!self.deny.is_match(host) && self.allow.is_match(host)
 	}
 
-	if acl_event_content.is_allowed(server_name) {
-		trace!("server {server_name} is allowed by ACL");
+	/// Returns Ok if the ACL allows the server, otherwise M_FORBIDDEN.
+	pub fn check(&self, server_name: &ServerName) -> Result {
+		if !self.is_allowed(server_name) {
+			return Err!(Request(Forbidden("Server was denied by room ACL")));
+		}
+
 		Ok(())
-	} else {
-		debug!("Server {server_name} was denied by room ACL in {room_id}");
-		Err!(Request(Forbidden("Server was denied by room ACL")))
 	}
 }
+
+/// The anchored regex of an ACL glob, where `*` matches any run of
+/// characters and `?` any one.
+fn glob_regex(glob: &str) -> String {
+	let mut out = String::with_capacity(glob.len().saturating_add(8));
+	out.push_str("^(?s:");
+	for c in glob.chars() {
+		match c {
+			| '*' => out.push_str(".*"),
+			| '?' => out.push('.'),
+			| c => out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+		}
+	}
+
+	out.push_str(")$");
+	out
+}
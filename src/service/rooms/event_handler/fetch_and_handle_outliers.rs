@@ -1,6 +1,6 @@
 use std::{
 	collections::{BTreeMap, HashSet, VecDeque, hash_map},
-	time::Instant,
+	time::{Duration, Instant},
 };
 
 use conduwuit::{
@@ -11,7 +11,11 @@
 	CanonicalJsonValue, OwnedEventId, RoomId, ServerName, api::federation::event::get_event,
 };
 
-use super::get_room_version_id;
+use super::{Delivery, get_room_version_id};
+
+/// How long each server asked for an event has to answer before the next is
+/// asked instead.
+const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Find the event and auth it. Once the event is validated (steps 1 - 8)
 /// it is appended to the outliers Tree.
@@ -21,7 +25,7 @@
 /// a. Look in the main timeline (pduid_pdu tree)
 /// b. Look at outlier pdu tree
 /// c. Ask origin server over federation
-/// d. TODO: Ask other servers over federation?
+/// d. Ask other servers in the room over federation
 #[implement(super::Service)]
 pub(super) async fn fetch_and_handle_outliers<'a>(
 	&self,
@@ -29,6 +33,7 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 	events: &'a [OwnedEventId],
 	create_event: &'a PduEvent,
 	room_id: &'a RoomId,
+	delivery: Delivery,
 ) -> Vec<(PduEvent, Option<BTreeMap<String, CanonicalJsonValue>>)> {
 	let back_off = |id| match self
 		.services
@@ -46,6 +51,8 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 		},
 	};
 
+	// servers to ask for events, found once any has to be asked for
+	let mut candidates = None;
 	let mut events_with_auth_events = Vec::with_capacity(events.len());
 	for id in events {
 		// a. Look in the main timeline (pduid_pdu tree)
@@ -57,7 +64,8 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 			continue;
 		}
 
-		// c. Ask origin server over federation
+		// c. Ask origin server over federation, then
+		// d. other servers in the room
 		// We also handle its auth chain here so we don't get a stack overflow in
 		// handle_outlier_pdu.
 		let mut todo_auth_events: VecDeque<_> = [id.clone()].into();
@@ -99,18 +107,26 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 				continue;
 			}
 
+			if candidates.is_none() {
+				candidates = Some(self.candidate_servers(room_id, Some(origin)).await);
+			}
+
 			debug!("Fetching {next_id} over federation.");
+			let servers = candidates.as_deref().unwrap_or_default();
 			match self
-				.services
-				.sending
-				.send_federation_request(origin, get_event::v1::Request {
-					event_id: (*next_id).to_owned(),
-					include_unredacted_content: None,
+				.ask_servers(room_id, servers, EVENT_TIMEOUT, |server| {
+					self.services.sending.send_federation_request(
+						server,
+						get_event::v1::Request {
+							event_id: (*next_id).to_owned(),
+							include_unredacted_content: None,
+						},
+					)
 				})
 				.await
 			{
-				| Ok(res) => {
-					debug!("Got {next_id} over federation");
+				| Some((server, res)) => {
+					debug!("Got {next_id} over federation from {server}");
 					let Ok(room_version_id) = get_room_version_id(create_event) else {
 						back_off((*next_id).to_owned());
 						continue;
@@ -151,11 +167,14 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 						warn!("Auth event list invalid");
 					}
 
-					events_in_reverse_order.push((next_id.clone(), value));
+					events_in_reverse_order.push((next_id.clone(), value, server.to_owned()));
 					events_all.insert(next_id);
 				},
-				| Err(e) => {
-					debug_error!("Failed to fetch event {next_id}: {e}");
+				| None => {
+					debug_error!(
+						"Failed to fetch event {next_id} from {} servers",
+						servers.len()
+					);
 					back_off((*next_id).to_owned());
 				},
 			}
@@ -173,7 +192,7 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 			pdus.push((local_pdu.clone(), None));
 		}
 
-		for (next_id, value) in events_in_reverse_order.into_iter().rev() {
+		for (next_id, value, server) in events_in_reverse_order.into_iter().rev() {
 			if let Some((time, tries)) = self
 				.services
 				.globals
@@ -206,10 +225,20 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 			))
 			.await
 			{
-				| Ok((pdu, json)) =>
+				| Ok((pdu, json)) => {
+					// the auth chain fetched along with the event
+					let delivery = if next_id == *id {
+						delivery
+					} else {
+						Delivery::MissingAuth
+					};
+					self.record_provenance(&next_id, &server, delivery, false)
+						.await;
+
 					if next_id == *id {
 						pdus.push((pdu, Some(json)));
-					},
+					}
+				},
 				| Err(e) => {
 					warn!("Authentication of event {next_id} failed: {e:?}");
 					back_off(next_id);
@@ -10,7 +10,7 @@
 	uint,
 };
 
-use super::check_room_id;
+use super::{Delivery, check_room_id};
 
 #[implement(super::Service)]
 #[tracing::instrument(
@@ -40,7 +40,13 @@ pub(super) async fn fetch_prev(
 		self.services.server.check_running()?;
 
 		match self
-			.fetch_and_handle_outliers(origin, &[prev_event_id.clone()], create_event, room_id)
+			.fetch_and_handle_outliers(
+				origin,
+				&[prev_event_id.clone()],
+				create_event,
+				room_id,
+				Delivery::MissingPrev,
+			)
 			.boxed()
 			.await
 			.pop()
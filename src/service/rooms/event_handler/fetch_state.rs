@@ -7,6 +7,7 @@
 	events::StateEventType,
 };
 
+use super::Delivery;
 use crate::rooms::short::ShortStateKey;
 
 /// Call /state_ids to find out what the state at this pdu is. We trust the
@@ -33,11 +34,14 @@ pub(super) async fn fetch_state(
 			event_id: event_id.to_owned(),
 		})
 		.await
-		.inspect_err(|e| debug_warn!("Fetching state for event failed: {e}"))?;
+		.map_err(|e| {
+			debug_warn!("Fetching state for event failed: {e}");
+			Error::remote_fetch_failed(origin, "/state_ids", &e)
+		})?;
 
 	debug!("Fetching state events");
 	let state_vec = self
-		.fetch_and_handle_outliers(origin, &res.pdu_ids, create_event, room_id)
+		.fetch_and_handle_outliers(origin, &res.pdu_ids, create_event, room_id, Delivery::State)
 		.boxed()
 		.await;
 
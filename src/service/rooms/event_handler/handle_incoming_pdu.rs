@@ -13,6 +13,7 @@
 };
 use ruma::{CanonicalJsonValue, EventId, RoomId, ServerName, UserId, events::StateEventType};
 
+use super::Delivery;
 use crate::rooms::timeline::RawPduId;
 
 /// When receiving an event one needs to:
@@ -56,6 +57,7 @@ pub async fn handle_incoming_pdu<'a>(
 	event_id: &'a EventId,
 	value: BTreeMap<String, CanonicalJsonValue>,
 	is_timeline_event: bool,
+	delivery: Delivery,
 ) -> Result<Option<RawPduId>> {
 	// 1. Skip the PDU if we already have it as a timeline event
 	if let Ok(pdu_id) = self.services.timeline.get_pdu_id(event_id).await {
@@ -110,6 +112,9 @@ pub async fn handle_incoming_pdu<'a>(
 		.handle_outlier_pdu(origin, create_event, event_id, room_id, value, false)
 		.await?;
 
+	self.record_provenance(event_id, origin, delivery, is_timeline_event)
+		.await;
+
 	// 8. if not timeline event: stop
 	if !is_timeline_event {
 		return Ok(None);
@@ -9,7 +9,7 @@
 	api::client::error::ErrorKind, events::StateEventType,
 };
 
-use super::{check_room_id, get_room_version_id, to_room_version};
+use super::{Delivery, check_room_id, get_room_version_id, to_room_version};
 
 #[implement(super::Service)]
 #[allow(clippy::too_many_arguments)]
@@ -33,7 +33,7 @@ pub(super) async fn handle_outlier_pdu<'a>(
 	let mut val = match self
 		.services
 		.server_keys
-		.verify_event(&value, Some(&room_version_id))
+		.verify_event_from(origin, &value, &room_version_id)
 		.await
 	{
 		| Ok(ruma::signatures::Verified::All) => value,
@@ -82,6 +82,7 @@ pub(super) async fn handle_outlier_pdu<'a>(
 			&incoming_pdu.auth_events,
 			create_event,
 			room_id,
+			Delivery::MissingAuth,
 		))
 		.await;
 	}
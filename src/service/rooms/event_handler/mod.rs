@@ -6,8 +6,12 @@
 mod handle_outlier_pdu;
 mod handle_prev_pdu;
 mod parse_incoming_pdu;
+mod provenance;
 mod resolve_state;
+mod servers;
 mod state_at_incoming;
+#[cfg(test)]
+mod tests;
 mod upgrade_outlier_pdu;
 
 use std::{
@@ -18,20 +22,33 @@
 };
 
 use async_trait::async_trait;
-use conduwuit::{Err, PduEvent, Result, RoomVersion, Server, utils::MutexMap};
+use conduwuit::{Err, PduEvent, Result, RoomVersion, Server, err, utils::MutexMap};
+use database::Map;
 use ruma::{
 	OwnedEventId, OwnedRoomId, RoomId, RoomVersionId,
 	events::room::create::RoomCreateEventContent,
 };
 
-use crate::{Dep, globals, rooms, sending, server_keys};
+pub use self::{
+	acl_check::Acl,
+	provenance::{Delivery, Provenance},
+	servers::Failures,
+};
+use crate::{Dep, globals, rooms, rooms::short::ShortStateHash, sending, server_keys};
 
 pub struct Service {
 	pub mutex_federation: RoomMutexMap,
 	pub federation_handletime: StdRwLock<HandleTimeMap>,
+	pub server_failures: Failures,
+	acl_cache: StdRwLock<AclCache>,
+	db: Data,
 	services: Services,
 }
 
+struct Data {
+	shorteventid_provenance: Arc<Map>,
+}
+
 struct Services {
 	globals: Dep<globals::Service>,
 	sending: Dep<sending::Service>,
@@ -43,13 +60,17 @@ struct Services {
 	short: Dep<rooms::short::Service>,
 	state: Dep<rooms::state::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
 	state_compressor: Dep<rooms::state_compressor::Service>,
+	state_growth: Dep<rooms::state_growth::Service>,
+	freeze: Dep<rooms::freeze::Service>,
 	timeline: Dep<rooms::timeline::Service>,
 	server: Arc<Server>,
 }
 
 type RoomMutexMap = MutexMap<OwnedRoomId, ()>;
 type HandleTimeMap = HashMap<OwnedRoomId, (OwnedEventId, Instant)>;
+type AclCache = HashMap<OwnedRoomId, (ShortStateHash, Option<Arc<Acl>>)>;
 
 #[async_trait]
 impl crate::Service for Service {
@@ -57,6 +78,11 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			mutex_federation: RoomMutexMap::new(),
 			federation_handletime: HandleTimeMap::new().into(),
+			server_failures: Failures::default(),
+			acl_cache: AclCache::new().into(),
+			db: Data {
+				shorteventid_provenance: args.db["shorteventid_provenance"].clone(),
+			},
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
@@ -69,8 +95,11 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 				state_compressor: args
 					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
+				state_growth: args.depend::<rooms::state_growth::Service>("rooms::state_growth"),
+				freeze: args.depend::<rooms::freeze::Service>("rooms::freeze"),
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 				server: args.server.clone(),
 			},
@@ -88,9 +117,26 @@ async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
 			.len();
 		writeln!(out, "federation_handletime: {federation_handletime}")?;
 
+		let server_failures = self.server_failures.len();
+		writeln!(out, "server_failures: {server_failures}")?;
+
+		let acl_cache = self.acl_cache.read().expect("locked for reading").len();
+		writeln!(out, "acl_cache: {acl_cache}")?;
+
 		Ok(())
 	}
 
+	async fn clear_cache(&self) { self.acl_cache.write().expect("locked for writing").clear(); }
+
+	async fn clear_cache_entry(&self, key: &str) -> Result<bool> {
+		let room_id = RoomId::parse(key)
+			.map_err(|e| err!(Request(InvalidParam("Invalid room ID {key:?}: {e}"))))?;
+
+		let mut cache = self.acl_cache.write().expect("locked for writing");
+
+		Ok(cache.remove(&room_id).is_some())
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -0,0 +1,195 @@
+//! Event provenance
+//!
+//! Which server delivered a remote event to us, how and when, as opposed to the
+//! origin the event claims. It is recorded once, when the event is first
+//! accepted, packed with the delivering server's short ID.
+
+use std::fmt;
+
+use conduwuit::{Result, err, implement, utils::millis_since_unix_epoch};
+use ruma::{EventId, OwnedServerName, ServerName};
+
+use crate::rooms::short::{ShortEventId, ShortServerName};
+
+/// How an event came to us.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Delivery {
+	/// Pushed to us in a federation transaction.
+	Transaction,
+
+	/// Paginated backwards from a remote server.
+	Backfill,
+
+	/// Fetched as a missing previous event of another.
+	MissingPrev,
+
+	/// Fetched as a missing auth event of another.
+	MissingAuth,
+
+	/// Fetched as part of the state at an event.
+	State,
+
+	/// Sent with a membership handshake, e.g. `/send_join` either way.
+	Membership,
+
+	/// Fetched by an admin command.
+	Admin,
+}
+
+/// Where, how and when we got a remote event.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Provenance {
+	/// The server which delivered the event, not necessarily its origin.
+	pub server: OwnedServerName,
+
+	pub delivery: Delivery,
+
+	/// Whether it was handled as a timeline event rather than an outlier.
+	pub timeline: bool,
+
+	/// When we received it, in milliseconds since the unix epoch.
+	pub received: u64,
+}
+
+/// A packed record: the delivery and timeline flag in one byte, then the
+/// receive time and the delivering server's short ID.
+type Packed = [u8; PACKED_LEN];
+
+const PACKED_LEN: usize = 1 + size_of::<u64>() + size_of::<ShortServerName>();
+
+const TIMELINE: u8 = 0x80;
+
+/// Records that the server delivered the event, unless we already knew where
+/// the event came from.
+#[implement(super::Service)]
+pub(super) async fn record_provenance(
+	&self,
+	event_id: &EventId,
+	server: &ServerName,
+	delivery: Delivery,
+	timeline: bool,
+) {
+	const BUFSIZE: usize = size_of::<ShortEventId>();
+
+	let shorteventid = self
+		.services
+		.short
+		.get_or_create_shorteventid(event_id)
+		.await;
+
+	if self
+		.db
+		.shorteventid_provenance
+		.acontains::<BUFSIZE, _>(&shorteventid)
+		.await
+	{
+		return;
+	}
+
+	let shortservername = self
+		.services
+		.short
+		.get_or_create_shortservername(server)
+		.await;
+
+	let packed = pack(delivery, timeline, millis_since_unix_epoch(), shortservername);
+	self.db
+		.shorteventid_provenance
+		.aput_raw::<BUFSIZE, _, _>(shorteventid, packed);
+}
+
+/// Where, how and when we got the event, if it was received over federation
+/// since provenance is recorded.
+#[implement(super::Service)]
+pub async fn get_provenance(&self, event_id: &EventId) -> Result<Provenance> {
+	const BUFSIZE: usize = size_of::<ShortEventId>();
+
+	let shorteventid = self.services.short.get_shorteventid(event_id).await?;
+	let packed = self
+		.db
+		.shorteventid_provenance
+		.aqry::<BUFSIZE, _>(&shorteventid)
+		.await?;
+
+	let (delivery, timeline, received, shortservername) = unpack(&packed)
+		.ok_or_else(|| err!(Database("Invalid provenance record for {event_id}")))?;
+
+	let server = self
+		.services
+		.short
+		.get_servername_from_short(shortservername)
+		.await?;
+
+	Ok(Provenance { server, delivery, timeline, received })
+}
+
+#[must_use]
+pub(super) fn pack(
+	delivery: Delivery,
+	timeline: bool,
+	received: u64,
+	shortservername: ShortServerName,
+) -> Packed {
+	let mut packed = [0_u8; PACKED_LEN];
+	let (flags, rest) = packed.split_at_mut(1);
+	let (received_bytes, server_bytes) = rest.split_at_mut(size_of::<u64>());
+
+	flags.fill(delivery.to_u8() | if timeline { TIMELINE } else { 0 });
+	received_bytes.copy_from_slice(&received.to_be_bytes());
+	server_bytes.copy_from_slice(&shortservername.to_be_bytes());
+
+	packed
+}
+
+#[must_use]
+pub(super) fn unpack(packed: &[u8]) -> Option<(Delivery, bool, u64, ShortServerName)> {
+	let (&flags, rest) = packed.split_first()?;
+	let (received, shortservername) = rest.split_at_checked(size_of::<u64>())?;
+
+	let delivery = Delivery::from_u8(flags & !TIMELINE)?;
+	let received = u64::from_be_bytes(received.try_into().ok()?);
+	let shortservername = ShortServerName::from_be_bytes(shortservername.try_into().ok()?);
+
+	Some((delivery, flags & TIMELINE != 0, received, shortservername))
+}
+
+impl Delivery {
+	fn to_u8(self) -> u8 {
+		match self {
+			| Self::Transaction => 1,
+			| Self::Backfill => 2,
+			| Self::MissingPrev => 3,
+			| Self::MissingAuth => 4,
+			| Self::State => 5,
+			| Self::Membership => 6,
+			| Self::Admin => 7,
+		}
+	}
+
+	fn from_u8(byte: u8) -> Option<Self> {
+		Some(match byte {
+			| 1 => Self::Transaction,
+			| 2 => Self::Backfill,
+			| 3 => Self::MissingPrev,
+			| 4 => Self::MissingAuth,
+			| 5 => Self::State,
+			| 6 => Self::Membership,
+			| 7 => Self::Admin,
+			| _ => return None,
+		})
+	}
+}
+
+impl fmt::Display for Delivery {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			| Self::Transaction => "transaction",
+			| Self::Backfill => "backfill",
+			| Self::MissingPrev => "missing prev event",
+			| Self::MissingAuth => "missing auth event",
+			| Self::State => "state fetch",
+			| Self::Membership => "membership handshake",
+			| Self::Admin => "admin fetch",
+		})
+	}
+}
@@ -5,7 +5,7 @@
 };
 
 use conduwuit::{
-	Error, Result, err, implement,
+	Error, Result, err, error, implement,
 	state_res::{self, StateMap},
 	trace,
 	utils::stream::{IterStream, ReadyExt, TryWidebandExt, WidebandExt, automatic_width},
@@ -28,7 +28,10 @@ pub async fn resolve_state(
 		.services
 		.state
 		.get_room_shortstatehash(room_id)
-		.map_err(|e| err!(Database(error!("No state for {room_id:?}: {e:?}"))))
+		.map_err(|e| {
+			error!(?room_id, "No state for room: {e}");
+			Error::not_found(format!("State of {room_id}"))
+		})
 		.await?;
 
 	let current_state_ids: HashMap<_, _> = self
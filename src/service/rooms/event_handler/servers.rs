@@ -0,0 +1,208 @@
+use std::{
+	cmp::Reverse,
+	collections::HashMap,
+	future::Future,
+	sync::RwLock,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{
+	Result, debug, debug_warn, implement,
+	utils::stream::{ReadyExt, TryIgnore},
+};
+use futures::StreamExt;
+use ruma::{
+	Int, OwnedRoomId, OwnedServerName, RoomId, ServerName,
+	events::{StateEventType, room::power_levels::RoomPowerLevelsEventContent},
+};
+
+/// How long a server which failed to give us the events of a room is not
+/// asked for them again.
+pub const FAILURE_COOLDOWN: Duration = Duration::from_secs(10 * 60);
+
+/// How many of the latest events of a room are looked at for which servers
+/// were active in it lately.
+const RECENT_EVENTS: usize = 100;
+
+/// Servers which failed to give us the events of a room lately, by room and
+/// server.
+#[derive(Debug, Default)]
+pub struct Failures {
+	failed: RwLock<HashMap<(OwnedRoomId, OwnedServerName), Instant>>,
+}
+
+/// A server of a room, ranked for asking it for the room's events.
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(super) struct Candidate {
+	/// Highest power level among the users of the server.
+	pub(super) power: Reverse<Int>,
+
+	/// How many of the latest events of the room precede the latest sent by
+	/// one of its users; `usize::MAX` if none of them did.
+	pub(super) inactivity: usize,
+
+	pub(super) server: OwnedServerName,
+}
+
+/// Servers to ask in turn for events of a room: first `first` when given,
+/// usually the server which sent us what referenced the events, then the
+/// other servers in the room, having the most powerful and most recently
+/// active users first. Servers which failed lately are left out, other than
+/// `first`.
+#[implement(super::Service)]
+pub async fn candidate_servers(
+	&self,
+	room_id: &RoomId,
+	first: Option<&ServerName>,
+) -> Vec<OwnedServerName> {
+	let power_levels: RoomPowerLevelsEventContent = self
+		.services
+		.state_accessor
+		.room_state_get_content(room_id, &StateEventType::RoomPowerLevels, "")
+		.await
+		.unwrap_or_default();
+
+	let mut recent = HashMap::<OwnedServerName, usize>::new();
+	self.services
+		.timeline
+		.pdus_rev(None, room_id, None)
+		.ignore_err()
+		.take(RECENT_EVENTS)
+		.enumerate()
+		.ready_for_each(|(position, (_, pdu))| {
+			recent
+				.entry(pdu.sender.server_name().to_owned())
+				.or_insert(position);
+		})
+		.await;
+
+	let candidates: Vec<_> = self
+		.services
+		.state_cache
+		.room_servers(room_id)
+		.filter(|&server| {
+			let ours = self.services.globals.server_is_ours(server);
+			let first = first.is_some_and(|first| first == server);
+			async move { !ours && !first }
+		})
+		.map(|server| Candidate {
+			power: Reverse(
+				power_levels
+					.users
+					.iter()
+					.filter(|(user_id, _)| user_id.server_name() == server)
+					.map(|(_, &level)| level)
+					.max()
+					.unwrap_or(power_levels.users_default),
+			),
+			inactivity: recent.get(server).copied().unwrap_or(usize::MAX),
+			server: server.to_owned(),
+		})
+		.collect()
+		.await;
+
+	let now = Instant::now();
+	first
+		.map(ToOwned::to_owned)
+		.into_iter()
+		.chain(
+			rank(candidates)
+				.into_iter()
+				.filter(|server| !self.server_failures.cooling(room_id, server, now)),
+		)
+		.collect()
+}
+
+/// Asks the servers in turn until one of them answers, giving each of them
+/// `timeout` to answer. Those which fail are left out by `candidate_servers`
+/// for a while; those which answer are forgiven for failing before.
+#[implement(super::Service)]
+pub async fn ask_servers<'a, T, F, Fut>(
+	&self,
+	room_id: &RoomId,
+	servers: &'a [OwnedServerName],
+	timeout: Duration,
+	ask: F,
+) -> Option<(&'a ServerName, T)>
+where
+	F: FnMut(&'a ServerName) -> Fut + Send,
+	Fut: Future<Output = Result<T>> + Send,
+	T: Send,
+{
+	ask_in_turn(&self.server_failures, room_id, servers, timeout, ask).await
+}
+
+pub(super) async fn ask_in_turn<'a, T, F, Fut>(
+	failures: &Failures,
+	room_id: &RoomId,
+	servers: &'a [OwnedServerName],
+	timeout: Duration,
+	mut ask: F,
+) -> Option<(&'a ServerName, T)>
+where
+	F: FnMut(&'a ServerName) -> Fut,
+	Fut: Future<Output = Result<T>>,
+{
+	for server in servers {
+		let server: &ServerName = server;
+		match tokio::time::timeout(timeout, ask(server)).await {
+			| Ok(Ok(answer)) => {
+				failures.forgive(room_id, server);
+				return Some((server, answer));
+			},
+			| Ok(Err(e)) => {
+				debug_warn!(%room_id, "{server} failed to give us events: {e}");
+			},
+			| Err(_) => {
+				debug_warn!(%room_id, "{server} did not give us events within {timeout:?}");
+			},
+		}
+
+		failures.failed(room_id, server, Instant::now());
+	}
+
+	debug!(%room_id, "None of {} servers gave us events", servers.len());
+	None
+}
+
+/// Orders the candidates best first.
+pub(super) fn rank(mut candidates: Vec<Candidate>) -> Vec<OwnedServerName> {
+	candidates.sort_unstable();
+	candidates
+		.into_iter()
+		.map(|candidate| candidate.server)
+		.collect()
+}
+
+impl Failures {
+	/// Notes the server failed for the room, forgetting failures past the
+	/// cooldown.
+	pub(super) fn failed(&self, room_id: &RoomId, server: &ServerName, now: Instant) {
+		let mut failed = self.failed.write().expect("locked for writing");
+		failed.retain(|_, &mut failed| now.saturating_duration_since(failed) < FAILURE_COOLDOWN);
+		failed.insert((room_id.to_owned(), server.to_owned()), now);
+	}
+
+	pub(super) fn forgive(&self, room_id: &RoomId, server: &ServerName) {
+		self.failed
+			.write()
+			.expect("locked for writing")
+			.remove(&(room_id.to_owned(), server.to_owned()));
+	}
+
+	/// Whether the server failed for the room within the cooldown.
+	#[must_use]
+	pub fn cooling(&self, room_id: &RoomId, server: &ServerName, now: Instant) -> bool {
+		self.failed
+			.read()
+			.expect("locked for reading")
+			.get(&(room_id.to_owned(), server.to_owned()))
+			.is_some_and(|&failed| now.saturating_duration_since(failed) < FAILURE_COOLDOWN)
+	}
+
+	#[must_use]
+	pub fn len(&self) -> usize { self.failed.read().expect("locked for reading").len() }
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
@@ -5,7 +5,7 @@
 };
 
 use conduwuit::{
-	Result, debug, err, implement,
+	Error, Result, debug, debug_warn, implement,
 	matrix::{PduEvent, StateMap},
 	trace,
 	utils::stream::{BroadbandExt, IterStream, ReadyExt, TryBroadbandExt, TryWidebandExt},
@@ -46,7 +46,10 @@ pub(super) async fn state_at_incoming_degree_one(
 		.timeline
 		.get_pdu(prev_event)
 		.await
-		.map_err(|e| err!(Database("Could not find prev event, but we know the state: {e:?}")))?;
+		.map_err(|e| {
+			debug_warn!(?prev_event, "Could not find prev event, but we know the state: {e}");
+			Error::not_found(format!("Event {prev_event}"))
+		})?;
 
 	if let Some(state_key) = &prev_pdu.state_key {
 		let shortstatekey = self
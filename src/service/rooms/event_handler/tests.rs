@@ -0,0 +1,234 @@
+use std::{
+	cmp::Reverse,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{Err, Result, err, http::StatusCode};
+use ruma::{
+	OwnedServerName, events::room::server_acl::RoomServerAclEventContent, owned_server_name,
+	room_id, server_name,
+};
+
+use super::{
+	Acl, Delivery,
+	provenance::{pack, unpack},
+	servers::{Candidate, FAILURE_COOLDOWN, Failures, ask_in_turn, rank},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn candidate(server: OwnedServerName, power: i64, inactivity: usize) -> Candidate {
+	Candidate {
+		power: Reverse(power.try_into().expect("valid power level")),
+		inactivity,
+		server,
+	}
+}
+
+#[test]
+fn rank_by_power_then_activity() {
+	let ranked = rank(vec![
+		candidate(owned_server_name!("idle.example"), 0, usize::MAX),
+		candidate(owned_server_name!("chatty.example"), 0, 3),
+		candidate(owned_server_name!("mods.example"), 50, 40),
+		candidate(owned_server_name!("admins.example"), 100, usize::MAX),
+		candidate(owned_server_name!("chattier.example"), 0, 0),
+	]);
+
+	let ranked: Vec<_> = ranked.iter().map(|server| server.as_str()).collect();
+	assert_eq!(ranked, [
+		"admins.example",
+		"mods.example",
+		"chattier.example",
+		"chatty.example",
+		"idle.example",
+	]);
+}
+
+#[tokio::test]
+async fn ask_next_when_first_fails() {
+	let failures = Failures::default();
+	let room_id = room_id!("!room:example.org");
+	let servers = [
+		owned_server_name!("dead.example"),
+		owned_server_name!("alive.example"),
+		owned_server_name!("unasked.example"),
+	];
+
+	let asked = Mutex::new(Vec::new());
+	let answer = ask_in_turn(&failures, room_id, &servers, TIMEOUT, |server| {
+		asked.lock().expect("locked").push(server.to_owned());
+		async move {
+			if server.as_str() == "dead.example" {
+				return Err!(BadServerResponse("connection refused"));
+			}
+
+			Ok(server.to_owned())
+		}
+	})
+	.await;
+
+	let (server, answer) = answer.expect("an answer");
+	assert_eq!(server.as_str(), "alive.example");
+	assert_eq!(answer, servers[1]);
+	assert_eq!(*asked.lock().expect("locked"), servers[..2]);
+
+	let now = Instant::now();
+	assert!(failures.cooling(room_id, &servers[0], now), "dead server cooling down");
+	assert!(!failures.cooling(room_id, &servers[1], now));
+	assert!(!failures.cooling(room_id!("!other:example.org"), &servers[0], now), "per room");
+	assert!(!failures.cooling(room_id, &servers[0], now + FAILURE_COOLDOWN), "cooled down");
+}
+
+#[tokio::test]
+async fn ask_next_when_first_times_out() {
+	let failures = Failures::default();
+	let room_id = room_id!("!room:example.org");
+	let servers = [owned_server_name!("slow.example"), owned_server_name!("fast.example")];
+
+	let answer = ask_in_turn(
+		&failures,
+		room_id,
+		&servers,
+		Duration::from_millis(10),
+		|server| async move {
+			if server.as_str() == "slow.example" {
+				futures::future::pending::<()>().await;
+			}
+
+			Result::<_>::Ok(())
+		},
+	)
+	.await;
+
+	assert_eq!(answer.map(|(server, ())| server), Some(&*servers[1]));
+	assert!(failures.cooling(room_id, &servers[0], Instant::now()));
+}
+
+#[tokio::test]
+async fn answer_forgives_failure() {
+	let failures = Failures::default();
+	let room_id = room_id!("!room:example.org");
+	let servers = [owned_server_name!("flaky.example")];
+
+	failures.failed(room_id, &servers[0], Instant::now());
+	assert_eq!(failures.len(), 1);
+
+	let answer =
+		ask_in_turn(&failures, room_id, &servers, TIMEOUT, |_| async { Result::<_>::Ok(()) })
+			.await;
+
+	assert!(answer.is_some());
+	assert!(failures.is_empty());
+}
+
+#[tokio::test]
+async fn no_server_answers() {
+	let failures = Failures::default();
+	let room_id = room_id!("!room:example.org");
+	let servers = [owned_server_name!("a.example"), owned_server_name!("b.example")];
+
+	let answer = ask_in_turn(&failures, room_id, &servers, TIMEOUT, |_| async {
+		Result::<()>::Err(err!(BadServerResponse("gone")))
+	})
+	.await;
+
+	assert!(answer.is_none());
+	assert_eq!(failures.len(), 2);
+}
+
+#[test]
+fn failures_expire() {
+	let failures = Failures::default();
+	let room_id = room_id!("!room:example.org");
+	let start = Instant::now();
+
+	failures.failed(room_id, server_name!("old.example"), start);
+	failures.failed(room_id, server_name!("new.example"), start + FAILURE_COOLDOWN);
+
+	assert_eq!(failures.len(), 1, "the old failure is forgotten");
+}
+
+fn acl(allow: &[&str], deny: &[&str], allow_ip_literals: bool) -> Option<Acl> {
+	let content = RoomServerAclEventContent::new(
+		allow_ip_literals,
+		allow.iter().map(ToString::to_string).collect(),
+		deny.iter().map(ToString::to_string).collect(),
+	);
+
+	Acl::new(room_id!("!room:example.org"), &content)
+}
+
+#[test]
+fn acl_deny_glob() {
+	let acl = acl(&["*"], &["*.evil.example", "evil.example", "spam?.example"], false)
+		.expect("valid ACL");
+
+	let denied = acl
+		.check(server_name!("crawler.evil.example"))
+		.expect_err("denied by glob");
+
+	assert_eq!(denied.status_code(), StatusCode::FORBIDDEN);
+	assert!(!acl.is_allowed(server_name!("evil.example")));
+	assert!(!acl.is_allowed(server_name!("evil.example:8448")), "ports are not matched");
+	assert!(!acl.is_allowed(server_name!("spam1.example")));
+
+	acl.check(server_name!("matrix.org")).expect("allowed");
+	assert!(acl.is_allowed(server_name!("notevil.example")));
+	assert!(acl.is_allowed(server_name!("spam12.example")), "? matches one character");
+	assert!(acl.is_allowed(server_name!("evil.example.org")), "globs are anchored");
+}
+
+#[test]
+fn acl_allow_glob() {
+	let acl = acl(&["*.friends.example", "friends.example"], &[], false).expect("valid ACL");
+
+	assert!(acl.is_allowed(server_name!("friends.example")));
+	assert!(acl.is_allowed(server_name!("a.friends.example")));
+	assert!(!acl.is_allowed(server_name!("strangers.example")));
+	assert!(!acl.is_allowed(server_name!("friends.example.evil")));
+}
+
+#[test]
+fn acl_ip_literals() {
+	let denied = acl(&["*"], &[], false).expect("valid ACL");
+	assert!(!denied.is_allowed(server_name!("1.2.3.4")));
+	assert!(!denied.is_allowed(server_name!("[::1]:8448")));
+
+	let allowed = acl(&["*"], &[], true).expect("valid ACL");
+	assert!(allowed.is_allowed(server_name!("1.2.3.4")));
+}
+
+#[test]
+fn acl_literal_characters() {
+	let acl = acl(&["*"], &["a.b.example"], false).expect("valid ACL");
+
+	assert!(!acl.is_allowed(server_name!("a.b.example")));
+	assert!(acl.is_allowed(server_name!("aXb.example")), "dots match themselves only");
+}
+
+#[test]
+fn acl_broken_ignored() {
+	assert!(acl(&[], &[], false).is_none(), "empty allow");
+	assert!(acl(&["*"], &["*"], false).is_none(), "wildcard allowed and denied");
+}
+
+#[test]
+fn provenance_packed() {
+	let received = 1_700_000_000_000_u64;
+	let packed = pack(Delivery::MissingAuth, false, received, 42);
+	assert_eq!(packed.len(), 17, "a byte, the receive time and the short server name");
+	assert_eq!(unpack(&packed), Some((Delivery::MissingAuth, false, received, 42)));
+
+	let packed = pack(Delivery::Transaction, true, received, u64::MAX);
+	assert_eq!(unpack(&packed), Some((Delivery::Transaction, true, received, u64::MAX)));
+}
+
+#[test]
+fn provenance_invalid() {
+	let packed = pack(Delivery::Backfill, false, 0, 1);
+	assert_eq!(unpack(packed.get(..16).expect("shorter")), None, "truncated");
+	assert_eq!(unpack(&[0_u8; 17]), None, "unknown delivery");
+	assert_eq!(unpack(&[]), None);
+}
@@ -122,7 +122,7 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 
 	// Soft fail check before doing state res
 	debug!("Performing soft-fail check");
-	let soft_fail = match (auth_check, incoming_pdu.redacts_id(&room_version_id)) {
+	let mut soft_fail = match (auth_check, incoming_pdu.redacts_id(&room_version_id)) {
 		| (false, _) => true,
 		| (true, None) => false,
 		| (true, Some(redact_id)) =>
@@ -133,6 +133,17 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 				.await?,
 	};
 
+	// Events the room is frozen to, and state events flooding it, are kept out
+	// of its state
+	let frozen = !soft_fail && self.services.freeze.check_remote(&incoming_pdu).await;
+	let flooding = !soft_fail
+		&& !frozen
+		&& incoming_pdu.state_key.is_some()
+		&& self.services.state_growth.check_remote(&incoming_pdu).await;
+
+	let restricted = frozen || flooding;
+	soft_fail |= restricted;
+
 	// 13. Use state resolution to find new room state
 
 	// We start looking at current room state now, so lets lock the room
@@ -181,7 +192,7 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 		.map(Arc::new)
 		.await;
 
-	if incoming_pdu.state_key.is_some() {
+	if incoming_pdu.state_key.is_some() && !restricted {
 		debug!("Event is a state-event. Deriving new room state");
 
 		// We also add state after incoming event to the fork states
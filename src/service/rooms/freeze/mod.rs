@@ -0,0 +1,174 @@
+//! Room freeze
+//!
+//! A frozen room takes no new events except from members at or above a power
+//! level, without its power levels having to change. Local users below it are
+//! refused, and remote events from them soft-failed, until the room is
+//! unfrozen or the freeze expires. Everyone may still leave.
+//!
+//! Events are checked here before they enter a room, so any other restriction
+//! on who may send there belongs alongside the freeze.
+
+#[cfg(test)]
+mod tests;
+
+use std::{sync::Arc, time::Duration};
+
+use conduwuit::{Err, PduEvent, Result, implement, utils::millis_since_unix_epoch};
+use database::{Deserialized, Json, Map};
+use futures::TryFutureExt;
+use ruma::{
+	Int, RoomId,
+	events::{
+		StateEventType, TimelineEventType,
+		room::{
+			member::{MembershipState, RoomMemberEventContent},
+			power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+		},
+	},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{Dep, globals, rooms};
+
+pub struct Service {
+	db: Data,
+	services: Services,
+}
+
+struct Data {
+	roomid_freeze: Arc<Map>,
+}
+
+struct Services {
+	globals: Dep<globals::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+}
+
+/// A freeze of a room.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Freeze {
+	/// Members at or above this power level may still send.
+	pub power_level: Int,
+
+	/// When the room was frozen, in milliseconds since the epoch.
+	pub since: u64,
+
+	/// When the freeze ends by itself, in milliseconds since the epoch.
+	pub until: Option<u64>,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				roomid_freeze: args.db["roomid_freeze"].clone(),
+			},
+			services: Services {
+				globals: args.depend::<globals::Service>("globals"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Freezes the room for members below `power_level`, for `duration` or until
+/// unfrozen. Freezing a frozen room replaces its freeze.
+#[implement(Service)]
+pub fn freeze(&self, room_id: &RoomId, power_level: Int, duration: Option<Duration>) -> Freeze {
+	let since = millis_since_unix_epoch();
+	let until = duration
+		.map(|duration| u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+		.map(|duration| since.saturating_add(duration));
+
+	let freeze = Freeze { power_level, since, until };
+
+	self.db.roomid_freeze.raw_put(room_id, Json(&freeze));
+
+	freeze
+}
+
+/// Unfreezes the room, returning whether it was frozen.
+#[implement(Service)]
+pub async fn unfreeze(&self, room_id: &RoomId) -> bool {
+	let frozen = self.get(room_id).await.is_some();
+	self.db.roomid_freeze.remove(room_id);
+
+	frozen
+}
+
+/// The room's freeze, unless it is not frozen or the freeze has expired.
+#[implement(Service)]
+pub async fn get(&self, room_id: &RoomId) -> Option<Freeze> {
+	self.db
+		.roomid_freeze
+		.get(room_id)
+		.await
+		.deserialized()
+		.ok()
+		.filter(|freeze: &Freeze| !freeze.expired(millis_since_unix_epoch()))
+}
+
+/// Refuses an event about to be sent by a local user the room is frozen to.
+#[implement(Service)]
+pub async fn check_local(&self, pdu: &PduEvent) -> Result {
+	if !self.restricted(pdu).await {
+		return Ok(());
+	}
+
+	Err!(Request(Forbidden(
+		"This room is frozen by the server administrators; only moderators may send events \
+		 until it is unfrozen."
+	)))
+}
+
+/// Whether to soft-fail an event from a remote user the room is frozen to.
+#[implement(Service)]
+pub async fn check_remote(&self, pdu: &PduEvent) -> bool { self.restricted(pdu).await }
+
+/// Whether the room is frozen to the sender of the event.
+#[implement(Service)]
+async fn restricted(&self, pdu: &PduEvent) -> bool {
+	let Some(freeze) = self.get(&pdu.room_id).await else {
+		return false;
+	};
+
+	if pdu.sender == self.services.globals.server_user || leaves(pdu) {
+		return false;
+	}
+
+	let power_level = self
+		.services
+		.state_accessor
+		.room_state_get_content::<RoomPowerLevelsEventContent>(
+			&pdu.room_id,
+			&StateEventType::RoomPowerLevels,
+			"",
+		)
+		.map_ok(RoomPowerLevels::from)
+		.await
+		.map_or_else(|_| Int::from(0), |power_levels| power_levels.for_user(&pdu.sender));
+
+	!freeze.exempts(power_level)
+}
+
+impl Freeze {
+	#[inline]
+	#[must_use]
+	pub fn expired(&self, now: u64) -> bool { self.until.is_some_and(|until| until <= now) }
+
+	#[inline]
+	#[must_use]
+	pub fn exempts(&self, power_level: Int) -> bool { power_level >= self.power_level }
+}
+
+/// Whether the event is its sender leaving the room.
+pub(super) fn leaves(pdu: &PduEvent) -> bool {
+	pdu.kind == TimelineEventType::RoomMember
+		&& pdu.state_key.as_deref() == Some(pdu.sender.as_str())
+		&& pdu
+			.get_content::<RoomMemberEventContent>()
+			.is_ok_and(|content| content.membership == MembershipState::Leave)
+}
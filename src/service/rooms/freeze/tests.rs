@@ -0,0 +1,29 @@
+use ruma::int;
+
+use super::Freeze;
+
+fn freeze(until: Option<u64>) -> Freeze {
+	Freeze {
+		power_level: int!(50),
+		since: 1_000,
+		until,
+	}
+}
+
+#[test]
+fn freeze_expires() {
+	assert!(!freeze(None).expired(u64::MAX), "freeze without duration expired");
+	assert!(!freeze(Some(2_000)).expired(1_999));
+	assert!(freeze(Some(2_000)).expired(2_000));
+	assert!(freeze(Some(2_000)).expired(3_000));
+}
+
+#[test]
+fn freeze_exempts_moderators() {
+	let freeze = freeze(None);
+	assert!(freeze.exempts(int!(100)));
+	assert!(freeze.exempts(int!(50)), "threshold itself not exempt");
+	assert!(!freeze.exempts(int!(49)));
+	assert!(!freeze.exempts(int!(0)));
+	assert!(!freeze.exempts(int!(-1)));
+}
@@ -1,4 +1,11 @@
 //! Lazy Loading
+//!
+//! Remembers which members were sent to each device of a user in each room,
+//! and the state they were sent at, so later responses need only include the
+//! members of senders the device has not yet seen.
+
+#[cfg(test)]
+mod tests;
 
 use std::{collections::HashSet, sync::Arc};
 
@@ -10,6 +17,8 @@
 use futures::{Stream, StreamExt, pin_mut};
 use ruma::{DeviceId, OwnedUserId, RoomId, UserId, api::client::filter::LazyLoadOptions};
 
+use crate::rooms::short::ShortStateHash;
+
 pub struct Service {
 	db: Data,
 }
@@ -30,13 +39,20 @@ pub struct Context<'a> {
 	pub device_id: &'a DeviceId,
 	pub room_id: &'a RoomId,
 	pub token: Option<u64>,
+	pub shortstatehash: Option<ShortStateHash>,
 	pub options: Option<&'a LazyLoadOptions>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Status {
 	Unseen,
-	Seen(u64),
+	/// Sent in the response to the request made with `token`, at the room's
+	/// `shortstatehash`. A zero token is left by older versions for a member
+	/// which was sent but not yet confirmed.
+	Seen {
+		token: u64,
+		shortstatehash: ShortStateHash,
+	},
 }
 
 pub type Witness = HashSet<OwnedUserId>;
@@ -86,16 +102,8 @@ pub async fn witness_retain(&self, senders: Witness, ctx: &Context<'_>) -> Witne
 	let _cork = self.db.db.cork();
 	let mut senders = Witness::with_capacity(senders.len());
 	while let Some((status, sender)) = witness.next().await {
-		if include_redundant || status == Status::Unseen {
+		if include_redundant || retain(status, ctx.token) {
 			senders.insert(sender.into());
-			continue;
-		}
-
-		if let Status::Seen(seen) = status {
-			if seen == 0 || ctx.token == Some(seen) {
-				senders.insert(sender.into());
-				continue;
-			}
 		}
 	}
 
@@ -122,23 +130,49 @@ fn witness<'a, I>(
 		.map(into_status)
 		.zip(senders.stream())
 		.map(move |(status, sender)| {
-			if matches!(status, Status::Unseen) {
-				self.db
-					.lazyloadedids
-					.put_aput::<8, _, _>(make_key(sender), 0_u64);
-			} else if matches!(status, Status::Seen(0)) {
+			if let Some(token) = record(status, ctx.token) {
+				let shortstatehash = ctx.shortstatehash.unwrap_or(0);
 				self.db
 					.lazyloadedids
-					.put_aput::<8, _, _>(make_key(sender), ctx.token.unwrap_or(0_u64));
+					.put(make_key(sender), (token, shortstatehash));
 			}
 
 			status
 		})
 }
 
+/// Whether the member of a sender with `status` is sent in the response to
+/// the request made with `token`: when it never was, or was in the response to
+/// this same request, which the client must not have received.
+pub(super) fn retain(status: Status, token: Option<u64>) -> bool {
+	match status {
+		| Status::Unseen => true,
+		| Status::Seen { token: seen, .. } => seen == 0 || token == Some(seen),
+	}
+}
+
+/// The token to record a sender's member as sent with in the response to the
+/// request made with `token`, unless the record stands.
+pub(super) fn record(status: Status, token: Option<u64>) -> Option<u64> {
+	match status {
+		| Status::Unseen => Some(token.unwrap_or(0)),
+		| Status::Seen { token: 0, .. } => token.filter(|&token| token != 0),
+		| Status::Seen { .. } => None,
+	}
+}
+
 fn into_status(result: Result<Handle<'_>>) -> Status {
-	match result.and_then(|handle| handle.deserialized()) {
-		| Ok(seen) => Status::Seen(seen),
+	let Ok(handle) = result else {
+		return Status::Unseen;
+	};
+
+	// Older versions recorded the token alone.
+	let seen = (&handle)
+		.deserialized()
+		.or_else(|_| (&handle).deserialized().map(|token| (token, 0)));
+
+	match seen {
+		| Ok((token, shortstatehash)) => Status::Seen { token, shortstatehash },
 		| Err(_) => Status::Unseen,
 	}
 }
@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use super::{Status, record, retain};
+
+/// The members sent to one device in one room, by sender, standing in for the
+/// lazyloadedids column.
+#[derive(Default)]
+struct Sent(BTreeMap<&'static str, (u64, u64)>);
+
+impl Sent {
+	/// Runs the witness for a sync since `token` at `shortstatehash`, returning
+	/// the senders whose members are sent.
+	fn sync(&mut self, senders: &[&'static str], token: u64, shortstatehash: u64) -> Vec<&str> {
+		senders
+			.iter()
+			.filter(|&&sender| {
+				let status =
+					self.0
+						.get(sender)
+						.map_or(Status::Unseen, |&(token, shortstatehash)| Status::Seen {
+							token,
+							shortstatehash,
+						});
+
+				if let Some(token) = record(status, Some(token)) {
+					self.0.insert(sender, (token, shortstatehash));
+				}
+
+				retain(status, Some(token))
+			})
+			.copied()
+			.collect()
+	}
+
+	fn reset(&mut self) { self.0.clear(); }
+}
+
+#[test]
+fn incremental_sync_does_not_resend_members() {
+	let mut sent = Sent::default();
+
+	assert_eq!(sent.sync(&["@alice:a", "@bob:b"], 10, 1), ["@alice:a", "@bob:b"]);
+	assert_eq!(sent.0["@alice:a"], (10, 1), "recorded with the token and state");
+	assert!(sent.sync(&["@alice:a", "@bob:b"], 20, 2).is_empty());
+	assert_eq!(sent.sync(&["@alice:a", "@carol:c"], 30, 2), ["@carol:c"]);
+	assert_eq!(sent.0["@alice:a"], (10, 1), "record of a member not resent stands");
+}
+
+#[test]
+fn retried_sync_resends_members() {
+	let mut sent = Sent::default();
+
+	assert_eq!(sent.sync(&["@alice:a"], 10, 1), ["@alice:a"]);
+	assert_eq!(sent.sync(&["@alice:a"], 10, 1), ["@alice:a"]);
+	assert!(sent.sync(&["@alice:a"], 20, 1).is_empty());
+}
+
+#[test]
+fn gappy_sync_resends_members() {
+	let mut sent = Sent::default();
+
+	assert_eq!(sent.sync(&["@alice:a"], 10, 1), ["@alice:a"]);
+	assert!(sent.sync(&["@alice:a"], 20, 1).is_empty());
+
+	sent.reset();
+	assert_eq!(sent.sync(&["@alice:a"], 30, 5), ["@alice:a"]);
+	assert_eq!(sent.0["@alice:a"], (30, 5));
+	assert!(sent.sync(&["@alice:a"], 40, 5).is_empty());
+}
+
+#[test]
+fn unconfirmed_record_is_resent_once() {
+	let mut sent = Sent::default();
+	sent.0.insert("@alice:a", (0, 0));
+
+	assert_eq!(sent.sync(&["@alice:a"], 10, 1), ["@alice:a"]);
+	assert_eq!(sent.0["@alice:a"], (10, 1));
+	assert!(sent.sync(&["@alice:a"], 20, 1).is_empty());
+}
@@ -1,45 +1,59 @@
+pub mod activity;
 pub mod alias;
 pub mod auth_chain;
+pub mod direct;
 pub mod directory;
 pub mod event_handler;
+pub mod freeze;
 pub mod lazy_loading;
 pub mod metadata;
 pub mod outlier;
 pub mod pdu_metadata;
+pub mod purge;
 pub mod read_receipt;
 pub mod search;
+pub mod send_queue;
 pub mod short;
 pub mod spaces;
 pub mod state;
 pub mod state_accessor;
 pub mod state_cache;
 pub mod state_compressor;
+pub mod state_growth;
 pub mod threads;
 pub mod timeline;
 pub mod typing;
+pub mod usage;
 pub mod user;
 
 use std::sync::Arc;
 
 pub struct Service {
+	pub activity: Arc<activity::Service>,
 	pub alias: Arc<alias::Service>,
 	pub auth_chain: Arc<auth_chain::Service>,
+	pub direct: Arc<direct::Service>,
 	pub directory: Arc<directory::Service>,
 	pub event_handler: Arc<event_handler::Service>,
+	pub freeze: Arc<freeze::Service>,
 	pub lazy_loading: Arc<lazy_loading::Service>,
 	pub metadata: Arc<metadata::Service>,
 	pub outlier: Arc<outlier::Service>,
 	pub pdu_metadata: Arc<pdu_metadata::Service>,
+	pub purge: Arc<purge::Service>,
 	pub read_receipt: Arc<read_receipt::Service>,
 	pub search: Arc<search::Service>,
+	pub send_queue: Arc<send_queue::Service>,
 	pub short: Arc<short::Service>,
 	pub spaces: Arc<spaces::Service>,
 	pub state: Arc<state::Service>,
 	pub state_accessor: Arc<state_accessor::Service>,
 	pub state_cache: Arc<state_cache::Service>,
 	pub state_compressor: Arc<state_compressor::Service>,
+	pub state_growth: Arc<state_growth::Service>,
 	pub threads: Arc<threads::Service>,
 	pub timeline: Arc<timeline::Service>,
 	pub typing: Arc<typing::Service>,
+	pub usage: Arc<usage::Service>,
 	pub user: Arc<user::Service>,
 }
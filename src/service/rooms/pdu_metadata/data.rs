@@ -55,6 +55,8 @@ pub(super) fn add_relation(&self, from: u64, to: u64) {
 		self.tofrom_relation.aput_raw::<BUFSIZE, _, _>(key, []);
 	}
 
+	/// Relations of the target, loaded in the direction of `dir` starting after
+	/// `from`.
 	pub(super) fn get_relations<'a>(
 		&'a self,
 		user_id: &'a UserId,
@@ -62,7 +64,7 @@ pub(super) fn get_relations<'a>(
 		target: ShortEventId,
 		from: PduCount,
 		dir: Direction,
-	) -> impl Stream<Item = PdusIterItem> + Send + '_ {
+	) -> impl Stream<Item = PdusIterItem> + Send + 'a {
 		let mut current = ArrayVec::<u8, 16>::new();
 		current.extend(target.to_be_bytes());
 		current.extend(from.saturating_inc(dir).into_unsigned().to_be_bytes());
@@ -75,17 +77,40 @@ pub(super) fn get_relations<'a>(
 		.ready_take_while(move |key| key.starts_with(&target.to_be_bytes()))
 		.map(|to_from| u64_from_u8(&to_from[8..16]))
 		.map(PduCount::from_unsigned)
-		.wide_filter_map(move |shorteventid| async move {
-			let pdu_id: RawPduId = PduId { shortroomid, shorteventid }.into();
+		.wide_filter_map(move |shorteventid| {
+			self.get_relation(user_id, shortroomid, shorteventid)
+		})
+	}
 
-			let mut pdu = self.services.timeline.get_pdu_from_id(&pdu_id).await.ok()?;
+	/// Positions of the relations of the target, without loading them.
+	pub(super) fn relation_counts(
+		&self,
+		target: ShortEventId,
+	) -> impl Stream<Item = PduCount> + Send + '_ {
+		self.tofrom_relation
+			.raw_keys_from(&target.to_be_bytes())
+			.ignore_err()
+			.ready_take_while(move |key| key.starts_with(&target.to_be_bytes()))
+			.map(|to_from| u64_from_u8(&to_from[8..16]))
+			.map(PduCount::from_unsigned)
+	}
 
-			if pdu.sender != user_id {
-				pdu.remove_transaction_id().log_err().ok();
-			}
+	/// The relation at `shorteventid`, as `user_id` is shown it.
+	pub(super) async fn get_relation(
+		&self,
+		user_id: &UserId,
+		shortroomid: ShortRoomId,
+		shorteventid: PduCount,
+	) -> Option<PdusIterItem> {
+		let pdu_id: RawPduId = PduId { shortroomid, shorteventid }.into();
 
-			Some((shorteventid, pdu))
-		})
+		let mut pdu = self.services.timeline.get_pdu_from_id(&pdu_id).await.ok()?;
+
+		if pdu.sender != user_id {
+			pdu.remove_transaction_id().log_err().ok();
+		}
+
+		Some((shorteventid, pdu))
 	}
 
 	#[inline]
@@ -1,12 +1,22 @@
 mod data;
-use std::sync::Arc;
-
-use conduwuit::{PduCount, Result};
-use futures::{StreamExt, future::try_join};
-use ruma::{EventId, RoomId, UserId, api::Direction};
+#[cfg(test)]
+mod tests;
+
+use std::{collections::HashSet, sync::Arc};
+
+use conduwuit::{
+	PduCount, Result,
+	utils::{IterStream, ReadyExt, stream::WidebandExt},
+};
+use futures::{Stream, StreamExt, future::try_join, stream};
+use ruma::{
+	EventId, RoomId, UserId,
+	api::Direction,
+	events::{TimelineEventType, relation::RelationType},
+};
 
 use self::data::{Data, PdusIterItem};
-use crate::{Dep, rooms};
+use crate::{Dep, rooms, rooms::short::ShortEventId};
 
 pub struct Service {
 	services: Services,
@@ -43,69 +53,122 @@ pub fn add_relation(&self, from: PduCount, to: PduCount) {
 		}
 	}
 
+	/// Relations of a target event, optionally recursing through relations of
+	/// relations up to `max_depth`, exclusively between the `from` and `to`
+	/// positions in the direction of pagination. Results are filtered by
+	/// `rel_type` and `event_type` and exclude redacted events not sent by
+	/// `user_id`.
+	///
+	/// Relations are ordered by their position in the stream, a total order
+	/// which remains stable between requests. It is a topological order too,
+	/// as an event is only appended to the timeline after those it relates to.
+	/// Without recursion the relations are read from the `from` position on,
+	/// so taking a page reads no more than it needs; recursing reads the
+	/// positions of all the relations first, loading only those taken.
 	#[allow(clippy::too_many_arguments)]
-	pub async fn get_relations(
-		&self,
-		user_id: &UserId,
-		room_id: &RoomId,
-		target: &EventId,
-		from: PduCount,
-		limit: usize,
+	pub fn get_relations<'a>(
+		&'a self,
+		user_id: &'a UserId,
+		room_id: &'a RoomId,
+		target: &'a EventId,
+		rel_type: Option<&'a RelationType>,
+		event_type: Option<&'a TimelineEventType>,
+		from: Option<PduCount>,
+		to: Option<PduCount>,
 		max_depth: u8,
 		dir: Direction,
-	) -> Vec<PdusIterItem> {
-		let room_id = self.services.short.get_shortroomid(room_id);
-
-		let target = self.services.timeline.get_pdu_count(target);
-
-		let Ok((room_id, target)) = try_join(room_id, target).await else {
-			return Vec::new();
+	) -> impl Stream<Item = PdusIterItem> + Send + 'a {
+		let shortroomid = self.services.short.get_shortroomid(room_id);
+		let target = self
+			.services
+			.timeline
+			.get_pdu_count_in_room(room_id, target);
+
+		let before_to = move |count: &PduCount| match (to, dir) {
+			| (None, _) => true,
+			| (Some(to), Direction::Forward) => *count < to,
+			| (Some(to), Direction::Backward) => *count > to,
 		};
 
-		let target = match target {
-			| PduCount::Normal(c) => c,
-			// TODO: Support backfilled relations
-			| _ => 0, // This will result in an empty iterator
-		};
-
-		let mut pdus: Vec<_> = self
-			.db
-			.get_relations(user_id, room_id, target, from, dir)
-			.collect()
-			.await;
-
-		let mut stack: Vec<_> = pdus
-			.iter()
-			.filter(|_| max_depth > 0)
-			.map(|pdu| (pdu.clone(), 1))
-			.collect();
-
-		'limit: while let Some(stack_pdu) = stack.pop() {
-			let target = match stack_pdu.0.0 {
-				| PduCount::Normal(c) => c,
+		stream::once(try_join(shortroomid, target))
+			.filter_map(move |found| async move {
 				// TODO: Support backfilled relations
-				| PduCount::Backfilled(_) => 0, // This will result in an empty iterator
-			};
-
-			let relations: Vec<_> = self
-				.db
-				.get_relations(user_id, room_id, target, from, dir)
-				.collect()
-				.await;
-
-			for relation in relations {
-				if stack_pdu.1 < max_depth {
-					stack.push((relation.clone(), stack_pdu.1.saturating_add(1)));
-				}
+				let Ok((shortroomid, PduCount::Normal(target))) = found else {
+					return None;
+				};
+
+				let from = from.unwrap_or(match dir {
+					| Direction::Forward => PduCount::Normal(0),
+					| Direction::Backward => PduCount::max(),
+				});
+
+				let relations = if max_depth > 1 {
+					self.relation_counts(target, max_depth, from, dir)
+						.await
+						.into_iter()
+						.stream()
+						.wide_filter_map(move |count| {
+							self.db.get_relation(user_id, shortroomid, count)
+						})
+						.boxed()
+				} else {
+					self.db
+						.get_relations(user_id, shortroomid, target, from, dir)
+						.boxed()
+				};
+
+				Some(relations)
+			})
+			.flatten()
+			.ready_take_while(move |(count, _)| before_to(count))
+			.ready_filter(move |(_, pdu)| event_type.is_none_or(|kind| *kind == pdu.kind))
+			.ready_filter(move |(_, pdu)| {
+				rel_type.is_none_or(|rel_type| pdu.relation_type_equal(rel_type))
+			})
+			.ready_filter(move |(_, pdu)| !pdu.is_redacted() || pdu.sender == user_id)
+	}
 
-				pdus.push(relation);
-				if pdus.len() >= limit {
-					break 'limit;
+	/// Positions of the relations of the target and of their relations up to
+	/// `max_depth`, after `from` in the direction of pagination.
+	async fn relation_counts(
+		&self,
+		target: ShortEventId,
+		max_depth: u8,
+		from: PduCount,
+		dir: Direction,
+	) -> Vec<PduCount> {
+		let mut counts: Vec<PduCount> = Vec::new();
+		let mut visited: HashSet<PduCount> = HashSet::new();
+		let mut level = vec![target];
+		for depth in 1..=max_depth {
+			let mut next = Vec::new();
+			for target in level {
+				let relations: Vec<_> = self.db.relation_counts(target).collect().await;
+				for count in relations {
+					if !visited.insert(count) {
+						continue;
+					}
+
+					counts.push(count);
+					if let (PduCount::Normal(count), true) = (count, depth < max_depth) {
+						next.push(count);
+					}
 				}
 			}
+
+			level = next;
+		}
+
+		counts.sort_unstable();
+		match dir {
+			| Direction::Forward => counts.retain(|count| *count > from),
+			| Direction::Backward => {
+				counts.retain(|count| *count < from);
+				counts.reverse();
+			},
 		}
 
-		pdus
+		counts
 	}
 
 	#[tracing::instrument(skip_all, level = "debug")]
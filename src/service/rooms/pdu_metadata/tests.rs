@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+use conduwuit::{PduCount, config::Figment, pdu::PduBuilder};
+use futures::StreamExt;
+use ruma::{
+	EventId, OwnedEventId, RoomId,
+	api::Direction,
+	events::{TimelineEventType, relation::RelationType},
+};
+use serde_json::{json, value::to_raw_value};
+
+use crate::{Services, testing::Test};
+
+/// More relations than a single read of the database was once capped at.
+const MANY: usize = 1100;
+const PAGE: usize = 100;
+
+/// Every seventh relation is a reference, the others are annotations.
+fn is_reference(i: usize) -> bool { i % 7 == 0 }
+
+async fn paginate_all(
+	services: &Services,
+	room_id: &RoomId,
+	target: &EventId,
+	rel_type: Option<&RelationType>,
+	dir: Direction,
+) -> Vec<(PduCount, OwnedEventId)> {
+	let user_id = &services.globals.server_user;
+	let mut out = Vec::new();
+	let mut from = None;
+	loop {
+		let page: Vec<_> = services
+			.rooms
+			.pdu_metadata
+			.get_relations(user_id, room_id, target, rel_type, None, from, None, 1, dir)
+			.take(PAGE)
+			.map(|(count, pdu)| (count, pdu.event_id))
+			.collect()
+			.await;
+
+		let Some(&(last, _)) = page.last() else {
+			break;
+		};
+
+		from = Some(last);
+		out.extend(page);
+	}
+
+	out
+}
+
+fn assert_pages(results: &[(PduCount, OwnedEventId)], expected: &[OwnedEventId], dir: Direction) {
+	let events: HashSet<_> = results.iter().map(|(_, event_id)| event_id).collect();
+	let counts: Vec<_> = results.iter().map(|&(count, _)| count).collect();
+
+	assert_eq!(results.len(), expected.len(), "missing or extra results");
+	assert_eq!(events.len(), results.len(), "duplicate results across pages");
+	assert!(expected.iter().all(|event_id| events.contains(event_id)), "unexpected results");
+	match dir {
+		| Direction::Forward => assert!(counts.is_sorted(), "results out of order"),
+		| Direction::Backward => assert!(counts.iter().rev().is_sorted(), "results out of order"),
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn relations_paginate_past_many() {
+	let test = Test::start(Figment::new().join(("server_name", "example.org")))
+		.await
+		.expect("started");
+
+	let services = &test.services;
+	let timeline = &services.rooms.timeline;
+	let sender = &services.globals.server_user;
+	let room_id = services.admin.get_admin_room().await.expect("admin room");
+	let state_lock = services.rooms.state.mutex.lock(&room_id).await;
+
+	let content = json!({ "msgtype": "m.text", "body": "target" });
+	let target = timeline
+		.build_and_append_pdu(PduBuilder::timeline(&content), sender, &room_id, &state_lock)
+		.await
+		.expect("target sent");
+
+	let (mut all, mut annotations) = (Vec::new(), Vec::new());
+	for i in 0..MANY {
+		let (event_type, content) = if is_reference(i) {
+			(
+				TimelineEventType::RoomMessage,
+				json!({
+					"msgtype": "m.text",
+					"body": format!("reference {i}"),
+					"m.relates_to": { "rel_type": "m.reference", "event_id": target },
+				}),
+			)
+		} else {
+			(
+				TimelineEventType::Reaction,
+				json!({
+					"m.relates_to": { "rel_type": "m.annotation", "event_id": target, "key": format!("{i}") },
+				}),
+			)
+		};
+
+		let builder = PduBuilder {
+			event_type,
+			content: to_raw_value(&content).expect("serialized"),
+			..Default::default()
+		};
+
+		let event_id = timeline
+			.build_and_append_pdu(builder, sender, &room_id, &state_lock)
+			.await
+			.expect("relation sent");
+
+		if !is_reference(i) {
+			annotations.push(event_id.clone());
+		}
+
+		all.push(event_id);
+	}
+
+	drop(state_lock);
+	for dir in [Direction::Forward, Direction::Backward] {
+		let results = paginate_all(services, &room_id, &target, None, dir).await;
+		assert_pages(&results, &all, dir);
+
+		let annotation = RelationType::Annotation;
+		let results = paginate_all(services, &room_id, &target, Some(&annotation), dir).await;
+		assert_pages(&results, &annotations, dir);
+	}
+
+	test.stop().await;
+}
@@ -0,0 +1,321 @@
+//! Room purge
+//!
+//! Deletes everything stored about a room: its events and their indices, its
+//! state, memberships, receipts, notification counts and the rest. Bans and
+//! disabled federation are kept, so the room is not simply joined again.
+//!
+//! The room's data is gathered first, then deleted column by column in the
+//! order [`plan`] gives, down to the short IDs of its events and the room.
+
+mod plan;
+#[cfg(test)]
+mod tests;
+
+use std::{collections::BTreeSet, str, sync::Arc};
+
+use conduwuit::{
+	Result, implement,
+	utils::{
+		ReadyExt,
+		stream::{IterStream, TryIgnore},
+		u64_from_u8,
+	},
+};
+use database::{Database, Interfix, serialize_to_vec};
+use futures::{Stream, StreamExt, TryFutureExt, future};
+use ruma::{OwnedEventId, RoomAliasId, RoomId, ServerName, UserId};
+use serde::Deserialize;
+
+pub use self::plan::{Keys, Room, STATEDIFF, plan};
+use crate::{Dep, federation, globals, rooms, users};
+
+pub struct Service {
+	db: Arc<Database>,
+	services: Services,
+}
+
+struct Services {
+	globals: Dep<globals::Service>,
+	users: Dep<users::Service>,
+	federation: Dep<federation::Service>,
+	auth_chain: Dep<rooms::auth_chain::Service>,
+	short: Dep<rooms::short::Service>,
+	spaces: Dep<rooms::spaces::Service>,
+	state: Dep<rooms::state::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	state_compressor: Dep<rooms::state_compressor::Service>,
+	typing: Dep<rooms::typing::Service>,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: args.db.clone(),
+			services: Services {
+				globals: args.depend::<globals::Service>("globals"),
+				users: args.depend::<users::Service>("users"),
+				federation: args.depend::<federation::Service>("federation"),
+				auth_chain: args.depend::<rooms::auth_chain::Service>("rooms::auth_chain"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				state_compressor: args
+					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
+				typing: args.depend::<rooms::typing::Service>("rooms::typing"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Only the ID of a stored PDU is needed to purge it.
+#[derive(Deserialize)]
+struct StoredPdu {
+	event_id: OwnedEventId,
+}
+
+/// Gathers what is stored about the room, for [`plan`]. Nothing is left to
+/// gather once the room is purged.
+#[implement(Service)]
+pub async fn gather(&self, room_id: &RoomId) -> Result<Room> {
+	const STRIDE: usize = size_of::<u64>();
+
+	let mut room = Room::new(room_id.to_owned());
+	room.shortroomid = self.services.short.get_shortroomid(room_id).await.ok();
+
+	let prefix = serialize_to_vec((room_id, Interfix))?;
+	let after_prefix = |key: &[u8]| {
+		key.strip_prefix(prefix.as_slice())
+			.and_then(|rest| str::from_utf8(rest).ok())
+			.map(ToOwned::to_owned)
+	};
+
+	for column in plan::MEMBERSHIP {
+		self.db
+			.get(column)?
+			.raw_keys_prefix(&prefix)
+			.ignore_err()
+			.ready_filter_map(|key| UserId::parse(after_prefix(key)?).ok())
+			.ready_for_each(|user_id| {
+				room.members.insert(user_id);
+			})
+			.await;
+	}
+
+	self.db
+		.get("roomserverids")?
+		.raw_keys_prefix(&prefix)
+		.ignore_err()
+		.ready_filter_map(|key| ServerName::parse(after_prefix(key)?).ok())
+		.ready_for_each(|server| {
+			room.servers.insert(server);
+		})
+		.await;
+
+	self.db
+		.get("aliasid_alias")?
+		.raw_stream_prefix(&prefix)
+		.ignore_err()
+		.ready_filter_map(|(_, alias)| RoomAliasId::parse(str::from_utf8(alias).ok()?).ok())
+		.ready_for_each(|alias| {
+			room.aliases.insert(alias);
+		})
+		.await;
+
+	for user_id in &room.members {
+		if !self.services.globals.user_is_local(user_id) {
+			continue;
+		}
+
+		self.services
+			.users
+			.all_device_ids(user_id)
+			.ready_for_each(|device_id| {
+				room.devices.insert((user_id.clone(), device_id.to_owned()));
+			})
+			.await;
+	}
+
+	let mut roots = BTreeSet::new();
+	if let Ok(shortstatehash) = self.services.state.get_room_shortstatehash(room_id).await {
+		roots.insert(shortstatehash);
+	}
+
+	if let Some(shortroomid) = room.shortroomid {
+		let shortroomid = shortroomid.to_be_bytes();
+		self.db
+			.get("pduid_pdu")?
+			.raw_stream_prefix(&shortroomid)
+			.ignore_err()
+			.ready_for_each(|(pdu_id, pdu)| {
+				if let Some(count) = pdu_id.get(STRIDE..STRIDE.saturating_mul(2)) {
+					room.counts.insert(u64_from_u8(count));
+				}
+
+				if let Ok(StoredPdu { event_id }) = serde_json::from_slice(pdu) {
+					room.events.insert(event_id);
+				}
+			})
+			.await;
+
+		self.db
+			.get("roomsynctoken_shortstatehash")?
+			.raw_stream_prefix(&shortroomid)
+			.ignore_err()
+			.ready_filter_map(|(_, shortstatehash)| {
+				(shortstatehash.len() == STRIDE).then(|| u64_from_u8(shortstatehash))
+			})
+			.ready_for_each(|shortstatehash| {
+				roots.insert(shortstatehash);
+			})
+			.await;
+	}
+
+	let shorteventid_shortstatehash = self.db.get("shorteventid_shortstatehash")?;
+	for event_id in &room.events {
+		let Ok(shorteventid) = self.services.short.get_shorteventid(event_id).await else {
+			continue;
+		};
+
+		room.shorteventids.insert(shorteventid);
+		if let Ok(shortstatehash) = shorteventid_shortstatehash
+			.get(&shorteventid.to_be_bytes())
+			.await
+		{
+			roots.insert(u64_from_u8(&shortstatehash));
+		}
+	}
+
+	let (groups, state_events) = self.services.state_compressor.state_closure(roots).await;
+	room.shortstatehashes = groups;
+	room.shorteventids.extend(state_events);
+
+	let shorteventid_authchain = self.db.get("shorteventid_authchain")?;
+	let mut auth_events = BTreeSet::new();
+	for shorteventid in &room.shorteventids {
+		if let Ok(chain) = shorteventid_authchain
+			.get(&shorteventid.to_be_bytes())
+			.await
+		{
+			auth_events.extend(chain.chunks_exact(STRIDE).map(u64_from_u8));
+		}
+	}
+
+	room.shorteventids.extend(auth_events);
+	let events: Vec<OwnedEventId> = room
+		.shorteventids
+		.iter()
+		.stream()
+		.filter_map(|&shorteventid| async move {
+			self.services
+				.short
+				.get_eventid_from_short(shorteventid)
+				.await
+				.ok()
+		})
+		.collect()
+		.await;
+
+	room.events.extend(events);
+
+	if !room.shortstatehashes.is_empty() {
+		self.db
+			.get("statehash_shortstatehash")?
+			.raw_stream()
+			.ignore_err()
+			.ready_filter_map(|(statehash, shortstatehash)| {
+				(shortstatehash.len() == STRIDE
+					&& room.shortstatehashes.contains(&u64_from_u8(shortstatehash)))
+				.then(|| statehash.to_vec())
+			})
+			.ready_for_each(|statehash| {
+				room.statehashes.insert(statehash);
+			})
+			.await;
+	}
+
+	Ok(room)
+}
+
+/// Deletes the keys of one column of the room's [`plan`], or only counts them
+/// when it is a dry run. Returns how many rows there were.
+#[implement(Service)]
+pub async fn purge_column(
+	&self,
+	room: &Room,
+	column: &str,
+	keys: &[Keys],
+	dry_run: bool,
+) -> Result<usize> {
+	let map = self.db.get(column)?;
+
+	let mut rows = 0_usize;
+	for keys in keys {
+		let count = match keys {
+			| Keys::Prefix(prefix) => map.raw_count_prefix(prefix).await,
+			| Keys::Key(key) => usize::from(map.exists(key).await.is_ok()),
+		};
+
+		rows = rows.saturating_add(count);
+	}
+
+	if dry_run || rows == 0 {
+		return Ok(rows);
+	}
+
+	if column == STATEDIFF {
+		self.services
+			.state_compressor
+			.purge_statediffs(&room.shortstatehashes);
+
+		return Ok(rows);
+	}
+
+	let mut batch = self.db.db.write_batch();
+	for keys in keys {
+		match keys {
+			| Keys::Prefix(prefix) => batch.delete_prefix(map, prefix),
+			| Keys::Key(key) => batch.remove(map, key),
+		}
+	}
+
+	batch.commit();
+
+	Ok(rows)
+}
+
+/// Deletes the keys of the room's whole [`plan`] column by column, or only
+/// counts them when it is a dry run, yielding how many rows each column had.
+/// The room is to be forgotten with [`Service::forget_room`] afterwards.
+#[implement(Service)]
+pub fn purge_room<'a>(
+	&'a self,
+	room: &'a Room,
+	dry_run: bool,
+) -> impl Stream<Item = Result<(&'static str, usize)>> + Send + 'a {
+	future::ready(plan(room))
+		.map_ok(move |plan| {
+			plan.into_iter()
+				.stream()
+				.then(move |(column, keys)| async move {
+					self.purge_column(room, column, &keys, dry_run)
+						.await
+						.map(|rows| (column, rows))
+				})
+		})
+		.try_flatten_stream()
+}
+
+/// Drops what is kept in memory about a purged room. Auth chains cached for
+/// several events at once are only found by scanning the whole cache, which
+/// is cleared instead.
+#[implement(Service)]
+pub async fn forget_room(&self, room_id: &RoomId) {
+	self.services.auth_chain.clear_cache();
+	self.services.typing.forget_room(room_id).await;
+	self.services.spaces.clear_room_hierarchy(room_id);
+	self.services.federation.clear_room_state_ids(room_id);
+	self.services.state_cache.clear_appservice_in_room_cache();
+}
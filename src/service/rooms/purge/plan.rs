@@ -0,0 +1,270 @@
+use std::collections::BTreeSet;
+
+use conduwuit::Result;
+use database::{Interfix, serialize_to_vec};
+use ruma::{
+	OwnedDeviceId, OwnedEventId, OwnedRoomAliasId, OwnedRoomId, OwnedServerName, OwnedUserId,
+};
+
+use crate::rooms::short::{ShortEventId, ShortRoomId, ShortStateHash};
+
+/// What is stored about a room, gathered before purging it.
+#[derive(Debug)]
+pub struct Room {
+	pub room_id: OwnedRoomId,
+
+	/// None once the room is purged, or if we never stored events of it.
+	pub shortroomid: Option<ShortRoomId>,
+
+	/// The counts of its timeline events, as in their PDU IDs.
+	pub counts: BTreeSet<u64>,
+
+	/// Its timeline, state and auth chain events.
+	pub events: BTreeSet<OwnedEventId>,
+	pub shorteventids: BTreeSet<ShortEventId>,
+
+	/// Its state groups, and the state hashes they are stored under.
+	pub shortstatehashes: BTreeSet<ShortStateHash>,
+	pub statehashes: BTreeSet<Vec<u8>>,
+
+	/// Everyone who has a membership in it, local or not.
+	pub members: BTreeSet<OwnedUserId>,
+
+	/// The devices of its local members, which lazy-loading is tracked for.
+	pub devices: BTreeSet<(OwnedUserId, OwnedDeviceId)>,
+
+	pub servers: BTreeSet<OwnedServerName>,
+	pub aliases: BTreeSet<OwnedRoomAliasId>,
+}
+
+impl Room {
+	/// A room nothing is known to be stored of yet.
+	#[must_use]
+	pub fn new(room_id: OwnedRoomId) -> Self {
+		Self {
+			room_id,
+			shortroomid: None,
+			counts: BTreeSet::new(),
+			events: BTreeSet::new(),
+			shorteventids: BTreeSet::new(),
+			shortstatehashes: BTreeSet::new(),
+			statehashes: BTreeSet::new(),
+			members: BTreeSet::new(),
+			devices: BTreeSet::new(),
+			servers: BTreeSet::new(),
+			aliases: BTreeSet::new(),
+		}
+	}
+}
+
+/// Keys of a column to purge.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Keys {
+	Prefix(Vec<u8>),
+	Key(Vec<u8>),
+}
+
+/// The column state diffs are kept in, purged through the state compressor.
+pub const STATEDIFF: &str = "shortstatehash_statediff";
+
+/// Columns which are keyed by the room ID alone.
+const ROOM_KEYED: &[&str] = &[
+	"publicroomid_folded",
+	"publicroomids",
+	"roomid_freeze",
+	"roomid_invitedcount",
+	"roomid_inviteviaservers",
+	"roomid_joinedcount",
+];
+
+/// Columns whose keys start with the room ID, not counting those the room's
+/// members, servers and aliases are gathered from.
+const ROOM_PREFIXED: &[&str] = &[
+	"readreceiptid_readreceipt",
+	"referencedevents",
+	"roomday_activity",
+	"roomday_stategrowth",
+	"roomdaysender_activity",
+	"roomid_pduleaves",
+	"roomuserdataid_accountdata",
+	"roomuserid_lastprivatereadupdate",
+	"roomuserid_privateread",
+	"roomusertype_roomuserdataid",
+];
+
+/// Columns the room's membership is gathered from, and starting with its ID.
+pub(super) const MEMBERSHIP: &[&str] = &[
+	"roomuserid_invitecount",
+	"roomuserid_joined",
+	"roomuserid_knockedcount",
+	"roomuserid_leftcount",
+	"roomuseroncejoinedids",
+];
+
+/// Columns keyed by a user and the room.
+const USER_ROOM_KEYED: &[&str] = &[
+	"userroomid_highlightcount",
+	"userroomid_invitestate",
+	"userroomid_joined",
+	"userroomid_knockedstate",
+	"userroomid_leftstate",
+	"userroomid_notificationcount",
+];
+
+/// Columns keyed by a user, the room and a thread.
+const USER_ROOM_PREFIXED: &[&str] =
+	&["userroomthreadid_highlightcount", "userroomthreadid_notificationcount"];
+
+/// Columns keyed by the short room ID and then other things.
+const SHORTROOMID_PREFIXED: &[&str] = &["threadid_userids", "tokenids"];
+
+/// Columns keyed by an event ID.
+const EVENT_KEYED: &[&str] = &["eventid_outlierpdu", "eventid_pduid", "softfailedeventids"];
+
+/// Columns keyed by a short event ID.
+const SHORTEVENTID_KEYED: &[&str] = &[
+	"shorteventid_authchain",
+	"shorteventid_provenance",
+	"shorteventid_shortstatehash",
+];
+
+/// The keys to delete per column for purging the room, in order. What the
+/// room's data is gathered from comes last so a purge which was interrupted
+/// can be run again to finish it.
+pub fn plan(room: &Room) -> Result<Vec<(&'static str, Vec<Keys>)>> {
+	let room_id = room.room_id.as_str();
+	let room_key = || Keys::Key(room_id.as_bytes().to_vec());
+	let room_prefix = serialize_to_vec((room_id, Interfix))?;
+	let shortroom_prefix = || {
+		room.shortroomid
+			.iter()
+			.map(|shortroomid| Keys::Prefix(shortroomid.to_be_bytes().to_vec()))
+			.collect::<Vec<_>>()
+	};
+
+	let mut plan: Vec<(&'static str, Vec<Keys>)> = Vec::new();
+	plan.extend(ROOM_KEYED.iter().map(|&col| (col, vec![room_key()])));
+	plan.extend(
+		ROOM_PREFIXED
+			.iter()
+			.map(|&col| (col, vec![Keys::Prefix(room_prefix.clone())])),
+	);
+
+	for &col in USER_ROOM_KEYED {
+		let keys = room
+			.members
+			.iter()
+			.map(|user_id| serialize_to_vec((user_id, room_id)).map(Keys::Key))
+			.collect::<Result<_>>()?;
+
+		plan.push((col, keys));
+	}
+
+	for &col in USER_ROOM_PREFIXED {
+		let keys = room
+			.members
+			.iter()
+			.map(|user_id| serialize_to_vec((user_id, room_id, Interfix)).map(Keys::Prefix))
+			.collect::<Result<_>>()?;
+
+		plan.push((col, keys));
+	}
+
+	let lazy_loading = room
+		.devices
+		.iter()
+		.map(|(user_id, device_id)| {
+			serialize_to_vec((user_id, device_id, room_id, Interfix)).map(Keys::Prefix)
+		})
+		.collect::<Result<_>>()?;
+
+	plan.push(("lazyloadedids", lazy_loading));
+
+	let server_rooms = room
+		.servers
+		.iter()
+		.map(|server| serialize_to_vec((server, room_id)).map(Keys::Key))
+		.collect::<Result<_>>()?;
+
+	plan.push(("serverroomids", server_rooms));
+
+	// local aliases are keyed without the server name
+	let aliases = || {
+		room.aliases
+			.iter()
+			.map(|alias| Keys::Key(alias.alias().as_bytes().to_vec()))
+			.collect()
+	};
+
+	plan.push(("alias_roomid", aliases()));
+	plan.push(("alias_userid", aliases()));
+
+	plan.extend(
+		SHORTROOMID_PREFIXED
+			.iter()
+			.map(|&col| (col, shortroom_prefix())),
+	);
+
+	// relations are keyed by the counts of the events they relate
+	let relations = room
+		.counts
+		.iter()
+		.map(|count| Keys::Prefix(count.to_be_bytes().to_vec()))
+		.collect();
+
+	plan.push(("tofrom_relation", relations));
+
+	let event_keys = || {
+		room.events
+			.iter()
+			.map(|event_id| Keys::Key(event_id.as_bytes().to_vec()))
+			.collect::<Vec<_>>()
+	};
+
+	let shortevent_keys = || {
+		room.shorteventids
+			.iter()
+			.map(|short| Keys::Key(short.to_be_bytes().to_vec()))
+			.collect::<Vec<_>>()
+	};
+
+	plan.extend(EVENT_KEYED.iter().map(|&col| (col, event_keys())));
+	plan.extend(
+		SHORTEVENTID_KEYED
+			.iter()
+			.map(|&col| (col, shortevent_keys())),
+	);
+
+	let statediffs = room
+		.shortstatehashes
+		.iter()
+		.map(|short| Keys::Key(short.to_be_bytes().to_vec()))
+		.collect();
+
+	plan.push((STATEDIFF, statediffs));
+
+	let statehashes = room.statehashes.iter().cloned().map(Keys::Key).collect();
+	plan.push(("statehash_shortstatehash", statehashes));
+
+	// what the room's events and state were gathered from
+	plan.push(("eventid_shorteventid", event_keys()));
+	plan.push(("shorteventid_eventid", shortevent_keys()));
+	plan.push(("roomsynctoken_shortstatehash", shortroom_prefix()));
+	plan.push(("pduid_pdu", shortroom_prefix()));
+	plan.push(("roomid_shortstatehash", vec![room_key()]));
+
+	// what its members, servers and aliases were gathered from
+	plan.extend(
+		MEMBERSHIP
+			.iter()
+			.map(|&col| (col, vec![Keys::Prefix(room_prefix.clone())])),
+	);
+
+	plan.push(("roomserverids", vec![Keys::Prefix(room_prefix.clone())]));
+	plan.push(("aliasid_alias", vec![Keys::Prefix(room_prefix)]));
+
+	// last as everything keyed by the short room ID is found with it
+	plan.push(("roomid_shortroomid", vec![room_key()]));
+
+	Ok(plan)
+}
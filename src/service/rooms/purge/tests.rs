@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use conduwuit::{config::Figment, utils::stream::TryIgnore};
+use database::serialize_to_vec;
+use futures::{StreamExt, TryStreamExt};
+use ruma::{
+	OwnedRoomId, RoomId, owned_device_id, owned_event_id, owned_room_id, owned_server_name,
+	owned_user_id,
+};
+
+use super::{Keys, Room, plan};
+use crate::testing::Test;
+
+type Store = BTreeMap<&'static str, BTreeSet<Vec<u8>>>;
+
+fn key<T: serde::Serialize>(val: T) -> Vec<u8> { serialize_to_vec(val).expect("serialized") }
+
+fn concat(parts: &[&[u8]]) -> Vec<u8> { parts.concat() }
+
+/// A small room as the services would have stored it, with the keys laid out
+/// the way they write them.
+fn synthetic_room(room_id: OwnedRoomId, shortroomid: u64, base: u64) -> Room {
+	let server = room_id.server_name().expect("has server").to_owned();
+	let local = owned_user_id!("@alice:example.org");
+	let remote = ruma::UserId::parse(format!("@bob:{server}")).expect("valid user");
+	let alias =
+		ruma::RoomAliasId::parse(format!("#room{shortroomid}:example.org")).expect("valid alias");
+
+	Room {
+		shortroomid: Some(shortroomid),
+		counts: [base, base.saturating_add(1)].into(),
+		events: [
+			ruma::EventId::parse(format!("$create{shortroomid}")).expect("valid event"),
+			ruma::EventId::parse(format!("$message{shortroomid}")).expect("valid event"),
+		]
+		.into(),
+		shorteventids: [base, base.saturating_add(1), base.saturating_add(2)].into(),
+		shortstatehashes: [base, base.saturating_add(1)].into(),
+		statehashes: [format!("statehash{shortroomid}").into_bytes()].into(),
+		members: [local.clone(), remote].into(),
+		devices: [(local, owned_device_id!("DEVICE"))].into(),
+		servers: [server, owned_server_name!("example.org")].into(),
+		aliases: [alias].into(),
+		room_id,
+	}
+}
+
+fn store_room(store: &mut Store, room: &Room) {
+	let room_id: &RoomId = &room.room_id;
+	let short = room.shortroomid.expect("short room ID").to_be_bytes();
+	let mut put = |column: &'static str, key: Vec<u8>| {
+		store.entry(column).or_default().insert(key);
+	};
+
+	for column in [
+		"publicroomid_folded",
+		"publicroomids",
+		"roomid_freeze",
+		"roomid_invitedcount",
+		"roomid_inviteviaservers",
+		"roomid_joinedcount",
+		"roomid_shortroomid",
+		"roomid_shortstatehash",
+	] {
+		put(column, room_id.as_bytes().to_vec());
+	}
+
+	for &count in &room.counts {
+		let pdu_id = concat(&[&short, &count.to_be_bytes()]);
+		put("pduid_pdu", pdu_id.clone());
+		put("threadid_userids", pdu_id.clone());
+		put("tokenids", concat(&[&short, b"lunch\xff", &pdu_id]));
+		put("roomsynctoken_shortstatehash", pdu_id.clone());
+		put("readreceiptid_readreceipt", key((room_id, count, "@alice:example.org")));
+		put("roomid_pduleaves", key((room_id, count)));
+		put("roomuserdataid_accountdata", key((room_id, "@alice:example.org", count)));
+		put("tofrom_relation", concat(&[&count.to_be_bytes(), &count.to_be_bytes()]));
+	}
+
+	for event_id in &room.events {
+		for column in [
+			"eventid_outlierpdu",
+			"eventid_pduid",
+			"eventid_shorteventid",
+			"softfailedeventids",
+		] {
+			put(column, event_id.as_bytes().to_vec());
+		}
+
+		put("referencedevents", key((room_id, event_id)));
+	}
+
+	for short in &room.shorteventids {
+		for column in [
+			"shorteventid_authchain",
+			"shorteventid_eventid",
+			"shorteventid_provenance",
+			"shorteventid_shortstatehash",
+		] {
+			put(column, short.to_be_bytes().to_vec());
+		}
+	}
+
+	for short in &room.shortstatehashes {
+		put("shortstatehash_statediff", short.to_be_bytes().to_vec());
+	}
+
+	for statehash in &room.statehashes {
+		put("statehash_shortstatehash", statehash.clone());
+	}
+
+	for user_id in &room.members {
+		for column in [
+			"roomuserid_invitecount",
+			"roomuserid_joined",
+			"roomuserid_knockedcount",
+			"roomuserid_lastprivatereadupdate",
+			"roomuserid_leftcount",
+			"roomuserid_privateread",
+			"roomuseroncejoinedids",
+		] {
+			put(column, key((room_id, user_id)));
+		}
+
+		for column in [
+			"userroomid_highlightcount",
+			"userroomid_invitestate",
+			"userroomid_joined",
+			"userroomid_knockedstate",
+			"userroomid_leftstate",
+			"userroomid_notificationcount",
+		] {
+			put(column, key((user_id, room_id)));
+		}
+
+		put("userroomthreadid_notificationcount", key((user_id, room_id, "$thread")));
+		put("userroomthreadid_highlightcount", key((user_id, room_id, "$thread")));
+		put("roomusertype_roomuserdataid", key((room_id, user_id, "m.tag")));
+	}
+
+	for (user_id, device_id) in &room.devices {
+		put("lazyloadedids", key((user_id, device_id, room_id, "@bob:example.org")));
+	}
+
+	for server in &room.servers {
+		put("roomserverids", key((room_id, server)));
+		put("serverroomids", key((server, room_id)));
+	}
+
+	for alias in &room.aliases {
+		put("alias_roomid", alias.alias().as_bytes().to_vec());
+		put("alias_userid", alias.alias().as_bytes().to_vec());
+		put("aliasid_alias", key((room_id, 1_u64)));
+	}
+
+	put("roomday_activity", key((room_id, 20_000_u64)));
+	put("roomday_stategrowth", key((room_id, 20_000_u64)));
+	put("roomdaysender_activity", key((room_id, 20_000_u64, "@bob:example.org")));
+}
+
+fn apply(store: &mut Store, plan: &[(&'static str, Vec<Keys>)]) {
+	for (column, keys) in plan {
+		let Some(rows) = store.get_mut(column) else {
+			continue;
+		};
+
+		for keys in keys {
+			match keys {
+				| Keys::Prefix(prefix) => rows.retain(|key| !key.starts_with(prefix)),
+				| Keys::Key(key) => {
+					rows.remove(key);
+				},
+			}
+		}
+	}
+}
+
+fn rows(store: &Store) -> usize { store.values().map(BTreeSet::len).sum() }
+
+#[test]
+fn purge_leaves_nothing_of_the_room() {
+	let purged = synthetic_room(owned_room_id!("!purged:example.org"), 1, 100);
+	let kept = synthetic_room(owned_room_id!("!kept:remote.example"), 2, 200);
+
+	let mut store = Store::new();
+	store_room(&mut store, &kept);
+	let kept_rows = rows(&store);
+
+	store_room(&mut store, &purged);
+	assert!(rows(&store) > kept_rows);
+
+	let plan = plan(&purged).expect("planned");
+	let planned: BTreeSet<_> = plan.iter().map(|(column, _)| *column).collect();
+	for column in store.keys() {
+		assert!(planned.contains(column), "{column} is purged");
+	}
+
+	apply(&mut store, &plan);
+	assert_eq!(rows(&store), kept_rows, "only the purged room's keys removed");
+
+	let room_id = purged.room_id.as_bytes();
+	let shortroomid = 1_u64.to_be_bytes();
+	for (column, keys) in &store {
+		for key in keys {
+			let has_room = key.windows(room_id.len()).any(|part| part == room_id);
+			assert!(!has_room, "{column} has a key of the room");
+			assert!(!key.starts_with(&shortroomid), "{column} has a key of the short room");
+		}
+	}
+}
+
+#[test]
+fn purge_again_is_harmless() {
+	let kept = synthetic_room(owned_room_id!("!kept:remote.example"), 2, 200);
+	let mut store = Store::new();
+	store_room(&mut store, &kept);
+	let kept_rows = rows(&store);
+
+	// all that is gathered of a purged room
+	let purged = Room::new(owned_room_id!("!purged:example.org"));
+
+	apply(&mut store, &plan(&purged).expect("planned"));
+	assert_eq!(rows(&store), kept_rows);
+}
+
+#[test]
+fn gathering_columns_purged_last() {
+	let room = synthetic_room(owned_room_id!("!room:example.org"), 1, 100);
+	let plan = plan(&room).expect("planned");
+	let position = |column: &str| {
+		plan.iter()
+			.position(|(planned, _)| *planned == column)
+			.expect("column planned")
+	};
+
+	assert_eq!(position("roomid_shortroomid"), plan.len().saturating_sub(1));
+	assert!(position("pduid_pdu") > position("eventid_pduid"));
+	assert!(position("eventid_shorteventid") > position("shorteventid_authchain"));
+	assert!(position("roomuserid_joined") > position("userroomid_joined"));
+	assert!(position("aliasid_alias") > position("alias_roomid"));
+
+	let event = owned_event_id!("$message1");
+	assert!(plan.iter().any(|(column, keys)| {
+		*column == "eventid_pduid" && keys.contains(&Keys::Key(event.as_bytes().to_vec()))
+	}));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn purge_populated_database() {
+	const SHORTROOM: [&str; 4] =
+		["pduid_pdu", "roomsynctoken_shortstatehash", "threadid_userids", "tokenids"];
+
+	let test = Test::start(Figment::new().join(("server_name", "example.org")))
+		.await
+		.expect("started");
+
+	// the admin room, created with the database
+	let services = &test.services;
+	let room_id = services.admin.get_admin_room().await.expect("admin room");
+	let purge = &services.rooms.purge;
+	let room = purge.gather(&room_id).await.expect("gathered");
+	assert!(!room.events.is_empty(), "room has events");
+
+	let auth_chain = &services.rooms.auth_chain;
+	let chain_key: Vec<u64> = room.shorteventids.iter().copied().take(2).collect();
+	auth_chain.cache_auth_chain_vec(chain_key.clone(), &chain_key);
+
+	let purged: Vec<_> = purge
+		.purge_room(&room, false)
+		.try_collect()
+		.await
+		.expect("purged");
+
+	assert!(purged.iter().any(|&(_, rows)| rows > 0), "rows deleted");
+	purge.forget_room(&room_id).await;
+	assert!(
+		auth_chain
+			.get_cached_eventid_authchain(&chain_key)
+			.await
+			.is_err(),
+		"chain cached for several of the events forgotten"
+	);
+
+	let shortroomid = room.shortroomid.expect("short room ID").to_be_bytes();
+	let shorteventids: BTreeSet<_> = room
+		.shorteventids
+		.iter()
+		.map(|short| short.to_be_bytes())
+		.collect();
+
+	for (column, map) in services.db.iter() {
+		let keys: Vec<Vec<u8>> = map
+			.raw_keys()
+			.ignore_err()
+			.map(<[u8]>::to_vec)
+			.collect()
+			.await;
+
+		for key in &keys {
+			let contains = |part: &[u8]| key.windows(part.len()).any(|window| window == part);
+			assert!(!contains(room_id.as_bytes()), "{column} has a key of the room");
+			for event_id in &room.events {
+				assert!(!contains(event_id.as_bytes()), "{column} has a key of {event_id}");
+			}
+
+			if SHORTROOM.contains(column) {
+				assert!(!key.starts_with(&shortroomid), "{column} has a key of the short room");
+			}
+
+			if column.starts_with("shorteventid_") {
+				assert!(!shorteventids.iter().any(|short| key.starts_with(short)), "{column}");
+			}
+		}
+	}
+
+	test.stop().await;
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::BTreeSet, sync::Arc};
 
 use conduwuit::{
 	Result,
@@ -7,11 +7,12 @@
 use database::{Deserialized, Json, Map};
 use futures::{Stream, StreamExt};
 use ruma::{
-	CanonicalJsonObject, RoomId, UserId,
+	CanonicalJsonObject, OwnedUserId, RoomId, UserId,
 	events::{AnySyncEphemeralRoomEvent, receipt::ReceiptEvent},
 	serde::Raw,
 };
 
+use super::{ReceiptKey, receipt_keys};
 use crate::{Dep, globals};
 
 pub(super) struct Data {
@@ -46,19 +47,42 @@ pub(super) async fn readreceipt_update(
 		room_id: &RoomId,
 		event: &ReceiptEvent,
 	) {
-		// Remove old entry
+		self.readreceipts_update(room_id, &[(user_id.to_owned(), event.clone())])
+			.await;
+	}
+
+	pub(super) async fn readreceipts_update(
+		&self,
+		room_id: &RoomId,
+		receipts: &[(OwnedUserId, ReceiptEvent)],
+	) {
+		let replacing: Vec<_> = receipts
+			.iter()
+			.map(|(user_id, event)| (user_id, receipt_keys(&event.content)))
+			.collect();
+
+		// Remove the entries replaced, of all the users in one pass over the room
 		let last_possible_key = (room_id, u64::MAX);
 		self.readreceiptid_readreceipt
-			.rev_keys_from_raw(&last_possible_key)
+			.rev_stream_from_raw(&last_possible_key)
 			.ignore_err()
-			.ready_take_while(|key| key.starts_with(room_id.as_bytes()))
-			.ready_filter_map(|key| key.ends_with(user_id.as_bytes()).then_some(key))
+			.ready_take_while(|(key, _)| key.starts_with(room_id.as_bytes()))
+			.ready_filter_map(|(key, stored)| {
+				replacing
+					.iter()
+					.any(|(user_id, keys)| {
+						key.ends_with(user_id.as_bytes()) && replaces(keys, stored)
+					})
+					.then_some(key)
+			})
 			.ready_for_each(|key| self.readreceiptid_readreceipt.del(key))
 			.await;
 
-		let count = self.services.globals.next_count().unwrap();
-		let latest_id = (room_id, count, user_id);
-		self.readreceiptid_readreceipt.put(latest_id, Json(event));
+		for (user_id, event) in receipts {
+			let count = self.services.globals.next_count().unwrap();
+			let latest_id = (room_id, count, user_id);
+			self.readreceiptid_readreceipt.put(latest_id, Json(event));
+		}
 	}
 
 	pub(super) fn readreceipts_since<'a>(
@@ -116,3 +140,11 @@ pub(super) async fn last_privateread_update(
 			.unwrap_or(0)
 	}
 }
+
+/// Whether a stored receipt event is replaced by one of the receipts `keys`.
+/// Those which cannot be read are replaced by anything.
+fn replaces(keys: &BTreeSet<ReceiptKey>, stored: &[u8]) -> bool {
+	serde_json::from_slice::<ReceiptEvent>(stored)
+		.map(|stored| receipt_keys(&stored.content).is_subset(keys))
+		.unwrap_or(true)
+}
@@ -1,15 +1,21 @@
 mod data;
+#[cfg(test)]
+mod tests;
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::Arc,
+};
 
 use conduwuit::{
 	Result, debug, err,
 	matrix::pdu::{PduCount, PduId, RawPduId},
+	utils::{MutexMap, ReadyExt, set::latest_by_key},
 	warn,
 };
-use futures::{Stream, TryFutureExt, try_join};
+use futures::{Stream, StreamExt, TryFutureExt, try_join};
 use ruma::{
-	OwnedEventId, OwnedUserId, RoomId, UserId,
+	OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
 	events::{
 		AnySyncEphemeralRoomEvent, SyncEphemeralRoomEvent,
 		receipt::{ReceiptEvent, ReceiptEventContent, Receipts},
@@ -23,6 +29,18 @@
 pub struct Service {
 	services: Services,
 	db: Data,
+
+	/// Held replacing receipts in a room, so two replacing the same receipt
+	/// at once cannot both be kept.
+	mutex: MutexMap<OwnedRoomId, ()>,
+}
+
+/// Which of a user's receipts a receipt replaces: their last of the same type
+/// in the same thread.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(super) struct ReceiptKey {
+	receipt_type: String,
+	thread: Option<String>,
 }
 
 struct Services {
@@ -40,6 +58,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 			},
 			db: Data::new(&args),
+			mutex: MutexMap::new(),
 		}))
 	}
 
@@ -47,14 +66,40 @@ fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 impl Service {
-	/// Replaces the previous read receipt.
+	/// Replaces the user's previous receipt of the same type in the same
+	/// thread.
 	pub async fn readreceipt_update(
 		&self,
 		user_id: &UserId,
 		room_id: &RoomId,
 		event: &ReceiptEvent,
 	) {
+		let lock = self.mutex.lock(room_id).await;
 		self.db.readreceipt_update(user_id, room_id, event).await;
+		drop(lock);
+
+		self.services
+			.sending
+			.flush_room(room_id)
+			.await
+			.expect("room flush failed");
+	}
+
+	/// Replaces the previous read receipts of each of the users in the room at
+	/// once, such as for those received together over federation.
+	pub async fn readreceipts_update(
+		&self,
+		room_id: &RoomId,
+		receipts: &[(OwnedUserId, ReceiptEvent)],
+	) {
+		if receipts.is_empty() {
+			return;
+		}
+
+		let lock = self.mutex.lock(room_id).await;
+		self.db.readreceipts_update(room_id, receipts).await;
+		drop(lock);
+
 		self.services
 			.sending
 			.flush_room(room_id)
@@ -114,6 +159,27 @@ pub fn readreceipts_since<'a>(
 		self.db.readreceipts_since(room_id, since)
 	}
 
+	/// The latest receipt of each user, receipt type and thread in the room
+	/// among those changed after `since` and up to `to`, oldest first. A
+	/// receipt replaced while they are read is not returned alongside the one
+	/// replacing it.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn latest_receipts_since<'a>(
+		&'a self,
+		room_id: &'a RoomId,
+		since: u64,
+		to: Option<u64>,
+	) -> Vec<ReceiptItem<'a>> {
+		let receipts = self
+			.db
+			.readreceipts_since(room_id, since)
+			.ready_take_while(|(_, count, _)| to.is_none_or(|to| *count <= to))
+			.collect()
+			.await;
+
+		latest_by_key(receipts, |(user_id, _, event)| (*user_id, raw_receipt_keys(event)))
+	}
+
 	/// Sets a private read marker at PDU `count`.
 	#[inline]
 	#[tracing::instrument(skip(self), level = "debug")]
@@ -144,15 +210,18 @@ pub fn pack_receipts<I>(receipts: I) -> Raw<SyncEphemeralRoomEvent<ReceiptEventC
 where
 	I: Iterator<Item = Raw<AnySyncEphemeralRoomEvent>>,
 {
-	let mut json = BTreeMap::new();
+	let mut json: BTreeMap<OwnedEventId, Receipts> = BTreeMap::new();
 	for value in receipts {
 		let receipt = serde_json::from_str::<SyncEphemeralRoomEvent<ReceiptEventContent>>(
 			value.json().get(),
 		);
 		match receipt {
 			| Ok(value) =>
-				for (event, receipt) in value.content {
-					json.insert(event, receipt);
+				for (event, receipts) in value.content {
+					let packed = json.entry(event).or_default();
+					for (receipt_type, users) in receipts {
+						packed.entry(receipt_type).or_default().extend(users);
+					}
 				},
 			| _ => {
 				debug!("failed to parse receipt: {:?}", receipt);
@@ -167,3 +236,23 @@ pub fn pack_receipts<I>(receipts: I) -> Raw<SyncEphemeralRoomEvent<ReceiptEventC
 			.expect("received valid json"),
 	)
 }
+
+/// The types and threads of the receipts in a receipt event.
+pub(super) fn receipt_keys(content: &ReceiptEventContent) -> BTreeSet<ReceiptKey> {
+	content
+		.values()
+		.flat_map(|receipts| receipts.iter())
+		.flat_map(|(receipt_type, users)| {
+			users.values().map(|receipt| ReceiptKey {
+				receipt_type: receipt_type.to_string(),
+				thread: receipt.thread.as_str().map(ToOwned::to_owned),
+			})
+		})
+		.collect()
+}
+
+fn raw_receipt_keys(event: &Raw<AnySyncEphemeralRoomEvent>) -> BTreeSet<ReceiptKey> {
+	serde_json::from_str::<SyncEphemeralRoomEvent<ReceiptEventContent>>(event.json().get())
+		.map(|event| receipt_keys(&event.content))
+		.unwrap_or_default()
+}
@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+
+use conduwuit::utils::set::latest_by_key;
+use ruma::{
+	event_id,
+	events::{
+		AnySyncEphemeralRoomEvent,
+		receipt::{ReceiptEventContent, ReceiptType},
+	},
+	serde::Raw,
+	user_id,
+};
+use serde_json::{Value, json};
+
+use super::{ReceiptKey, pack_receipts, receipt_keys};
+
+fn content(value: Value) -> ReceiptEventContent {
+	serde_json::from_value(value).expect("valid receipt content")
+}
+
+fn event(value: Value) -> Raw<AnySyncEphemeralRoomEvent> {
+	Raw::from_json(
+		serde_json::value::to_raw_value(&json!({ "type": "m.receipt", "content": value }))
+			.expect("valid json"),
+	)
+}
+
+fn key(receipt_type: &str, thread: Option<&str>) -> ReceiptKey {
+	ReceiptKey {
+		receipt_type: receipt_type.to_owned(),
+		thread: thread.map(ToOwned::to_owned),
+	}
+}
+
+#[test]
+fn receipt_keys_threads() {
+	let keys = receipt_keys(&content(json!({
+		"$a": {
+			"m.read": {
+				"@u:x": { "ts": 1 },
+			},
+		},
+		"$b": {
+			"m.read": {
+				"@u:x": { "ts": 2, "thread_id": "$root" },
+			},
+			"m.read.private": {
+				"@u:x": { "ts": 2, "thread_id": "main" },
+			},
+		},
+	})));
+
+	assert_eq!(keys.len(), 3);
+	assert!(keys.contains(&key("m.read", None)));
+	assert!(keys.contains(&key("m.read", Some("$root"))));
+	assert!(keys.contains(&key("m.read.private", Some("main"))));
+}
+
+#[test]
+fn pack_receipts_merged() {
+	let packed = pack_receipts(
+		[
+			event(json!({ "$a": { "m.read": { "@u:x": { "ts": 1 } } } })),
+			event(json!({ "$a": { "m.read": { "@v:x": { "ts": 2 } } } })),
+			event(json!({ "$b": { "m.read": { "@w:x": { "ts": 3 } } } })),
+		]
+		.into_iter(),
+	)
+	.deserialize()
+	.expect("valid receipts");
+
+	let users = &packed.content[event_id!("$a")][&ReceiptType::Read];
+	assert_eq!(users.len(), 2);
+	assert!(users.contains_key(user_id!("@u:x")));
+	assert!(users.contains_key(user_id!("@v:x")));
+	assert_eq!(packed.content.len(), 2);
+}
+
+/// Writers replacing receipts while syncs read them: each sync returns the
+/// changes in its window collapsed to the latest per key, so no sync returns
+/// the same receipt twice and the last receipt of each key is returned.
+#[test]
+fn receipts_latest_interleaved() {
+	const USERS: u64 = 3;
+	const THREADS: u64 = 2;
+
+	// deterministic pseudo-random sequence
+	let mut seed: u64 = 0x2545_F491;
+	let mut next = move |bound: u64| {
+		seed ^= seed << 13;
+		seed ^= seed >> 7;
+		seed ^= seed << 17;
+		seed % bound
+	};
+
+	// (count, (user, thread), value); stale entries are left in the log as a
+	// racing writer would leave them before being removed
+	let mut log: Vec<(u64, (u64, u64), u64)> = Vec::new();
+	let mut since = 0;
+	let mut delivered = HashMap::new();
+	for count in 1..=500 {
+		log.push((count, (next(USERS), next(THREADS)), count));
+
+		if next(7) == 0 {
+			let window: Vec<_> = log
+				.iter()
+				.filter(|(at, ..)| *at > since && *at <= count)
+				.copied()
+				.collect();
+
+			let latest = latest_by_key(window, |(_, key, _)| *key);
+			let keys: HashSet<_> = latest.iter().map(|(_, key, _)| *key).collect();
+			assert_eq!(keys.len(), latest.len(), "duplicate receipt in one sync");
+			assert!(latest.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+			for (_, key, value) in latest {
+				delivered.insert(key, value);
+			}
+
+			since = count;
+		}
+	}
+
+	let rest: Vec<_> = log.iter().filter(|(at, ..)| *at > since).copied().collect();
+
+	for (_, key, value) in latest_by_key(rest, |(_, key, _)| *key) {
+		delivered.insert(key, value);
+	}
+
+	let expected = latest_by_key(log, |(_, key, _)| *key);
+	assert_eq!(delivered.len(), expected.len());
+	for (_, key, value) in expected {
+		assert_eq!(delivered[&key], value);
+	}
+}
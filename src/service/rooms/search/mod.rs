@@ -1,3 +1,7 @@
+mod snippet;
+#[cfg(test)]
+mod tests;
+
 use std::sync::Arc;
 
 use conduwuit::{
@@ -13,6 +17,7 @@
 use futures::{Stream, StreamExt};
 use ruma::{RoomId, UserId, api::client::search::search_events::v3::Criteria};
 
+pub use self::snippet::{highlights, result_text, snippet};
 use crate::{
 	Dep, rooms,
 	rooms::{
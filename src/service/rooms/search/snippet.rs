@@ -0,0 +1,207 @@
+use std::{collections::BTreeSet, iter::once};
+
+use conduwuit::PduEvent;
+use serde::Deserialize;
+
+use super::tokenize;
+
+/// Bodies up to this many characters are not shortened into a snippet.
+pub(super) const SNIPPET_LEN: usize = 200;
+
+/// Characters of context kept before the first match in a snippet.
+const SNIPPET_CONTEXT: usize = 60;
+
+/// Tags which separate the words around them when stripped.
+const BLOCK_TAGS: &[&str] = &[
+	"blockquote",
+	"br",
+	"div",
+	"h1",
+	"h2",
+	"h3",
+	"h4",
+	"h5",
+	"h6",
+	"hr",
+	"li",
+	"ol",
+	"p",
+	"pre",
+	"td",
+	"th",
+	"tr",
+	"ul",
+];
+
+#[derive(Deserialize)]
+struct ExtractText {
+	body: Option<String>,
+	format: Option<String>,
+	formatted_body: Option<String>,
+}
+
+/// The text of a message as plain text: its HTML formatted body stripped of
+/// markup and reply fallbacks when it has one, otherwise its body, with runs
+/// of whitespace collapsed.
+#[must_use]
+pub fn result_text(pdu: &PduEvent) -> Option<String> {
+	let content: ExtractText = pdu.get_content().ok()?;
+	let text = match content.formatted_body {
+		| Some(html) if content.format.as_deref() == Some("org.matrix.custom.html") =>
+			plain_text(&html),
+		| _ => content.body?,
+	};
+
+	Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// The terms to highlight in results, as the search term is tokenized for
+/// querying the index, along with the forms in which they appear in the
+/// texts of the results.
+#[must_use]
+pub fn highlights<'a, I>(search_term: &str, texts: I) -> Vec<String>
+where
+	I: IntoIterator<Item = &'a str>,
+{
+	let mut highlights: BTreeSet<_> = tokenize(search_term).collect();
+	let forms: Vec<_> = texts
+		.into_iter()
+		.flat_map(words)
+		.map(|(_, word)| word)
+		.filter(|word| highlights.contains(&word.to_lowercase()))
+		.map(ToOwned::to_owned)
+		.collect();
+
+	highlights.extend(forms);
+	highlights.into_iter().collect()
+}
+
+/// A window of the text around the first of its words matching one of the
+/// highlights, when the text is too long to be shown whole.
+#[must_use]
+pub fn snippet(text: &str, highlights: &[String]) -> Option<String> {
+	let len = text.chars().count();
+	if len <= SNIPPET_LEN {
+		return None;
+	}
+
+	let matched = words(text)
+		.find(|(_, word)| highlights.contains(&word.to_lowercase()))
+		.and_then(|(offset, _)| text.get(..offset))
+		.map_or(0, |before| before.chars().count());
+
+	let end = matched
+		.saturating_sub(SNIPPET_CONTEXT)
+		.saturating_add(SNIPPET_LEN)
+		.min(len);
+
+	let start = end.saturating_sub(SNIPPET_LEN);
+	let window: String = text
+		.chars()
+		.skip(start)
+		.take(end.saturating_sub(start))
+		.collect();
+
+	let mut snippet = String::with_capacity(window.len().saturating_add(6));
+	if start > 0 {
+		snippet.push('…');
+	}
+
+	snippet.push_str(window.trim());
+	if end < len {
+		snippet.push('…');
+	}
+
+	Some(snippet)
+}
+
+/// Words of the text along with their byte offsets, split as by `tokenize`.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> + '_ {
+	let mut start = None;
+	text.char_indices()
+		.chain(once((text.len(), ' ')))
+		.filter_map(move |(offset, c)| {
+			if c.is_alphanumeric() {
+				start.get_or_insert(offset);
+				return None;
+			}
+
+			let start = start.take()?;
+			text.get(start..offset).map(|word| (start, word))
+		})
+}
+
+/// Strips the HTML of a formatted body down to its text, dropping reply
+/// fallbacks and decoding character references.
+pub(super) fn plain_text(html: &str) -> String {
+	let mut out = String::with_capacity(html.len());
+	let mut rest = html;
+	while let Some(pos) = rest.find(['<', '&']) {
+		let (text, tail) = rest.split_at(pos);
+		out.push_str(text);
+
+		if tail.starts_with('&') {
+			let decoded = tail
+				.find(';')
+				.and_then(|end| Some((end, decode_reference(tail.get(1..end)?)?)));
+
+			let Some((end, c)) = decoded else {
+				out.push('&');
+				rest = tail.get(1..).unwrap_or_default();
+				continue;
+			};
+
+			out.push(c);
+			rest = tail.get(end.saturating_add(1)..).unwrap_or_default();
+			continue;
+		}
+
+		let Some(end) = tail.find('>') else {
+			rest = "";
+			break;
+		};
+
+		let tag = tail.get(1..end).unwrap_or_default();
+		rest = tail.get(end.saturating_add(1)..).unwrap_or_default();
+
+		let name = tag
+			.trim_start_matches('/')
+			.split(|c: char| c.is_whitespace() || c == '/')
+			.next()
+			.unwrap_or_default()
+			.to_ascii_lowercase();
+
+		if name == "mx-reply" && !tag.starts_with('/') {
+			const CLOSE: &str = "</mx-reply>";
+			rest = rest
+				.find(CLOSE)
+				.and_then(|close| rest.get(close.saturating_add(CLOSE.len())..))
+				.unwrap_or_default();
+		} else if BLOCK_TAGS.contains(&name.as_str()) {
+			out.push(' ');
+		}
+	}
+
+	out.push_str(rest);
+	out
+}
+
+fn decode_reference(reference: &str) -> Option<char> {
+	match reference {
+		| "amp" => Some('&'),
+		| "lt" => Some('<'),
+		| "gt" => Some('>'),
+		| "quot" => Some('"'),
+		| "apos" => Some('\''),
+		| "nbsp" => Some(' '),
+		| _ => {
+			let number = reference.strip_prefix('#')?;
+			let code = match number.strip_prefix(['x', 'X']) {
+				| Some(hex) => u32::from_str_radix(hex, 16),
+				| None => number.parse(),
+			};
+
+			code.ok().and_then(char::from_u32)
+		},
+	}
+}
@@ -0,0 +1,104 @@
+use conduwuit::{PduEvent, pdu::EventHash};
+use ruma::{event_id, events::TimelineEventType, room_id, uint, user_id};
+use serde_json::{json, value::to_raw_value};
+
+use super::{
+	highlights, result_text, snippet,
+	snippet::{SNIPPET_LEN, plain_text},
+};
+
+fn message(content: serde_json::Value) -> PduEvent {
+	PduEvent {
+		event_id: event_id!("$message:example.org").to_owned(),
+		room_id: room_id!("!room:example.org").to_owned(),
+		sender: user_id!("@alice:example.org").to_owned(),
+		origin: None,
+		origin_server_ts: uint!(1),
+		kind: TimelineEventType::RoomMessage,
+		content: to_raw_value(&content).expect("valid content"),
+		state_key: None,
+		prev_events: Vec::new(),
+		depth: uint!(1),
+		auth_events: Vec::new(),
+		redacts: None,
+		unsigned: None,
+		hashes: EventHash { sha256: String::new() },
+		signatures: None,
+	}
+}
+
+#[test]
+fn highlights_multiple_terms() {
+	let texts = ["The Quick brown fox", "QUICK thinking, brown-nosing", "slow"];
+	let highlights = highlights("quick Brown, foxes!", texts);
+
+	assert_eq!(highlights, ["QUICK", "Quick", "brown", "foxes", "quick"]);
+}
+
+#[test]
+fn highlights_without_results() {
+	assert_eq!(highlights("Hello  world", []), ["hello", "world"]);
+	assert!(highlights("  ,. ", []).is_empty(), "no empty terms");
+}
+
+#[test]
+fn snippet_around_match() {
+	let before = "lorem ipsum ".repeat(20);
+	let after = " dolor sit amet".repeat(20);
+	let text = format!("{before}needle{after}");
+	let snippet = snippet(&text, &["needle".to_owned()]).expect("long text");
+
+	assert!(snippet.starts_with('…'), "{snippet}");
+	assert!(snippet.ends_with('…'), "{snippet}");
+	assert!(snippet.contains("needle"), "{snippet}");
+	assert!(snippet.chars().count() <= SNIPPET_LEN.saturating_add(2), "{snippet}");
+}
+
+#[test]
+fn snippet_of_short_text() {
+	assert!(snippet("a short needle", &["needle".to_owned()]).is_none());
+
+	let text = format!("needle {}", "haystack ".repeat(40));
+	let snippet = snippet(&text, &["needle".to_owned()]).expect("long text");
+	assert!(snippet.starts_with("needle"), "{snippet}");
+}
+
+#[test]
+fn snippet_strips_html() {
+	let html = format!(
+		"<mx-reply><blockquote><a href=\"https://matrix.to/\">In reply to</a> <b>old</b> \
+		 needle</blockquote></mx-reply><p>{}<strong>needle</strong> &amp; \
+		 <a href=\"https://example.org\">link</a></p><br/>{}",
+		"hay ".repeat(40),
+		"stack ".repeat(40),
+	);
+
+	let pdu = message(json!({
+		"msgtype": "m.text",
+		"body": "> <@bob:example.org> old needle\n\nplain needle",
+		"format": "org.matrix.custom.html",
+		"formatted_body": html,
+	}));
+
+	let text = result_text(&pdu).expect("text");
+	assert!(!text.contains("In reply to"), "{text}");
+
+	let snippet = snippet(&text, &["needle".to_owned()]).expect("long text");
+	assert!(!snippet.contains(['<', '>']), "{snippet}");
+	assert!(!snippet.contains("href"), "{snippet}");
+	assert!(snippet.contains("needle & link stack"), "{snippet}");
+}
+
+#[test]
+fn result_text_of_plain_body() {
+	let pdu = message(json!({ "msgtype": "m.text", "body": "<b>not\n  html</b>" }));
+
+	assert_eq!(result_text(&pdu).as_deref(), Some("<b>not html</b>"));
+}
+
+#[test]
+fn html_to_plain_text() {
+	assert_eq!(plain_text("a<br>b<em>c</em>"), "a bc");
+	assert_eq!(plain_text("&lt;tag&gt; &#65;&#x42; &bogus; & done"), "<tag> AB &bogus; & done");
+	assert_eq!(plain_text("unterminated <b"), "unterminated ");
+}
@@ -0,0 +1,29 @@
+#[cfg(conduwuit_bench)]
+extern crate test;
+
+#[cfg(conduwuit_bench)]
+#[cfg_attr(conduwuit_bench, bench)]
+fn send_queue_uncontended(b: &mut test::Bencher) {
+	use futures::FutureExt;
+	use ruma::{room_id, user_id};
+
+	let queue = super::Queue::default();
+	let (alice, room) = (user_id!("@alice:example.org"), room_id!("!a:example.org"));
+
+	b.iter(|| {
+		let mut ticket = queue.enter(alice, room, 32).expect("queued");
+		ticket.turn().now_or_never().expect("no wait");
+		test::black_box(ticket);
+	});
+}
+
+#[cfg(conduwuit_bench)]
+#[cfg_attr(conduwuit_bench, bench)]
+fn send_queue_disabled(b: &mut test::Bencher) {
+	use ruma::{room_id, user_id};
+
+	let queue = super::Queue::default();
+	let (alice, room) = (user_id!("@alice:example.org"), room_id!("!a:example.org"));
+
+	b.iter(|| test::black_box(queue.enter(alice, room, 0)));
+}
@@ -0,0 +1,50 @@
+mod queue;
+
+#[cfg(test)]
+mod benches;
+#[cfg(test)]
+mod tests;
+
+use std::{fmt::Write, sync::Arc};
+
+use async_trait::async_trait;
+use conduwuit::{Result, Server};
+use ruma::{RoomId, UserId};
+
+pub use self::queue::{Queue, Ticket};
+
+/// Orders the messages a user sends to a room by when their requests arrive,
+/// rather than by when each finishes being received and authenticated.
+pub struct Service {
+	server: Arc<Server>,
+	queue: Queue,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			server: args.server.clone(),
+			queue: Queue::default(),
+		}))
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		writeln!(out, "send_queue_pending: {}", self.queue.len())?;
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Takes the next place in the queue of messages from `sender` to `room_id`
+	/// as a request arrives. Returns None when the queue is disabled or full,
+	/// in which case the message is not ordered with the others.
+	#[must_use]
+	pub fn enter(&self, sender: &UserId, room_id: &RoomId) -> Option<Ticket> {
+		self.queue
+			.enter(sender, room_id, self.server.config.send_queue_capacity)
+	}
+}
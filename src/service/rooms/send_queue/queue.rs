@@ -0,0 +1,118 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	sync::{Arc, Mutex},
+};
+
+use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+use tokio::sync::watch;
+
+type Key = (OwnedUserId, OwnedRoomId);
+type Queues = Arc<Mutex<HashMap<Key, Entry>>>;
+
+/// Per-(user, room) FIFOs of tickets. Queues are created by the first ticket
+/// and removed once every ticket issued by them has been dropped.
+#[derive(Debug, Default)]
+pub struct Queue {
+	queues: Queues,
+}
+
+#[derive(Debug)]
+struct Entry {
+	/// Number of the next ticket issued.
+	next: u64,
+
+	/// Every ticket numbered below this has been dropped. Waiting tickets are
+	/// notified as it advances.
+	serving: watch::Sender<u64>,
+
+	/// Tickets dropped before their turn, so their successors don't have to
+	/// wait for them.
+	done: BTreeSet<u64>,
+}
+
+/// A place in the queue; the holder's turn comes once every earlier ticket
+/// has been dropped.
+#[derive(Debug)]
+pub struct Ticket {
+	key: Key,
+	number: u64,
+	serving: watch::Receiver<u64>,
+	queues: Queues,
+}
+
+impl Queue {
+	#[must_use]
+	pub fn enter(&self, sender: &UserId, room_id: &RoomId, capacity: usize) -> Option<Ticket> {
+		let capacity: u64 = capacity.try_into().ok().filter(|&capacity| capacity > 0)?;
+		let key: Key = (sender.into(), room_id.into());
+		let mut queues = self.queues.lock().expect("locked");
+		let entry = queues.entry(key.clone()).or_insert_with(|| Entry {
+			next: 0,
+			serving: watch::Sender::new(0),
+			done: BTreeSet::new(),
+		});
+
+		if entry.next.saturating_sub(*entry.serving.borrow()) >= capacity {
+			if entry.next == 0 {
+				queues.remove(&key);
+			}
+
+			return None;
+		}
+
+		let number = entry.next;
+		entry.next = entry.next.saturating_add(1);
+
+		Some(Ticket {
+			number,
+			serving: entry.serving.subscribe(),
+			queues: self.queues.clone(),
+			key,
+		})
+	}
+
+	/// Number of tickets issued whose turn has not yet passed.
+	#[must_use]
+	pub fn len(&self) -> usize {
+		self.queues
+			.lock()
+			.expect("locked")
+			.values()
+			.map(|entry| entry.next.saturating_sub(*entry.serving.borrow()))
+			.map(|pending| usize::try_from(pending).unwrap_or(usize::MAX))
+			.fold(0_usize, usize::saturating_add)
+	}
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.queues.lock().expect("locked").is_empty() }
+}
+
+impl Ticket {
+	/// Waits until every earlier ticket for the same user and room has been
+	/// dropped. Returns immediately when there are none.
+	pub async fn turn(&mut self) {
+		let number = self.number;
+		_ = self.serving.wait_for(|&serving| serving >= number).await;
+	}
+}
+
+impl Drop for Ticket {
+	fn drop(&mut self) {
+		let mut queues = self.queues.lock().expect("locked");
+		let Some(entry) = queues.get_mut(&self.key) else {
+			return;
+		};
+
+		entry.done.insert(self.number);
+		let mut serving = *entry.serving.borrow();
+		while entry.done.remove(&serving) {
+			serving = serving.saturating_add(1);
+		}
+
+		if serving >= entry.next {
+			queues.remove(&self.key);
+		} else {
+			entry.serving.send_replace(serving);
+		}
+	}
+}
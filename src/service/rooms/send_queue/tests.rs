@@ -0,0 +1,69 @@
+use futures::FutureExt;
+use ruma::{room_id, user_id};
+
+use super::Queue;
+
+#[test]
+fn send_queue_uncontended() {
+	let queue = Queue::default();
+	let (alice, room) = (user_id!("@alice:example.org"), room_id!("!a:example.org"));
+
+	let mut ticket = queue.enter(alice, room, 32).expect("queued");
+	assert!(ticket.turn().now_or_never().is_some(), "first ticket never waits");
+	assert_eq!(queue.len(), 1);
+
+	drop(ticket);
+	assert!(queue.is_empty(), "queue removed with its last ticket");
+
+	assert!(queue.enter(alice, room, 0).is_none(), "disabled");
+	assert!(queue.is_empty());
+}
+
+#[test]
+fn send_queue_arrival_order() {
+	let queue = Queue::default();
+	let (alice, bob) = (user_id!("@alice:example.org"), user_id!("@bob:example.org"));
+	let room = room_id!("!a:example.org");
+
+	let first = queue.enter(alice, room, 32).expect("queued");
+	let mut second = queue.enter(alice, room, 32).expect("queued");
+	let third = queue.enter(alice, room, 32).expect("queued");
+	let mut fourth = queue.enter(alice, room, 32).expect("queued");
+	let mut other = queue.enter(bob, room, 32).expect("queued");
+
+	assert!(second.turn().now_or_never().is_none(), "waits for the first");
+	assert!(other.turn().now_or_never().is_some(), "other senders are not ordered");
+
+	// A later ticket abandoned before its turn does not hold up the others.
+	drop(third);
+	assert!(second.turn().now_or_never().is_none(), "still waits for the first");
+
+	drop(first);
+	assert!(second.turn().now_or_never().is_some(), "first dropped");
+	assert!(fourth.turn().now_or_never().is_none(), "waits for the second");
+
+	drop(second);
+	assert!(fourth.turn().now_or_never().is_some(), "third already dropped");
+
+	drop((fourth, other));
+	assert!(queue.is_empty());
+}
+
+#[test]
+fn send_queue_overflow() {
+	let queue = Queue::default();
+	let (alice, room) = (user_id!("@alice:example.org"), room_id!("!a:example.org"));
+
+	let first = queue.enter(alice, room, 2).expect("queued");
+	let second = queue.enter(alice, room, 2).expect("queued");
+	assert!(queue.enter(alice, room, 2).is_none(), "full");
+
+	drop(first);
+	let mut third = queue
+		.enter(alice, room, 2)
+		.expect("queued once there is room");
+	assert!(third.turn().now_or_never().is_none(), "ordered after the second");
+
+	drop(second);
+	assert!(third.turn().now_or_never().is_some());
+}
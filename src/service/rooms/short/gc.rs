@@ -0,0 +1,202 @@
+use std::{collections::HashSet, mem::size_of, sync::Arc};
+
+use conduwuit::{
+	Result, debug, implement,
+	utils::{IterStream, TryReadyExt, u64_from_u8},
+};
+use database::Map;
+use futures::StreamExt;
+
+use super::{ShortEventId, ShortStateKey};
+
+/// Outcome of a pass of [`Service::gc_short_ids`].
+#[derive(Debug, Default)]
+pub struct Gc {
+	/// Short event IDs found unreferenced.
+	pub events: usize,
+
+	/// Short state keys found unreferenced.
+	pub statekeys: usize,
+
+	/// Bytes of keys and values in both directions of the removed mappings.
+	pub bytes: usize,
+}
+
+#[derive(Default)]
+struct Referenced {
+	events: HashSet<ShortEventId>,
+	statekeys: HashSet<ShortStateKey>,
+}
+
+/// Removes short event ID and short state key mappings which nothing refers to
+/// any longer, e.g. after the events of a room were purged. A short event ID
+/// is kept while its event is stored as a PDU or outlier, or it is present in
+/// any state diff, auth chain or event state mapping. A short state key is
+/// kept while it is present in any state diff.
+///
+/// Only IDs below `watermark` are considered so mappings created during the
+/// pass are never collected. The caller is responsible for holding off state
+/// writes for IDs allocated earlier which are still being ingested. This
+/// should run after any collection of unreferenced state groups so their
+/// diffs no longer count as references. The provenance recorded for a
+/// collected event goes with it, and the in-memory caches of state info and
+/// auth chains, which hold short IDs, are cleared once any mapping is removed.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "info")]
+pub async fn gc_short_ids(&self, watermark: u64, dry_run: bool) -> Result<Gc> {
+	let referenced = self.referenced().await?;
+	debug!(
+		events = referenced.events.len(),
+		statekeys = referenced.statekeys.len(),
+		"Collected references"
+	);
+
+	let mut events = Vec::new();
+	self.db
+		.shorteventid_eventid
+		.raw_stream()
+		.ready_try_for_each(|(short, event_id)| {
+			let shorteventid = u64_from_u8(short);
+			if shorteventid < watermark && !referenced.events.contains(&shorteventid) {
+				events.push((short.to_vec(), event_id.to_vec()));
+			}
+
+			Ok(())
+		})
+		.await?;
+
+	let events: Vec<_> = events
+		.into_iter()
+		.stream()
+		.filter_map(|(short, event_id)| async move {
+			let stored = self.db.eventid_pduid.exists(&event_id).await.is_ok()
+				|| self.db.eventid_outlierpdu.exists(&event_id).await.is_ok();
+
+			(!stored).then_some((short, event_id))
+		})
+		.collect()
+		.await;
+
+	let mut statekeys = Vec::new();
+	self.db
+		.shortstatekey_statekey
+		.raw_stream()
+		.ready_try_for_each(|(short, statekey)| {
+			let shortstatekey = u64_from_u8(short);
+			if shortstatekey < watermark && !referenced.statekeys.contains(&shortstatekey) {
+				statekeys.push((short.to_vec(), statekey.to_vec()));
+			}
+
+			Ok(())
+		})
+		.await?;
+
+	let gc = Gc {
+		events: events.len(),
+		statekeys: statekeys.len(),
+		bytes: events
+			.iter()
+			.chain(statekeys.iter())
+			.map(|(short, long)| mapping_size(short, long))
+			.fold(0_usize, usize::saturating_add),
+	};
+
+	if dry_run {
+		return Ok(gc);
+	}
+
+	for (short, event_id) in &events {
+		let (forward, reverse) = (&self.db.shorteventid_eventid, &self.db.eventid_shorteventid);
+		remove_mapping(forward, reverse, short, event_id).await;
+		self.db.shorteventid_provenance.remove(short);
+	}
+
+	for (short, statekey) in &statekeys {
+		let (forward, reverse) =
+			(&self.db.shortstatekey_statekey, &self.db.statekey_shortstatekey);
+		remove_mapping(forward, reverse, short, statekey).await;
+	}
+
+	if gc.events > 0 || gc.statekeys > 0 {
+		self.services.auth_chain.clear_cache();
+		self.services
+			.state_compressor
+			.stateinfo_cache
+			.lock()
+			.expect("locked")
+			.clear();
+	}
+
+	Ok(gc)
+}
+
+/// Removes both directions of a mapping; the reverse entry is only removed
+/// while it still points at `short`.
+async fn remove_mapping(forward: &Map, reverse: &Arc<Map>, short: &[u8], long: &[u8]) {
+	forward.remove(short);
+	if reverse
+		.get(long)
+		.await
+		.is_ok_and(|current| *current == *short)
+	{
+		reverse.remove(long);
+	}
+}
+
+#[implement(super::Service)]
+async fn referenced(&self) -> Result<Referenced> {
+	const STRIDE: usize = size_of::<u64>();
+
+	let mut referenced = Referenced::default();
+
+	// Values are the parent shortstatehash followed by the added and then the
+	// removed (shortstatekey, shorteventid) pairs, separated by a zero.
+	self.db
+		.shortstatehash_statediff
+		.raw_stream()
+		.ready_try_for_each(|(_, diff)| {
+			let mut i = STRIDE;
+			while let Some(pair) = diff.get(i..i.saturating_add(2 * STRIDE)) {
+				let (statekey, event) = pair.split_at(STRIDE);
+				if statekey.iter().all(|&b| b == 0) {
+					i = i.saturating_add(STRIDE);
+					continue;
+				}
+
+				referenced.statekeys.insert(u64_from_u8(statekey));
+				referenced.events.insert(u64_from_u8(event));
+				i = i.saturating_add(2 * STRIDE);
+			}
+
+			Ok(())
+		})
+		.await?;
+
+	self.db
+		.shorteventid_authchain
+		.raw_stream()
+		.ready_try_for_each(|(short, chain)| {
+			referenced.events.insert(u64_from_u8(short));
+			referenced
+				.events
+				.extend(chain.chunks_exact(STRIDE).map(u64_from_u8));
+
+			Ok(())
+		})
+		.await?;
+
+	self.db
+		.shorteventid_shortstatehash
+		.raw_keys()
+		.ready_try_for_each(|short| {
+			referenced.events.insert(u64_from_u8(short));
+			Ok(())
+		})
+		.await?;
+
+	Ok(referenced)
+}
+
+fn mapping_size(short: &[u8], long: &[u8]) -> usize {
+	short.len().saturating_add(long.len()).saturating_mul(2)
+}
@@ -1,17 +1,24 @@
+mod gc;
+mod warmup;
+
 use std::{borrow::Borrow, fmt::Debug, mem::size_of_val, sync::Arc};
 
+use async_trait::async_trait;
 pub use conduwuit::matrix::pdu::{ShortEventId, ShortId, ShortRoomId, ShortStateKey};
-use conduwuit::{Result, err, implement, matrix::StateKey, utils, utils::IterStream};
-use database::{Deserialized, Get, Map, Qry};
+use conduwuit::{Result, Server, err, implement, matrix::StateKey, utils, utils::IterStream};
+use database::{Deserialized, Get, Lane, Map, Qry};
 use futures::{Stream, StreamExt};
-use ruma::{EventId, RoomId, events::StateEventType};
+use ruma::{EventId, OwnedServerName, RoomId, ServerName, events::StateEventType};
 use serde::Deserialize;
+use tokio::sync::Notify;
 
-use crate::{Dep, globals};
+pub use self::gc::Gc;
+use crate::{Dep, globals, rooms};
 
 pub struct Service {
 	db: Data,
 	services: Services,
+	interrupt: Notify,
 }
 
 struct Data {
@@ -21,14 +28,28 @@ struct Data {
 	shortstatekey_statekey: Arc<Map>,
 	roomid_shortroomid: Arc<Map>,
 	statehash_shortstatehash: Arc<Map>,
+	shortstatehash_statediff: Arc<Map>,
+	shorteventid_authchain: Arc<Map>,
+	shorteventid_shortstatehash: Arc<Map>,
+	eventid_pduid: Arc<Map>,
+	eventid_outlierpdu: Arc<Map>,
+	servername_shortservername: Arc<Map>,
+	shortservername_servername: Arc<Map>,
+	shorteventid_provenance: Arc<Map>,
 }
 
 struct Services {
+	server: Arc<Server>,
 	globals: Dep<globals::Service>,
+	auth_chain: Dep<rooms::auth_chain::Service>,
+	state: Dep<rooms::state::Service>,
+	state_compressor: Dep<rooms::state_compressor::Service>,
 }
 
 pub type ShortStateHash = ShortId;
+pub type ShortServerName = ShortId;
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
@@ -39,13 +60,42 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				shortstatekey_statekey: args.db["shortstatekey_statekey"].clone(),
 				roomid_shortroomid: args.db["roomid_shortroomid"].clone(),
 				statehash_shortstatehash: args.db["statehash_shortstatehash"].clone(),
+				shortstatehash_statediff: args.db["shortstatehash_statediff"].clone(),
+				shorteventid_authchain: args.db["shorteventid_authchain"].clone(),
+				shorteventid_shortstatehash: args.db["shorteventid_shortstatehash"].clone(),
+				eventid_pduid: args.db["eventid_pduid"].clone(),
+				eventid_outlierpdu: args.db["eventid_outlierpdu"].clone(),
+				servername_shortservername: args.db["servername_shortservername"].clone(),
+				shortservername_servername: args.db["shortservername_servername"].clone(),
+				shorteventid_provenance: args.db["shorteventid_provenance"].clone(),
 			},
 			services: Services {
+				server: args.server.clone(),
 				globals: args.depend::<globals::Service>("globals"),
+				auth_chain: args.depend::<rooms::auth_chain::Service>("rooms::auth_chain"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_compressor: args
+					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
 			},
+			interrupt: Notify::new(),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		if !self.services.server.config.cache_warmup {
+			return Ok(());
+		}
+
+		tokio::select! {
+			() = self.interrupt.notified() => (),
+			() = Lane::Background.scope(self.warmup()) => (),
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -258,3 +308,48 @@ pub async fn get_or_create_shortroomid(&self, room_id: &RoomId) -> ShortRoomId {
 			short
 		})
 }
+
+#[implement(Service)]
+pub async fn get_or_create_shortservername(&self, server_name: &ServerName) -> ShortServerName {
+	const BUFSIZE: usize = size_of::<ShortServerName>();
+
+	if let Ok(shortservername) = self
+		.db
+		.servername_shortservername
+		.get(server_name)
+		.await
+		.deserialized()
+	{
+		return shortservername;
+	}
+
+	let shortservername = self.services.globals.next_count().unwrap();
+	debug_assert!(size_of_val(&shortservername) == BUFSIZE, "buffer requirement changed");
+
+	self.db
+		.servername_shortservername
+		.raw_aput::<BUFSIZE, _, _>(server_name, shortservername);
+
+	self.db
+		.shortservername_servername
+		.aput_raw::<BUFSIZE, _, _>(shortservername, server_name);
+
+	shortservername
+}
+
+#[implement(Service)]
+pub async fn get_servername_from_short(
+	&self,
+	shortservername: ShortServerName,
+) -> Result<OwnedServerName> {
+	const BUFSIZE: usize = size_of::<ShortServerName>();
+
+	self.db
+		.shortservername_servername
+		.aqry::<BUFSIZE, _>(&shortservername)
+		.await
+		.deserialized()
+		.map_err(|e| {
+			err!(Database("Failed to find ServerName from short {shortservername:?}: {e:?}"))
+		})
+}
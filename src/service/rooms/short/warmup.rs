@@ -0,0 +1,105 @@
+//! Cache Warming
+//!
+//! The short ID mappings are served from the row caches of their columns,
+//! which are empty after a restart. Warming reads the current state of the
+//! most recently changed rooms through the usual lookups so the first syncs
+//! after startup are not all served from disk.
+
+use std::time::{Duration, Instant};
+
+use conduwuit::{
+	debug, implement, info,
+	utils::{IterStream, ReadyExt},
+};
+use futures::StreamExt;
+use ruma::OwnedEventId;
+
+use crate::rooms::state_compressor::parse_compressed_state_event;
+
+/// Longest warming may take before giving up on the remaining rooms.
+const BUDGET: Duration = Duration::from_secs(2);
+
+/// Reads the state of recently changed rooms until the short state key and
+/// short event ID caches would be full, the time budget is spent or the
+/// server is shutting down. The state info of each room is loaded on the way
+/// and so warms that cache as well.
+#[implement(super::Service)]
+#[tracing::instrument(name = "warmup", level = "debug", skip(self))]
+pub(super) async fn warmup(&self) {
+	let config = &self.services.server.config;
+	let capacity = |cap: u32| usize::try_from(cap).unwrap_or(usize::MAX);
+	let statekeys_cap = capacity(config.shortstatekey_cache_capacity);
+	let events_cap = capacity(config.shorteventid_cache_capacity);
+
+	let started = Instant::now();
+	let rooms = self
+		.services
+		.state
+		.recently_changed_rooms(capacity(config.stateinfo_cache_capacity))
+		.await;
+
+	let (mut warmed_rooms, mut statekeys, mut events) = (0_usize, 0_usize, 0_usize);
+	for (shortstatehash, room_id) in rooms {
+		if !self.services.server.running() || started.elapsed() >= BUDGET {
+			break;
+		}
+
+		if statekeys >= statekeys_cap && events >= events_cap {
+			break;
+		}
+
+		let full_state = match self
+			.services
+			.state_compressor
+			.load_shortstatehash_info(shortstatehash)
+			.await
+		{
+			| Ok(info) => info.last().map(|info| info.full_state.clone()),
+			| Err(e) => {
+				debug!(%room_id, "Skipping room whose state could not be loaded: {e}");
+				continue;
+			},
+		};
+
+		let (shortstatekeys, shorteventids): (Vec<_>, Vec<_>) = full_state
+			.iter()
+			.flat_map(|state| state.iter().copied())
+			.map(parse_compressed_state_event)
+			.unzip();
+
+		let found = self
+			.multi_get_statekey_from_short(
+				shortstatekeys
+					.into_iter()
+					.take(statekeys_cap.saturating_sub(statekeys))
+					.stream(),
+			)
+			.ready_filter(Result::is_ok)
+			.count()
+			.await;
+
+		statekeys = statekeys.saturating_add(found);
+
+		let found = self
+			.multi_get_eventid_from_short::<OwnedEventId, _>(
+				shorteventids
+					.into_iter()
+					.take(events_cap.saturating_sub(events))
+					.stream(),
+			)
+			.ready_filter(Result::is_ok)
+			.count()
+			.await;
+
+		events = events.saturating_add(found);
+		warmed_rooms = warmed_rooms.saturating_add(1);
+	}
+
+	info!(
+		rooms = warmed_rooms,
+		statekeys,
+		events,
+		elapsed = ?started.elapsed(),
+		"Warmed short ID caches"
+	);
+}
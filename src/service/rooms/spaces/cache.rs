@@ -0,0 +1,189 @@
+use std::{
+	fmt::Write,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Result, at};
+use lru_cache::LruCache;
+use ruma::{
+	OwnedRoomId, RoomId,
+	api::federation::space::{
+		SpaceHierarchyChildSummary, SpaceHierarchyParentSummary, get_hierarchy,
+	},
+	room::RoomType,
+};
+
+use crate::cache::{Counted, Resizable, Usage};
+
+/// Size-bounded and time-bounded cache of space hierarchy summaries, local and
+/// remote. A room whose servers could not give us its summary is cached as
+/// `None` so they are not asked again until it expires.
+pub(super) struct Cache {
+	entries: Counted<Mutex<LruCache<Key, Entry>>>,
+	ttl: Duration,
+}
+
+/// The room and whether its summary was requested with `suggested_only`. A
+/// summary of all children also serves requests for the suggested ones only.
+type Key = (OwnedRoomId, bool);
+type Entry = (Instant, Option<SpaceHierarchyParentSummary>);
+
+/// Whether an expired summary may be served.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Freshness {
+	Fresh,
+
+	/// Paginating through a hierarchy, which is walked from the top again for
+	/// each page, so the rooms on the way must stay as they were.
+	Snapshot,
+}
+
+impl Cache {
+	pub(super) fn new(capacity: usize, ttl: Duration) -> Self {
+		Self {
+			entries: Mutex::new(LruCache::new(capacity)).into(),
+			ttl,
+		}
+	}
+
+	pub(super) fn get(
+		&self,
+		room_id: &RoomId,
+		suggested_only: bool,
+		freshness: Freshness,
+	) -> Option<Option<SpaceHierarchyParentSummary>> {
+		let mut entries = self.entries.lock().expect("locked");
+		let mut lookup = |key: Key| match entries.get_mut(&key) {
+			| Some((cached, summary))
+				if freshness == Freshness::Snapshot || cached.elapsed() < self.ttl =>
+				Some(summary.clone()),
+			| _ => None,
+		};
+
+		let found = match lookup((room_id.to_owned(), suggested_only)) {
+			| None if suggested_only => lookup((room_id.to_owned(), false)),
+			| found => found,
+		};
+
+		self.entries.counters().tally(found)
+	}
+
+	pub(super) fn insert(
+		&self,
+		room_id: &RoomId,
+		suggested_only: bool,
+		summary: Option<SpaceHierarchyParentSummary>,
+	) {
+		self.entries
+			.lock()
+			.expect("locked")
+			.insert((room_id.to_owned(), suggested_only), (Instant::now(), summary));
+	}
+
+	/// Caches a federation `/hierarchy` response for the room, along with the
+	/// children which are not spaces themselves: their summaries are
+	/// complete without the `m.space.child` state the response leaves out.
+	pub(super) fn insert_response(
+		&self,
+		room_id: &RoomId,
+		suggested_only: bool,
+		response: &get_hierarchy::v1::Response,
+	) {
+		let children = response
+			.children
+			.iter()
+			.filter(|child| child.room_type != Some(RoomType::Space))
+			.filter(|child| child.room_id != room_id)
+			.cloned()
+			.map(child_summary);
+
+		for child in children {
+			let child_id = child.room_id.clone();
+			self.insert(&child_id, suggested_only, Some(child));
+		}
+
+		self.insert(room_id, suggested_only, Some(response.room.clone()));
+	}
+
+	/// Drops the cached summaries of a room, e.g. when its children changed.
+	pub(super) fn remove_room(&self, room_id: &RoomId) -> bool {
+		let mut entries = self.entries.lock().expect("locked");
+		let keys: Vec<Key> = entries
+			.iter()
+			.map(at!(0))
+			.filter(|(cached_room_id, _)| *cached_room_id == room_id)
+			.cloned()
+			.collect();
+
+		for key in &keys {
+			entries.remove(key);
+		}
+
+		!keys.is_empty()
+	}
+
+	pub(super) fn clear(&self) { self.entries.lock().expect("locked").clear(); }
+
+	pub(super) fn len(&self) -> usize { self.entries.lock().expect("locked").len() }
+
+	pub(super) fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let len = self.len();
+		let counters = self.entries.counters();
+
+		writeln!(out, "roomid_spacehierarchy_cache: {len} ({counters})")?;
+
+		Ok(())
+	}
+
+	#[cfg(test)]
+	pub(super) fn hits(&self) -> u64 { self.entries.counters().hits() }
+
+	#[cfg(test)]
+	pub(super) fn misses(&self) -> u64 { self.entries.counters().misses() }
+}
+
+#[async_trait]
+impl Resizable for Cache {
+	async fn usage(&self) -> Usage { self.entries.usage().await }
+
+	async fn set_capacity(&self, capacity: usize) { self.entries.set_capacity(capacity).await; }
+
+	fn reset_counters(&self) { self.entries.reset_counters(); }
+}
+
+fn child_summary(child: SpaceHierarchyChildSummary) -> SpaceHierarchyParentSummary {
+	let SpaceHierarchyChildSummary {
+		canonical_alias,
+		name,
+		num_joined_members,
+		room_id,
+		topic,
+		world_readable,
+		guest_can_join,
+		avatar_url,
+		join_rule,
+		room_type,
+		allowed_room_ids,
+		encryption,
+		room_version,
+	} = child;
+
+	SpaceHierarchyParentSummary {
+		canonical_alias,
+		name,
+		num_joined_members,
+		room_id,
+		topic,
+		world_readable,
+		guest_can_join,
+		avatar_url,
+		join_rule,
+		room_type,
+		allowed_room_ids,
+		children_state: Vec::new(),
+		encryption,
+		room_version,
+	}
+}
@@ -1,12 +1,13 @@
+mod cache;
 mod pagination_token;
 #[cfg(test)]
 mod tests;
 
-use std::{fmt::Write, sync::Arc};
+use std::{fmt::Write, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use conduwuit::{
-	Err, Error, PduEvent, Result, implement,
+	Err, Error, PduEvent, Result, err, implement,
 	utils::{
 		IterStream,
 		future::{BoolExt, TryExtExt},
@@ -15,15 +16,11 @@
 	},
 };
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, pin_mut, stream::FuturesUnordered};
-use lru_cache::LruCache;
 use ruma::{
 	OwnedEventId, OwnedRoomId, OwnedServerName, RoomId, ServerName, UserId,
 	api::{
 		client::space::SpaceHierarchyRoomsChunk,
-		federation::{
-			self,
-			space::{SpaceHierarchyChildSummary, SpaceHierarchyParentSummary},
-		},
+		federation::{self, space::SpaceHierarchyParentSummary},
 	},
 	events::{
 		StateEventType,
@@ -32,14 +29,13 @@
 	serde::Raw,
 	space::SpaceRoomJoinRule,
 };
-use tokio::sync::{Mutex, MutexGuard};
 
-pub use self::pagination_token::PaginationToken;
-use crate::{Dep, rooms, sending};
+pub use self::{cache::Freshness, pagination_token::PaginationToken};
+use crate::{Dep, cache::Resizable, rooms, sending};
 
 pub struct Service {
 	services: Services,
-	pub roomid_spacehierarchy_cache: Mutex<Cache>,
+	roomid_spacehierarchy_cache: cache::Cache,
 }
 
 struct Services {
@@ -51,10 +47,6 @@ struct Services {
 	sending: Dep<sending::Service>,
 }
 
-pub struct CachedSpaceHierarchySummary {
-	summary: SpaceHierarchyParentSummary,
-}
-
 #[allow(clippy::large_enum_variant)]
 pub enum SummaryAccessibility {
 	Accessible(SpaceHierarchyParentSummary),
@@ -68,14 +60,13 @@ pub enum Identifier<'a> {
 	ServerName(&'a ServerName),
 }
 
-type Cache = LruCache<OwnedRoomId, Option<CachedSpaceHierarchySummary>>;
-
 #[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		let config = &args.server.config;
 		let cache_size = f64::from(config.roomid_spacehierarchy_cache_capacity);
 		let cache_size = cache_size * config.cache_capacity_modifier;
+		let cache_ttl = Duration::from_secs(config.roomid_spacehierarchy_cache_ttl);
 		Ok(Arc::new(Self {
 			services: Services {
 				state_accessor: args
@@ -87,23 +78,40 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 				sending: args.depend::<sending::Service>("sending"),
 			},
-			roomid_spacehierarchy_cache: Mutex::new(LruCache::new(usize_from_f64(cache_size)?)),
+			roomid_spacehierarchy_cache: cache::Cache::new(
+				usize_from_f64(cache_size)?,
+				cache_ttl,
+			),
 		}))
 	}
 
 	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
-		let roomid_spacehierarchy_cache = self.roomid_spacehierarchy_cache.lock().await.len();
+		self.roomid_spacehierarchy_cache.memory_usage(out)
+	}
 
-		writeln!(out, "roomid_spacehierarchy_cache: {roomid_spacehierarchy_cache}")?;
+	async fn clear_cache(&self) { self.roomid_spacehierarchy_cache.clear(); }
 
-		Ok(())
+	async fn clear_cache_entry(&self, key: &str) -> Result<bool> {
+		let room_id = RoomId::parse(key)
+			.map_err(|e| err!(Request(InvalidParam("Invalid room ID {key:?}: {e}"))))?;
+
+		Ok(self.roomid_spacehierarchy_cache.remove_room(&room_id))
 	}
 
-	async fn clear_cache(&self) { self.roomid_spacehierarchy_cache.lock().await.clear(); }
+	fn caches(&self) -> Vec<(&'static str, &dyn Resizable)> {
+		vec![("roomid_spacehierarchy_cache", &self.roomid_spacehierarchy_cache)]
+	}
 
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
+/// Drops the cached hierarchy summaries of a room, as when its `m.space.child`
+/// state changes.
+#[implement(Service)]
+pub fn clear_room_hierarchy(&self, room_id: &RoomId) {
+	self.roomid_spacehierarchy_cache.remove_room(room_id);
+}
+
 /// Gets the summary of a space using solely local information
 #[implement(Service)]
 pub async fn get_summary_and_children_local(
@@ -113,29 +121,12 @@ pub async fn get_summary_and_children_local(
 ) -> Result<Option<SummaryAccessibility>> {
 	match self
 		.roomid_spacehierarchy_cache
-		.lock()
-		.await
-		.get_mut(current_room)
-		.as_ref()
+		.get(current_room, false, Freshness::Fresh)
 	{
 		| None => (), // cache miss
 		| Some(None) => return Ok(None),
-		| Some(Some(cached)) => {
-			let allowed_rooms = cached.summary.allowed_room_ids.iter().map(AsRef::as_ref);
-
-			let is_accessible_child = self.is_accessible_child(
-				current_room,
-				&cached.summary.join_rule,
-				identifier,
-				allowed_rooms,
-			);
-
-			let accessibility = if is_accessible_child.await {
-				SummaryAccessibility::Accessible(cached.summary.clone())
-			} else {
-				SummaryAccessibility::Inaccessible
-			};
-
+		| Some(Some(summary)) => {
+			let accessibility = self.accessibility(current_room, summary, identifier).await;
 			return Ok(Some(accessibility));
 		},
 	}
@@ -154,10 +145,8 @@ pub async fn get_summary_and_children_local(
 		return Ok(None);
 	};
 
-	self.roomid_spacehierarchy_cache.lock().await.insert(
-		current_room.to_owned(),
-		Some(CachedSpaceHierarchySummary { summary: summary.clone() }),
-	);
+	self.roomid_spacehierarchy_cache
+		.insert(current_room, false, Some(summary.clone()));
 
 	Ok(Some(SummaryAccessibility::Accessible(summary)))
 }
@@ -171,7 +160,18 @@ async fn get_summary_and_children_federation(
 	suggested_only: bool,
 	user_id: &UserId,
 	via: &[OwnedServerName],
+	freshness: Freshness,
 ) -> Result<Option<SummaryAccessibility>> {
+	let identifier = Identifier::UserId(user_id);
+	let cache = &self.roomid_spacehierarchy_cache;
+	if let Some(cached) = cache.get(current_room, suggested_only, freshness) {
+		let Some(summary) = cached else {
+			return Ok(None);
+		};
+
+		return Ok(Some(self.accessibility(current_room, summary, &identifier).await));
+	}
+
 	let request = federation::space::get_hierarchy::v1::Request {
 		room_id: current_room.to_owned(),
 		suggested_only,
@@ -186,50 +186,43 @@ async fn get_summary_and_children_federation(
 		})
 		.collect();
 
-	let Some(Ok(response)) = requests.next().await else {
-		self.roomid_spacehierarchy_cache
-			.lock()
-			.await
-			.insert(current_room.to_owned(), None);
-
-		return Ok(None);
+	let response = loop {
+		match requests.next().await {
+			| Some(Ok(response)) => break response,
+			| Some(Err(_)) => continue,
+			| None => {
+				cache.insert(current_room, suggested_only, None);
+				return Ok(None);
+			},
+		}
 	};
 
-	let summary = response.room;
-	self.roomid_spacehierarchy_cache.lock().await.insert(
-		current_room.to_owned(),
-		Some(CachedSpaceHierarchySummary { summary: summary.clone() }),
-	);
-
-	response
-		.children
-		.into_iter()
-		.stream()
-		.then(|child| {
-			self.roomid_spacehierarchy_cache
-				.lock()
-				.map(|lock| (child, lock))
-		})
-		.ready_filter_map(|(child, mut cache)| {
-			(!cache.contains_key(current_room)).then_some((child, cache))
-		})
-		.for_each(|(child, cache)| self.cache_insert(cache, current_room, child))
-		.await;
+	cache.insert_response(current_room, suggested_only, &response);
 
-	let identifier = Identifier::UserId(user_id);
-	let allowed_room_ids = summary.allowed_room_ids.iter().map(AsRef::as_ref);
+	Ok(Some(
+		self.accessibility(current_room, response.room, &identifier)
+			.await,
+	))
+}
 
+/// Whether the summary of the room is accessible with the given identifier.
+#[implement(Service)]
+async fn accessibility(
+	&self,
+	current_room: &RoomId,
+	summary: SpaceHierarchyParentSummary,
+	identifier: &Identifier<'_>,
+) -> SummaryAccessibility {
+	let allowed_rooms = summary.allowed_room_ids.iter().map(AsRef::as_ref);
 	let is_accessible_child = self
-		.is_accessible_child(current_room, &summary.join_rule, &identifier, allowed_room_ids)
+		.is_accessible_child(current_room, &summary.join_rule, identifier, allowed_rooms)
 		.await;
 
-	let accessibility = if is_accessible_child {
+	if is_accessible_child {
 		SummaryAccessibility::Accessible(summary)
 	} else {
 		SummaryAccessibility::Inaccessible
-	};
-
-	Ok(Some(accessibility))
+	}
 }
 
 /// Simply returns the stripped m.space.child events of a room
@@ -283,6 +276,7 @@ pub async fn get_summary_and_children_client(
 	suggested_only: bool,
 	user_id: &UserId,
 	via: &[OwnedServerName],
+	freshness: Freshness,
 ) -> Result<Option<SummaryAccessibility>> {
 	let identifier = Identifier::UserId(user_id);
 
@@ -293,8 +287,14 @@ pub async fn get_summary_and_children_client(
 		return Ok(Some(response));
 	}
 
-	self.get_summary_and_children_federation(current_room, suggested_only, user_id, via)
-		.await
+	self.get_summary_and_children_federation(
+		current_room,
+		suggested_only,
+		user_id,
+		via,
+		freshness,
+	)
+	.await
 }
 
 #[implement(Service)]
@@ -474,93 +474,6 @@ pub fn get_parent_children_via(
 		})
 }
 
-#[implement(Service)]
-async fn cache_insert(
-	&self,
-	mut cache: MutexGuard<'_, Cache>,
-	current_room: &RoomId,
-	child: SpaceHierarchyChildSummary,
-) {
-	let SpaceHierarchyChildSummary {
-		canonical_alias,
-		name,
-		num_joined_members,
-		room_id,
-		topic,
-		world_readable,
-		guest_can_join,
-		avatar_url,
-		join_rule,
-		room_type,
-		allowed_room_ids,
-		encryption,
-		room_version,
-	} = child;
-
-	let summary = SpaceHierarchyParentSummary {
-		canonical_alias,
-		name,
-		num_joined_members,
-		topic,
-		world_readable,
-		guest_can_join,
-		avatar_url,
-		join_rule,
-		room_type,
-		allowed_room_ids,
-		room_id: room_id.clone(),
-		children_state: self
-			.get_space_child_events(&room_id)
-			.map(PduEvent::into_stripped_spacechild_state_event)
-			.collect()
-			.await,
-		encryption,
-		room_version,
-	};
-
-	cache.insert(current_room.to_owned(), Some(CachedSpaceHierarchySummary { summary }));
-}
-
-// Here because cannot implement `From` across ruma-federation-api and
-// ruma-client-api types
-impl From<CachedSpaceHierarchySummary> for SpaceHierarchyRoomsChunk {
-	fn from(value: CachedSpaceHierarchySummary) -> Self {
-		let SpaceHierarchyParentSummary {
-			canonical_alias,
-			name,
-			num_joined_members,
-			room_id,
-			topic,
-			world_readable,
-			guest_can_join,
-			avatar_url,
-			join_rule,
-			room_type,
-			children_state,
-			allowed_room_ids,
-			encryption,
-			room_version,
-		} = value.summary;
-
-		Self {
-			canonical_alias,
-			name,
-			num_joined_members,
-			room_id,
-			topic,
-			world_readable,
-			guest_can_join,
-			avatar_url,
-			join_rule,
-			room_type,
-			children_state,
-			encryption,
-			room_version,
-			allowed_room_ids,
-		}
-	}
-}
-
 /// Here because cannot implement `From` across ruma-federation-api and
 /// ruma-client-api types
 #[must_use]
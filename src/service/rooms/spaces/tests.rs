@@ -1,12 +1,18 @@
-use std::str::FromStr;
+use std::{str::FromStr, time::Duration};
 
 use ruma::{
-	UInt,
-	api::federation::space::{SpaceHierarchyParentSummary, SpaceHierarchyParentSummaryInit},
+	OwnedRoomId, UInt,
+	api::federation::space::{
+		SpaceHierarchyChildSummary, SpaceHierarchyChildSummaryInit, SpaceHierarchyParentSummary,
+		SpaceHierarchyParentSummaryInit, get_hierarchy,
+	},
 	owned_room_id, owned_server_name,
+	room::RoomType,
+	room_id,
 	space::SpaceRoomJoinRule,
 };
 
+use super::cache::{Cache, Freshness};
 use crate::rooms::spaces::{PaginationToken, get_parent_children_via};
 
 #[test]
@@ -145,3 +151,122 @@ fn pagination_token_to_string() {
 		"9,34_3_1_true"
 	);
 }
+
+fn parent(room_id: OwnedRoomId) -> SpaceHierarchyParentSummary {
+	SpaceHierarchyParentSummaryInit {
+		num_joined_members: UInt::from(1_u32),
+		room_id,
+		world_readable: true,
+		guest_can_join: false,
+		join_rule: SpaceRoomJoinRule::Public,
+		children_state: Vec::new(),
+		allowed_room_ids: Vec::new(),
+	}
+	.into()
+}
+
+fn child(room_id: OwnedRoomId, room_type: Option<RoomType>) -> SpaceHierarchyChildSummary {
+	let mut child: SpaceHierarchyChildSummary = SpaceHierarchyChildSummaryInit {
+		num_joined_members: UInt::from(1_u32),
+		room_id,
+		world_readable: true,
+		guest_can_join: false,
+		join_rule: SpaceRoomJoinRule::Public,
+		allowed_room_ids: Vec::new(),
+	}
+	.into();
+
+	child.room_type = room_type;
+	child
+}
+
+#[test]
+fn hierarchy_cache_remote_child() {
+	let cache = Cache::new(16, Duration::from_secs(60));
+	let space = room_id!("!space:remote.example.org");
+	let room = room_id!("!room:remote.example.org");
+	let subspace = room_id!("!subspace:remote.example.org");
+
+	let mut response = get_hierarchy::v1::Response::new(parent(space.to_owned()));
+	response.children =
+		vec![child(room.to_owned(), None), child(subspace.to_owned(), Some(RoomType::Space))];
+
+	assert!(cache.get(space, false, Freshness::Fresh).is_none(), "first call misses");
+	cache.insert_response(space, false, &response);
+
+	let cached = cache
+		.get(space, false, Freshness::Fresh)
+		.flatten()
+		.expect("second call served from cache");
+	assert_eq!(cached.room_id, space);
+
+	let cached = cache
+		.get(room, false, Freshness::Fresh)
+		.flatten()
+		.expect("remote child served from cache");
+	assert_eq!(cached.room_id, room);
+	assert!(cached.children_state.is_empty());
+
+	assert!(
+		cache.get(subspace, false, Freshness::Fresh).is_none(),
+		"child space needs its own children"
+	);
+
+	assert_eq!((cache.hits(), cache.misses()), (2, 2));
+}
+
+#[test]
+fn hierarchy_cache_suggested_only() {
+	let cache = Cache::new(16, Duration::from_secs(60));
+	let all = room_id!("!all:example.org");
+	let suggested = room_id!("!suggested:example.org");
+
+	cache.insert(all, false, Some(parent(all.to_owned())));
+	cache.insert(suggested, true, Some(parent(suggested.to_owned())));
+
+	assert!(
+		cache.get(all, true, Freshness::Fresh).is_some(),
+		"all children include suggested"
+	);
+	assert!(cache.get(suggested, true, Freshness::Fresh).is_some());
+	assert!(cache.get(suggested, false, Freshness::Fresh).is_none());
+}
+
+#[test]
+fn hierarchy_cache_snapshot() {
+	let cache = Cache::new(16, Duration::ZERO);
+	let space = room_id!("!space:example.org");
+	let unreachable = room_id!("!unreachable:example.org");
+
+	cache.insert(space, false, Some(parent(space.to_owned())));
+	cache.insert(unreachable, false, None);
+
+	assert!(cache.get(space, false, Freshness::Fresh).is_none(), "expired");
+	assert!(
+		cache
+			.get(space, false, Freshness::Snapshot)
+			.flatten()
+			.is_some(),
+		"expired summary kept for pagination"
+	);
+	assert!(
+		matches!(cache.get(unreachable, false, Freshness::Snapshot), Some(None)),
+		"failure cached"
+	);
+}
+
+#[test]
+fn hierarchy_cache_remove_room() {
+	let cache = Cache::new(16, Duration::from_secs(60));
+	let space = room_id!("!space:example.org");
+	let other = room_id!("!other:example.org");
+
+	cache.insert(space, false, Some(parent(space.to_owned())));
+	cache.insert(space, true, Some(parent(space.to_owned())));
+	cache.insert(other, false, Some(parent(other.to_owned())));
+
+	assert!(cache.remove_room(space));
+	assert!(!cache.remove_room(space), "nothing left to remove");
+	assert!(cache.get(space, true, Freshness::Fresh).is_none());
+	assert!(cache.get(other, false, Freshness::Fresh).is_some());
+}
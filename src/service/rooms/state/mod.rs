@@ -1,3 +1,6 @@
+#[cfg(test)]
+mod tests;
+
 use std::{collections::HashMap, fmt::Write, iter::once, sync::Arc};
 
 use async_trait::async_trait;
@@ -11,7 +14,7 @@
 	},
 	warn,
 };
-use database::{Deserialized, Ignore, Interfix, Map};
+use database::{Batch, Deserialized, Ignore, Interfix, Map, serialize_key};
 use futures::{
 	FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt, future::join_all, pin_mut,
 };
@@ -27,8 +30,8 @@
 use crate::{
 	Dep, globals, rooms,
 	rooms::{
-		short::{ShortEventId, ShortStateHash},
-		state_compressor::{CompressedState, parse_compressed_state_event},
+		short::{ShortEventId, ShortStateHash, ShortStateKey},
+		state_compressor::{CompressedState, CompressedStateEvent, parse_compressed_state_event},
 	},
 };
 
@@ -143,12 +146,7 @@ pub async fn force_state(
 						.await?;
 				},
 				| TimelineEventType::SpaceChild => {
-					self.services
-						.spaces
-						.roomid_spacehierarchy_cache
-						.lock()
-						.await
-						.remove(&pdu.room_id);
+					self.services.spaces.clear_room_hierarchy(&pdu.room_id);
 				},
 				| _ => continue,
 			}
@@ -318,6 +316,115 @@ pub async fn append_to_state(&self, new_pdu: &PduEvent) -> Result<u64> {
 		}
 	}
 
+	/// Stages on `batch` the state after each of a batch of state events
+	/// appended to the room in turn, each as a diff from the room's current
+	/// state. Returns the shortstatehashes of the state before each event and,
+	/// last, of the state after the batch.
+	#[tracing::instrument(skip(self, batch, pdus), level = "debug")]
+	pub async fn append_batch_to_state<'a>(
+		&'a self,
+		batch: &mut Batch<'a>,
+		room_id: &RoomId,
+		pdus: &[PduEvent],
+	) -> Result<Vec<ShortStateHash>> {
+		let previous = self.get_room_shortstatehash(room_id).await?;
+		let states_parents = self
+			.services
+			.state_compressor
+			.load_shortstatehash_info(previous)
+			.await?;
+
+		let mut events = Vec::with_capacity(pdus.len());
+		for pdu in pdus {
+			let state_key = pdu
+				.state_key
+				.as_deref()
+				.ok_or_else(|| err!("{} in a state batch is not a state event", pdu.event_id))?;
+
+			let shortstatekey = self
+				.services
+				.short
+				.get_or_create_shortstatekey(&pdu.kind.to_string().into(), state_key)
+				.await;
+
+			let event = self
+				.services
+				.state_compressor
+				.compress_state_event(shortstatekey, &pdu.event_id)
+				.await;
+
+			events.push(event);
+		}
+
+		let parent = states_parents
+			.last()
+			.map(|info| info.full_state.clone())
+			.unwrap_or_default();
+
+		let mut shortstatehashes = Vec::with_capacity(events.len().saturating_add(1));
+		shortstatehashes.push(previous);
+		for (statediffnew, statediffremoved) in batch_diffs(&parent, &events) {
+			let shortstatehash = self.services.globals.next_count()?;
+			self.services.state_compressor.stage_state_from_diff(
+				batch,
+				shortstatehash,
+				Arc::new(statediffnew),
+				Arc::new(statediffremoved),
+				2,
+				states_parents.clone(),
+			)?;
+
+			shortstatehashes.push(shortstatehash);
+		}
+
+		Ok(shortstatehashes)
+	}
+
+	/// Stages on `batch` the state before each of a batch of events, the last
+	/// of them as the room's only forward extremity, and the room's state
+	/// after them.
+	pub async fn stage_batch<'a>(
+		&'a self,
+		batch: &mut Batch<'a>,
+		room_id: &RoomId,
+		events: &[(ShortEventId, ShortStateHash)],
+		leaf: &EventId,
+		shortstatehash: ShortStateHash,
+		_state_lock: &RoomMutexGuard,
+	) -> Result {
+		for (shorteventid, before) in events {
+			batch.insert(
+				&self.db.shorteventid_shortstatehash,
+				&shorteventid.to_be_bytes(),
+				before.to_be_bytes(),
+			);
+		}
+
+		let prefix = (room_id, Interfix);
+		let leaves: Vec<Vec<u8>> = self
+			.db
+			.roomid_pduleaves
+			.keys_prefix_raw(&prefix)
+			.ignore_err()
+			.map(<[u8]>::to_vec)
+			.collect()
+			.await;
+
+		for key in &leaves {
+			batch.remove(&self.db.roomid_pduleaves, key);
+		}
+
+		let key = serialize_key((room_id, leaf))?;
+		batch.insert(&self.db.roomid_pduleaves, &key, leaf.as_bytes());
+		batch.insert(
+			&self.db.roomid_shortstatehash,
+			room_id.as_bytes(),
+			shortstatehash.to_be_bytes(),
+		);
+
+		Ok(())
+	}
+
 	#[tracing::instrument(skip_all, level = "debug")]
 	pub async fn summary_stripped(&self, event: &PduEvent) -> Vec<Raw<AnyStrippedStateEvent>> {
 		let cells = [
@@ -381,6 +488,29 @@ pub async fn get_room_shortstatehash(&self, room_id: &RoomId) -> Result<ShortSta
 			.deserialized()
 	}
 
+	/// Returns the `limit` rooms whose state changed most recently with their
+	/// current shortstatehash, newest first. Shortstatehashes are allocated
+	/// from the global counter, so the highest are the most recent.
+	pub async fn recently_changed_rooms(
+		&self,
+		limit: usize,
+	) -> Vec<(ShortStateHash, OwnedRoomId)> {
+		let mut rooms: Vec<_> = self
+			.db
+			.roomid_shortstatehash
+			.stream()
+			.ignore_err()
+			.map(|(room_id, shortstatehash): (&RoomId, ShortStateHash)| {
+				(shortstatehash, room_id.to_owned())
+			})
+			.collect()
+			.await;
+
+		rooms.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+		rooms.truncate(limit);
+		rooms
+	}
+
 	pub fn get_forward_extremities<'a>(
 		&'a self,
 		room_id: &'a RoomId,
@@ -476,3 +606,32 @@ pub async fn get_auth_events(
 			.await
 	}
 }
+
+/// The diffs from the `parent` state of the state after each of `events` in
+/// turn. An event replaces the state of the same key before it, whether in
+/// the parent or earlier in the batch.
+pub(super) fn batch_diffs(
+	parent: &CompressedState,
+	events: &[CompressedStateEvent],
+) -> Vec<(CompressedState, CompressedState)> {
+	let mut added = CompressedState::new();
+	let mut removed = CompressedState::new();
+	let mut diffs = Vec::with_capacity(events.len());
+	for event in events {
+		let key = event.get(..size_of::<ShortStateKey>()).unwrap_or_default();
+		let same_key =
+			|state: &CompressedState| state.iter().find(|other| other.starts_with(key)).copied();
+
+		if let Some(replaced) = same_key(&added) {
+			added.remove(&replaced);
+		} else if let Some(replaced) = same_key(parent) {
+			removed.insert(replaced);
+		}
+
+		added.insert(*event);
+
+		diffs.push((added.clone(), removed.clone()));
+	}
+
+	diffs
+}
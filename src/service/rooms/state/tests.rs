@@ -0,0 +1,54 @@
+use super::batch_diffs;
+use crate::rooms::state_compressor::{CompressedState, compress_state_event};
+
+#[test]
+fn batch_diffs_new_keys() {
+	let parent: CompressedState = [compress_state_event(1, 10)].into();
+	let events = [compress_state_event(2, 20), compress_state_event(3, 30)];
+
+	let diffs = batch_diffs(&parent, &events);
+	assert_eq!(diffs.len(), 2);
+
+	let (added, removed) = &diffs[0];
+	assert_eq!(*added, [compress_state_event(2, 20)].into());
+	assert!(removed.is_empty());
+
+	let (added, removed) = &diffs[1];
+	assert_eq!(*added, [compress_state_event(2, 20), compress_state_event(3, 30)].into());
+	assert!(removed.is_empty());
+}
+
+#[test]
+fn batch_diffs_replace_parent() {
+	let parent: CompressedState =
+		[compress_state_event(1, 10), compress_state_event(2, 11)].into();
+	let events = [compress_state_event(1, 20)];
+
+	let diffs = batch_diffs(&parent, &events);
+	let (added, removed) = &diffs[0];
+	assert_eq!(*added, [compress_state_event(1, 20)].into());
+	assert_eq!(*removed, [compress_state_event(1, 10)].into());
+}
+
+#[test]
+fn batch_diffs_replace_earlier_in_batch() {
+	let parent: CompressedState = [compress_state_event(1, 10)].into();
+	let events = [
+		compress_state_event(1, 20),
+		compress_state_event(2, 21),
+		compress_state_event(1, 22),
+	];
+
+	let diffs = batch_diffs(&parent, &events);
+	assert_eq!(diffs.len(), 3);
+
+	// the state between keeps the first replacement
+	let (added, removed) = &diffs[1];
+	assert_eq!(*added, [compress_state_event(1, 20), compress_state_event(2, 21)].into());
+	assert_eq!(*removed, [compress_state_event(1, 10)].into());
+
+	// the parent's event is removed once, and only the latest of the batch kept
+	let (added, removed) = &diffs[2];
+	assert_eq!(*added, [compress_state_event(1, 22), compress_state_event(2, 21)].into());
+	assert_eq!(*removed, [compress_state_event(1, 10)].into());
+}
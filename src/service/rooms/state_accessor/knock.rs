@@ -0,0 +1,84 @@
+use conduwuit::{Err, Error, Result, implement, matrix::state_res::RoomVersion};
+use ruma::{
+	CanonicalJsonObject, CanonicalJsonValue, RoomId, RoomVersionId, UserId,
+	api::client::error::ErrorKind,
+	canonical_json::to_canonical_value,
+	events::room::{
+		join_rules::JoinRule,
+		member::{MembershipState, RoomMemberEventContent},
+	},
+};
+
+/// Checks that the room can be knocked on, returning its version.
+#[implement(super::Service)]
+pub async fn check_knockable(&self, room_id: &RoomId) -> Result<RoomVersionId> {
+	let room_version = self.services.state.get_room_version(room_id).await?;
+	let join_rule = self.get_join_rules(room_id).await;
+	knockable(&room_version, &join_rule)?;
+
+	Ok(room_version)
+}
+
+/// Knocking arrived in room version 7 with the `knock` join rule, and
+/// `knock_restricted` in version 10; rooms of other versions or join rules
+/// cannot be knocked on.
+pub fn knockable(room_version: &RoomVersionId, join_rule: &JoinRule) -> Result {
+	let rules = supports_knocking(room_version)?;
+	match join_rule {
+		| JoinRule::Knock => Ok(()),
+		| JoinRule::KnockRestricted(_) if rules.knock_restricted_join_rule => Ok(()),
+		| JoinRule::KnockRestricted(_) => Err(incompatible(room_version)),
+		| _ => Err!(Request(Forbidden("This room does not allow knocking."))),
+	}
+}
+
+/// Fails with M_INCOMPATIBLE_ROOM_VERSION unless rooms of the version can be
+/// knocked on at all.
+pub fn supports_knocking(room_version: &RoomVersionId) -> Result<RoomVersion> {
+	match RoomVersion::new(room_version) {
+		| Ok(rules) if rules.allow_knocking => Ok(rules),
+		| _ => Err(incompatible(room_version)),
+	}
+}
+
+fn incompatible(room_version: &RoomVersionId) -> Error {
+	Error::BadRequest(
+		ErrorKind::IncompatibleRoomVersion { room_version: room_version.clone() },
+		"Room version does not support knocking.",
+	)
+}
+
+/// Fills in the knock event template a resident server gave us for the user
+/// with the member content, after checking that the template is for the user
+/// knocking on the room.
+pub fn knock_event(
+	mut template: CanonicalJsonObject,
+	room_id: &RoomId,
+	user_id: &UserId,
+	content: &RoomMemberEventContent,
+) -> Result<CanonicalJsonObject> {
+	let field = |name: &str| match template.get(name) {
+		| Some(CanonicalJsonValue::String(value)) => Some(value.as_str()),
+		| _ => None,
+	};
+
+	if field("type") != Some("m.room.member") {
+		return Err!(BadServerResponse("make_knock template is not a membership event."));
+	}
+
+	if field("room_id") != Some(room_id.as_str()) {
+		return Err!(BadServerResponse("make_knock template is for another room."));
+	}
+
+	if field("sender") != Some(user_id.as_str()) || field("state_key") != Some(user_id.as_str()) {
+		return Err!(BadServerResponse("make_knock template is for another user."));
+	}
+
+	if content.membership != MembershipState::Knock {
+		return Err!(Request(InvalidParam("Knock event must have knock membership.")));
+	}
+
+	template.insert("content".to_owned(), to_canonical_value(content)?);
+
+	Ok(template)
+}
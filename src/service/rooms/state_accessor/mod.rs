@@ -1,12 +1,17 @@
+mod knock;
+mod restricted;
 mod room_state;
 mod server_can;
 mod state;
+#[cfg(test)]
+mod tests;
+mod tombstone;
 mod user_can;
 
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use conduwuit::{Result, err};
+use conduwuit::{Result, Server, err};
 use database::Map;
 use ruma::{
 	EventEncryptionAlgorithm, JsOption, OwnedRoomAliasId, RoomId, UserId,
@@ -28,6 +33,10 @@
 	room::RoomType,
 };
 
+pub use self::{
+	knock::{knock_event, knockable, supports_knocking},
+	restricted::{RestrictedJoin, evaluate_restricted_join},
+};
 use crate::{Dep, rooms};
 
 pub struct Service {
@@ -36,6 +45,7 @@ pub struct Service {
 }
 
 struct Services {
+	server: Arc<Server>,
 	short: Dep<rooms::short::Service>,
 	state: Dep<rooms::state::Service>,
 	state_compressor: Dep<rooms::state_compressor::Service>,
@@ -52,6 +62,7 @@ impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			services: Services {
+				server: args.server.clone(),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
@@ -0,0 +1,113 @@
+use std::collections::BTreeSet;
+
+use conduwuit::{implement, matrix::state_res::RoomVersion};
+use futures::{StreamExt, stream};
+use ruma::{
+	OwnedRoomId, OwnedUserId, RoomId, RoomVersionId, UserId,
+	events::room::join_rules::{AllowRule, JoinRule},
+};
+
+use crate::rooms::state::RoomMutexGuard;
+
+/// Whether the `allow` conditions of a room's join rule let a user join it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RestrictedJoin {
+	/// The room does not restrict joins by membership of other rooms.
+	Unrestricted,
+
+	/// The user is in this room, which the join rule allows members of.
+	Allowed(OwnedRoomId),
+
+	/// The user is in none of the rooms the join rule allows members of, as
+	/// far as we know.
+	Denied,
+}
+
+/// Evaluates the `allow` conditions of the room's join rule for the user, from
+/// the rooms we know the user to be joined to.
+#[implement(super::Service)]
+pub async fn restricted_join(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	room_version: &RoomVersionId,
+) -> RestrictedJoin {
+	let join_rule = self.get_join_rules(room_id).await;
+	let joined: BTreeSet<&RoomId> = stream::iter(allow_rooms(&join_rule))
+		.filter_map(|allow_room| async move {
+			self.services
+				.state_cache
+				.is_joined(user_id, allow_room)
+				.await
+				.then_some(allow_room)
+		})
+		.collect()
+		.await;
+
+	evaluate_restricted_join(room_version, &join_rule, |allow_room| joined.contains(allow_room))
+}
+
+/// Picks a local user who may authorise the user's join of the room through
+/// `join_authorised_via_users_server`: one joined to it who could invite them.
+#[implement(super::Service)]
+pub async fn restricted_join_authoriser(
+	&self,
+	room_id: &RoomId,
+	user_id: &UserId,
+	state_lock: &RoomMutexGuard,
+) -> Option<OwnedUserId> {
+	self.services
+		.state_cache
+		.local_users_in_room(room_id)
+		.filter(|authoriser| self.user_can_invite(room_id, authoriser, user_id, state_lock))
+		.boxed()
+		.next()
+		.await
+		.map(ToOwned::to_owned)
+}
+
+/// Decides a restricted join given whether the user is joined to each room
+/// the join rule allows members of. Rooms of versions before 8 do not support
+/// restricted joins, nor do those before 10 `knock_restricted`.
+pub fn evaluate_restricted_join<F>(
+	room_version: &RoomVersionId,
+	join_rule: &JoinRule,
+	is_joined: F,
+) -> RestrictedJoin
+where
+	F: Fn(&RoomId) -> bool,
+{
+	let Ok(rules) = RoomVersion::new(room_version) else {
+		return RestrictedJoin::Unrestricted;
+	};
+
+	let supported = match join_rule {
+		| JoinRule::Restricted(_) => rules.restricted_join_rules,
+		| JoinRule::KnockRestricted(_) => rules.knock_restricted_join_rule,
+		| _ => false,
+	};
+
+	if !supported {
+		return RestrictedJoin::Unrestricted;
+	}
+
+	allow_rooms(join_rule)
+		.find(|allow_room| is_joined(allow_room))
+		.map_or(RestrictedJoin::Denied, |allow_room| {
+			RestrictedJoin::Allowed(allow_room.to_owned())
+		})
+}
+
+/// The rooms whose members the join rule allows to join, if it is restricted.
+fn allow_rooms(join_rule: &JoinRule) -> impl Iterator<Item = &RoomId> + Send {
+	let allow = match join_rule {
+		| JoinRule::Restricted(restricted) | JoinRule::KnockRestricted(restricted) =>
+			restricted.allow.as_slice(),
+		| _ => &[],
+	};
+
+	allow.iter().filter_map(|rule| match rule {
+		| AllowRule::RoomMembership(membership) => Some(membership.room_id.as_ref()),
+		| _ => None,
+	})
+}
@@ -1,7 +1,7 @@
 use std::borrow::Borrow;
 
 use conduwuit::{
-	Result, err, implement,
+	Error, Result, implement,
 	matrix::{PduEvent, StateKey},
 };
 use futures::{Stream, StreamExt, TryFutureExt};
@@ -35,7 +35,7 @@ pub fn room_state_full<'a>(
 		.state
 		.get_room_shortstatehash(room_id)
 		.map_ok(|shortstatehash| self.state_full(shortstatehash).map(Ok).boxed())
-		.map_err(move |e| err!(Database("Missing state for {room_id:?}: {e:?}")))
+		.map_err(move |e| missing_state(room_id, e))
 		.try_flatten_stream()
 }
 
@@ -50,7 +50,7 @@ pub fn room_state_full_pdus<'a>(
 		.state
 		.get_room_shortstatehash(room_id)
 		.map_ok(|shortstatehash| self.state_full_pdus(shortstatehash).map(Ok).boxed())
-		.map_err(move |e| err!(Database("Missing state for {room_id:?}: {e:?}")))
+		.map_err(move |e| missing_state(room_id, e))
 		.try_flatten_stream()
 }
 
@@ -91,3 +91,12 @@ pub async fn room_state_get(
 		.and_then(|shortstatehash| self.state_get(shortstatehash, event_type, state_key))
 		.await
 }
+
+/// The error for a room whose current state cannot be found.
+fn missing_state(room_id: &RoomId, e: Error) -> Error {
+	if !e.is_not_found() {
+		return e;
+	}
+
+	Error::not_found(format!("State of {room_id}"))
+}
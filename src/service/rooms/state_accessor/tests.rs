@@ -0,0 +1,192 @@
+use http::StatusCode;
+use ruma::{
+	CanonicalJsonObject, CanonicalJsonValue, RoomVersionId,
+	api::{
+		OutgoingResponse,
+		client::{error::ErrorKind, uiaa::UiaaResponse},
+	},
+	events::room::{
+		join_rules::{AllowRule, JoinRule, Restricted},
+		member::{MembershipState, RoomMemberEventContent},
+	},
+	room_id, user_id,
+};
+use serde_json::{Value, json};
+
+use super::{
+	knock::{knock_event, knockable},
+	restricted::{RestrictedJoin, evaluate_restricted_join},
+	tombstone::{enforced, tombstoned},
+};
+
+#[test]
+fn tombstoned_error_names_replacement() {
+	let replacement = room_id!("!new:example.com");
+	let response: UiaaResponse = tombstoned(replacement).into();
+	let response = response
+		.try_into_http_response::<Vec<u8>>()
+		.expect("serializable response");
+
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+	let body: Value = serde_json::from_slice(response.body()).expect("json body");
+	assert_eq!(body["errcode"], "M_FORBIDDEN");
+	assert_eq!(body["replacement_room"], replacement.as_str());
+	assert!(
+		body["error"]
+			.as_str()
+			.is_some_and(|error| error.contains(replacement.as_str()))
+	);
+}
+
+#[test]
+fn tombstone_enforcement_appservice_exemption() {
+	assert!(!enforced(false, false, false), "disabled by default");
+	assert!(!enforced(false, true, true));
+
+	assert!(enforced(true, false, false));
+	assert!(enforced(true, false, true), "appservices not exempt unless configured");
+	assert!(enforced(true, true, false), "exemption does not extend to users");
+	assert!(!enforced(true, true, true));
+}
+
+#[test]
+fn knock_room_versions() {
+	let incompatible = |room_version: RoomVersionId, join_rule: JoinRule| {
+		knockable(&room_version, &join_rule)
+			.is_err_and(|e| matches!(e.kind(), ErrorKind::IncompatibleRoomVersion { .. }))
+	};
+
+	for room_version in [RoomVersionId::V1, RoomVersionId::V5, RoomVersionId::V6] {
+		assert!(incompatible(room_version, JoinRule::Knock));
+	}
+
+	assert!(knockable(&RoomVersionId::V7, &JoinRule::Knock).is_ok());
+	assert!(knockable(&RoomVersionId::V11, &JoinRule::Knock).is_ok());
+
+	let restricted = || JoinRule::KnockRestricted(Restricted::new(Vec::new()));
+	assert!(incompatible(RoomVersionId::V9, restricted()), "knock_restricted needs v10");
+	assert!(knockable(&RoomVersionId::V10, &restricted()).is_ok());
+}
+
+#[test]
+fn knock_join_rules() {
+	for join_rule in [JoinRule::Public, JoinRule::Invite, JoinRule::Private] {
+		let error = knockable(&RoomVersionId::V10, &join_rule).expect_err("not knockable");
+		assert!(matches!(error.kind(), ErrorKind::Forbidden { .. }), "{error}");
+	}
+}
+
+fn template(value: &Value) -> CanonicalJsonObject {
+	serde_json::from_value(value.clone()).expect("valid template")
+}
+
+#[test]
+fn knock_event_from_template() {
+	let room_id = room_id!("!room:remote.example");
+	let user_id = user_id!("@alice:example.com");
+	let content = RoomMemberEventContent {
+		displayname: Some("Alice".to_owned()),
+		reason: Some("let me in".to_owned()),
+		..RoomMemberEventContent::new(MembershipState::Knock)
+	};
+
+	let template = template(&json!({
+		"type": "m.room.member",
+		"room_id": room_id,
+		"sender": user_id,
+		"state_key": user_id,
+		"content": { "membership": "join" },
+		"depth": 10,
+	}));
+
+	let event = knock_event(template, room_id, user_id, &content).expect("valid template");
+	let Some(CanonicalJsonValue::Object(content)) = event.get("content") else {
+		panic!("content set");
+	};
+
+	let content = serde_json::to_value(content).expect("serializable");
+	assert_eq!(content["membership"], "knock", "template membership replaced");
+	assert_eq!(content["displayname"], "Alice");
+	assert_eq!(content["reason"], "let me in");
+	assert!(event.contains_key("depth"), "rest of the template kept");
+}
+
+#[test]
+fn knock_event_bad_template() {
+	let room_id = room_id!("!room:remote.example");
+	let user_id = user_id!("@alice:example.com");
+	let content = RoomMemberEventContent::new(MembershipState::Knock);
+	let base = json!({
+		"type": "m.room.member",
+		"room_id": room_id,
+		"sender": user_id,
+		"state_key": user_id,
+	});
+
+	let with = |field: &str, value: &str| {
+		let mut bad = base.clone();
+		bad[field] = value.into();
+		template(&bad)
+	};
+
+	for bad in [
+		with("type", "m.room.message"),
+		with("room_id", "!other:remote.example"),
+		with("sender", "@mallory:remote.example"),
+		with("state_key", "@mallory:remote.example"),
+	] {
+		assert!(knock_event(bad, room_id, user_id, &content).is_err());
+	}
+
+	let join = RoomMemberEventContent::new(MembershipState::Join);
+	assert!(knock_event(template(&base), room_id, user_id, &join).is_err());
+}
+
+#[test]
+fn restricted_join_membership() {
+	let allowed = room_id!("!space:example.com");
+	let other = room_id!("!other:example.com");
+	let join_rule = JoinRule::Restricted(Restricted::new(vec![
+		AllowRule::room_membership(other.to_owned()),
+		AllowRule::room_membership(allowed.to_owned()),
+	]));
+
+	assert_eq!(
+		evaluate_restricted_join(&RoomVersionId::V9, &join_rule, |room_id| room_id == allowed),
+		RestrictedJoin::Allowed(allowed.to_owned())
+	);
+	assert_eq!(
+		evaluate_restricted_join(&RoomVersionId::V9, &join_rule, |_| false),
+		RestrictedJoin::Denied
+	);
+
+	let empty = JoinRule::Restricted(Restricted::new(Vec::new()));
+	assert_eq!(
+		evaluate_restricted_join(&RoomVersionId::V9, &empty, |_| true),
+		RestrictedJoin::Denied,
+		"no room to be a member of"
+	);
+
+	assert_eq!(
+		evaluate_restricted_join(&RoomVersionId::V9, &JoinRule::Public, |_| false),
+		RestrictedJoin::Unrestricted
+	);
+}
+
+#[test]
+fn restricted_join_room_versions() {
+	let allowed = room_id!("!space:example.com");
+	let allow = || Restricted::new(vec![AllowRule::room_membership(allowed.to_owned())]);
+	let evaluate = |room_version: RoomVersionId, join_rule: &JoinRule| {
+		evaluate_restricted_join(&room_version, join_rule, |_| false)
+	};
+
+	let restricted = JoinRule::Restricted(allow());
+	assert_eq!(evaluate(RoomVersionId::V7, &restricted), RestrictedJoin::Unrestricted);
+	assert_eq!(evaluate(RoomVersionId::V8, &restricted), RestrictedJoin::Denied);
+
+	let knock_restricted = JoinRule::KnockRestricted(allow());
+	assert_eq!(evaluate(RoomVersionId::V9, &knock_restricted), RestrictedJoin::Unrestricted);
+	assert_eq!(evaluate(RoomVersionId::V10, &knock_restricted), RestrictedJoin::Denied);
+}
@@ -0,0 +1,54 @@
+use conduwuit::{Error, Result, implement};
+use http::StatusCode;
+use ruma::{
+	OwnedRoomId, RoomId,
+	api::client::error::{Error as RumaError, ErrorBody},
+	events::{StateEventType, room::tombstone::RoomTombstoneEventContent},
+};
+use serde_json::json;
+
+/// Gets the room which replaced this one, when it has been tombstoned.
+#[implement(super::Service)]
+pub async fn get_tombstone_replacement(&self, room_id: &RoomId) -> Result<OwnedRoomId> {
+	self.room_state_get_content(room_id, &StateEventType::RoomTombstone, "")
+		.await
+		.map(|content: RoomTombstoneEventContent| content.replacement_room)
+}
+
+/// Refuses a message event from a local user into a tombstoned room when
+/// "reject_events_in_tombstoned_rooms" is enabled.
+#[implement(super::Service)]
+pub async fn check_not_tombstoned(&self, room_id: &RoomId, appservice: bool) -> Result {
+	let config = &self.services.server.config;
+	if !enforced(
+		config.reject_events_in_tombstoned_rooms,
+		config.tombstone_exempt_appservices,
+		appservice,
+	) {
+		return Ok(());
+	}
+
+	match self.get_tombstone_replacement(room_id).await {
+		| Ok(replacement) => Err(tombstoned(&replacement)),
+		| Err(_) => Ok(()),
+	}
+}
+
+pub(super) fn enforced(reject: bool, exempt_appservices: bool, appservice: bool) -> bool {
+	reject && !(appservice && exempt_appservices)
+}
+
+/// M_FORBIDDEN naming the replacement room in `replacement_room`, so clients
+/// can offer to follow the upgrade.
+pub(super) fn tombstoned(replacement: &RoomId) -> Error {
+	let body = json!({
+		"errcode": "M_FORBIDDEN",
+		"error": format!("This room has been replaced by {replacement}."),
+		"replacement_room": replacement,
+	});
+
+	Error::Ruma(RumaError {
+		status_code: StatusCode::FORBIDDEN,
+		body: ErrorBody::Json(body),
+	})
+}
@@ -1,16 +1,21 @@
-use std::{
-	collections::{HashMap, HashSet},
-	sync::{Arc, RwLock},
-};
+#[cfg(test)]
+mod tests;
+
+use std::{collections::HashSet, sync::Arc};
 
 use conduwuit::{
 	Result, is_not_empty,
 	result::LogErr,
-	utils::{ReadyExt, StreamTools, stream::TryIgnore},
+	utils::{IterStream, ReadyExt, StreamTools, stream::TryIgnore},
 	warn,
 };
 use database::{Deserialized, Ignore, Interfix, Json, Map, serialize_key};
-use futures::{Stream, StreamExt, future::join5, pin_mut, stream::iter};
+use futures::{
+	Stream, StreamExt,
+	future::{join, join5},
+	pin_mut,
+	stream::iter,
+};
 use itertools::Itertools;
 use ruma::{
 	OwnedRoomId, OwnedServerName, RoomId, ServerName, UserId,
@@ -28,10 +33,14 @@
 	serde::Raw,
 };
 
-use crate::{Dep, account_data, appservice::RegistrationInfo, config, globals, rooms, users};
+use crate::{
+	Dep, account_data,
+	appservice::{InterestCache, RegistrationInfo},
+	config, globals, rooms, users,
+};
 
 pub struct Service {
-	appservice_in_room_cache: AppServiceInRoomCache,
+	appservice_in_room_cache: InterestCache,
 	services: Services,
 	db: Data,
 }
@@ -60,16 +69,16 @@ struct Data {
 	userroomid_joined: Arc<Map>,
 	userroomid_leftstate: Arc<Map>,
 	userroomid_knockedstate: Arc<Map>,
+	userroomid_profileoverride: Arc<Map>,
 }
 
-type AppServiceInRoomCache = RwLock<HashMap<OwnedRoomId, HashMap<String, bool>>>;
 type StrippedStateEventItem = (OwnedRoomId, Vec<Raw<AnyStrippedStateEvent>>);
 type SyncStateEventItem = (OwnedRoomId, Vec<Raw<AnySyncStateEvent>>);
 
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
-			appservice_in_room_cache: RwLock::new(HashMap::new()),
+			appservice_in_room_cache: InterestCache::default(),
 			services: Services {
 				account_data: args.depend::<account_data::Service>("account_data"),
 				config: args.depend::<config::Service>("config"),
@@ -94,6 +103,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				userroomid_joined: args.db["userroomid_joined"].clone(),
 				userroomid_leftstate: args.db["userroomid_leftstate"].clone(),
 				userroomid_knockedstate: args.db["userroomid_knockedstate"].clone(),
+				userroomid_profileoverride: args.db["userroomid_profileoverride"].clone(),
 			},
 		}))
 	}
@@ -296,14 +306,7 @@ pub async fn appservice_in_room(
 		room_id: &RoomId,
 		appservice: &RegistrationInfo,
 	) -> bool {
-		if let Some(cached) = self
-			.appservice_in_room_cache
-			.read()
-			.expect("locked")
-			.get(room_id)
-			.and_then(|map| map.get(&appservice.registration.id))
-			.copied()
-		{
+		if let Some(cached) = self.appservice_in_room_cache.get(room_id, appservice) {
 			return cached;
 		}
 
@@ -323,11 +326,7 @@ pub async fn appservice_in_room(
 				.await;
 
 		self.appservice_in_room_cache
-			.write()
-			.expect("locked")
-			.entry(room_id.into())
-			.or_default()
-			.insert(appservice.registration.id.clone(), in_room);
+			.insert(room_id, appservice, in_room);
 
 		in_room
 	}
@@ -389,6 +388,9 @@ pub fn mark_as_left(&self, user_id: &UserId, room_id: &RoomId) {
 		self.db.roomuserid_knockedcount.remove(&roomuser_id);
 
 		self.db.roomid_inviteviaservers.remove(room_id);
+
+		// a profile set for the room does not outlive the membership
+		self.db.userroomid_profileoverride.remove(&userroom_id);
 	}
 
 	/// Direct DB function to directly mark a user as knocked. It is not
@@ -506,6 +508,59 @@ pub fn get_shared_rooms<'a>(
 		set::intersection_sorted_stream2(a, b)
 	}
 
+	/// A page of at most `limit` of the rooms common between two users, in
+	/// order of room ID after `since`, and the room ID the next page follows
+	/// when there is one. Only the smaller of the two users' joined rooms is
+	/// read through, each room checked against the other user's membership.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn mutual_rooms_page(
+		&self,
+		user_a: &UserId,
+		user_b: &UserId,
+		since: Option<&RoomId>,
+		limit: usize,
+	) -> (Vec<OwnedRoomId>, Option<OwnedRoomId>) {
+		let (rooms, other) = self.fewer_rooms_joined(user_a, user_b).await;
+
+		let mutual: Vec<OwnedRoomId> = rooms
+			.into_iter()
+			.filter(|room_id| since.is_none_or(|since| room_id.as_str() > since.as_str()))
+			.stream()
+			.filter_map(|room_id| async move {
+				self.is_joined(other, &room_id).await.then_some(room_id)
+			})
+			.take(limit.max(1).saturating_add(1))
+			.collect()
+			.await;
+
+		page(mutual, limit)
+	}
+
+	/// The rooms of whichever of the users is joined to fewer, and the other
+	/// user. Both users' rooms are read in step, stopping once the first runs
+	/// out.
+	async fn fewer_rooms_joined<'a>(
+		&self,
+		user_a: &'a UserId,
+		user_b: &'a UserId,
+	) -> (Vec<OwnedRoomId>, &'a UserId) {
+		let a = self.rooms_joined(user_a);
+		let b = self.rooms_joined(user_b);
+		pin_mut!(a, b);
+
+		let (mut rooms_a, mut rooms_b) = (Vec::new(), Vec::new());
+		loop {
+			match join(a.next(), b.next()).await {
+				| (None, _) => return (rooms_a, user_b),
+				| (Some(_), None) => return (rooms_b, user_a),
+				| (Some(room_a), Some(room_b)) => {
+					rooms_a.push(room_a.to_owned());
+					rooms_b.push(room_b.to_owned());
+				},
+			}
+		}
+	}
+
 	/// Returns an iterator of all joined members of a room.
 	#[tracing::instrument(skip(self), level = "debug")]
 	pub fn room_members<'a>(
@@ -856,18 +911,11 @@ pub async fn servers_route_via(&self, room_id: &RoomId) -> Result<Vec<OwnedServe
 	}
 
 	pub fn get_appservice_in_room_cache_usage(&self) -> (usize, usize) {
-		let cache = self.appservice_in_room_cache.read().expect("locked");
-
-		(cache.len(), cache.capacity())
+		self.appservice_in_room_cache.usage()
 	}
 
 	#[tracing::instrument(level = "debug", skip_all)]
-	pub fn clear_appservice_in_room_cache(&self) {
-		self.appservice_in_room_cache
-			.write()
-			.expect("locked")
-			.clear();
-	}
+	pub fn clear_appservice_in_room_cache(&self) { self.appservice_in_room_cache.clear(); }
 
 	#[tracing::instrument(level = "debug", skip(self))]
 	pub async fn update_joined_count(&self, room_id: &RoomId) {
@@ -929,10 +977,7 @@ pub async fn update_joined_count(&self, room_id: &RoomId) {
 			self.db.serverroomids.put_raw(serverroom_id, []);
 		}
 
-		self.appservice_in_room_cache
-			.write()
-			.expect("locked")
-			.remove(room_id);
+		self.appservice_in_room_cache.invalidate(room_id);
 	}
 
 	#[tracing::instrument(level = "debug", skip(self))]
@@ -999,3 +1044,18 @@ pub async fn add_servers_invite_via(&self, room_id: &RoomId, servers: Vec<OwnedS
 			.insert(room_id.as_bytes(), &servers);
 	}
 }
+
+/// Takes up to `limit`, and at least one, of the rooms, with the last of them
+/// as the token of the next page if any are left over.
+pub(super) fn page(
+	mut rooms: Vec<OwnedRoomId>,
+	limit: usize,
+) -> (Vec<OwnedRoomId>, Option<OwnedRoomId>) {
+	let limit = limit.max(1);
+	let more = rooms.len() > limit;
+	rooms.truncate(limit);
+
+	let next = rooms.last().filter(|_| more).cloned();
+
+	(rooms, next)
+}
@@ -0,0 +1,53 @@
+use ruma::{OwnedRoomId, RoomId};
+
+use super::page;
+
+fn rooms(count: usize) -> Vec<OwnedRoomId> {
+	(0..count)
+		.map(|i| RoomId::parse(format!("!room{i:04}:example.com")).expect("valid room id"))
+		.collect()
+}
+
+#[test]
+fn mutual_rooms_page_boundary() {
+	let (joined, next) = page(rooms(10), 10);
+	assert_eq!(joined.len(), 10);
+	assert_eq!(next, None, "a full last page has no next");
+
+	let (joined, next) = page(rooms(11), 10);
+	assert_eq!(joined, rooms(10));
+	assert_eq!(next.as_ref(), joined.last(), "next page follows the last room sent");
+
+	let (joined, next) = page(rooms(9), 10);
+	assert_eq!(joined.len(), 9);
+	assert_eq!(next, None);
+}
+
+#[test]
+fn mutual_rooms_pages_resume() {
+	const LIMIT: usize = 100;
+
+	let mutual = rooms(250);
+	let mut since: Option<OwnedRoomId> = None;
+	let mut received = Vec::new();
+	let mut pages: usize = 0;
+	loop {
+		let after: Vec<_> = mutual
+			.iter()
+			.filter(|room_id| since.as_ref().is_none_or(|since| *room_id > since))
+			.take(LIMIT.saturating_add(1))
+			.cloned()
+			.collect();
+
+		let (joined, next) = page(after, LIMIT);
+		received.extend(joined);
+		pages = pages.saturating_add(1);
+		match next {
+			| Some(next) => since = Some(next),
+			| None => break,
+		}
+	}
+
+	assert_eq!(pages, 3);
+	assert_eq!(received, mutual, "each room sent once, in order");
+}
@@ -1,29 +1,38 @@
+mod snapshot;
+#[cfg(test)]
+mod tests;
+
 use std::{
 	collections::{BTreeSet, HashMap},
 	fmt::{Debug, Write},
 	mem::size_of,
 	sync::{Arc, Mutex},
+	time::Instant,
 };
 
 use async_trait::async_trait;
 use conduwuit::{
-	Result,
+	Error, Result,
 	arrayvec::ArrayVec,
-	at, checked, err, expected, utils,
+	at, checked, debug_warn, err, error, utils,
 	utils::{bytes, math::usize_from_f64, stream::IterStream},
 };
-use database::Map;
+use database::{Batch, Map};
 use futures::{Stream, StreamExt};
 use lru_cache::LruCache;
 use ruma::{EventId, RoomId};
 
+pub use self::snapshot::{SnapshotStats, Snapshots};
 use crate::{
-	Dep, rooms,
+	Dep,
+	cache::{Counted, Resizable},
+	rooms,
 	rooms::short::{ShortEventId, ShortId, ShortStateHash, ShortStateKey},
 };
 
 pub struct Service {
-	pub stateinfo_cache: Mutex<StateInfoLruCache>,
+	pub stateinfo_cache: Counted<Mutex<StateInfoLruCache>>,
+	pub snapshots: Snapshots,
 	db: Data,
 	services: Services,
 }
@@ -73,7 +82,8 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		let cache_capacity =
 			f64::from(config.stateinfo_cache_capacity) * config.cache_capacity_modifier;
 		Ok(Arc::new(Self {
-			stateinfo_cache: LruCache::new(usize_from_f64(cache_capacity)?).into(),
+			stateinfo_cache: Mutex::new(LruCache::new(usize_from_f64(cache_capacity)?)).into(),
+			snapshots: Snapshots::new(config),
 			db: Data {
 				shortstatehash_statediff: args.db["shortstatehash_statediff"].clone(),
 			},
@@ -105,13 +115,25 @@ async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
 		let bytes = ents.values().copied().fold(0_usize, usize::saturating_add);
 
 		let bytes = bytes::pretty(bytes);
-		writeln!(out, "stateinfo_cache: {cache_len} {ents_len} ({bytes})")?;
+		let counters = self.stateinfo_cache.counters();
+		writeln!(out, "stateinfo_cache: {cache_len} {ents_len} ({bytes}) ({counters})")?;
+
+		let (rooms, snapshots) = self.snapshots.totals();
+		writeln!(out, "state_snapshots: {rooms} rooms ({snapshots} snapshots)")?;
 
 		Ok(())
 	}
 
 	async fn clear_cache(&self) { self.stateinfo_cache.lock().expect("locked").clear(); }
 
+	async fn clear_cache_entry(&self, key: &str) -> Result<bool> {
+		evict_shortstatehash(&mut self.stateinfo_cache.lock()?, key)
+	}
+
+	fn caches(&self) -> Vec<(&'static str, &dyn Resizable)> {
+		vec![("stateinfo_cache", &self.stateinfo_cache)]
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -123,8 +145,14 @@ pub async fn load_shortstatehash_info(
 		&self,
 		shortstatehash: ShortStateHash,
 	) -> Result<ShortStateInfoVec> {
-		if let Some(r) = self.stateinfo_cache.lock()?.get_mut(&shortstatehash) {
-			return Ok(r.clone());
+		let cached = self
+			.stateinfo_cache
+			.lock()?
+			.get_mut(&shortstatehash)
+			.cloned();
+
+		if let Some(r) = self.stateinfo_cache.counters().tally(cached) {
+			return Ok(r);
 		}
 
 		let stack = self.new_shortstatehash_info(shortstatehash).await?;
@@ -253,113 +281,38 @@ pub fn save_state_from_diff(
 		statediffnew: Arc<CompressedState>,
 		statediffremoved: Arc<CompressedState>,
 		diff_to_sibling: usize,
-		mut parent_states: ParentStatesVec,
+		parent_states: ParentStatesVec,
 	) -> Result {
-		let statediffnew_len = statediffnew.len();
-		let statediffremoved_len = statediffremoved.len();
-		let diffsum = checked!(statediffnew_len + statediffremoved_len)?;
-
-		if parent_states.len() > 3 {
-			// Number of layers
-			// To many layers, we have to go deeper
-			let parent = parent_states.pop().expect("parent must have a state");
-
-			let mut parent_new = (*parent.added).clone();
-			let mut parent_removed = (*parent.removed).clone();
-
-			for removed in statediffremoved.iter() {
-				if !parent_new.remove(removed) {
-					// It was not added in the parent and we removed it
-					parent_removed.insert(*removed);
-				}
-				// Else it was added in the parent and we removed it again. We
-				// can forget this change
-			}
-
-			for new in statediffnew.iter() {
-				if !parent_removed.remove(new) {
-					// It was not touched in the parent and we added it
-					parent_new.insert(*new);
-				}
-				// Else it was removed in the parent and we added it again. We
-				// can forget this change
-			}
-
-			self.save_state_from_diff(
-				shortstatehash,
-				Arc::new(parent_new),
-				Arc::new(parent_removed),
-				diffsum,
-				parent_states,
-			)?;
-
-			return Ok(());
-		}
+		let diff = layer_diff(statediffnew, statediffremoved, diff_to_sibling, parent_states)?;
+		self.save_statediff(shortstatehash, &diff);
 
-		if parent_states.is_empty() {
-			// There is no parent layer, create a new state
-			self.save_statediff(shortstatehash, &StateDiff {
-				parent: None,
-				added: statediffnew,
-				removed: statediffremoved,
-			});
-
-			return Ok(());
-		}
-
-		// Else we have two options.
-		// 1. We add the current diff on top of the parent layer.
-		// 2. We replace a layer above
-
-		let parent = parent_states.pop().expect("parent must have a state");
-		let parent_added_len = parent.added.len();
-		let parent_removed_len = parent.removed.len();
-		let parent_diff = checked!(parent_added_len + parent_removed_len)?;
-
-		if checked!(diffsum * diffsum)? >= checked!(2 * diff_to_sibling * parent_diff)? {
-			// Diff too big, we replace above layer(s)
-			let mut parent_new = (*parent.added).clone();
-			let mut parent_removed = (*parent.removed).clone();
-
-			for removed in statediffremoved.iter() {
-				if !parent_new.remove(removed) {
-					// It was not added in the parent and we removed it
-					parent_removed.insert(*removed);
-				}
-				// Else it was added in the parent and we removed it again. We
-				// can forget this change
-			}
-
-			for new in statediffnew.iter() {
-				if !parent_removed.remove(new) {
-					// It was not touched in the parent and we added it
-					parent_new.insert(*new);
-				}
-				// Else it was removed in the parent and we added it again. We
-				// can forget this change
-			}
+		Ok(())
+	}
 
-			self.save_state_from_diff(
-				shortstatehash,
-				Arc::new(parent_new),
-				Arc::new(parent_removed),
-				diffsum,
-				parent_states,
-			)?;
-		} else {
-			// Diff small enough, we add diff as layer on top of parent
-			self.save_statediff(shortstatehash, &StateDiff {
-				parent: Some(parent.shortstatehash),
-				added: statediffnew,
-				removed: statediffremoved,
-			});
-		}
+	/// Stages on `batch` the diff `save_state_from_diff` would save, so it is
+	/// only written with the rest of the batch.
+	pub fn stage_state_from_diff<'a>(
+		&'a self,
+		batch: &mut Batch<'a>,
+		shortstatehash: ShortStateHash,
+		statediffnew: Arc<CompressedState>,
+		statediffremoved: Arc<CompressedState>,
+		diff_to_sibling: usize,
+		parent_states: ParentStatesVec,
+	) -> Result {
+		let diff = layer_diff(statediffnew, statediffremoved, diff_to_sibling, parent_states)?;
+		batch.insert(
+			&self.db.shortstatehash_statediff,
+			&shortstatehash.to_be_bytes(),
+			statediff_value(&diff),
+		);
 
 		Ok(())
 	}
 
 	/// Returns the new shortstatehash, and the state diff from the previous
-	/// room state
+	/// room state. Every so often the new state is saved as a full snapshot
+	/// rather than as a diff, see `Snapshots`.
 	#[tracing::instrument(skip(self, new_state_ids_compressed), level = "debug")]
 	pub async fn save_state(
 		&self,
@@ -389,6 +342,7 @@ pub async fn save_state(
 			});
 		}
 
+		let new_state = new_state_ids_compressed.clone();
 		let states_parents = if let Some(p) = previous_shortstatehash {
 			self.load_shortstatehash_info(p).await.unwrap_or_default()
 		} else {
@@ -413,7 +367,15 @@ pub async fn save_state(
 				(new_state_ids_compressed, Arc::new(CompressedState::new()))
 			};
 
-		if !already_existed {
+		if already_existed {
+			// the state was saved before
+		} else if self.snapshot_due(room_id, &states_parents, &statediffnew, &statediffremoved) {
+			self.save_statediff(new_shortstatehash, &StateDiff {
+				parent: None,
+				added: new_state,
+				removed: Arc::default(),
+			});
+		} else {
 			self.save_state_from_diff(
 				new_shortstatehash,
 				statediffnew.clone(),
@@ -430,10 +392,87 @@ pub async fn save_state(
 		})
 	}
 
+	/// Whether a new state with the given diff to the top of `parent_states`
+	/// is to be saved as a full snapshot. The drift from the full state at
+	/// the bottom is estimated from the sizes of the diffs above it.
+	fn snapshot_due(
+		&self,
+		room_id: &RoomId,
+		parent_states: &[ShortStateInfo],
+		added: &CompressedState,
+		removed: &CompressedState,
+	) -> bool {
+		if parent_states.is_empty() {
+			return false;
+		}
+
+		let drift = parent_states
+			.iter()
+			.skip(1)
+			.map(|layer| layer.added.len().saturating_add(layer.removed.len()))
+			.fold(added.len().saturating_add(removed.len()), usize::saturating_add);
+
+		self.snapshots.change(room_id, drift, Instant::now())
+	}
+
+	/// The state groups reachable from `roots` through their parents, and the
+	/// events added or removed by any of them. Groups whose diff is missing
+	/// end the walk along their branch.
+	pub async fn state_closure<I>(
+		&self,
+		roots: I,
+	) -> (BTreeSet<ShortStateHash>, BTreeSet<ShortEventId>)
+	where
+		I: IntoIterator<Item = ShortStateHash> + Send,
+		I::IntoIter: Send,
+	{
+		let mut groups = BTreeSet::new();
+		let mut events = BTreeSet::new();
+		for root in roots {
+			let mut next = Some(root);
+			while let Some(shortstatehash) = next.take_if(|group| !groups.contains(group)) {
+				let Ok(StateDiff { parent, added, removed }) =
+					self.get_statediff(shortstatehash).await
+				else {
+					break;
+				};
+
+				groups.insert(shortstatehash);
+				events.extend(
+					added
+						.iter()
+						.chain(removed.iter())
+						.copied()
+						.map(parse_compressed_state_event)
+						.map(at!(1)),
+				);
+
+				next = parent;
+			}
+		}
+
+		(groups, events)
+	}
+
+	/// Deletes the diffs of the state groups, e.g. those of a room being
+	/// purged, along with their cached info. Nothing else may refer to them.
+	pub fn purge_statediffs(&self, shortstatehashes: &BTreeSet<ShortStateHash>) {
+		let mut cache = self.stateinfo_cache.lock().expect("locked");
+		for shortstatehash in shortstatehashes {
+			cache.remove(shortstatehash);
+			self.db
+				.shortstatehash_statediff
+				.remove(&shortstatehash.to_be_bytes());
+		}
+	}
+
+	/// The diff of the state from its parent. A corrupt value, as left by a
+	/// partial write or damage to the disk, is read as far as it is intact
+	/// with a loud error, so the room stays usable rather than failing every
+	/// load of its state, at the cost of the entries after the corruption.
 	#[tracing::instrument(skip(self), level = "debug", name = "get")]
 	async fn get_statediff(&self, shortstatehash: ShortStateHash) -> Result<StateDiff> {
 		const BUFSIZE: usize = size_of::<ShortStateHash>();
-		const STRIDE: usize = size_of::<ShortStateHash>();
 
 		let value = self
 			.db
@@ -441,69 +480,218 @@ async fn get_statediff(&self, shortstatehash: ShortStateHash) -> Result<StateDif
 			.aqry::<BUFSIZE, _>(&shortstatehash)
 			.await
 			.map_err(|e| {
-				err!(Database("Failed to find StateDiff from short {shortstatehash:?}: {e}"))
+				if !e.is_not_found() {
+					return e;
+				}
+
+				debug_warn!(?shortstatehash, "Failed to find StateDiff: {e}");
+				Error::StateUnavailable { shortstatehash }
 			})?;
 
-		let parent = utils::u64_from_bytes(&value[0..size_of::<u64>()])
-			.ok()
-			.take_if(|parent| *parent != 0);
+		let len = value.len();
+		let Some((diff, intact)) = read_statediff(shortstatehash, &value) else {
+			error!(?shortstatehash, len, "StateDiff is corrupt beyond recovery");
+			return Err(Error::CorruptStateDiff { shortstatehash, len });
+		};
 
-		debug_assert!(value.len() % STRIDE == 0, "value not aligned to stride");
-		let _num_values = value.len() / STRIDE;
+		if !intact {
+			error!(
+				?shortstatehash,
+				len,
+				parent = ?diff.parent,
+				added = diff.added.len(),
+				removed = diff.removed.len(),
+				"StateDiff is corrupt; using the entries before the corruption. The state may \
+				 lack some entries until the room's state changes again."
+			);
+		}
 
-		let mut add_mode = true;
-		let mut added = CompressedState::new();
-		let mut removed = CompressedState::new();
+		Ok(diff)
+	}
 
-		let mut i = STRIDE;
-		while let Some(v) = value.get(i..expected!(i + 2 * STRIDE)) {
-			if add_mode && v.starts_with(&0_u64.to_be_bytes()) {
-				add_mode = false;
-				i = expected!(i + STRIDE);
-				continue;
+	fn save_statediff(&self, shortstatehash: ShortStateHash, diff: &StateDiff) {
+		self.db
+			.shortstatehash_statediff
+			.insert(&shortstatehash.to_be_bytes(), &statediff_value(diff));
+	}
+}
+
+/// The diff to save for a state with the given diff from its parent. There
+/// are multiple layers of diffs, see `save_state_from_diff`.
+fn layer_diff(
+	statediffnew: Arc<CompressedState>,
+	statediffremoved: Arc<CompressedState>,
+	diff_to_sibling: usize,
+	mut parent_states: ParentStatesVec,
+) -> Result<StateDiff> {
+	let statediffnew_len = statediffnew.len();
+	let statediffremoved_len = statediffremoved.len();
+	let diffsum = checked!(statediffnew_len + statediffremoved_len)?;
+
+	if parent_states.len() > 3 {
+		// Number of layers
+		// To many layers, we have to go deeper
+		let parent = parent_states.pop().expect("parent must have a state");
+
+		let mut parent_new = (*parent.added).clone();
+		let mut parent_removed = (*parent.removed).clone();
+
+		for removed in statediffremoved.iter() {
+			if !parent_new.remove(removed) {
+				// It was not added in the parent and we removed it
+				parent_removed.insert(*removed);
 			}
-			if add_mode {
-				added.insert(v.try_into()?);
-			} else {
-				removed.insert(v.try_into()?);
+			// Else it was added in the parent and we removed it again. We
+			// can forget this change
+		}
+
+		for new in statediffnew.iter() {
+			if !parent_removed.remove(new) {
+				// It was not touched in the parent and we added it
+				parent_new.insert(*new);
 			}
-			i = expected!(i + 2 * STRIDE);
+			// Else it was removed in the parent and we added it again. We
+			// can forget this change
 		}
 
-		Ok(StateDiff {
-			parent,
-			added: Arc::new(added),
-			removed: Arc::new(removed),
-		})
+		return layer_diff(
+			Arc::new(parent_new),
+			Arc::new(parent_removed),
+			diffsum,
+			parent_states,
+		);
 	}
 
-	fn save_statediff(&self, shortstatehash: ShortStateHash, diff: &StateDiff) {
-		let mut value = Vec::<u8>::with_capacity(
-			2_usize
-				.saturating_add(diff.added.len())
-				.saturating_add(diff.removed.len()),
-		);
+	if parent_states.is_empty() {
+		// There is no parent layer, create a new state
+		return Ok(StateDiff {
+			parent: None,
+			added: statediffnew,
+			removed: statediffremoved,
+		});
+	}
 
-		let parent = diff.parent.unwrap_or(0_u64);
-		value.extend_from_slice(&parent.to_be_bytes());
+	// Else we have two options.
+	// 1. We add the current diff on top of the parent layer.
+	// 2. We replace a layer above
 
-		for new in diff.added.iter() {
-			value.extend_from_slice(&new[..]);
+	let parent = parent_states.pop().expect("parent must have a state");
+	let parent_added_len = parent.added.len();
+	let parent_removed_len = parent.removed.len();
+	let parent_diff = checked!(parent_added_len + parent_removed_len)?;
+
+	if checked!(diffsum * diffsum)? >= checked!(2 * diff_to_sibling * parent_diff)? {
+		// Diff too big, we replace above layer(s)
+		let mut parent_new = (*parent.added).clone();
+		let mut parent_removed = (*parent.removed).clone();
+
+		for removed in statediffremoved.iter() {
+			if !parent_new.remove(removed) {
+				// It was not added in the parent and we removed it
+				parent_removed.insert(*removed);
+			}
+			// Else it was added in the parent and we removed it again. We
+			// can forget this change
 		}
 
-		if !diff.removed.is_empty() {
-			value.extend_from_slice(&0_u64.to_be_bytes());
-			for removed in diff.removed.iter() {
-				value.extend_from_slice(&removed[..]);
+		for new in statediffnew.iter() {
+			if !parent_removed.remove(new) {
+				// It was not touched in the parent and we added it
+				parent_new.insert(*new);
 			}
+			// Else it was removed in the parent and we added it again. We
+			// can forget this change
 		}
 
-		self.db
-			.shortstatehash_statediff
-			.insert(&shortstatehash.to_be_bytes(), &value);
+		layer_diff(Arc::new(parent_new), Arc::new(parent_removed), diffsum, parent_states)
+	} else {
+		// Diff small enough, we add diff as layer on top of parent
+		Ok(StateDiff {
+			parent: Some(parent.shortstatehash),
+			added: statediffnew,
+			removed: statediffremoved,
+		})
 	}
 }
 
+/// The diff stored as the parent, or zero for none, the added entries, and if
+/// any were removed a zero separator followed by the removed entries.
+fn statediff_value(diff: &StateDiff) -> Vec<u8> {
+	let mut value = Vec::<u8>::with_capacity(
+		2_usize
+			.saturating_add(diff.added.len())
+			.saturating_add(diff.removed.len()),
+	);
+
+	let parent = diff.parent.unwrap_or(0_u64);
+	value.extend_from_slice(&parent.to_be_bytes());
+
+	for new in diff.added.iter() {
+		value.extend_from_slice(&new[..]);
+	}
+
+	if !diff.removed.is_empty() {
+		value.extend_from_slice(&0_u64.to_be_bytes());
+		for removed in diff.removed.iter() {
+			value.extend_from_slice(&removed[..]);
+		}
+	}
+
+	value
+}
+
+/// Reads a stored diff as far as it is intact, returning whether all of it
+/// was. Values which end partway through an entry are corrupt, as are those
+/// without a whole parent or naming themselves as it, which are not read at
+/// all.
+fn read_statediff(shortstatehash: ShortStateHash, value: &[u8]) -> Option<(StateDiff, bool)> {
+	const STRIDE: usize = size_of::<ShortStateHash>();
+	const SEPARATOR: [u8; STRIDE] = 0_u64.to_be_bytes();
+
+	let parent = value.get(..STRIDE).map(utils::u64_from_u8)?;
+	let parent = (parent != 0).then_some(parent);
+	if parent == Some(shortstatehash) {
+		return None;
+	}
+
+	let mut add_mode = true;
+	let mut added = CompressedState::new();
+	let mut removed = CompressedState::new();
+
+	let mut rest = value.get(STRIDE..).unwrap_or_default();
+	let intact = loop {
+		if rest.is_empty() {
+			break true;
+		}
+
+		if add_mode && rest.starts_with(&SEPARATOR) {
+			add_mode = false;
+			rest = rest.get(STRIDE..).unwrap_or_default();
+			continue;
+		}
+
+		let Some((entry, after)) = rest.split_first_chunk::<{ 2 * STRIDE }>() else {
+			break false;
+		};
+
+		if add_mode {
+			added.insert(*entry);
+		} else {
+			removed.insert(*entry);
+		}
+
+		rest = after;
+	};
+
+	let diff = StateDiff {
+		parent,
+		added: Arc::new(added),
+		removed: Arc::new(removed),
+	};
+
+	Some((diff, intact))
+}
+
 #[inline]
 #[must_use]
 pub(crate) fn compress_state_event(
@@ -540,3 +728,13 @@ fn compressed_state_size(compressed_state: &CompressedState) -> usize {
 		.checked_mul(size_of::<CompressedStateEvent>())
 		.expect("CompressedState size overflow")
 }
+
+/// Evicts the state info of the shortstatehash given as `key`. The layers of
+/// other entries which are based on it are left as they are.
+fn evict_shortstatehash(cache: &mut StateInfoLruCache, key: &str) -> Result<bool> {
+	let shortstatehash: ShortStateHash = key
+		.parse()
+		.map_err(|e| err!(Request(InvalidParam("Invalid shortstatehash {key:?}: {e}"))))?;
+
+	Ok(cache.remove(&shortstatehash).is_some())
+}
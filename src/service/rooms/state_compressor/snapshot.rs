@@ -0,0 +1,83 @@
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use conduwuit::Config;
+use ruma::{OwnedRoomId, RoomId};
+
+/// Decides when the state of a room is saved as a full snapshot rather than
+/// as a diff, keeping track of the state changes of each room since its last
+/// snapshot. Nothing is remembered across restarts.
+#[derive(Debug)]
+pub struct Snapshots {
+	rooms: Mutex<HashMap<OwnedRoomId, SnapshotStats>>,
+	changes: usize,
+	drift: usize,
+	min_interval: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SnapshotStats {
+	/// State changes since the last snapshot.
+	pub changes: usize,
+
+	/// Snapshots taken since startup.
+	pub snapshots: usize,
+
+	pub last: Option<Instant>,
+}
+
+impl Snapshots {
+	pub(super) fn new(config: &Config) -> Self {
+		Self {
+			rooms: Mutex::default(),
+			changes: config.state_snapshot_changes,
+			drift: config.state_snapshot_drift,
+			min_interval: Duration::from_secs(config.state_snapshot_min_interval),
+		}
+	}
+
+	/// Notes a change of the room's state, which differs by `drift` entries
+	/// from the full state its diffs would be based on, and returns whether
+	/// the new state is to be saved as a full snapshot.
+	pub(super) fn change(&self, room_id: &RoomId, drift: usize, now: Instant) -> bool {
+		let mut rooms = self.rooms.lock().expect("locked");
+		let room = rooms.entry(room_id.to_owned()).or_default();
+		room.changes = room.changes.saturating_add(1);
+
+		let due = (self.changes > 0 && room.changes >= self.changes)
+			|| (self.drift > 0 && drift >= self.drift);
+
+		let allowed = room
+			.last
+			.is_none_or(|last| now.saturating_duration_since(last) >= self.min_interval);
+
+		if !due || !allowed {
+			return false;
+		}
+
+		room.changes = 0;
+		room.snapshots = room.snapshots.saturating_add(1);
+		room.last = Some(now);
+		true
+	}
+
+	#[must_use]
+	pub fn stats(&self, room_id: &RoomId) -> Option<SnapshotStats> {
+		self.rooms.lock().expect("locked").get(room_id).copied()
+	}
+
+	/// Number of rooms tracked and of snapshots taken in all of them.
+	#[must_use]
+	pub fn totals(&self) -> (usize, usize) {
+		let rooms = self.rooms.lock().expect("locked");
+		let snapshots = rooms
+			.values()
+			.map(|room| room.snapshots)
+			.fold(0_usize, usize::saturating_add);
+
+		(rooms.len(), snapshots)
+	}
+}
@@ -0,0 +1,182 @@
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{Config, config::Figment};
+use lru_cache::LruCache;
+use ruma::room_id;
+
+use super::{
+	ShortStateInfo, Snapshots, StateDiff, StateInfoLruCache, compress_state_event,
+	evict_shortstatehash, read_statediff, statediff_value,
+};
+
+fn cache() -> StateInfoLruCache {
+	let mut cache = LruCache::new(8);
+	for shortstatehash in [1, 2, 3] {
+		let info = ShortStateInfo { shortstatehash, ..Default::default() };
+		cache.insert(shortstatehash, vec![info]);
+	}
+
+	cache
+}
+
+#[test]
+fn evict_shortstatehash_entry() {
+	let mut cache = cache();
+
+	assert!(evict_shortstatehash(&mut cache, "2").unwrap());
+	assert!(!cache.contains_key(&2));
+	assert!(cache.contains_key(&1));
+	assert!(cache.contains_key(&3));
+
+	assert!(!evict_shortstatehash(&mut cache, "2").unwrap());
+	assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn evict_shortstatehash_invalid() {
+	let mut cache = cache();
+
+	assert!(evict_shortstatehash(&mut cache, "!room:example.org").is_err());
+	assert_eq!(cache.len(), 3);
+}
+
+fn snapshots(changes: usize, drift: usize, min_interval: u64) -> Snapshots {
+	let config = Figment::new()
+		.join(("server_name", "example.com"))
+		.join(("database_path", "/tmp/conduwuit_state_compressor"))
+		.join(("state_snapshot_changes", changes))
+		.join(("state_snapshot_drift", drift))
+		.join(("state_snapshot_min_interval", min_interval));
+
+	Snapshots::new(&Config::new(&config).expect("valid config"))
+}
+
+/// Adds a new state entry `count` times, `step` apart, and returns the most
+/// entries replayed on top of a full state to load any of those states.
+fn simulate(snapshots: &Snapshots, count: usize, step: Duration) -> usize {
+	let room_id = room_id!("!busy:example.org");
+	let start = Instant::now();
+	let mut walk = 0_usize;
+	let mut longest = 0_usize;
+	for change in 0..count {
+		let drift = walk.saturating_add(1);
+		let now = u32::try_from(change)
+			.ok()
+			.and_then(|change| step.checked_mul(change))
+			.and_then(|elapsed| start.checked_add(elapsed))
+			.expect("time of the change");
+
+		walk = if snapshots.change(room_id, drift, now) {
+			0
+		} else {
+			drift
+		};
+		longest = longest.max(walk);
+	}
+
+	longest
+}
+
+#[test]
+fn snapshot_bounds_walk() {
+	let snapshots = snapshots(1000, 500, 60);
+	let longest = simulate(&snapshots, 10_000, Duration::from_secs(1));
+
+	assert_eq!(longest, 499, "bounded by the drift");
+
+	let stats = snapshots
+		.stats(room_id!("!busy:example.org"))
+		.expect("tracked");
+
+	assert_eq!(stats.snapshots, 20);
+	assert_eq!(snapshots.totals(), (1, 20));
+}
+
+#[test]
+fn snapshot_rate_limited() {
+	let snapshots = snapshots(100, 0, 60);
+	let longest = simulate(&snapshots, 10_000, Duration::from_millis(100));
+
+	// a snapshot every 60s at most, that is every 600 changes
+	assert_eq!(longest, 599);
+	assert_eq!(snapshots.totals(), (1, 17));
+}
+
+#[test]
+fn snapshot_disabled() {
+	let snapshots = snapshots(0, 0, 0);
+
+	assert_eq!(simulate(&snapshots, 10_000, Duration::ZERO), 10_000);
+	assert_eq!(snapshots.totals(), (1, 0));
+}
+
+fn statediff(parent: Option<u64>, added: &[(u64, u64)], removed: &[(u64, u64)]) -> StateDiff {
+	let compress = |entries: &[(u64, u64)]| {
+		entries
+			.iter()
+			.map(|&(shortstatekey, shorteventid)| {
+				compress_state_event(shortstatekey, shorteventid)
+			})
+			.collect()
+	};
+
+	StateDiff {
+		parent,
+		added: Arc::new(compress(added)),
+		removed: Arc::new(compress(removed)),
+	}
+}
+
+fn entries(diff: &StateDiff) -> (Option<u64>, usize, usize) {
+	(diff.parent, diff.added.len(), diff.removed.len())
+}
+
+#[test]
+fn statediff_read_intact() {
+	for diff in [
+		statediff(None, &[(1, 10), (2, 20)], &[]),
+		statediff(Some(7), &[(1, 11)], &[(1, 10), (2, 20)]),
+		statediff(Some(7), &[], &[(3, 30)]),
+		statediff(Some(7), &[], &[]),
+	] {
+		let value = statediff_value(&diff);
+		let (read, intact) = read_statediff(9, &value).expect("readable");
+
+		assert!(intact);
+		assert_eq!(read.parent, diff.parent);
+		assert_eq!(read.added, diff.added);
+		assert_eq!(read.removed, diff.removed);
+	}
+}
+
+#[test]
+fn statediff_read_truncated() {
+	let value = statediff_value(&statediff(Some(7), &[(1, 11), (2, 21)], &[(1, 10), (2, 20)]));
+
+	let cut = |len: usize| {
+		read_statediff(9, &value[..len]).map(|(diff, intact)| (entries(&diff), intact))
+	};
+
+	assert_eq!(cut(value.len()), Some(((Some(7), 2, 2), true)));
+	assert_eq!(cut(value.len().saturating_sub(3)), Some(((Some(7), 2, 1), false)));
+	assert_eq!(cut(8 + 16 + 5), Some(((Some(7), 1, 0), false)));
+	assert_eq!(cut(8 + 16 + 16), Some(((Some(7), 2, 0), true)));
+	assert_eq!(cut(8), Some(((Some(7), 0, 0), true)));
+	assert_eq!(cut(5), None);
+	assert_eq!(cut(0), None);
+}
+
+#[test]
+fn statediff_read_garbage() {
+	let garbage: Vec<u8> = (0..45_u8).map(|byte| byte.wrapping_mul(37) | 1).collect();
+	let (diff, intact) = read_statediff(9, &garbage).expect("parent readable");
+	assert!(!intact);
+	assert_eq!(diff.added.len(), 2);
+	assert!(diff.removed.is_empty());
+
+	let own_parent = statediff_value(&statediff(Some(9), &[(1, 10)], &[]));
+	assert!(read_statediff(9, &own_parent).is_none());
+}
@@ -0,0 +1,348 @@
+//! State growth
+//!
+//! Limits how many distinct state keys one sender sets in a room. Local users
+//! are refused past an hourly limit for each event type; remote users setting
+//! far more within a short window are reported to the admin room, and their
+//! state events optionally soft-failed. Each room's state events are counted
+//! by day so admins can see how its state grows.
+
+#[cfg(test)]
+mod tests;
+mod window;
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Error, PduEvent, Result, Server, implement,
+	utils::{
+		millis_since_unix_epoch,
+		stream::TryIgnore,
+		time::{self, pretty},
+	},
+};
+use database::{Deserialized, Interfix, Json, Map};
+use futures::StreamExt;
+use http::StatusCode;
+use ruma::{
+	OwnedRoomId, OwnedUserId, RoomId,
+	api::client::error::{ErrorKind, RetryAfter},
+	events::StateEventType,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+use self::window::{Verdict, Windows};
+use crate::{Dep, admin, globals};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+	local: Mutex<Windows<(OwnedRoomId, OwnedUserId, StateEventType), String>>,
+	remote: Mutex<Windows<(OwnedRoomId, OwnedUserId), (StateEventType, String)>>,
+	daily: Mutex<HashMap<(OwnedRoomId, u64), Growth>>,
+	interrupt: Notify,
+}
+
+struct Data {
+	roomday_stategrowth: Arc<Map>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
+	globals: Dep<globals::Service>,
+}
+
+/// State events sent in a room over one day.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Growth {
+	/// State events let through.
+	pub accepted: u64,
+
+	/// State events refused from local users over the limit.
+	pub refused: u64,
+
+	/// State events from remote users over the limit.
+	pub flooded: u64,
+
+	/// State events let through from each sender.
+	pub senders: BTreeMap<OwnedUserId, u64>,
+}
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// How often the daily counts are written to the database.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let window = Duration::from_secs(args.server.config.federation_state_flood_window);
+
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
+				globals: args.depend::<globals::Service>("globals"),
+			},
+			db: Data {
+				roomday_stategrowth: args.db["roomday_stategrowth"].clone(),
+			},
+			local: Mutex::new(Windows::new(HOUR)),
+			remote: Mutex::new(Windows::new(window)),
+			daily: Mutex::default(),
+			interrupt: Notify::new(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let mut i = interval(PERSIST_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			let now = Instant::now();
+			self.local.lock().expect("locked").prune(now);
+			self.remote.lock().expect("locked").prune(now);
+			self.persist().await;
+		}
+
+		self.persist().await;
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let local = self.local.lock().expect("locked").len();
+		let remote = self.remote.lock().expect("locked").len();
+		let daily = self.daily.lock().expect("locked").len();
+		writeln!(out, "state_growth_local_senders: {local}")?;
+		writeln!(out, "state_growth_remote_senders: {remote}")?;
+		writeln!(out, "state_growth_unsaved_days: {daily}")?;
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Counts a state event about to be sent by a local user, refusing it when
+/// the user has set too many distinct state keys of its type in the room this
+/// hour.
+#[implement(Service)]
+pub fn check_local(&self, pdu: &PduEvent) -> Result {
+	let Some(state_key) = self.counted(pdu) else {
+		return Ok(());
+	};
+
+	if pdu.sender == self.services.globals.server_user {
+		return Ok(());
+	}
+
+	let limit = self.services.server.config.state_keys_per_sender_hourly;
+	let of = (pdu.room_id.clone(), pdu.sender.clone(), pdu.kind.to_string().into());
+	let now = Instant::now();
+	let verdict = self
+		.local
+		.lock()
+		.expect("locked")
+		.count(of, state_key.to_owned(), limit, now);
+
+	if let Verdict::Over { until, .. } = verdict {
+		self.record(&pdu.room_id, |growth| growth.refused = growth.refused.saturating_add(1));
+
+		let retry_after = until.saturating_duration_since(now);
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded {
+				retry_after: Some(RetryAfter::Delay(retry_after)),
+			},
+			format!(
+				"Too many distinct {} state keys set in this room; try again in {}.",
+				pdu.kind,
+				pretty(retry_after)
+			)
+			.into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	self.accepted(pdu);
+
+	Ok(())
+}
+
+/// Counts a state event from a remote user which passed auth, returning
+/// whether to soft-fail it as flooding the room's state. The admin room is
+/// told the first time a sender goes over the limit in a window.
+#[implement(Service)]
+pub async fn check_remote(&self, pdu: &PduEvent) -> bool {
+	let Some(state_key) = self.counted(pdu) else {
+		return false;
+	};
+
+	if self.services.globals.user_is_local(&pdu.sender) {
+		return false;
+	}
+
+	let config = &self.services.server.config;
+	let of = (pdu.room_id.clone(), pdu.sender.clone());
+	let key = (pdu.kind.to_string().into(), state_key.to_owned());
+	let verdict = self.remote.lock().expect("locked").count(
+		of,
+		key,
+		config.federation_state_keys_per_sender,
+		Instant::now(),
+	);
+
+	let Verdict::Over { first, .. } = verdict else {
+		self.accepted(pdu);
+		return false;
+	};
+
+	self.record(&pdu.room_id, |growth| growth.flooded = growth.flooded.saturating_add(1));
+
+	let soft_fail = config.federation_state_flood_soft_fail;
+	if first {
+		let action = if soft_fail {
+			"Their further state events are soft-failed until then."
+		} else {
+			"Their state events are still accepted."
+		};
+
+		self.services
+			.admin
+			.send_text(&format!(
+				"{} set over {} distinct state keys in {} within {}. {action}",
+				pdu.sender,
+				config.federation_state_keys_per_sender,
+				pdu.room_id,
+				pretty(Duration::from_secs(config.federation_state_flood_window)),
+			))
+			.await;
+	}
+
+	soft_fail
+}
+
+/// Daily counts of the room's state events, newest first.
+#[implement(Service)]
+pub async fn growth(&self, room_id: &RoomId, days: usize) -> Vec<(u64, Growth)> {
+	self.persist().await;
+
+	let prefix = (room_id, Interfix);
+	let mut growth: Vec<(u64, Growth)> = self
+		.db
+		.roomday_stategrowth
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.map(|((_, day), growth): ((&RoomId, u64), Growth)| (day, growth))
+		.collect()
+		.await;
+
+	growth.reverse();
+	growth.truncate(days);
+	growth
+}
+
+/// Writes the daily counts gathered since last time to the database, adding
+/// them to those already there.
+#[implement(Service)]
+async fn persist(&self) {
+	let daily = std::mem::take(&mut *self.daily.lock().expect("locked"));
+	for ((room_id, day), growth) in daily {
+		let key = (&room_id, day);
+		let mut saved: Growth = self
+			.db
+			.roomday_stategrowth
+			.qry(&key)
+			.await
+			.deserialized()
+			.unwrap_or_default();
+
+		saved.merge(growth);
+		self.db.roomday_stategrowth.put(key, Json(saved));
+	}
+}
+
+/// The state key of an event counted towards the limits.
+#[implement(Service)]
+fn counted<'a>(&self, pdu: &'a PduEvent) -> Option<&'a str> {
+	let state_key = pdu.state_key.as_deref()?;
+	let kind = pdu.kind.to_string();
+
+	(!self
+		.services
+		.server
+		.config
+		.state_flood_allowed_types
+		.contains(&kind))
+	.then_some(state_key)
+}
+
+#[implement(Service)]
+fn accepted(&self, pdu: &PduEvent) {
+	self.record(&pdu.room_id, |growth| {
+		growth.accepted = growth.accepted.saturating_add(1);
+		let sent = growth.senders.entry(pdu.sender.clone()).or_default();
+		*sent = sent.saturating_add(1);
+	});
+}
+
+#[implement(Service)]
+fn record<F: FnOnce(&mut Growth)>(&self, room_id: &RoomId, f: F) {
+	let day = millis_since_unix_epoch() / DAY_MILLIS;
+	let mut daily = self.daily.lock().expect("locked");
+	f(daily.entry((room_id.to_owned(), day)).or_default());
+}
+
+impl Growth {
+	pub(super) fn merge(&mut self, other: Self) {
+		self.accepted = self.accepted.saturating_add(other.accepted);
+		self.refused = self.refused.saturating_add(other.refused);
+		self.flooded = self.flooded.saturating_add(other.flooded);
+		for (sender, sent) in other.senders {
+			let total = self.senders.entry(sender).or_default();
+			*total = total.saturating_add(sent);
+		}
+	}
+
+	/// Senders who sent the most, most first.
+	#[must_use]
+	pub fn top_senders(&self, n: usize) -> Vec<(&OwnedUserId, u64)> {
+		let mut senders: Vec<_> = self
+			.senders
+			.iter()
+			.map(|(sender, sent)| (sender, *sent))
+			.collect();
+
+		senders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+		senders.truncate(n);
+		senders
+	}
+}
+
+/// Start of the day numbered `day` since the epoch, formatted as a date.
+#[must_use]
+pub fn date(day: u64) -> String {
+	let since_epoch = Duration::from_millis(day.saturating_mul(DAY_MILLIS));
+	let ts = std::time::UNIX_EPOCH
+		.checked_add(since_epoch)
+		.unwrap_or(std::time::UNIX_EPOCH);
+
+	time::format(ts, "%Y-%m-%d")
+}
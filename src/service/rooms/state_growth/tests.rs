@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use ruma::owned_user_id;
+
+use super::{
+	Growth,
+	window::{Verdict, Windows},
+};
+
+const PERIOD: Duration = Duration::from_secs(60);
+
+fn later(now: Instant, secs: u64) -> Instant {
+	now.checked_add(Duration::from_secs(secs))
+		.expect("instant in range")
+}
+
+#[test]
+fn distinct_keys_limited() {
+	let now = Instant::now();
+	let mut windows = Windows::new(PERIOD);
+
+	for key in ["a", "b", "c"] {
+		assert_eq!(windows.count("sender", key, 3, now), Verdict::Within);
+	}
+
+	let until = later(now, 60);
+	assert_eq!(windows.count("sender", "d", 3, now), Verdict::Over { until, first: true });
+	assert_eq!(windows.count("sender", "e", 3, now), Verdict::Over { until, first: false });
+	assert_eq!(windows.count("sender", "a", 3, now), Verdict::Within, "counted already");
+	assert_eq!(windows.count("other", "d", 3, now), Verdict::Within, "other sender");
+	assert_eq!(windows.count("sender", "f", 0, now), Verdict::Within, "not limited");
+}
+
+#[test]
+fn window_expires() {
+	let now = Instant::now();
+	let mut windows = Windows::new(PERIOD);
+
+	assert_eq!(windows.count("sender", "a", 1, now), Verdict::Within);
+	assert!(matches!(windows.count("sender", "b", 1, later(now, 30)), Verdict::Over { .. }));
+
+	let next = later(now, 60);
+	assert_eq!(windows.count("sender", "b", 1, next), Verdict::Within);
+	assert_eq!(
+		windows.count("sender", "c", 1, next),
+		Verdict::Over { until: later(next, 60), first: true },
+		"reported again in the next window",
+	);
+}
+
+#[test]
+fn prune_forgets_passed_windows() {
+	let now = Instant::now();
+	let mut windows = Windows::new(PERIOD);
+
+	windows.count("early", "a", 1, now);
+	windows.count("late", "a", 1, later(now, 30));
+	assert_eq!(windows.len(), 2);
+
+	windows.prune(later(now, 60));
+	assert_eq!(windows.len(), 1);
+	assert_eq!(windows.count("late", "b", 1, later(now, 60)), Verdict::Over {
+		until: later(now, 90),
+		first: true,
+	});
+
+	windows.prune(later(now, 90));
+	assert_eq!(windows.len(), 0);
+}
+
+#[test]
+fn growth_merges() {
+	let alice = owned_user_id!("@alice:example.com");
+	let bob = owned_user_id!("@bob:example.com");
+
+	let mut saved = Growth {
+		accepted: 3,
+		refused: 1,
+		flooded: 0,
+		senders: [(alice.clone(), 3)].into(),
+	};
+
+	saved.merge(Growth {
+		accepted: 4,
+		refused: 0,
+		flooded: 2,
+		senders: [(alice, 1), (bob, 3)].into(),
+	});
+
+	assert_eq!((saved.accepted, saved.refused, saved.flooded), (7, 1, 2));
+	let top: Vec<_> = saved
+		.top_senders(5)
+		.into_iter()
+		.map(|(sender, sent)| (sender.as_str(), sent))
+		.collect();
+
+	assert_eq!(top, [("@alice:example.com", 4), ("@bob:example.com", 3)]);
+	assert_eq!(saved.top_senders(1).len(), 1);
+}
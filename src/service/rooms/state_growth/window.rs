@@ -0,0 +1,85 @@
+use std::{
+	collections::{HashMap, HashSet},
+	hash::Hash,
+	time::{Duration, Instant},
+};
+
+/// Distinct keys counted for each of a set of senders over a fixed window,
+/// which starts with the first key counted for the sender.
+pub(super) struct Windows<K, T> {
+	period: Duration,
+	windows: HashMap<K, Window<T>>,
+}
+
+struct Window<T> {
+	start: Instant,
+	keys: HashSet<T>,
+	over: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum Verdict {
+	/// Newly counted, or counted already in this window.
+	Within,
+
+	/// Over the limit until the window ends; `first` for the first key found
+	/// over it in this window.
+	Over {
+		until: Instant,
+		first: bool,
+	},
+}
+
+impl<K: Eq + Hash, T: Eq + Hash> Windows<K, T> {
+	pub(super) fn new(period: Duration) -> Self { Self { period, windows: HashMap::new() } }
+
+	/// Counts `key` for `of`, unless it was counted already in the window, or
+	/// counting it would take `of` past `limit`. A limit of zero counts
+	/// nothing.
+	pub(super) fn count(&mut self, of: K, key: T, limit: usize, now: Instant) -> Verdict {
+		if limit == 0 {
+			return Verdict::Within;
+		}
+
+		let window = self.windows.entry(of).or_insert_with(|| Window {
+			start: now,
+			keys: HashSet::new(),
+			over: false,
+		});
+
+		if now.saturating_duration_since(window.start) >= self.period {
+			*window = Window {
+				start: now,
+				keys: HashSet::new(),
+				over: false,
+			};
+		}
+
+		if window.keys.contains(&key) {
+			return Verdict::Within;
+		}
+
+		if window.keys.len() >= limit {
+			let first = !window.over;
+			window.over = true;
+
+			return Verdict::Over {
+				until: window.start.checked_add(self.period).unwrap_or(now),
+				first,
+			};
+		}
+
+		window.keys.insert(key);
+
+		Verdict::Within
+	}
+
+	/// Forgets the senders whose window has passed.
+	pub(super) fn prune(&mut self, now: Instant) {
+		let period = self.period;
+		self.windows
+			.retain(|_, window| now.saturating_duration_since(window.start) < period);
+	}
+
+	pub(super) fn len(&self) -> usize { self.windows.len() }
+}
@@ -6,7 +6,7 @@
 	utils,
 	utils::stream::TryReadyExt,
 };
-use database::{Database, Deserialized, Json, KeyVal, Map};
+use database::{Batch, Database, Deserialized, Json, KeyVal, Map};
 use futures::{FutureExt, Stream, TryFutureExt, TryStreamExt, future::select_ok, pin_mut};
 use ruma::{CanonicalJsonObject, EventId, OwnedUserId, RoomId, UserId, api::Direction};
 
@@ -19,6 +19,8 @@ pub(super) struct Data {
 	pduid_pdu: Arc<Map>,
 	userroomid_highlightcount: Arc<Map>,
 	userroomid_notificationcount: Arc<Map>,
+	userroomthreadid_highlightcount: Arc<Map>,
+	userroomthreadid_notificationcount: Arc<Map>,
 	pub(super) db: Arc<Database>,
 	services: Services,
 }
@@ -38,6 +40,8 @@ pub(super) fn new(args: &crate::Args<'_>) -> Self {
 			pduid_pdu: db["pduid_pdu"].clone(),
 			userroomid_highlightcount: db["userroomid_highlightcount"].clone(),
 			userroomid_notificationcount: db["userroomid_notificationcount"].clone(),
+			userroomthreadid_highlightcount: db["userroomthreadid_highlightcount"].clone(),
+			userroomthreadid_notificationcount: db["userroomthreadid_notificationcount"].clone(),
 			db: args.db.clone(),
 			services: Services {
 				short: args.depend::<rooms::short::Service>("rooms::short"),
@@ -118,6 +122,11 @@ pub(super) async fn get_pdu_id(&self, event_id: &EventId) -> Result<RawPduId> {
 			.map(|handle| RawPduId::from(&*handle))
 	}
 
+	/// Writes the index entry of an event stored at the id.
+	pub(super) fn put_pdu_id(&self, event_id: &EventId, pdu_id: &RawPduId) {
+		self.eventid_pduid.insert(event_id, pdu_id);
+	}
+
 	/// Returns the pdu directly from `eventid_pduid` only.
 	pub(super) async fn get_non_outlier_pdu(&self, event_id: &EventId) -> Result<PduEvent> {
 		let pduid = self.get_pdu_id(event_id).await?;
@@ -186,9 +195,7 @@ pub(super) async fn append_pdu(
 	) {
 		debug_assert!(matches!(count, PduCount::Normal(_)), "PduCount not Normal");
 
-		self.pduid_pdu.raw_put(pdu_id, Json(json));
-		self.eventid_pduid.insert(pdu.event_id.as_bytes(), pdu_id);
-		self.eventid_outlierpdu.remove(pdu.event_id.as_bytes());
+		self.write_pdu(pdu_id, &pdu.event_id, json);
 	}
 
 	pub(super) fn prepend_backfill_pdu(
@@ -197,9 +204,32 @@ pub(super) fn prepend_backfill_pdu(
 		event_id: &EventId,
 		json: &CanonicalJsonObject,
 	) {
-		self.pduid_pdu.raw_put(pdu_id, Json(json));
-		self.eventid_pduid.insert(event_id, pdu_id);
-		self.eventid_outlierpdu.remove(event_id);
+		self.write_pdu(pdu_id, event_id, json);
+	}
+
+	/// Stores a PDU with its event ID index and drops any outlier copy in one
+	/// atomic write, so a crash cannot leave the index pointing at nothing.
+	fn write_pdu(&self, pdu_id: &RawPduId, event_id: &EventId, json: &CanonicalJsonObject) {
+		let mut batch = self.write_batch();
+		self.stage_pdu(&mut batch, pdu_id, event_id, json);
+		batch.commit();
+	}
+
+	/// Starts a batch of writes against any of the database's maps.
+	pub(super) fn write_batch(&self) -> Batch<'_> { self.db.db.write_batch() }
+
+	/// Stages the writes storing a PDU on a batch of writes spanning more than
+	/// the timeline.
+	pub(super) fn stage_pdu<'a>(
+		&'a self,
+		batch: &mut Batch<'a>,
+		pdu_id: &RawPduId,
+		event_id: &EventId,
+		json: &CanonicalJsonObject,
+	) {
+		batch.raw_put(&self.pduid_pdu, pdu_id, Json(json));
+		batch.insert(&self.eventid_pduid, event_id, pdu_id);
+		batch.remove(&self.eventid_outlierpdu, event_id);
 	}
 
 	/// Removes a pdu and creates a new one with the same id.
@@ -269,9 +299,12 @@ fn each_pdu((pdu_id, pdu): KeyVal<'_>, user_id: Option<&UserId>) -> Result<PdusI
 		Ok((pdu_id.pdu_count(), pdu))
 	}
 
+	/// Counts the event against the room, and against its thread too when it
+	/// is in one.
 	pub(super) fn increment_notification_counts(
 		&self,
 		room_id: &RoomId,
+		thread_root: Option<&EventId>,
 		notifies: Vec<OwnedUserId>,
 		highlights: Vec<OwnedUserId>,
 	) {
@@ -282,6 +315,12 @@ pub(super) fn increment_notification_counts(
 			userroom_id.push(0xFF);
 			userroom_id.extend_from_slice(room_id.as_bytes());
 			increment(&self.userroomid_notificationcount, &userroom_id);
+
+			if let Some(thread_root) = thread_root {
+				userroom_id.push(0xFF);
+				userroom_id.extend_from_slice(thread_root.as_bytes());
+				increment(&self.userroomthreadid_notificationcount, &userroom_id);
+			}
 		}
 
 		for user in highlights {
@@ -289,6 +328,12 @@ pub(super) fn increment_notification_counts(
 			userroom_id.push(0xFF);
 			userroom_id.extend_from_slice(room_id.as_bytes());
 			increment(&self.userroomid_highlightcount, &userroom_id);
+
+			if let Some(thread_root) = thread_root {
+				userroom_id.push(0xFF);
+				userroom_id.extend_from_slice(thread_root.as_bytes());
+				increment(&self.userroomthreadid_highlightcount, &userroom_id);
+			}
 		}
 	}
 
@@ -1,12 +1,17 @@
 mod data;
+mod repair;
+mod state_batch;
+#[cfg(test)]
+mod tests;
 
 use std::{
 	borrow::Borrow,
 	cmp,
-	collections::{BTreeMap, HashSet},
+	collections::{BTreeMap, HashMap, HashSet},
 	fmt::Write,
 	iter::once,
-	sync::Arc,
+	sync::{Arc, Mutex, atomic::AtomicU64},
+	time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
@@ -16,7 +21,7 @@
 	matrix::{
 		Event,
 		pdu::{EventHash, PduBuilder, PduCount, PduEvent, gen_event_id},
-		state_res::{self, RoomVersion},
+		state_res::{self, RoomVersion, StateMap},
 	},
 	utils::{
 		self, IterStream, MutexMap, MutexMapGuard, ReadyExt, future::TryExtExt, stream::TryIgnore,
@@ -28,7 +33,7 @@
 };
 use ruma::{
 	CanonicalJsonObject, CanonicalJsonValue, EventId, OwnedEventId, OwnedRoomId, OwnedServerName,
-	RoomId, RoomVersionId, ServerName, UserId,
+	RoomId, RoomVersionId, ServerName, UInt, UserId,
 	api::federation,
 	canonical_json::to_canonical_value,
 	events::{
@@ -49,12 +54,10 @@
 use serde_json::value::{RawValue as RawJsonValue, to_raw_value};
 
 use self::data::Data;
-pub use self::data::PdusIterItem;
+pub use self::{data::PdusIterItem, state_batch::StateBatch};
 use crate::{
-	Dep, account_data, admin, appservice,
-	appservice::NamespaceRegex,
-	globals, pusher, rooms,
-	rooms::{short::ShortRoomId, state_compressor::CompressedState},
+	Dep, account_data, admin, appservice, globals, pusher, rooms,
+	rooms::{event_handler::Delivery, short::ShortRoomId, state_compressor::CompressedState},
 	sending, server_keys, users,
 };
 
@@ -80,18 +83,38 @@ struct ExtractBody {
 	body: Option<String>,
 }
 
+/// What is needed of the room to sign an event, looked up beforehand.
+struct Draft {
+	builder: PduBuilder,
+	room_version_id: RoomVersionId,
+	prev_events: Vec<OwnedEventId>,
+	depth: UInt,
+	auth_events: StateMap<PduEvent>,
+
+	/// The state event the drafted one replaces, for its unsigned fields.
+	prev_state: Option<PduEvent>,
+}
+
 pub struct Service {
 	services: Services,
 	db: Data,
 	pub mutex_insert: RoomMutexMap,
+
+	/// Event ID index entries restored since the server started.
+	pdu_id_repairs: AtomicU64,
+
+	/// When events were last searched for in a room without being found.
+	pdu_id_misses: Mutex<HashMap<(OwnedRoomId, OwnedEventId), Instant>>,
 }
 
 struct Services {
 	server: Arc<Server>,
 	account_data: Dep<account_data::Service>,
+	activity: Dep<rooms::activity::Service>,
 	appservice: Dep<appservice::Service>,
 	admin: Dep<admin::Service>,
 	alias: Dep<rooms::alias::Service>,
+	directory: Dep<rooms::directory::Service>,
 	globals: Dep<globals::Service>,
 	short: Dep<rooms::short::Service>,
 	state: Dep<rooms::state::Service>,
@@ -108,11 +131,17 @@ struct Services {
 	search: Dep<rooms::search::Service>,
 	spaces: Dep<rooms::spaces::Service>,
 	event_handler: Dep<rooms::event_handler::Service>,
+	state_growth: Dep<rooms::state_growth::Service>,
+	freeze: Dep<rooms::freeze::Service>,
 }
 
 type RoomMutexMap = MutexMap<OwnedRoomId, ()>;
 pub type RoomMutexGuard = MutexMapGuard<OwnedRoomId, ()>;
 
+/// How long each server asked for backfill has to answer before the next is
+/// asked instead.
+const BACKFILL_TIMEOUT: Duration = Duration::from_secs(15);
+
 #[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
@@ -120,9 +149,11 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 			services: Services {
 				server: args.server.clone(),
 				account_data: args.depend::<account_data::Service>("account_data"),
+				activity: args.depend::<rooms::activity::Service>("rooms::activity"),
 				appservice: args.depend::<appservice::Service>("appservice"),
 				admin: args.depend::<admin::Service>("admin"),
 				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
+				directory: args.depend::<rooms::directory::Service>("rooms::directory"),
 				globals: args.depend::<globals::Service>("globals"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
@@ -141,9 +172,13 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
 				event_handler: args
 					.depend::<rooms::event_handler::Service>("rooms::event_handler"),
+				state_growth: args.depend::<rooms::state_growth::Service>("rooms::state_growth"),
+				freeze: args.depend::<rooms::freeze::Service>("rooms::freeze"),
 			},
 			db: Data::new(&args),
 			mutex_insert: RoomMutexMap::new(),
+			pdu_id_repairs: AtomicU64::new(0),
+			pdu_id_misses: Mutex::default(),
 		}))
 	}
 
@@ -285,7 +320,7 @@ pub async fn append_pdu<'a, Leafs>(
 			.short
 			.get_shortroomid(&pdu.room_id)
 			.await
-			.map_err(|_| err!(Database("Room does not exist")))?;
+			.map_err(|_| Error::not_found(format!("Room {}", pdu.room_id)))?;
 
 		// Make unsigned fields correct. This is not properly documented in the spec,
 		// but state events need to have previous content in the unsigned field, so
@@ -367,6 +402,21 @@ pub async fn append_pdu<'a, Leafs>(
 
 		drop(insert_lock);
 
+		self.append_pdu_effects(pdu, pdu_id, shortroomid, count2)
+			.await?;
+
+		Ok(pdu_id)
+	}
+
+	/// Notifies, indexes and relays an event just appended to the timeline,
+	/// and updates what else follows from its kind.
+	async fn append_pdu_effects(
+		&self,
+		pdu: &PduEvent,
+		pdu_id: RawPduId,
+		shortroomid: ShortRoomId,
+		count2: PduCount,
+	) -> Result {
 		// See if the event matches any known pushers via power level
 		let power_levels: RoomPowerLevelsEventContent = self
 			.services
@@ -435,6 +485,14 @@ pub async fn append_pdu<'a, Leafs>(
 				}
 			}
 
+			if notify || highlight {
+				let muted = self.services.pusher.muted(user, pdu).await;
+				if muted.is_some_and(|muted| muted.exclude_from_unread) {
+					notify = false;
+					highlight = false;
+				}
+			}
+
 			if notify {
 				notifies.push(user.clone());
 			}
@@ -455,8 +513,22 @@ pub async fn append_pdu<'a, Leafs>(
 				.await;
 		}
 
-		self.db
-			.increment_notification_counts(&pdu.room_id, notifies, highlights);
+		let thread_root = pdu
+			.get_content::<ExtractRelatesTo>()
+			.ok()
+			.and_then(|content| match content.relates_to {
+				| Relation::Thread(thread) => Some(thread.event_id),
+				| _ => None,
+			});
+
+		self.db.increment_notification_counts(
+			&pdu.room_id,
+			thread_root.as_deref(),
+			notifies,
+			highlights,
+		);
+
+		self.services.activity.record(pdu);
 
 		match pdu.kind {
 			| TimelineEventType::RoomRedaction => {
@@ -493,13 +565,13 @@ pub async fn append_pdu<'a, Leafs>(
 			},
 			| TimelineEventType::SpaceChild =>
 				if let Some(_state_key) = &pdu.state_key {
-					self.services
-						.spaces
-						.roomid_spacehierarchy_cache
-						.lock()
-						.await
-						.remove(&pdu.room_id);
+					self.services.spaces.clear_room_hierarchy(&pdu.room_id);
 				},
+			| TimelineEventType::RoomName
+			| TimelineEventType::RoomTopic
+			| TimelineEventType::RoomCanonicalAlias => {
+				self.services.directory.update_folded(pdu).await;
+			},
 			| TimelineEventType::RoomMember => {
 				if let Some(state_key) = &pdu.state_key {
 					// if the state_key fails
@@ -513,6 +585,21 @@ pub async fn append_pdu<'a, Leafs>(
 						| _ => None,
 					};
 
+					if self.services.activity.is_enabled() {
+						let was_joined = self
+							.services
+							.state_cache
+							.is_joined(target_user_id, &pdu.room_id)
+							.await;
+
+						self.services.activity.record_membership(
+							&pdu.room_id,
+							target_user_id,
+							&content.membership,
+							was_joined,
+						);
+					}
+
 					// Update our membership info, we do this here incase a user is invited or
 					// knocked and immediately leaves we need the DB to record the invite or
 					// knock event for auth
@@ -588,42 +675,12 @@ pub async fn append_pdu<'a, Leafs>(
 				continue;
 			}
 
-			// If the RoomMember event has a non-empty state_key, it is targeted at someone.
-			// If it is our appservice user, we send this PDU to it.
-			if pdu.kind == TimelineEventType::RoomMember {
-				if let Some(state_key_uid) = &pdu
-					.state_key
-					.as_ref()
-					.and_then(|state_key| UserId::parse(state_key.as_str()).ok())
-				{
-					let appservice_uid = appservice.registration.sender_localpart.as_str();
-					if state_key_uid == &appservice_uid {
-						self.services
-							.sending
-							.send_pdu_appservice(appservice.registration.id.clone(), pdu_id)?;
-						continue;
-					}
-				}
-			}
-
-			let matching_users = |users: &NamespaceRegex| {
-				appservice.users.is_match(pdu.sender.as_str())
-					|| pdu.kind == TimelineEventType::RoomMember
-						&& pdu
-							.state_key
-							.as_ref()
-							.is_some_and(|state_key| users.is_match(state_key))
-			};
-			let matching_aliases = |aliases: NamespaceRegex| {
-				self.services
+			if appservice.is_event_match(pdu)
+				|| self
+					.services
 					.alias
-					.local_aliases_for_room(&pdu.room_id)
-					.ready_any(move |room_alias| aliases.is_match(room_alias.as_str()))
-			};
-
-			if matching_aliases(appservice.aliases.clone()).await
-				|| appservice.rooms.is_match(pdu.room_id.as_str())
-				|| matching_users(&appservice.users)
+					.appservice_alias_match(&pdu.room_id, appservice)
+					.await
 			{
 				self.services
 					.sending
@@ -631,7 +688,7 @@ pub async fn append_pdu<'a, Leafs>(
 			}
 		}
 
-		Ok(pdu_id)
+		Ok(())
 	}
 
 	pub async fn create_hash_and_sign_event(
@@ -642,15 +699,6 @@ pub async fn create_hash_and_sign_event(
 		_mutex_lock: &RoomMutexGuard, /* Take mutex guard to make sure users get the room
 		                               * state mutex */
 	) -> Result<(PduEvent, CanonicalJsonObject)> {
-		let PduBuilder {
-			event_type,
-			content,
-			unsigned,
-			state_key,
-			redacts,
-			timestamp,
-		} = pdu_builder;
-
 		let prev_events: Vec<OwnedEventId> = self
 			.services
 			.state
@@ -667,8 +715,9 @@ pub async fn create_hash_and_sign_event(
 			.get_room_version(room_id)
 			.await
 			.or_else(|_| {
-				if event_type == TimelineEventType::RoomCreate {
-					let content: RoomCreateEventContent = serde_json::from_str(content.get())?;
+				if pdu_builder.event_type == TimelineEventType::RoomCreate {
+					let content: RoomCreateEventContent =
+						serde_json::from_str(pdu_builder.content.get())?;
 					Ok(content.room_version)
 				} else {
 					Err(Error::InconsistentRoomState(
@@ -678,16 +727,49 @@ pub async fn create_hash_and_sign_event(
 				}
 			})?;
 
-		let room_version = RoomVersion::new(&room_version_id).expect("room version is supported");
-
 		let auth_events = self
 			.services
 			.state
-			.get_auth_events(room_id, &event_type, sender, state_key.as_deref(), &content)
+			.get_auth_events(
+				room_id,
+				&pdu_builder.event_type,
+				sender,
+				pdu_builder.state_key.as_deref(),
+				&pdu_builder.content,
+			)
 			.await?;
 
-		// Our depth is the maximum depth of prev_events + 1
-		let depth = prev_events
+		let depth = self.depth_after(&prev_events).await;
+
+		let prev_state = match &pdu_builder.state_key {
+			| Some(state_key) => self
+				.services
+				.state_accessor
+				.room_state_get(room_id, &pdu_builder.event_type.to_string().into(), state_key)
+				.await
+				.ok(),
+			| None => None,
+		};
+
+		self.sign_draft(
+			Draft {
+				builder: pdu_builder,
+				room_version_id,
+				prev_events,
+				depth,
+				auth_events,
+				prev_state,
+			},
+			sender,
+			room_id,
+		)
+		.await
+	}
+
+	/// Depth of an event with these prev_events: the greatest of theirs, plus
+	/// one.
+	async fn depth_after(&self, prev_events: &[OwnedEventId]) -> UInt {
+		prev_events
 			.iter()
 			.stream()
 			.map(Ok)
@@ -696,28 +778,47 @@ pub async fn create_hash_and_sign_event(
 			.ignore_err()
 			.ready_fold(uint!(0), cmp::max)
 			.await
-			.saturating_add(uint!(1));
+			.saturating_add(uint!(1))
+	}
+
+	/// Hashes and signs the drafted event once its auth events authorize it.
+	async fn sign_draft(
+		&self,
+		draft: Draft,
+		sender: &UserId,
+		room_id: &RoomId,
+	) -> Result<(PduEvent, CanonicalJsonObject)> {
+		let Draft {
+			builder:
+				PduBuilder {
+					event_type,
+					content,
+					unsigned,
+					state_key,
+					redacts,
+					timestamp,
+				},
+			room_version_id,
+			prev_events,
+			depth,
+			auth_events,
+			prev_state,
+		} = draft;
+
+		let room_version = RoomVersion::new(&room_version_id).expect("room version is supported");
 
 		let mut unsigned = unsigned.unwrap_or_default();
 
-		if let Some(state_key) = &state_key {
-			if let Ok(prev_pdu) = self
-				.services
-				.state_accessor
-				.room_state_get(room_id, &event_type.to_string().into(), state_key)
-				.await
-			{
-				unsigned.insert("prev_content".to_owned(), prev_pdu.get_content_as_value());
-				unsigned.insert(
-					"prev_sender".to_owned(),
-					serde_json::to_value(&prev_pdu.sender)
-						.expect("UserId::to_value always works"),
-				);
-				unsigned.insert(
-					"replaces_state".to_owned(),
-					serde_json::to_value(&prev_pdu.event_id).expect("EventId is valid json"),
-				);
-			}
+		if let Some(prev_pdu) = prev_state {
+			unsigned.insert("prev_content".to_owned(), prev_pdu.get_content_as_value());
+			unsigned.insert(
+				"prev_sender".to_owned(),
+				serde_json::to_value(&prev_pdu.sender).expect("UserId::to_value always works"),
+			);
+			unsigned.insert(
+				"replaces_state".to_owned(),
+				serde_json::to_value(&prev_pdu.event_id).expect("EventId is valid json"),
+			);
 		}
 
 		let mut pdu = PduEvent {
@@ -752,23 +853,7 @@ pub async fn create_hash_and_sign_event(
 			signatures: None,
 		};
 
-		let auth_fetch = |k: &StateEventType, s: &str| {
-			let key = (k.clone(), s.into());
-			ready(auth_events.get(&key))
-		};
-
-		let auth_check = state_res::auth_check(
-			&room_version,
-			&pdu,
-			None, // TODO: third_party_invite
-			auth_fetch,
-		)
-		.await
-		.map_err(|e| err!(Request(Forbidden(warn!("Auth check failed: {e:?}")))))?;
-
-		if !auth_check {
-			return Err!(Request(Forbidden("Event is not authorized.")));
-		}
+		authorize(&room_version, &pdu, &auth_events).await?;
 
 		// Hash and sign
 		let mut pdu_json = utils::to_canonical_object(&pdu).map_err(|e| {
@@ -834,8 +919,41 @@ pub async fn build_and_append_pdu(
 			.create_hash_and_sign_event(pdu_builder, sender, room_id, state_lock)
 			.await?;
 
+		self.check_local_pdu(&pdu, sender).await?;
+
+		// We append to state before appending the pdu, so we don't have a moment in
+		// time with the pdu without it's state. This is okay because append_pdu can't
+		// fail.
+		let statehashid = self.services.state.append_to_state(&pdu).await?;
+
+		let pdu_id = self
+			.append_pdu(
+				&pdu,
+				pdu_json,
+				// Since this PDU references all pdu_leaves we can update the leaves
+				// of the room
+				once(pdu.event_id.borrow()),
+				state_lock,
+			)
+			.boxed()
+			.await?;
+
+		// We set the room state after inserting the pdu, so that we never have a moment
+		// in time where events in the current room state do not exist
+		self.services
+			.state
+			.set_room_state(&pdu.room_id, statehashid, state_lock);
+
+		self.send_local_pdu(&pdu, &pdu_id).await?;
+
+		Ok(pdu.event_id)
+	}
+
+	/// Checks a locally built event against what the server itself allows,
+	/// beyond the auth rules, before it is appended.
+	async fn check_local_pdu(&self, pdu: &PduEvent, sender: &UserId) -> Result {
 		if self.services.admin.is_admin_room(&pdu.room_id).await {
-			self.check_pdu_for_admin_room(&pdu, sender).boxed().await?;
+			self.check_pdu_for_admin_room(pdu, sender).boxed().await?;
 		}
 
 		// If redaction event is not authorized, do not append it to the timeline
@@ -893,29 +1011,14 @@ pub async fn build_and_append_pdu(
 			}
 		}
 
-		// We append to state before appending the pdu, so we don't have a moment in
-		// time with the pdu without it's state. This is okay because append_pdu can't
-		// fail.
-		let statehashid = self.services.state.append_to_state(&pdu).await?;
+		self.services.freeze.check_local(pdu).await?;
+		self.services.state_growth.check_local(pdu)?;
 
-		let pdu_id = self
-			.append_pdu(
-				&pdu,
-				pdu_json,
-				// Since this PDU references all pdu_leaves we can update the leaves
-				// of the room
-				once(pdu.event_id.borrow()),
-				state_lock,
-			)
-			.boxed()
-			.await?;
-
-		// We set the room state after inserting the pdu, so that we never have a moment
-		// in time where events in the current room state do not exist
-		self.services
-			.state
-			.set_room_state(&pdu.room_id, statehashid, state_lock);
+		Ok(())
+	}
 
+	/// Sends a locally appended event to the other servers in the room.
+	async fn send_local_pdu(&self, pdu: &PduEvent, pdu_id: &RawPduId) -> Result {
 		let mut servers: HashSet<OwnedServerName> = self
 			.services
 			.state_cache
@@ -942,10 +1045,8 @@ pub async fn build_and_append_pdu(
 
 		self.services
 			.sending
-			.send_pdu_servers(servers.iter().map(AsRef::as_ref).stream(), &pdu_id)
-			.await?;
-
-		Ok(pdu.event_id)
+			.send_pdu_servers(servers.iter().map(AsRef::as_ref).stream(), pdu_id)
+			.await
 	}
 
 	/// Append the incoming event setting the state snapshot to the state from
@@ -1091,83 +1192,36 @@ pub async fn backfill_if_required(&self, room_id: &RoomId, from: PduCount) -> Re
 			return Ok(());
 		}
 
-		let power_levels: RoomPowerLevelsEventContent = self
+		let servers = self
 			.services
-			.state_accessor
-			.room_state_get_content(room_id, &StateEventType::RoomPowerLevels, "")
-			.await
-			.unwrap_or_default();
-
-		let room_mods = power_levels.users.iter().filter_map(|(user_id, level)| {
-			if level > &power_levels.users_default
-				&& !self.services.globals.user_is_local(user_id)
-			{
-				Some(user_id.server_name())
-			} else {
-				None
-			}
-		});
-
-		let canonical_room_alias_server = once(
-			self.services
-				.state_accessor
-				.get_canonical_alias(room_id)
-				.await,
-		)
-		.filter_map(Result::ok)
-		.map(|alias| alias.server_name().to_owned())
-		.stream();
-
-		let mut servers = room_mods
-			.stream()
-			.map(ToOwned::to_owned)
-			.chain(canonical_room_alias_server)
-			.chain(
-				self.services
-					.server
-					.config
-					.trusted_servers
-					.iter()
-					.map(ToOwned::to_owned)
-					.stream(),
-			)
-			.ready_filter(|server_name| !self.services.globals.server_is_ours(server_name))
-			.filter_map(|server_name| async move {
-				self.services
-					.state_cache
-					.server_in_room(&server_name, room_id)
-					.await
-					.then_some(server_name)
-			})
-			.boxed();
+			.event_handler
+			.candidate_servers(room_id, None)
+			.await;
 
-		while let Some(ref backfill_server) = servers.next().await {
-			info!("Asking {backfill_server} for backfill");
-			let response = self
-				.services
-				.sending
-				.send_federation_request(
-					backfill_server,
+		let answer = self
+			.services
+			.event_handler
+			.ask_servers(room_id, &servers, BACKFILL_TIMEOUT, |server| {
+				info!("Asking {server} for backfill");
+				self.services.sending.send_federation_request(
+					server,
 					federation::backfill::get_backfill::v1::Request {
 						room_id: room_id.to_owned(),
 						v: vec![first_pdu.1.event_id.clone()],
 						limit: uint!(100),
 					},
 				)
-				.await;
-			match response {
-				| Ok(response) => {
-					for pdu in response.pdus {
-						if let Err(e) = self.backfill_pdu(backfill_server, pdu).boxed().await {
-							debug_warn!("Failed to add backfilled pdu in room {room_id}: {e}");
-						}
-					}
-					return Ok(());
-				},
-				| Err(e) => {
-					warn!("{backfill_server} failed to provide backfill for room {room_id}: {e}");
-				},
+			})
+			.await;
+
+		if let Some((backfill_server, response)) = answer {
+			for pdu in response.pdus {
+				if let Err(e) = self.backfill_pdu(backfill_server, pdu).boxed().await {
+					debug_warn!("Failed to add backfilled pdu in room {room_id}: {e}");
+				}
 			}
+
+			return Ok(());
 		}
 
 		info!("No servers could backfill, but backfill was needed in room {room_id}");
@@ -1195,7 +1249,7 @@ pub async fn backfill_pdu(&self, origin: &ServerName, pdu: Box<RawJsonValue>) ->
 
 		self.services
 			.event_handler
-			.handle_incoming_pdu(origin, &room_id, &event_id, value, false)
+			.handle_incoming_pdu(origin, &room_id, &event_id, value, false, Delivery::Backfill)
 			.boxed()
 			.await?;
 
@@ -1305,3 +1359,30 @@ async fn check_pdu_for_admin_room(&self, pdu: &PduEvent, sender: &UserId) -> Res
 
 	Ok(())
 }
+
+/// Checks the event against the auth rules with the auth events given.
+async fn authorize(
+	room_version: &RoomVersion,
+	pdu: &PduEvent,
+	auth_events: &StateMap<PduEvent>,
+) -> Result {
+	let auth_fetch = |k: &StateEventType, s: &str| {
+		let key = (k.clone(), s.into());
+		ready(auth_events.get(&key))
+	};
+
+	let auth_check = state_res::auth_check(
+		room_version,
+		pdu,
+		None, // TODO: third_party_invite
+		auth_fetch,
+	)
+	.await
+	.map_err(|e| err!(Request(Forbidden(warn!("Auth check failed: {e:?}")))))?;
+
+	if !auth_check {
+		return Err!(Request(Forbidden("Event is not authorized.")));
+	}
+
+	Ok(())
+}
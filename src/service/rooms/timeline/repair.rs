@@ -0,0 +1,153 @@
+//! Event ID index repair
+//!
+//! Old bugs and crashes could leave an event stored in a room's timeline
+//! without its `eventid_pduid` entry, so looking it up by ID fails though it
+//! is plainly in the room. Lookups of events the caller knows to be in a room
+//! fall back to searching its most recent events, restoring the entry when the
+//! event is found there; the `check event-index` admin command restores those
+//! of a whole room the same way. Searches which found nothing are not repeated
+//! for a while, so made up event IDs cannot have the room searched each time.
+
+use std::{
+	sync::atomic::Ordering,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{
+	PduCount, Result,
+	utils::{ReadyExt, stream::TryIgnore},
+	warn,
+};
+use futures::{StreamExt, pin_mut};
+use ruma::{EventId, OwnedEventId, RoomId};
+
+use super::{PduId, RawPduId};
+
+/// Most recent events of a room searched for an event missing from the index.
+const SEARCH_LIMIT: usize = 4096;
+
+/// How long a search which did not find its event is not repeated.
+const MISS_TTL: Duration = Duration::from_secs(60);
+
+/// Most searches which did not find their event remembered at once.
+const MISS_LIMIT: usize = 4096;
+
+impl super::Service {
+	/// Returns the pdu's id, searching the room's recent events for it when it
+	/// is missing from the index, which is then restored.
+	pub async fn get_pdu_id_in_room(
+		&self,
+		room_id: &RoomId,
+		event_id: &EventId,
+	) -> Result<RawPduId> {
+		let missing = match self.get_pdu_id(event_id).await {
+			| Err(e)
+				if e.is_not_found()
+					&& self.services.server.config.rebuild_missing_event_index =>
+				e,
+			| found => return found,
+		};
+
+		let Some(pdu_id) = self.search_pdu_id(room_id, event_id).await else {
+			return Err(missing);
+		};
+
+		self.repair_pdu_id(room_id, event_id, &pdu_id);
+
+		Ok(pdu_id)
+	}
+
+	/// Returns the `count` of the pdu's id as [`Self::get_pdu_id_in_room`]
+	/// finds it.
+	pub async fn get_pdu_count_in_room(
+		&self,
+		room_id: &RoomId,
+		event_id: &EventId,
+	) -> Result<PduCount> {
+		self.get_pdu_id_in_room(room_id, event_id)
+			.await
+			.map(|pdu_id| pdu_id.pdu_count())
+	}
+
+	/// Restores the index entries missing for every event of the room's
+	/// timeline. Returns how many events there are and how many entries were
+	/// restored.
+	pub async fn repair_room_pdu_ids(&self, room_id: &RoomId) -> Result<(usize, usize)> {
+		let shortroomid = self.services.short.get_shortroomid(room_id).await?;
+		let events: Vec<(PduCount, OwnedEventId)> = self
+			.pdus(None, room_id, None)
+			.ignore_err()
+			.map(|(count, pdu)| (count, pdu.event_id))
+			.collect()
+			.await;
+
+		let mut repaired = 0_usize;
+		for (count, event_id) in &events {
+			if self
+				.get_pdu_id(event_id)
+				.await
+				.is_err_and(|e| e.is_not_found())
+			{
+				let pdu_id: RawPduId = PduId { shortroomid, shorteventid: *count }.into();
+				self.repair_pdu_id(room_id, event_id, &pdu_id);
+				repaired = repaired.saturating_add(1);
+			}
+		}
+
+		Ok((events.len(), repaired))
+	}
+
+	/// Index entries restored since the server started.
+	#[must_use]
+	pub fn pdu_id_repairs(&self) -> u64 { self.pdu_id_repairs.load(Ordering::Relaxed) }
+
+	/// Whether the event was searched for in the room without being found
+	/// shortly before.
+	pub(super) fn recently_missed(&self, room_id: &RoomId, event_id: &EventId) -> bool {
+		let key = (room_id.to_owned(), event_id.to_owned());
+		self.pdu_id_misses
+			.lock()
+			.expect("locked")
+			.get(&key)
+			.is_some_and(|missed| missed.elapsed() < MISS_TTL)
+	}
+
+	/// The id the event is stored at among the room's most recent events.
+	async fn search_pdu_id(&self, room_id: &RoomId, event_id: &EventId) -> Option<RawPduId> {
+		if self.recently_missed(room_id, event_id) {
+			return None;
+		}
+
+		let shortroomid = self.services.short.get_shortroomid(room_id).await.ok()?;
+		let found = self
+			.pdus_rev(None, room_id, None)
+			.ignore_err()
+			.take(SEARCH_LIMIT)
+			.ready_filter(|(_, pdu)| *pdu.event_id == *event_id);
+
+		pin_mut!(found);
+		let Some((count, _)) = found.next().await else {
+			self.remember_miss(room_id, event_id);
+			return None;
+		};
+
+		Some(PduId { shortroomid, shorteventid: count }.into())
+	}
+
+	fn remember_miss(&self, room_id: &RoomId, event_id: &EventId) {
+		let mut misses = self.pdu_id_misses.lock().expect("locked");
+		if misses.len() >= MISS_LIMIT {
+			misses.retain(|_, missed| missed.elapsed() < MISS_TTL);
+		}
+
+		if misses.len() < MISS_LIMIT {
+			misses.insert((room_id.to_owned(), event_id.to_owned()), Instant::now());
+		}
+	}
+
+	fn repair_pdu_id(&self, room_id: &RoomId, event_id: &EventId, pdu_id: &RawPduId) {
+		self.db.put_pdu_id(event_id, pdu_id);
+		self.pdu_id_repairs.fetch_add(1, Ordering::Relaxed);
+		warn!(%room_id, %event_id, "Restored the missing event ID index entry of a stored event");
+	}
+}
@@ -0,0 +1,285 @@
+//! State batches
+//!
+//! The state events of a batch are built in order, each against the room's
+//! state with the events before it in the batch applied, so a power level
+//! change early in the batch authorizes or forbids the events after it.
+//! Nothing is appended until every event is built and authorized; the events,
+//! the state before each and the room's new state are then written at once.
+
+use std::{collections::HashMap, iter::once};
+
+use conduwuit::{
+	Err, Error, Result, at, implement,
+	matrix::{PduBuilder, PduCount, PduEvent, PduId, RawPduId, StateMap, TypeStateKey},
+	state_res,
+};
+use futures::StreamExt;
+use ruma::{CanonicalJsonObject, OwnedEventId, RoomId, RoomVersionId, UInt, UserId, uint};
+
+use super::{Draft, RoomMutexGuard};
+
+/// The events of a state batch built so far, in order.
+#[derive(Default)]
+pub struct StateBatch {
+	events: Vec<(PduEvent, CanonicalJsonObject)>,
+
+	/// Index of the latest event of the batch with each type and state key.
+	state: HashMap<TypeStateKey, usize>,
+}
+
+impl StateBatch {
+	#[inline]
+	#[must_use]
+	pub fn len(&self) -> usize { self.events.len() }
+
+	#[inline]
+	#[must_use]
+	pub fn is_empty(&self) -> bool { self.events.is_empty() }
+
+	/// The batch's state event last setting the type and state key.
+	pub(super) fn get(&self, key: &TypeStateKey) -> Option<&PduEvent> {
+		self.state
+			.get(key)
+			.and_then(|&index| self.events.get(index))
+			.map(at!(0))
+	}
+
+	/// The auth events of the next event of the batch: those of `state`, the
+	/// room's state of interest before the batch, with the batch applied over
+	/// it.
+	pub(super) fn auth_events(
+		&self,
+		mut state: StateMap<PduEvent>,
+		auth_types: &[TypeStateKey],
+	) -> StateMap<PduEvent> {
+		for (key, &index) in &self.state {
+			if let Some((pdu, _)) = self.events.get(index) {
+				state.insert(key.clone(), pdu.clone());
+			}
+		}
+
+		state.retain(|key, _| auth_types.contains(key));
+		state
+	}
+
+	pub(super) fn push(&mut self, pdu: PduEvent, pdu_json: CanonicalJsonObject) {
+		if let Some(state_key) = &pdu.state_key {
+			let key = (pdu.kind.to_string().into(), state_key.clone());
+			self.state.insert(key, self.events.len());
+		}
+
+		self.events.push((pdu, pdu_json));
+	}
+}
+
+/// Builds, authorizes and checks the state events in order, each against the
+/// room's state with the batch so far applied. On failure nothing has been
+/// appended, and the index of the event which failed is given with the error.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, builders, state_lock), fields(len = builders.len()), level = "debug")]
+pub async fn build_state_batch(
+	&self,
+	builders: Vec<PduBuilder>,
+	sender: &UserId,
+	room_id: &RoomId,
+	state_lock: &RoomMutexGuard,
+) -> Result<StateBatch, (usize, Error)> {
+	let room_version_id = self
+		.services
+		.state
+		.get_room_version(room_id)
+		.await
+		.map_err(|e| (0, e))?;
+
+	let mut prev_events: Vec<OwnedEventId> = self
+		.services
+		.state
+		.get_forward_extremities(room_id)
+		.take(20)
+		.map(Into::into)
+		.collect()
+		.await;
+
+	let mut depth = self.depth_after(&prev_events).await;
+	let mut batch = StateBatch::default();
+	for (index, builder) in builders.into_iter().enumerate() {
+		let (pdu, pdu_json) = self
+			.build_batch_event(
+				builder,
+				&batch,
+				sender,
+				room_id,
+				&room_version_id,
+				prev_events,
+				depth,
+				state_lock,
+			)
+			.await
+			.map_err(|e| (index, e))?;
+
+		// each event follows the one before it in the batch
+		prev_events = vec![pdu.event_id.clone()];
+		depth = pdu.depth.saturating_add(uint!(1));
+		batch.push(pdu, pdu_json);
+	}
+
+	Ok(batch)
+}
+
+#[implement(super::Service)]
+#[allow(clippy::too_many_arguments)]
+async fn build_batch_event(
+	&self,
+	builder: PduBuilder,
+	batch: &StateBatch,
+	sender: &UserId,
+	room_id: &RoomId,
+	room_version_id: &RoomVersionId,
+	prev_events: Vec<OwnedEventId>,
+	depth: UInt,
+	_state_lock: &RoomMutexGuard,
+) -> Result<(PduEvent, CanonicalJsonObject)> {
+	let Some(state_key) = builder.state_key.clone() else {
+		return Err!(Request(InvalidParam("Only state events can be sent in a state batch.")));
+	};
+
+	let own_key: TypeStateKey = (builder.event_type.to_string().into(), state_key);
+	let auth_types = state_res::auth_types_for_event(
+		&builder.event_type,
+		sender,
+		Some(&own_key.1),
+		&builder.content,
+	)?;
+
+	let mut state = StateMap::new();
+	for key in auth_types.iter().chain(once(&own_key)) {
+		let (event_type, state_key) = key;
+		if let Ok(pdu) = self
+			.services
+			.state_accessor
+			.room_state_get(room_id, event_type, state_key)
+			.await
+		{
+			state.insert(key.clone(), pdu);
+		}
+	}
+
+	let prev_state = batch.get(&own_key).or_else(|| state.get(&own_key)).cloned();
+
+	let (pdu, pdu_json) = self
+		.sign_draft(
+			Draft {
+				builder,
+				room_version_id: room_version_id.clone(),
+				prev_events,
+				depth,
+				auth_events: batch.auth_events(state, &auth_types),
+				prev_state,
+			},
+			sender,
+			room_id,
+		)
+		.await?;
+
+	self.check_local_pdu(&pdu, sender).await?;
+
+	Ok((pdu, pdu_json))
+}
+
+/// Appends a built state batch to the room. The events, the state before each
+/// and the room's state after the last, with the state diffs they need, are
+/// written in one batch of writes;
+/// the events are then notified about and sent on as any other. Returns the
+/// event IDs in order.
+#[implement(super::Service)]
+#[tracing::instrument(skip_all, fields(len = batch.len()), level = "debug")]
+pub async fn append_state_batch(
+	&self,
+	batch: StateBatch,
+	room_id: &RoomId,
+	state_lock: &RoomMutexGuard,
+) -> Result<Vec<OwnedEventId>> {
+	let (pdus, pdu_jsons): (Vec<_>, Vec<_>) = batch.events.into_iter().unzip();
+	let Some(last) = pdus.last() else {
+		return Ok(Vec::new());
+	};
+
+	// Coalesce database writes for the remainder of this scope.
+	let _cork = self.db.db.cork_and_flush();
+
+	let shortroomid = self
+		.services
+		.short
+		.get_shortroomid(room_id)
+		.await
+		.map_err(|_| Error::not_found(format!("Room {room_id}")))?;
+
+	let mut writes = self.db.write_batch();
+	let shortstatehashes = self
+		.services
+		.state
+		.append_batch_to_state(&mut writes, room_id, &pdus)
+		.await?;
+
+	let Some((&room_state, before)) = shortstatehashes.split_last() else {
+		return Err!("No state saved for the state batch");
+	};
+
+	let mut states = Vec::with_capacity(pdus.len());
+	for (pdu, &before) in pdus.iter().zip(before) {
+		let shorteventid = self
+			.services
+			.short
+			.get_or_create_shorteventid(&pdu.event_id)
+			.await;
+
+		states.push((shorteventid, before));
+	}
+
+	for pdu in &pdus {
+		self.services
+			.pdu_metadata
+			.mark_as_referenced(room_id, pdu.prev_events.iter().map(AsRef::as_ref));
+	}
+
+	let insert_lock = self.mutex_insert.lock(room_id).await;
+
+	let count1 = self.services.globals.next_count()?;
+	self.services
+		.read_receipt
+		.private_read_set(room_id, &last.sender, count1);
+	self.services
+		.user
+		.reset_notification_counts(&last.sender, room_id);
+
+	let mut pdu_ids = Vec::with_capacity(pdus.len());
+	for _ in &pdus {
+		let count = PduCount::Normal(self.services.globals.next_count()?);
+		let pdu_id: RawPduId = PduId { shortroomid, shorteventid: count }.into();
+		pdu_ids.push((count, pdu_id));
+	}
+
+	for ((pdu, pdu_json), (_, pdu_id)) in pdus.iter().zip(&pdu_jsons).zip(&pdu_ids) {
+		self.db
+			.stage_pdu(&mut writes, pdu_id, &pdu.event_id, pdu_json);
+	}
+
+	self.services
+		.state
+		.stage_batch(&mut writes, room_id, &states, &last.event_id, room_state, state_lock)
+		.await?;
+
+	writes.commit();
+	drop(insert_lock);
+
+	for (pdu, &(count, pdu_id)) in pdus.iter().zip(&pdu_ids) {
+		self.append_pdu_effects(pdu, pdu_id, shortroomid, count)
+			.await?;
+	}
+
+	for (pdu, (_, pdu_id)) in pdus.iter().zip(&pdu_ids) {
+		self.send_local_pdu(pdu, pdu_id).await?;
+	}
+
+	Ok(pdus.into_iter().map(|pdu| pdu.event_id).collect())
+}
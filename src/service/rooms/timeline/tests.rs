@@ -0,0 +1,258 @@
+use conduwuit::{
+	config::Figment,
+	matrix::{PduBuilder, PduEvent, StateMap, TypeStateKey},
+	state_res::{RoomVersion, auth_types_for_event},
+	utils::stream::TryIgnore,
+};
+use futures::StreamExt;
+use ruma::{
+	CanonicalJsonObject, OwnedEventId, RoomVersionId,
+	events::{
+		StateEventType, TimelineEventType,
+		room::{power_levels::RoomPowerLevelsEventContent, topic::RoomTopicEventContent},
+	},
+	int, owned_event_id,
+};
+use serde_json::{Value, json};
+
+use super::{authorize, state_batch::StateBatch};
+use crate::testing::Test;
+
+const ROOM: &str = "!room:example.org";
+const ALICE: &str = "@alice:example.org";
+
+fn state_event(id: &str, kind: &str, state_key: &str, content: Value) -> PduEvent {
+	serde_json::from_value(json!({
+		"event_id": format!("${id}"),
+		"room_id": ROOM,
+		"sender": ALICE,
+		"origin_server_ts": 1,
+		"type": kind,
+		"content": content,
+		"state_key": state_key,
+		"prev_events": [],
+		"depth": 1,
+		"auth_events": [],
+		"hashes": { "sha256": "" },
+	}))
+	.expect("valid pdu")
+}
+
+fn key(pdu: &PduEvent) -> TypeStateKey {
+	let state_key = pdu.state_key.clone().expect("state event");
+	(pdu.kind.to_string().into(), state_key)
+}
+
+/// A room Alice created and moderates, with state events needing power 50.
+fn room_state() -> StateMap<PduEvent> {
+	[
+		state_event(
+			"create",
+			"m.room.create",
+			"",
+			json!({ "creator": ALICE, "room_version": "10" }),
+		),
+		state_event("join", "m.room.member", ALICE, json!({ "membership": "join" })),
+		state_event(
+			"power",
+			"m.room.power_levels",
+			"",
+			json!({ "users": { ALICE: 100 }, "state_default": 50 }),
+		),
+	]
+	.into_iter()
+	.map(|pdu| (key(&pdu), pdu))
+	.collect()
+}
+
+/// Authorizes the next event of the batch as building the batch does.
+async fn authorize_next(batch: &StateBatch, pdu: &PduEvent) -> conduwuit::Result {
+	let room_version = RoomVersion::new(&RoomVersionId::V10).expect("supported");
+	let state_key = pdu.state_key.as_deref();
+	let auth_types = auth_types_for_event(&pdu.kind, &pdu.sender, state_key, &pdu.content)
+		.expect("auth types");
+
+	let auth_events = batch.auth_events(room_state(), &auth_types);
+	authorize(&room_version, pdu, &auth_events).await
+}
+
+fn demote() -> PduEvent {
+	state_event(
+		"demote",
+		"m.room.power_levels",
+		"",
+		json!({ "users": { ALICE: 50 }, "state_default": 100 }),
+	)
+}
+
+fn name() -> PduEvent { state_event("name", "m.room.name", "", json!({ "name": "Room" })) }
+
+#[tokio::test]
+async fn state_batch_authorized_alone() {
+	let batch = StateBatch::default();
+	authorize_next(&batch, &name())
+		.await
+		.expect("name is authorized");
+}
+
+#[tokio::test]
+async fn state_batch_power_levels_apply_in_order() {
+	let mut batch = StateBatch::default();
+	authorize_next(&batch, &demote())
+		.await
+		.expect("demoting is authorized");
+
+	batch.push(demote(), CanonicalJsonObject::new());
+	assert_eq!(batch.len(), 1);
+
+	authorize_next(&batch, &name())
+		.await
+		.expect_err("name is not authorized after the demotion");
+}
+
+#[test]
+fn state_batch_latest_of_key() {
+	let mut batch = StateBatch::default();
+	let power_key = (StateEventType::RoomPowerLevels, "".into());
+	assert!(batch.get(&power_key).is_none());
+
+	batch.push(demote(), CanonicalJsonObject::new());
+	let promote = state_event(
+		"promote",
+		"m.room.power_levels",
+		"",
+		json!({ "users": { ALICE: 100 }, "state_default": 50 }),
+	);
+
+	batch.push(promote, CanonicalJsonObject::new());
+	assert_eq!(batch.len(), 2);
+
+	let latest = batch.get(&power_key).expect("in the batch");
+	assert_eq!(latest.event_id.as_str(), "$promote");
+	assert_eq!(latest.kind, TimelineEventType::RoomPowerLevels);
+
+	let auth_events = batch.auth_events(room_state(), &[power_key.clone()]);
+	assert_eq!(auth_events.len(), 1);
+	assert_eq!(auth_events[&power_key].event_id.as_str(), "$promote");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_event_index_repaired() {
+	let test = Test::start(Figment::new().join(("server_name", "example.org")))
+		.await
+		.expect("started");
+
+	let services = &test.services;
+	let timeline = &services.rooms.timeline;
+	let room_id = services.admin.get_admin_room().await.expect("admin room");
+	let events: Vec<OwnedEventId> = timeline
+		.pdus(None, &room_id, None)
+		.ignore_err()
+		.map(|(_, pdu)| pdu.event_id)
+		.collect()
+		.await;
+
+	let (first, last) = (&events[0], &events[events.len().saturating_sub(1)]);
+	assert_ne!(first, last, "room has several events");
+
+	let expected = timeline.get_pdu_id(last).await.expect("indexed");
+	let index = &services.db["eventid_pduid"];
+	index.remove(first.as_bytes());
+	index.remove(last.as_bytes());
+	assert!(timeline.get_pdu_id(last).await.is_err(), "index entry missing");
+
+	let repairs = timeline.pdu_id_repairs();
+	let found = timeline
+		.get_pdu_id_in_room(&room_id, last)
+		.await
+		.expect("found among the recent events");
+
+	assert_eq!(found, expected);
+	assert_eq!(timeline.get_pdu_id(last).await.ok(), Some(expected), "index entry restored");
+	assert_eq!(timeline.pdu_id_repairs(), repairs.saturating_add(1));
+
+	let made_up = owned_event_id!("$made-up");
+	assert!(
+		timeline
+			.get_pdu_id_in_room(&room_id, &made_up)
+			.await
+			.is_err()
+	);
+	assert!(timeline.recently_missed(&room_id, &made_up), "search not repeated for a while");
+
+	let repaired = timeline
+		.repair_room_pdu_ids(&room_id)
+		.await
+		.expect("repaired the room");
+
+	assert_eq!(repaired, (events.len(), 1), "only the entry still missing restored");
+	assert!(timeline.get_pdu_id(first).await.is_ok(), "index entry restored");
+
+	test.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn state_batch_failure_persists_nothing() {
+	let test = Test::start(Figment::new().join(("server_name", "example.org")))
+		.await
+		.expect("started");
+
+	let services = &test.services;
+	let (timeline, state) = (&services.rooms.timeline, &services.rooms.state);
+	let sender = &services.globals.server_user;
+	let room_id = services.admin.get_admin_room().await.expect("admin room");
+	let persisted = || async {
+		let events: Vec<OwnedEventId> = timeline
+			.pdus(None, &room_id, None)
+			.ignore_err()
+			.map(|(_, pdu)| pdu.event_id)
+			.collect()
+			.await;
+
+		let extremities: Vec<OwnedEventId> = state
+			.get_forward_extremities(&room_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let statediffs = services.db["shortstatehash_statediff"]
+			.raw_keys()
+			.ignore_err()
+			.count()
+			.await;
+
+		let shortstatehash = state.get_room_shortstatehash(&room_id).await.ok();
+
+		(events, extremities, statediffs, shortstatehash)
+	};
+
+	// the server user gives up its power, so the topic after it is refused
+	let mut power_levels: RoomPowerLevelsEventContent = services
+		.rooms
+		.state_accessor
+		.room_state_get_content(&room_id, &StateEventType::RoomPowerLevels, "")
+		.await
+		.expect("power levels");
+
+	power_levels.users.insert(sender.clone(), int!(0));
+	let builders = vec![
+		PduBuilder::state(String::new(), &power_levels),
+		PduBuilder::state(String::new(), &RoomTopicEventContent::new("Refused".into())),
+	];
+
+	let before = persisted().await;
+	let state_lock = state.mutex.lock(&room_id).await;
+	let built = timeline
+		.build_state_batch(builders, sender, &room_id, &state_lock)
+		.await;
+
+	drop(state_lock);
+	let Err((index, _)) = built else {
+		panic!("batch authorized after the sender gave up its power");
+	};
+
+	assert_eq!(index, 1, "refused at the topic");
+	assert_eq!(persisted().await, before, "nothing of the batch persisted");
+
+	test.stop().await;
+}
@@ -1,7 +1,19 @@
-use std::{collections::BTreeMap, sync::Arc};
+#[cfg(test)]
+mod tests;
+mod typers;
+
+use std::{
+	collections::{BTreeMap, HashSet},
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
 
+use async_trait::async_trait;
 use conduwuit::{
-	Result, Server, debug_info, trace,
+	Result, Server, debug_info,
+	result::LogErr,
+	trace,
 	utils::{self, IterStream},
 };
 use futures::StreamExt;
@@ -10,18 +22,26 @@
 	api::federation::transactions::edu::{Edu, TypingContent},
 	events::SyncEphemeralRoomEvent,
 };
-use tokio::sync::{RwLock, broadcast};
+use tokio::{
+	sync::{Notify, RwLock, broadcast},
+	time::sleep_until,
+};
 
+use self::typers::Typers;
 use crate::{Dep, globals, sending, sending::EduBuf, users};
 
+/// Typing notifications are only kept in memory; they expire within seconds
+/// and are meaningless after a restart. Expired users are removed by the
+/// worker when their timeout is reached rather than when next looked up.
 pub struct Service {
 	server: Arc<Server>,
 	services: Services,
-	/// u64 is unix timestamp of timeout
-	pub typing: RwLock<BTreeMap<OwnedRoomId, BTreeMap<OwnedUserId, u64>>>,
-	/// timestamp of the last change to typing users
-	pub last_typing_update: RwLock<BTreeMap<OwnedRoomId, u64>>,
-	pub typing_update_sender: broadcast::Sender<OwnedRoomId>,
+	typers: Mutex<Typers>,
+	/// count of the last change to the typing users of each room
+	last_typing_update: RwLock<BTreeMap<OwnedRoomId, u64>>,
+	typing_update_sender: broadcast::Sender<OwnedRoomId>,
+	/// wakes the worker when the earliest expiry may have changed
+	expiry_changed: Notify,
 }
 
 struct Services {
@@ -30,6 +50,7 @@ struct Services {
 	users: Dep<users::Service>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
@@ -39,18 +60,57 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				sending: args.depend::<sending::Service>("sending"),
 				users: args.depend::<users::Service>("users"),
 			},
-			typing: RwLock::new(BTreeMap::new()),
+			typers: Mutex::new(Typers::default()),
 			last_typing_update: RwLock::new(BTreeMap::new()),
 			typing_update_sender: broadcast::channel(100).0,
+			expiry_changed: Notify::new(),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		while self.server.running() {
+			let next_expiry = self.typers.lock().expect("locked").next_expiry();
+			let expired = async {
+				match next_expiry {
+					| Some(until) => sleep_until(until.into()).await,
+					| None => std::future::pending().await,
+				}
+			};
+
+			tokio::select! {
+				() = self.expiry_changed.notified() => continue,
+				() = expired => (),
+			}
+
+			self.typings_expire(Instant::now()).await.log_err().ok();
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.expiry_changed.notify_one(); }
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let (rooms, users) = {
+			let typers = self.typers.lock().expect("locked");
+			(typers.room_count(), typers.user_count())
+		};
+
+		let last_typing_update = self.last_typing_update.read().await.len();
+		writeln!(out, "typing_rooms: {rooms}")?;
+		writeln!(out, "typing_users: {users}")?;
+		writeln!(out, "typing_last_update: {last_typing_update}")?;
+
+		Ok(())
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 impl Service {
 	/// Sets a user as typing until the timeout timestamp is reached or
-	/// roomtyping_remove is called.
+	/// roomtyping_remove is called. Clients are only notified when the user
+	/// starts typing; a refreshed timeout changes nothing they can see.
 	pub async fn typing_add(
 		&self,
 		user_id: &UserId,
@@ -58,21 +118,17 @@ pub async fn typing_add(
 		timeout: u64,
 	) -> Result<()> {
 		debug_info!("typing started {user_id:?} in {room_id:?} timeout:{timeout:?}");
-		// update clients
-		self.typing
-			.write()
-			.await
-			.entry(room_id.to_owned())
-			.or_default()
-			.insert(user_id.to_owned(), timeout);
+		let started =
+			self.typers
+				.lock()
+				.expect("locked")
+				.add(room_id, user_id, expiry_instant(timeout));
 
-		self.last_typing_update
-			.write()
-			.await
-			.insert(room_id.to_owned(), self.services.globals.next_count()?);
+		self.expiry_changed.notify_one();
 
-		if self.typing_update_sender.send(room_id.to_owned()).is_err() {
-			trace!("receiver found what it was looking for and is no longer interested");
+		// update clients
+		if started {
+			self.typing_updated(room_id).await?;
 		}
 
 		// update federation
@@ -86,21 +142,11 @@ pub async fn typing_add(
 	/// Removes a user from typing before the timeout is reached.
 	pub async fn typing_remove(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
 		debug_info!("typing stopped {user_id:?} in {room_id:?}");
-		// update clients
-		self.typing
-			.write()
-			.await
-			.entry(room_id.to_owned())
-			.or_default()
-			.remove(user_id);
-
-		self.last_typing_update
-			.write()
-			.await
-			.insert(room_id.to_owned(), self.services.globals.next_count()?);
+		let stopped = self.typers.lock().expect("locked").remove(room_id, user_id);
 
-		if self.typing_update_sender.send(room_id.to_owned()).is_err() {
-			trace!("receiver found what it was looking for and is no longer interested");
+		// update clients
+		if stopped {
+			self.typing_updated(room_id).await?;
 		}
 
 		// update federation
@@ -111,65 +157,76 @@ pub async fn typing_remove(&self, user_id: &UserId, room_id: &RoomId) -> Result<
 		Ok(())
 	}
 
-	pub async fn wait_for_update(&self, room_id: &RoomId) {
+	/// Resolves once typing changes in any of the rooms. Updates are listened
+	/// for from the call on, not from when the future is first polled.
+	pub fn wait_for_update(
+		&self,
+		rooms: HashSet<OwnedRoomId>,
+	) -> impl Future<Output = ()> + Send + use<> {
 		let mut receiver = self.typing_update_sender.subscribe();
-		while let Ok(next) = receiver.recv().await {
-			if next == room_id {
-				break;
+		async move {
+			while let Ok(next) = receiver.recv().await {
+				if rooms.contains(&next) {
+					break;
+				}
 			}
 		}
 	}
 
-	/// Makes sure that typing events with old timestamps get removed.
-	async fn typings_maintain(&self, room_id: &RoomId) -> Result<()> {
-		let current_timestamp = utils::millis_since_unix_epoch();
-		let mut removable = Vec::new();
+	/// Removes the users whose typing timed out by `now`.
+	async fn typings_expire(&self, now: Instant) -> Result<()> {
+		let expired = self.typers.lock().expect("locked").expire(now);
 
-		{
-			let typing = self.typing.read().await;
-			let Some(room) = typing.get(room_id) else {
-				return Ok(());
-			};
+		let mut rooms: Vec<_> = expired.iter().map(|(room_id, _)| room_id).collect();
+		rooms.sort_unstable();
+		rooms.dedup();
 
-			for (user, timeout) in room {
-				if *timeout < current_timestamp {
-					removable.push(user.clone());
-				}
-			}
-		};
+		// update clients
+		for room_id in rooms {
+			self.typing_updated(room_id).await?;
+		}
 
-		if !removable.is_empty() {
-			let typing = &mut self.typing.write().await;
-			let room = typing.entry(room_id.to_owned()).or_default();
-			for user in &removable {
-				debug_info!("typing timeout {user:?} in {room_id:?}");
-				room.remove(user);
+		// update federation
+		for (room_id, user_id) in &expired {
+			debug_info!("typing timeout {user_id:?} in {room_id:?}");
+			if self.services.globals.user_is_local(user_id) {
+				self.federation_send(room_id, user_id, false).await?;
 			}
+		}
 
-			// update clients
-			self.last_typing_update
-				.write()
-				.await
-				.insert(room_id.to_owned(), self.services.globals.next_count()?);
+		Ok(())
+	}
 
-			if self.typing_update_sender.send(room_id.to_owned()).is_err() {
-				trace!("receiver found what it was looking for and is no longer interested");
-			}
+	/// Records a change to the typing users of the room and wakes the syncs
+	/// waiting on it.
+	async fn typing_updated(&self, room_id: &RoomId) -> Result<()> {
+		self.last_typing_update
+			.write()
+			.await
+			.insert(room_id.to_owned(), self.services.globals.next_count()?);
 
-			// update federation
-			for user in &removable {
-				if self.services.globals.user_is_local(user) {
-					self.federation_send(room_id, user, false).await?;
-				}
-			}
+		if self.typing_update_sender.send(room_id.to_owned()).is_err() {
+			trace!("receiver found what it was looking for and is no longer interested");
 		}
 
 		Ok(())
 	}
 
+	/// Drops everything kept about typing in the room, e.g. when it is purged,
+	/// without telling anyone.
+	pub async fn forget_room(&self, room_id: &RoomId) {
+		{
+			let mut typers = self.typers.lock().expect("locked");
+			for user_id in typers.users(room_id) {
+				typers.remove(room_id, &user_id);
+			}
+		}
+
+		self.last_typing_update.write().await.remove(room_id);
+	}
+
 	/// Returns the count of the last typing update in this room.
 	pub async fn last_typing_update(&self, room_id: &RoomId) -> Result<u64> {
-		self.typings_maintain(room_id).await?;
 		Ok(self
 			.last_typing_update
 			.read()
@@ -185,16 +242,11 @@ pub async fn typings_all(
 		room_id: &RoomId,
 		sender_user: &UserId,
 	) -> Result<SyncEphemeralRoomEvent<ruma::events::typing::TypingEventContent>> {
-		let room_typing_indicators = self.typing.read().await.get(room_id).cloned();
-
-		let Some(typing_indicators) = room_typing_indicators else {
-			return Ok(SyncEphemeralRoomEvent {
-				content: ruma::events::typing::TypingEventContent { user_ids: Vec::new() },
-			});
-		};
+		let typing_indicators: Vec<OwnedUserId> =
+			self.typers.lock().expect("locked").users(room_id);
 
 		let user_ids: Vec<_> = typing_indicators
-			.into_keys()
+			.into_iter()
 			.stream()
 			.filter_map(|typing_user_id| async move {
 				(!self
@@ -238,3 +290,13 @@ async fn federation_send(
 		Ok(())
 	}
 }
+
+/// Converts a timeout given as a unix timestamp in milliseconds to the
+/// instant it is reached.
+fn expiry_instant(timeout: u64) -> Instant {
+	let now = Instant::now();
+	let remaining = timeout.saturating_sub(utils::millis_since_unix_epoch());
+
+	now.checked_add(Duration::from_millis(remaining))
+		.unwrap_or(now)
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use ruma::{OwnedRoomId, OwnedUserId, owned_room_id, owned_user_id};
+
+use super::typers::Typers;
+
+fn after(now: Instant, secs: u64) -> Instant {
+	now.checked_add(Duration::from_secs(secs)).unwrap()
+}
+
+#[test]
+fn typing_refresh_and_remove() {
+	let room_id = owned_room_id!("!room:example.org");
+	let user_id = owned_user_id!("@alice:example.org");
+	let now = Instant::now();
+
+	let mut typers = Typers::default();
+	assert!(typers.add(&room_id, &user_id, after(now, 5)));
+	assert!(!typers.add(&room_id, &user_id, after(now, 10)));
+	assert_eq!(typers.user_count(), 1);
+	assert_eq!(typers.next_expiry(), Some(after(now, 10)));
+
+	// the refreshed timeout replaces the first one
+	assert!(typers.expire(after(now, 5)).is_empty());
+	assert_eq!(typers.users(&room_id), vec![user_id.clone()]);
+
+	assert!(typers.remove(&room_id, &user_id));
+	assert!(!typers.remove(&room_id, &user_id));
+	assert_eq!(typers.room_count(), 0);
+	assert_eq!(typers.next_expiry(), None);
+}
+
+#[test]
+fn typing_expiry_many_rooms() {
+	const ROOMS: usize = 1000;
+	const USERS: usize = 5;
+
+	let rooms: Vec<OwnedRoomId> = (0..ROOMS)
+		.map(|i| format!("!room{i}:example.org").try_into().unwrap())
+		.collect();
+
+	let users: Vec<OwnedUserId> = (0..USERS)
+		.map(|i| format!("@user{i}:example.org").try_into().unwrap())
+		.collect();
+
+	let now = Instant::now();
+	let mut typers = Typers::default();
+	for (i, room_id) in rooms.iter().enumerate() {
+		for user_id in &users {
+			let secs = u64::try_from(i % 30).unwrap();
+			typers.add(room_id, user_id, after(now, secs.saturating_add(1)));
+		}
+	}
+
+	assert_eq!(typers.room_count(), ROOMS);
+	assert_eq!(typers.user_count(), ROOMS.saturating_mul(USERS));
+
+	// every user typing again keeps one entry each
+	for room_id in &rooms {
+		for user_id in &users {
+			typers.add(room_id, user_id, after(now, 15));
+		}
+	}
+
+	assert_eq!(typers.user_count(), ROOMS.saturating_mul(USERS));
+
+	let expired = typers.expire(after(now, 14));
+	assert!(expired.is_empty());
+
+	let expired = typers.expire(after(now, 15));
+	assert_eq!(expired.len(), ROOMS.saturating_mul(USERS));
+	assert_eq!(typers.room_count(), 0);
+	assert_eq!(typers.user_count(), 0);
+	assert_eq!(typers.next_expiry(), None);
+}
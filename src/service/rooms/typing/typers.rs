@@ -0,0 +1,103 @@
+use std::{
+	collections::{BTreeSet, HashMap, hash_map::Entry},
+	time::Instant,
+};
+
+use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+
+/// Users typing in each room until their typing expires. Rooms are dropped as
+/// soon as nobody types in them, so the memory held is proportional to the
+/// number of users currently typing.
+#[derive(Debug, Default)]
+pub(super) struct Typers {
+	rooms: HashMap<OwnedRoomId, HashMap<OwnedUserId, Instant>>,
+
+	/// Every typing user ordered by expiry, the earliest first.
+	expiry: BTreeSet<(Instant, OwnedRoomId, OwnedUserId)>,
+}
+
+impl Typers {
+	/// Sets the user typing until `until`, returning whether they were not
+	/// typing before.
+	pub(super) fn add(&mut self, room_id: &RoomId, user_id: &UserId, until: Instant) -> bool {
+		let room = self.rooms.entry(room_id.to_owned()).or_default();
+		let started = match room.entry(user_id.to_owned()) {
+			| Entry::Occupied(mut entry) => {
+				let previous = entry.insert(until);
+				self.expiry
+					.remove(&(previous, room_id.to_owned(), user_id.to_owned()));
+				false
+			},
+			| Entry::Vacant(entry) => {
+				entry.insert(until);
+				true
+			},
+		};
+
+		self.expiry
+			.insert((until, room_id.to_owned(), user_id.to_owned()));
+
+		started
+	}
+
+	/// Removes the user from the typing users, returning whether they were
+	/// typing.
+	pub(super) fn remove(&mut self, room_id: &RoomId, user_id: &UserId) -> bool {
+		let Some(room) = self.rooms.get_mut(room_id) else {
+			return false;
+		};
+
+		let Some(until) = room.remove(user_id) else {
+			return false;
+		};
+
+		if room.is_empty() {
+			self.rooms.remove(room_id);
+		}
+
+		self.expiry
+			.remove(&(until, room_id.to_owned(), user_id.to_owned()));
+
+		true
+	}
+
+	/// Removes every user whose typing expired by `now`, returning them.
+	pub(super) fn expire(&mut self, now: Instant) -> Vec<(OwnedRoomId, OwnedUserId)> {
+		let mut expired = Vec::new();
+		while self.expiry.first().is_some_and(|(until, ..)| *until <= now) {
+			let Some((_, room_id, user_id)) = self.expiry.pop_first() else {
+				break;
+			};
+
+			if let Some(room) = self.rooms.get_mut(&room_id) {
+				room.remove(&user_id);
+				if room.is_empty() {
+					self.rooms.remove(&room_id);
+				}
+			}
+
+			expired.push((room_id, user_id));
+		}
+
+		expired
+	}
+
+	/// When the next typing user expires, if anyone is typing.
+	pub(super) fn next_expiry(&self) -> Option<Instant> {
+		self.expiry.first().map(|(until, ..)| *until)
+	}
+
+	/// Users typing in the room.
+	pub(super) fn users(&self, room_id: &RoomId) -> Vec<OwnedUserId> {
+		self.rooms
+			.get(room_id)
+			.map(|room| room.keys().cloned().collect())
+			.unwrap_or_default()
+	}
+
+	/// Number of rooms in which anyone is typing.
+	pub(super) fn room_count(&self) -> usize { self.rooms.len() }
+
+	/// Number of users typing across all rooms.
+	pub(super) fn user_count(&self) -> usize { self.expiry.len() }
+}
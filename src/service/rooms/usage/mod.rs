@@ -0,0 +1,309 @@
+//! Room resource usage
+//!
+//! Measures what each room costs the server: its members, the events stored
+//! of it, the size of its current state and the media its recent messages
+//! reference. Ranking every room of a large server takes one pass over all of
+//! them, keeping only the top few; measurements are cached for a while, as
+//! taking them again means reading much of the database.
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+	cmp::Reverse,
+	collections::{BTreeSet, BinaryHeap, HashMap},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	PduEvent, Result, implement,
+	utils::stream::{ReadyExt, TryIgnore},
+};
+use database::Map;
+use futures::{StreamExt, pin_mut};
+use ruma::{Mxc, OwnedMxcUri, OwnedRoomId, RoomId, events::TimelineEventType};
+use serde::Deserialize;
+
+use crate::{Dep, media, rooms};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+	cache: Mutex<HashMap<(OwnedRoomId, Measure), (u64, Instant)>>,
+}
+
+struct Services {
+	media: Dep<media::Service>,
+	metadata: Dep<rooms::metadata::Service>,
+	short: Dep<rooms::short::Service>,
+	state: Dep<rooms::state::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	state_compressor: Dep<rooms::state_compressor::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+struct Data {
+	pduid_pdu: Arc<Map>,
+}
+
+/// What rooms can be ranked by.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Measure {
+	/// Entries in the room's current state.
+	State,
+
+	/// Events stored of the room.
+	Events,
+
+	/// Joined members, local and remote.
+	Members,
+
+	/// Bytes of the stored media its recent messages reference.
+	Media,
+}
+
+/// What a room costs the server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Usage {
+	pub room_id: OwnedRoomId,
+	pub members: u64,
+	pub local_members: usize,
+	pub events: u64,
+	pub state: u64,
+	pub media_bytes: u64,
+}
+
+/// The files a message or sticker references.
+#[derive(Debug, Default, Deserialize)]
+struct Attachment {
+	url: Option<OwnedMxcUri>,
+
+	#[serde(default)]
+	info: AttachmentInfo,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AttachmentInfo {
+	thumbnail_url: Option<OwnedMxcUri>,
+}
+
+/// The `limit` rooms measuring the most of those pushed so far; of the same
+/// measurement the lowest room IDs are kept.
+#[derive(Debug)]
+struct Top {
+	limit: usize,
+	heap: BinaryHeap<Reverse<(u64, Reverse<OwnedRoomId>)>>,
+}
+
+/// How long a measurement is reused.
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Most recent events of a room looked through for the media they reference.
+const MEDIA_SCAN_MAX: usize = 10_000;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				media: args.depend::<media::Service>("media"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				state_compressor: args
+					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+			db: Data { pduid_pdu: args.db["pduid_pdu"].clone() },
+			cache: Mutex::new(HashMap::new()),
+		}))
+	}
+
+	async fn clear_cache(&self) { self.cache.lock().expect("locked").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// The usage of the `limit` rooms measuring the most by `by`, most first.
+#[implement(Service)]
+pub async fn top(&self, by: Measure, limit: usize) -> Vec<Usage> {
+	self.cache
+		.lock()
+		.expect("locked")
+		.retain(|_, (_, measured)| measured.elapsed() < CACHE_TTL);
+
+	let mut top = Top::new(limit);
+	let room_ids = self.services.metadata.iter_ids();
+	pin_mut!(room_ids);
+	while let Some(room_id) = room_ids.next().await {
+		top.push(self.measure(room_id, by).await, room_id);
+	}
+
+	let mut usages = Vec::with_capacity(limit);
+	for (room_id, _) in top.into_sorted() {
+		usages.push(self.usage(&room_id).await);
+	}
+
+	usages
+}
+
+/// Measures the room every way.
+#[implement(Service)]
+pub async fn usage(&self, room_id: &RoomId) -> Usage {
+	Usage {
+		room_id: room_id.to_owned(),
+		members: self.measure(room_id, Measure::Members).await,
+		local_members: self
+			.services
+			.state_cache
+			.local_users_in_room(room_id)
+			.count()
+			.await,
+		events: self.measure(room_id, Measure::Events).await,
+		state: self.measure(room_id, Measure::State).await,
+		media_bytes: self.measure(room_id, Measure::Media).await,
+	}
+}
+
+/// Measures the room, or reuses a recent measurement.
+#[implement(Service)]
+pub async fn measure(&self, room_id: &RoomId, measure: Measure) -> u64 {
+	let key = (room_id.to_owned(), measure);
+	let cached = self
+		.cache
+		.lock()
+		.expect("locked")
+		.get(&key)
+		.filter(|(_, measured)| measured.elapsed() < CACHE_TTL)
+		.map(|&(value, _)| value);
+
+	if let Some(value) = cached {
+		return value;
+	}
+
+	let value = match measure {
+		| Measure::State => self.state_size(room_id).await,
+		| Measure::Events => self.event_count(room_id).await,
+		| Measure::Members => self
+			.services
+			.state_cache
+			.room_joined_count(room_id)
+			.await
+			.unwrap_or(0),
+		| Measure::Media => self.media_bytes(room_id).await,
+	};
+
+	self.cache
+		.lock()
+		.expect("locked")
+		.insert(key, (value, Instant::now()));
+
+	value
+}
+
+#[implement(Service)]
+async fn state_size(&self, room_id: &RoomId) -> u64 {
+	let Ok(shortstatehash) = self.services.state.get_room_shortstatehash(room_id).await else {
+		return 0;
+	};
+
+	self.services
+		.state_compressor
+		.load_shortstatehash_info(shortstatehash)
+		.await
+		.ok()
+		.and_then(|stack| stack.last().map(|info| info.full_state.len()))
+		.and_then(|len| len.try_into().ok())
+		.unwrap_or(0)
+}
+
+#[implement(Service)]
+async fn event_count(&self, room_id: &RoomId) -> u64 {
+	let Ok(shortroomid) = self.services.short.get_shortroomid(room_id).await else {
+		return 0;
+	};
+
+	let count = self
+		.db
+		.pduid_pdu
+		.raw_keys_prefix(&shortroomid.to_be_bytes())
+		.ignore_err()
+		.count()
+		.await;
+
+	count.try_into().unwrap_or(u64::MAX)
+}
+
+/// Sums the sizes of the stored files which the room's most recent messages
+/// and stickers reference, counting each file once.
+#[implement(Service)]
+async fn media_bytes(&self, room_id: &RoomId) -> u64 {
+	let mut bytes: u64 = 0;
+	for mxc in self.media(room_id).await {
+		let Ok(mxc) = <Mxc<'_>>::try_from(mxc.as_str()) else {
+			continue;
+		};
+
+		if let Some(size) = self.services.media.get_size(&mxc).await {
+			bytes = bytes.saturating_add(size);
+		}
+	}
+
+	bytes
+}
+
+/// The files which the room's most recent messages and stickers reference,
+/// looking back through at most [`MEDIA_SCAN_MAX`] events. Files of encrypted
+/// messages are not seen.
+#[implement(Service)]
+pub async fn media(&self, room_id: &RoomId) -> BTreeSet<OwnedMxcUri> {
+	self.services
+		.timeline
+		.pdus_rev(None, room_id, None)
+		.ignore_err()
+		.take(MEDIA_SCAN_MAX)
+		.ready_fold_default(|mut mxcs: BTreeSet<_>, (_, pdu)| {
+			mxcs.extend(attachments(&pdu));
+			mxcs
+		})
+		.await
+}
+
+/// The files a message or sticker references, with its thumbnail.
+fn attachments(pdu: &PduEvent) -> Vec<OwnedMxcUri> {
+	if !matches!(pdu.kind, TimelineEventType::RoomMessage | TimelineEventType::Sticker) {
+		return Vec::new();
+	}
+
+	let attachment: Attachment = pdu.get_content().unwrap_or_default();
+	attachment
+		.url
+		.into_iter()
+		.chain(attachment.info.thumbnail_url)
+		.collect()
+}
+
+impl Top {
+	fn new(limit: usize) -> Self { Self { limit, heap: BinaryHeap::new() } }
+
+	fn push(&mut self, value: u64, room_id: &RoomId) {
+		self.heap
+			.push(Reverse((value, Reverse(room_id.to_owned()))));
+
+		if self.heap.len() > self.limit {
+			self.heap.pop();
+		}
+	}
+
+	/// The rooms kept, most first.
+	fn into_sorted(self) -> Vec<(OwnedRoomId, u64)> {
+		self.heap
+			.into_sorted_vec()
+			.into_iter()
+			.map(|Reverse((value, Reverse(room_id)))| (room_id, value))
+			.collect()
+	}
+}
@@ -0,0 +1,72 @@
+use conduwuit::PduEvent;
+use ruma::{owned_room_id, room_id};
+use serde_json::{Value, json};
+
+use super::{Top, attachments};
+
+fn event(kind: &str, content: Value) -> PduEvent {
+	serde_json::from_value(json!({
+		"event_id": "$event",
+		"room_id": "!room:example.org",
+		"sender": "@alice:example.org",
+		"origin_server_ts": 1,
+		"type": kind,
+		"content": content,
+		"prev_events": [],
+		"depth": 1,
+		"auth_events": [],
+		"hashes": { "sha256": "" },
+	}))
+	.expect("valid pdu")
+}
+
+#[test]
+fn room_usage_top_kept() {
+	let mut top = Top::new(2);
+	top.push(5, room_id!("!a:x"));
+	top.push(9, room_id!("!b:x"));
+	top.push(1, room_id!("!c:x"));
+	top.push(5, room_id!("!0:x"));
+
+	assert_eq!(top.into_sorted(), vec![
+		(owned_room_id!("!b:x"), 9),
+		(owned_room_id!("!0:x"), 5),
+	]);
+}
+
+#[test]
+fn room_usage_top_none() {
+	let mut top = Top::new(0);
+	top.push(5, room_id!("!a:x"));
+
+	assert!(top.into_sorted().is_empty());
+}
+
+#[test]
+fn room_usage_attachments() {
+	let image = event(
+		"m.room.message",
+		json!({
+			"msgtype": "m.image",
+			"body": "cat.png",
+			"url": "mxc://example.org/cat",
+			"info": { "thumbnail_url": "mxc://example.org/cat-small" },
+		}),
+	);
+
+	let found: Vec<_> = attachments(&image)
+		.iter()
+		.map(ToString::to_string)
+		.collect();
+
+	assert_eq!(found, ["mxc://example.org/cat", "mxc://example.org/cat-small"]);
+
+	let sticker = event("m.sticker", json!({ "body": "hi", "url": "mxc://example.org/hi" }));
+	assert_eq!(attachments(&sticker).len(), 1);
+
+	let text = event("m.room.message", json!({ "msgtype": "m.text", "body": "hi" }));
+	assert!(attachments(&text).is_empty());
+
+	let topic = event("m.room.topic", json!({ "url": "mxc://example.org/not-media" }));
+	assert!(attachments(&topic).is_empty());
+}
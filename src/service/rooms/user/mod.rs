@@ -1,8 +1,17 @@
-use std::sync::Arc;
+#[cfg(test)]
+mod tests;
 
-use conduwuit::{Result, implement};
-use database::{Database, Deserialized, Map};
-use ruma::{RoomId, UserId};
+use std::{collections::BTreeMap, sync::Arc};
+
+use conduwuit::{
+	Result, implement,
+	utils::stream::{ReadyExt, TryIgnore},
+};
+use database::{Database, Deserialized, Interfix, Map, serialize_key};
+use ruma::{
+	EventId, OwnedEventId, RoomId, UInt, UserId,
+	api::client::sync::sync_events::UnreadNotificationsCount, events::receipt::ReceiptThread,
+};
 
 use crate::{Dep, globals, rooms, rooms::short::ShortStateHash};
 
@@ -15,10 +24,23 @@ struct Data {
 	db: Arc<Database>,
 	userroomid_notificationcount: Arc<Map>,
 	userroomid_highlightcount: Arc<Map>,
+	userroomthreadid_notificationcount: Arc<Map>,
+	userroomthreadid_highlightcount: Arc<Map>,
 	roomuserid_lastnotificationread: Arc<Map>,
 	roomsynctoken_shortstatehash: Arc<Map>,
 }
 
+/// Unread notification and highlight counts of a room, or of one thread in it.
+///
+/// The counts of the room as a whole take in those of its threads, which are
+/// also kept apart so a client reading threads separately can be sent them on
+/// their own, and the room's main timeline without them.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Counts {
+	pub notifications: u64,
+	pub highlights: u64,
+}
+
 struct Services {
 	globals: Dep<globals::Service>,
 	short: Dep<rooms::short::Service>,
@@ -31,6 +53,10 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				db: args.db.clone(),
 				userroomid_notificationcount: args.db["userroomid_notificationcount"].clone(),
 				userroomid_highlightcount: args.db["userroomid_highlightcount"].clone(),
+				userroomthreadid_notificationcount: args.db["userroomthreadid_notificationcount"]
+					.clone(),
+				userroomthreadid_highlightcount: args.db["userroomthreadid_highlightcount"]
+					.clone(),
 				roomuserid_lastnotificationread: args.db["userroomid_highlightcount"].clone(),
 				roomsynctoken_shortstatehash: args.db["roomsynctoken_shortstatehash"].clone(),
 			},
@@ -45,12 +71,66 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
+/// Marks the whole room read, its threads included.
 #[implement(Service)]
 pub fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
 	let userroom_id = (user_id, room_id);
 	self.db.userroomid_highlightcount.put(userroom_id, 0_u64);
 	self.db.userroomid_notificationcount.put(userroom_id, 0_u64);
 
+	let prefix = serialize_key((user_id, room_id, Interfix)).expect("serialized prefix");
+	self.db
+		.userroomthreadid_notificationcount
+		.delete_prefix(&prefix);
+	self.db
+		.userroomthreadid_highlightcount
+		.delete_prefix(&prefix);
+
+	self.notification_read(user_id, room_id);
+}
+
+/// Marks read what a read receipt for `thread` covers: one thread, the main
+/// timeline without the threads, or everything for an unthreaded receipt.
+#[implement(Service)]
+pub async fn reset_thread_notification_counts(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	thread: &ReceiptThread,
+) {
+	if !matches!(thread, ReceiptThread::Main | ReceiptThread::Thread(_)) {
+		self.reset_notification_counts(user_id, room_id);
+		return;
+	}
+
+	let mut total = Counts {
+		notifications: self.notification_count(user_id, room_id).await,
+		highlights: self.highlight_count(user_id, room_id).await,
+	};
+
+	let mut threads = self.thread_notification_counts(user_id, room_id).await;
+	let before: Vec<_> = threads.keys().cloned().collect();
+	read(&mut total, &mut threads, thread);
+
+	let userroom_id = (user_id, room_id);
+	self.db
+		.userroomid_notificationcount
+		.put(userroom_id, total.notifications);
+	self.db
+		.userroomid_highlightcount
+		.put(userroom_id, total.highlights);
+
+	for thread_id in before.iter().filter(|id| !threads.contains_key(*id)) {
+		let key = (user_id, room_id, thread_id);
+		self.db.userroomthreadid_notificationcount.del(key);
+		self.db.userroomthreadid_highlightcount.del(key);
+	}
+
+	self.notification_read(user_id, room_id);
+}
+
+#[implement(Service)]
+fn notification_read(&self, user_id: &UserId, room_id: &RoomId) {
 	let roomuser_id = (room_id, user_id);
 	let count = self.services.globals.next_count().unwrap();
 	self.db
@@ -58,6 +138,65 @@ pub fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
 		.put(roomuser_id, count);
 }
 
+/// Counts for sync. When the client reads threads separately these are the
+/// counts of the main timeline alone, along with those of each thread;
+/// otherwise the counts of the whole room.
+#[implement(Service)]
+pub async fn unread_notifications(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	threaded: bool,
+) -> (Counts, BTreeMap<OwnedEventId, Counts>) {
+	let total = Counts {
+		notifications: self.notification_count(user_id, room_id).await,
+		highlights: self.highlight_count(user_id, room_id).await,
+	};
+
+	if !threaded {
+		return (total, BTreeMap::new());
+	}
+
+	let threads = self.thread_notification_counts(user_id, room_id).await;
+
+	(main(total, &threads), threads)
+}
+
+/// Counts of each thread in the room with anything unread.
+#[implement(Service)]
+pub async fn thread_notification_counts(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+) -> BTreeMap<OwnedEventId, Counts> {
+	let prefix = (user_id, room_id, Interfix);
+	let mut threads = BTreeMap::<OwnedEventId, Counts>::new();
+
+	self.db
+		.userroomthreadid_notificationcount
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.ready_for_each(|((_, _, thread_id), count): ((&UserId, &RoomId, &EventId), u64)| {
+			threads
+				.entry(thread_id.to_owned())
+				.or_default()
+				.notifications = count;
+		})
+		.await;
+
+	self.db
+		.userroomthreadid_highlightcount
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.ready_for_each(|((_, _, thread_id), count): ((&UserId, &RoomId, &EventId), u64)| {
+			threads.entry(thread_id.to_owned()).or_default().highlights = count;
+		})
+		.await;
+
+	threads.retain(|_, counts| *counts != Counts::default());
+	threads
+}
+
 #[implement(Service)]
 pub async fn notification_count(&self, user_id: &UserId, room_id: &RoomId) -> u64 {
 	let key = (user_id, room_id);
@@ -127,3 +266,56 @@ pub async fn get_token_shortstatehash(
 		.await
 		.deserialized()
 }
+
+/// Applies a threaded read receipt to the counts of the room as a whole and
+/// of its threads, dropping the threads it leaves with nothing unread.
+pub(super) fn read(
+	total: &mut Counts,
+	threads: &mut BTreeMap<OwnedEventId, Counts>,
+	thread: &ReceiptThread,
+) {
+	match thread {
+		| ReceiptThread::Thread(thread_id) =>
+			if let Some(counts) = threads.remove(thread_id) {
+				*total = total.saturating_sub(counts);
+			},
+		| ReceiptThread::Main => {
+			*total = threads
+				.values()
+				.fold(Counts::default(), |sum, counts| Counts {
+					notifications: sum.notifications.saturating_add(counts.notifications),
+					highlights: sum.highlights.saturating_add(counts.highlights),
+				});
+		},
+		| _ => {
+			*total = Counts::default();
+			threads.clear();
+		},
+	}
+}
+
+/// Counts of the main timeline, being those of the room less its threads'.
+pub(super) fn main(total: Counts, threads: &BTreeMap<OwnedEventId, Counts>) -> Counts {
+	threads
+		.values()
+		.fold(total, |main, &counts| main.saturating_sub(counts))
+}
+
+impl Counts {
+	#[must_use]
+	fn saturating_sub(self, other: Self) -> Self {
+		Self {
+			notifications: self.notifications.saturating_sub(other.notifications),
+			highlights: self.highlights.saturating_sub(other.highlights),
+		}
+	}
+}
+
+impl From<Counts> for UnreadNotificationsCount {
+	fn from(counts: Counts) -> Self {
+		Self {
+			notification_count: Some(UInt::new_saturating(counts.notifications)),
+			highlight_count: Some(UInt::new_saturating(counts.highlights)),
+		}
+	}
+}
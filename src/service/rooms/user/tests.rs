@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use ruma::{EventId, OwnedEventId, event_id, events::receipt::ReceiptThread};
+
+use super::{Counts, main, read};
+
+#[derive(Default)]
+struct Room {
+	total: Counts,
+	threads: BTreeMap<OwnedEventId, Counts>,
+}
+
+impl Room {
+	/// Counts an event the way the timeline does when it is appended.
+	fn notify(&mut self, thread: Option<&EventId>, highlight: bool) {
+		let buckets = [
+			Some(&mut self.total),
+			thread.map(|thread| self.threads.entry(thread.to_owned()).or_default()),
+		];
+
+		for counts in buckets.into_iter().flatten() {
+			counts.notifications = counts.notifications.saturating_add(1);
+			if highlight {
+				counts.highlights = counts.highlights.saturating_add(1);
+			}
+		}
+	}
+
+	fn read(&mut self, thread: &ReceiptThread) {
+		read(&mut self.total, &mut self.threads, thread);
+	}
+
+	fn main(&self) -> Counts { main(self.total, &self.threads) }
+
+	fn thread(&self, thread: &EventId) -> Counts {
+		self.threads.get(thread).copied().unwrap_or_default()
+	}
+}
+
+const fn counts(notifications: u64, highlights: u64) -> Counts {
+	Counts { notifications, highlights }
+}
+
+#[test]
+fn two_threads_interleaved_receipts() {
+	let first = event_id!("$first:example.com");
+	let second = event_id!("$second:example.com");
+	let mut room = Room::default();
+
+	room.notify(None, false);
+	room.notify(Some(first), false);
+	room.notify(Some(second), true);
+	room.notify(Some(first), true);
+	room.notify(None, true);
+
+	assert_eq!(room.total, counts(5, 3));
+	assert_eq!(room.main(), counts(2, 1));
+	assert_eq!(room.thread(first), counts(2, 1));
+	assert_eq!(room.thread(second), counts(1, 1));
+
+	room.read(&ReceiptThread::Thread(first.to_owned()));
+	assert_eq!(room.thread(first), counts(0, 0));
+	assert_eq!(room.thread(second), counts(1, 1), "other thread untouched");
+	assert_eq!(room.main(), counts(2, 1), "main timeline untouched");
+	assert_eq!(room.total, counts(3, 2));
+
+	room.notify(Some(first), false);
+	room.notify(Some(second), false);
+	room.read(&ReceiptThread::Main);
+	assert_eq!(room.main(), counts(0, 0));
+	assert_eq!(room.thread(first), counts(1, 0));
+	assert_eq!(room.thread(second), counts(2, 1));
+	assert_eq!(room.total, counts(3, 1));
+
+	room.notify(None, false);
+	room.read(&ReceiptThread::Thread(second.to_owned()));
+	assert_eq!(room.main(), counts(1, 0));
+	assert_eq!(room.thread(first), counts(1, 0));
+	assert_eq!(room.thread(second), counts(0, 0));
+	assert_eq!(room.total, counts(2, 0));
+}
+
+#[test]
+fn unthreaded_receipt_clears_threads() {
+	let first = event_id!("$first:example.com");
+	let second = event_id!("$second:example.com");
+	let mut room = Room::default();
+
+	room.notify(None, true);
+	room.notify(Some(first), false);
+	room.notify(Some(second), true);
+	room.read(&ReceiptThread::Unthreaded);
+
+	assert_eq!(room.total, counts(0, 0));
+	assert_eq!(room.main(), counts(0, 0));
+	assert!(room.threads.is_empty(), "thread counts cleared");
+}
+
+#[test]
+fn receipt_for_thread_without_unread() {
+	let first = event_id!("$first:example.com");
+	let mut room = Room::default();
+
+	room.notify(None, false);
+	room.read(&ReceiptThread::Thread(first.to_owned()));
+
+	assert_eq!(room.total, counts(1, 0));
+	assert_eq!(room.main(), counts(1, 0));
+}
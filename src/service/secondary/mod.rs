@@ -0,0 +1,93 @@
+use std::{cmp, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{Result, Server, debug, warn};
+use database::Database;
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval, sleep},
+};
+
+/// Keeps a `rocksdb_secondary` instance caught up with its primary.
+pub struct Service {
+	interval: Duration,
+	interrupt: Notify,
+	db: Arc<Database>,
+	server: Arc<Server>,
+}
+
+/// Upper bound on the delay between attempts after repeated failures.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let interval = args.server.config.rocksdb_secondary_catchup_interval_ms;
+
+		Ok(Arc::new(Self {
+			interval: Duration::from_millis(interval.max(1)),
+			interrupt: Notify::new(),
+			db: args.db.clone(),
+			server: args.server.clone(),
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "secondary", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		if !self.db.is_secondary() {
+			return Ok(());
+		}
+
+		let mut i = interval(self.interval);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		let mut backoff = Duration::ZERO;
+		while self.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			match self.catch_up().await {
+				| Ok(_) => backoff = Duration::ZERO,
+				| Err(e) => {
+					backoff =
+						cmp::min(MAX_BACKOFF, cmp::max(self.interval, backoff.saturating_mul(2)));
+					warn!(?backoff, "Failed to catch up with primary: {e}");
+					tokio::select! {
+						() = self.interrupt.notified() => break,
+						() = sleep(backoff) => (),
+					}
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Applies the primary's latest writes, returning how many sequence numbers
+	/// were applied. Does nothing unless running as a secondary.
+	pub async fn catch_up(&self) -> Result<u64> {
+		if !self.db.is_secondary() {
+			return Ok(0);
+		}
+
+		let db = Arc::clone(&self.db);
+		let applied = self
+			.server
+			.runtime()
+			.spawn_blocking(move || db.db.update())
+			.await??;
+
+		if applied > 0 {
+			debug!(%applied, "Caught up with primary");
+		}
+
+		Ok(applied)
+	}
+}
@@ -0,0 +1,182 @@
+use std::{fmt, time::Duration};
+
+use conduwuit::{Config, Error};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// What went wrong sending to a federation destination, deciding how long it
+/// is left before it is retried.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Class {
+	/// It could not be connected to or did not answer in time.
+	Unreachable,
+
+	/// It answered with a 5xx; it is likely only busy, so its backoff stays
+	/// short.
+	ServerError,
+
+	/// It answered with a 4xx, so it is likely not a homeserver any more or
+	/// has gone wrong in a way which won't clear by itself soon.
+	ClientError,
+
+	/// It refused our signatures; retrying does not help until keys change.
+	Signature,
+}
+
+/// The backoff of a federation destination, persisted so that it carries
+/// across restarts.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Backoff {
+	/// Class of the last failure.
+	pub class: Class,
+
+	/// Transactions failed in a row.
+	pub tries: u32,
+
+	/// Milliseconds since the epoch before which the destination is not
+	/// retried.
+	pub retry_at: u64,
+}
+
+/// The curve of backoff delays, from the config.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Curve {
+	initial: Duration,
+	max: Duration,
+	multiplier: f64,
+	jitter: f64,
+}
+
+/// Upper bound of the backoff after 5xx answers.
+const SERVER_ERROR_MAX: Duration = Duration::from_secs(60 * 60);
+
+impl Class {
+	/// Classifies the error a transaction failed with.
+	#[must_use]
+	pub fn of(error: &Error) -> Self {
+		match error {
+			| Error::Federation(_, error) => Self::of_status(error.status_code),
+			| Error::Reqwest(error) => error.status().map_or(Self::Unreachable, Self::of_status),
+			| _ => Self::Unreachable,
+		}
+	}
+
+	fn of_status(status: StatusCode) -> Self {
+		match status {
+			| StatusCode::UNAUTHORIZED => Self::Signature,
+			| status if status.is_server_error() => Self::ServerError,
+			| status if status.is_client_error() => Self::ClientError,
+			| _ => Self::Unreachable,
+		}
+	}
+
+	/// Failures in a row the curve of this class starts out as if it had
+	/// already seen.
+	const fn head_start(self) -> u32 {
+		match self {
+			| Self::Unreachable | Self::ServerError => 0,
+			| Self::ClientError => 2,
+			| Self::Signature => 4,
+		}
+	}
+}
+
+impl fmt::Display for Class {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			| Self::Unreachable => "unreachable",
+			| Self::ServerError => "server error",
+			| Self::ClientError => "client error",
+			| Self::Signature => "signature refused",
+		})
+	}
+}
+
+impl Backoff {
+	/// The backoff after a transaction failed at `now` with an error of
+	/// `class`, it being the `tries`th failure in a row. A destination asking
+	/// to be left until `retry_at` is left at least that long. `jitter` is a
+	/// sample from -1 to 1 spreading retries out by the curve's jitter.
+	#[must_use]
+	pub(super) fn failed(
+		curve: &Curve,
+		class: Class,
+		tries: u32,
+		retry_at: Option<u64>,
+		now: u64,
+		jitter: f64,
+	) -> Self {
+		let delay = curve.delay(class, tries, jitter);
+		let delay = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX);
+		let retry_at = now.saturating_add(delay).max(retry_at.unwrap_or(0));
+
+		Self { class, tries, retry_at }
+	}
+
+	/// How long is left to wait as of `now`.
+	#[inline]
+	#[must_use]
+	pub fn remaining(&self, now: u64) -> Duration {
+		Duration::from_millis(self.retry_at.saturating_sub(now))
+	}
+
+	/// Whether the delay has reached the curve's maximum, leaving the
+	/// destination retried only at the longest interval.
+	#[must_use]
+	pub(super) fn dormant(&self, curve: &Curve) -> bool {
+		curve.delay(self.class, self.tries, 0.0) >= curve.max
+	}
+}
+
+impl Curve {
+	pub(super) fn new(config: &Config) -> Self {
+		Self {
+			initial: Duration::from_secs(config.federation_backoff_initial),
+			max: Duration::from_secs(config.federation_backoff_max),
+			multiplier: config.federation_backoff_multiplier.max(1.0),
+			jitter: config.federation_backoff_jitter.clamp(0.0, 1.0),
+		}
+	}
+
+	/// Delay before retrying after the `tries`th failure in a row, grown by
+	/// the multiplier with each failure before it, then spread by `jitter`.
+	/// Jitter does not take it over the maximum.
+	pub(super) fn delay(&self, class: Class, tries: u32, jitter: f64) -> Duration {
+		let max = match class {
+			| Class::ServerError => self.max.min(SERVER_ERROR_MAX),
+			| _ => self.max,
+		};
+
+		let grown = tries.saturating_sub(1).saturating_add(class.head_start());
+		let grown = i32::try_from(grown).unwrap_or(i32::MAX);
+		let factor =
+			self.multiplier.powi(grown) * jitter.clamp(-1.0, 1.0).mul_add(self.jitter, 1.0);
+
+		Duration::try_from_secs_f64(self.initial.as_secs_f64() * factor)
+			.map(round_millis)
+			.unwrap_or(max)
+			.min(max)
+	}
+
+	/// Fewest failures in a row of `class` at which the delay reaches the
+	/// maximum, leaving the destination dormant.
+	pub(super) fn dormant_tries(&self, class: Class) -> u32 {
+		(1..=u32::BITS)
+			.find(|&tries| self.delay(class, tries, 0.0) >= self.max)
+			.unwrap_or(u32::BITS)
+	}
+}
+
+fn round_millis(delay: Duration) -> Duration {
+	let millis = delay.as_micros().saturating_add(500) / 1000;
+	Duration::from_millis(u64::try_from(millis).unwrap_or(u64::MAX))
+}
+
+/// A sample to spread retries out by.
+#[must_use]
+pub(super) fn jitter() -> f64 {
+	use rand::Rng;
+
+	rand::thread_rng().gen_range(-1.0..=1.0)
+}
@@ -0,0 +1,169 @@
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+use conduwuit::Server;
+use ruma::{
+	OwnedRoomId, OwnedUserId,
+	api::federation::transactions::edu::{
+		Edu, PresenceContent, PresenceUpdate, ReceiptContent, ReceiptMap, TypingContent,
+	},
+};
+
+use super::EduBuf;
+
+/// The EDUs for a transaction to a federation destination, coalesced from
+/// those pending for it.
+#[derive(Debug, Default)]
+pub(super) struct Batch {
+	/// EDUs to send, up to the limit.
+	pub(super) edus: Vec<EduBuf>,
+
+	/// EDUs past the limit, left for the next transaction.
+	pub(super) overflow: Vec<EduBuf>,
+
+	/// EDUs dropped for being superseded by a later one or repeated.
+	pub(super) superseded: usize,
+}
+
+/// Counts of the EDUs sent to federation destinations, for the average number
+/// per transaction with and without coalescing.
+#[derive(Debug, Default)]
+pub(super) struct Stats {
+	transactions: AtomicU64,
+	edus: AtomicU64,
+	superseded: AtomicU64,
+}
+
+/// Where an EDU goes among those of the batch, kept in the order the first of
+/// its kind was pending.
+enum Slot {
+	Raw(EduBuf),
+	Receipts,
+	Presence,
+	Typing(OwnedRoomId, OwnedUserId),
+}
+
+/// Coalesces the pending EDUs, oldest first, into as few as carry the same
+/// news: receipts are merged into one EDU keeping the newest of each user in
+/// each room, presence into one keeping the newest of each user, and typing
+/// to the newest of each user in each room, so typing which started and
+/// stopped is only sent as stopped. EDUs repeated exactly are sent once; any
+/// others are left alone.
+pub(super) fn coalesce<I>(pending: I, limit: usize) -> Batch
+where
+	I: IntoIterator<Item = EduBuf>,
+{
+	let mut slots = Vec::new();
+	let mut seen = HashSet::new();
+	let mut receipts = BTreeMap::<OwnedRoomId, ReceiptMap>::new();
+	let mut presence = BTreeMap::<OwnedUserId, PresenceUpdate>::new();
+	let mut typing = HashMap::<(OwnedRoomId, OwnedUserId), TypingContent>::new();
+	let (mut has_receipts, mut has_presence) = (false, false);
+
+	let mut count: usize = 0;
+	for edu in pending {
+		count = count.saturating_add(1);
+		if !seen.insert(edu.clone()) {
+			continue;
+		}
+
+		match serde_json::from_slice(&edu) {
+			| Ok(Edu::Receipt(content)) => {
+				if !has_receipts {
+					slots.push(Slot::Receipts);
+					has_receipts = true;
+				}
+
+				for (room_id, map) in content.receipts {
+					receipts
+						.entry(room_id)
+						.or_insert_with(|| ReceiptMap { read: BTreeMap::new() })
+						.read
+						.extend(map.read);
+				}
+			},
+			| Ok(Edu::Presence(content)) => {
+				if !has_presence {
+					slots.push(Slot::Presence);
+					has_presence = true;
+				}
+
+				for update in content.push {
+					presence.insert(update.user_id.clone(), update);
+				}
+			},
+			| Ok(Edu::Typing(content)) => {
+				let key = (content.room_id.clone(), content.user_id.clone());
+				if typing.insert(key.clone(), content).is_none() {
+					slots.push(Slot::Typing(key.0, key.1));
+				}
+			},
+			| _ => slots.push(Slot::Raw(edu)),
+		}
+	}
+
+	let mut presence = Some(presence);
+	let mut receipts = Some(receipts);
+	let mut edus: Vec<EduBuf> = slots
+		.into_iter()
+		.filter_map(|slot| match slot {
+			| Slot::Raw(edu) => Some(edu),
+			| Slot::Receipts => receipts
+				.take()
+				.map(|receipts| Edu::Receipt(ReceiptContent { receipts }))
+				.map(serialize),
+			| Slot::Presence => presence
+				.take()
+				.map(|presence| {
+					Edu::Presence(PresenceContent { push: presence.into_values().collect() })
+				})
+				.map(serialize),
+			| Slot::Typing(room_id, user_id) => typing
+				.remove(&(room_id, user_id))
+				.map(Edu::Typing)
+				.map(serialize),
+		})
+		.collect();
+
+	let superseded = count.saturating_sub(edus.len());
+	let overflow = edus.split_off(limit.min(edus.len()));
+
+	Batch { edus, overflow, superseded }
+}
+
+fn serialize(edu: Edu) -> EduBuf {
+	let mut buf = EduBuf::new();
+	serde_json::to_writer(&mut buf, &edu).expect("failed to serialize coalesced EDU to JSON");
+	buf
+}
+
+impl Stats {
+	/// Counts a transaction composed from the batch, publishing the totals to
+	/// the server metrics.
+	pub(super) fn record(&self, server: &Server, batch: &Batch) {
+		let edus = u64::try_from(batch.edus.len()).unwrap_or(u64::MAX);
+		let superseded = u64::try_from(batch.superseded).unwrap_or(u64::MAX);
+
+		let metrics = &server.metrics;
+		metrics.set(
+			"federation_transactions",
+			self.transactions
+				.fetch_add(1, Ordering::Relaxed)
+				.saturating_add(1),
+		);
+		metrics.set(
+			"federation_transaction_edus",
+			self.edus
+				.fetch_add(edus, Ordering::Relaxed)
+				.saturating_add(edus),
+		);
+		metrics.set(
+			"federation_transaction_edus_superseded",
+			self.superseded
+				.fetch_add(superseded, Ordering::Relaxed)
+				.saturating_add(superseded),
+		);
+	}
+}
@@ -4,21 +4,32 @@
 	Error, Result, at, utils,
 	utils::{ReadyExt, stream::TryIgnore},
 };
-use database::{Database, Deserialized, Map};
+use database::{Database, Deserialized, Json, Map};
 use futures::{Stream, StreamExt};
 use ruma::{OwnedServerName, ServerName, UserId};
 
-use super::{Destination, SendingEvent};
+use super::{Destination, SendingEvent, backoff::Backoff, defunct::Mark};
 use crate::{Dep, globals};
 
-pub(super) type OutgoingItem = (Key, SendingEvent, Destination);
+pub(super) type OutgoingItem = (Key, SendingEvent, Destination, Enqueued);
 pub(super) type SendingItem = (Key, SendingEvent);
-pub(super) type QueueItem = (Key, SendingEvent);
+pub(super) type QueueItem = (Key, SendingEvent, Enqueued);
 pub(super) type Key = Vec<u8>;
 
+/// Milliseconds since the epoch at which a PDU was queued; unknown for EDUs
+/// and for PDUs queued before the time was recorded.
+pub(super) type Enqueued = Option<u64>;
+
+/// Leads the value of a queued PDU, followed by the time it was queued. An
+/// EDU value is JSON which cannot start with this byte, and PDUs queued before
+/// the time was recorded have an empty value.
+const PDU_ENQUEUED: u8 = 0xFF;
+
 pub struct Data {
 	servercurrentevent_data: Arc<Map>,
 	servernameevent_data: Arc<Map>,
+	servername_backoff: Arc<Map>,
+	servername_defunct: Arc<Map>,
 	servername_educount: Arc<Map>,
 	pub(super) db: Arc<Database>,
 	services: Services,
@@ -34,6 +45,8 @@ pub(super) fn new(args: &crate::Args<'_>) -> Self {
 		Self {
 			servercurrentevent_data: db["servercurrentevent_data"].clone(),
 			servernameevent_data: db["servernameevent_data"].clone(),
+			servername_backoff: db["servername_backoff"].clone(),
+			servername_defunct: db["servername_defunct"].clone(),
 			servername_educount: db["servername_educount"].clone(),
 			db: args.db.clone(),
 			services: Services {
@@ -75,9 +88,13 @@ pub(super) fn mark_as_active<'a, I>(&self, events: I)
 		I: Iterator<Item = &'a QueueItem>,
 	{
 		events
-			.filter(|(key, _)| !key.is_empty())
-			.for_each(|(key, val)| {
-				let val = if let SendingEvent::Edu(val) = &val { &**val } else { &[] };
+			.filter(|(key, ..)| !key.is_empty())
+			.for_each(|(key, event, enqueued)| {
+				let stamp = enqueued.map(pdu_value);
+				let val = match event {
+					| SendingEvent::Edu(val) => &**val,
+					| _ => stamp.as_ref().map_or(&[][..], <[u8; 9]>::as_slice),
+				};
 
 				self.servercurrentevent_data.insert(key, val);
 				self.servernameevent_data.remove(key);
@@ -90,10 +107,23 @@ pub fn active_requests(&self) -> impl Stream<Item = OutgoingItem> + Send + '_ {
 			.raw_stream()
 			.ignore_err()
 			.map(|(key, val)| {
-				let (dest, event) =
+				let (dest, event, enqueued) =
+					parse_servercurrentevent(key, val).expect("invalid servercurrentevent");
+
+				(key.to_vec(), event, dest, enqueued)
+			})
+	}
+
+	#[inline]
+	pub fn queued_all(&self) -> impl Stream<Item = OutgoingItem> + Send + '_ {
+		self.servernameevent_data
+			.raw_stream()
+			.ignore_err()
+			.map(|(key, val)| {
+				let (dest, event, enqueued) =
 					parse_servercurrentevent(key, val).expect("invalid servercurrentevent");
 
-				(key.to_vec(), event, dest)
+				(key.to_vec(), event, dest, enqueued)
 			})
 	}
 
@@ -108,7 +138,7 @@ pub fn active_requests_for(
 			.ignore_err()
 			.ready_take_while(move |(key, _)| key.starts_with(&prefix))
 			.map(|(key, val)| {
-				let (_, event) =
+				let (_, event, _) =
 					parse_servercurrentevent(key, val).expect("invalid servercurrentevent");
 
 				(key.to_vec(), event)
@@ -134,6 +164,7 @@ pub(super) fn queue_requests<'a, I>(&self, requests: I) -> Vec<Vec<u8>>
 			})
 			.collect();
 
+		let stamp = pdu_value(utils::time::now_millis());
 		self.servernameevent_data.insert_batch(
 			keys.iter()
 				.map(Vec::as_slice)
@@ -142,7 +173,7 @@ pub(super) fn queue_requests<'a, I>(&self, requests: I) -> Vec<Vec<u8>>
 					let value = if let SendingEvent::Edu(value) = &event {
 						&**value
 					} else {
-						&[]
+						stamp.as_slice()
 					};
 
 					(key, value)
@@ -162,13 +193,45 @@ pub fn queued_requests(
 			.ignore_err()
 			.ready_take_while(move |(key, _)| key.starts_with(&prefix))
 			.map(|(key, val)| {
-				let (_, event) =
+				let (_, event, enqueued) =
 					parse_servercurrentevent(key, val).expect("invalid servercurrentevent");
 
-				(key.to_vec(), event)
+				(key.to_vec(), event, enqueued)
 			})
 	}
 
+	pub(super) fn set_backoff(&self, server_name: &ServerName, backoff: &Backoff) {
+		self.servername_backoff.raw_put(server_name, Json(backoff));
+	}
+
+	pub(super) fn clear_backoff(&self, server_name: &ServerName) {
+		self.servername_backoff.remove(server_name);
+	}
+
+	/// The backoff of every federation destination which last failed.
+	pub(super) fn backoffs(&self) -> impl Stream<Item = (OwnedServerName, Backoff)> + Send + '_ {
+		self.servername_backoff.stream().ignore_err().map(
+			|(server_name, backoff): (&ServerName, Backoff)| (server_name.to_owned(), backoff),
+		)
+	}
+
+	pub(super) fn set_defunct(&self, server_name: &ServerName, mark: &Mark) {
+		self.servername_defunct.raw_put(server_name, Json(mark));
+	}
+
+	pub(super) fn clear_defunct(&self, server_name: &ServerName) {
+		self.servername_defunct.remove(server_name);
+	}
+
+	/// Every federation destination an admin marked defunct which has not
+	/// answered since.
+	pub(super) fn defunct(&self) -> impl Stream<Item = (OwnedServerName, Mark)> + Send + '_ {
+		self.servername_defunct
+			.stream()
+			.ignore_err()
+			.map(|(server_name, mark): (&ServerName, Mark)| (server_name.to_owned(), mark))
+	}
+
 	pub(super) fn set_latest_educount(&self, server_name: &ServerName, last_count: u64) {
 		self.servername_educount.raw_put(server_name, last_count);
 	}
@@ -182,7 +245,10 @@ pub async fn get_latest_educount(&self, server_name: &ServerName) -> u64 {
 	}
 }
 
-fn parse_servercurrentevent(key: &[u8], value: &[u8]) -> Result<(Destination, SendingEvent)> {
+fn parse_servercurrentevent(
+	key: &[u8],
+	value: &[u8],
+) -> Result<(Destination, SendingEvent, Enqueued)> {
 	// Appservices start with a plus
 	Ok::<_, Error>(if key.starts_with(b"+") {
 		let mut parts = key[1..].splitn(2, |&b| b == 0xFF);
@@ -196,14 +262,8 @@ fn parse_servercurrentevent(key: &[u8], value: &[u8]) -> Result<(Destination, Se
 			Error::bad_database("Invalid server bytes in server_currenttransaction")
 		})?;
 
-		(
-			Destination::Appservice(server),
-			if value.is_empty() {
-				SendingEvent::Pdu(event.into())
-			} else {
-				SendingEvent::Edu(value.into())
-			},
-		)
+		let (event, enqueued) = parse_value(event, value);
+		(Destination::Appservice(server), event, enqueued)
 	} else if key.starts_with(b"$") {
 		let mut parts = key[1..].splitn(3, |&b| b == 0xFF);
 
@@ -223,15 +283,9 @@ fn parse_servercurrentevent(key: &[u8], value: &[u8]) -> Result<(Destination, Se
 			.next()
 			.ok_or_else(|| Error::bad_database("Invalid bytes in servercurrentpdus."))?;
 
-		(
-			Destination::Push(user_id.to_owned(), pushkey_string),
-			if value.is_empty() {
-				SendingEvent::Pdu(event.into())
-			} else {
-				// I'm pretty sure this should never be called
-				SendingEvent::Edu(value.into())
-			},
-		)
+		// I'm pretty sure this should never be an EDU
+		let (event, enqueued) = parse_value(event, value);
+		(Destination::Push(user_id.to_owned(), pushkey_string), event, enqueued)
 	} else {
 		let mut parts = key.splitn(2, |&b| b == 0xFF);
 
@@ -244,15 +298,30 @@ fn parse_servercurrentevent(key: &[u8], value: &[u8]) -> Result<(Destination, Se
 			Error::bad_database("Invalid server bytes in server_currenttransaction")
 		})?;
 
+		let (event, enqueued) = parse_value(event, value);
 		(
 			Destination::Federation(OwnedServerName::parse(&server).map_err(|_| {
 				Error::bad_database("Invalid server string in server_currenttransaction")
 			})?),
-			if value.is_empty() {
-				SendingEvent::Pdu(event.into())
-			} else {
-				SendingEvent::Edu(value.into())
-			},
+			event,
+			enqueued,
 		)
 	})
 }
+
+pub(super) fn parse_value(event: &[u8], value: &[u8]) -> (SendingEvent, Enqueued) {
+	match value {
+		| [] => (SendingEvent::Pdu(event.into()), None),
+		| [PDU_ENQUEUED, stamp @ ..] if stamp.len() == 8 => {
+			let enqueued = stamp.try_into().map(u64::from_be_bytes).ok();
+			(SendingEvent::Pdu(event.into()), enqueued)
+		},
+		| value => (SendingEvent::Edu(value.into()), None),
+	}
+}
+
+pub(super) fn pdu_value(enqueued: u64) -> [u8; 9] {
+	let mut value = [PDU_ENQUEUED; 9];
+	value[1..].copy_from_slice(&enqueued.to_be_bytes());
+	value
+}
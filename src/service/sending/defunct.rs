@@ -0,0 +1,211 @@
+use std::{
+	collections::HashMap,
+	error::Error as _,
+	fmt,
+	sync::{Mutex, RwLock},
+};
+
+use conduwuit::Error;
+use hickory_resolver::{ResolveError, ResolveErrorKind::Proto, proto::ProtoErrorKind};
+use http::StatusCode;
+use ruma::{OwnedServerName, ServerName};
+use serde::{Deserialize, Serialize};
+
+/// How a federation destination fails which suggests it is gone for good
+/// rather than down for a while.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Symptom {
+	/// It answers 403 to everything.
+	Forbidden,
+
+	/// It answers 410.
+	Gone,
+
+	/// Its name no longer resolves.
+	NoDns,
+}
+
+/// Failures with the same kind of symptom in a row.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Streak {
+	/// Symptom of the latest failure.
+	pub symptom: Symptom,
+
+	/// Milliseconds since the epoch of the first failure.
+	pub since: u64,
+
+	pub failures: u32,
+}
+
+/// An admin marked the destination defunct; kept across restarts until it
+/// answers again.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Mark {
+	/// Milliseconds since the epoch at which it was marked.
+	pub at: u64,
+
+	/// Its users are left out of the heroes of room summaries.
+	pub hide_heroes: bool,
+}
+
+/// Least failures in a row before a destination is classified defunct, so a
+/// window shorter than the backoff is not met by a single failure.
+const MIN_FAILURES: u32 = 3;
+
+/// Which federation destinations look to be defunct.
+#[derive(Debug, Default)]
+pub(super) struct Tracker {
+	destinations: RwLock<HashMap<OwnedServerName, State>>,
+	hold: Mutex<HashMap<OwnedServerName, u64>>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct State {
+	streak: Option<Streak>,
+
+	/// The streak lasted the observation window; cleared only by success.
+	classified: bool,
+
+	mark: Option<Mark>,
+}
+
+impl Symptom {
+	/// The symptom the error a transaction failed with shows, if any.
+	#[must_use]
+	pub fn of(error: &Error) -> Option<Self> {
+		match error {
+			| Error::Federation(_, error) => Self::of_status(error.status_code),
+			| Error::Reqwest(error) => match error.status() {
+				| Some(status) => Self::of_status(status),
+				| None => no_records(error).then_some(Self::NoDns),
+			},
+			| _ => None,
+		}
+	}
+
+	pub(super) fn of_status(status: StatusCode) -> Option<Self> {
+		match status {
+			| StatusCode::FORBIDDEN => Some(Self::Forbidden),
+			| StatusCode::GONE => Some(Self::Gone),
+			| _ => None,
+		}
+	}
+}
+
+impl fmt::Display for Symptom {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			| Self::Forbidden => "forbidden",
+			| Self::Gone => "gone",
+			| Self::NoDns => "no DNS records",
+		})
+	}
+}
+
+/// Whether the request failed as the destination's name has no addresses.
+fn no_records(error: &reqwest::Error) -> bool {
+	let mut source = error.source();
+	while let Some(error) = source {
+		if let Some(error) = error.downcast_ref::<ResolveError>() {
+			return matches!(
+				error.kind(),
+				Proto(error) if matches!(error.kind(), ProtoErrorKind::NoRecordsFound { .. })
+			);
+		}
+
+		source = error.source();
+	}
+
+	false
+}
+
+impl Tracker {
+	/// A transaction to the destination failed at `now`, showing `symptom`.
+	/// Returns the streak when it has just lasted `window` milliseconds, for
+	/// the destination to be reported once. A failure without a symptom
+	/// breaks the streak; a `window` of zero classifies nothing.
+	pub(super) fn failed(
+		&self,
+		server: &ServerName,
+		symptom: Option<Symptom>,
+		now: u64,
+		window: u64,
+	) -> Option<Streak> {
+		let mut destinations = self.destinations.write().expect("locked for writing");
+		let Some(symptom) = symptom else {
+			if let Some(state) = destinations.get_mut(server) {
+				state.streak = None;
+			}
+
+			return None;
+		};
+
+		let state = destinations.entry(server.to_owned()).or_default();
+		let streak = state
+			.streak
+			.get_or_insert(Streak { symptom, since: now, failures: 0 });
+
+		streak.symptom = symptom;
+		streak.failures = streak.failures.saturating_add(1);
+		let lasted = window > 0
+			&& streak.failures >= MIN_FAILURES
+			&& now.saturating_sub(streak.since) >= window;
+
+		if state.classified || !lasted {
+			return None;
+		}
+
+		state.classified = true;
+		Some(*streak)
+	}
+
+	/// We exchanged something with the destination, so it is healthy again.
+	/// Returns whether it had been classified or marked defunct.
+	pub(super) fn succeeded(&self, server: &ServerName) -> bool {
+		if !self
+			.destinations
+			.read()
+			.expect("locked for reading")
+			.contains_key(server)
+		{
+			return false;
+		}
+
+		self.hold.lock().expect("locked").remove(server);
+		self.destinations
+			.write()
+			.expect("locked for writing")
+			.remove(server)
+			.is_some_and(|state| state.classified || state.mark.is_some())
+	}
+
+	/// An admin marked the destination defunct.
+	pub(super) fn mark(&self, server: &ServerName, mark: Mark) {
+		let mut destinations = self.destinations.write().expect("locked for writing");
+		destinations.entry(server.to_owned()).or_default().mark = Some(mark);
+	}
+
+	pub(super) fn marked(&self, server: &ServerName) -> Option<Mark> {
+		self.destinations
+			.read()
+			.expect("locked for reading")
+			.get(server)
+			.and_then(|state| state.mark)
+	}
+
+	/// Asks for the destination to be left until `retry_at`, in milliseconds
+	/// since the epoch, by the worker sending to it.
+	pub(super) fn hold(&self, server: &ServerName, retry_at: u64) {
+		self.hold
+			.lock()
+			.expect("locked")
+			.insert(server.to_owned(), retry_at);
+	}
+
+	/// Until when the destination was asked to be left; asking once answers
+	/// once.
+	pub(super) fn take_hold(&self, server: &ServerName) -> Option<u64> {
+		self.hold.lock().expect("locked").remove(server)
+	}
+}
@@ -0,0 +1,160 @@
+use std::{
+	collections::HashMap,
+	sync::{
+		RwLock,
+		atomic::{AtomicU64, Ordering},
+	},
+	time::Duration,
+};
+
+use conduwuit::{Server, utils::time::now_millis};
+use ruma::{OwnedServerName, ServerName};
+
+/// Age of the oldest unsent PDU for each federation destination, kept as PDUs
+/// are queued and transactions complete so that computing it is cheap.
+#[derive(Debug, Default)]
+pub(super) struct Tracker {
+	destinations: RwLock<HashMap<OwnedServerName, Pending>>,
+	published: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Pending {
+	/// Milliseconds since the epoch at which the oldest unsent PDU was queued.
+	oldest: u64,
+
+	/// Backoff has reached `federation_backoff_max`.
+	dormant: bool,
+}
+
+/// Outbound federation lag across destinations. Dormant destinations are
+/// excluded from the ages, as servers which have been down for days would
+/// otherwise mask lag to those still reachable.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Lag {
+	/// Age of the oldest unsent PDU to any destination.
+	pub max: Duration,
+
+	/// 95th percentile of the age of the oldest unsent PDU per destination.
+	pub p95: Duration,
+
+	/// Destinations more than [`BEHIND`] behind.
+	pub behind: usize,
+
+	/// Destinations with unsent PDUs which are in long-term backoff.
+	pub dormant: usize,
+
+	/// Destinations with unsent PDUs.
+	pub destinations: usize,
+}
+
+/// A destination this far behind is counted in [`Lag::behind`].
+pub const BEHIND: Duration = Duration::from_secs(300);
+
+/// Minimum interval between publishing the lag to the server metrics.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(10);
+
+impl Tracker {
+	/// PDUs were queued for the destinations at `now`; their lag starts now
+	/// unless older PDUs are still unsent.
+	pub(super) fn queued<'a, I>(&self, servers: I, now: u64)
+	where
+		I: Iterator<Item = &'a ServerName>,
+	{
+		let mut destinations = self.destinations.write().expect("locked for writing");
+		for server in servers {
+			destinations
+				.entry(server.to_owned())
+				.or_insert(Pending { oldest: now, dormant: false });
+		}
+	}
+
+	/// A transaction to the destination succeeded and the next one holds
+	/// PDUs queued no earlier than `oldest`; none remain unsent without one.
+	pub(super) fn sending(&self, server: &ServerName, oldest: Option<u64>) {
+		let mut destinations = self.destinations.write().expect("locked for writing");
+		match oldest {
+			| Some(oldest) => {
+				destinations.insert(server.to_owned(), Pending { oldest, dormant: false });
+			},
+			| None => {
+				destinations.remove(server);
+			},
+		}
+	}
+
+	/// A transaction to the destination failed.
+	pub(super) fn failed(&self, server: &ServerName, dormant: bool) {
+		if let Some(pending) = self
+			.destinations
+			.write()
+			.expect("locked for writing")
+			.get_mut(server)
+		{
+			pending.dormant = dormant;
+		}
+	}
+
+	/// Age of the oldest unsent PDU and whether the destination is dormant,
+	/// for every destination with unsent PDUs, oldest first.
+	pub(super) fn destinations(&self, now: u64) -> Vec<(OwnedServerName, Duration, bool)> {
+		let mut destinations: Vec<_> = self
+			.destinations
+			.read()
+			.expect("locked for reading")
+			.iter()
+			.map(|(server, pending)| {
+				let age = Duration::from_millis(now.saturating_sub(pending.oldest));
+				(server.clone(), age, pending.dormant)
+			})
+			.collect();
+
+		destinations.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+		destinations
+	}
+
+	pub(super) fn lag(&self, now: u64) -> Lag {
+		let destinations = self.destinations(now);
+		let ages: Vec<_> = destinations
+			.iter()
+			.filter(|(.., dormant)| !dormant)
+			.map(|(_, age, _)| *age)
+			.collect();
+
+		// Oldest first: the 95th percentile is 5% of the way down.
+		let p95 = ages.len().saturating_mul(5).div_ceil(100).saturating_sub(1);
+
+		Lag {
+			max: ages.first().copied().unwrap_or_default(),
+			p95: ages.get(p95).copied().unwrap_or_default(),
+			behind: ages.iter().filter(|&&age| age > BEHIND).count(),
+			dormant: destinations.len().saturating_sub(ages.len()),
+			destinations: destinations.len(),
+		}
+	}
+
+	/// Publishes the lag to the server metrics, at most once per
+	/// [`PUBLISH_INTERVAL`].
+	pub(super) fn publish(&self, server: &Server) {
+		let now = now_millis();
+		let interval = u64::try_from(PUBLISH_INTERVAL.as_millis()).unwrap_or(u64::MAX);
+		let last = self.published.load(Ordering::Relaxed);
+		if now.saturating_sub(last) < interval
+			|| self
+				.published
+				.compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+				.is_err()
+		{
+			return;
+		}
+
+		let lag = self.lag(now);
+		let metrics = &server.metrics;
+		metrics.set("federation_lag_max_secs", lag.max.as_secs());
+		metrics.set("federation_lag_p95_secs", lag.p95.as_secs());
+		metrics.set("federation_lag_behind", lag.behind.try_into().unwrap_or(u64::MAX));
+		metrics.set("federation_lag_dormant", lag.dormant.try_into().unwrap_or(u64::MAX));
+		metrics
+			.set("federation_lag_destinations", lag.destinations.try_into().unwrap_or(u64::MAX));
+	}
+}
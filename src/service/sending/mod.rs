@@ -1,36 +1,52 @@
 mod appservice;
+mod backoff;
+mod batch;
 mod data;
+mod defunct;
 mod dest;
+mod lag;
+mod queues;
 mod sender;
+#[cfg(test)]
+mod tests;
 
 use std::{
+	collections::HashMap,
 	fmt::Debug,
 	hash::{DefaultHasher, Hash, Hasher},
 	iter::once,
 	sync::Arc,
+	time::Duration,
 };
 
 use async_trait::async_trait;
 use conduwuit::{
-	Result, Server, debug, debug_warn, err, error,
+	Result, Server, at, debug, debug_warn, err, error, info,
 	smallvec::SmallVec,
-	utils::{ReadyExt, TryReadyExt, available_parallelism, math::usize_from_u64_truncated},
+	utils::{
+		ReadyExt, TryReadyExt, available_parallelism, math::usize_from_u64_truncated,
+		time::now_millis,
+	},
 	warn,
 };
 use futures::{FutureExt, Stream, StreamExt};
 use ruma::{
-	RoomId, ServerName, UserId,
+	OwnedServerName, RoomId, ServerName, UserId,
 	api::{OutgoingRequest, appservice::Registration},
 };
 use tokio::{task, task::JoinSet};
 
 use self::data::Data;
 pub use self::{
+	backoff::{Backoff, Class},
+	defunct::{Mark, Streak, Symptom},
 	dest::Destination,
+	lag::{BEHIND, Lag},
+	queues::{Queue, Queues},
 	sender::{EDU_LIMIT, PDU_LIMIT},
 };
 use crate::{
-	Dep, account_data, client, federation, globals, presence, pusher, rooms,
+	Dep, account_data, admin, client, federation, globals, presence, pusher, rooms,
 	rooms::timeline::RawPduId, users,
 };
 
@@ -39,9 +55,14 @@ pub struct Service {
 	server: Arc<Server>,
 	services: Services,
 	channels: Vec<(loole::Sender<Msg>, loole::Receiver<Msg>)>,
+	lag: lag::Tracker,
+	queues: queues::Tracker,
+	defunct: defunct::Tracker,
+	batches: batch::Stats,
 }
 
 struct Services {
+	admin: Dep<admin::Service>,
 	client: Dep<client::Service>,
 	globals: Dep<globals::Service>,
 	state: Dep<rooms::state::Service>,
@@ -86,6 +107,7 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 			db: Data::new(&args),
 			server: args.server.clone(),
 			services: Services {
+				admin: args.depend::<admin::Service>("admin"),
 				client: args.depend::<client::Service>("client"),
 				globals: args.depend::<globals::Service>("globals"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
@@ -101,10 +123,19 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				federation: args.depend::<federation::Service>("federation"),
 			},
 			channels: (0..num_senders).map(|_| loole::unbounded()).collect(),
+			lag: lag::Tracker::default(),
+			queues: queues::Tracker::default(),
+			defunct: defunct::Tracker::default(),
+			batches: batch::Stats::default(),
 		}))
 	}
 
 	async fn worker(self: Arc<Self>) -> Result {
+		self.db
+			.defunct()
+			.ready_for_each(|(server, mark)| self.defunct.mark(&server, mark))
+			.await;
+
 		let mut senders =
 			self.channels
 				.iter()
@@ -202,6 +233,13 @@ pub async fn send_pdu_servers<'a, S>(&self, servers: S, pdu_id: &RawPduId) -> Re
 
 		let _cork = self.db.db.cork();
 		let keys = self.db.queue_requests(requests.iter().map(|(o, e)| (e, o)));
+		self.lag.queued(
+			requests.iter().filter_map(|(dest, _)| match dest {
+				| Destination::Federation(server) => Some(&**server),
+				| _ => None,
+			}),
+			now_millis(),
+		);
 
 		for ((dest, event), queue_id) in requests.into_iter().zip(keys) {
 			self.dispatch(Msg { dest, event, queue_id })?;
@@ -289,6 +327,135 @@ pub async fn flush_servers<'a, S>(&self, servers: S) -> Result<()>
 			.await
 	}
 
+	/// Lag of outbound federation over all destinations.
+	#[must_use]
+	pub fn lag(&self) -> Lag { self.lag.lag(now_millis()) }
+
+	/// Each destination with unsent PDUs, the age of its oldest and whether it
+	/// is dormant in long-term backoff; oldest first.
+	#[must_use]
+	pub fn lagging(&self) -> Vec<(OwnedServerName, Duration, bool)> {
+		self.lag.destinations(now_millis())
+	}
+
+	/// Queued PDUs and EDUs and the backoff of each federation destination
+	/// with anything queued or which has been sent to, or only of `server`.
+	pub async fn queues(&self, server: Option<&ServerName>) -> Queues {
+		let events = match server {
+			| Some(server) => {
+				let dest = Destination::Federation(server.to_owned());
+				self.db
+					.active_requests_for(&dest)
+					.map(at!(1))
+					.chain(self.db.queued_requests(&dest).map(at!(1)))
+					.map(move |event| (server.to_owned(), event))
+					.boxed()
+			},
+			| None => self
+				.db
+				.active_requests()
+				.chain(self.db.queued_all())
+				.ready_filter_map(|(_, event, dest, _)| match dest {
+					| Destination::Federation(server) => Some((server, event)),
+					| _ => None,
+				})
+				.boxed(),
+		};
+
+		let queued: HashMap<OwnedServerName, (usize, usize)> = events
+			.ready_fold(HashMap::new(), |mut queued, (server, event)| {
+				let (pdus, edus) = queued.entry(server).or_default();
+				match event {
+					| SendingEvent::Pdu(_) => *pdus = pdus.saturating_add(1),
+					| SendingEvent::Edu(_) => *edus = edus.saturating_add(1),
+					| SendingEvent::Flush => {},
+				}
+
+				queued
+			})
+			.await;
+
+		let queued = queued
+			.into_iter()
+			.map(|(server, (pdus, edus))| (server, pdus, edus));
+
+		let mut queues = self.queues.queues(queued, now_millis());
+		if let Some(server) = server {
+			queues.destinations.retain(|dest, _| **dest == *server);
+		}
+
+		queues
+	}
+
+	/// Retries sending to the federation destination now, without waiting for
+	/// its backoff to end.
+	pub fn retry(&self, server: &ServerName) -> Result {
+		self.queues.retry(server);
+		self.dispatch(Msg {
+			dest: Destination::Federation(server.to_owned()),
+			event: SendingEvent::Flush,
+			queue_id: Vec::<u8>::new(),
+		})
+	}
+
+	/// Marks the federation destination defunct, leaving it dormant in the
+	/// longest backoff and, with `hide_heroes`, its users out of the heroes
+	/// of room summaries, until it answers again. Returns its backoff.
+	pub async fn mark_defunct(&self, server: &ServerName, hide_heroes: bool) -> Result<Backoff> {
+		let now = now_millis();
+		let curve = backoff::Curve::new(&self.server.config);
+		let backoff = self
+			.queues(Some(server))
+			.await
+			.destinations
+			.remove(server)
+			.and_then(|queue| queue.backoff);
+
+		let class = backoff.map_or(Class::ClientError, |backoff| backoff.class);
+		let tries = backoff
+			.map_or(0, |backoff| backoff.tries)
+			.max(curve.dormant_tries(class));
+
+		let max = Duration::from_secs(self.server.config.federation_backoff_max);
+		let retry_at = now.saturating_add(u64::try_from(max.as_millis()).unwrap_or(u64::MAX));
+		let backoff = Backoff { class, tries, retry_at };
+		let mark = Mark { at: now, hide_heroes };
+
+		self.db.set_backoff(server, &backoff);
+		self.db.set_defunct(server, &mark);
+		self.defunct.mark(server, mark);
+		self.defunct.hold(server, retry_at);
+		self.lag.failed(server, true);
+		self.queues.resumed(server, backoff);
+
+		// the worker sending to it takes the hold when it sees the flush
+		self.dispatch(Msg {
+			dest: Destination::Federation(server.to_owned()),
+			event: SendingEvent::Flush,
+			queue_id: Vec::<u8>::new(),
+		})?;
+
+		Ok(backoff)
+	}
+
+	/// We exchanged something with the federation destination, so it is no
+	/// longer defunct if it looked to be.
+	pub fn exchanged(&self, server: &ServerName) {
+		if self.defunct.succeeded(server) {
+			self.db.clear_defunct(server);
+			info!(%server, "Federation destination which looked defunct answered again");
+		}
+	}
+
+	/// Whether the user's server was marked defunct with its users hidden
+	/// from the heroes of room summaries.
+	#[must_use]
+	pub fn hidden_hero(&self, user_id: &UserId) -> bool {
+		self.defunct
+			.marked(user_id.server_name())
+			.is_some_and(|mark| mark.hide_heroes)
+	}
+
 	/// Sends a request to a federation server
 	#[inline]
 	pub async fn send_federation_request<T>(
@@ -0,0 +1,150 @@
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	sync::{Mutex, RwLock},
+};
+
+use ruma::{OwnedServerName, ServerName};
+
+use super::backoff::Backoff;
+
+/// How transactions to each federation destination have been going, shared
+/// by the sender workers so it can be looked at from outside them.
+#[derive(Debug, Default)]
+pub(super) struct Tracker {
+	destinations: RwLock<HashMap<OwnedServerName, Attempts>>,
+	retry: Mutex<HashSet<OwnedServerName>>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Attempts {
+	backoff: Option<Backoff>,
+
+	last_error: Option<String>,
+
+	/// Milliseconds since the epoch at which a transaction last succeeded.
+	last_success: Option<u64>,
+}
+
+/// The queue of a federation destination, as of a snapshot.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Queue {
+	/// PDUs waiting to be sent or in a transaction not yet acknowledged.
+	pub pdus: usize,
+
+	/// EDUs likewise; those gathered when a transaction is composed, such as
+	/// presence and receipts, are not queued and not counted.
+	pub edus: usize,
+
+	/// The destination's backoff, while it lasts.
+	pub backoff: Option<Backoff>,
+
+	/// Error of the last failed transaction, even after one succeeded since.
+	pub last_error: Option<String>,
+
+	/// Milliseconds since the epoch at which a transaction last succeeded,
+	/// since the server started.
+	pub last_success: Option<u64>,
+}
+
+/// Queues of the federation destinations with anything queued or which have
+/// failed, and their totals.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Queues {
+	pub destinations: BTreeMap<OwnedServerName, Queue>,
+}
+
+impl Tracker {
+	/// A transaction to the destination failed, leaving it to back off.
+	pub(super) fn failed(&self, server: &ServerName, backoff: Backoff, error: String) {
+		let mut destinations = self.destinations.write().expect("locked for writing");
+		let attempts = destinations.entry(server.to_owned()).or_default();
+		attempts.backoff = Some(backoff);
+		attempts.last_error = Some(error);
+	}
+
+	/// The destination is backing off since before the server started.
+	pub(super) fn resumed(&self, server: &ServerName, backoff: Backoff) {
+		let mut destinations = self.destinations.write().expect("locked for writing");
+		destinations.entry(server.to_owned()).or_default().backoff = Some(backoff);
+	}
+
+	/// A transaction to the destination succeeded at `now`, returning whether
+	/// it was backing off.
+	pub(super) fn succeeded(&self, server: &ServerName, now: u64) -> bool {
+		let mut destinations = self.destinations.write().expect("locked for writing");
+		let attempts = destinations.entry(server.to_owned()).or_default();
+		attempts.last_success = Some(now);
+		attempts.backoff.take().is_some()
+	}
+
+	/// Asks for the destination to be retried now despite its backoff.
+	pub(super) fn retry(&self, server: &ServerName) {
+		self.retry.lock().expect("locked").insert(server.to_owned());
+	}
+
+	/// Whether the destination was asked to be retried now; asking once
+	/// answers once.
+	pub(super) fn take_retry(&self, server: &ServerName) -> bool {
+		self.retry.lock().expect("locked").remove(server)
+	}
+
+	/// Queues of the destinations given their queued PDUs and EDUs, along
+	/// with every destination known to have failed or succeeded. Backoff
+	/// which ended by `now` is not reported.
+	pub(super) fn queues<I>(&self, queued: I, now: u64) -> Queues
+	where
+		I: IntoIterator<Item = (OwnedServerName, usize, usize)>,
+	{
+		let mut destinations: BTreeMap<_, Queue> = self
+			.destinations
+			.read()
+			.expect("locked for reading")
+			.iter()
+			.map(|(server, attempts)| {
+				let queue = Queue {
+					backoff: attempts.backoff.filter(|backoff| backoff.retry_at > now),
+					last_error: attempts.last_error.clone(),
+					last_success: attempts.last_success,
+					..Queue::default()
+				};
+
+				(server.clone(), queue)
+			})
+			.collect();
+
+		for (server, pdus, edus) in queued {
+			let queue = destinations.entry(server).or_default();
+			queue.pdus = queue.pdus.saturating_add(pdus);
+			queue.edus = queue.edus.saturating_add(edus);
+		}
+
+		Queues { destinations }
+	}
+}
+
+impl Queues {
+	/// Queued PDUs across destinations.
+	#[must_use]
+	pub fn pdus(&self) -> usize {
+		self.destinations
+			.values()
+			.fold(0, |total, queue| total.saturating_add(queue.pdus))
+	}
+
+	/// Queued EDUs across destinations.
+	#[must_use]
+	pub fn edus(&self) -> usize {
+		self.destinations
+			.values()
+			.fold(0, |total, queue| total.saturating_add(queue.edus))
+	}
+
+	/// Destinations backing off after failing.
+	#[must_use]
+	pub fn backing_off(&self) -> usize {
+		self.destinations
+			.values()
+			.filter(|queue| queue.backoff.is_some())
+			.count()
+	}
+}
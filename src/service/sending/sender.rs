@@ -1,22 +1,23 @@
 use std::{
 	collections::{BTreeMap, HashMap, HashSet},
-	fmt::Debug,
+	fmt::{Debug, Write},
 	sync::{
 		Arc,
 		atomic::{AtomicU64, AtomicUsize, Ordering},
 	},
-	time::{Duration, Instant},
+	time::{Duration, Instant, SystemTime},
 };
 
 use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
 use conduwuit::{
-	Error, Result, debug, err, error,
+	Error, Result, at, debug, err, error,
 	result::LogErr,
 	trace,
 	utils::{
 		ReadyExt, calculate_hash, continue_exponential_backoff_secs,
 		future::TryExtExt,
 		stream::{BroadbandExt, IterStream, WidebandExt},
+		time::{now_millis, pretty},
 	},
 	warn,
 };
@@ -31,6 +32,7 @@
 	RoomId, RoomVersionId, ServerName, UInt,
 	api::{
 		appservice::event::push_events::v1::EphemeralData,
+		client::error::{ErrorKind, RetryAfter},
 		federation::transactions::{
 			edu::{
 				DeviceListUpdateContent, Edu, PresenceContent, PresenceUpdate, ReceiptContent,
@@ -51,14 +53,20 @@
 use serde_json::value::{RawValue as RawJsonValue, to_raw_value};
 
 use super::{
-	Destination, EduBuf, EduVec, Msg, SendingEvent, Service, appservice, data::QueueItem,
+	Destination, EduBuf, EduVec, Msg, SendingEvent, Service, appservice,
+	backoff::{self, Backoff, Class, Curve},
+	batch,
+	data::QueueItem,
+	defunct::{Streak, Symptom},
 };
+use crate::client;
 
 #[derive(Debug)]
 enum TransactionStatus {
 	Running,
-	Failed(u32, Instant), // number of times failed, time of last failure
-	Retrying(u32),        // number of times failed
+	// number of times failed, time of last failure, time not to retry before
+	Failed(u32, Instant, Option<Instant>),
+	Retrying(u32), // number of times failed
 }
 
 type SendingError = (Destination, Error);
@@ -71,6 +79,7 @@ enum TransactionStatus {
 const SELECT_RECEIPT_LIMIT: usize = 256;
 const SELECT_EDU_LIMIT: usize = EDU_LIMIT - 2;
 const DEQUEUE_LIMIT: usize = 48;
+const DEQUEUE_EDU_LIMIT: usize = EDU_LIMIT * 4;
 
 pub const PDU_LIMIT: usize = 50;
 pub const EDU_LIMIT: usize = 100;
@@ -81,10 +90,17 @@ pub(super) async fn sender(self: Arc<Self>, id: usize) -> Result {
 		let mut statuses: CurTransactionStatus = CurTransactionStatus::new();
 		let mut futures: SendingFutures<'_> = FuturesUnordered::new();
 
+		let resumed = self.resume_backoff(id, &mut statuses).await;
+
 		self.startup_netburst(id, &mut futures, &mut statuses)
 			.boxed()
 			.await;
 
+		let curve = Curve::new(&self.server.config);
+		for (server, backoff) in resumed {
+			self.lag.failed(&server, backoff.dormant(&curve));
+		}
+
 		self.work_loop(id, &mut futures, &mut statuses).await;
 
 		if !futures.is_empty() {
@@ -137,22 +153,162 @@ async fn handle_response<'a>(
 	) {
 		match response {
 			| Ok(dest) => self.handle_response_ok(&dest, futures, statuses).await,
-			| Err((dest, e)) => Self::handle_response_err(dest, statuses, &e),
+			| Err((dest, e)) => {
+				if let Destination::Federation(server) = &dest {
+					self.observe_failure(server, &e).await;
+				}
+
+				self.handle_response_err(dest, statuses, &e);
+			},
 		}
+
+		self.lag.publish(&self.server);
 	}
 
-	fn handle_response_err(dest: Destination, statuses: &mut CurTransactionStatus, e: &Error) {
+	fn handle_response_err(
+		&self,
+		dest: Destination,
+		statuses: &mut CurTransactionStatus,
+		e: &Error,
+	) {
 		debug!(dest = ?dest, "{e:?}");
-		statuses.entry(dest).and_modify(|e| {
-			*e = match e {
-				| TransactionStatus::Running => TransactionStatus::Failed(1, Instant::now()),
-				| &mut TransactionStatus::Retrying(ref n) =>
-					TransactionStatus::Failed(n.saturating_add(1), Instant::now()),
-				| TransactionStatus::Failed(..) => {
-					panic!("Request that was not even running failed?!")
-				},
-			}
-		});
+		let tries = match statuses.get(&dest) {
+			| Some(TransactionStatus::Running) => 1,
+			| Some(TransactionStatus::Retrying(n)) => n.saturating_add(1),
+			| Some(TransactionStatus::Failed(..)) => {
+				panic!("Request that was not even running failed?!")
+			},
+			| None => return,
+		};
+
+		let delay = match &dest {
+			| Destination::Federation(server) => Some(self.federation_failed(server, tries, e)),
+			| _ => {
+				let max = Duration::from_secs(self.server.config.sender_retry_backoff_limit);
+				retry_after(e).map(|delay| delay.min(max))
+			},
+		};
+
+		let retry_at = delay.and_then(|delay| Instant::now().checked_add(delay));
+		statuses.insert(dest, TransactionStatus::Failed(tries, Instant::now(), retry_at));
+	}
+
+	/// Backs off from a federation destination after its `tries`th failed
+	/// transaction in a row, returning how long to wait before retrying.
+	fn federation_failed(&self, server: &ServerName, tries: u32, e: &Error) -> Duration {
+		let config = &self.server.config;
+		let curve = Curve::new(config);
+		let now = now_millis();
+		let max = Duration::from_secs(config.federation_backoff_max);
+		let retry_at = retry_after(e)
+			.map(|delay| delay.min(max))
+			.map(|delay| u64::try_from(delay.as_millis()).unwrap_or(u64::MAX))
+			.map(|delay| now.saturating_add(delay));
+
+		// a destination marked defunct stays dormant until it answers
+		let class = Class::of(e);
+		let tries = match self.defunct.marked(server) {
+			| Some(_) => tries.max(curve.dormant_tries(class)),
+			| None => tries,
+		};
+
+		let backoff = Backoff::failed(&curve, class, tries, retry_at, now, backoff::jitter());
+
+		self.db.set_backoff(server, &backoff);
+		self.lag.failed(server, backoff.dormant(&curve));
+		self.queues.failed(server, backoff, e.to_string());
+
+		backoff.remaining(now)
+	}
+
+	/// Notes what the transaction to the federation destination failed with,
+	/// reporting the destination once it has looked defunct for as long as
+	/// `federation_defunct_window`.
+	async fn observe_failure(&self, server: &ServerName, e: &Error) {
+		let window = self
+			.server
+			.config
+			.federation_defunct_window
+			.saturating_mul(1000);
+
+		let Some(streak) = self
+			.defunct
+			.failed(server, Symptom::of(e), now_millis(), window)
+		else {
+			return;
+		};
+
+		warn!(
+			%server,
+			symptom = %streak.symptom,
+			failures = streak.failures,
+			"Federation destination looks defunct"
+		);
+
+		if let Err(e) = self.report_defunct(server, &streak).await {
+			error!(%server, "Failed to report defunct destination: {e}");
+		}
+	}
+
+	/// Tells the admin room which rooms, and how many local users in them,
+	/// share the destination which looks defunct.
+	async fn report_defunct(&self, server: &ServerName, streak: &Streak) -> Result {
+		const ROOMS_LISTED: usize = 20;
+
+		let room_ids: Vec<OwnedRoomId> = self
+			.services
+			.state_cache
+			.server_rooms(server)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let mut users = HashSet::new();
+		let mut rooms = Vec::with_capacity(room_ids.len());
+		for room_id in room_ids {
+			let local: Vec<OwnedUserId> = self
+				.services
+				.state_cache
+				.local_users_in_room(&room_id)
+				.map(ToOwned::to_owned)
+				.collect()
+				.await;
+
+			rooms.push((local.len(), room_id));
+			users.extend(local);
+		}
+
+		rooms.sort_unstable_by(|a, b| b.cmp(a));
+
+		let lasted = Duration::from_millis(now_millis().saturating_sub(streak.since));
+		let mut msg = format!(
+			"Federation destination **{server}** looks defunct: the last {} transactions to it \
+			 over {} failed ({}).\n\nWe share {} rooms with it, with {} local users in them.\n",
+			streak.failures,
+			pretty(lasted),
+			streak.symptom,
+			rooms.len(),
+			users.len(),
+		);
+
+		for (local, room_id) in rooms.iter().take(ROOMS_LISTED) {
+			writeln!(msg, "- {room_id}: {local} local users")?;
+		}
+
+		if rooms.len() > ROOMS_LISTED {
+			writeln!(msg, "- and {} more", rooms.len().saturating_sub(ROOMS_LISTED))?;
+		}
+
+		write!(
+			msg,
+			"\nIt counts as healthy again as soon as it answers. `!admin federation \
+			 mark-defunct {server}` leaves it dormant in the longest backoff until then; with \
+			 `--hide-heroes` its users are also left out of room summaries."
+		)?;
+
+		self.services.admin.send_text(&msg).await;
+
+		Ok(())
 	}
 
 	#[allow(clippy::needless_pass_by_ref_mut)]
@@ -166,19 +322,22 @@ async fn handle_response_ok<'a>(
 		self.db.delete_all_active_requests_for(dest).await;
 
 		// Find events that have been added since starting the last request
-		let new_events = self
-			.db
-			.queued_requests(dest)
-			.take(DEQUEUE_LIMIT)
-			.collect::<Vec<_>>()
-			.await;
+		let new_events = self.dequeue(dest).await;
+
+		if let Destination::Federation(server) = dest {
+			let oldest = new_events.iter().filter_map(at!(2)).min();
+			self.lag.sending(server, oldest);
+			if self.queues.succeeded(server, now_millis()) {
+				self.db.clear_backoff(server);
+			}
+
+			self.exchanged(server);
+		}
 
 		// Insert any pdus we found
 		if !new_events.is_empty() {
-			self.db.mark_as_active(new_events.iter());
-
-			let new_events_vec = new_events.into_iter().map(|(_, event)| event).collect();
-			futures.push(self.send_events(dest.clone(), new_events_vec));
+			let events = self.compose(dest, new_events).await;
+			futures.push(self.send_events(dest.clone(), events));
 		} else {
 			statuses.remove(dest);
 		}
@@ -192,7 +351,8 @@ async fn handle_request<'a>(
 		futures: &mut SendingFutures<'a>,
 		statuses: &mut CurTransactionStatus,
 	) {
-		let iv = vec![(msg.queue_id, msg.event)];
+		let enqueued = matches!(msg.event, SendingEvent::Pdu(_)).then(now_millis);
+		let iv = vec![(msg.queue_id, msg.event, enqueued)];
 		if let Ok(Some(events)) = self.select_events(&msg.dest, iv, statuses).await {
 			if !events.is_empty() {
 				futures.push(self.send_events(msg.dest, events));
@@ -231,6 +391,31 @@ async fn finish_responses<'a>(&'a self, futures: &mut SendingFutures<'a>) {
 		}
 	}
 
+	/// Carries on the backoff of the federation destinations this worker
+	/// sends to which were backing off when the server stopped, so they are
+	/// not all retried the moment it starts again.
+	async fn resume_backoff(
+		&self,
+		id: usize,
+		statuses: &mut CurTransactionStatus,
+	) -> Vec<(OwnedServerName, Backoff)> {
+		let now = now_millis();
+		self.db
+			.backoffs()
+			.ready_filter(|(server, _)| {
+				self.shard_id(&Destination::Federation(server.clone())) == id
+			})
+			.ready_fold(Vec::new(), |mut resumed, (server, backoff)| {
+				let retry_at = Instant::now().checked_add(backoff.remaining(now));
+				let status = TransactionStatus::Failed(backoff.tries, Instant::now(), retry_at);
+				statuses.insert(Destination::Federation(server.clone()), status);
+				self.queues.resumed(&server, backoff);
+				resumed.push((server, backoff));
+				resumed
+			})
+			.await
+	}
+
 	#[tracing::instrument(
 		name = "netburst",
 		level = "debug",
@@ -247,9 +432,10 @@ async fn startup_netburst<'a>(
 		let keep =
 			usize::try_from(self.server.config.startup_netburst_keep).unwrap_or(usize::MAX);
 		let mut txns = HashMap::<Destination, Vec<SendingEvent>>::new();
+		let mut oldest = HashMap::<OwnedServerName, u64>::new();
 		let mut active = self.db.active_requests().boxed();
 
-		while let Some((key, event, dest)) = active.next().await {
+		while let Some((key, event, dest, enqueued)) = active.next().await {
 			if self.shard_id(&dest) != id {
 				continue;
 			}
@@ -260,10 +446,25 @@ async fn startup_netburst<'a>(
 				self.db.delete_active_request(&key);
 			} else {
 				entry.push(event);
+				if let (Destination::Federation(server), Some(enqueued)) = (&dest, enqueued) {
+					oldest
+						.entry(server.clone())
+						.and_modify(|oldest| *oldest = enqueued.min(*oldest))
+						.or_insert(enqueued);
+				}
 			}
 		}
 
+		for (server, oldest) in oldest {
+			self.lag.sending(&server, Some(oldest));
+		}
+
 		for (dest, events) in txns {
+			// Those still backing off are retried once it ends, like any other
+			if statuses.contains_key(&dest) {
+				continue;
+			}
+
 			if self.server.config.startup_netburst && !events.is_empty() {
 				statuses.insert(dest.clone(), TransactionStatus::Running);
 				futures.push(self.send_events(dest.clone(), events));
@@ -294,39 +495,121 @@ async fn select_events(
 		}
 
 		let _cork = self.db.db.cork();
-		let mut events = Vec::new();
 
 		// Must retry any previous transaction for this remote.
 		if retry {
-			self.db
+			let events: Vec<_> = self
+				.db
 				.active_requests_for(dest)
-				.ready_for_each(|(_, e)| events.push(e))
+				.map(|(_, event)| event)
+				.collect()
 				.await;
 
-			return Ok(Some(events));
+			return Ok(Some(self.batch(dest, events, Vec::new())));
 		}
 
 		// Compose the next transaction
-		let _cork = self.db.db.cork();
-		if !new_events.is_empty() {
-			self.db.mark_as_active(new_events.iter());
-			for (_, e) in new_events {
-				events.push(e);
+		Ok(Some(self.compose(dest, new_events).await))
+	}
+
+	/// Takes the events queued for the destination which go in its next
+	/// transaction off the queue. Federation destinations are given up to a
+	/// transaction's worth of PDUs and several of EDUs, as coalescing them
+	/// leaves fewer; the rest stay queued for the transactions after.
+	async fn dequeue(&self, dest: &Destination) -> Vec<QueueItem> {
+		let queued = self.db.queued_requests(dest);
+		if !matches!(dest, Destination::Federation(_)) {
+			return queued.take(DEQUEUE_LIMIT).collect().await;
+		}
+
+		pin_mut!(queued);
+		let (mut pdus, mut edus, mut scanned) = (0_usize, 0_usize, 0_usize);
+		let mut events = Vec::new();
+		while let Some(event) = queued.next().await {
+			let take = match event.1 {
+				| SendingEvent::Pdu(_) if pdus < PDU_LIMIT => {
+					pdus = pdus.saturating_add(1);
+					true
+				},
+				| SendingEvent::Edu(_) if edus < DEQUEUE_EDU_LIMIT => {
+					edus = edus.saturating_add(1);
+					true
+				},
+				| SendingEvent::Flush => true,
+				| _ => false,
+			};
+
+			if take {
+				events.push(event);
+			}
+
+			scanned = scanned.saturating_add(1);
+			if (pdus >= PDU_LIMIT && edus >= DEQUEUE_EDU_LIMIT)
+				|| scanned >= PDU_LIMIT.saturating_add(DEQUEUE_EDU_LIMIT)
+			{
+				break;
 			}
 		}
 
+		events
+	}
+
+	/// Composes the next transaction to the destination of the events taken
+	/// off its queue, along with the EDUs of federation destinations gathered
+	/// from their rooms.
+	async fn compose(&self, dest: &Destination, new_events: Vec<QueueItem>) -> Vec<SendingEvent> {
+		self.db.mark_as_active(new_events.iter());
+		let events = new_events.into_iter().map(|(_, event, _)| event).collect();
+
+		let Destination::Federation(server_name) = dest else {
+			return events;
+		};
+
 		// Add EDU's into the transaction
-		if let Destination::Federation(server_name) = dest {
-			if let Ok((select_edus, last_count)) = self.select_edus(server_name).await {
-				debug_assert!(select_edus.len() <= EDU_LIMIT, "exceeded edus limit");
-				let select_edus = select_edus.into_iter().map(SendingEvent::Edu);
+		let mut edus = Vec::new();
+		if let Ok((select_edus, last_count)) = self.select_edus(server_name).await {
+			edus.extend(select_edus);
+			self.db.set_latest_educount(server_name, last_count);
+		}
 
-				events.extend(select_edus);
-				self.db.set_latest_educount(server_name, last_count);
-			}
+		self.batch(dest, events, edus)
+	}
+
+	/// Coalesces the EDUs of a transaction to a federation destination, the
+	/// events' own followed by those gathered, which are newer. EDUs past the
+	/// limit of a transaction are queued again for the next one.
+	fn batch(
+		&self,
+		dest: &Destination,
+		events: Vec<SendingEvent>,
+		gathered: Vec<EduBuf>,
+	) -> Vec<SendingEvent> {
+		if !matches!(dest, Destination::Federation(_)) {
+			return events;
 		}
 
-		Ok(Some(events))
+		let (edus, mut events): (Vec<_>, Vec<_>) = events
+			.into_iter()
+			.partition(|event| matches!(event, SendingEvent::Edu(_)));
+
+		let edus = edus
+			.into_iter()
+			.filter_map(|event| match event {
+				| SendingEvent::Edu(edu) => Some(edu),
+				| _ => None,
+			})
+			.chain(gathered);
+
+		let batch = batch::coalesce(edus, EDU_LIMIT);
+		self.batches.record(&self.server, &batch);
+		if !batch.overflow.is_empty() {
+			let overflow: Vec<_> = batch.overflow.into_iter().map(SendingEvent::Edu).collect();
+			self.db
+				.queue_requests(overflow.iter().map(|event| (event, dest)));
+		}
+
+		events.extend(batch.edus.into_iter().map(SendingEvent::Edu));
+		events
 	}
 
 	fn select_events_current(
@@ -335,16 +618,44 @@ fn select_events_current(
 		statuses: &mut CurTransactionStatus,
 	) -> Result<(bool, bool)> {
 		let (mut allow, mut retry) = (true, false);
+		let forced =
+			matches!(dest, Destination::Federation(server) if self.queues.take_retry(server));
+
+		// marked defunct: leave it until the backoff the mark set
+		let held = match dest {
+			| Destination::Federation(server) if !forced => self.defunct.take_hold(server),
+			| _ => None,
+		};
+
+		if let Some(retry_at) = held {
+			let remaining = Duration::from_millis(retry_at.saturating_sub(now_millis()));
+			let tries = match statuses.get(dest) {
+				| Some(TransactionStatus::Failed(tries, ..)) => Some(*tries),
+				| None => Some(0),
+				// failing leaves it dormant as it is marked
+				| Some(_) => None,
+			};
+
+			if let Some(tries) = tries {
+				let retry_at = Instant::now().checked_add(remaining);
+				let status = TransactionStatus::Failed(tries, Instant::now(), retry_at);
+				statuses.insert(dest.clone(), status);
+			}
+		}
+
 		statuses
 			.entry(dest.clone()) // TODO: can we avoid cloning?
 			.and_modify(|e| match e {
-				TransactionStatus::Failed(tries, time) => {
-					// Fail if a request has failed recently (exponential backoff)
+				TransactionStatus::Failed(tries, time, retry_at) => {
+					// Fail if a request has failed recently: federation destinations wait
+					// until the time their backoff set, also covering Retry-After, and push
+					// gateways back off quadratically
 					let min = self.server.config.sender_timeout;
 					let max = self.server.config.sender_retry_backoff_limit;
-					if continue_exponential_backoff_secs(min, max, time.elapsed(), *tries)
-						&& !matches!(dest, Destination::Appservice(_))
-					{
+					let backoff = matches!(dest, Destination::Push(..))
+						&& continue_exponential_backoff_secs(min, max, time.elapsed(), *tries);
+
+					if !forced && (backoff || retry_at.is_some_and(|at| Instant::now() < at)) {
 						allow = false;
 					} else {
 						retry = true;
@@ -863,7 +1174,7 @@ async fn send_events_dest_federation(
 		let result = self
 			.services
 			.federation
-			.execute_on(&self.services.client.sender, &server, request)
+			.execute_on(client::Federation::Sender, &server, request)
 			.await;
 
 		for (event_id, result) in result.iter().flat_map(|resp| resp.pdus.iter()) {
@@ -919,3 +1230,21 @@ pub async fn convert_to_outgoing_federation_event(
 		to_raw_value(&pdu_json).expect("CanonicalJson is valid serde_json::Value")
 	}
 }
+
+/// Delay before retrying which a destination asked for, whether with a
+/// `Retry-After` header or `retry_after_ms`.
+fn retry_after(e: &Error) -> Option<Duration> {
+	let Error::Federation(_, e) = e else {
+		return None;
+	};
+
+	match e.error_kind()? {
+		| ErrorKind::LimitExceeded {
+			retry_after: Some(RetryAfter::Delay(delay)),
+		} => Some(*delay),
+		| ErrorKind::LimitExceeded {
+			retry_after: Some(RetryAfter::DateTime(at)),
+		} => at.duration_since(SystemTime::now()).ok(),
+		| _ => None,
+	}
+}
@@ -0,0 +1,466 @@
+use std::time::Duration;
+
+use conduwuit::{Config, config::Figment};
+use ruma::{OwnedServerName, ServerName};
+use serde_json::{Value, json};
+
+use super::{
+	EDU_LIMIT, EduBuf, SendingEvent,
+	backoff::{Backoff, Class, Curve},
+	batch::coalesce,
+	data::{parse_value, pdu_value},
+	defunct::{self, Mark, Streak, Symptom},
+	lag::{Lag, Tracker},
+	queues,
+};
+
+fn server(name: &str) -> OwnedServerName { name.try_into().expect("valid server name") }
+
+#[test]
+fn queue_record_enqueue_time() {
+	let pdu_id = [1_u8; 16];
+
+	let (event, enqueued) = parse_value(&pdu_id, &pdu_value(1_234));
+	assert_eq!(event, SendingEvent::Pdu(pdu_id[..].into()));
+	assert_eq!(enqueued, Some(1_234));
+
+	let (event, enqueued) = parse_value(&pdu_id, &[]);
+	assert_eq!(event, SendingEvent::Pdu(pdu_id[..].into()), "record without the time");
+	assert_eq!(enqueued, None);
+
+	let edu = br#"{"edu_type":"m.typing"}"#;
+	let (event, enqueued) = parse_value(&[0, 0, 0, 1], edu);
+	assert_eq!(event, SendingEvent::Edu(edu[..].into()));
+	assert_eq!(enqueued, None);
+}
+
+#[test]
+fn federation_lag() {
+	let tracker = Tracker::default();
+	assert_eq!(tracker.lag(0), Lag::default());
+
+	let servers: Vec<_> = (0..40)
+		.map(|i| server(&format!("s{i}.example.com")))
+		.collect();
+
+	// One destination queued each second from t=0 to t=39s.
+	for (i, server) in (0_u64..).zip(&servers) {
+		tracker.queued([&**server].into_iter(), i.saturating_mul(1000));
+	}
+
+	let now = 400_000;
+	let lag = tracker.lag(now);
+	assert_eq!(lag.destinations, 40);
+	assert_eq!(lag.max, Duration::from_secs(400));
+	assert_eq!(lag.p95, Duration::from_secs(399), "second oldest of 40");
+	assert_eq!(lag.behind, 40);
+	assert_eq!(lag.dormant, 0);
+
+	// Queueing more does not reset the age of what is already unsent.
+	tracker.queued([&*servers[0]].into_iter(), now);
+	assert_eq!(tracker.lag(now).max, Duration::from_secs(400));
+
+	// Dormant destinations are counted separately.
+	tracker.failed(&servers[0], true);
+	tracker.failed(&servers[1], true);
+	let lag = tracker.lag(now);
+	assert_eq!(lag.dormant, 2);
+	assert_eq!(lag.max, Duration::from_secs(398));
+	assert_eq!(lag.p95, Duration::from_secs(397));
+	assert_eq!(lag.behind, 38);
+
+	// Success moves on to the next batch, or clears the destination.
+	tracker.sending(&servers[0], Some(now.saturating_sub(10_000)));
+	for server in &servers[1..] {
+		tracker.sending(server, None);
+	}
+
+	let lag = tracker.lag(now);
+	assert_eq!(lag.destinations, 1);
+	assert_eq!((lag.max, lag.behind, lag.dormant), (Duration::from_secs(10), 0, 0));
+
+	let unknown: &ServerName = "unknown.example.com".try_into().expect("valid");
+	tracker.failed(unknown, true);
+	assert_eq!(tracker.destinations(now).len(), 1, "failures alone are not lag");
+}
+
+fn config() -> Config {
+	let config = Figment::new()
+		.join(("server_name", "example.com"))
+		.join(("database_path", "/tmp/conduwuit_sending"));
+
+	Config::new(&config).expect("valid config")
+}
+
+/// Retry delay in seconds after failing `tries` times in a row, without
+/// jitter.
+fn delay(curve: &Curve, class: Class, tries: u32) -> u64 {
+	let backoff = Backoff::failed(curve, class, tries, None, 0, 0.0);
+	backoff.remaining(0).as_secs()
+}
+
+#[test]
+fn dormant_after_backoff_limit() {
+	let curve = Curve::new(&config());
+	let dormant =
+		|class, tries| Backoff::failed(&curve, class, tries, None, 0, 0.0).dormant(&curve);
+
+	// 30s doubling reaches the default limit of a day at 13 tries.
+	assert!(!dormant(Class::Unreachable, 1));
+	assert!(!dormant(Class::Unreachable, 12));
+	assert!(dormant(Class::Unreachable, 13));
+	assert!(dormant(Class::ClientError, 11), "head start of two");
+	assert!(!dormant(Class::ServerError, 100), "capped below the limit");
+}
+
+#[test]
+fn backoff_curves() {
+	let curve = Curve::new(&config());
+
+	let unreachable: Vec<_> = (1..=5)
+		.map(|tries| delay(&curve, Class::Unreachable, tries))
+		.collect();
+	assert_eq!(unreachable, [30, 60, 120, 240, 480]);
+	assert_eq!(delay(&curve, Class::Unreachable, 40), 86_400, "capped");
+
+	// A busy server is never left for more than an hour.
+	assert_eq!(delay(&curve, Class::ServerError, 1), 30);
+	assert_eq!(delay(&curve, Class::ServerError, 8), 3_600);
+	assert_eq!(delay(&curve, Class::ServerError, 30), 3_600);
+
+	// A server answering 4xx, or refusing our signatures, is likely not coming
+	// back soon.
+	assert_eq!(delay(&curve, Class::ClientError, 1), 120);
+	assert_eq!(delay(&curve, Class::Signature, 1), 480);
+	assert_eq!(delay(&curve, Class::Signature, 20), 86_400);
+}
+
+#[test]
+fn backoff_jitter() {
+	let curve = Curve::new(&config());
+
+	// The default jitter is a fifth either way.
+	let early = Backoff::failed(&curve, Class::Unreachable, 3, None, 0, -1.0);
+	let late = Backoff::failed(&curve, Class::Unreachable, 3, None, 0, 1.0);
+	assert_eq!(early.retry_at, 96_000);
+	assert_eq!(late.retry_at, 144_000);
+
+	let capped = Backoff::failed(&curve, Class::Unreachable, 40, None, 0, 1.0);
+	assert_eq!(capped.remaining(0).as_secs(), 86_400, "jitter stays within the limit");
+}
+
+#[test]
+fn backoff_outcomes() {
+	let curve = Curve::new(&config());
+
+	// Timeouts, then the server comes up but is overloaded and asks to be left
+	// for a while, then is replaced by something answering 404.
+	let outcomes = [
+		(Class::Unreachable, None, 30_000),
+		(Class::Unreachable, None, 60_000),
+		(Class::ServerError, None, 120_000),
+		(Class::ServerError, Some(600_000), 600_000),
+		(Class::ClientError, None, 1_920_000),
+	];
+
+	let now = 1_000_000;
+	for (tries, (class, retry_after, wait)) in (1..).zip(outcomes) {
+		let retry_at = retry_after.map(|retry_after| now + retry_after);
+		let backoff = Backoff::failed(&curve, class, tries, retry_at, now, 0.0);
+		assert_eq!(backoff.class, class);
+		assert_eq!(backoff.tries, tries);
+		assert_eq!(backoff.retry_at, now + wait, "failure {tries}");
+		assert_eq!(backoff.remaining(backoff.retry_at), Duration::ZERO);
+	}
+}
+
+#[test]
+fn destination_queues() {
+	let curve = Curve::new(&config());
+	let tracker = queues::Tracker::default();
+	let (down, limited, idle) = (
+		server("down.example.com"),
+		server("limited.example.com"),
+		server("idle.example.com"),
+	);
+
+	let failed = |server, class, tries, retry_at, error: &str, now| {
+		let backoff = Backoff::failed(&curve, class, tries, retry_at, now, 0.0);
+		tracker.failed(server, backoff, error.to_owned());
+	};
+
+	// 30s after the first failure, 60s after the second.
+	failed(&down, Class::Unreachable, 1, None, "timed out", 0);
+	failed(&down, Class::Unreachable, 2, None, "refused", 1_000);
+	failed(&limited, Class::ServerError, 1, Some(3_600_000), "M_LIMIT_EXCEEDED", 0);
+	assert!(!tracker.succeeded(&idle, 500), "was not backing off");
+
+	let queues = tracker.queues([(down.clone(), 3, 1), (limited.clone(), 2, 0)], 2_000);
+	assert_eq!((queues.pdus(), queues.edus(), queues.backing_off()), (5, 1, 2));
+
+	let queue = &queues.destinations[&down];
+	let backoff = queue.backoff.expect("backing off");
+	assert_eq!((queue.pdus, queue.edus), (3, 1));
+	assert_eq!(
+		(backoff.class, backoff.tries, backoff.retry_at),
+		(Class::Unreachable, 2, 61_000)
+	);
+	assert_eq!(queue.last_error.as_deref(), Some("refused"));
+
+	let backoff = queues.destinations[&limited].backoff.expect("backing off");
+	assert_eq!(backoff.retry_at, 3_600_000, "retry-after is later");
+	assert_eq!(queues.destinations[&idle], queues::Queue {
+		last_success: Some(500),
+		..Default::default()
+	});
+
+	// The backoff ends once the destination gets through, but its last error
+	// is kept.
+	assert!(tracker.succeeded(&down, 750_000), "was backing off");
+	let queues = tracker.queues([], 750_000);
+	let queue = &queues.destinations[&down];
+	assert_eq!((queue.backoff, queue.last_success), (None, Some(750_000)));
+	assert_eq!(queue.last_error.as_deref(), Some("refused"));
+	assert_eq!(queues.backing_off(), 1);
+
+	tracker.retry(&limited);
+	assert!(tracker.take_retry(&limited));
+	assert!(!tracker.take_retry(&limited), "retried once");
+}
+
+#[test]
+fn defunct_after_window() {
+	let tracker = defunct::Tracker::default();
+	let gone = server("gone.example.com");
+	let hour = 3_600_000;
+	let window = 24 * hour;
+
+	// Classified once it has failed the same way for the whole window, and
+	// at least three times.
+	assert_eq!(tracker.failed(&gone, Some(Symptom::Forbidden), 0, window), None);
+	assert_eq!(tracker.failed(&gone, Some(Symptom::Forbidden), window, window), None);
+	assert_eq!(
+		tracker.failed(&gone, Some(Symptom::NoDns), window + hour, window),
+		Some(Streak {
+			symptom: Symptom::NoDns,
+			since: 0,
+			failures: 3
+		})
+	);
+	assert_eq!(
+		tracker.failed(&gone, Some(Symptom::NoDns), window + 2 * hour, window),
+		None,
+		"reported once"
+	);
+
+	// Anything exchanged with it makes it healthy again.
+	assert!(tracker.succeeded(&gone));
+	assert!(!tracker.succeeded(&gone), "already healthy");
+	assert_eq!(tracker.failed(&gone, Some(Symptom::Gone), window * 3, window), None);
+}
+
+#[test]
+fn defunct_streak_broken() {
+	let tracker = defunct::Tracker::default();
+	let busy = server("busy.example.com");
+	let window = 1_000;
+
+	tracker.failed(&busy, Some(Symptom::Forbidden), 0, window);
+	tracker.failed(&busy, Some(Symptom::Forbidden), 500, window);
+	assert_eq!(tracker.failed(&busy, None, 1_000, window), None, "a timeout breaks it");
+	assert_eq!(tracker.failed(&busy, Some(Symptom::Forbidden), 2_000, window), None);
+	assert_eq!(tracker.failed(&busy, Some(Symptom::Forbidden), 2_500, window), None);
+
+	let streak = tracker.failed(&busy, Some(Symptom::Forbidden), 3_000, window);
+	assert_eq!(streak.map(|streak| (streak.since, streak.failures)), Some((2_000, 3)));
+
+	// A window of zero disables classification.
+	let other = server("other.example.com");
+	for now in 0..10 {
+		assert_eq!(tracker.failed(&other, Some(Symptom::Gone), now * window, 0), None);
+	}
+
+	assert!(!tracker.succeeded(&server("never.example.com")));
+}
+
+#[test]
+fn defunct_marks() {
+	let curve = Curve::new(&config());
+	let tracker = defunct::Tracker::default();
+	let marked = server("marked.example.com");
+	let mark = Mark { at: 5, hide_heroes: true };
+
+	tracker.mark(&marked, mark);
+	tracker.hold(&marked, 86_400_000);
+	assert_eq!(tracker.marked(&marked), Some(mark));
+	assert_eq!(tracker.take_hold(&marked), Some(86_400_000));
+	assert_eq!(tracker.take_hold(&marked), None, "held once");
+
+	// Marked destinations stay in the longest backoff.
+	let tries = curve.dormant_tries(Class::Unreachable);
+	assert_eq!(tries, 13);
+	assert_eq!(curve.dormant_tries(Class::ClientError), 11);
+	let backoff = Backoff::failed(&curve, Class::Unreachable, tries, None, 0, 0.0);
+	assert!(backoff.dormant(&curve));
+
+	// Inconsistent failures keep the mark, success clears it.
+	tracker.failed(&marked, None, 0, 1_000);
+	assert_eq!(tracker.marked(&marked), Some(mark));
+	assert!(tracker.succeeded(&marked), "was marked");
+	assert_eq!(tracker.marked(&marked), None);
+}
+
+#[test]
+fn defunct_symptoms() {
+	use http::StatusCode;
+
+	assert_eq!(Symptom::of_status(StatusCode::FORBIDDEN), Some(Symptom::Forbidden));
+	assert_eq!(Symptom::of_status(StatusCode::GONE), Some(Symptom::Gone));
+	assert_eq!(Symptom::of_status(StatusCode::NOT_FOUND), None);
+	assert_eq!(Symptom::of_status(StatusCode::BAD_GATEWAY), None);
+	assert_eq!(Symptom::of(&conduwuit::err!("timed out")), None);
+}
+
+fn edu(value: &Value) -> EduBuf {
+	let mut buf = EduBuf::new();
+	serde_json::to_writer(&mut buf, value).expect("serialized");
+	buf
+}
+
+fn parse(edu: &EduBuf) -> Value { serde_json::from_slice(edu).expect("valid EDU") }
+
+fn receipt(room: usize, user: usize, event: usize) -> EduBuf {
+	edu(&json!({
+		"edu_type": "m.receipt",
+		"content": {
+			format!("!room{room}:example.org"): {
+				"m.read": {
+					format!("@user{user}:example.org"): {
+						"data": { "ts": event },
+						"event_ids": [format!("$event{event}")],
+					},
+				},
+			},
+		},
+	}))
+}
+
+fn typing(user: usize, typing: bool) -> EduBuf {
+	edu(&json!({
+		"edu_type": "m.typing",
+		"content": {
+			"room_id": "!room:example.org",
+			"user_id": format!("@user{user}:example.org"),
+			"typing": typing,
+		},
+	}))
+}
+
+fn presence(user: usize, presence: &str) -> EduBuf {
+	edu(&json!({
+		"edu_type": "m.presence",
+		"content": {
+			"push": [{
+				"user_id": format!("@user{user}:example.org"),
+				"presence": presence,
+				"last_active_ago": 0,
+				"currently_active": false,
+			}],
+		},
+	}))
+}
+
+#[test]
+fn coalesce_receipts() {
+	// 300 receipt updates by 30 users across 3 rooms, 10 updates each
+	let pending: Vec<_> = (0..300).map(|i| receipt(i % 3, i % 30, i)).collect();
+
+	let batch = coalesce(pending, EDU_LIMIT);
+	assert!(batch.edus.len() <= EDU_LIMIT);
+	assert!(batch.overflow.is_empty());
+	assert_eq!(batch.edus.len(), 1, "receipts merged into one EDU");
+	assert_eq!(batch.superseded, 299);
+
+	let edu = parse(&batch.edus[0]);
+	assert_eq!(edu["edu_type"], "m.receipt");
+
+	let rooms = edu["content"].as_object().expect("rooms");
+	assert_eq!(rooms.len(), 3);
+
+	let mut users = 0;
+	for (room, receipts) in rooms {
+		for (user, receipt) in receipts["m.read"].as_object().expect("users") {
+			users += 1;
+
+			// the last of the user's updates: the greatest i for which i % 30 is theirs
+			let user: usize = user
+				.trim_start_matches("@user")
+				.trim_end_matches(":example.org")
+				.parse()
+				.expect("user number");
+
+			let latest = 270 + user;
+			assert_eq!(room, &format!("!room{}:example.org", latest % 3));
+			assert_eq!(receipt["event_ids"], json!([format!("$event{latest}")]), "{user}");
+		}
+	}
+
+	assert_eq!(users, 30, "one receipt per user survives");
+}
+
+#[test]
+fn coalesce_typing() {
+	let pending = [
+		typing(1, true),
+		typing(2, true),
+		typing(1, false),
+		typing(1, true),
+		typing(1, false),
+	];
+
+	let batch = coalesce(pending, EDU_LIMIT);
+	assert_eq!(batch.superseded, 3);
+
+	let edus: Vec<_> = batch.edus.iter().map(parse).collect();
+	assert_eq!(edus.len(), 2);
+	assert_eq!(edus[0]["content"]["user_id"], "@user1:example.org");
+	assert_eq!(edus[0]["content"]["typing"], false, "started and stopped, so stopped");
+	assert_eq!(edus[1]["content"]["user_id"], "@user2:example.org");
+	assert_eq!(edus[1]["content"]["typing"], true);
+}
+
+#[test]
+fn coalesce_presence() {
+	let pending = [presence(1, "online"), presence(2, "unavailable"), presence(1, "offline")];
+
+	let batch = coalesce(pending, EDU_LIMIT);
+	assert_eq!(batch.edus.len(), 1);
+
+	let edu = parse(&batch.edus[0]);
+	let push = edu["content"]["push"].as_array().expect("updates");
+	assert_eq!(push.len(), 2);
+	assert_eq!(push[0]["user_id"], "@user1:example.org");
+	assert_eq!(push[0]["presence"], "offline", "newest presence of the user");
+	assert_eq!(push[1]["presence"], "unavailable");
+}
+
+#[test]
+fn coalesce_limit() {
+	let device = edu(&json!({
+		"edu_type": "m.device_list_update",
+		"content": { "user_id": "@user1:example.org", "device_id": "A", "stream_id": 1, "prev_id": [] },
+	}));
+
+	let pending = (0..150).map(|user| typing(user, true)).chain([
+		device.clone(),
+		device.clone(),
+		receipt(0, 0, 0),
+	]);
+
+	let batch = coalesce(pending, EDU_LIMIT);
+	assert_eq!(batch.edus.len(), EDU_LIMIT);
+	assert_eq!(batch.overflow.len(), 52, "typing, the device list update and the receipt");
+	assert_eq!(batch.superseded, 1, "repeated device list update");
+	assert_eq!(batch.overflow[50], device);
+	assert_eq!(parse(&batch.overflow[51])["edu_type"], "m.receipt");
+}
@@ -0,0 +1,87 @@
+#[cfg(conduwuit_bench)]
+extern crate test;
+
+#[cfg(conduwuit_bench)]
+fn signed_events(
+	count: usize,
+) -> (ruma::RoomVersionId, Vec<(super::PubKeyMap, ruma::CanonicalJsonObject)>) {
+	use ruma::{
+		CanonicalJsonObject, RoomVersionId,
+		serde::Base64,
+		signatures::{Ed25519KeyPair, hash_and_sign_event},
+	};
+	use serde_json::json;
+
+	let room_version = RoomVersionId::V11;
+	let document = Ed25519KeyPair::generate().expect("generated keypair");
+	let keypair = Ed25519KeyPair::from_der(&document, "bench".into()).expect("loaded keypair");
+	let keys: super::PubKeyMap = [(
+		"example.org".to_owned(),
+		[("ed25519:bench".to_owned(), Base64::new(keypair.public_key().to_vec()))].into(),
+	)]
+	.into();
+
+	let events = (0..count)
+		.map(|i| {
+			let mut event: CanonicalJsonObject = serde_json::from_value(json!({
+				"auth_events": [],
+				"content": { "body": format!("message {i}"), "msgtype": "m.text" },
+				"depth": 1,
+				"origin_server_ts": 1_000_000,
+				"prev_events": [],
+				"room_id": "!room:example.org",
+				"sender": "@user:example.org",
+				"type": "m.room.message",
+			}))
+			.expect("valid canonical json");
+
+			hash_and_sign_event("example.org", &keypair, &mut event, &room_version)
+				.expect("signed event");
+
+			(keys.clone(), event)
+		})
+		.collect();
+
+	(room_version, events)
+}
+
+#[cfg(conduwuit_bench)]
+#[cfg_attr(conduwuit_bench, bench)]
+fn verify_sequential_100(b: &mut test::Bencher) {
+	let runtime = tokio::runtime::Runtime::new().expect("runtime");
+	let (room_version, events) = signed_events(100);
+	let events: Vec<_> = events.into_iter().map(|event| vec![event]).collect();
+
+	b.iter(|| {
+		runtime.block_on(async {
+			for event in &events {
+				let (room_version, event) = (room_version.clone(), event.clone());
+				let results = runtime
+					.spawn_blocking(move || super::verify::verify_batch(&room_version, &event))
+					.await
+					.expect("joined");
+
+				assert!(results.iter().all(Result::is_ok));
+			}
+		});
+	});
+}
+
+#[cfg(conduwuit_bench)]
+#[cfg_attr(conduwuit_bench, bench)]
+fn verify_batch_100(b: &mut test::Bencher) {
+	let runtime = tokio::runtime::Runtime::new().expect("runtime");
+	let (room_version, events) = signed_events(100);
+
+	b.iter(|| {
+		runtime.block_on(async {
+			let (room_version, events) = (room_version.clone(), events.clone());
+			let results = runtime
+				.spawn_blocking(move || super::verify::verify_batch(&room_version, &events))
+				.await
+				.expect("joined");
+
+			assert!(results.iter().all(Result::is_ok));
+		});
+	});
+}
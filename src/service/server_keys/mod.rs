@@ -1,33 +1,53 @@
 mod acquire;
+#[cfg(test)]
+mod benches;
 mod get;
 mod keypair;
+mod refresh;
 mod request;
 mod sign;
+#[cfg(test)]
+mod tests;
+mod verified;
 mod verify;
 
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+	collections::{BTreeMap, HashMap},
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
 
+use async_trait::async_trait;
 use conduwuit::{
-	Result, Server, implement,
-	utils::{IterStream, timepoint_from_now},
+	Err, Result, Server, implement,
+	utils::{IterStream, math::usize_from_f64, timepoint_from_now},
 };
 use database::{Deserialized, Json, Map};
 use futures::StreamExt;
 use ruma::{
-	CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedServerSigningKeyId, RoomVersionId,
-	ServerName, ServerSigningKeyId,
+	CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedServerName, OwnedServerSigningKeyId,
+	RoomVersionId, ServerName, ServerSigningKeyId,
 	api::federation::discovery::{ServerSigningKeys, VerifyKey},
 	serde::Raw,
 	signatures::{Ed25519KeyPair, PublicKeyMap, PublicKeySet},
 };
 use serde_json::value::RawValue as RawJsonValue;
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
 
-use crate::{Dep, globals, sending};
+pub use self::refresh::Refreshed;
+use crate::{Dep, cache::Resizable, globals, sending};
 
 pub struct Service {
 	keypair: Box<Ed25519KeyPair>,
 	verify_keys: VerifyKeys,
 	minimum_valid: Duration,
+	verified_cache: verified::Cache,
+	recent: Mutex<HashMap<OwnedServerName, Instant>>,
+	interrupt: Notify,
 	services: Services,
 	db: Data,
 }
@@ -46,9 +66,13 @@ struct Data {
 pub type PubKeyMap = PublicKeyMap;
 pub type PubKeys = PublicKeySet;
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		let minimum_valid = Duration::from_secs(3600);
+		let config = &args.server.config;
+		let cache_size = f64::from(config.verified_events_cache_capacity);
+		let cache_size = cache_size * config.cache_capacity_modifier;
 
 		let (keypair, verify_keys) = keypair::init(args.db)?;
 		debug_assert!(verify_keys.len() == 1, "only one active verify_key supported");
@@ -57,6 +81,12 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 			keypair,
 			verify_keys,
 			minimum_valid,
+			verified_cache: verified::Cache::new(
+				usize_from_f64(cache_size)?,
+				Duration::from_secs(60),
+			),
+			recent: Mutex::default(),
+			interrupt: Notify::new(),
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
@@ -68,6 +98,48 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		let mut i = interval(refresh::RENEW_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.renew_expiring().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let verified_cache = self.verified_cache.len();
+		let counters = self.verified_cache.counters();
+		writeln!(out, "verified_cache: {verified_cache} ({counters})")?;
+
+		let recent = self.recent.lock()?.len();
+		writeln!(out, "recent_key_servers: {recent}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) { self.verified_cache.clear(); }
+
+	async fn health(&self) -> Result {
+		if self.verify_keys.is_empty() {
+			return Err!("no signing key loaded");
+		}
+
+		Ok(())
+	}
+
+	fn caches(&self) -> Vec<(&'static str, &dyn Resizable)> {
+		vec![("verified_cache", &self.verified_cache)]
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -92,23 +164,19 @@ pub fn active_verify_key(&self) -> (&ServerSigningKeyId, &VerifyKey) {
 
 #[implement(Service)]
 async fn add_signing_keys(&self, new_keys: ServerSigningKeys) {
-	let origin = &new_keys.server_name;
+	let origin = new_keys.server_name.clone();
 
 	// (timo) Not atomic, but this is not critical
-	let mut keys: ServerSigningKeys = self
+	let keys = self
 		.db
 		.server_signingkeys
-		.get(origin)
+		.get(&origin)
 		.await
 		.deserialized()
-		.unwrap_or_else(|_| {
-			// Just insert "now", it doesn't matter
-			ServerSigningKeys::new(origin.to_owned(), MilliSecondsSinceUnixEpoch::now())
-		});
-
-	keys.verify_keys.extend(new_keys.verify_keys);
-	keys.old_verify_keys.extend(new_keys.old_verify_keys);
-	self.db.server_signingkeys.raw_put(origin, Json(&keys));
+		.ok();
+
+	let keys = refresh::merge_signing_keys(keys, new_keys);
+	self.db.server_signingkeys.raw_put(&origin, Json(&keys));
 }
 
 #[implement(Service)]
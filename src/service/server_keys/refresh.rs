@@ -0,0 +1,170 @@
+use std::{
+	mem::take,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{Err, Result, debug, debug_info, debug_warn, implement};
+use ruma::{
+	OwnedServerName, ServerName,
+	api::federation::discovery::{OldVerifyKey, ServerSigningKeys},
+};
+
+/// How often the keys of recently seen servers are checked for expiring.
+pub(super) const RENEW_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How long after we last looked up its keys a server is still renewed.
+const RECENT_TRAFFIC: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Outcome of refreshing the signing keys of a server.
+#[derive(Debug)]
+pub struct Refreshed {
+	/// Whether the server answered for its keys itself.
+	pub from_origin: bool,
+
+	/// How many notaries answered for its keys.
+	pub notaries: usize,
+
+	/// Verified events from the server which were forgotten, so that they are
+	/// checked against the new keys.
+	pub evicted: usize,
+
+	/// The keys of the server now stored.
+	pub keys: ServerSigningKeys,
+}
+
+/// Fetches the signing keys of the server again, from itself and from the
+/// trusted notaries as configured, even when we have them already, and
+/// forgets the events from it which were verified with the keys we had.
+#[implement(super::Service)]
+pub async fn refresh_keys(&self, origin: &ServerName) -> Result<Refreshed> {
+	let config = &self.services.server.config;
+
+	let mut from_origin = false;
+	if !config.only_query_trusted_key_servers {
+		match self.server_request(origin).await {
+			| Ok(server_keys) => {
+				self.add_signing_keys(server_keys).await;
+				from_origin = true;
+			},
+			| Err(e) => debug_warn!(%origin, "Failed to refresh signing keys from origin: {e}"),
+		}
+	}
+
+	let mut notaries = 0_usize;
+	for notary in self.services.globals.trusted_servers() {
+		match self.notary_request(notary, origin).await {
+			| Ok(server_keys) => {
+				for server_keys in server_keys {
+					self.add_signing_keys(server_keys).await;
+				}
+
+				notaries = notaries.saturating_add(1);
+			},
+			| Err(e) => debug_warn!(%origin, %notary, "Failed to refresh signing keys: {e}"),
+		}
+	}
+
+	if !from_origin && notaries == 0 {
+		return Err!(BadServerResponse(
+			"Neither {origin} nor any notary gave us its signing keys"
+		));
+	}
+
+	let evicted = self.verified_cache.remove_origin(origin);
+	let keys = self.signing_keys_for(origin).await?;
+	debug!(%origin, from_origin, notaries, evicted, "Refreshed signing keys");
+
+	Ok(Refreshed { from_origin, notaries, evicted, keys })
+}
+
+/// Notes that we looked up the keys of the server, so that they are renewed
+/// before they expire for a while.
+#[implement(super::Service)]
+pub(super) fn seen(&self, origin: &ServerName) {
+	if self.services.globals.server_is_ours(origin) {
+		return;
+	}
+
+	self.recent
+		.lock()
+		.expect("locked")
+		.insert(origin.to_owned(), Instant::now());
+}
+
+/// Refreshes the keys of the servers seen lately which expire within the
+/// minimum validity we ask of keys.
+#[implement(super::Service)]
+pub(super) async fn renew_expiring(&self) {
+	let now = Instant::now();
+	let servers: Vec<OwnedServerName> = {
+		let mut recent = self.recent.lock().expect("locked");
+		recent.retain(|_, seen| now.saturating_duration_since(*seen) < RECENT_TRAFFIC);
+		recent.keys().cloned().collect()
+	};
+
+	let renew_before = self.minimum_valid_ts();
+	for server in &servers {
+		let Ok(keys) = self.signing_keys_for(server).await else {
+			continue;
+		};
+
+		if keys.valid_until_ts > renew_before {
+			continue;
+		}
+
+		match self.refresh_keys(server).await {
+			| Ok(refreshed) => debug_info!(
+				%server,
+				valid_until_ts = ?refreshed.keys.valid_until_ts,
+				"Renewed expiring signing keys"
+			),
+			| Err(e) => debug_warn!(%server, "Failed to renew expiring signing keys: {e}"),
+		}
+	}
+}
+
+/// Merges keys newly received for a server into those stored for it. When
+/// the new keys are at least as recent, keys of ours which the server no
+/// longer lists are kept as old keys, expired when the keys we had were valid
+/// until, so that events already signed with them still verify. Keys from an
+/// older response, such as one cached by a notary, are only added.
+pub(super) fn merge_signing_keys(
+	stored: Option<ServerSigningKeys>,
+	new: ServerSigningKeys,
+) -> ServerSigningKeys {
+	let Some(mut keys) = stored else {
+		return new;
+	};
+
+	if new.valid_until_ts < keys.valid_until_ts {
+		for (key_id, key) in new.verify_keys {
+			if !super::key_exists(&keys, &key_id) {
+				keys.verify_keys.insert(key_id, key);
+			}
+		}
+
+		for (key_id, old) in new.old_verify_keys {
+			keys.old_verify_keys.entry(key_id).or_insert(old);
+		}
+
+		return keys;
+	}
+
+	let expired_ts = keys.valid_until_ts;
+	for (key_id, key) in take(&mut keys.verify_keys) {
+		if !new.verify_keys.contains_key(&key_id) {
+			keys.old_verify_keys
+				.entry(key_id)
+				.or_insert_with(|| OldVerifyKey::new(expired_ts, key.key));
+		}
+	}
+
+	keys.old_verify_keys.extend(new.old_verify_keys);
+	keys.old_verify_keys
+		.retain(|key_id, _| !new.verify_keys.contains_key(key_id));
+
+	keys.verify_keys = new.verify_keys;
+	keys.valid_until_ts = new.valid_until_ts;
+	keys.signatures = new.signatures;
+	keys
+}
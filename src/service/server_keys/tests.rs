@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedServerSigningKeyId, ServerSigningKeyId, Signatures, UInt,
+	api::federation::discovery::{OldVerifyKey, ServerSigningKeys, VerifyKey},
+	owned_server_name,
+	serde::Base64,
+	server_name,
+};
+
+use super::{extract_key, merge_old_keys, refresh::merge_signing_keys, verified};
+
+fn ts(millis: u32) -> MilliSecondsSinceUnixEpoch {
+	MilliSecondsSinceUnixEpoch(UInt::from(millis))
+}
+
+fn key_id(id: &str) -> OwnedServerSigningKeyId {
+	<&ServerSigningKeyId>::try_from(id)
+		.expect("valid key id")
+		.to_owned()
+}
+
+fn key(byte: u8) -> Base64 { Base64::new(vec![byte; 32]) }
+
+fn server_keys(
+	valid_until: u32,
+	verify: &[(&str, u8)],
+	old: &[(&str, u8, u32)],
+) -> ServerSigningKeys {
+	ServerSigningKeys {
+		server_name: owned_server_name!("remote.example"),
+		verify_keys: verify
+			.iter()
+			.map(|&(id, byte)| (key_id(id), VerifyKey::new(key(byte))))
+			.collect(),
+		old_verify_keys: old
+			.iter()
+			.map(|&(id, byte, expired)| (key_id(id), OldVerifyKey::new(ts(expired), key(byte))))
+			.collect(),
+		signatures: Signatures::new(),
+		valid_until_ts: ts(valid_until),
+	}
+}
+
+#[test]
+fn merge_first_keys() {
+	let new = server_keys(2000, &[("ed25519:a", 1)], &[]);
+	let merged = merge_signing_keys(None, new);
+
+	assert_eq!(merged.valid_until_ts, ts(2000));
+	assert!(merged.verify_keys.contains_key(&key_id("ed25519:a")));
+}
+
+#[test]
+fn merge_rotated_keys() {
+	let stored = server_keys(1000, &[("ed25519:a", 1)], &[]);
+	let new = server_keys(5000, &[("ed25519:b", 2)], &[]);
+	let merged = merge_signing_keys(Some(stored), new);
+
+	assert_eq!(merged.valid_until_ts, ts(5000), "validity renewed");
+	assert_eq!(merged.verify_keys.keys().collect::<Vec<_>>(), [&key_id("ed25519:b")]);
+
+	let old = merged
+		.old_verify_keys
+		.get(&key_id("ed25519:a"))
+		.expect("old key");
+	assert_eq!(old.expired_ts, ts(1000), "expired when it was last known valid");
+	assert_eq!(old.key, key(1));
+
+	let found = extract_key(merged.clone(), &key_id("ed25519:a")).expect("old key kept");
+	assert_eq!(found.key, key(1), "events signed with the old key still verify");
+	assert!(
+		merge_old_keys(merged)
+			.verify_keys
+			.contains_key(&key_id("ed25519:a"))
+	);
+}
+
+#[test]
+fn merge_expiry_from_server() {
+	let stored = server_keys(1000, &[("ed25519:a", 1)], &[]);
+	let new = server_keys(5000, &[("ed25519:b", 2)], &[("ed25519:a", 1, 800)]);
+	let merged = merge_signing_keys(Some(stored), new);
+
+	let old = merged
+		.old_verify_keys
+		.get(&key_id("ed25519:a"))
+		.expect("old key");
+	assert_eq!(old.expired_ts, ts(800), "the server's own expiry is kept");
+}
+
+#[test]
+fn merge_older_response() {
+	let stored = server_keys(5000, &[("ed25519:b", 2)], &[("ed25519:a", 1, 1000)]);
+	let older = server_keys(1000, &[("ed25519:a", 1), ("ed25519:c", 3)], &[]);
+	let merged = merge_signing_keys(Some(stored), older);
+
+	assert_eq!(merged.valid_until_ts, ts(5000), "validity not set back");
+	assert!(merged.verify_keys.contains_key(&key_id("ed25519:b")), "current key kept");
+	assert!(merged.verify_keys.contains_key(&key_id("ed25519:c")), "unknown key learned");
+	assert!(
+		!merged.verify_keys.contains_key(&key_id("ed25519:a")),
+		"expired key not revived"
+	);
+	assert!(merged.old_verify_keys.contains_key(&key_id("ed25519:a")));
+}
+
+#[test]
+fn merge_key_listed_again() {
+	let stored = server_keys(1000, &[("ed25519:b", 2)], &[("ed25519:a", 1, 500)]);
+	let new = server_keys(5000, &[("ed25519:a", 1), ("ed25519:b", 2)], &[]);
+	let merged = merge_signing_keys(Some(stored), new);
+
+	assert_eq!(merged.verify_keys.len(), 2);
+	assert!(merged.old_verify_keys.is_empty(), "listed as current again");
+}
+
+#[test]
+fn verified_cache_remove_origin() {
+	let cache = verified::Cache::new(8, Duration::from_secs(60));
+	let digest = |byte: u8| -> verified::Digest { [byte; 32] };
+
+	cache.insert(server_name!("remote.example"), digest(1));
+	cache.insert(server_name!("remote.example"), digest(2));
+	cache.insert(server_name!("other.example"), digest(1));
+
+	assert_eq!(cache.remove_origin(server_name!("remote.example")), 2);
+	assert!(!cache.contains(server_name!("remote.example"), &digest(1)));
+	assert!(cache.contains(server_name!("other.example"), &digest(1)));
+	assert_eq!(cache.remove_origin(server_name!("remote.example")), 0);
+}
@@ -0,0 +1,96 @@
+use std::{
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Result, utils::hash::sha256};
+use lru_cache::LruCache;
+use ruma::{CanonicalJsonObject, OwnedServerName, ServerName};
+
+use super::PubKeyMap;
+use crate::cache::{Counted, Counters, Resizable, Usage};
+
+/// Digest over an event and the public keys it was verified against.
+pub(super) type Digest = sha256::Digest;
+
+/// Short-lived record of events whose signatures and content hash have been
+/// verified, keyed by the origin the event was received from.
+pub(super) struct Cache {
+	entries: Counted<Mutex<LruCache<Key, Instant>>>,
+	ttl: Duration,
+}
+
+type Key = (OwnedServerName, Digest);
+
+/// Hashes the event together with the keys used to check it; a change to
+/// either yields a different entry.
+pub(super) fn digest(event: &CanonicalJsonObject, keys: &PubKeyMap) -> Result<Digest> {
+	let event = serde_json::to_vec(event)?;
+	let keys = serde_json::to_vec(keys)?;
+
+	Ok(sha256::delimited([event, keys].iter()))
+}
+
+impl Cache {
+	pub(super) fn new(capacity: usize, ttl: Duration) -> Self {
+		Self {
+			entries: Mutex::new(LruCache::new(capacity)).into(),
+			ttl,
+		}
+	}
+
+	pub(super) fn contains(&self, origin: &ServerName, digest: &Digest) -> bool {
+		let key = (origin.to_owned(), *digest);
+		let mut entries = self.entries.lock().expect("locked");
+		let found = match entries.get_mut(&key) {
+			| Some(verified) if verified.elapsed() < self.ttl => Some(()),
+			| Some(_) => {
+				entries.remove(&key);
+				None
+			},
+			| None => None,
+		};
+
+		self.entries.counters().tally(found).is_some()
+	}
+
+	pub(super) fn insert(&self, origin: &ServerName, digest: Digest) {
+		self.entries
+			.lock()
+			.expect("locked")
+			.insert((origin.to_owned(), digest), Instant::now());
+	}
+
+	/// Forgets the events received from the origin, returning how many.
+	pub(super) fn remove_origin(&self, origin: &ServerName) -> usize {
+		let mut entries = self.entries.lock().expect("locked");
+		let keys: Vec<_> = entries
+			.iter()
+			.map(|(key, _)| key)
+			.filter(|(server, _)| &**server == origin)
+			.cloned()
+			.collect();
+
+		for key in &keys {
+			entries.remove(key);
+		}
+
+		keys.len()
+	}
+
+	pub(super) fn clear(&self) { self.entries.lock().expect("locked").clear(); }
+
+	pub(super) fn len(&self) -> usize { self.entries.lock().expect("locked").len() }
+
+	pub(super) fn counters(&self) -> &Counters { self.entries.counters() }
+}
+
+#[async_trait]
+impl Resizable for Cache {
+	async fn usage(&self) -> Usage { self.entries.usage().await }
+
+	async fn set_capacity(&self, capacity: usize) { self.entries.set_capacity(capacity).await; }
+
+	fn reset_counters(&self) { self.entries.reset_counters(); }
+}
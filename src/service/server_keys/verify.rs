@@ -1,9 +1,12 @@
-use conduwuit::{Err, Result, implement, pdu::gen_event_id_canonical_json};
+use conduwuit::{Err, Result, debug_warn, implement, pdu::gen_event_id_canonical_json};
 use ruma::{
-	CanonicalJsonObject, CanonicalJsonValue, OwnedEventId, RoomVersionId, signatures::Verified,
+	CanonicalJsonObject, CanonicalJsonValue, OwnedEventId, RoomVersionId, ServerName,
+	signatures::Verified,
 };
 use serde_json::value::RawValue as RawJsonValue;
 
+use super::{PubKeyMap, verified};
+
 #[implement(super::Service)]
 pub async fn validate_and_add_event_id(
 	&self,
@@ -54,7 +57,126 @@ pub async fn verify_event(
 ) -> Result<Verified> {
 	let room_version = room_version.unwrap_or(&RoomVersionId::V11);
 	let keys = self.get_event_keys(event, room_version).await?;
-	ruma::signatures::verify_event(&keys, event, room_version).map_err(Into::into)
+	let batch = vec![(keys, event.clone())];
+
+	self.verify_on_pool(room_version, batch)
+		.await?
+		.pop()
+		.expect("one result for one event")
+}
+
+/// Verifies an event received from `origin`, skipping the signature checks
+/// when the same event was recently verified against the same keys.
+#[implement(super::Service)]
+pub async fn verify_event_from(
+	&self,
+	origin: &ServerName,
+	event: &CanonicalJsonObject,
+	room_version: &RoomVersionId,
+) -> Result<Verified> {
+	let keys = self.get_event_keys(event, room_version).await?;
+	let digest = verified::digest(event, &keys)?;
+	if self.verified_cache.contains(origin, &digest) {
+		return Ok(Verified::All);
+	}
+
+	let verified = self
+		.verify_on_pool(room_version, vec![(keys, event.clone())])
+		.await?
+		.pop()
+		.expect("one result for one event")?;
+
+	if matches!(verified, Verified::All) {
+		self.verified_cache.insert(origin, digest);
+	}
+
+	Ok(verified)
+}
+
+/// Verifies a transaction's worth of events from `origin` in a single job on
+/// the blocking pool. Events which pass are remembered so the per-event
+/// verification during their handling is skipped; those which fail are left
+/// for that per-event path to identify and reject. Returns the number of
+/// events which did not verify.
+#[implement(super::Service)]
+#[tracing::instrument(level = "debug", skip_all, fields(%origin))]
+pub async fn verify_events<'a, I>(
+	&self,
+	origin: &ServerName,
+	room_version: &RoomVersionId,
+	events: I,
+) -> Result<usize>
+where
+	I: Iterator<Item = &'a CanonicalJsonObject> + Send,
+{
+	let mut digests = Vec::new();
+	let mut batch = Vec::new();
+	for event in events {
+		let mut event = event.clone();
+		event.remove("unsigned");
+
+		let Ok(keys) = self.get_event_keys(&event, room_version).await else {
+			continue;
+		};
+
+		let digest = verified::digest(&event, &keys)?;
+		if !self.verified_cache.contains(origin, &digest) {
+			digests.push(digest);
+			batch.push((keys, event));
+		}
+	}
+
+	let results = self.verify_on_pool(room_version, batch).await?;
+	debug_assert_eq!(results.len(), digests.len(), "one result per event");
+
+	let mut failed: usize = 0;
+	for (digest, result) in digests.into_iter().zip(results) {
+		match result {
+			| Ok(Verified::All) => self.verified_cache.insert(origin, digest),
+			| _ => failed = failed.saturating_add(1),
+		}
+	}
+
+	if failed > 0 {
+		debug_warn!(%failed, "Some events in batch did not verify");
+	}
+
+	Ok(failed)
+}
+
+#[implement(super::Service)]
+async fn verify_on_pool(
+	&self,
+	room_version: &RoomVersionId,
+	batch: Vec<(PubKeyMap, CanonicalJsonObject)>,
+) -> Result<Vec<Result<Verified>>> {
+	if batch.is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let room_version = room_version.clone();
+	self.services
+		.server
+		.runtime()
+		.spawn_blocking(move || verify_batch(&room_version, &batch))
+		.await
+		.map_err(Into::into)
+}
+
+/// Checks the signatures and content hash of each event in turn. The
+/// signature library does not expose ed25519 batch verification, so batching
+/// amortizes the handoff to the blocking pool rather than the curve math;
+/// results stay per-event either way.
+pub(super) fn verify_batch(
+	room_version: &RoomVersionId,
+	batch: &[(PubKeyMap, CanonicalJsonObject)],
+) -> Vec<Result<Verified>> {
+	batch
+		.iter()
+		.map(|(keys, event)| {
+			ruma::signatures::verify_event(keys, event, room_version).map_err(Into::into)
+		})
+		.collect()
 }
 
 #[implement(super::Service)]
@@ -10,6 +10,8 @@
 use conduwuit::{Err, Result, Server, err, error::inspect_log, utils::string::SplitInfallible};
 use database::Database;
 
+use crate::cache::Resizable;
+
 /// Abstract interface for a Service
 #[async_trait]
 pub(crate) trait Service: Any + Send + Sync {
@@ -33,9 +35,22 @@ fn interrupt(&self) {}
 	/// Clear any caches or similar runtime state.
 	async fn clear_cache(&self) {}
 
+	/// Evict the entries for `key` from the service's caches, returning
+	/// whether there were any. The form of the key depends on the service.
+	async fn clear_cache_entry(&self, _key: &str) -> Result<bool> {
+		Err!("Evicting single cache entries is not supported by {}", self.name())
+	}
+
 	/// Memory usage report in a markdown string.
 	async fn memory_usage(&self, _out: &mut (dyn Write + Send)) -> Result { Ok(()) }
 
+	/// Whether the service is able to serve requests. An error explains what
+	/// is wrong and makes the server report itself as not ready.
+	async fn health(&self) -> Result { Ok(()) }
+
+	/// Caches held by the service which can be resized at runtime, by name.
+	fn caches(&self) -> Vec<(&'static str, &dyn Resizable)> { Vec::new() }
+
 	/// Return the name of the service.
 	/// i.e. `crate::service::make_name(std::module_path!())`
 	fn name(&self) -> &str;
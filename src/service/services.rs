@@ -1,45 +1,58 @@
 use std::{
 	any::Any,
 	collections::BTreeMap,
+	iter,
 	sync::{Arc, RwLock},
 };
 
-use conduwuit::{Result, Server, debug, debug_info, info, trace, utils::stream::IterStream};
+use conduwuit::{Err, Result, Server, debug, debug_info, info, trace, utils::stream::IterStream};
 use database::Database;
-use futures::{Stream, StreamExt, TryStreamExt};
+use futures::{FutureExt, Stream, StreamExt, TryStreamExt, future};
 use tokio::sync::Mutex;
 
 use crate::{
-	account_data, admin, appservice, client, config, emergency, federation, globals, key_backups,
+	account_data, account_validity, admin, appservice, cache,
+	cache::Usage,
+	client, config, db_stats, emergency, federation, globals, key_backups, mailer,
 	manager::Manager,
-	media, presence, pusher, resolver, rooms, sending, server_keys, service,
+	media, presence, pusher, reindex, resolver, rooms, secondary, sending, server_keys, service,
 	service::{Args, Map, Service},
-	sync, transaction_ids, uiaa, updates, users,
+	shedding, sync, transaction_ids, uiaa, uisi, updates, users,
 };
 
 pub struct Services {
 	pub account_data: Arc<account_data::Service>,
+	pub account_validity: Arc<account_validity::Service>,
 	pub admin: Arc<admin::Service>,
 	pub appservice: Arc<appservice::Service>,
 	pub config: Arc<config::Service>,
 	pub client: Arc<client::Service>,
+	pub db_stats: Arc<db_stats::Service>,
 	pub emergency: Arc<emergency::Service>,
 	pub globals: Arc<globals::Service>,
 	pub key_backups: Arc<key_backups::Service>,
+	pub mailer: Arc<mailer::Service>,
 	pub media: Arc<media::Service>,
 	pub presence: Arc<presence::Service>,
 	pub pusher: Arc<pusher::Service>,
+	pub reindex: Arc<reindex::Service>,
 	pub resolver: Arc<resolver::Service>,
 	pub rooms: rooms::Service,
 	pub federation: Arc<federation::Service>,
+	pub secondary: Arc<secondary::Service>,
 	pub sending: Arc<sending::Service>,
 	pub server_keys: Arc<server_keys::Service>,
+	pub shedding: Arc<shedding::Service>,
 	pub sync: Arc<sync::Service>,
 	pub transaction_ids: Arc<transaction_ids::Service>,
 	pub uiaa: Arc<uiaa::Service>,
+	pub uisi: Arc<uisi::Service>,
 	pub updates: Arc<updates::Service>,
 	pub users: Arc<users::Service>,
 
+	/// Services of the other server names hosted by this process.
+	pub virtual_hosts: Vec<Arc<Self>>,
+
 	manager: Mutex<Option<Arc<Manager>>>,
 	pub(crate) service: Arc<Map>,
 	pub server: Arc<Server>,
@@ -47,8 +60,18 @@ pub struct Services {
 }
 
 impl Services {
-	#[allow(clippy::cognitive_complexity)]
 	pub async fn build(server: Arc<Server>) -> Result<Arc<Self>> {
+		let mut virtual_hosts = Vec::with_capacity(server.virtual_hosts.len());
+		for host in &server.virtual_hosts {
+			debug_info!("Building services for virtual host {}...", host.name);
+			virtual_hosts.push(Self::build_host(host.clone(), Vec::new()).await?);
+		}
+
+		Self::build_host(server, virtual_hosts).await
+	}
+
+	#[allow(clippy::cognitive_complexity)]
+	async fn build_host(server: Arc<Server>, virtual_hosts: Vec<Arc<Self>>) -> Result<Arc<Self>> {
 		let db = Database::open(&server).await?;
 		let service: Arc<Map> = Arc::new(RwLock::new(BTreeMap::new()));
 		macro_rules! build {
@@ -65,48 +88,63 @@ macro_rules! build {
 
 		Ok(Arc::new(Self {
 			account_data: build!(account_data::Service),
+			account_validity: build!(account_validity::Service),
 			admin: build!(admin::Service),
 			appservice: build!(appservice::Service),
 			resolver: build!(resolver::Service),
 			client: build!(client::Service),
+			db_stats: build!(db_stats::Service),
 			config: build!(config::Service),
 			emergency: build!(emergency::Service),
 			globals: build!(globals::Service),
 			key_backups: build!(key_backups::Service),
+			mailer: build!(mailer::Service),
 			media: build!(media::Service),
 			presence: build!(presence::Service),
 			pusher: build!(pusher::Service),
+			reindex: build!(reindex::Service),
 			rooms: rooms::Service {
+				activity: build!(rooms::activity::Service),
 				alias: build!(rooms::alias::Service),
 				auth_chain: build!(rooms::auth_chain::Service),
+				direct: build!(rooms::direct::Service),
 				directory: build!(rooms::directory::Service),
 				event_handler: build!(rooms::event_handler::Service),
+				freeze: build!(rooms::freeze::Service),
 				lazy_loading: build!(rooms::lazy_loading::Service),
 				metadata: build!(rooms::metadata::Service),
 				outlier: build!(rooms::outlier::Service),
 				pdu_metadata: build!(rooms::pdu_metadata::Service),
+				purge: build!(rooms::purge::Service),
 				read_receipt: build!(rooms::read_receipt::Service),
 				search: build!(rooms::search::Service),
+				send_queue: build!(rooms::send_queue::Service),
 				short: build!(rooms::short::Service),
 				spaces: build!(rooms::spaces::Service),
 				state: build!(rooms::state::Service),
 				state_accessor: build!(rooms::state_accessor::Service),
 				state_cache: build!(rooms::state_cache::Service),
 				state_compressor: build!(rooms::state_compressor::Service),
+				state_growth: build!(rooms::state_growth::Service),
 				threads: build!(rooms::threads::Service),
 				timeline: build!(rooms::timeline::Service),
 				typing: build!(rooms::typing::Service),
+				usage: build!(rooms::usage::Service),
 				user: build!(rooms::user::Service),
 			},
 			federation: build!(federation::Service),
+			secondary: build!(secondary::Service),
 			sending: build!(sending::Service),
 			server_keys: build!(server_keys::Service),
+			shedding: build!(shedding::Service),
 			sync: build!(sync::Service),
 			transaction_ids: build!(transaction_ids::Service),
 			uiaa: build!(uiaa::Service),
+			uisi: build!(uisi::Service),
 			updates: build!(updates::Service),
 			users: build!(users::Service),
 
+			virtual_hosts,
 			manager: Mutex::new(None),
 			service,
 			server,
@@ -115,10 +153,23 @@ macro_rules! build {
 	}
 
 	pub async fn start(self: &Arc<Self>) -> Result<Arc<Self>> {
-		debug_info!("Starting services...");
+		self.start_host().await?;
+		for host in &self.virtual_hosts {
+			host.start_host().await?;
+		}
+
+		Ok(Arc::clone(self))
+	}
+
+	async fn start_host(self: &Arc<Self>) -> Result {
+		debug_info!("Starting services for {}...", self.server.name);
 
 		self.admin.set_services(Some(Arc::clone(self)).as_ref());
 		super::migrations::migrations(self).await?;
+		if self.server.config.recreate_admin_room && !self.db.is_read_only() {
+			admin::recreate_admin_room(self).boxed().await?;
+		}
+
 		self.manager
 			.lock()
 			.await
@@ -138,11 +189,20 @@ pub async fn start(self: &Arc<Self>) -> Result<Arc<Self>> {
 		}
 
 		debug_info!("Services startup complete.");
-		Ok(Arc::clone(self))
+		Ok(())
 	}
 
 	pub async fn stop(&self) {
-		info!("Shutting down services...");
+		for host in &self.virtual_hosts {
+			_ = host.server.shutdown();
+			host.stop_host().await;
+		}
+
+		self.stop_host().await;
+	}
+
+	async fn stop_host(&self) {
+		info!("Shutting down services for {}...", self.server.name);
 
 		// set the server user as offline
 		if self.server.config.allow_local_presence && !self.db.is_read_only() {
@@ -162,7 +222,19 @@ pub async fn stop(&self) {
 		debug_info!("Services shutdown complete.");
 	}
 
+	/// Resolves when the service manager of this or any virtual host finishes.
 	pub async fn poll(&self) -> Result<()> {
+		let hosts = self
+			.virtual_hosts
+			.iter()
+			.map(|host| host.poll_host().boxed());
+
+		future::select_all(iter::once(self.poll_host().boxed()).chain(hosts))
+			.await
+			.0
+	}
+
+	async fn poll_host(&self) -> Result<()> {
 		if let Some(manager) = self.manager.lock().await.as_ref() {
 			return manager.poll().await;
 		}
@@ -178,6 +250,91 @@ pub async fn clear_cache(&self) {
 			.await;
 	}
 
+	/// Evicts the entries for `key` from the caches of the named service,
+	/// returning whether there were any.
+	pub async fn clear_cache_entry(&self, service: &str, key: &str) -> Result<bool> {
+		let Some(service) = self
+			.service
+			.read()
+			.expect("locked for reading")
+			.get(service)
+			.and_then(|val| val.0.upgrade())
+		else {
+			return Err!(Request(NotFound("No service named {service:?}")));
+		};
+
+		service.clear_cache_entry(key).await
+	}
+
+	/// Usage of the runtime-resizable cache of every service, by name.
+	pub async fn cache_usage(&self) -> Vec<(&'static str, Usage)> {
+		cache::collect(&self.service).await
+	}
+
+	/// Zeroes the hit and miss counters of every cache.
+	pub async fn reset_cache_counters(&self) {
+		for service in self.services().collect::<Vec<_>>().await {
+			for (_, cache) in service.caches() {
+				cache.reset_counters();
+			}
+		}
+	}
+
+	/// Changes the capacity of the named cache until the server restarts,
+	/// returning its usage afterwards.
+	pub async fn set_cache_capacity(&self, name: &str, capacity: usize) -> Result<Usage> {
+		for service in self.services().collect::<Vec<_>>().await {
+			if let Some((_, cache)) = service
+				.caches()
+				.into_iter()
+				.find(|(cache, _)| *cache == name)
+			{
+				cache.set_capacity(capacity).await;
+				return Ok(cache.usage().await);
+			}
+		}
+
+		Err!(Request(NotFound("No resizable cache named {name:?}")))
+	}
+
+	/// Components keeping this host from being ready to serve requests, with
+	/// the reason for each; empty when ready. Besides the health reported by
+	/// each service this covers shutdown, a read-only database and the
+	/// supervision of the service workers.
+	pub async fn health(&self) -> BTreeMap<String, String> {
+		let mut failing = BTreeMap::new();
+		if !self.server.running() {
+			failing.insert("server".into(), "shutting down".into());
+		}
+
+		if self.db.is_read_only() {
+			failing.insert("database".into(), "read-only".into());
+		}
+
+		match self.manager.lock().await.as_ref() {
+			| None => {
+				failing.insert("services".into(), "not started".into());
+			},
+			| Some(manager) => {
+				if manager.is_stopped() {
+					failing.insert("services".into(), "workers no longer supervised".into());
+				}
+
+				for name in manager.restarting() {
+					failing.insert(name, "worker restarting after a panic".into());
+				}
+			},
+		}
+
+		for service in self.services().collect::<Vec<_>>().await {
+			if let Err(e) = service.health().await {
+				failing.insert(service.name().to_owned(), e.message());
+			}
+		}
+
+		failing
+	}
+
 	pub async fn memory_usage(&self) -> Result<String> {
 		self.services()
 			.map(Ok)
@@ -0,0 +1,131 @@
+use std::{fmt, str::FromStr};
+
+use conduwuit::{Err, Error};
+use http::Method;
+
+/// Kinds of request which are shed together under overload.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Class {
+	/// Client `/sync` long-polls, including sliding sync.
+	ClientSync,
+
+	/// Every other client-server request, such as sending messages.
+	ClientInteractive,
+
+	/// Media downloads, thumbnails and uploads on any API.
+	Media,
+
+	/// Inbound federation transactions, invites and membership handshakes.
+	FederationSend,
+
+	/// Federation queries such as backfill, state and key lookups.
+	FederationRead,
+}
+
+impl Class {
+	pub const ALL: [Self; 5] = [
+		Self::ClientSync,
+		Self::ClientInteractive,
+		Self::Media,
+		Self::FederationSend,
+		Self::FederationRead,
+	];
+	/// Order in which classes are shed when `load_shedding_priority` is not
+	/// configured, lowest priority first.
+	pub const DEFAULT_PRIORITY: [Self; 5] = [
+		Self::Media,
+		Self::FederationRead,
+		Self::ClientSync,
+		Self::FederationSend,
+		Self::ClientInteractive,
+	];
+
+	#[must_use]
+	pub fn as_str(self) -> &'static str {
+		match self {
+			| Self::ClientSync => "client_sync",
+			| Self::ClientInteractive => "client_interactive",
+			| Self::Media => "media",
+			| Self::FederationSend => "federation_send",
+			| Self::FederationRead => "federation_read",
+		}
+	}
+
+	/// Name of the metric counting shed requests of this class.
+	#[must_use]
+	pub fn shed_metric(self) -> &'static str {
+		match self {
+			| Self::ClientSync => "shed_requests_client_sync",
+			| Self::ClientInteractive => "shed_requests_client_interactive",
+			| Self::Media => "shed_requests_media",
+			| Self::FederationSend => "shed_requests_federation_send",
+			| Self::FederationRead => "shed_requests_federation_read",
+		}
+	}
+
+	/// Whether the latency of this class counts towards overload. Syncs are
+	/// held open on purpose and would always appear slow.
+	#[must_use]
+	pub fn latency_sensitive(self) -> bool { self != Self::ClientSync }
+
+	#[inline]
+	pub(super) fn index(self) -> usize {
+		Self::ALL
+			.iter()
+			.position(|class| *class == self)
+			.expect("class is listed")
+	}
+}
+
+impl fmt::Display for Class {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.as_str()) }
+}
+
+impl FromStr for Class {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match Self::ALL.iter().find(|class| class.as_str() == s) {
+			| Some(class) => Ok(*class),
+			| None => Err!(Config(
+				"load_shedding_priority",
+				"Unknown request class {s:?}; expected one of client_sync, client_interactive, \
+				 media, federation_send or federation_read."
+			)),
+		}
+	}
+}
+
+/// Classifies a request by its method and path before it is routed.
+#[must_use]
+pub fn classify(method: &Method, path: &str) -> Class {
+	if path.starts_with("/_matrix/media/")
+		|| path.starts_with("/_matrix/client/v1/media/")
+		|| path.starts_with("/_matrix/federation/v1/media/")
+	{
+		return Class::Media;
+	}
+
+	if let Some(path) = path.strip_prefix("/_matrix/federation/") {
+		let endpoint = path.split('/').nth(1).unwrap_or_default();
+		let send = endpoint.starts_with("send")
+			|| endpoint == "invite"
+			|| endpoint.starts_with("exchange_third_party_invite");
+
+		return if send && *method == Method::PUT {
+			Class::FederationSend
+		} else {
+			Class::FederationRead
+		};
+	}
+
+	if path.starts_with("/_matrix/key/") {
+		return Class::FederationRead;
+	}
+
+	if path.starts_with("/_matrix/client/") && path.ends_with("/sync") {
+		return Class::ClientSync;
+	}
+
+	Class::ClientInteractive
+}
@@ -0,0 +1,325 @@
+mod class;
+#[cfg(test)]
+mod tests;
+
+use std::{
+	fmt::Write,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicU64, AtomicUsize, Ordering},
+	},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{Err, Result, Server, info, warn};
+
+pub use self::class::{Class, classify};
+
+/// Rejects requests of the lowest-priority classes while the server is
+/// overloaded, so interactive clients and inbound federation keep working.
+///
+/// Overload is judged once per [`WINDOW`] from the number of requests in
+/// flight and the p99 latency of the requests which completed in the window.
+/// While overloaded one more class is shed each window, starting from the
+/// lowest priority; once load falls below three quarters of the thresholds
+/// classes are readmitted one per window. The highest-priority class is never
+/// shed.
+pub struct Service {
+	enabled: bool,
+	max_inflight: usize,
+	max_p99: Duration,
+	retry_after: Duration,
+
+	/// Classes lowest priority first.
+	priority: Vec<Class>,
+
+	/// Number of classes at the front of `priority` being shed.
+	level: AtomicUsize,
+
+	classes: [ClassState; Class::ALL.len()],
+	window: Mutex<Window>,
+	server: Arc<Server>,
+}
+
+#[derive(Default)]
+struct ClassState {
+	inflight: AtomicUsize,
+	shed: AtomicU64,
+}
+
+struct Window {
+	started: Instant,
+	latencies: [Vec<Duration>; Class::ALL.len()],
+	p99: [Duration; Class::ALL.len()],
+}
+
+/// Current state of one class, for display.
+#[derive(Debug)]
+pub struct ClassStatus {
+	pub class: Class,
+	pub shedding: bool,
+	pub inflight: usize,
+	pub p99: Duration,
+	pub shed: u64,
+}
+
+/// Held for the duration of an admitted request.
+pub struct Admission {
+	service: Arc<Service>,
+	class: Class,
+	started: Instant,
+}
+
+/// Interval over which latencies are collected and overload is judged.
+pub const WINDOW: Duration = Duration::from_secs(1);
+
+/// Latency samples kept per class and window; later requests are not sampled.
+const MAX_SAMPLES: usize = 1024;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		let priority = if config.load_shedding_priority.is_empty() {
+			Class::DEFAULT_PRIORITY.to_vec()
+		} else {
+			priority(&config.load_shedding_priority)?
+		};
+
+		Ok(Arc::new(Self {
+			enabled: config.load_shedding,
+			max_inflight: config.load_shedding_max_inflight,
+			max_p99: Duration::from_millis(config.load_shedding_p99_ms),
+			retry_after: Duration::from_secs(config.load_shedding_retry_after_secs),
+			priority,
+			level: AtomicUsize::new(0),
+			classes: Default::default(),
+			window: Mutex::new(Window::new(Instant::now())),
+			server: args.server.clone(),
+		}))
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		writeln!(out, "shedding_level: {}", self.level())?;
+
+		Ok(())
+	}
+
+	async fn health(&self) -> Result {
+		match self.level() {
+			| 0 => Ok(()),
+			| level => Err!("shedding {level} request classes under load"),
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Admits a request of `class`, or returns how long the client should wait
+	/// before retrying when the class is being shed.
+	pub fn admit(self: &Arc<Self>, class: Class) -> Result<Admission, Duration> {
+		if self.enabled {
+			self.evaluate(Instant::now());
+		}
+
+		if self.enabled && self.is_shedding(class) {
+			let state = &self.classes[class.index()];
+			let shed = state.shed.fetch_add(1, Ordering::Relaxed).saturating_add(1);
+			self.server.metrics.set(class.shed_metric(), shed);
+
+			return Err(self.retry_after);
+		}
+
+		self.classes[class.index()]
+			.inflight
+			.fetch_add(1, Ordering::Relaxed);
+
+		Ok(Admission {
+			service: self.clone(),
+			class,
+			started: Instant::now(),
+		})
+	}
+
+	/// Number of classes currently being shed.
+	#[inline]
+	#[must_use]
+	pub fn level(&self) -> usize { self.level.load(Ordering::Relaxed) }
+
+	#[must_use]
+	pub fn is_shedding(&self, class: Class) -> bool {
+		self.priority
+			.iter()
+			.take(self.level())
+			.any(|shed| *shed == class)
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn is_enabled(&self) -> bool { self.enabled }
+
+	/// State of every class, highest priority first.
+	#[must_use]
+	pub fn status(&self) -> Vec<ClassStatus> {
+		let window = self.window.lock().expect("locked");
+
+		self.priority
+			.iter()
+			.rev()
+			.map(|&class| {
+				let state = &self.classes[class.index()];
+				ClassStatus {
+					class,
+					shedding: self.is_shedding(class),
+					inflight: state.inflight.load(Ordering::Relaxed),
+					p99: window.p99[class.index()],
+					shed: state.shed.load(Ordering::Relaxed),
+				}
+			})
+			.collect()
+	}
+
+	/// Closes the current window once it has lasted [`WINDOW`], adjusting the
+	/// shedding level from its measurements.
+	fn evaluate(&self, now: Instant) {
+		let Ok(mut window) = self.window.try_lock() else {
+			return;
+		};
+
+		if now.saturating_duration_since(window.started) < WINDOW {
+			return;
+		}
+
+		let closed = std::mem::replace(&mut *window, Window::new(now));
+		window.p99 = closed.p99();
+
+		let inflight = self
+			.classes
+			.iter()
+			.map(|state| state.inflight.load(Ordering::Relaxed))
+			.fold(0_usize, usize::saturating_add);
+
+		let p99 = Class::ALL
+			.iter()
+			.filter(|class| class.latency_sensitive())
+			.map(|class| window.p99[class.index()])
+			.max()
+			.unwrap_or_default();
+
+		let level = self.level();
+		let max_level = self.priority.len().saturating_sub(1);
+		let next = next_level(level, max_level, self.pressure(inflight, p99));
+		if next != level {
+			self.level.store(next, Ordering::Relaxed);
+			self.log_level_change(level, next, inflight, p99);
+		}
+	}
+
+	fn pressure(&self, inflight: usize, p99: Duration) -> Pressure {
+		let relieved = |value: u128, max: u128| value.saturating_mul(4) <= max.saturating_mul(3);
+		let (p99, max_p99) = (p99.as_millis(), self.max_p99.as_millis());
+
+		if inflight > self.max_inflight || p99 > max_p99 {
+			Pressure::Overloaded
+		} else if inflight.saturating_mul(4) <= self.max_inflight.saturating_mul(3)
+			&& relieved(p99, max_p99)
+		{
+			Pressure::Relieved
+		} else {
+			Pressure::Steady
+		}
+	}
+
+	fn log_level_change(&self, from: usize, to: usize, inflight: usize, p99: Duration) {
+		let shed: Vec<_> = self.priority.iter().take(to).map(Class::as_str).collect();
+		if to > from {
+			warn!(%inflight, ?p99, ?shed, "Overloaded; shedding requests");
+		} else {
+			info!(%inflight, ?p99, ?shed, "Load reduced; readmitting requests");
+		}
+	}
+
+	fn finish(&self, class: Class, elapsed: Duration) {
+		self.classes[class.index()]
+			.inflight
+			.fetch_sub(1, Ordering::Relaxed);
+
+		if !self.enabled {
+			return;
+		}
+
+		let mut window = self.window.lock().expect("locked");
+		let samples = &mut window.latencies[class.index()];
+		if samples.len() < MAX_SAMPLES {
+			samples.push(elapsed);
+		}
+	}
+}
+
+impl Drop for Admission {
+	fn drop(&mut self) { self.service.finish(self.class, self.started.elapsed()); }
+}
+
+impl Window {
+	fn new(started: Instant) -> Self {
+		Self {
+			started,
+			latencies: Default::default(),
+			p99: Default::default(),
+		}
+	}
+
+	fn p99(mut self) -> [Duration; Class::ALL.len()] {
+		self.latencies
+			.each_mut()
+			.map(|samples| percentile(samples, 99))
+	}
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Pressure {
+	Overloaded,
+	Steady,
+	Relieved,
+}
+
+fn next_level(level: usize, max_level: usize, pressure: Pressure) -> usize {
+	match pressure {
+		| Pressure::Overloaded => level.saturating_add(1).min(max_level),
+		| Pressure::Steady => level,
+		| Pressure::Relieved => level.saturating_sub(1),
+	}
+}
+
+/// The `pct` percentile of `samples`, or zero when there are none.
+fn percentile(samples: &mut [Duration], pct: usize) -> Duration {
+	if samples.is_empty() {
+		return Duration::ZERO;
+	}
+
+	samples.sort_unstable();
+	let rank = samples.len().saturating_mul(pct).div_ceil(100);
+	samples[rank.saturating_sub(1)]
+}
+
+fn priority(names: &[String]) -> Result<Vec<Class>> {
+	let mut priority = Vec::with_capacity(Class::ALL.len());
+	for name in names {
+		let class: Class = name.parse()?;
+		if !priority.contains(&class) {
+			priority.push(class);
+		}
+	}
+
+	// Unlisted classes are given the highest priority so they are never shed by
+	// accident.
+	priority.extend(
+		Class::DEFAULT_PRIORITY
+			.iter()
+			.filter(|class| !names.iter().any(|name| name == class.as_str())),
+	);
+
+	Ok(priority)
+}
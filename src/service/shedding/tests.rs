@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use http::Method;
+
+use super::{Class, Pressure, classify, next_level, percentile, priority};
+
+#[test]
+fn classify_requests() {
+	let cases = [
+		(Method::GET, "/_matrix/client/v3/sync", Class::ClientSync),
+		(
+			Method::POST,
+			"/_matrix/client/unstable/org.matrix.simplified_msc3575/sync",
+			Class::ClientSync,
+		),
+		(
+			Method::PUT,
+			"/_matrix/client/v3/rooms/!a:b/send/m.room.message/1",
+			Class::ClientInteractive,
+		),
+		(Method::GET, "/_matrix/client/v1/media/thumbnail/a/b", Class::Media),
+		(Method::GET, "/_matrix/media/v3/download/a/b", Class::Media),
+		(Method::GET, "/_matrix/federation/v1/media/download/a", Class::Media),
+		(Method::PUT, "/_matrix/federation/v1/send/1", Class::FederationSend),
+		(Method::PUT, "/_matrix/federation/v2/send_join/!a:b/$c", Class::FederationSend),
+		(Method::PUT, "/_matrix/federation/v2/invite/!a:b/$c", Class::FederationSend),
+		(Method::GET, "/_matrix/federation/v1/backfill/!a:b", Class::FederationRead),
+		(Method::GET, "/_matrix/federation/v1/make_join/!a:b/@c:d", Class::FederationRead),
+		(Method::GET, "/_matrix/key/v2/server", Class::FederationRead),
+	];
+
+	for (method, path, class) in cases {
+		assert_eq!(classify(&method, path), class, "{method} {path}");
+	}
+}
+
+#[test]
+fn priority_order() {
+	let names = ["federation_read".to_owned(), "media".to_owned()];
+	let order = priority(&names).expect("valid priority");
+
+	assert_eq!(order.len(), Class::ALL.len(), "every class is ranked");
+	assert_eq!(&order[..2], &[Class::FederationRead, Class::Media], "listed classes first");
+	assert_eq!(order.last(), Some(&Class::ClientInteractive), "unlisted keep default order");
+
+	assert!(priority(&["thumbnails".to_owned()]).is_err(), "unknown class rejected");
+}
+
+#[test]
+fn shedding_level_steps() {
+	let max = Class::ALL.len().saturating_sub(1);
+
+	assert_eq!(next_level(0, max, Pressure::Overloaded), 1);
+	assert_eq!(next_level(max, max, Pressure::Overloaded), max, "top class never shed");
+	assert_eq!(next_level(2, max, Pressure::Steady), 2);
+	assert_eq!(next_level(2, max, Pressure::Relieved), 1);
+	assert_eq!(next_level(0, max, Pressure::Relieved), 0);
+}
+
+#[test]
+fn latency_percentile() {
+	let mut samples: Vec<_> = (1..=100).rev().map(Duration::from_millis).collect();
+
+	assert_eq!(percentile(&mut samples, 99), Duration::from_millis(99));
+	assert_eq!(percentile(&mut samples[..1], 99), Duration::from_millis(1));
+	assert_eq!(percentile(&mut [], 99), Duration::ZERO);
+}
@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+
+use conduwuit::{
+	implement,
+	utils::{
+		future::TryExtExt,
+		stream::{BroadbandExt, IterStream, ReadyExt},
+	},
+};
+use futures::{
+	FutureExt, StreamExt,
+	future::{join, join3},
+};
+use ruma::{
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
+	events::{StateEventType, room::member::MembershipState},
+};
+
+use super::Service;
+use crate::rooms::short::ShortStateHash;
+
+/// The `changed` and `left` device lists of a sync.
+#[derive(Debug, Default)]
+pub struct DeviceLists {
+	pub changed: HashSet<OwnedUserId>,
+	pub left: HashSet<OwnedUserId>,
+}
+
+/// A room as it was at one sync token, as the user syncing and one other
+/// member were in it.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct Membership {
+	pub(super) encrypted: bool,
+	pub(super) sender_joined: bool,
+	pub(super) user_joined: bool,
+}
+
+/// Whether a user shared an encrypted room with the user syncing as of the
+/// `since` token, and whether they do now.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct Shared {
+	pub(super) since: bool,
+	pub(super) now: bool,
+}
+
+/// Users who began or stopped sharing an encrypted room with the user syncing
+/// between `since` and now. Only users whose membership changed in a room the
+/// user syncing is or was in are considered, along with everyone in a room the
+/// syncing user joined or left, or that was encrypted, in the meantime.
+///
+/// Users whose devices changed while sharing a room all along are not included
+/// here; those come from the key changes instead.
+#[implement(Service)]
+pub async fn device_list_changes(&self, sender_user: &UserId, since: u64) -> DeviceLists {
+	let rooms_left = self
+		.services
+		.state_cache
+		.rooms_left(sender_user)
+		.map(|(room_id, _)| room_id)
+		.broad_filter_map(|room_id| async move {
+			self.services
+				.state_cache
+				.get_left_count(&room_id, sender_user)
+				.await
+				.is_ok_and(|count| count > since)
+				.then_some(room_id)
+		});
+
+	let rooms: Vec<OwnedRoomId> = self
+		.services
+		.state_cache
+		.rooms_joined(sender_user)
+		.map(ToOwned::to_owned)
+		.chain(rooms_left)
+		.collect()
+		.await;
+
+	let candidates = rooms
+		.iter()
+		.stream()
+		.broad_then(|room_id| {
+			self.membership_changes(sender_user, room_id, since)
+				.map(move |user_ids| (room_id, user_ids))
+		})
+		.ready_fold(
+			HashMap::<OwnedUserId, Vec<OwnedRoomId>>::new(),
+			|mut candidates, (room_id, user_ids)| {
+				for user_id in user_ids {
+					candidates.entry(user_id).or_default().push(room_id.clone());
+				}
+
+				candidates
+			},
+		)
+		.await;
+
+	let shared: Vec<_> = candidates
+		.into_iter()
+		.stream()
+		.ready_filter(|(user_id, _)| user_id != sender_user)
+		.broad_then(|(user_id, rooms)| async move {
+			let shared = self.shared(sender_user, &user_id, &rooms, since).await;
+			(user_id, shared)
+		})
+		.collect()
+		.await;
+
+	diff(shared)
+}
+
+/// Users whose sharing of the room with the user syncing may have changed
+/// since `since`: those whose membership changed, or everyone in it when the
+/// room itself changed for the user syncing.
+#[implement(Service)]
+async fn membership_changes(
+	&self,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	since: u64,
+) -> Vec<OwnedUserId> {
+	let (since_state, current_state) = join(
+		self.services.user.get_token_shortstatehash(room_id, since),
+		self.services.state.get_room_shortstatehash(room_id),
+	)
+	.await;
+
+	let Ok(current_state) = current_state else {
+		return Vec::new();
+	};
+
+	let since_state = since_state.ok();
+	if since_state == Some(current_state) {
+		return Vec::new();
+	}
+
+	let mut user_ids: Vec<OwnedUserId> = match since_state {
+		| None => Vec::new(),
+		| Some(since_state) =>
+			self.services
+				.state_accessor
+				.state_added((since_state, current_state))
+				.broad_filter_map(|(shortstatekey, _)| {
+					self.services
+						.short
+						.get_statekey_from_short(shortstatekey)
+						.ok()
+				})
+				.ready_filter_map(|(event_type, state_key)| {
+					(event_type == StateEventType::RoomMember)
+						.then(|| UserId::parse(state_key.as_str()).ok())
+						.flatten()
+				})
+				.collect()
+				.await,
+	};
+
+	let room_changed = match since_state {
+		| None => true,
+		| Some(since_state) => {
+			let (then, now) = join(
+				self.membership(since_state, sender_user, sender_user),
+				self.membership(current_state, sender_user, sender_user),
+			)
+			.await;
+
+			then.encrypted != now.encrypted || then.sender_joined != now.sender_joined
+		},
+	};
+
+	if room_changed {
+		self.services
+			.state_cache
+			.room_members(room_id)
+			.map(ToOwned::to_owned)
+			.ready_for_each(|user_id| user_ids.push(user_id))
+			.await;
+	}
+
+	user_ids
+}
+
+/// Whether `user_id` shares an encrypted room with the user syncing, as of
+/// `since` and now. Any room they shared at `since` either still has them both
+/// or is among the `rooms` their membership changed in.
+#[implement(Service)]
+async fn shared(
+	&self,
+	sender_user: &UserId,
+	user_id: &UserId,
+	rooms: &[OwnedRoomId],
+	since: u64,
+) -> Shared {
+	let shared_rooms: Vec<OwnedRoomId> = self
+		.services
+		.state_cache
+		.get_shared_rooms(sender_user, user_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let shared_now = shared_rooms
+		.iter()
+		.stream()
+		.broad_any(|room_id| self.services.state_accessor.is_encrypted_room(room_id));
+
+	let shared_since =
+		shared_rooms
+			.iter()
+			.chain(rooms)
+			.stream()
+			.broad_any(|room_id| async move {
+				let Ok(since_state) = self
+					.services
+					.user
+					.get_token_shortstatehash(room_id, since)
+					.await
+				else {
+					return false;
+				};
+
+				shares(self.membership(since_state, sender_user, user_id).await)
+			});
+
+	let (since, now) = join(shared_since, shared_now).await;
+
+	Shared { since, now }
+}
+
+#[implement(Service)]
+async fn membership(
+	&self,
+	shortstatehash: ShortStateHash,
+	sender_user: &UserId,
+	user_id: &UserId,
+) -> Membership {
+	let encrypted = self
+		.services
+		.state_accessor
+		.state_get(shortstatehash, &StateEventType::RoomEncryption, "")
+		.is_ok();
+
+	let sender = self
+		.services
+		.state_accessor
+		.user_membership(shortstatehash, sender_user);
+
+	let user = self
+		.services
+		.state_accessor
+		.user_membership(shortstatehash, user_id);
+
+	let (encrypted, sender, user) = join3(encrypted, sender, user).await;
+
+	Membership {
+		encrypted,
+		sender_joined: sender == MembershipState::Join,
+		user_joined: user == MembershipState::Join,
+	}
+}
+
+#[inline]
+pub(super) fn shares(membership: Membership) -> bool {
+	membership.encrypted && membership.sender_joined && membership.user_joined
+}
+
+/// Users sharing an encrypted room now but not at `since` go in `changed`, and
+/// those who did then but no longer do go in `left`.
+pub(super) fn diff<I>(shared: I) -> DeviceLists
+where
+	I: IntoIterator<Item = (OwnedUserId, Shared)>,
+{
+	shared
+		.into_iter()
+		.fold(DeviceLists::default(), |mut lists, (user_id, shared)| {
+			match (shared.since, shared.now) {
+				| (false, true) => lists.changed.insert(user_id),
+				| (true, false) => lists.left.insert(user_id),
+				| _ => false,
+			};
+
+			lists
+		})
+}
@@ -1,8 +1,13 @@
+mod device_lists;
+#[cfg(test)]
+mod tests;
+mod token;
 mod watch;
 
 use std::{
 	collections::{BTreeMap, BTreeSet},
 	sync::{Arc, Mutex, Mutex as StdMutex},
+	time::{Duration, Instant},
 };
 
 use conduwuit::{Result, Server};
@@ -16,6 +21,7 @@
 	},
 };
 
+pub use self::{device_lists::DeviceLists, token::Token};
 use crate::{Dep, rooms};
 
 pub struct Service {
@@ -36,14 +42,18 @@ pub struct Data {
 	keychangeid_userid: Arc<Map>,
 	roomusertype_roomuserdataid: Arc<Map>,
 	readreceiptid_readreceipt: Arc<Map>,
+	roomuserid_lastprivatereadupdate: Arc<Map>,
 	userid_lastonetimekeyupdate: Arc<Map>,
 }
 
 struct Services {
 	server: Arc<Server>,
 	short: Dep<rooms::short::Service>,
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
 	typing: Dep<rooms::typing::Service>,
+	user: Dep<rooms::user::Service>,
 }
 
 struct SlidingSyncCache {
@@ -54,14 +64,30 @@ struct SlidingSyncCache {
 	extensions: ExtensionsConfig,
 }
 
-#[derive(Default)]
 struct SnakeSyncCache {
 	lists: BTreeMap<String, v5::request::List>,
 	subscriptions: BTreeMap<OwnedRoomId, v5::request::RoomSubscription>,
 	known_rooms: BTreeMap<String, BTreeMap<OwnedRoomId, u64>>,
 	extensions: v5::request::Extensions,
+	last_used: Instant,
 }
 
+impl Default for SnakeSyncCache {
+	fn default() -> Self {
+		Self {
+			lists: BTreeMap::new(),
+			subscriptions: BTreeMap::new(),
+			known_rooms: BTreeMap::new(),
+			extensions: v5::request::Extensions::default(),
+			last_used: Instant::now(),
+		}
+	}
+}
+
+/// Idle time after which the state of a simplified sliding sync connection is
+/// dropped; its client is then given a full resync.
+const SNAKE_CONNECTION_IDLE: Duration = Duration::from_secs(30 * 60);
+
 type DbConnections<K, V> = Mutex<BTreeMap<K, V>>;
 type DbConnectionsKey = (OwnedUserId, OwnedDeviceId, String);
 type DbConnectionsVal = Arc<Mutex<SlidingSyncCache>>;
@@ -82,13 +108,19 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				keychangeid_userid: args.db["keychangeid_userid"].clone(),
 				roomusertype_roomuserdataid: args.db["roomusertype_roomuserdataid"].clone(),
 				readreceiptid_readreceipt: args.db["readreceiptid_readreceipt"].clone(),
+				roomuserid_lastprivatereadupdate: args.db["roomuserid_lastprivatereadupdate"]
+					.clone(),
 				userid_lastonetimekeyupdate: args.db["userid_lastonetimekeyupdate"].clone(),
 			},
 			services: Services {
 				server: args.server.clone(),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 				typing: args.depend::<rooms::typing::Service>("rooms::typing"),
+				user: args.depend::<rooms::user::Service>("rooms::user"),
 			},
 			connections: StdMutex::new(BTreeMap::new()),
 			snake_connections: StdMutex::new(BTreeMap::new()),
@@ -112,16 +144,18 @@ fn some_or_sticky<T>(target: &mut Option<T>, cached: Option<T>) {
 }
 
 impl Service {
+	/// Whether the connection is still cached. Idle connections are dropped
+	/// first, and the one found is kept from idling out before the request
+	/// using it updates it.
 	pub fn snake_connection_cached(
 		&self,
 		user_id: OwnedUserId,
 		device_id: OwnedDeviceId,
 		conn_id: Option<String>,
 	) -> bool {
-		self.snake_connections
-			.lock()
-			.unwrap()
-			.contains_key(&(user_id, device_id, conn_id))
+		let mut cache = self.snake_connections.lock().expect("locked");
+
+		connection_cached(&mut cache, &(user_id, device_id, conn_id), Instant::now())
 	}
 
 	pub fn forget_snake_sync_connection(
@@ -168,6 +202,7 @@ pub fn update_snake_sync_request_with_cache(
 	) -> BTreeMap<String, BTreeMap<OwnedRoomId, u64>> {
 		let conn_id = request.conn_id.clone();
 		let mut cache = self.snake_connections.lock().expect("locked");
+		prune_idle_connections(&mut cache, Instant::now());
 		let cached = Arc::clone(
 			cache
 				.entry((user_id, device_id, conn_id))
@@ -176,6 +211,8 @@ pub fn update_snake_sync_request_with_cache(
 		let cached = &mut cached.lock().expect("locked");
 		drop(cache);
 
+		cached.last_used = Instant::now();
+
 		//v5::Request::try_from_http_request(req, path_args);
 		for (list_id, list) in &mut request.lists {
 			if let Some(cached_list) = cached.lists.get(list_id) {
@@ -487,3 +524,36 @@ pub fn update_snake_sync_subscriptions(
 		cached.subscriptions = subscriptions;
 	}
 }
+
+/// Whether the connection is cached once the idle ones are dropped, marking
+/// it used at `now` if so.
+fn connection_cached(
+	connections: &mut BTreeMap<SnakeConnectionsKey, SnakeConnectionsVal>,
+	key: &SnakeConnectionsKey,
+	now: Instant,
+) -> bool {
+	prune_idle_connections(connections, now);
+	let Some(cached) = connections.get(key) else {
+		return false;
+	};
+
+	cached.lock().expect("locked").last_used = now;
+
+	true
+}
+
+/// Drops the connections idle for longer than [`SNAKE_CONNECTION_IDLE`],
+/// returning how many were dropped.
+fn prune_idle_connections(
+	connections: &mut BTreeMap<SnakeConnectionsKey, SnakeConnectionsVal>,
+	now: Instant,
+) -> usize {
+	let before = connections.len();
+	connections.retain(|_, cached| {
+		let last_used = cached.lock().expect("locked").last_used;
+
+		now.saturating_duration_since(last_used) <= SNAKE_CONNECTION_IDLE
+	});
+
+	before.saturating_sub(connections.len())
+}
@@ -0,0 +1,147 @@
+use std::{
+	collections::BTreeMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use ruma::{OwnedUserId, owned_device_id, owned_user_id};
+
+use super::{
+	SNAKE_CONNECTION_IDLE, SnakeSyncCache, Token, connection_cached,
+	device_lists::{DeviceLists, Membership, Shared, diff, shares},
+	prune_idle_connections,
+};
+
+#[test]
+fn prune_idle_snake_connections() {
+	let now = Instant::now();
+	let later = now
+		.checked_add(SNAKE_CONNECTION_IDLE)
+		.and_then(|idle| idle.checked_add(Duration::from_secs(1)))
+		.expect("instant in range");
+
+	let user_id = owned_user_id!("@alice:example.com");
+	let device_id = owned_device_id!("DEVICE");
+	let mut connections = BTreeMap::new();
+	for conn_id in ["idle", "active"] {
+		let key = (user_id.clone(), device_id.clone(), Some(conn_id.to_owned()));
+		connections.insert(key, Arc::new(Mutex::new(SnakeSyncCache::default())));
+	}
+
+	assert_eq!(prune_idle_connections(&mut connections, now), 0);
+
+	let active = (user_id.clone(), device_id.clone(), Some("active".to_owned()));
+	connections[&active].lock().expect("locked").last_used = later;
+
+	assert_eq!(prune_idle_connections(&mut connections, later), 1);
+	assert_eq!(connections.len(), 1);
+	assert!(connections.contains_key(&active));
+}
+
+#[test]
+fn idle_snake_connection_not_cached() {
+	let now = Instant::now();
+	let later = now
+		.checked_add(SNAKE_CONNECTION_IDLE)
+		.and_then(|idle| idle.checked_add(Duration::from_secs(1)))
+		.expect("instant in range");
+
+	let key = (owned_user_id!("@alice:example.com"), owned_device_id!("DEVICE"), None);
+	let mut connections = BTreeMap::new();
+	connections.insert(key.clone(), Arc::new(Mutex::new(SnakeSyncCache::default())));
+
+	assert!(connection_cached(&mut connections, &key, now));
+	assert!(
+		!connection_cached(&mut connections, &key, later),
+		"idled out before it was pruned"
+	);
+	assert!(connections.is_empty());
+
+	let halfway = SNAKE_CONNECTION_IDLE
+		.checked_div(2)
+		.and_then(|half| now.checked_add(half))
+		.expect("instant in range");
+
+	connections.insert(key.clone(), Arc::new(Mutex::new(SnakeSyncCache::default())));
+	assert!(connection_cached(&mut connections, &key, halfway));
+	assert!(connection_cached(&mut connections, &key, later), "marked used when found");
+}
+
+#[test]
+fn sync_token_round_trip() {
+	for token in [Token::from(42), Token { count: 42, to_device: Some(7) }] {
+		assert_eq!(token.to_string().parse::<Token>(), Ok(token));
+	}
+
+	assert_eq!("42".parse(), Ok(Token::from(42)));
+	assert_eq!("42_7".parse::<Token>().map(|token| token.to_device()), Ok(7));
+	assert_eq!(Token::from(42).to_device(), 42);
+
+	for invalid in ["", "x", "42_", "_7", "42_7_1"] {
+		assert!(invalid.parse::<Token>().is_err(), "{invalid:?}");
+	}
+}
+
+const JOINED: Membership = Membership {
+	encrypted: true,
+	sender_joined: true,
+	user_joined: true,
+};
+
+const LEFT: Membership = Membership { user_joined: false, ..JOINED };
+
+const UNENCRYPTED: Membership = Membership { encrypted: false, ..JOINED };
+
+/// Device lists for one user, from the rooms shared with them at `since` and
+/// now.
+fn device_lists(since: &[Membership], now: &[Membership]) -> (OwnedUserId, DeviceLists) {
+	let user_id = owned_user_id!("@bob:example.com");
+	let shared = Shared {
+		since: since.iter().copied().any(shares),
+		now: now.iter().copied().any(shares),
+	};
+
+	(user_id.clone(), diff([(user_id, shared)]))
+}
+
+#[test]
+fn device_lists_join() {
+	let (user_id, lists) = device_lists(&[LEFT], &[JOINED]);
+	assert!(lists.changed.contains(&user_id));
+	assert!(lists.left.is_empty());
+
+	let (_, lists) = device_lists(&[], &[UNENCRYPTED]);
+	assert!(lists.changed.is_empty(), "room not encrypted");
+
+	let (user_id, lists) = device_lists(&[UNENCRYPTED], &[JOINED]);
+	assert!(lists.changed.contains(&user_id), "room encrypted since");
+
+	let (_, lists) = device_lists(&[JOINED], &[JOINED, JOINED]);
+	assert!(lists.changed.is_empty(), "already shared another room");
+}
+
+#[test]
+fn device_lists_leave() {
+	let (user_id, lists) = device_lists(&[JOINED], &[LEFT]);
+	assert!(lists.left.contains(&user_id), "left the last shared room");
+	assert!(lists.changed.is_empty());
+
+	let sender_left = Membership { sender_joined: false, ..JOINED };
+	let (user_id, lists) = device_lists(&[JOINED], &[sender_left]);
+	assert!(lists.left.contains(&user_id), "sender left the last shared room");
+
+	let (_, lists) = device_lists(&[JOINED, JOINED], &[LEFT, JOINED]);
+	assert!(lists.left.is_empty(), "still sharing another room");
+}
+
+#[test]
+fn device_lists_join_and_leave_between_syncs() {
+	let (_, lists) = device_lists(&[LEFT], &[LEFT]);
+	assert!(lists.changed.is_empty() && lists.left.is_empty(), "joined and left again");
+
+	let (_, lists) = device_lists(&[JOINED, LEFT], &[LEFT, JOINED]);
+	assert!(lists.changed.is_empty() && lists.left.is_empty(), "moved to another room");
+
+	let (_, lists) = device_lists(&[JOINED], &[JOINED]);
+	assert!(lists.changed.is_empty() && lists.left.is_empty(), "left and joined again");
+}
@@ -0,0 +1,46 @@
+use std::{fmt, str::FromStr};
+
+/// The `since` and `next_batch` tokens of the sync API. Most of what a sync
+/// sends is bounded by the one count; to-device messages are sent a page at a
+/// time, so when they did not all fit, the token also carries the count of the
+/// last one sent.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Token {
+	pub count: u64,
+	pub to_device: Option<u64>,
+}
+
+impl Token {
+	/// Count of the last to-device message the client has received.
+	#[inline]
+	#[must_use]
+	pub fn to_device(&self) -> u64 { self.to_device.unwrap_or(self.count) }
+}
+
+impl From<u64> for Token {
+	fn from(count: u64) -> Self { Self { count, to_device: None } }
+}
+
+impl FromStr for Token {
+	type Err = std::num::ParseIntError;
+
+	fn from_str(token: &str) -> Result<Self, Self::Err> {
+		let Some((count, to_device)) = token.split_once('_') else {
+			return Ok(Self { count: token.parse()?, to_device: None });
+		};
+
+		Ok(Self {
+			count: count.parse()?,
+			to_device: Some(to_device.parse()?),
+		})
+	}
+}
+
+impl fmt::Display for Token {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.to_device {
+			| Some(to_device) => write!(f, "{}_{to_device}", self.count),
+			| None => write!(f, "{}", self.count),
+		}
+	}
+}
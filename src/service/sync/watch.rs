@@ -1,10 +1,25 @@
-use conduwuit::{Result, implement, trace};
-use futures::{FutureExt, StreamExt, pin_mut, stream::FuturesUnordered};
-use ruma::{DeviceId, UserId};
+use std::collections::HashSet;
 
+use conduwuit::{implement, trace};
+use futures::{FutureExt, StreamExt, stream::FuturesUnordered};
+use ruma::{DeviceId, OwnedRoomId, UserId};
+
+/// Past this many joined rooms, one watch over every room stands in for the
+/// watches on each of them.
+const ROOMS_MAX: usize = 1024;
+
+/// Watches everything which would change the user's next sync, returning a
+/// future which resolves on the first change.
+///
+/// Every watch is in place once this returns, before the caller builds its
+/// response, so changes made while it does still end the long-poll.
 #[implement(super::Service)]
 #[tracing::instrument(skip(self), level = "debug")]
-pub async fn watch(&self, user_id: &UserId, device_id: &DeviceId) -> Result {
+pub async fn watch<'a>(
+	&'a self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+) -> impl Future<Output = ()> + Send + 'a {
 	let userid_bytes = user_id.as_bytes().to_vec();
 	let mut userid_prefix = userid_bytes.clone();
 	userid_prefix.push(0xFF);
@@ -34,49 +49,66 @@ pub async fn watch(&self, user_id: &UserId, device_id: &DeviceId) -> Result {
 	);
 
 	// Events for rooms we are in
-	let rooms_joined = self.services.state_cache.rooms_joined(user_id);
-
-	pin_mut!(rooms_joined);
-	while let Some(room_id) = rooms_joined.next().await {
-		let Ok(short_roomid) = self.services.short.get_shortroomid(room_id).await else {
-			continue;
-		};
-
-		let roomid_bytes = room_id.as_bytes().to_vec();
-		let mut roomid_prefix = roomid_bytes.clone();
-		roomid_prefix.push(0xFF);
-
-		// Key changes
-		futures.push(self.db.keychangeid_userid.watch_prefix(&roomid_prefix));
-
-		// Room account data
-		let mut roomuser_prefix = roomid_prefix.clone();
-		roomuser_prefix.extend_from_slice(&userid_prefix);
-
-		futures.push(
-			self.db
-				.roomusertype_roomuserdataid
-				.watch_prefix(&roomuser_prefix),
-		);
-
-		// PDUs
-		let short_roomid = short_roomid.to_be_bytes().to_vec();
-		futures.push(self.db.pduid_pdu.watch_prefix(&short_roomid));
-
-		// EDUs
-		let typing_room_id = room_id.to_owned();
-		let typing_wait_for_update = async move {
-			self.services.typing.wait_for_update(&typing_room_id).await;
-		};
-
-		futures.push(typing_wait_for_update.boxed());
-		futures.push(
-			self.db
-				.readreceiptid_readreceipt
-				.watch_prefix(&roomid_prefix),
-		);
+	let rooms_joined: Vec<OwnedRoomId> = self
+		.services
+		.state_cache
+		.rooms_joined(user_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	if rooms_joined.len() > ROOMS_MAX {
+		trace!(rooms = rooms_joined.len(), "watching all rooms");
+		futures.push(self.db.keychangeid_userid.watch_prefix(&[]));
+		futures.push(self.db.roomusertype_roomuserdataid.watch_prefix(&[]));
+		futures.push(self.db.pduid_pdu.watch_prefix(&[]));
+		futures.push(self.db.readreceiptid_readreceipt.watch_prefix(&[]));
+		futures.push(self.db.roomuserid_lastprivatereadupdate.watch_prefix(&[]));
+	} else {
+		for room_id in &rooms_joined {
+			let Ok(short_roomid) = self.services.short.get_shortroomid(room_id).await else {
+				continue;
+			};
+
+			let roomid_bytes = room_id.as_bytes().to_vec();
+			let mut roomid_prefix = roomid_bytes.clone();
+			roomid_prefix.push(0xFF);
+
+			// Key changes
+			futures.push(self.db.keychangeid_userid.watch_prefix(&roomid_prefix));
+
+			// Room account data
+			let mut roomuser_prefix = roomid_prefix.clone();
+			roomuser_prefix.extend_from_slice(&userid_prefix);
+
+			futures.push(
+				self.db
+					.roomusertype_roomuserdataid
+					.watch_prefix(&roomuser_prefix),
+			);
+
+			// PDUs
+			let short_roomid = short_roomid.to_be_bytes().to_vec();
+			futures.push(self.db.pduid_pdu.watch_prefix(&short_roomid));
+
+			// Receipts, public and private
+			futures.push(
+				self.db
+					.readreceiptid_readreceipt
+					.watch_prefix(&roomid_prefix),
+			);
+			futures.push(
+				self.db
+					.roomuserid_lastprivatereadupdate
+					.watch_prefix(&roomuser_prefix),
+			);
+		}
 	}
 
+	// EDUs
+	let rooms_joined: HashSet<OwnedRoomId> = rooms_joined.into_iter().collect();
+	futures.push(self.services.typing.wait_for_update(rooms_joined).boxed());
+
 	let mut globaluserdata_prefix = vec![0xFF];
 	globaluserdata_prefix.extend_from_slice(&userid_prefix);
 
@@ -99,14 +131,15 @@ pub async fn watch(&self, user_id: &UserId, device_id: &DeviceId) -> Result {
 	// Server shutdown
 	futures.push(self.services.server.until_shutdown().boxed());
 
-	if !self.services.server.running() {
-		return Ok(());
-	}
-
-	// Wait until one of them finds something
-	trace!(futures = futures.len(), "watch started");
-	futures.next().await;
-	trace!(futures = futures.len(), "watch finished");
+	let running = self.services.server.running();
+	async move {
+		if !running {
+			return;
+		}
 
-	Ok(())
+		// Wait until one of them finds something
+		trace!(futures = futures.len(), "watch started");
+		futures.next().await;
+		trace!(futures = futures.len(), "watch finished");
+	}
 }
@@ -0,0 +1,58 @@
+//! Services of servers of their own, each on a database in a temporary
+//! directory, for tests exercising them end to end.
+
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use conduwuit::{Config, Result, Server, config::Figment, log::Log, utils::TempDir};
+use tokio::runtime::Handle;
+
+use crate::Services;
+
+/// Started services, and the directories of their databases.
+pub struct Test {
+	pub services: Arc<Services>,
+	_dirs: Vec<TempDir>,
+}
+
+impl Test {
+	/// Starts the services of the server `config` describes, and of its
+	/// `virtual_hosts`, each on a database of its own. Federation is disabled
+	/// unless it is configured.
+	pub async fn start(config: Figment) -> Result<Self> {
+		let dir = TempDir::new("services");
+		let mut raw = config
+			.join(("database_path", dir.path()))
+			.join(("rocksdb_direct_io", false))
+			.join(("allow_federation", false));
+
+		let mut dirs = vec![dir];
+		for name in Config::new(&raw)?.virtual_hosts.into_keys() {
+			let dir = TempDir::new("services");
+			let database: BTreeMap<_, _> = [("database_path", dir.path())].into();
+			let host: BTreeMap<String, BTreeMap<&str, &Path>> =
+				[(name.to_string(), database)].into();
+			raw = raw.join(("virtual_hosts", host));
+			dirs.push(dir);
+		}
+
+		let config = Config::new(&raw)?;
+		let virtual_hosts = config.virtual_hosts(&raw)?;
+		let runtime = Handle::current();
+		let log = Log::default();
+		let mut server = Server::new(config, Some(runtime.clone()), log.clone());
+		server.virtual_hosts = virtual_hosts
+			.into_iter()
+			.map(|config| Server::new(config, Some(runtime.clone()), log.clone()))
+			.map(Arc::new)
+			.collect();
+
+		let services = Services::build(Arc::new(server)).await?.start().await?;
+
+		Ok(Self { services, _dirs: dirs })
+	}
+
+	pub async fn stop(self) {
+		_ = self.services.server.shutdown();
+		self.services.stop().await;
+	}
+}
@@ -0,0 +1,293 @@
+//! Decryption failure reports
+//!
+//! Clients which opt in report the encrypted events they could not decrypt,
+//! so operators have something to go on when users complain. Only admins see
+//! the reports, counted by room, by the server of the event's sender and by
+//! why decryption failed; one remote server whose sessions never arrive shows
+//! up as most failures being of its users' events.
+//!
+//! Reports are rate limited per user, capped in number and removed once
+//! older than the retention period.
+
+mod stats;
+#[cfg(test)]
+mod tests;
+
+use std::{
+	collections::HashMap,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicUsize, Ordering},
+	},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	Err, Error, PduCount, Result, Server, debug_info, debug_warn, implement,
+	utils::{
+		millis_since_unix_epoch,
+		stream::{ReadyExt, TryIgnore},
+		u64_from_u8,
+	},
+};
+use database::{Json, Lane, Map};
+use futures::StreamExt;
+use http::StatusCode;
+use ruma::{
+	EventId, OwnedUserId, RoomId, UserId, api::client::error::ErrorKind,
+	events::TimelineEventType,
+};
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
+
+pub use self::stats::{Class, Report, Stats, top};
+use crate::{Dep, globals, rooms};
+
+pub struct Service {
+	services: Services,
+	db: Data,
+	limiter: Limiter,
+
+	/// Reports stored, as of the last sweep and those added since.
+	stored: AtomicUsize,
+	interrupt: Notify,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+struct Data {
+	uisiid_report: Arc<Map>,
+}
+
+/// Reports each user sent in their current hour.
+#[derive(Debug, Default)]
+struct Limiter {
+	windows: Mutex<HashMap<OwnedUserId, (u64, usize)>>,
+}
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const HOUR_MILLIS: u64 = 60 * 60 * 1000;
+
+/// Longest session ID accepted; megolm session IDs are 43 characters.
+const SESSION_ID_MAX: usize = 128;
+
+/// Most events of a room's timeline looked through counting its encrypted
+/// messages.
+const SCAN_MAX: usize = 100_000;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+			db: Data {
+				uisiid_report: args.db["uisiid_report"].clone(),
+			},
+			limiter: Limiter::default(),
+			stored: AtomicUsize::new(0),
+			interrupt: Notify::new(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		// expired reports are still swept once reporting is turned off
+		let mut i = interval(SWEEP_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			let removed = Lane::Background.scope(self.sweep()).await;
+			if removed > 0 {
+				debug_info!(%removed, "Removed old decryption failure reports");
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether clients may report decryption failures.
+#[implement(Service)]
+#[must_use]
+pub fn is_enabled(&self) -> bool { self.services.server.config.uisi_reports }
+
+/// Stores `reporter`'s report of failing to decrypt the event. Once as many
+/// reports as the cap are stored, more are accepted but not kept until the
+/// next sweep makes room.
+#[implement(Service)]
+pub async fn report(
+	&self,
+	reporter: &UserId,
+	room_id: &RoomId,
+	event_id: &EventId,
+	session_id: &str,
+	class: Class,
+) -> Result {
+	if !self.is_enabled() {
+		return Err!(Request(Forbidden(
+			"Decryption failure reports are not collected on this server."
+		)));
+	}
+
+	if session_id.len() > SESSION_ID_MAX {
+		return Err!(Request(InvalidParam("Session ID is too long.")));
+	}
+
+	if !self.services.state_cache.is_joined(reporter, room_id).await {
+		return Err!(Request(Forbidden("You are not joined to this room.")));
+	}
+
+	let config = &self.services.server.config;
+	let now = millis_since_unix_epoch();
+	if !self
+		.limiter
+		.allow(reporter, now, config.uisi_reports_per_user_hourly)
+	{
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded { retry_after: None },
+			"Too many decryption failure reports; try again later.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	if self.stored.load(Ordering::Relaxed) >= config.uisi_report_max {
+		debug_warn!(%reporter, %event_id, "Decryption failure reports are full; not kept");
+		return Ok(());
+	}
+
+	// a report of an event of another room names no sender
+	let sender_server = self
+		.services
+		.timeline
+		.get_pdu(event_id)
+		.await
+		.ok()
+		.filter(|pdu| pdu.room_id == room_id)
+		.map(|pdu| pdu.sender.server_name().to_owned());
+
+	let report = Report {
+		room_id: room_id.to_owned(),
+		event_id: event_id.to_owned(),
+		session_id: session_id.to_owned(),
+		class,
+		reporter: reporter.to_owned(),
+		sender_server,
+		reported_at: now,
+	};
+
+	let key = report_key(now, self.services.globals.next_count()?);
+	self.db.uisiid_report.raw_put(key, Json(&report));
+	self.stored.fetch_add(1, Ordering::Relaxed);
+
+	Ok(())
+}
+
+/// Counts the reports since `since`, in milliseconds since the epoch, of the
+/// room or all rooms.
+#[implement(Service)]
+pub async fn stats(&self, room_id: Option<&RoomId>, since: u64) -> Stats {
+	let mut stats = Stats::default();
+	self.db
+		.uisiid_report
+		.raw_stream_from(&since.to_be_bytes())
+		.ignore_err()
+		.ready_filter_map(|(_, report)| serde_json::from_slice::<Report>(report).ok())
+		.ready_for_each(|report| stats.add(report, room_id))
+		.await;
+
+	stats
+}
+
+/// Encrypted messages sent in the room since `since`, in milliseconds since
+/// the epoch, looking back through at most [`SCAN_MAX`] events.
+#[implement(Service)]
+pub async fn encrypted_messages(&self, room_id: &RoomId, since: u64) -> usize {
+	self.services
+		.timeline
+		.pdus_rev(None, room_id, Some(PduCount::max()))
+		.ignore_err()
+		.take(SCAN_MAX)
+		.ready_take_while(|(_, pdu)| u64::from(pdu.origin_server_ts) >= since)
+		.ready_filter(|(_, pdu)| pdu.kind == TimelineEventType::RoomEncrypted)
+		.count()
+		.await
+}
+
+/// Removes the reports older than the retention period, then the oldest of
+/// the rest over the cap. Returns how many were removed.
+#[implement(Service)]
+async fn sweep(&self) -> usize {
+	let config = &self.services.server.config;
+	let retention = config.uisi_report_retention.saturating_mul(1000);
+	let cutoff = millis_since_unix_epoch().saturating_sub(retention);
+
+	let keys: Vec<Vec<u8>> = self
+		.db
+		.uisiid_report
+		.raw_keys()
+		.ignore_err()
+		.map(<[u8]>::to_vec)
+		.collect()
+		.await;
+
+	let timestamps: Vec<u64> = keys
+		.iter()
+		.map(|key| key.get(..size_of::<u64>()).map_or(0, u64_from_u8))
+		.collect();
+
+	let removed = stats::to_remove(&timestamps, cutoff, config.uisi_report_max);
+	for key in keys.iter().take(removed) {
+		self.db.uisiid_report.remove(key);
+	}
+
+	self.stored
+		.store(keys.len().saturating_sub(removed), Ordering::Relaxed);
+
+	removed
+}
+
+/// Reports are keyed by when they were made, so they are in order of age.
+fn report_key(reported_at: u64, count: u64) -> [u8; 16] {
+	let mut key = [0_u8; 16];
+	let (at, id) = key.split_at_mut(size_of::<u64>());
+	at.copy_from_slice(&reported_at.to_be_bytes());
+	id.copy_from_slice(&count.to_be_bytes());
+	key
+}
+
+impl Limiter {
+	/// Whether the user may report again at `now`, counting the report if so.
+	/// A `limit` of zero allows any number.
+	fn allow(&self, user_id: &UserId, now: u64, limit: usize) -> bool {
+		let mut windows = self.windows.lock().expect("locked");
+		windows.retain(|_, (start, _)| now.saturating_sub(*start) < HOUR_MILLIS);
+
+		let (_, sent) = windows.entry(user_id.to_owned()).or_insert((now, 0));
+		if limit > 0 && *sent >= limit {
+			return false;
+		}
+
+		*sent = sent.saturating_add(1);
+		true
+	}
+}
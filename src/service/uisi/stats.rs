@@ -0,0 +1,140 @@
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	fmt,
+	hash::Hash,
+};
+
+use ruma::{OwnedEventId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId};
+use serde::{Deserialize, Serialize};
+
+/// Why a client could not decrypt an event, as it reports it.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Class {
+	/// The megolm session never arrived.
+	MissingSession,
+
+	/// The session arrived, but only from a later message index.
+	UnknownIndex,
+
+	/// The sender's device withheld the session.
+	Withheld,
+
+	/// The session came from a device the client does not trust.
+	Untrusted,
+
+	/// The event was sent before the user could have had the session.
+	Historical,
+
+	#[serde(other)]
+	Other,
+}
+
+/// A client's report of failing to decrypt an event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Report {
+	pub room_id: OwnedRoomId,
+	pub event_id: OwnedEventId,
+	pub session_id: String,
+	pub class: Class,
+	pub reporter: OwnedUserId,
+
+	/// Server of the event's sender, when we have the event.
+	pub sender_server: Option<OwnedServerName>,
+
+	/// Milliseconds since the epoch.
+	pub reported_at: u64,
+}
+
+/// Decryption failure reports counted several ways. Each user's reports of
+/// the same event count once, however often their client sent it.
+#[derive(Debug, Default)]
+pub struct Stats {
+	pub failures: usize,
+	pub rooms: HashMap<OwnedRoomId, usize>,
+
+	/// By the server of the events' senders; `None` for events we do not
+	/// have.
+	pub servers: HashMap<Option<OwnedServerName>, usize>,
+
+	pub classes: BTreeMap<Class, usize>,
+	reporters: HashSet<OwnedUserId>,
+	events: HashSet<OwnedEventId>,
+	seen: HashSet<(OwnedUserId, OwnedEventId)>,
+}
+
+impl fmt::Display for Class {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			| Self::MissingSession => "missing session",
+			| Self::UnknownIndex => "unknown message index",
+			| Self::Withheld => "withheld",
+			| Self::Untrusted => "untrusted device",
+			| Self::Historical => "historical",
+			| Self::Other => "other",
+		})
+	}
+}
+
+impl Stats {
+	/// Counts the report, unless `room_id` is given and it is of another room.
+	pub fn add(&mut self, report: Report, room_id: Option<&RoomId>) {
+		if room_id.is_some_and(|room_id| room_id != report.room_id) {
+			return;
+		}
+
+		if !self
+			.seen
+			.insert((report.reporter.clone(), report.event_id.clone()))
+		{
+			return;
+		}
+
+		self.failures = self.failures.saturating_add(1);
+		increment(&mut self.rooms, report.room_id);
+		increment(&mut self.servers, report.sender_server);
+
+		let class = self.classes.entry(report.class).or_default();
+		*class = class.saturating_add(1);
+
+		self.reporters.insert(report.reporter);
+		self.events.insert(report.event_id);
+	}
+
+	/// Users who reported failures.
+	#[must_use]
+	pub fn reporters(&self) -> usize { self.reporters.len() }
+
+	/// Distinct events reported.
+	#[must_use]
+	pub fn events(&self) -> usize { self.events.len() }
+}
+
+/// The `limit` largest counts, largest first.
+#[must_use]
+pub fn top<K: Clone + Ord>(counts: &HashMap<K, usize>, limit: usize) -> Vec<(K, usize)> {
+	let mut top: Vec<_> = counts
+		.iter()
+		.map(|(key, &count)| (key.clone(), count))
+		.collect();
+
+	top.sort_unstable_by(|(a_key, a), (b_key, b)| b.cmp(a).then_with(|| a_key.cmp(b_key)));
+	top.truncate(limit);
+	top
+}
+
+fn increment<K: Eq + Hash>(counts: &mut HashMap<K, usize>, key: K) {
+	let count = counts.entry(key).or_default();
+	*count = count.saturating_add(1);
+}
+
+/// How many of the oldest reports are to be removed, of those reported at
+/// `timestamps` in order: all from before `cutoff`, and then as many more as
+/// keeps at most `max`.
+#[must_use]
+pub(super) fn to_remove(timestamps: &[u64], cutoff: u64, max: usize) -> usize {
+	let expired = timestamps.partition_point(|&reported_at| reported_at < cutoff);
+	let kept = timestamps.len().saturating_sub(expired);
+
+	expired.saturating_add(kept.saturating_sub(max))
+}
@@ -0,0 +1,124 @@
+use ruma::{owned_room_id, owned_server_name, room_id, user_id};
+
+use super::{
+	Class, HOUR_MILLIS, Limiter, Report, Stats, report_key,
+	stats::{to_remove, top},
+};
+
+fn report(room: &str, event: &str, reporter: &str, server: Option<&str>, class: Class) -> Report {
+	Report {
+		room_id: room.try_into().expect("valid room"),
+		event_id: event.try_into().expect("valid event"),
+		session_id: "session".to_owned(),
+		class,
+		reporter: reporter.try_into().expect("valid user"),
+		sender_server: server.map(|server| server.try_into().expect("valid server")),
+		reported_at: 0,
+	}
+}
+
+#[test]
+fn uisi_stats_counts() {
+	let mut stats = Stats::default();
+	let reports = [
+		report("!a:x", "$1", "@u:x", Some("bad.example"), Class::MissingSession),
+		report("!a:x", "$2", "@u:x", Some("bad.example"), Class::MissingSession),
+		report("!a:x", "$2", "@v:x", Some("bad.example"), Class::UnknownIndex),
+		report("!b:x", "$3", "@v:x", Some("good.example"), Class::Withheld),
+		report("!b:x", "$4", "@v:x", None, Class::Other),
+	];
+
+	for report in reports {
+		stats.add(report, None);
+	}
+
+	assert_eq!(stats.failures, 5);
+	assert_eq!(stats.reporters(), 2);
+	assert_eq!(stats.events(), 4);
+	assert_eq!(stats.rooms[&owned_room_id!("!a:x")], 3);
+	assert_eq!(stats.servers[&Some(owned_server_name!("bad.example"))], 3);
+	assert_eq!(stats.servers[&None], 1);
+	assert_eq!(stats.classes[&Class::MissingSession], 2);
+	assert_eq!(stats.classes.get(&Class::Untrusted), None);
+}
+
+#[test]
+fn uisi_stats_resent_once() {
+	let mut stats = Stats::default();
+	for _ in 0..3 {
+		stats.add(report("!a:x", "$1", "@u:x", None, Class::MissingSession), None);
+	}
+
+	assert_eq!(stats.failures, 1);
+	assert_eq!(stats.classes[&Class::MissingSession], 1);
+}
+
+#[test]
+fn uisi_stats_room_filter() {
+	let mut stats = Stats::default();
+	let room = room_id!("!b:x");
+	stats.add(report("!a:x", "$1", "@u:x", None, Class::Other), Some(room));
+	stats.add(report("!b:x", "$2", "@u:x", None, Class::Other), Some(room));
+
+	assert_eq!(stats.failures, 1);
+	assert_eq!(stats.rooms.len(), 1);
+	assert!(stats.rooms.contains_key(room));
+}
+
+#[test]
+fn uisi_top() {
+	let mut stats = Stats::default();
+	for (event, room) in [("$1", "!a:x"), ("$2", "!b:x"), ("$3", "!b:x"), ("$4", "!c:x")] {
+		stats.add(report(room, event, "@u:x", None, Class::Other), None);
+	}
+
+	let top = top(&stats.rooms, 2);
+	assert_eq!(top, vec![(owned_room_id!("!b:x"), 2), (owned_room_id!("!a:x"), 1)]);
+}
+
+#[test]
+fn uisi_to_remove() {
+	let timestamps = [10, 20, 30, 40, 50];
+
+	assert_eq!(to_remove(&timestamps, 0, 10), 0);
+	assert_eq!(to_remove(&timestamps, 25, 10), 2);
+	assert_eq!(to_remove(&timestamps, 25, 2), 3);
+	assert_eq!(to_remove(&timestamps, 0, 0), 5);
+	assert_eq!(to_remove(&[], 25, 2), 0);
+}
+
+#[test]
+fn uisi_report_keys_ordered() {
+	let earlier = report_key(1_000, 9);
+	let later = report_key(2_000, 1);
+
+	assert!(earlier < later);
+	assert!(report_key(1_000, 1) < earlier);
+	assert!(earlier.starts_with(&1_000_u64.to_be_bytes()));
+}
+
+#[test]
+fn uisi_limiter() {
+	let limiter = Limiter::default();
+	let user = user_id!("@u:x");
+
+	assert!(limiter.allow(user, 0, 2));
+	assert!(limiter.allow(user, 1, 2));
+	assert!(!limiter.allow(user, 2, 2));
+	assert!(limiter.allow(user_id!("@v:x"), 2, 2));
+
+	// the hour starting with the first report has passed
+	assert!(limiter.allow(user, HOUR_MILLIS, 2));
+
+	let unlimited = Limiter::default();
+	assert!((0..100).all(|now| unlimited.allow(user, now, 0)));
+}
+
+#[test]
+fn uisi_class_names() {
+	let class: Class = serde_json::from_str("\"missing_session\"").expect("known class");
+	assert_eq!(class, Class::MissingSession);
+
+	let class: Class = serde_json::from_str("\"new_kind\"").expect("unknown class");
+	assert_eq!(class, Class::Other);
+}
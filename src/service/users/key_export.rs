@@ -0,0 +1,349 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use conduwuit::{Err, Result, err};
+use database::{Deserialized, Map};
+use futures::StreamExt;
+use ruma::{
+	CanonicalJsonObject, CanonicalJsonValue, OneTimeKeyAlgorithm, OwnedDeviceId, OwnedUserId,
+	UInt, UserId,
+	encryption::{CrossSigningKey, DeviceKeys, KeyUsage},
+	serde::{Base64, Raw},
+	signatures::{PublicKeyMap, verify_json},
+};
+use serde::{Deserialize, Serialize};
+
+/// Version of the key export format written by this server.
+pub const KEY_EXPORT_VERSION: u32 = 1;
+
+/// Public cross-signing and device key state of one user, for moving it to
+/// another server. Private keys never leave the user's clients and are not
+/// part of an export.
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "user_id": "@alice:example.org",
+///   "master_key": { "user_id": "@alice:example.org", "usage": ["master"], ... },
+///   "self_signing_key": { ... },
+///   "user_signing_key": { ... },
+///   "devices": {
+///     "ABCDEFGH": {
+///       "keys": { "user_id": "@alice:example.org", "device_id": "ABCDEFGH", ... },
+///       "one_time_key_counts": { "signed_curve25519": 50 }
+///     }
+///   }
+/// }
+/// ```
+///
+/// Keys are stored as uploaded, including every signature the server holds on
+/// them, so trust established by other users is carried over.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeyExport {
+	pub version: u32,
+
+	pub user_id: OwnedUserId,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub master_key: Option<Raw<CrossSigningKey>>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub self_signing_key: Option<Raw<CrossSigningKey>>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub user_signing_key: Option<Raw<CrossSigningKey>>,
+
+	#[serde(default)]
+	pub devices: BTreeMap<OwnedDeviceId, DeviceKeysExport>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct DeviceKeysExport {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub keys: Option<Raw<DeviceKeys>>,
+
+	/// Informational only; one-time keys are claimed and replenished by the
+	/// device and are not imported.
+	#[serde(default)]
+	pub one_time_key_counts: BTreeMap<OneTimeKeyAlgorithm, UInt>,
+}
+
+/// Outcome of [`Service::import_keys`].
+#[derive(Debug, Default)]
+pub struct KeyImport {
+	/// Cross-signing keys written.
+	pub cross_signing_keys: usize,
+
+	/// Device key records written.
+	pub devices: usize,
+
+	/// Devices in the export which do not exist on this server.
+	pub skipped_devices: Vec<OwnedDeviceId>,
+}
+
+impl super::Service {
+	/// Collects the public key state of a user for export.
+	pub async fn export_keys(&self, user_id: &UserId) -> Result<KeyExport> {
+		let mut devices = BTreeMap::new();
+		let mut device_ids = self.all_device_ids(user_id).boxed();
+		while let Some(device_id) = device_ids.next().await {
+			let device = DeviceKeysExport {
+				keys: self.get_device_keys(user_id, device_id).await.ok(),
+				one_time_key_counts: self.count_one_time_keys(user_id, device_id).await,
+			};
+
+			devices.insert(device_id.to_owned(), device);
+		}
+
+		Ok(KeyExport {
+			version: KEY_EXPORT_VERSION,
+			user_id: user_id.to_owned(),
+			master_key: self
+				.raw_cross_signing_key(&self.db.userid_masterkeyid, user_id)
+				.await,
+			self_signing_key: self
+				.raw_cross_signing_key(&self.db.userid_selfsigningkeyid, user_id)
+				.await,
+			user_signing_key: self
+				.raw_cross_signing_key(&self.db.userid_usersigningkeyid, user_id)
+				.await,
+			devices,
+		})
+	}
+
+	/// The cross-signing key referenced from `index` as stored, with all of its
+	/// signatures.
+	async fn raw_cross_signing_key(
+		&self,
+		index: &Arc<Map>,
+		user_id: &UserId,
+	) -> Option<Raw<CrossSigningKey>> {
+		let key_id = index.get(user_id).await.ok()?;
+
+		self.db.keyid_key.get(&*key_id).await.deserialized().ok()
+	}
+
+	/// Validates and stores an export for `user_id`. Device keys are only
+	/// stored for devices which exist on this server.
+	pub async fn import_keys(&self, user_id: &UserId, export: &KeyExport) -> Result<KeyImport> {
+		validate_export(user_id, export)?;
+
+		let cross_signing_keys =
+			[&export.master_key, &export.self_signing_key, &export.user_signing_key];
+
+		let mut import = KeyImport {
+			cross_signing_keys: cross_signing_keys
+				.iter()
+				.filter(|key| key.is_some())
+				.count(),
+			..KeyImport::default()
+		};
+
+		if import.cross_signing_keys > 0 {
+			self.add_cross_signing_keys(
+				user_id,
+				&export.master_key,
+				&export.self_signing_key,
+				&export.user_signing_key,
+				true,
+			)
+			.await?;
+		}
+
+		for (device_id, device) in &export.devices {
+			let Some(keys) = &device.keys else {
+				continue;
+			};
+
+			if self.get_device_metadata(user_id, device_id).await.is_err() {
+				import.skipped_devices.push(device_id.clone());
+				continue;
+			}
+
+			self.add_device_keys(user_id, device_id, keys).await;
+			import.devices = import.devices.saturating_add(1);
+		}
+
+		Ok(import)
+	}
+}
+
+/// Checks an export belongs to `user_id` and that the signatures its keys
+/// carry from the user's own keys are valid. Self- and user-signing keys must
+/// be signed by the master key and device keys by the device itself.
+/// Signatures from other users are kept without checking, as their keys are
+/// not part of the export.
+pub fn validate_export(user_id: &UserId, export: &KeyExport) -> Result {
+	if export.version != KEY_EXPORT_VERSION {
+		return Err!(Request(InvalidParam(
+			"Unsupported key export version {}; expected {KEY_EXPORT_VERSION}.",
+			export.version
+		)));
+	}
+
+	if export.user_id != user_id {
+		return Err!(Request(InvalidParam(
+			"Key export is for {} and cannot be imported for {user_id}.",
+			export.user_id
+		)));
+	}
+
+	let master = export
+		.master_key
+		.as_ref()
+		.map(|key| cross_signing_key(user_id, key, &KeyUsage::Master, "master key"))
+		.transpose()?;
+
+	let self_signing = export
+		.self_signing_key
+		.as_ref()
+		.map(|key| cross_signing_key(user_id, key, &KeyUsage::SelfSigning, "self-signing key"))
+		.transpose()?;
+
+	let user_signing = export
+		.user_signing_key
+		.as_ref()
+		.map(|key| cross_signing_key(user_id, key, &KeyUsage::UserSigning, "user-signing key"))
+		.transpose()?;
+
+	let mut known: BTreeMap<String, Base64> = [&master, &self_signing, &user_signing]
+		.into_iter()
+		.flatten()
+		.map(|(key_id, public_key, _)| (key_id.clone(), public_key.clone()))
+		.collect();
+
+	let mut devices = Vec::new();
+	for (device_id, device) in &export.devices {
+		let Some(keys) = &device.keys else {
+			continue;
+		};
+
+		let what = format!("device keys of {device_id}");
+		let parsed = keys
+			.deserialize()
+			.map_err(|e| err!(Request(InvalidParam("Invalid {what}: {e}"))))?;
+
+		if parsed.user_id != user_id || parsed.device_id != *device_id {
+			return Err!(Request(InvalidParam(
+				"The {what} belong to {} device {}.",
+				parsed.user_id,
+				parsed.device_id
+			)));
+		}
+
+		let key_id = format!("ed25519:{device_id}");
+		let public_key = parsed
+			.keys
+			.iter()
+			.find(|(id, _)| id.as_str() == key_id)
+			.map(|(_, key)| Base64::parse(key))
+			.transpose()
+			.map_err(|e| err!(Request(InvalidParam("Invalid ed25519 key in {what}: {e}"))))?
+			.ok_or_else(|| err!(Request(InvalidParam("The {what} have no ed25519 key."))))?;
+
+		known.insert(key_id.clone(), public_key);
+		devices.push((keys.json().get(), key_id, what));
+	}
+
+	let required = master.as_ref().map(|(key_id, ..)| key_id.as_str());
+	for (key, what) in [(&self_signing, "self-signing key"), (&user_signing, "user-signing key")]
+	{
+		let Some((.., json)) = key else {
+			continue;
+		};
+
+		let required = required.ok_or_else(|| {
+			err!(Request(InvalidParam("The {what} cannot be imported without a master key.")))
+		})?;
+
+		verify_signatures(user_id, json, &known, Some(required), what)?;
+	}
+
+	if let Some((.., json)) = &master {
+		verify_signatures(user_id, json, &known, None, "master key")?;
+	}
+
+	for (json, key_id, what) in &devices {
+		verify_signatures(user_id, json, &known, Some(key_id.as_str()), what)?;
+	}
+
+	Ok(())
+}
+
+/// Parses a cross-signing key of `user_id`, returning its key ID, public key
+/// and JSON.
+fn cross_signing_key<'a>(
+	user_id: &UserId,
+	key: &'a Raw<CrossSigningKey>,
+	usage: &KeyUsage,
+	what: &str,
+) -> Result<(String, Base64, &'a str)> {
+	let parsed = key
+		.deserialize()
+		.map_err(|e| err!(Request(InvalidParam("Invalid {what}: {e}"))))?;
+
+	if parsed.user_id != user_id {
+		return Err!(Request(InvalidParam("The {what} belongs to {}.", parsed.user_id)));
+	}
+
+	if !parsed.usage.contains(usage) {
+		return Err!(Request(InvalidParam("The {what} is not marked for {usage:?} use.")));
+	}
+
+	let mut keys = parsed.keys.iter();
+	let (Some((key_id, public_key)), None) = (keys.next(), keys.next()) else {
+		return Err!(Request(InvalidParam("The {what} must contain exactly one key.")));
+	};
+
+	let public_key = Base64::parse(public_key)
+		.map_err(|e| err!(Request(InvalidParam("Invalid public key in {what}: {e}"))))?;
+
+	Ok((key_id.to_string(), public_key, key.json().get()))
+}
+
+/// Verifies each signature by `user_id` on `json` made with a key in `known`,
+/// and that one by `required` is present.
+fn verify_signatures(
+	user_id: &UserId,
+	json: &str,
+	known: &BTreeMap<String, Base64>,
+	required: Option<&str>,
+	what: &str,
+) -> Result {
+	let object: CanonicalJsonObject = serde_json::from_str(json)?;
+	let signatures = match object.get("signatures") {
+		| Some(CanonicalJsonValue::Object(signatures)) => signatures.get(user_id.as_str()),
+		| _ => None,
+	};
+
+	let signatures = match signatures {
+		| Some(CanonicalJsonValue::Object(signatures)) => signatures.clone(),
+		| _ => CanonicalJsonObject::new(),
+	};
+
+	if let Some(required) = required {
+		if !signatures.contains_key(required) {
+			return Err!(Request(InvalidParam("The {what} is not signed by {required}.")));
+		}
+	}
+
+	for (key_id, signature) in signatures {
+		let Some(public_key) = known.get(&key_id) else {
+			continue;
+		};
+
+		let mut signed = object.clone();
+		let signature: CanonicalJsonObject = [(key_id.clone(), signature)].into();
+		let signatures: CanonicalJsonObject =
+			[(user_id.to_string(), CanonicalJsonValue::Object(signature))].into();
+		signed.insert("signatures".to_owned(), CanonicalJsonValue::Object(signatures));
+
+		let public_keys: PublicKeyMap =
+			[(user_id.to_string(), [(key_id.clone(), public_key.clone())].into())].into();
+
+		verify_json(&public_keys, &signed).map_err(|e| {
+			err!(Request(InvalidParam("Invalid signature by {key_id} on the {what}: {e}")))
+		})?;
+	}
+
+	Ok(())
+}
@@ -1,10 +1,19 @@
+mod key_export;
+mod room_profile;
+mod shadow_ban;
+mod status;
+#[cfg(test)]
+mod tests;
+mod tombstone;
+
 use std::{collections::BTreeMap, mem, sync::Arc};
 
+use async_trait::async_trait;
 use conduwuit::{
-	Err, Error, Result, Server, at, debug_warn, err, trace,
+	Err, Error, Result, Server, at, debug_info, debug_warn, err, trace,
 	utils::{self, ReadyExt, stream::TryIgnore, string::Unquoted},
 };
-use database::{Deserialized, Ignore, Interfix, Json, Map};
+use database::{Deserialized, Ignore, Interfix, Json, Map, serialize_key};
 use futures::{Stream, StreamExt, TryFutureExt};
 use ruma::{
 	DeviceId, KeyId, MilliSecondsSinceUnixEpoch, OneTimeKeyAlgorithm, OneTimeKeyId,
@@ -17,12 +26,23 @@
 	serde::Raw,
 };
 use serde_json::json;
+use tokio::{
+	sync::Notify,
+	time::{MissedTickBehavior, interval},
+};
 
+pub use self::{
+	key_export::{DeviceKeysExport, KEY_EXPORT_VERSION, KeyExport, KeyImport, validate_export},
+	room_profile::{Profile, overrides_profile, room_profile_content},
+	shadow_ban::{fake_event_id, shadow_drops_state},
+	status::{AccountStatus, MembershipEntry},
+};
 use crate::{Dep, account_data, admin, globals, rooms};
 
 pub struct Service {
 	services: Services,
 	db: Data,
+	interrupt: Notify,
 }
 
 struct Services {
@@ -44,19 +64,25 @@ struct Data {
 	token_userdeviceid: Arc<Map>,
 	userdeviceid_metadata: Arc<Map>,
 	userdeviceid_token: Arc<Map>,
+	userdeviceid_tombstone: Arc<Map>,
 	userfilterid_filter: Arc<Map>,
+	userroomid_profileoverride: Arc<Map>,
 	userid_avatarurl: Arc<Map>,
 	userid_blurhash: Arc<Map>,
+	userid_deactivated: Arc<Map>,
 	userid_devicelistversion: Arc<Map>,
 	userid_displayname: Arc<Map>,
 	userid_lastonetimekeyupdate: Arc<Map>,
 	userid_masterkeyid: Arc<Map>,
 	userid_password: Arc<Map>,
 	userid_selfsigningkeyid: Arc<Map>,
+	userid_shadowbanned: Arc<Map>,
+	userid_suspended: Arc<Map>,
 	userid_usersigningkeyid: Arc<Map>,
 	useridprofilekey_value: Arc<Map>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
@@ -79,21 +105,58 @@ fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 				token_userdeviceid: args.db["token_userdeviceid"].clone(),
 				userdeviceid_metadata: args.db["userdeviceid_metadata"].clone(),
 				userdeviceid_token: args.db["userdeviceid_token"].clone(),
+				userdeviceid_tombstone: args.db["userdeviceid_tombstone"].clone(),
 				userfilterid_filter: args.db["userfilterid_filter"].clone(),
+				userroomid_profileoverride: args.db["userroomid_profileoverride"].clone(),
 				userid_avatarurl: args.db["userid_avatarurl"].clone(),
 				userid_blurhash: args.db["userid_blurhash"].clone(),
+				userid_deactivated: args.db["userid_deactivated"].clone(),
 				userid_devicelistversion: args.db["userid_devicelistversion"].clone(),
 				userid_displayname: args.db["userid_displayname"].clone(),
 				userid_lastonetimekeyupdate: args.db["userid_lastonetimekeyupdate"].clone(),
 				userid_masterkeyid: args.db["userid_masterkeyid"].clone(),
 				userid_password: args.db["userid_password"].clone(),
 				userid_selfsigningkeyid: args.db["userid_selfsigningkeyid"].clone(),
+				userid_shadowbanned: args.db["userid_shadowbanned"].clone(),
+				userid_suspended: args.db["userid_suspended"].clone(),
 				userid_usersigningkeyid: args.db["userid_usersigningkeyid"].clone(),
 				useridprofilekey_value: args.db["useridprofilekey_value"].clone(),
 			},
+			interrupt: Notify::new(),
 		}))
 	}
 
+	#[tracing::instrument(skip_all, name = "users", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let dropped = self.drop_ghost_invites().await;
+		if dropped > 0 {
+			debug_info!(%dropped, "Dropped pending invites of deactivated users");
+		}
+
+		let grace = self.device_deletion_grace();
+		if grace.is_zero() {
+			return Ok(());
+		}
+
+		let mut i = interval(grace.min(tombstone::SWEEP_INTERVAL));
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			let purged = self.purge_expired_devices().await;
+			if purged > 0 {
+				debug_info!(%purged, "Purged deleted devices after their grace period");
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -128,21 +191,55 @@ pub fn create(&self, user_id: &UserId, password: Option<&str>) -> Result<()> {
 
 	/// Deactivate account
 	pub async fn deactivate_account(&self, user_id: &UserId) -> Result<()> {
-		// Remove all associated devices
+		// Remove all associated devices; nobody can log in to reattach them
 		self.all_device_ids(user_id)
-			.for_each(|device_id| self.remove_device(user_id, device_id))
+			.for_each(|device_id| self.purge_device(user_id, device_id))
 			.await;
 
+		for device_id in self.tombstoned_devices(user_id).await {
+			self.purge_device(user_id, &device_id).await;
+		}
+
 		// Set the password to "" to indicate a deactivated account. Hashes will never
 		// result in an empty string, so the user will not be able to log in again.
 		// Systems like changing the password without logging in should check if the
 		// account is deactivated.
 		self.set_password(user_id, None)?;
+		self.db
+			.userid_deactivated
+			.put(user_id, utils::millis_since_unix_epoch());
 
 		// TODO: Unhook 3PID
 		Ok(())
 	}
 
+	/// Deletes the cross-signing keys and the one-time keys left of a user,
+	/// telling other users they changed.
+	pub async fn remove_keys(&self, user_id: &UserId) {
+		for userid_keyid in [
+			&self.db.userid_masterkeyid,
+			&self.db.userid_selfsigningkeyid,
+			&self.db.userid_usersigningkeyid,
+		] {
+			if let Ok(key_id) = userid_keyid.get(user_id).await {
+				self.db.keyid_key.remove(&key_id);
+			}
+
+			userid_keyid.remove(user_id);
+		}
+
+		let prefix = (user_id, Interfix);
+		self.db
+			.onetimekeyid_onetimekeys
+			.keys_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_for_each(|key| self.db.onetimekeyid_onetimekeys.remove(key))
+			.await;
+
+		self.db.userid_lastonetimekeyupdate.remove(user_id);
+		self.mark_device_key_update(user_id).await;
+	}
+
 	/// Check if a user has an account on this homeserver.
 	#[inline]
 	pub async fn exists(&self, user_id: &UserId) -> bool {
@@ -284,6 +381,8 @@ pub async fn create_device(
 			))));
 		}
 
+		self.reattach_device(user_id, device_id).await;
+
 		let key = (user_id, device_id);
 		let val = Device {
 			device_id: device_id.into(),
@@ -297,15 +396,34 @@ pub async fn create_device(
 		self.set_token(user_id, device_id, token).await
 	}
 
-	/// Removes a device from a user.
+	/// Removes a device from a user. Within the device deletion grace period
+	/// the device is only tombstoned: its token is revoked and it is no longer
+	/// listed, but its to-device queue and keys remain until it is purged.
 	pub async fn remove_device(&self, user_id: &UserId, device_id: &DeviceId) {
-		let userdeviceid = (user_id, device_id);
+		if self.device_deletion_grace().is_zero() {
+			self.purge_device(user_id, device_id).await;
+			return;
+		}
 
-		// Remove tokens
-		if let Ok(old_token) = self.db.userdeviceid_token.qry(&userdeviceid).await {
-			self.db.userdeviceid_token.del(userdeviceid);
-			self.db.token_userdeviceid.remove(&old_token);
+		let userdeviceid = (user_id, device_id);
+		self.remove_token(user_id, device_id).await;
+		if self
+			.db
+			.userdeviceid_metadata
+			.qry(&userdeviceid)
+			.await
+			.is_ok()
+		{
+			self.db.userdeviceid_metadata.del(userdeviceid);
+			self.tombstone_device(user_id, device_id);
 		}
+	}
+
+	/// Deletes a device and everything held for it, telling other users it
+	/// was deleted.
+	pub async fn purge_device(&self, user_id: &UserId, device_id: &DeviceId) {
+		let userdeviceid = (user_id, device_id);
+		self.remove_token(user_id, device_id).await;
 
 		// Remove todevice events
 		let prefix = (user_id, device_id, Interfix);
@@ -320,10 +438,20 @@ pub async fn remove_device(&self, user_id: &UserId, device_id: &DeviceId) {
 
 		increment(&self.db.userid_devicelistversion, user_id.as_bytes());
 
+		self.db.keyid_key.del(userdeviceid);
 		self.db.userdeviceid_metadata.del(userdeviceid);
+		self.db.userdeviceid_tombstone.del(userdeviceid);
 		self.mark_device_key_update(user_id).await;
 	}
 
+	async fn remove_token(&self, user_id: &UserId, device_id: &DeviceId) {
+		let userdeviceid = (user_id, device_id);
+		if let Ok(old_token) = self.db.userdeviceid_token.qry(&userdeviceid).await {
+			self.db.userdeviceid_token.del(userdeviceid);
+			self.db.token_userdeviceid.remove(&old_token);
+		}
+	}
+
 	/// Returns an iterator over all device ids of this user.
 	pub fn all_device_ids<'a>(
 		&'a self,
@@ -800,29 +928,52 @@ pub fn get_to_device_events<'a>(
 			.map(at!(1))
 	}
 
-	pub async fn remove_to_device_events<Until>(
+	/// Up to `limit` of the to-device events after `since` up to `to`, and,
+	/// when more remain, the count of the last one returned, after which the
+	/// next page starts.
+	pub async fn get_to_device_page(
 		&self,
 		user_id: &UserId,
 		device_id: &DeviceId,
-		until: Until,
-	) where
-		Until: Into<Option<u64>> + Send,
-	{
+		since: u64,
+		to: u64,
+		limit: usize,
+	) -> (Vec<Raw<AnyToDeviceEvent>>, Option<u64>) {
 		type Key<'a> = (&'a UserId, &'a DeviceId, u64);
 
-		let until = until.into().unwrap_or(u64::MAX);
-		let from = (user_id, device_id, until);
-		self.db
+		let from = (user_id, device_id, since.saturating_add(1));
+		let events: Vec<_> = self
+			.db
 			.todeviceid_events
-			.rev_keys_from(&from)
+			.stream_from(&from)
 			.ignore_err()
-			.ready_take_while(move |(user_id_, device_id_, _): &Key<'_>| {
-				user_id == *user_id_ && device_id == *device_id_
-			})
-			.ready_for_each(|key: Key<'_>| {
-				self.db.todeviceid_events.del(key);
+			.ready_take_while(move |((user_id_, device_id_, count), _): &(Key<'_>, _)| {
+				user_id == *user_id_ && device_id == *device_id_ && *count <= to
 			})
+			.map(|((_, _, count), event)| (count, event))
+			.take(limit.max(1).saturating_add(1))
+			.collect()
 			.await;
+
+		page(events, limit)
+	}
+
+	/// Deletes the to-device events up to `until`, which the device has
+	/// acknowledged, with one range deletion.
+	pub async fn remove_to_device_events<Until>(
+		&self,
+		user_id: &UserId,
+		device_id: &DeviceId,
+		until: Until,
+	) where
+		Until: Into<Option<u64>> + Send,
+	{
+		let until = until.into().unwrap_or(u64::MAX);
+		let from = serialize_key((user_id, device_id, 0_u64)).expect("serialized start key");
+		let to = serialize_key((user_id, device_id, until.saturating_add(1)))
+			.expect("serialized end key");
+
+		self.db.todeviceid_events.delete_range(&from, &to);
 	}
 
 	pub async fn update_device_metadata(
@@ -1125,3 +1276,15 @@ fn increment(db: &Arc<Map>, key: &[u8]) {
 	let new = utils::increment(old.ok().as_deref());
 	db.insert(key, new);
 }
+
+/// Takes up to `limit`, and at least one, of the counted events, returning
+/// with them the count of the last one taken if any are left over.
+pub(super) fn page<T>(mut events: Vec<(u64, T)>, limit: usize) -> (Vec<T>, Option<u64>) {
+	let limit = limit.max(1);
+	let more = events.len() > limit;
+	events.truncate(limit);
+
+	let watermark = events.last().map(at!(0)).filter(|_| more);
+
+	(events.into_iter().map(at!(1)).collect(), watermark)
+}
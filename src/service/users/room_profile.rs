@@ -0,0 +1,84 @@
+use futures::future::join3;
+use ruma::{
+	OwnedMxcUri, RoomId, UserId,
+	events::room::member::{MembershipState, RoomMemberEventContent},
+};
+
+/// What a user shows of themselves in their joins.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Profile {
+	pub displayname: Option<String>,
+	pub avatar_url: Option<OwnedMxcUri>,
+	pub blurhash: Option<String>,
+}
+
+impl super::Service {
+	/// The user's global profile.
+	pub async fn profile(&self, user_id: &UserId) -> Profile {
+		let (displayname, avatar_url, blurhash) =
+			join3(self.displayname(user_id), self.avatar_url(user_id), self.blurhash(user_id))
+				.await;
+
+		Profile {
+			displayname: displayname.ok(),
+			avatar_url: avatar_url.ok(),
+			blurhash: blurhash.ok(),
+		}
+	}
+
+	/// Notes whether a join the user sent themselves shows a profile other than
+	/// their global one. Rooms with such a join are left out when a change of
+	/// the global profile is sent to the user's rooms; the note is dropped when
+	/// the user leaves the room.
+	pub async fn record_room_profile(
+		&self,
+		user_id: &UserId,
+		room_id: &RoomId,
+		content: &RoomMemberEventContent,
+	) {
+		if content.membership != MembershipState::Join {
+			return;
+		}
+
+		let key = (user_id, room_id);
+		if overrides_profile(content, &self.profile(user_id).await) {
+			self.db.userroomid_profileoverride.put_raw(key, []);
+		} else {
+			self.db.userroomid_profileoverride.del(key);
+		}
+	}
+
+	/// Whether the user shows a profile of their own in the room.
+	pub async fn has_room_profile(&self, user_id: &UserId, room_id: &RoomId) -> bool {
+		let key = (user_id, room_id);
+		self.db.userroomid_profileoverride.qry(&key).await.is_ok()
+	}
+}
+
+/// Whether a join shows a profile other than the global one.
+#[must_use]
+pub fn overrides_profile(content: &RoomMemberEventContent, global: &Profile) -> bool {
+	content.displayname != global.displayname || content.avatar_url != global.avatar_url
+}
+
+/// The user's join `member` changed to show the name and avatar of `room`,
+/// each falling back to that of the `global` profile. With neither set the
+/// join shows the global profile again.
+#[must_use]
+pub fn room_profile_content(
+	member: RoomMemberEventContent,
+	room: Profile,
+	global: Profile,
+) -> RoomMemberEventContent {
+	let (avatar_url, blurhash) = match room.avatar_url {
+		| Some(avatar_url) => (Some(avatar_url), room.blurhash),
+		| None => (global.avatar_url, global.blurhash),
+	};
+
+	RoomMemberEventContent {
+		displayname: room.displayname.or(global.displayname),
+		avatar_url,
+		blurhash,
+		..member
+	}
+}
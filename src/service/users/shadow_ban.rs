@@ -0,0 +1,63 @@
+use conduwuit::utils;
+use ruma::{
+	OwnedEventId, UserId,
+	events::{StateEventType, room::member::MembershipState},
+};
+
+/// Length of the hash in a room version 4 or later event ID, which fabricated
+/// IDs mimic.
+const EVENT_ID_LENGTH: usize = 43;
+
+impl super::Service {
+	/// Whether the events a user sends in rooms are dropped while they are told
+	/// the events were sent.
+	pub async fn is_shadow_banned(&self, user_id: &UserId) -> bool {
+		self.db.userid_shadowbanned.get(user_id).await.is_ok()
+	}
+
+	/// Shadow-bans a local account, returning whether it was not already.
+	pub async fn shadow_ban(&self, user_id: &UserId) -> bool {
+		if self.is_shadow_banned(user_id).await {
+			return false;
+		}
+
+		self.db
+			.userid_shadowbanned
+			.put(user_id, utils::millis_since_unix_epoch());
+
+		true
+	}
+
+	/// Lifts the shadow-ban of an account, returning whether it had one.
+	pub async fn unshadow_ban(&self, user_id: &UserId) -> bool {
+		if !self.is_shadow_banned(user_id).await {
+			return false;
+		}
+
+		self.db.userid_shadowbanned.remove(user_id);
+
+		true
+	}
+}
+
+/// Whether a shadow-banned user's state event is dropped. Of their own
+/// memberships only invites are, so they still join and leave rooms.
+#[must_use]
+pub fn shadow_drops_state(
+	event_type: &StateEventType,
+	membership: Option<&MembershipState>,
+) -> bool {
+	match event_type {
+		| StateEventType::RoomMember => membership == Some(&MembershipState::Invite),
+		| _ => true,
+	}
+}
+
+/// An ID for an event of a shadow-banned user which was not sent, looking like
+/// that of one which was.
+#[must_use]
+pub fn fake_event_id() -> OwnedEventId {
+	format!("${}", utils::random_string(EVENT_ID_LENGTH))
+		.try_into()
+		.expect("valid event ID")
+}
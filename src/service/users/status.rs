@@ -0,0 +1,184 @@
+use std::fmt;
+
+use conduwuit::{
+	Err, Error, Result, debug_info, utils,
+	utils::{ReadyExt, stream::TryIgnore},
+};
+use futures::StreamExt;
+use http::StatusCode;
+use ruma::{
+	OwnedRoomId, OwnedUserId, UserId,
+	api::client::error::{Error as RumaError, ErrorBody},
+};
+use serde_json::json;
+
+/// Whether a local account may take part in rooms.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AccountStatus {
+	Active,
+	/// Locked by an admin; the account keeps its rooms but may not join or be
+	/// invited to new ones until it is unsuspended.
+	Suspended,
+	Deactivated,
+}
+
+/// The ways a local user comes to be invited to or joined into a room, each
+/// of which is refused for an account which is not active.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MembershipEntry {
+	/// Joining through the client API.
+	Join,
+	/// Invited by a user of this server.
+	Invite,
+	/// Invited by a user of another server.
+	FederationInvite,
+	/// Knocking on a room.
+	Knock,
+	/// Joined by an admin command.
+	ForceJoin,
+	/// Joined to the "auto_join_rooms" on registration.
+	AutoJoin,
+	/// Joined by an appservice on behalf of one of its users.
+	Appservice,
+}
+
+impl super::Service {
+	/// The status of a local account. The server user is always active, though
+	/// it is deactivated while no emergency password is set.
+	pub async fn account_status(&self, user_id: &UserId) -> AccountStatus {
+		if user_id == self.services.globals.server_user {
+			return AccountStatus::Active;
+		}
+
+		if self.db.userid_deactivated.get(user_id).await.is_ok() {
+			return AccountStatus::Deactivated;
+		}
+
+		if self.is_suspended(user_id).await {
+			return AccountStatus::Suspended;
+		}
+
+		AccountStatus::Active
+	}
+
+	/// The one check of whether a user may join or be invited to a room by
+	/// `entry`. Users of other servers are left to their own server.
+	pub async fn check_membership_entry(
+		&self,
+		user_id: &UserId,
+		entry: MembershipEntry,
+	) -> Result {
+		if !self.services.globals.user_is_local(user_id) {
+			return Ok(());
+		}
+
+		check_entry(user_id, self.account_status(user_id).await, entry)
+	}
+
+	pub async fn is_suspended(&self, user_id: &UserId) -> bool {
+		self.db.userid_suspended.get(user_id).await.is_ok()
+	}
+
+	/// Suspends a local account, returning whether it was not already.
+	pub async fn suspend(&self, user_id: &UserId) -> bool {
+		if self.is_suspended(user_id).await {
+			return false;
+		}
+
+		self.db
+			.userid_suspended
+			.put(user_id, utils::millis_since_unix_epoch());
+
+		true
+	}
+
+	/// Lifts the suspension of an account, returning whether it had one.
+	pub async fn unsuspend(&self, user_id: &UserId) -> bool {
+		if !self.is_suspended(user_id).await {
+			return false;
+		}
+
+		self.db.userid_suspended.remove(user_id);
+
+		true
+	}
+
+	/// Drops the invites left pending for deactivated accounts, which can
+	/// never be accepted, returning how many were dropped.
+	pub(super) async fn drop_ghost_invites(&self) -> usize {
+		let deactivated: Vec<OwnedUserId> = self
+			.db
+			.userid_deactivated
+			.keys()
+			.ignore_err()
+			.ready_filter(|&user_id| user_id != self.services.globals.server_user)
+			.map(|user_id: &UserId| user_id.to_owned())
+			.collect()
+			.await;
+
+		let mut dropped: usize = 0;
+		for user_id in deactivated {
+			let rooms: Vec<OwnedRoomId> = self
+				.services
+				.state_cache
+				.rooms_invited(&user_id)
+				.map(|(room_id, _)| room_id)
+				.collect()
+				.await;
+
+			for room_id in rooms {
+				debug_info!(%user_id, %room_id, "Dropping invite of deactivated user");
+				self.services.state_cache.mark_as_left(&user_id, &room_id);
+				self.services.state_cache.forget(&room_id, &user_id);
+				self.services
+					.state_cache
+					.update_joined_count(&room_id)
+					.await;
+
+				dropped = dropped.saturating_add(1);
+			}
+		}
+
+		dropped
+	}
+}
+
+/// Refuses `entry` into a room for an account with `status`: M_FORBIDDEN
+/// when it is deactivated, M_USER_SUSPENDED when it is suspended.
+pub(super) fn check_entry(
+	user_id: &UserId,
+	status: AccountStatus,
+	entry: MembershipEntry,
+) -> Result {
+	match status {
+		| AccountStatus::Active => Ok(()),
+		| AccountStatus::Deactivated => {
+			Err!(Request(Forbidden("{user_id} is deactivated and cannot {entry}.")))
+		},
+		| AccountStatus::Suspended =>
+			Err(suspended(&format!("{user_id} is suspended and cannot {entry}."))),
+	}
+}
+
+fn suspended(message: &str) -> Error {
+	let body = json!({
+		"errcode": "M_USER_SUSPENDED",
+		"error": message,
+	});
+
+	Error::Ruma(RumaError {
+		status_code: StatusCode::FORBIDDEN,
+		body: ErrorBody::Json(body),
+	})
+}
+
+impl fmt::Display for MembershipEntry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			| Self::Join | Self::AutoJoin | Self::Appservice => "join rooms",
+			| Self::Invite | Self::FederationInvite => "be invited to rooms",
+			| Self::Knock => "knock on rooms",
+			| Self::ForceJoin => "be joined to rooms",
+		})
+	}
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+
+use http::StatusCode;
+use ruma::{
+	CanonicalJsonObject, OwnedDeviceId, UserId,
+	api::{OutgoingResponse, client::uiaa::UiaaResponse},
+	events::{
+		StateEventType,
+		room::member::{MembershipState, RoomMemberEventContent},
+	},
+	owned_device_id, owned_mxc_uri,
+	serde::{Base64, Raw},
+	signatures::{Ed25519KeyPair, sign_json},
+	user_id,
+};
+use serde_json::{Value, json};
+
+use super::{
+	AccountStatus, DeviceKeysExport, KEY_EXPORT_VERSION, KeyExport, MembershipEntry, Profile,
+	fake_event_id, overrides_profile, page, room_profile_content, shadow_drops_state,
+	status::check_entry, validate_export,
+};
+use crate::sync::Token;
+
+const ALICE: &UserId = user_id!("@alice:example.org");
+
+/// Generates a keypair whose version is its own public key, as used for
+/// cross-signing keys, or the given device ID.
+fn keypair(version: Option<&str>) -> (Ed25519KeyPair, String) {
+	let document = Ed25519KeyPair::generate().expect("generated keypair");
+	let public_key = Ed25519KeyPair::from_der(&document, String::new())
+		.expect("loaded keypair")
+		.public_key()
+		.to_vec();
+
+	let public_key: Base64 = Base64::new(public_key);
+	let public_key = public_key.encode();
+	let version = version.map_or_else(|| public_key.clone(), ToOwned::to_owned);
+	let keypair = Ed25519KeyPair::from_der(&document, version).expect("loaded keypair");
+
+	(keypair, public_key)
+}
+
+fn signed<T>(value: serde_json::Value, signers: &[&Ed25519KeyPair]) -> Raw<T> {
+	let mut object: CanonicalJsonObject =
+		serde_json::from_value(value).expect("valid canonical json");
+
+	for signer in signers {
+		sign_json(ALICE.as_str(), *signer, &mut object).expect("signed object");
+	}
+
+	Raw::from_json(serde_json::value::to_raw_value(&object).expect("raw json"))
+}
+
+fn cross_signing_key(usage: &str, public_key: &str) -> serde_json::Value {
+	json!({
+		"user_id": ALICE,
+		"usage": [usage],
+		"keys": { format!("ed25519:{public_key}"): public_key },
+	})
+}
+
+fn device_keys(device_id: &str, public_key: &str) -> serde_json::Value {
+	json!({
+		"user_id": ALICE,
+		"device_id": device_id,
+		"algorithms": ["m.olm.v1.curve25519-aes-sha2", "m.megolm.v1.aes-sha2"],
+		"keys": {
+			format!("curve25519:{device_id}"): public_key,
+			format!("ed25519:{device_id}"): public_key,
+		},
+	})
+}
+
+/// A complete export for `ALICE` with one device signed by itself and by the
+/// self-signing key.
+fn fixture() -> KeyExport {
+	let (master, master_public) = keypair(None);
+	let (self_signing, self_signing_public) = keypair(None);
+	let (user_signing, user_signing_public) = keypair(None);
+	let device_id: OwnedDeviceId = owned_device_id!("ABCDEFGH");
+	let (device, device_public) = keypair(Some(device_id.as_str()));
+
+	let device = DeviceKeysExport {
+		keys: Some(signed(device_keys(device_id.as_str(), &device_public), &[
+			&device,
+			&self_signing,
+		])),
+		one_time_key_counts: [("signed_curve25519".into(), 50_u32.into())].into(),
+	};
+
+	KeyExport {
+		version: KEY_EXPORT_VERSION,
+		user_id: ALICE.to_owned(),
+		master_key: Some(signed(cross_signing_key("master", &master_public), &[])),
+		self_signing_key: Some(signed(
+			cross_signing_key("self_signing", &self_signing_public),
+			&[&master],
+		)),
+		user_signing_key: Some(signed(
+			cross_signing_key("user_signing", &user_signing_public),
+			&[&master],
+		)),
+		devices: [(device_id, device)].into(),
+	}
+}
+
+#[test]
+fn key_export_valid() {
+	let export = fixture();
+	validate_export(ALICE, &export).expect("valid export");
+
+	let json = serde_json::to_string(&export).expect("serialized export");
+	let export: KeyExport = serde_json::from_str(&json).expect("deserialized export");
+	validate_export(ALICE, &export).expect("valid export after round trip");
+}
+
+#[test]
+fn key_export_user_mismatch() {
+	let export = fixture();
+	let bob = user_id!("@bob:example.org");
+
+	assert!(validate_export(bob, &export).is_err());
+}
+
+#[test]
+fn key_export_tampered_device() {
+	let mut export = fixture();
+	let device = export.devices.values_mut().next().expect("has device");
+	let keys = device.keys.as_ref().expect("has device keys").json().get();
+	let tampered = keys.replace("m.megolm.v1.aes-sha2", "m.megolm.v2.aes-sha2");
+	device.keys = Some(Raw::from_json(
+		serde_json::value::RawValue::from_string(tampered).expect("raw json"),
+	));
+
+	assert!(validate_export(ALICE, &export).is_err());
+}
+
+#[test]
+fn key_export_unsigned_self_signing_key() {
+	let mut export = fixture();
+	let (_, public_key) = keypair(None);
+	export.self_signing_key = Some(signed(cross_signing_key("self_signing", &public_key), &[]));
+
+	assert!(validate_export(ALICE, &export).is_err());
+}
+
+#[test]
+fn key_export_without_master_key() {
+	let mut export = fixture();
+	export.master_key = None;
+
+	assert!(validate_export(ALICE, &export).is_err());
+}
+
+#[test]
+fn deleted_device_grace_period() {
+	use std::time::Duration;
+
+	use super::tombstone::within_grace;
+
+	let grace = Duration::from_secs(2 * 60 * 60);
+	let deleted_at: u64 = 1_000_000;
+	let expires_at = deleted_at.saturating_add(2 * 60 * 60 * 1000);
+
+	assert!(within_grace(deleted_at, deleted_at, grace), "relogin straight away");
+	assert!(
+		within_grace(deleted_at, expires_at.saturating_sub(1), grace),
+		"relogin in window"
+	);
+	assert!(!within_grace(deleted_at, expires_at, grace), "relogin after window");
+	assert!(!within_grace(deleted_at, deleted_at, Duration::ZERO), "no grace period");
+
+	// A clock which went backwards does not expire the tombstone early.
+	assert!(within_grace(deleted_at, deleted_at.saturating_sub(1000), grace), "clock skew");
+}
+
+#[test]
+fn membership_entry_by_account_status() {
+	use AccountStatus::*;
+	use MembershipEntry::*;
+
+	let entries = [Join, Invite, FederationInvite, Knock, ForceJoin, AutoJoin, Appservice];
+	for entry in entries {
+		assert!(check_entry(ALICE, Active, entry).is_ok(), "{entry:?} refused for active user");
+
+		for (status, errcode) in [(Deactivated, "M_FORBIDDEN"), (Suspended, "M_USER_SUSPENDED")] {
+			let error = check_entry(ALICE, status, entry).expect_err("refused");
+			let response: UiaaResponse = error.into();
+			let response = response
+				.try_into_http_response::<Vec<u8>>()
+				.expect("serializable response");
+
+			assert_eq!(response.status(), StatusCode::FORBIDDEN, "{entry:?} for {status:?}");
+
+			let body: Value = serde_json::from_slice(response.body()).expect("json body");
+			assert_eq!(body["errcode"], errcode, "{entry:?} for {status:?}");
+			assert!(
+				body["error"]
+					.as_str()
+					.is_some_and(|error| error.contains(ALICE.as_str())),
+				"{entry:?} for {status:?} names the user"
+			);
+		}
+	}
+}
+
+#[test]
+fn to_device_page_limit() {
+	let events: Vec<_> = (1..=5_u64).map(|count| (count, count)).collect();
+
+	assert_eq!(page(events.clone(), 5), (vec![1, 2, 3, 4, 5], None));
+	assert_eq!(page(events.clone(), 10), (vec![1, 2, 3, 4, 5], None));
+	assert_eq!(page(events.clone(), 2), (vec![1, 2], Some(2)));
+	assert_eq!(page(events, 0), (vec![1], Some(1)), "always makes progress");
+	assert_eq!(page(Vec::<(u64, u64)>::new(), 2), (vec![], None));
+}
+
+/// A device offline for long syncs three times for its 5000 queued messages,
+/// each sync acknowledging the previous one.
+#[test]
+fn to_device_paged_over_syncs() {
+	const QUEUED: u64 = 5000;
+	const LIMIT: usize = 2000;
+
+	// Standing in for the todeviceid_events column of one device
+	let mut queue: BTreeMap<u64, u64> = (1..=QUEUED).map(|count| (count, count)).collect();
+	let next_batch = QUEUED.saturating_add(10);
+
+	let mut sync = |since: &str| -> (Vec<u64>, String) {
+		let since: Token = since.parse().unwrap_or_default();
+		queue.retain(|&count, _| count > since.to_device());
+
+		let events = queue
+			.range(since.to_device().saturating_add(1)..)
+			.take_while(|&(&count, _)| count <= next_batch)
+			.map(|(&count, &event)| (count, event))
+			.take(LIMIT.saturating_add(1))
+			.collect();
+
+		let (events, to_device) = page(events, LIMIT);
+		(events, Token { count: next_batch, to_device }.to_string())
+	};
+
+	let (first, since) = sync("");
+	assert_eq!(first.len(), LIMIT);
+	assert_eq!(since, format!("{next_batch}_2000"));
+
+	let (second, since) = sync(&since);
+	assert_eq!(second.len(), LIMIT);
+	assert_eq!(since, format!("{next_batch}_4000"));
+
+	let (third, since) = sync(&since);
+	assert_eq!(third.len(), 1000);
+	assert_eq!(since, next_batch.to_string(), "no watermark once all are sent");
+
+	let received: Vec<u64> = [first, second, third].concat();
+	let expected: Vec<u64> = (1..=QUEUED).collect();
+	assert_eq!(received, expected, "each message sent once, in order");
+
+	let (fourth, _) = sync(&since);
+	assert!(fourth.is_empty());
+	assert!(queue.is_empty(), "acknowledged messages are deleted");
+}
+
+fn named(displayname: &str) -> Profile {
+	Profile {
+		displayname: Some(displayname.to_owned()),
+		avatar_url: Some(owned_mxc_uri!("mxc://example.org/alice")),
+		blurhash: Some("blur".to_owned()),
+	}
+}
+
+#[test]
+fn room_profile_kept_then_cleared() {
+	let global = named("Alice");
+	let join = RoomMemberEventContent {
+		displayname: global.displayname.clone(),
+		avatar_url: global.avatar_url.clone(),
+		..RoomMemberEventContent::new(MembershipState::Join)
+	};
+	assert!(!overrides_profile(&join, &global), "join shows the global profile");
+
+	let room = Profile {
+		displayname: Some("Al".to_owned()),
+		..Profile::default()
+	};
+	let join = room_profile_content(join, room, global.clone());
+	assert_eq!(join.displayname.as_deref(), Some("Al"));
+	assert_eq!(join.avatar_url, global.avatar_url, "global avatar kept");
+	assert_eq!(join.blurhash, global.blurhash);
+	assert!(overrides_profile(&join, &global), "room left out of the fan-out");
+
+	// the global name changes; the room was left out, so its join is unchanged
+	let global = named("Alicia");
+	assert!(overrides_profile(&join, &global), "room keeps its name");
+
+	let join = room_profile_content(join, Profile::default(), global.clone());
+	assert_eq!(join.displayname.as_deref(), Some("Alicia"));
+	assert_eq!(join.membership, MembershipState::Join);
+	assert!(!overrides_profile(&join, &global), "cleared room follows the global profile");
+}
+
+#[test]
+fn room_profile_avatar_blurhash() {
+	let global = named("Alice");
+	let member = RoomMemberEventContent::new(MembershipState::Join);
+	let room = Profile {
+		avatar_url: Some(owned_mxc_uri!("mxc://example.org/room")),
+		..Profile::default()
+	};
+
+	let join = room_profile_content(member, room, global.clone());
+	assert_eq!(join.displayname, global.displayname);
+	assert_eq!(join.blurhash, None, "global blurhash is of another avatar");
+	assert!(overrides_profile(&join, &global));
+}
+
+#[test]
+fn shadow_ban_dropped_state() {
+	let member = StateEventType::RoomMember;
+	assert!(shadow_drops_state(&StateEventType::RoomTopic, None));
+	assert!(shadow_drops_state(&StateEventType::RoomPowerLevels, None));
+	assert!(
+		shadow_drops_state(&member, Some(&MembershipState::Invite)),
+		"invites are dropped"
+	);
+
+	for membership in [MembershipState::Join, MembershipState::Leave, MembershipState::Ban] {
+		assert!(!shadow_drops_state(&member, Some(&membership)), "{membership} is sent");
+	}
+}
+
+#[test]
+fn shadow_ban_fake_event_ids() {
+	let (first, second) = (fake_event_id(), fake_event_id());
+	assert_ne!(first, second);
+	assert_eq!(first.as_str().len(), 44);
+	assert!(first.as_str().starts_with('$'));
+	assert_eq!(first.server_name(), None, "shaped like the IDs of current room versions");
+}
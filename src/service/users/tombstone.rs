@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use conduwuit::{
+	debug, debug_info, utils,
+	utils::{ReadyExt, stream::TryIgnore},
+};
+use database::{Deserialized, Ignore, Interfix};
+use futures::StreamExt;
+use ruma::{DeviceId, OwnedDeviceId, UserId};
+
+/// Longest interval between sweeps for tombstones past the grace period.
+pub(super) const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+impl super::Service {
+	/// Grace period for which deleted devices are kept as tombstones; zero
+	/// when devices are deleted immediately.
+	#[must_use]
+	pub fn device_deletion_grace(&self) -> Duration {
+		let hours = self.services.server.config.device_deletion_grace_hours;
+
+		Duration::from_secs(hours.saturating_mul(60 * 60))
+	}
+
+	/// Marks a device deleted without dropping its to-device queue or keys.
+	pub(super) fn tombstone_device(&self, user_id: &UserId, device_id: &DeviceId) {
+		let key = (user_id, device_id);
+		self.db
+			.userdeviceid_tombstone
+			.put(key, utils::millis_since_unix_epoch());
+	}
+
+	/// Called before a device is created. A tombstone still within the grace
+	/// period is lifted so the new session inherits the queue and keys of
+	/// the old one; an expired one is purged first.
+	pub(super) async fn reattach_device(&self, user_id: &UserId, device_id: &DeviceId) {
+		let key = (user_id, device_id);
+		let Ok(deleted_at) = self
+			.db
+			.userdeviceid_tombstone
+			.qry(&key)
+			.await
+			.deserialized::<u64>()
+		else {
+			return;
+		};
+
+		let now = utils::millis_since_unix_epoch();
+		if within_grace(deleted_at, now, self.device_deletion_grace()) {
+			debug_info!(%user_id, %device_id, "Reattaching deleted device to new login");
+			self.db.userdeviceid_tombstone.del(key);
+		} else {
+			self.purge_device(user_id, device_id).await;
+		}
+	}
+
+	/// Purges the tombstones of every user which have outlived the grace
+	/// period, returning how many were purged.
+	pub async fn purge_expired_devices(&self) -> usize {
+		let now = utils::millis_since_unix_epoch();
+		let grace = self.device_deletion_grace();
+		let expired: Vec<_> = self
+			.db
+			.userdeviceid_tombstone
+			.stream()
+			.ignore_err()
+			.ready_filter_map(
+				|((user_id, device_id), deleted_at): ((&UserId, &DeviceId), u64)| {
+					(!within_grace(deleted_at, now, grace))
+						.then(|| (user_id.to_owned(), device_id.to_owned()))
+				},
+			)
+			.collect()
+			.await;
+
+		for (user_id, device_id) in &expired {
+			debug!(%user_id, %device_id, "Purging deleted device after grace period");
+			self.purge_device(user_id, device_id).await;
+		}
+
+		expired.len()
+	}
+
+	/// Devices of the user which are deleted but still within the grace
+	/// period.
+	pub async fn tombstoned_devices(&self, user_id: &UserId) -> Vec<OwnedDeviceId> {
+		let prefix = (user_id, Interfix);
+		self.db
+			.userdeviceid_tombstone
+			.keys_prefix(&prefix)
+			.ignore_err()
+			.map(|(_, device_id): (Ignore, &DeviceId)| device_id.to_owned())
+			.collect()
+			.await
+	}
+}
+
+/// Whether a device deleted at `deleted_at` may still be reattached at `now`,
+/// both in milliseconds since the epoch.
+pub(super) fn within_grace(deleted_at: u64, now: u64, grace: Duration) -> bool {
+	let grace = u64::try_from(grace.as_millis()).unwrap_or(u64::MAX);
+
+	now.saturating_sub(deleted_at) < grace
+}